@@ -0,0 +1,131 @@
+//! Command round trip through the driver
+//!
+//! The `mock` group measures the driver's own overhead against a scripted
+//! [`MockTransport`]; the `tcp` group adds real syscalls, talking to a
+//! minimal device on a loopback socket. Run with `cargo bench`.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use nsrt::{
+    MockTransport, NSRT,
+    protocol::{ACK, Command, PACKET_LEN},
+};
+use std::{
+    hint::black_box,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+/// Driver with `exchanges` of (command, response) scripted
+fn scripted(exchanges: &[(Command, &[u8])]) -> NSRT {
+    let mock = MockTransport::new();
+    for &(command, response) in exchanges {
+        mock.expect(command.packet(0, response.len() as u32), response);
+    }
+    NSRT::with_transport(mock)
+}
+
+fn mock(c: &mut Criterion) {
+    let level = 62.5f32.to_le_bytes();
+    let leq = 60.0f32.to_le_bytes();
+    let temperature = 21.0f32.to_le_bytes();
+    let mut user_id = [0; 32];
+    user_id[..8].copy_from_slice(b"bench-01");
+
+    let mut group = c.benchmark_group("mock");
+    group.bench_function("read_level", |b| {
+        b.iter_batched_ref(
+            || scripted(&[(Command::ReadLevel, &level)]),
+            |nsrt| black_box(nsrt.read_level().unwrap()),
+            BatchSize::SmallInput,
+        );
+    });
+    group.bench_function("read_measurement", |b| {
+        b.iter_batched_ref(
+            || {
+                scripted(&[
+                    (Command::ReadLevel, &level),
+                    (Command::ReadLEQ, &leq),
+                    (Command::ReadTemperature, &temperature),
+                ])
+            },
+            |nsrt| black_box(nsrt.read_measurement().unwrap()),
+            BatchSize::SmallInput,
+        );
+    });
+    group.bench_function("read_user_id", |b| {
+        b.iter_batched_ref(
+            || scripted(&[(Command::ReadUserID, &user_id)]),
+            |nsrt| black_box(nsrt.read_user_id().unwrap()),
+            BatchSize::SmallInput,
+        );
+    });
+    group.bench_function("set_time_constant", |b| {
+        b.iter_batched(
+            || {
+                let mock = MockTransport::new();
+                let mut request = Command::WriteTau.packet(0, 4).to_vec();
+                request.extend(0.125f32.to_le_bytes());
+                mock.expect(request, [ACK]);
+                NSRT::with_transport(mock)
+            },
+            // The builder skips the stabilization wait
+            |nsrt| black_box(nsrt.time_constant(0.125).unwrap()),
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+/// Answer commands on `stream` like a device reading 62.5 dB
+fn serve(mut stream: TcpStream) {
+    let mut packet = [0; PACKET_LEN];
+    let mut data = Vec::new();
+    while stream.read_exact(&mut packet).is_ok() {
+        let code = u32::from_le_bytes(packet[..4].try_into().unwrap());
+        let count = u32::from_le_bytes(packet[8..].try_into().unwrap()) as usize;
+        let answer = if code & 0x8000_0000 == 0 {
+            data.resize(count, 0);
+            if stream.read_exact(&mut data).is_err() {
+                return;
+            }
+            vec![ACK]
+        } else {
+            62.5f32.to_le_bytes().repeat(count.div_ceil(4))[..count].to_vec()
+        };
+        if stream.write_all(&answer).is_err() {
+            return;
+        }
+    }
+}
+
+fn tcp(c: &mut Criterion) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = format!("tcp://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            stream.set_nodelay(true).unwrap();
+            thread::spawn(move || serve(stream));
+        }
+    });
+    let mut nsrt = NSRT::open_port(&port).unwrap();
+
+    let mut group = c.benchmark_group("tcp");
+    group.bench_function("read_level", |b| {
+        b.iter(|| black_box(nsrt.read_level().unwrap()));
+    });
+    group.bench_function("read_measurement", |b| {
+        b.iter(|| black_box(nsrt.read_measurement().unwrap()));
+    });
+    let mut nsrt = Some(nsrt);
+    group.bench_function("set_time_constant", |b| {
+        b.iter(|| {
+            let updated = nsrt.take().unwrap().time_constant(0.125).unwrap();
+            nsrt = Some(black_box(updated));
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mock, tcp);
+criterion_main!(benches);
@@ -0,0 +1,29 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set");
+        let config = cbindgen::Config::from_file(format!("{dir}/cbindgen.toml"))
+            .expect("failed to read cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{dir}/src/ffi.rs"))
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(format!("{dir}/include/nsrt.h"));
+    }
+
+    #[cfg(feature = "node")]
+    napi_build::setup();
+
+    #[cfg(feature = "grpc")]
+    {
+        let fds = protox::compile(["nsrt.proto"], ["proto"]).expect("failed to parse protos");
+        tonic_prost_build::configure()
+            .compile_fds(fds)
+            .expect("failed to generate gRPC code");
+    }
+}
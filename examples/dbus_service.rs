@@ -0,0 +1,22 @@
+use nsrt::{
+    NSRT, Sampler, Threshold,
+    dbus::{self, Meter},
+};
+use std::time::Duration;
+use zbus::Connection;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+    let meter = Meter::new(&sampler)
+        .await?
+        .threshold(Threshold::new(85.0).hysteresis(3.0));
+
+    let connection = Connection::session().await?;
+    println!("Serving {} on the session bus", dbus::BUS_NAME);
+    dbus::serve(&connection, &sampler, meter).await?;
+
+    Ok(())
+}
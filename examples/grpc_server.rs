@@ -0,0 +1,19 @@
+use nsrt::{NSRT, Sampler, grpc::MeterService};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+
+    let mut service = MeterService::new();
+    service.add_device(&sampler)?;
+
+    let addr = "0.0.0.0:50051".parse()?;
+    println!("Serving nsrt.v1.Meter on {addr}");
+
+    nsrt::grpc::serve(addr, service).await?;
+
+    Ok(())
+}
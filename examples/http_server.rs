@@ -0,0 +1,24 @@
+use nsrt::{NSRT, Sampler, Weighting};
+use std::{sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let nsrt = NSRT::open()?.weighting(Weighting::A)?.apply()?;
+    let sampler = Arc::new(Sampler::start(nsrt, Duration::from_secs(1)));
+
+    let listener = TcpListener::bind("0.0.0.0:8080").await?;
+    let addr = listener.local_addr()?;
+    println!("Serving the API on http://{addr}");
+    println!("  curl http://{addr}/measurement");
+    println!(
+        "  curl -X PATCH -H 'Content-Type: application/json' -d '{{\"weighting\":\"C\"}}' http://{addr}/config"
+    );
+    println!("  curl -N http://{addr}/events");
+
+    nsrt::http::serve(listener, sampler).await?;
+
+    Ok(())
+}
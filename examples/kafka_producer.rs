@@ -0,0 +1,19 @@
+use nsrt::{NSRT, Sampler, kafka::KafkaSink};
+use std::time::Duration;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+    let info = sampler.device().call(NSRT::read_info)?;
+
+    let sink = KafkaSink::new("localhost:9092", "nsrt.measurements", &info)?;
+    println!(
+        "Producing to nsrt.measurements, keyed by {}",
+        info.serial_number
+    );
+
+    sampler.attach(sink).join().expect("sink thread panicked")?;
+
+    Ok(())
+}
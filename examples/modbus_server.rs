@@ -0,0 +1,17 @@
+use nsrt::{NSRT, Sampler};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+
+    let listener = TcpListener::bind("0.0.0.0:5020").await?;
+    println!("Serving Modbus TCP on {}", listener.local_addr()?);
+
+    nsrt::modbus::serve(listener, &sampler).await?;
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use nsrt::{NSRT, Sampler, nats::NatsSink};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+
+    let sink = NatsSink::connect("nats://127.0.0.1:4222", "nsrt.measurements")
+        .await?
+        .jetstream("NSRT")
+        .await?;
+    println!("Publishing to nsrt.measurements");
+
+    tokio::task::spawn_blocking(move || sampler.attach(sink).join())
+        .await?
+        .expect("sink thread panicked")?;
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use nsrt::{NSRT, Sampler};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+
+    println!("Serving OPC UA on opc.tcp://0.0.0.0:4855/");
+    nsrt::opcua::serve(&sampler, "0.0.0.0", 4855).await?;
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use nsrt::{NSRT, Sampler, osc::OscSink};
+use std::time::Duration;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_millis(100));
+
+    let sink = OscSink::connect("127.0.0.1:9000")?
+        .address("/meter/spl")?
+        .interval(Duration::from_millis(250));
+    println!("Sending OSC to 127.0.0.1:9000 /meter/spl");
+
+    sampler.attach(sink).join().expect("sink thread panicked")?;
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use nsrt::{Pace, binlog::BinaryLogReader, csv::CsvWriter, replay};
+use std::{env, io, process};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    let (log, pace) = match args.as_slice() {
+        [_, log] => (log, Pace::AsFastAsPossible),
+        [_, log, speed] => (log, Pace::Scaled(speed.parse()?)),
+        _ => {
+            eprintln!("Usage: replay_log <binary log> [speed]");
+            process::exit(2);
+        }
+    };
+
+    let mut sink = CsvWriter::new(io::stdout().lock())?;
+    let count = replay(BinaryLogReader::open(log)?, &mut sink, pace)?;
+    eprintln!("Replayed {count} measurements");
+
+    Ok(())
+}
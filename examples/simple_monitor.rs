@@ -29,7 +29,7 @@ fn main() -> Result<()> {
         let leq = nsrt.read_leq()?;
         let temp = nsrt.read_temperature()?;
 
-        println!("{:10.1} | {:9.1} | {:8.1}", level, leq, temp);
+        println!("{:10.1} | {:9.1} | {:8.1}", level, leq, temp.as_celsius());
 
         thread::sleep(Duration::from_secs(1));
     }
@@ -0,0 +1,18 @@
+use nsrt::{NSRT, Sampler, Threshold, snmp::Agent};
+use std::{net::UdpSocket, time::Duration};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Opening NSRT_mk4 device...");
+
+    let sampler = Sampler::start(NSRT::open()?, Duration::from_secs(1));
+
+    let socket = UdpSocket::bind("0.0.0.0:1161")?;
+    println!("Serving SNMP on {}", socket.local_addr()?);
+
+    Agent::new("public")
+        .trap_target("127.0.0.1:162".parse()?)
+        .threshold(Threshold::new(85.0).hysteresis(3.0))
+        .serve(socket, &sampler)?;
+
+    Ok(())
+}
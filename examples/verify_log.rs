@@ -0,0 +1,36 @@
+use nsrt::{
+    binlog::BinaryLogReader,
+    signing::{self, VerifyingKey},
+};
+use std::{env, fs, process};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    let [_, log, seals, key] = args.as_slice() else {
+        eprintln!("Usage: verify_log <log> <seals> <public key file>");
+        process::exit(2);
+    };
+
+    let key = fs::read(key)?
+        .try_into()
+        .map_err(|_| "public key file must contain 32 raw bytes")?;
+    let key = VerifyingKey::from_bytes(&key)?;
+    let seals = signing::read_seals(fs::File::open(seals)?)?;
+
+    match signing::verify(BinaryLogReader::open(log)?, &seals, &key) {
+        Ok(verified) => {
+            println!("OK: {} records sealed", verified.sealed);
+            if verified.unsealed > 0 {
+                println!(
+                    "Warning: {} trailing records are not sealed",
+                    verified.unsealed
+                );
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("FAILED: {e}");
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Decodes arbitrary bytes as the response to one read command
+//!
+//! The first byte picks the command; the rest is the device's answer, which
+//! may be too short, too long or malformed. Decoding must return an error
+//! rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nsrt::{MockTransport, NSRT, protocol::Command};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, response)) = data.split_first() else {
+        return;
+    };
+    let reads: Vec<_> = Command::ALL
+        .into_iter()
+        .filter(|command| !command.is_write())
+        .collect();
+    let command = reads[usize::from(selector) % reads.len()];
+
+    let mock = MockTransport::new();
+    mock.expect(command.packet(0, command.length() as u32), response);
+
+    let mut nsrt = NSRT::with_transport(mock);
+    let _ = match command {
+        Command::ReadLevel => nsrt.read_level().map(drop),
+        Command::ReadLEQ => nsrt.read_leq().map(drop),
+        Command::ReadTemperature => nsrt.read_temperature().map(drop),
+        Command::ReadWeighting => nsrt.read_weighting().map(drop),
+        Command::ReadFS => nsrt.read_sampling_frequency().map(drop),
+        Command::ReadTau => nsrt.read_time_constant().map(drop),
+        Command::ReadModel => nsrt.read_model().map(drop),
+        Command::ReadSN => nsrt.read_serial_number().map(drop),
+        Command::ReadFWRev => nsrt.read_firmware_revision().map(drop),
+        Command::ReadDOC => nsrt.read_calibration_date().map(drop),
+        Command::ReadDOB => nsrt.read_birth_date().map(drop),
+        Command::ReadUserID => nsrt.read_user_id().map(drop),
+        Command::WriteWeighting
+        | Command::WriteFS
+        | Command::WriteTau
+        | Command::WriteUserID
+        | Command::WriteAudioDebug => Ok(()),
+    };
+});
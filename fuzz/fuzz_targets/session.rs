@@ -0,0 +1,51 @@
+//! Runs the composite reads against a device answering with arbitrary bytes
+//!
+//! The device ignores what it is asked and streams the input back as its
+//! answers, so responses arrive misaligned, truncated and interleaved with
+//! acknowledgements, as after a glitch on the wire. Every call must return,
+//! and return an error rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nsrt::{NSRT, Transport, Weighting};
+use std::io::{self, Cursor, Read, Write};
+
+/// Transport discarding writes and reading from a fixed buffer
+struct Wire(Cursor<Vec<u8>>);
+
+impl Read for Wire {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A silent device times out instead of signalling end of file
+        match self.0.read(buf)? {
+            0 if !buf.is_empty() => Err(io::ErrorKind::TimedOut.into()),
+            count => Ok(count),
+        }
+    }
+}
+
+impl Write for Wire {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Wire {}
+
+fuzz_target!(|data: &[u8]| {
+    let mut nsrt = NSRT::with_transport(Wire(Cursor::new(data.to_vec())));
+
+    let _ = nsrt.read_measurement();
+    let _ = nsrt.read_info();
+    let _ = nsrt.read_config();
+    let _ = nsrt.set_user_id("fuzz");
+    let _ = nsrt.resync();
+    // The fluent setters skip the stabilization wait, which would stall fuzzing
+    let _ = nsrt
+        .weighting(Weighting::A)
+        .and_then(|nsrt| nsrt.time_constant(0.125));
+});
@@ -0,0 +1,271 @@
+#ifndef NSRT_H
+#define NSRT_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdint.h>
+
+/**
+ * Version of the C API, raised on incompatible changes
+ */
+#define NSRT_API_VERSION 1
+
+/**
+ * C weighting
+ */
+#define NSRT_WEIGHTING_C 0
+
+/**
+ * A weighting
+ */
+#define NSRT_WEIGHTING_A 1
+
+/**
+ * Z weighting
+ */
+#define NSRT_WEIGHTING_Z 2
+
+/**
+ * Size of the text fields of [`NsrtInfo`], including the NUL terminator
+ */
+#define NSRT_TEXT_LEN 32
+
+/**
+ * Outcome of a call
+ */
+typedef enum NsrtStatus {
+  /**
+   * Success
+   */
+  NSRT_STATUS_OK = 0,
+  /**
+   * No device attached, or the port doesn't exist
+   */
+  NSRT_STATUS_NO_DEVICE = 1,
+  /**
+   * The device didn't answer in time
+   */
+  NSRT_STATUS_TIMEOUT = 2,
+  /**
+   * The device rejected a setting
+   */
+  NSRT_STATUS_NO_ACKNOWLEDGE = 3,
+  /**
+   * The device answered with something unexpected
+   */
+  NSRT_STATUS_INVALID_RESPONSE = 4,
+  /**
+   * An argument was out of range, not valid UTF-8, or a null pointer
+   */
+  NSRT_STATUS_INVALID_ARGUMENT = 5,
+  /**
+   * Any other serial port or I/O error
+   */
+  NSRT_STATUS_IO_ERROR = 6,
+  /**
+   * A bug in the library; the device handle should be closed
+   */
+  NSRT_STATUS_PANIC = 7,
+  /**
+   * The device's firmware is too old for the operation
+   */
+  NSRT_STATUS_UNSUPPORTED = 8,
+} NsrtStatus;
+
+/**
+ * Opaque device handle
+ */
+typedef struct NsrtDevice NsrtDevice;
+
+/**
+ * Readings taken at one point in time
+ */
+typedef struct NsrtMeasurement {
+  /**
+   * Seconds since the Unix epoch
+   */
+  double timestamp;
+  /**
+   * Running sound level in dB
+   */
+  float level;
+  /**
+   * LEQ in dB since the previous LEQ read
+   */
+  float leq;
+  /**
+   * Temperature in °C
+   */
+  float temperature;
+} NsrtMeasurement;
+
+/**
+ * Measurement settings
+ */
+typedef struct NsrtConfig {
+  /**
+   * One of the `NSRT_WEIGHTING_*` constants
+   */
+  int32_t weighting;
+  /**
+   * Time constant in seconds
+   */
+  float time_constant;
+  /**
+   * Sampling frequency in Hz, 32000 or 48000
+   */
+  uint32_t sampling_frequency;
+} NsrtConfig;
+
+/**
+ * Identity of a device
+ */
+typedef struct NsrtInfo {
+  /**
+   * Model name
+   */
+  char model[NSRT_TEXT_LEN];
+  /**
+   * Serial number
+   */
+  char serial_number[NSRT_TEXT_LEN];
+  /**
+   * Firmware revision
+   */
+  char firmware_revision[NSRT_TEXT_LEN];
+  /**
+   * User-defined identifier
+   */
+  char user_id[NSRT_TEXT_LEN];
+  /**
+   * Date of last calibration, in seconds since Jan 1 1904 UTC
+   */
+  uint64_t calibration_date;
+  /**
+   * Date of manufacture, in seconds since Jan 1 1904 UTC
+   */
+  uint64_t birth_date;
+} NsrtInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Version of the C API the library implements, `NSRT_API_VERSION`
+ */
+uint32_t nsrt_api_version(void);
+
+/**
+ * Description of the error of the last call on this thread, or an empty
+ * string if it succeeded
+ *
+ * The string stays valid until the next call on the same thread.
+ */
+const char *nsrt_last_error(void);
+
+/**
+ * Stable code of the error of the last call on this thread, see
+ * [`NsrtError::code`], or 0 if it succeeded or panicked
+ */
+uint32_t nsrt_last_error_code(void);
+
+/**
+ * Open the device on serial port `port`, or the first one found if `port`
+ * is null, and store its handle in `*out`
+ *
+ * # Safety
+ *
+ * `port` must be null or a NUL-terminated string, and `out` valid for
+ * writes.
+ */
+enum NsrtStatus nsrt_open(const char *port, struct NsrtDevice **out);
+
+/**
+ * Close the device and free its handle; null is ignored
+ *
+ * # Safety
+ *
+ * `dev` must be null or a handle from [`nsrt_open`] not closed before, and
+ * no other call may be using it.
+ */
+void nsrt_close(struct NsrtDevice *dev);
+
+/**
+ * Read the running sound level in dB
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `out` valid for writes.
+ */
+enum NsrtStatus nsrt_read_level(const struct NsrtDevice *dev, float *out);
+
+/**
+ * Read the LEQ in dB and restart its integration
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `out` valid for writes.
+ */
+enum NsrtStatus nsrt_read_leq(const struct NsrtDevice *dev, float *out);
+
+/**
+ * Read the temperature in °C
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `out` valid for writes.
+ */
+enum NsrtStatus nsrt_read_temperature(const struct NsrtDevice *dev, float *out);
+
+/**
+ * Read level, LEQ and temperature with a host timestamp
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `out` valid for writes.
+ */
+enum NsrtStatus nsrt_read_measurement(const struct NsrtDevice *dev, struct NsrtMeasurement *out);
+
+/**
+ * Read the measurement settings
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `out` valid for writes.
+ */
+enum NsrtStatus nsrt_read_config(const struct NsrtDevice *dev, struct NsrtConfig *out);
+
+/**
+ * Apply measurement settings, writing only those that differ, and wait for
+ * the device to stabilize
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `config` valid for reads.
+ */
+enum NsrtStatus nsrt_configure(const struct NsrtDevice *dev, const struct NsrtConfig *config);
+
+/**
+ * Read the identity of the device
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `out` valid for writes.
+ */
+enum NsrtStatus nsrt_read_info(const struct NsrtDevice *dev, struct NsrtInfo *out);
+
+/**
+ * Set the user ID, at most 31 bytes of UTF-8
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `user_id` a NUL-terminated string.
+ */
+enum NsrtStatus nsrt_set_user_id(const struct NsrtDevice *dev, const char *user_id);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NSRT_H */
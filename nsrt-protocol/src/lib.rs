@@ -0,0 +1,529 @@
+//! The `NSRT_mk4` wire protocol
+//!
+//! Every exchange starts with a 12-byte packet from the host: the command
+//! code, an address and a byte count, each a little-endian `u32`. For read
+//! commands (code with the top bit set) the device answers with `count`
+//! bytes. Write commands are followed by `count` bytes of data, and the
+//! device answers with a single [`ACK`] byte once the value is stored.
+//!
+//! [`Command`] is the command table used by both the driver and the
+//! simulator, so the two can't drift apart silently, and the `decode_*` and
+//! `encode_*` functions convert between payloads and values.
+//!
+//! The address field is ignored by every command and each count is fixed,
+//! so the firmware has no way to read a block of values with one command.
+//! It does answer queued commands in order, though: [`encode_requests`]
+//! packs several reads into one write, and [`Values`] splits the answers,
+//! read back as one block, into typed values.
+//!
+//! The crate is `no_std` and never allocates, so firmware talking to a meter
+//! can use it directly; the `nsrt` crate layers the serial driver on top.
+
+#![no_std]
+
+use core::{ffi::CStr, fmt};
+
+/// Length of a command packet
+pub const PACKET_LEN: usize = 12;
+
+/// Size of the text fields, including the NUL terminator
+pub const TEXT_LEN: usize = 32;
+
+/// Answer to a write command the device accepted
+pub const ACK: u8 = 0x06;
+
+/// How a command's payload is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Little-endian `f32`
+    F32,
+    /// Single byte: 0 for C, 1 for A, 2 for Z weighting
+    Weighting,
+    /// Little-endian `u16` in Hz, 32000 or 48000
+    SamplingFrequency,
+    /// Little-endian `u64` in seconds since Jan 1 1904 UTC
+    Date,
+    /// UTF-8 text, NUL-terminated within the length
+    Text,
+    /// Single byte: 0 for off, 1 for on
+    Flag,
+}
+
+/// Commands understood by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Command {
+    /// Running sound level in dB
+    ReadLevel = 0x8000_0010,
+    /// LEQ in dB since the previous LEQ read, restarting integration
+    ReadLEQ = 0x8000_0011,
+    /// Temperature in °C
+    ReadTemperature = 0x8000_0012,
+    /// Weighting curve
+    ReadWeighting = 0x8000_0020,
+    /// Sampling frequency
+    ReadFS = 0x8000_0021,
+    /// Time constant in seconds
+    ReadTau = 0x8000_0022,
+    /// Model name
+    ReadModel = 0x8000_0031,
+    /// Serial number
+    ReadSN = 0x8000_0032,
+    /// Firmware revision
+    ReadFWRev = 0x8000_0033,
+    /// Date of last calibration
+    ReadDOC = 0x8000_0034,
+    /// Date of manufacture
+    ReadDOB = 0x8000_0035,
+    /// User-defined identifier
+    ReadUserID = 0x8000_0036,
+    /// Set the weighting curve
+    WriteWeighting = 0x0000_0020,
+    /// Set the sampling frequency
+    WriteFS = 0x0000_0021,
+    /// Set the time constant in seconds
+    WriteTau = 0x0000_0022,
+    /// Set the user-defined identifier, at most 31 bytes
+    WriteUserID = 0x0000_0036,
+    /// Switch the USB audio output to a 94 dB 1 kHz test tone, from firmware
+    /// 1.4
+    WriteAudioDebug = 0x0000_0037,
+}
+
+impl Command {
+    /// Every command, reads first
+    pub const ALL: [Command; 17] = [
+        Command::ReadLevel,
+        Command::ReadLEQ,
+        Command::ReadTemperature,
+        Command::ReadWeighting,
+        Command::ReadFS,
+        Command::ReadTau,
+        Command::ReadModel,
+        Command::ReadSN,
+        Command::ReadFWRev,
+        Command::ReadDOC,
+        Command::ReadDOB,
+        Command::ReadUserID,
+        Command::WriteWeighting,
+        Command::WriteFS,
+        Command::WriteTau,
+        Command::WriteUserID,
+        Command::WriteAudioDebug,
+    ];
+
+    /// The command with wire code `code`
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.code() == code)
+    }
+
+    /// Code sent in the command packet
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Whether the host sends data rather than reading it
+    pub fn is_write(self) -> bool {
+        self.code() & 0x8000_0000 == 0
+    }
+
+    /// Payload length in bytes; for text, the size of the field including
+    /// its NUL terminator, which writes may fill only partly
+    pub fn length(self) -> usize {
+        match self.encoding() {
+            Encoding::Weighting | Encoding::Flag => 1,
+            Encoding::SamplingFrequency => 2,
+            Encoding::F32 => 4,
+            Encoding::Date => 8,
+            Encoding::Text => TEXT_LEN,
+        }
+    }
+
+    /// Layout of the payload
+    pub fn encoding(self) -> Encoding {
+        match self {
+            Command::ReadLevel
+            | Command::ReadLEQ
+            | Command::ReadTemperature
+            | Command::ReadTau
+            | Command::WriteTau => Encoding::F32,
+            Command::ReadWeighting | Command::WriteWeighting => Encoding::Weighting,
+            Command::ReadFS | Command::WriteFS => Encoding::SamplingFrequency,
+            Command::ReadDOC | Command::ReadDOB => Encoding::Date,
+            Command::ReadModel
+            | Command::ReadSN
+            | Command::ReadFWRev
+            | Command::ReadUserID
+            | Command::WriteUserID => Encoding::Text,
+            Command::WriteAudioDebug => Encoding::Flag,
+        }
+    }
+
+    /// Command packet transferring `count` bytes from `address`
+    pub fn packet(self, address: u32, count: u32) -> [u8; PACKET_LEN] {
+        let mut packet = [0; PACKET_LEN];
+        packet[..4].copy_from_slice(&self.code().to_le_bytes());
+        packet[4..8].copy_from_slice(&address.to_le_bytes());
+        packet[8..].copy_from_slice(&count.to_le_bytes());
+        packet
+    }
+}
+
+/// Weighting functions supported by the `NSRT_mk4`
+///
+/// Weightings added by later firmware read as [`Weighting::Unknown`] rather
+/// than failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Weighting {
+    /// C-weighting (dB-C)
+    C,
+    /// A-weighting (dB-A)
+    A,
+    /// Z-weighting (dB-Z) - flat frequency response
+    Z,
+    /// A weighting this crate doesn't know, by its code
+    Unknown(u8),
+}
+
+impl From<Weighting> for u8 {
+    fn from(weighting: Weighting) -> Self {
+        match weighting {
+            Weighting::C => 0,
+            Weighting::A => 1,
+            Weighting::Z => 2,
+            Weighting::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<u8> for Weighting {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => Weighting::C,
+            1 => Weighting::A,
+            2 => Weighting::Z,
+            code => Weighting::Unknown(code),
+        }
+    }
+}
+
+/// Sampling frequencies supported by the `NSRT_mk4`
+///
+/// Rates added by later firmware read as [`SamplingFrequency::Unknown`]
+/// rather than failing. Converting from a `u32`, as for user input, accepts
+/// only the known rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "u32", try_from = "u32")
+)]
+#[non_exhaustive]
+pub enum SamplingFrequency {
+    /// 32 kHz
+    Freq32kHz,
+    /// 48 kHz
+    Freq48kHz,
+    /// A rate this crate doesn't know, in Hz
+    Unknown(u16),
+}
+
+impl From<SamplingFrequency> for u16 {
+    fn from(freq: SamplingFrequency) -> Self {
+        match freq {
+            SamplingFrequency::Freq32kHz => 32000,
+            SamplingFrequency::Freq48kHz => 48000,
+            SamplingFrequency::Unknown(hz) => hz,
+        }
+    }
+}
+
+impl From<SamplingFrequency> for u32 {
+    fn from(freq: SamplingFrequency) -> Self {
+        u16::from(freq).into()
+    }
+}
+
+impl From<u16> for SamplingFrequency {
+    fn from(hz: u16) -> Self {
+        match hz {
+            32000 => SamplingFrequency::Freq32kHz,
+            48000 => SamplingFrequency::Freq48kHz,
+            hz => SamplingFrequency::Unknown(hz),
+        }
+    }
+}
+
+impl TryFrom<u32> for SamplingFrequency {
+    type Error = Error;
+
+    fn try_from(hz: u32) -> Result<Self, Error> {
+        match u16::try_from(hz).map(SamplingFrequency::from) {
+            Ok(SamplingFrequency::Unknown(_)) | Err(_) => {
+                Err(Error::UnsupportedSamplingFrequency(hz))
+            }
+            Ok(freq) => Ok(freq),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::F32 => "4-byte little-endian f32",
+            Encoding::Weighting => "1-byte weighting code",
+            Encoding::SamplingFrequency => "2-byte little-endian u16 in Hz",
+            Encoding::Date => "8-byte little-endian u64 of seconds since 1904",
+            Encoding::Text => "NUL-terminated UTF-8 text of up to 32 bytes",
+            Encoding::Flag => "1-byte flag, 0 or 1",
+        })
+    }
+}
+
+/// An answer that doesn't decode, with its raw bytes for diagnostics
+///
+/// Payloads are at most [`TEXT_LEN`] bytes; only that many are kept of
+/// longer ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Malformed {
+    expected: Encoding,
+    bytes: [u8; TEXT_LEN],
+    len: usize,
+}
+
+impl Malformed {
+    /// `data` that failed to decode as `expected`
+    pub fn new(expected: Encoding, data: &[u8]) -> Self {
+        let mut bytes = [0; TEXT_LEN];
+        let kept = data.len().min(TEXT_LEN);
+        bytes[..kept].copy_from_slice(&data[..kept]);
+        Self {
+            expected,
+            bytes,
+            len: data.len(),
+        }
+    }
+
+    /// The layout the answer should have had
+    pub fn expected(&self) -> Encoding {
+        self.expected
+    }
+
+    /// The raw answer, cut to [`TEXT_LEN`] bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len.min(TEXT_LEN)]
+    }
+}
+
+/// Formats as e.g. `expected 4-byte little-endian f32, got 2 bytes [00 7c]`
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got {} bytes [", self.expected, self.len)?;
+        for (i, byte) in self.bytes().iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{separator}{byte:02x}")?;
+        }
+        if self.len > TEXT_LEN {
+            f.write_str(" ...")?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// Error decoding or encoding a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// An answer that is too short or holds a value the device never sends
+    Malformed(Malformed),
+    /// A sampling frequency other than 32 or 48 kHz, in Hz
+    UnsupportedSamplingFrequency(u32),
+    /// Text that doesn't fit a text field with its terminator
+    TextTooLong,
+    /// Text containing a NUL character
+    TextContainsNul,
+    /// A write command among requests, which only reads may be
+    NotARead(Command),
+    /// A buffer too small for the requests
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Malformed(malformed) => write!(f, "Malformed response: {malformed}"),
+            Error::UnsupportedSamplingFrequency(hz) => {
+                write!(f, "Unsupported sampling frequency: {hz} Hz")
+            }
+            Error::TextTooLong => write!(f, "Text longer than {} bytes", TEXT_LEN - 1),
+            Error::TextContainsNul => write!(f, "Text must not contain NUL characters"),
+            Error::NotARead(command) => write!(f, "{command:?} is not a read command"),
+            Error::BufferTooSmall => write!(f, "Buffer too small for the requests"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// A [`Error::Malformed`] for `data` expected as `encoding`
+fn malformed(encoding: Encoding, data: &[u8]) -> Error {
+    Error::Malformed(Malformed::new(encoding, data))
+}
+
+/// Decode an [`Encoding::F32`] answer
+pub fn decode_f32(data: &[u8]) -> Result<f32, Error> {
+    data.first_chunk()
+        .map(|bytes| f32::from_le_bytes(*bytes))
+        .ok_or_else(|| malformed(Encoding::F32, data))
+}
+
+/// Decode an [`Encoding::Weighting`] answer
+pub fn decode_weighting(data: &[u8]) -> Result<Weighting, Error> {
+    data.first()
+        .map(|&code| Weighting::from(code))
+        .ok_or_else(|| malformed(Encoding::Weighting, data))
+}
+
+/// Decode an [`Encoding::SamplingFrequency`] answer
+pub fn decode_sampling_frequency(data: &[u8]) -> Result<SamplingFrequency, Error> {
+    let hz = data
+        .first_chunk()
+        .map(|bytes| u16::from_le_bytes(*bytes))
+        .ok_or_else(|| malformed(Encoding::SamplingFrequency, data))?;
+    Ok(SamplingFrequency::from(hz))
+}
+
+/// Decode an [`Encoding::Date`] answer, in seconds since Jan 1 1904 UTC
+pub fn decode_date(data: &[u8]) -> Result<u64, Error> {
+    data.first_chunk()
+        .map(|bytes| u64::from_le_bytes(*bytes))
+        .ok_or_else(|| malformed(Encoding::Date, data))
+}
+
+/// Decode an [`Encoding::Flag`] answer
+pub fn decode_flag(data: &[u8]) -> Result<bool, Error> {
+    match data.first() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        _ => Err(malformed(Encoding::Flag, data)),
+    }
+}
+
+/// Decode an [`Encoding::Text`] answer, borrowing the text from `data`
+pub fn decode_text(data: &[u8]) -> Result<&str, Error> {
+    CStr::from_bytes_until_nul(data)
+        .ok()
+        .and_then(|text| text.to_str().ok())
+        .ok_or_else(|| malformed(Encoding::Text, data))
+}
+
+/// Encode `text` with its NUL terminator into `buf`, returning the payload
+pub fn encode_text<'a>(text: &str, buf: &'a mut [u8; TEXT_LEN]) -> Result<&'a [u8], Error> {
+    if text.len() >= TEXT_LEN {
+        return Err(Error::TextTooLong);
+    }
+    if text.contains('\0') {
+        return Err(Error::TextContainsNul);
+    }
+    buf[..text.len()].copy_from_slice(text.as_bytes());
+    buf[text.len()] = 0;
+    Ok(&buf[..=text.len()])
+}
+
+/// A decoded answer to a read command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    /// Level, LEQ and temperature in their units, or time constant in seconds
+    F32(f32),
+    /// Weighting curve
+    Weighting(Weighting),
+    /// Sampling frequency
+    SamplingFrequency(SamplingFrequency),
+    /// Date in seconds since Jan 1 1904 UTC
+    Date(u64),
+    /// Text, borrowed from the answer
+    Text(&'a str),
+    /// On or off
+    Flag(bool),
+}
+
+/// Decode the answer to read `command` by its [`Encoding`]
+pub fn decode(command: Command, data: &[u8]) -> Result<Value<'_>, Error> {
+    Ok(match command.encoding() {
+        Encoding::F32 => Value::F32(decode_f32(data)?),
+        Encoding::Weighting => Value::Weighting(decode_weighting(data)?),
+        Encoding::SamplingFrequency => Value::SamplingFrequency(decode_sampling_frequency(data)?),
+        Encoding::Date => Value::Date(decode_date(data)?),
+        Encoding::Text => Value::Text(decode_text(data)?),
+        Encoding::Flag => Value::Flag(decode_flag(data)?),
+    })
+}
+
+/// Pack the packets of read `commands` back to back into `buf`, returning the
+/// request to send in one write
+pub fn encode_requests<'a>(commands: &[Command], buf: &'a mut [u8]) -> Result<&'a [u8], Error> {
+    let len = commands.len() * PACKET_LEN;
+    let request = buf.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+    for (packet, &command) in request.chunks_exact_mut(PACKET_LEN).zip(commands) {
+        if command.is_write() {
+            return Err(Error::NotARead(command));
+        }
+        packet.copy_from_slice(&command.packet(0, command.length() as u32));
+    }
+    Ok(request)
+}
+
+/// Total length of the answers to `commands`
+pub fn answers_len(commands: &[Command]) -> usize {
+    commands.iter().map(|command| command.length()).sum()
+}
+
+/// Typed values split from the answers to several read commands
+///
+/// ```
+/// use nsrt_protocol::{Command, Value, Values};
+///
+/// let commands = [Command::ReadLevel, Command::ReadTemperature];
+/// let block = [0, 0, 0x7c, 0x42, 0, 0, 0xac, 0x41];
+/// let values: Vec<_> = Values::new(&commands, &block).collect::<Result<_, _>>()?;
+/// assert_eq!(values, [Value::F32(63.0), Value::F32(21.5)]);
+/// # Ok::<(), nsrt_protocol::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Values<'a, 'c> {
+    commands: core::slice::Iter<'c, Command>,
+    block: &'a [u8],
+}
+
+impl<'a, 'c> Values<'a, 'c> {
+    /// Split `block`, the answers to `commands` in order
+    pub fn new(commands: &'c [Command], block: &'a [u8]) -> Self {
+        Self {
+            commands: commands.iter(),
+            block,
+        }
+    }
+}
+
+impl<'a> Iterator for Values<'a, '_> {
+    type Item = Result<Value<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &command = self.commands.next()?;
+        let Some((data, rest)) = self.block.split_at_checked(command.length()) else {
+            let error = malformed(command.encoding(), self.block);
+            self.block = &[];
+            return Some(Err(error));
+        };
+        self.block = rest;
+        Some(decode(command, data))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.commands.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Values<'_, '_> {}
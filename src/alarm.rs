@@ -0,0 +1,264 @@
+use crate::{Measurement, Threshold};
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// State of an [`Alarm`], ordered by severity
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum AlarmState {
+    /// Below every threshold
+    #[default]
+    Normal,
+    /// Above the warning threshold
+    Warning,
+    /// Above the critical threshold
+    Critical,
+}
+
+impl AlarmState {
+    /// Lowercase name, as used in configuration files and payloads
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for AlarmState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Threshold of one level of an [`Alarm`]
+///
+/// The level is entered once the running level has stayed above `level` for
+/// `dwell`, and left once it has stayed below `level - hysteresis` for
+/// `dwell`, so neither a single loud sample nor a brief lull changes the
+/// state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlarmLevel {
+    /// Level in dB above which the alarm level is entered
+    pub level: f32,
+    /// Drop in dB below `level` required to leave the alarm level
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hysteresis: f32,
+    /// Time the level must stay beyond the threshold before the state changes
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde", default))]
+    pub dwell: Duration,
+}
+
+impl AlarmLevel {
+    /// Create an alarm level at `level` dB without hysteresis or dwell time
+    pub fn new(level: f32) -> Self {
+        Self {
+            level,
+            hysteresis: 0.0,
+            dwell: Duration::ZERO,
+        }
+    }
+
+    /// Set the hysteresis in dB
+    #[must_use]
+    pub fn hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Set the minimum time beyond the threshold before the state changes
+    #[must_use]
+    pub fn dwell(mut self, dwell: Duration) -> Self {
+        self.dwell = dwell;
+        self
+    }
+
+    /// The level as a plain threshold, without the dwell time
+    pub fn threshold(&self) -> Threshold {
+        Threshold::new(self.level).hysteresis(self.hysteresis)
+    }
+}
+
+impl From<Threshold> for AlarmLevel {
+    fn from(threshold: Threshold) -> Self {
+        Self::new(threshold.level).hysteresis(threshold.hysteresis)
+    }
+}
+
+/// Warning and critical levels of an alarm, either of which may be unset
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alarm {
+    /// Level raising a warning, e.g. for a site manager
+    pub warning: Option<AlarmLevel>,
+    /// Level raising a critical alarm, e.g. for stopping work
+    pub critical: Option<AlarmLevel>,
+}
+
+impl Alarm {
+    /// Create an alarm without levels, which stays normal
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the warning level
+    #[must_use]
+    pub fn warning(mut self, level: AlarmLevel) -> Self {
+        self.warning = Some(level);
+        self
+    }
+
+    /// Set the critical level
+    #[must_use]
+    pub fn critical(mut self, level: AlarmLevel) -> Self {
+        self.critical = Some(level);
+        self
+    }
+
+    /// The level of `state`, none for the normal state or an unset level
+    pub fn level(&self, state: AlarmState) -> Option<AlarmLevel> {
+        match state {
+            AlarmState::Normal => None,
+            AlarmState::Warning => self.warning,
+            AlarmState::Critical => self.critical,
+        }
+    }
+}
+
+/// State change of an [`AlarmMonitor`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlarmTransition {
+    /// State before the change
+    pub from: AlarmState,
+    /// State after the change
+    pub to: AlarmState,
+    /// Measurement that completed the dwell time
+    pub measurement: Measurement,
+}
+
+impl AlarmTransition {
+    /// Whether the alarm became more severe
+    pub fn is_escalation(&self) -> bool {
+        self.to > self.from
+    }
+
+    /// The level entered when escalating, or left when de-escalating
+    pub fn level(&self, alarm: &Alarm) -> Option<AlarmLevel> {
+        alarm.level(self.from.max(self.to))
+    }
+}
+
+/// Tracks the state of an [`Alarm`] from measurements
+///
+/// Each level is entered and left independently, after its own dwell time,
+/// and the state is the most severe level entered. Dwell times are measured
+/// between measurement timestamps, so replayed logs give the same
+/// transitions as the live meter did.
+#[derive(Debug, Clone)]
+pub struct AlarmMonitor {
+    alarm: Alarm,
+    state: AlarmState,
+    warning: Dwell,
+    critical: Dwell,
+}
+
+impl AlarmMonitor {
+    /// Create a monitor in the normal state
+    pub fn new(alarm: Alarm) -> Self {
+        Self {
+            alarm,
+            state: AlarmState::Normal,
+            warning: Dwell::default(),
+            critical: Dwell::default(),
+        }
+    }
+
+    /// The monitored alarm
+    pub fn alarm(&self) -> Alarm {
+        self.alarm
+    }
+
+    /// The current state
+    pub fn state(&self) -> AlarmState {
+        self.state
+    }
+
+    /// Change the levels, e.g. as a [`Schedule`](crate::schedule::Schedule)
+    /// moves into another period
+    ///
+    /// The state and running dwell times are kept, so the new levels take
+    /// effect with the next measurement and their dwell times. A level that
+    /// is unset is left with the next measurement.
+    pub fn set_alarm(&mut self, alarm: Alarm) {
+        self.alarm = alarm;
+    }
+
+    /// Feed a measurement, returning the state change it caused, if any
+    pub fn update(&mut self, measurement: &Measurement) -> Option<AlarmTransition> {
+        match &self.alarm.warning {
+            Some(level) => self.warning.update(level, measurement),
+            None => self.warning = Dwell::default(),
+        }
+        match &self.alarm.critical {
+            Some(level) => self.critical.update(level, measurement),
+            None => self.critical = Dwell::default(),
+        }
+
+        let state = if self.critical.entered {
+            AlarmState::Critical
+        } else if self.warning.entered {
+            AlarmState::Warning
+        } else {
+            AlarmState::Normal
+        };
+        if state == self.state {
+            return None;
+        }
+        let from = std::mem::replace(&mut self.state, state);
+        Some(AlarmTransition {
+            from,
+            to: state,
+            measurement: *measurement,
+        })
+    }
+}
+
+/// Whether one alarm level is entered, and since when the level has been
+/// beyond its threshold
+#[derive(Debug, Clone, Default)]
+struct Dwell {
+    entered: bool,
+    since: Option<SystemTime>,
+}
+
+impl Dwell {
+    fn update(&mut self, level: &AlarmLevel, measurement: &Measurement) {
+        let beyond = if self.entered {
+            measurement.level < level.level - level.hysteresis
+        } else {
+            measurement.level > level.level
+        };
+        if !beyond {
+            self.since = None;
+            return;
+        }
+
+        let since = *self.since.get_or_insert(measurement.timestamp);
+        let dwelt = measurement
+            .timestamp
+            .duration_since(since)
+            .unwrap_or_default();
+        if dwelt >= level.dwell {
+            self.entered = !self.entered;
+            self.since = None;
+        }
+    }
+}
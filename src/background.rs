@@ -0,0 +1,90 @@
+use crate::{NSRT, Result};
+use std::{thread, time::Duration};
+
+/// Smallest margin of the source over the background for which the
+/// correction gives a reliable level, in dB
+const MIN_MARGIN: f32 = 3.0;
+
+/// Correction of a source level for the background noise measured alone
+///
+/// The two-phase workflow: measure the background with the source off,
+/// [`BackgroundCorrection::measure`], then the source with the background,
+/// [`BackgroundCorrection::measure_source`]. The background energy is taken
+/// out of the combined level, `10 log10(10^(L/10) - 10^(Lb/10))`. Below a
+/// margin of 3 dB the background dominates, and following ISO practice the
+/// result is only an upper bound: the combined level less 3 dB, flagged as
+/// unreliable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BackgroundCorrection {
+    /// Background level in dB
+    pub background: f32,
+}
+
+/// A source level corrected for background noise
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BackgroundCorrected {
+    /// Level of source and background together in dB
+    pub combined: f32,
+    /// Background level in dB
+    pub background: f32,
+    /// How far the combined level is above the background in dB
+    pub margin: f32,
+    /// Correction subtracted from the combined level in dB
+    pub correction: f32,
+    /// Level of the source alone in dB, an upper bound if not reliable
+    pub level: f32,
+    /// Whether the margin is at least 3 dB
+    pub reliable: bool,
+}
+
+impl BackgroundCorrection {
+    /// Correction for a background level of `background` dB
+    pub fn new(background: f32) -> Self {
+        Self { background }
+    }
+
+    /// Measure the LEQ of the background over `duration`, with the source
+    /// off
+    pub fn measure(nsrt: &mut NSRT, duration: Duration) -> Result<Self> {
+        Ok(Self::new(measure_leq(nsrt, duration)?))
+    }
+
+    /// Measure the LEQ of the source with the background over `duration`
+    /// and correct it
+    pub fn measure_source(
+        &self,
+        nsrt: &mut NSRT,
+        duration: Duration,
+    ) -> Result<BackgroundCorrected> {
+        Ok(self.correct(measure_leq(nsrt, duration)?))
+    }
+
+    /// Correct the level `combined` in dB of source and background together
+    pub fn correct(&self, combined: f32) -> BackgroundCorrected {
+        let margin = combined - self.background;
+        let reliable = margin >= MIN_MARGIN;
+        let correction = if reliable {
+            -10.0 * (1.0 - 10f32.powf(-margin / 10.0)).log10()
+        } else {
+            MIN_MARGIN
+        };
+        BackgroundCorrected {
+            combined,
+            background: self.background,
+            margin,
+            correction,
+            level: combined - correction,
+            reliable,
+        }
+    }
+}
+
+/// LEQ over the next `duration`
+pub(crate) fn measure_leq(nsrt: &mut NSRT, duration: Duration) -> Result<f32> {
+    // Restart integration, so the next LEQ covers just the duration
+    nsrt.read_leq()?;
+    thread::sleep(duration);
+    nsrt.read_leq()
+}
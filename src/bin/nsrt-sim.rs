@@ -0,0 +1,178 @@
+//! Simulated NSRT_mk4 on a pty or TCP port
+//!
+//! Usage: `nsrt-sim [--profile <profile>] [--serial <number>] [--link <path> | --tcp <addr>]`
+
+use nsrt::{
+    NsrtError, Result,
+    sim::{Profile, Simulator},
+};
+use std::{net::TcpListener, path::PathBuf, process::ExitCode};
+
+const USAGE: &str =
+    "Usage: nsrt-sim [--profile <profile>] [--serial <number>] [--link <path> | --tcp <addr>]
+
+Profiles: constant:<dB>, sine:<min>:<max>:<period s>, noise:<mean>:<spread>,
+          steps:<dB>@<s>,<dB>@<s>,...";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("nsrt-sim: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let mut profile = Profile::default();
+    let mut serial = None;
+    let mut link: Option<PathBuf> = None;
+    let mut tcp = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| NsrtError::InvalidParameter(format!("{arg} needs a value")))
+        };
+        match arg.as_str() {
+            "-p" | "--profile" => profile = value()?.parse()?,
+            "-s" | "--serial" => serial = Some(value()?),
+            "-l" | "--link" => link = Some(value()?.into()),
+            "-t" | "--tcp" => tcp = Some(value()?),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => return Err(NsrtError::InvalidParameter(format!("{arg}\n{USAGE}"))),
+        }
+    }
+
+    let mut simulator = Simulator::new(profile.clone());
+    if let Some(serial) = serial {
+        simulator = simulator.serial_number(serial);
+    }
+
+    match tcp {
+        Some(addr) => serve_tcp(&addr, &profile, simulator),
+        None => serve_pty(link, &profile, simulator),
+    }
+}
+
+/// Serve one host connection at a time on `addr`
+fn serve_tcp(addr: &str, profile: &Profile, mut simulator: Simulator) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let port = format!("tcp://{}", listener.local_addr()?);
+    eprintln!("Simulating {profile} on {port}");
+    eprintln!("Discover it with NSRT_PORTS={port}");
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        stream.set_nodelay(true)?;
+        if let Err(e) = simulator.serve(stream) {
+            eprintln!("nsrt-sim: connection closed: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve_pty(link: Option<PathBuf>, profile: &Profile, mut simulator: Simulator) -> Result<()> {
+    let pty = pty::Pty::open()?;
+    let mut port = pty.path.display().to_string();
+    if let Some(link) = link {
+        // Replace a link left behind by an earlier run
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(&pty.path, &link)?;
+        port = link.display().to_string();
+    }
+    eprintln!("Simulating {profile} on {port}");
+    eprintln!("Discover it with NSRT_PORTS={port}");
+
+    // The simulator keeps the other end open, so this only returns on errors
+    simulator.serve(pty.controller)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_pty(_link: Option<PathBuf>, _profile: &Profile, _simulator: Simulator) -> Result<()> {
+    Err(NsrtError::InvalidParameter(format!(
+        "ptys are not supported on this platform, use --tcp\n{USAGE}"
+    )))
+}
+
+#[cfg(unix)]
+mod pty {
+    use std::{
+        ffi::CStr,
+        fs::{File, OpenOptions},
+        io,
+        os::{
+            fd::{AsRawFd, FromRawFd},
+            unix::fs::OpenOptionsExt,
+        },
+        path::PathBuf,
+    };
+
+    /// Pseudo-terminal whose device side looks like a serial port
+    pub struct Pty {
+        /// Controlling side, read and written by the simulator
+        pub controller: File,
+        /// Path of the device side, opened by hosts
+        pub path: PathBuf,
+        /// Device side held open, so the controller survives hosts closing it
+        _device: File,
+    }
+
+    impl Pty {
+        pub fn open() -> io::Result<Self> {
+            // SAFETY: plain libc calls; the descriptor is owned by `controller`
+            // as soon as it is valid, and ptsname's buffer is copied at once
+            unsafe {
+                let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let controller = File::from_raw_fd(fd);
+                if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let name = libc::ptsname(fd);
+                if name.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+                let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+
+                let device = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .custom_flags(libc::O_NOCTTY)
+                    .open(&path)?;
+                make_raw(&device)?;
+
+                Ok(Self {
+                    controller,
+                    path,
+                    _device: device,
+                })
+            }
+        }
+    }
+
+    /// Pass bytes through unchanged, as the device protocol is binary
+    fn make_raw(file: &File) -> io::Result<()> {
+        // SAFETY: termios is plain data filled in by tcgetattr
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(file.as_raw_fd(), &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(file.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
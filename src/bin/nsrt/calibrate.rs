@@ -0,0 +1,121 @@
+use crate::{open, print_json};
+use clap::Args;
+use nsrt::{Calibrator, Result, registry::Registry};
+use serde::Serialize;
+use std::{
+    io::{self, BufRead, Write},
+    time::{Duration, SystemTime},
+};
+
+/// Deviation in dB from the nominal level beyond which the setup, rather
+/// than the meter, is the likelier cause
+const SUSPICIOUS_OFFSET: f32 = 1.0;
+
+#[derive(Args)]
+pub struct CalibrateArgs {
+    /// Level in dB the calibrator produces
+    #[arg(long, default_value_t = 94.0)]
+    nominal: f32,
+    /// How long to wait for a steady tone
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+    /// Also record now as the calibration date, in the registry as the
+    /// device's own date can't be written
+    #[arg(long)]
+    record_date: bool,
+    /// Don't prompt; measure at once and store the offset
+    #[arg(short, long)]
+    yes: bool,
+}
+
+/// Result of `nsrt calibrate --json`
+#[derive(Serialize)]
+struct Calibrated {
+    serial: String,
+    nominal: f32,
+    measured: f32,
+    spread: f32,
+    offset: f32,
+    /// Offset in the registry before this calibration
+    previous_offset: f32,
+    /// Whether the offset was stored
+    stored: bool,
+}
+
+/// Measure a calibrator's tone and store the resulting offset in the
+/// registry
+pub fn calibrate(port: Option<&str>, args: &CalibrateArgs, json: bool) -> Result<()> {
+    let mut nsrt = open(port)?;
+    let serial = nsrt.read_serial_number()?;
+    let path = Registry::default_path();
+    let mut registry = Registry::load(&path)?;
+    let mut entry = registry.get(&serial).cloned().unwrap_or_default();
+
+    if !args.yes {
+        prompt(&format!(
+            "Fit the {} dB calibrator to the microphone of {serial}, switch it on and press Enter",
+            args.nominal
+        ))?;
+    }
+    eprintln!("Waiting for a steady tone...");
+    let calibration = nsrt.calibrate(&Calibrator {
+        timeout: args.timeout,
+        ..Calibrator::new(args.nominal)
+    })?;
+    let offset = calibration.offset();
+
+    if !json {
+        println!("nominal:  {:.2} dB", calibration.nominal);
+        println!(
+            "measured: {:.2} dB (spread {:.2} dB)",
+            calibration.measured, calibration.spread
+        );
+        println!("offset:   {offset:+.2} dB (was {:+.2} dB)", entry.offset);
+    }
+    if offset.abs() > SUSPICIOUS_OFFSET {
+        eprintln!(
+            "Warning: the meter is more than {SUSPICIOUS_OFFSET} dB off; check that the calibrator sits tightly and produces {} dB",
+            args.nominal
+        );
+    }
+
+    let previous_offset = entry.offset;
+    let stored = args.yes || confirm(&format!("Store the offset in {}?", path.display()))?;
+    if stored {
+        entry.offset = offset;
+        if args.record_date {
+            entry.calibrated = Some(SystemTime::now());
+        }
+        registry.insert(&serial, entry)?;
+        registry.save(&path)?;
+        eprintln!("Stored the offset of {serial}");
+    }
+
+    if json {
+        print_json(&Calibrated {
+            serial,
+            nominal: calibration.nominal,
+            measured: calibration.measured,
+            spread: calibration.spread,
+            offset,
+            previous_offset,
+            stored,
+        })?;
+    }
+    Ok(())
+}
+
+/// Show `message` and wait for Enter
+fn prompt(message: &str) -> Result<String> {
+    eprint!("{message} ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer)
+}
+
+/// Ask a yes/no `question`, no unless answered yes
+fn confirm(question: &str) -> Result<bool> {
+    let answer = prompt(&format!("{question} [y/N]"))?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
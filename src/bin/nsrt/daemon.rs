@@ -0,0 +1,1377 @@
+//! Unattended logging driven by a TOML configuration file
+//!
+//! See `docs/nsrt-log.toml` for an annotated configuration.
+
+#[cfg(feature = "rpi")]
+use nsrt::rpi::GpioAlarm;
+use nsrt::{
+    Alarm, AlarmMonitor, AlarmState, Backpressure, DeviceConfig, DutyCycle, Interpolation,
+    Measurement, Metadata, NSRT, NsrtError, PortSettings, Priority, Quality, QueuedSink, Recovery,
+    Resampler, Result, Sampler, SamplerEvent, Sink, Temperature, Threshold, Watchdog,
+    binlog::BinaryLogWriter,
+    csv::CsvWriter,
+    encryption::{EncryptedLogWriter, LogKey},
+    registry::Registry,
+    report::{DailyReports, TimeZone},
+    schedule::{Schedule, SurveyPlan, Window},
+    sqlite::SqliteLog,
+    webhook::{Webhook, WebhookSink},
+};
+use serde::Deserialize;
+use std::{
+    fs, io, mem,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::TcpListener, runtime::Runtime, sync::broadcast::error::RecvError, time::MissedTickBehavior,
+};
+
+/// Measurements buffered for slow sinks before the oldest are skipped
+const BUFFER: usize = 1024;
+
+/// Time between checks of the configuration file for changes
+const RELOAD_CHECK: Duration = Duration::from_secs(2);
+
+/// Time between deletions and compactions of aged data
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Period aged SQLite rows are compacted into
+const COMPACTION_PERIOD: Duration = Duration::from_secs(3600);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Serial port of the device, or its alias in the device registry, the
+    /// first one found if unset
+    port: Option<String>,
+    /// Control lines and flow control of the serial port
+    #[serde(default)]
+    serial: PortSettings,
+    #[serde(with = "humantime_serde", default = "default_interval")]
+    interval: Duration,
+    /// Wait between attempts to open the device
+    #[serde(with = "humantime_serde", default = "default_reconnect_delay")]
+    reconnect_delay: Duration,
+    /// Time without measurements after which recovery starts
+    #[serde(with = "humantime_serde", default = "default_stall_timeout")]
+    stall_timeout: Duration,
+    /// Bursts to measure in instead of every interval, to save power
+    duty_cycle: Option<DutyCycle>,
+    /// Settings applied to the device every time it is opened
+    device: Option<DeviceConfig>,
+    #[serde(default)]
+    metadata: Metadata,
+    /// Warning level without dwell time, shorthand for `[alarm.warning]`
+    threshold: Option<Threshold>,
+    /// Warning and critical levels reported when entered and left, varying
+    /// by time of day if periods are set
+    alarm: Option<Schedule>,
+    /// Windows of time to record in, with their own interval and device
+    /// settings, recording at all times if unset
+    plan: Option<SurveyPlan>,
+    /// Endpoints notified of alarms and device faults
+    #[serde(default, rename = "webhook")]
+    webhooks: Vec<WebhookConfig>,
+    /// Raspberry Pi pin driven while the lowest alarm level is exceeded
+    #[cfg(feature = "rpi")]
+    gpio: Option<GpioConfig>,
+    /// Address to serve the driver metrics on for Prometheus, read at start
+    metrics: Option<SocketAddr>,
+    #[serde(rename = "sink")]
+    sinks: Vec<SinkConfig>,
+}
+
+impl Config {
+    /// The alarm schedule, from `[alarm]` or the `threshold` shorthand
+    fn alarm(&self) -> Option<Schedule> {
+        self.alarm.clone().or_else(|| {
+            self.threshold
+                .map(|threshold| Schedule::new(Alarm::new().warning(threshold.into())))
+        })
+    }
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_reconnect_delay() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_stall_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct WebhookConfig {
+    url: String,
+    /// Payload with `{{name}}` placeholders, see `nsrt::webhook`
+    template: Option<String>,
+    /// Only notify of alarms reaching this state
+    alarm: Option<AlarmState>,
+}
+
+#[cfg(feature = "rpi")]
+#[derive(Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct GpioConfig {
+    /// BCM GPIO number
+    pin: u8,
+    /// Minimum time the pin stays on
+    #[serde(with = "humantime_serde", default)]
+    hold: Duration,
+    #[serde(default)]
+    active_low: bool,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum SinkConfig {
+    Csv(LogFileConfig),
+    Binlog(LogFileConfig),
+    Sqlite(LogFileConfig),
+    Report {
+        dir: PathBuf,
+        /// Age after which reports are deleted
+        #[serde(with = "humantime_serde", default)]
+        retain: Option<Duration>,
+        /// IANA time zone whose days and hours the reports follow, UTC if
+        /// unset
+        time_zone: Option<String>,
+        queue: Option<usize>,
+        #[serde(default)]
+        backpressure: Backpressure,
+    },
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct LogFileConfig {
+    path: PathBuf,
+    #[serde(default)]
+    rotate: Rotation,
+    /// Grid to move measurements onto before aggregating or writing them
+    #[serde(with = "humantime_serde", default)]
+    resample: Option<Duration>,
+    /// Period to aggregate measurements over before writing them
+    #[serde(with = "humantime_serde", default)]
+    aggregate: Option<Duration>,
+    /// Age after which rotated files, or rows of an unrotated SQLite
+    /// database, are deleted
+    #[serde(with = "humantime_serde", default)]
+    retain: Option<Duration>,
+    /// Age after which rows of an SQLite database are compacted into hourly
+    /// aggregates
+    #[serde(with = "humantime_serde", default)]
+    compact: Option<Duration>,
+    /// File holding the hex key to encrypt a binary log with
+    key_file: Option<PathBuf>,
+    /// Environment variable holding the hex key to encrypt a binary log with
+    key_env: Option<String>,
+    /// Only log while the alarm is at this state or above
+    alarm: Option<AlarmState>,
+    /// Measurements queued for the sink, which then writes on a thread of
+    /// its own instead of holding up the others
+    queue: Option<usize>,
+    /// What to do with measurements once the queue is full
+    #[serde(default)]
+    backpressure: Backpressure,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Rotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotation {
+    /// Length of a period in seconds and of its label in file names
+    fn period(self) -> Option<(u64, usize)> {
+        match self {
+            Self::Never => None,
+            Self::Hourly => Some((3600, 13)),
+            Self::Daily => Some((86_400, 10)),
+        }
+    }
+}
+
+/// Log according to the configuration at `path` until `stop` completes
+///
+/// The file is checked for changes while logging. Valid changes are applied
+/// without closing the device, unless its port changed, and sinks whose
+/// settings are unchanged keep their open files and partial aggregates.
+pub fn run(path: &Path, stop: impl Future<Output = Result<()>>) -> Result<()> {
+    let mut daemon = Daemon::new(path)?;
+    let metrics = daemon.config.metrics;
+    let logged = Runtime::new()?.block_on(async {
+        if let Some(addr) = metrics {
+            serve_metrics(addr).await?;
+        }
+        daemon.log(stop).await
+    });
+
+    let mut flushed = Ok(());
+    for (_, sink) in &mut daemon.sinks {
+        flushed = flushed.and(sink.flush());
+    }
+    logged.and(flushed)
+}
+
+/// Serve the driver metrics at `addr` in the background
+async fn serve_metrics(addr: SocketAddr) -> Result<()> {
+    let handle = nsrt::metrics::install_prometheus()?;
+    let listener = TcpListener::bind(addr).await?;
+    eprintln!("nsrt: serving metrics on http://{addr}/metrics");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, nsrt::http::metrics(handle)).await {
+            eprintln!("nsrt: serving metrics failed: {e}");
+        }
+    });
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Config> {
+    let mut config: Config = toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| NsrtError::InvalidParameter(format!("{}: {}", path.display(), e.message())))?;
+    if config.sinks.is_empty() {
+        return Err(NsrtError::InvalidParameter(format!(
+            "{}: no sinks configured",
+            path.display()
+        )));
+    }
+    if config.stall_timeout <= config.interval {
+        return Err(NsrtError::InvalidParameter(format!(
+            "{}: stall_timeout must be longer than interval",
+            path.display()
+        )));
+    }
+    if let Some(duty_cycle) = &config.duty_cycle {
+        duty_cycle
+            .validate()
+            .map_err(|e| NsrtError::InvalidParameter(format!("{}: {e}", path.display())))?;
+        if config.stall_timeout <= duty_cycle.period {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: stall_timeout must be longer than the duty cycle period",
+                path.display()
+            )));
+        }
+    }
+    for sink in &config.sinks {
+        if let SinkConfig::Csv(file) | SinkConfig::Binlog(file) = sink
+            && file.retain.is_some()
+            && file.rotate == Rotation::Never
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: {} needs rotate to be pruned",
+                path.display(),
+                file.path.display()
+            )));
+        }
+        if let SinkConfig::Csv(file) | SinkConfig::Binlog(file) = sink
+            && file.compact.is_some()
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: {} is not an SQLite database and cannot be compacted",
+                path.display(),
+                file.path.display()
+            )));
+        }
+        if let SinkConfig::Csv(file) | SinkConfig::Sqlite(file) = sink
+            && (file.key_file.is_some() || file.key_env.is_some())
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: {} is not a binary log and cannot be encrypted",
+                path.display(),
+                file.path.display()
+            )));
+        }
+        if let SinkConfig::Binlog(file) = sink
+            && file.key_file.is_some()
+            && file.key_env.is_some()
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: {} has both key_file and key_env",
+                path.display(),
+                file.path.display()
+            )));
+        }
+    }
+    if config.sinks.iter().any(|sink| {
+        sink.queue().is_some_and(|(capacity, backpressure)| {
+            capacity == 0 || backpressure == Backpressure::Sample(0)
+        })
+    }) {
+        return Err(NsrtError::InvalidParameter(format!(
+            "{}: sink queues and samples must not be zero",
+            path.display()
+        )));
+    }
+    if config.threshold.is_some() && config.alarm.is_some() {
+        return Err(NsrtError::InvalidParameter(format!(
+            "{}: set either threshold or alarm, not both",
+            path.display()
+        )));
+    }
+    if let Some(schedule) = &config.alarm {
+        let alarms: Vec<&Alarm> = std::iter::once(&schedule.alarm)
+            .chain(schedule.periods.iter().map(|period| &period.alarm))
+            .collect();
+        if alarms
+            .iter()
+            .all(|alarm| alarm.warning.is_none() && alarm.critical.is_none())
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: alarm needs a warning or critical level",
+                path.display()
+            )));
+        }
+        for alarm in alarms {
+            if let (Some(warning), Some(critical)) = (alarm.warning, alarm.critical)
+                && critical.level <= warning.level
+            {
+                return Err(NsrtError::InvalidParameter(format!(
+                    "{}: the critical alarm level must be above the warning level",
+                    path.display()
+                )));
+            }
+        }
+    }
+    if config.alarm().is_none()
+        && (config.sinks.iter().any(|sink| sink.alarm().is_some())
+            || config
+                .webhooks
+                .iter()
+                .any(|webhook| webhook.alarm.is_some()))
+    {
+        return Err(NsrtError::InvalidParameter(format!(
+            "{}: sinks and webhooks limited to an alarm state need an alarm",
+            path.display()
+        )));
+    }
+    if let Some(plan) = &config.plan {
+        if plan.windows.is_empty() {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: plan needs a window",
+                path.display()
+            )));
+        }
+        if plan
+            .windows
+            .iter()
+            .filter_map(|window| window.interval)
+            .any(|interval| config.stall_timeout <= interval)
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "{}: stall_timeout must be longer than the interval of every window",
+                path.display()
+            )));
+        }
+    }
+    #[cfg(feature = "rpi")]
+    if config.gpio.is_some() && config.alarm().is_none() {
+        return Err(NsrtError::InvalidParameter(format!(
+            "{}: gpio needs a threshold or alarm",
+            path.display()
+        )));
+    }
+    // Name the logs after the device's alias unless they are named already
+    if let Some(port) = &config.port
+        && config.metadata.device.is_none()
+        && Registry::load_default()?.serial_of(port).is_some()
+    {
+        config.metadata.device = Some(port.clone());
+    }
+    Ok(config)
+}
+
+/// Open sinks along with the configuration they were opened from
+type Sinks = Vec<(SinkConfig, Box<dyn Sink + Send>)>;
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+struct Daemon<'a> {
+    path: &'a Path,
+    modified: Option<SystemTime>,
+    config: Config,
+    sinks: Sinks,
+    /// Alarm state, with the schedule its levels follow
+    alarm: Option<(Schedule, AlarmMonitor)>,
+    /// Survey window open at the last measurement, `None` before the first
+    /// measurement following the plan
+    window: Option<Option<Window>>,
+    /// Whether the settings of the current survey window are still to be
+    /// applied to the device
+    resettle: bool,
+    webhooks: Option<WebhookSink>,
+    #[cfg(feature = "rpi")]
+    gpio: Option<GpioAlarm>,
+    watchdog: Watchdog,
+    /// Whether the webhooks were told about the current device outage
+    faulted: bool,
+    /// When aged data was last deleted and compacted
+    maintained: Option<Instant>,
+}
+
+impl<'a> Daemon<'a> {
+    fn new(path: &'a Path) -> Result<Self> {
+        let modified = modified(path);
+        let config = load(path)?;
+        let sinks = open_sinks(&config, &mut Vec::new(), false)?;
+
+        Ok(Self {
+            path,
+            modified,
+            alarm: config.alarm().map(monitor),
+            window: None,
+            resettle: false,
+            webhooks: open_webhooks(&config),
+            #[cfg(feature = "rpi")]
+            gpio: open_gpio(&config)?,
+            watchdog: Watchdog::new(config.stall_timeout),
+            faulted: false,
+            maintained: None,
+            config,
+            sinks,
+        })
+    }
+
+    /// Sample the device into the sinks, reopening it whenever it fails
+    async fn log(&mut self, stop: impl Future<Output = Result<()>>) -> Result<()> {
+        tokio::pin!(stop);
+        let mut check = tokio::time::interval(RELOAD_CHECK);
+        check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Whether the device was open before, so that the first measurement
+        // after reopening it is flagged
+        let mut reopened = false;
+
+        loop {
+            if let Some(config) = self.reload() {
+                self.apply(config, None).await;
+            }
+
+            let nsrt = match open(&self.config) {
+                Ok(nsrt) => nsrt,
+                Err(e) => {
+                    eprintln!(
+                        "nsrt: cannot open device: {e} (error {}); retrying in {}",
+                        e.code(),
+                        humantime::format_duration(self.config.reconnect_delay)
+                    );
+                    self.fault(&e);
+                    tokio::select! {
+                        () = tokio::time::sleep(self.config.reconnect_delay) => continue,
+                        result = &mut stop => return result,
+                    }
+                }
+            };
+
+            let sampler = match self.config.duty_cycle {
+                // Validated when loaded
+                Some(duty_cycle) => Sampler::start_duty_cycled(nsrt, duty_cycle)?,
+                None => Sampler::start(nsrt, self.config.interval),
+            };
+            sampler.set_interval(self.config.interval);
+            let mut rx = sampler.broadcast(BUFFER).subscribe();
+            let events = sampler.events();
+            eprintln!("nsrt: logging");
+            self.faulted = false;
+            self.watchdog.restart();
+            // The device was opened with the settings outside any window
+            self.resettle = self.config.plan.is_some();
+            let mut first = std::mem::replace(&mut reopened, true);
+            if first {
+                metrics::counter!(nsrt::metrics::RECONNECTS).increment(1);
+            }
+
+            let stop = loop {
+                tokio::select! {
+                    received = rx.recv() => match received {
+                        Ok(mut measurement) => {
+                            if std::mem::take(&mut first) {
+                                measurement.quality |=
+                                    Quality::RECONNECTED | Quality::MISSING_SAMPLES;
+                            }
+                            if self.watchdog.feed(&measurement) {
+                                eprintln!("nsrt: sampling recovered");
+                            }
+                            self.write(&measurement)?;
+                            metrics::gauge!(nsrt::metrics::SINK_QUEUE_DEPTH, "queue" => "sinks")
+                                .set(rx.len() as f64);
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            eprintln!("nsrt: sinks fell behind, skipped {skipped} measurements");
+                        }
+                        Err(RecvError::Closed) => break None,
+                    },
+                    _ = check.tick() => {
+                        for event in events.try_iter() {
+                            if let SamplerEvent::Resumed { slept } = event {
+                                eprintln!(
+                                    "nsrt: host resumed after sleeping {}",
+                                    humantime::format_duration(Duration::from_secs(slept.as_secs()))
+                                );
+                            }
+                        }
+                        if let Some(config) = self.reload()
+                            && self.apply(config, Some(&sampler)).await
+                        {
+                            break None;
+                        }
+                        self.settle(&sampler).await;
+                        if self.recover(&sampler).await {
+                            break None;
+                        }
+                        self.report_webhooks();
+                        self.maintain();
+                    }
+                    result = &mut stop => break Some(result),
+                }
+            };
+
+            let stopped = stop_sampler(sampler, self.config.stall_timeout).await;
+            if let Some(result) = stop {
+                return result.and(stopped);
+            }
+            if let Err(e) = stopped {
+                eprintln!(
+                    "nsrt: device failed: {e} (error {}); reconnecting",
+                    e.code()
+                );
+                self.fault(&e);
+            }
+        }
+    }
+
+    /// Take the recovery step the watchdog asks for, returning whether the
+    /// device must be reopened
+    async fn recover(&mut self, sampler: &Sampler) -> bool {
+        match self.watchdog.check() {
+            None => false,
+            Some(Recovery::Resync) => {
+                eprintln!(
+                    "nsrt: no measurement for {}; resynchronizing",
+                    humantime::format_duration(self.config.stall_timeout)
+                );
+                let resync = sampler
+                    .device()
+                    .call_async_with_priority(Priority::High, |nsrt| nsrt.resync());
+                match tokio::time::timeout(self.config.stall_timeout, resync).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        eprintln!("nsrt: resynchronizing failed: {e} (error {})", e.code());
+                    }
+                    Err(_) => eprintln!("nsrt: resynchronizing timed out"),
+                }
+                false
+            }
+            Some(Recovery::Reconnect) => {
+                eprintln!("nsrt: sampling stalled; reconnecting");
+                true
+            }
+            Some(Recovery::Escalate(fault)) => {
+                eprintln!("nsrt: fault: {fault}; still reconnecting");
+                if let Some(webhooks) = &self.webhooks {
+                    webhooks.fault(&fault);
+                }
+                self.faulted = true;
+                true
+            }
+        }
+    }
+
+    /// Delete data past the sinks' retention and compact aged data, at most
+    /// every [`MAINTENANCE_INTERVAL`]
+    fn maintain(&mut self) {
+        if self
+            .maintained
+            .is_some_and(|maintained| maintained.elapsed() < MAINTENANCE_INTERVAL)
+        {
+            return;
+        }
+        self.maintained = Some(Instant::now());
+
+        let now = SystemTime::now();
+        for (config, sink) in &mut self.sinks {
+            match config.prune(now) {
+                Ok(0) => {}
+                Ok(deleted) => eprintln!("nsrt: pruned {deleted} expired files or rows"),
+                Err(e) => eprintln!("nsrt: cannot prune expired data: {e}"),
+            }
+            // Compact what the sink has buffered too
+            let compacted = sink.flush().and_then(|()| config.compact(now));
+            match compacted {
+                Ok(0) => {}
+                Ok(compacted) => eprintln!("nsrt: compacted {compacted} rows"),
+                Err(e) => eprintln!("nsrt: cannot compact aged data: {e}"),
+            }
+        }
+    }
+
+    /// Print the webhook deliveries given up on since the last call
+    fn report_webhooks(&self) {
+        if let Some(webhooks) = &self.webhooks {
+            for e in webhooks.take_errors() {
+                eprintln!("nsrt: {e}");
+            }
+        }
+    }
+
+    /// Notify the webhooks of a device failure, once per outage
+    fn fault(&mut self, error: &NsrtError) {
+        if let Some(webhooks) = &self.webhooks
+            && !self.faulted
+        {
+            webhooks.fault(error);
+        }
+        self.faulted = true;
+    }
+
+    /// Apply the interval and device settings of the survey window just
+    /// entered, or the configured ones once every window closed
+    async fn settle(&mut self, sampler: &Sampler) {
+        if !mem::take(&mut self.resettle) {
+            return;
+        }
+        let window = self.window.clone().flatten();
+        sampler.set_interval(
+            window
+                .as_ref()
+                .and_then(|window| window.interval)
+                .unwrap_or(self.config.interval),
+        );
+        if let Some(device) = window
+            .and_then(|window| window.device)
+            .or(self.config.device)
+            && let Err(e) = sampler
+                .device()
+                .call_async(move |nsrt| nsrt.configure(&device))
+                .await
+        {
+            eprintln!("nsrt: cannot configure device: {e}");
+        }
+    }
+
+    /// Follow the survey plan to the time of a measurement, returning
+    /// whether it is to be recorded
+    fn follow_plan(&mut self, time: SystemTime) -> Result<bool> {
+        let Some(plan) = &self.config.plan else {
+            return Ok(true);
+        };
+        let window = plan.window_at(time).ok().flatten();
+        if self
+            .window
+            .as_ref()
+            .is_some_and(|open| open.as_ref() == window)
+        {
+            return Ok(window.is_some());
+        }
+
+        match window {
+            Some(window) => match &window.name {
+                Some(name) => eprintln!("nsrt: the {name} survey window opened; recording"),
+                None => eprintln!(
+                    "nsrt: the survey window `{}` opened; recording",
+                    window.when
+                ),
+            },
+            None => eprintln!("nsrt: no survey window open; paused"),
+        }
+        let recording = window.is_some();
+        let was_recording = self.window.as_ref().is_some_and(Option::is_some);
+        self.window = Some(window.cloned());
+        self.resettle = true;
+        if was_recording && !recording {
+            // Close the session just ended, e.g. a partial aggregate
+            for (_, sink) in &mut self.sinks {
+                sink.flush()?;
+            }
+        }
+        Ok(recording)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let recording = self.follow_plan(measurement.timestamp)?;
+        let previous = self.alarm_state();
+        if let Some((schedule, monitor)) = &mut self.alarm
+            && !schedule.periods.is_empty()
+        {
+            let period = schedule.period_at(measurement.timestamp).ok().flatten();
+            let levels = period.map_or(schedule.alarm, |period| period.alarm);
+            if levels != monitor.alarm() {
+                match period.and_then(|period| period.name.as_deref()) {
+                    Some(name) => eprintln!("nsrt: the {name} alarm levels apply"),
+                    None => eprintln!("nsrt: the scheduled alarm levels changed"),
+                }
+                monitor.set_alarm(levels);
+                if let Some(webhooks) = &mut self.webhooks {
+                    webhooks.set_alarm(levels);
+                }
+            }
+        }
+        if let Some(transition) = self
+            .alarm
+            .as_mut()
+            .and_then(|(_, monitor)| monitor.update(measurement))
+        {
+            let verb = if transition.is_escalation() {
+                "raises"
+            } else {
+                "lowers"
+            };
+            eprintln!(
+                "nsrt: level {:.1} dB {verb} the alarm from {} to {}",
+                measurement.level, transition.from, transition.to
+            );
+        }
+        let state = self.alarm_state();
+
+        for (config, sink) in &mut self.sinks {
+            match config.alarm() {
+                Some(alarm) if state < alarm => {
+                    // Close the episode just left, e.g. a partial aggregate
+                    if previous >= alarm {
+                        sink.flush()?;
+                    }
+                }
+                _ if !recording => {}
+                _ => sink.write(measurement)?,
+            }
+        }
+        if let Some(webhooks) = &mut self.webhooks {
+            webhooks.write(measurement)?;
+        }
+        self.report_webhooks();
+        #[cfg(feature = "rpi")]
+        if let Some(gpio) = &mut self.gpio {
+            gpio.write(measurement)?;
+        }
+        Ok(())
+    }
+
+    fn alarm_state(&self) -> AlarmState {
+        self.alarm
+            .as_ref()
+            .map_or(AlarmState::Normal, |(_, monitor)| monitor.state())
+    }
+
+    /// The configuration, if the file changed and is valid
+    fn reload(&mut self) -> Option<Config> {
+        let modified = modified(self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        match load(self.path) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("nsrt: keeping the current configuration: {e}");
+                None
+            }
+        }
+    }
+
+    /// Switch to `config`, returning whether the device must be reopened
+    async fn apply(&mut self, config: Config, sampler: Option<&Sampler>) -> bool {
+        let old = &self.config;
+        let metadata_changed = config.metadata != old.metadata;
+        if config.sinks != old.sinks || metadata_changed {
+            match open_sinks(&config, &mut self.sinks, metadata_changed) {
+                Ok(sinks) => self.sinks = sinks,
+                Err(e) => {
+                    eprintln!("nsrt: keeping the current configuration: {e}");
+                    return false;
+                }
+            }
+        }
+        if config.stall_timeout != old.stall_timeout {
+            self.watchdog = Watchdog::new(config.stall_timeout);
+        }
+        let alarm_changed = config.alarm() != old.alarm();
+        if alarm_changed {
+            self.alarm = config.alarm().map(monitor);
+        }
+        if config.webhooks != old.webhooks || alarm_changed {
+            // Let pending deliveries finish without holding up logging
+            let previous = mem::replace(&mut self.webhooks, open_webhooks(&config));
+            tokio::task::spawn_blocking(move || drop(previous));
+            // Pick up the levels of the current period
+            if let (Some(webhooks), Some((_, monitor))) = (&mut self.webhooks, &self.alarm) {
+                webhooks.set_alarm(monitor.alarm());
+            }
+        } else if metadata_changed && let Some(webhooks) = &mut self.webhooks {
+            let _ = webhooks.set_metadata(&config.metadata);
+        }
+        #[cfg(feature = "rpi")]
+        if config.gpio != old.gpio || alarm_changed {
+            // Release the pin before claiming it again
+            self.gpio = None;
+            self.gpio = open_gpio(&config).unwrap_or_else(|e| {
+                eprintln!("nsrt: cannot open gpio: {e}");
+                None
+            });
+        }
+
+        let reconnect = config.port != old.port || config.serial != old.serial;
+        if config.plan != old.plan {
+            // Announce the window open under the new plan
+            self.window = None;
+        }
+        if let Some(sampler) = sampler.filter(|_| !reconnect) {
+            if config.duty_cycle != old.duty_cycle
+                && let Err(e) = sampler.set_duty_cycle(config.duty_cycle)
+            {
+                eprintln!("nsrt: cannot change the duty cycle: {e}");
+            }
+            if config.plan.is_some() || config.plan != old.plan {
+                // Settled with the next check, a window's settings taking
+                // precedence over the configured ones
+                self.resettle = true;
+            } else {
+                if config.interval != old.interval {
+                    sampler.set_interval(config.interval);
+                }
+                if let Some(device) = config.device.filter(|d| Some(*d) != old.device)
+                    && let Err(e) = sampler
+                        .device()
+                        .call_async(move |nsrt| nsrt.configure(&device))
+                        .await
+                {
+                    eprintln!("nsrt: cannot configure device: {e}");
+                }
+            }
+        }
+
+        self.config = config;
+        eprintln!("nsrt: reloaded {}", self.path.display());
+        if reconnect {
+            eprintln!("nsrt: port changed; reconnecting");
+        }
+        reconnect
+    }
+}
+
+/// Open the sinks of `config`, reusing unchanged ones from `current`
+///
+/// Reused sinks are given the new metadata if it changed, and sinks no longer
+/// configured are flushed and closed. If a sink fails to open, `current` is
+/// left untouched.
+fn open_sinks(config: &Config, current: &mut Sinks, metadata_changed: bool) -> Result<Sinks> {
+    // Open everything new first, remembering which current sink to reuse
+    let mut reused = vec![false; current.len()];
+    let mut planned = Vec::with_capacity(config.sinks.len());
+    for sink_config in &config.sinks {
+        let reuse = (0..current.len()).find(|&i| !reused[i] && current[i].0 == *sink_config);
+        planned.push(match reuse {
+            Some(i) => {
+                reused[i] = true;
+                Err(i)
+            }
+            None => {
+                let mut sink = sink_config.open()?;
+                sink.set_metadata(&config.metadata)?;
+                Ok(sink)
+            }
+        });
+    }
+
+    let mut old: Vec<_> = mem::take(current).into_iter().map(Some).collect();
+    let mut sinks = Vec::with_capacity(planned.len());
+    for (sink_config, sink) in config.sinks.iter().zip(planned) {
+        let sink = match sink {
+            Ok(sink) => sink,
+            Err(i) => {
+                let (_, mut sink) = old[i].take().expect("each sink is reused once");
+                if metadata_changed && let Err(e) = sink.set_metadata(&config.metadata) {
+                    eprintln!("nsrt: cannot update sink metadata: {e}");
+                }
+                sink
+            }
+        };
+        sinks.push((sink_config.clone(), sink));
+    }
+
+    for (_, mut sink) in old.into_iter().flatten() {
+        if let Err(e) = sink.flush() {
+            eprintln!("nsrt: cannot flush removed sink: {e}");
+        }
+    }
+    Ok(sinks)
+}
+
+fn open_webhooks(config: &Config) -> Option<WebhookSink> {
+    if config.webhooks.is_empty() {
+        return None;
+    }
+
+    let webhooks = config.webhooks.iter().map(|webhook| {
+        let mut hook = Webhook::new(&webhook.url);
+        if let Some(template) = &webhook.template {
+            hook = hook.template(template);
+        }
+        if let Some(state) = webhook.alarm {
+            hook = hook.min_state(state);
+        }
+        hook
+    });
+    let mut sink = WebhookSink::new(webhooks);
+    if let Some(schedule) = config.alarm() {
+        sink = sink.alarm(schedule.alarm);
+    }
+    // Only fails for sinks that write files
+    let _ = sink.set_metadata(&config.metadata);
+    Some(sink)
+}
+
+/// Alarm monitor starting with the default levels of `schedule`
+fn monitor(schedule: Schedule) -> (Schedule, AlarmMonitor) {
+    let monitor = AlarmMonitor::new(schedule.alarm);
+    (schedule, monitor)
+}
+
+/// Stop `sampler`, abandoning it if its device hangs for `timeout`
+async fn stop_sampler(sampler: Sampler, timeout: Duration) -> Result<()> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    // A plain thread, as the runtime would wait for a hung blocking task
+    thread::spawn(move || {
+        let _ = tx.send(sampler.stop());
+    });
+
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(Ok(stopped)) => stopped,
+        _ => Err(NsrtError::IoError(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "device hangs, abandoned it",
+        ))),
+    }
+}
+
+#[cfg(feature = "rpi")]
+fn open_gpio(config: &Config) -> Result<Option<GpioAlarm>> {
+    let (Some(gpio), Some(threshold)) = (
+        &config.gpio,
+        config
+            .alarm()
+            .and_then(|schedule| schedule.alarm.warning.or(schedule.alarm.critical))
+            .map(|level| level.threshold()),
+    ) else {
+        return Ok(None);
+    };
+    let alarm = GpioAlarm::new(gpio.pin, threshold)?
+        .hold(gpio.hold)
+        .active_low(gpio.active_low);
+    Ok(Some(alarm))
+}
+
+fn open(config: &Config) -> Result<NSRT> {
+    let registry = Registry::load_default()?;
+    let mut nsrt = match &config.port {
+        Some(port) if registry.serial_of(port).is_some() => {
+            registry.open_with(port, &config.serial)?
+        }
+        Some(port) => NSRT::open_port_with(port, &config.serial)?,
+        None => NSRT::open_with(&config.serial)?,
+    };
+    if let Some(device) = &config.device {
+        nsrt.configure(device)?;
+    }
+    Ok(nsrt)
+}
+
+impl SinkConfig {
+    /// Alarm state from which the sink is written to, if limited
+    fn alarm(&self) -> Option<AlarmState> {
+        match self {
+            Self::Csv(file) | Self::Binlog(file) | Self::Sqlite(file) => file.alarm,
+            Self::Report { .. } => None,
+        }
+    }
+
+    /// Capacity and backpressure of the queue the sink writes behind, if any
+    fn queue(&self) -> Option<(usize, Backpressure)> {
+        match self {
+            Self::Csv(file) | Self::Binlog(file) | Self::Sqlite(file) => {
+                file.queue.map(|capacity| (capacity, file.backpressure))
+            }
+            Self::Report {
+                queue,
+                backpressure,
+                ..
+            } => queue.map(|capacity| (capacity, *backpressure)),
+        }
+    }
+
+    fn open(&self) -> Result<Box<dyn Sink + Send>> {
+        let sink: Box<dyn Sink + Send> = match self {
+            Self::Csv(file) => file.open(|path| Ok(Box::new(CsvWriter::append(path)?))),
+            Self::Binlog(file) => match file.key()? {
+                Some(key) => {
+                    file.open(move |path| Ok(Box::new(EncryptedLogWriter::append(path, &key)?)))
+                }
+                None => file.open(|path| Ok(Box::new(BinaryLogWriter::append(path)?))),
+            },
+            Self::Sqlite(file) => file.open(|path| Ok(Box::new(SqliteLog::open(path)?))),
+            Self::Report { dir, time_zone, .. } => {
+                let mut reports = DailyReports::new(dir);
+                if let Some(name) = time_zone {
+                    let time_zone = TimeZone::get(name).map_err(|e| {
+                        NsrtError::InvalidParameter(format!("time_zone {name}: {e}"))
+                    })?;
+                    reports = reports.time_zone(time_zone);
+                }
+                fs::create_dir_all(dir)?;
+                Box::new(reports)
+            }
+        };
+        Ok(match self.queue() {
+            Some((capacity, backpressure)) => {
+                Box::new(QueuedSink::new(sink, capacity, backpressure))
+            }
+            None => sink,
+        })
+    }
+
+    /// Delete what the sink stored longer than its retention before `now`,
+    /// returning the number of files or rows deleted
+    fn prune(&self, now: SystemTime) -> Result<u64> {
+        match self {
+            Self::Csv(file) | Self::Binlog(file) | Self::Sqlite(file) => {
+                let Some(cutoff) = file.retain.and_then(|retain| now.checked_sub(retain)) else {
+                    return Ok(0);
+                };
+                match (self, file.rotate.period()) {
+                    (_, Some((length, label_len))) => {
+                        let name = file.path.file_stem().unwrap_or_default().to_string_lossy();
+                        let extension = file
+                            .path
+                            .extension()
+                            .map_or(String::new(), |e| format!(".{}", e.to_string_lossy()));
+                        let dir = file
+                            .path
+                            .parent()
+                            .filter(|dir| !dir.as_os_str().is_empty())
+                            .unwrap_or(Path::new("."));
+                        prune_files(dir, cutoff, |file_name| {
+                            let label = file_name
+                                .strip_prefix(&format!("{name}-"))?
+                                .strip_suffix(&extension)?;
+                            let start = period_start(label, label_len)?;
+                            Some(start + Duration::from_secs(length))
+                        })
+                    }
+                    (Self::Sqlite(_), None) if file.path.exists() => {
+                        SqliteLog::open(&file.path)?.prune(cutoff)
+                    }
+                    _ => Ok(0),
+                }
+            }
+            Self::Report { dir, retain, .. } => {
+                let Some(cutoff) = retain.and_then(|retain| now.checked_sub(retain)) else {
+                    return Ok(0);
+                };
+                prune_files(dir, cutoff, |file_name| {
+                    let start = period_start(file_name.strip_suffix(".json")?, 10)?;
+                    Some(start + Duration::from_secs(86_400))
+                })
+            }
+        }
+    }
+
+    /// Compact the rows of an unrotated SQLite database older than its
+    /// compaction age before `now`, returning the number of rows compacted
+    fn compact(&self, now: SystemTime) -> Result<u64> {
+        let Self::Sqlite(file) = self else {
+            return Ok(0);
+        };
+        match file.compact.and_then(|age| now.checked_sub(age)) {
+            Some(before) if file.rotate == Rotation::Never && file.path.exists() => {
+                SqliteLog::open(&file.path)?.compact(before, COMPACTION_PERIOD)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+/// Delete the files in `dir` whose data ends, according to `end`, by
+/// `cutoff`, returning how many were deleted
+fn prune_files(
+    dir: &Path,
+    cutoff: SystemTime,
+    end: impl Fn(&str) -> Option<SystemTime>,
+) -> Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut deleted = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name
+            .to_str()
+            .and_then(&end)
+            .is_some_and(|end| end <= cutoff)
+        {
+            fs::remove_file(entry.path())?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Start of the UTC period labelled `label` in a file name, `2024-05-01` for
+/// a day or `2024-05-01T13` for an hour
+fn period_start(label: &str, label_len: usize) -> Option<SystemTime> {
+    let time = match label_len {
+        10 if label.len() == 10 => format!("{label}T00:00:00Z"),
+        13 if label.len() == 13 => format!("{label}:00:00Z"),
+        _ => return None,
+    };
+    humantime::parse_rfc3339(&time).ok()
+}
+
+impl LogFileConfig {
+    fn open(
+        &self,
+        open: impl Fn(&Path) -> Result<Box<dyn Sink + Send>> + Send + 'static,
+    ) -> Box<dyn Sink + Send> {
+        let sink: Box<dyn Sink + Send> =
+            Box::new(Rotating::new(&self.path, self.rotate, Box::new(open)));
+        let sink: Box<dyn Sink + Send> = match self.aggregate {
+            Some(period) if !period.is_zero() => Box::new(Aggregate::new(sink, period)),
+            _ => sink,
+        };
+        match self.resample {
+            Some(step) if !step.is_zero() => {
+                Box::new(Resampler::new(sink, step, Interpolation::Linear))
+            }
+            _ => sink,
+        }
+    }
+
+    /// Key to encrypt the log with, read when the sink is opened
+    fn key(&self) -> Result<Option<LogKey>> {
+        if let Some(path) = &self.key_file {
+            return LogKey::from_file(path).map(Some);
+        }
+        let Some(var) = &self.key_env else {
+            return Ok(None);
+        };
+        let hex = std::env::var(var)
+            .map_err(|_| NsrtError::InvalidParameter(format!("{var} is not set")))?;
+        LogKey::from_hex(&hex).map(Some)
+    }
+}
+
+/// Opens the file a [`Rotating`] sink logs a period to
+type OpenFn = Box<dyn Fn(&Path) -> Result<Box<dyn Sink + Send>> + Send>;
+
+/// Log file sink starting a new file every hour or day
+///
+/// Rotated files are named after the UTC start of their period, e.g.
+/// `levels.csv` becomes `levels-2024-05-01.csv` or `levels-2024-05-01T13.csv`.
+/// Files are appended to, so restarts continue the current file.
+struct Rotating {
+    path: PathBuf,
+    rotation: Rotation,
+    open: OpenFn,
+    metadata: Metadata,
+    current: Option<(u64, Box<dyn Sink + Send>)>,
+}
+
+impl Rotating {
+    fn new(path: &Path, rotation: Rotation, open: OpenFn) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            rotation,
+            open,
+            metadata: Metadata::default(),
+            current: None,
+        }
+    }
+
+    /// Index of the period containing `time` and the file it is logged to
+    fn period(&self, time: SystemTime) -> (u64, PathBuf) {
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let Some((length, label_len)) = self.rotation.period() else {
+            return (0, self.path.clone());
+        };
+
+        let period = secs / length;
+        let start = UNIX_EPOCH + Duration::from_secs(period * length);
+        let label = &humantime::format_rfc3339_seconds(start).to_string()[..label_len];
+
+        let mut name = self.path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("-{label}"));
+        if let Some(extension) = self.path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        (period, self.path.with_file_name(name))
+    }
+}
+
+impl Sink for Rotating {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.metadata = metadata.clone();
+        if let Some((_, sink)) = &mut self.current {
+            sink.set_metadata(metadata)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let (period, path) = self.period(measurement.timestamp);
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|(current, _)| *current != period)
+        {
+            if let Some((_, mut sink)) = self.current.take() {
+                sink.flush()?;
+            }
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            let mut sink = (self.open)(&path)?;
+            sink.set_metadata(&self.metadata)?;
+            self.current = Some((period, sink));
+        }
+
+        let (_, sink) = self.current.as_mut().expect("opened above");
+        sink.write(measurement)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.current {
+            Some((_, sink)) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Sink combining the measurements of each period into one
+///
+/// Periods are aligned to multiples of their length since the Unix epoch. The
+/// combined measurement is stamped with the period start and holds the
+/// maximum level, the energy average of the LEQs and the mean temperature. It
+/// carries the quality flags of all the measurements, and is flagged as
+/// missing samples if they start or end more than one and a half of their
+/// average spacing away from the period's bounds.
+pub struct Aggregate<S> {
+    inner: S,
+    period: Duration,
+    current: Option<Period>,
+}
+
+struct Period {
+    index: u128,
+    start: SystemTime,
+    count: u32,
+    level: f32,
+    energy: f64,
+    temperature: f64,
+    quality: Quality,
+    first: SystemTime,
+    last: Measurement,
+}
+
+impl<S: Sink> Aggregate<S> {
+    pub fn new(inner: S, period: Duration) -> Self {
+        Self {
+            inner,
+            period,
+            current: None,
+        }
+    }
+
+    fn emit(&mut self) -> Result<()> {
+        let Some(period) = self.current.take() else {
+            return Ok(());
+        };
+        let count = f64::from(period.count);
+        let mut quality = period.quality;
+        if period.count > 1 {
+            let covered = period
+                .last
+                .timestamp
+                .duration_since(period.first)
+                .unwrap_or_default();
+            let slack = covered / (period.count - 1) * 3 / 2;
+            let lead = period
+                .first
+                .duration_since(period.start)
+                .unwrap_or_default();
+            let end = period.start + self.period;
+            let trail = end
+                .duration_since(period.last.timestamp)
+                .unwrap_or_default();
+            if lead > slack || trail > slack {
+                quality |= Quality::MISSING_SAMPLES;
+            }
+        }
+        self.inner.write(&Measurement {
+            timestamp: period.start,
+            level: period.level,
+            leq: (10.0 * (period.energy / count).log10()) as f32,
+            temperature: Temperature::from_celsius((period.temperature / count) as f32),
+            quality,
+            ..period.last
+        })
+    }
+}
+
+impl<S: Sink> Sink for Aggregate<S> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.inner.set_metadata(metadata)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let since_epoch = measurement
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let length = self.period.as_nanos();
+        let index = since_epoch.as_nanos() / length;
+
+        if self.current.as_ref().is_some_and(|p| p.index != index) {
+            self.emit()?;
+        }
+        let period = self.current.get_or_insert_with(|| Period {
+            index,
+            start: UNIX_EPOCH
+                + Duration::from_nanos(u64::try_from(index * length).unwrap_or(u64::MAX)),
+            count: 0,
+            level: f32::NEG_INFINITY,
+            energy: 0.0,
+            temperature: 0.0,
+            quality: Quality::GOOD,
+            first: measurement.timestamp,
+            last: *measurement,
+        });
+
+        period.count += 1;
+        period.level = period.level.max(measurement.level);
+        period.energy += 10f64.powf(f64::from(measurement.leq) / 10.0);
+        period.temperature += f64::from(measurement.temperature.as_celsius());
+        period.quality |= measurement.quality;
+        period.last = *measurement;
+        Ok(())
+    }
+
+    /// Write the partial current period and flush the inner sink
+    fn flush(&mut self) -> Result<()> {
+        self.emit()?;
+        self.inner.flush()
+    }
+}
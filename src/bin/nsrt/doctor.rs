@@ -0,0 +1,279 @@
+use crate::{open, print_json};
+use nsrt::{CheckStatus, ErrorKind, NSRT, NsrtError};
+use serde::Serialize;
+use std::{io, process::ExitCode};
+
+/// Outcome of one step of the diagnosis
+#[derive(Serialize)]
+struct Finding {
+    step: &'static str,
+    status: CheckStatus,
+    detail: String,
+    /// What to do about a warning or failure
+    hint: Option<String>,
+}
+
+/// Diagnosis as printed with `--json`
+#[derive(Serialize)]
+struct Diagnosis<'a> {
+    /// Whether every step passed, possibly with warnings
+    ready: bool,
+    findings: &'a [Finding],
+}
+
+impl Finding {
+    fn pass(step: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            step,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(step: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            step,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Walk through everything between the USB cable and a working meter,
+/// stopping at the first step that fails, and print what was found with
+/// hints for fixing it
+pub fn doctor(port: Option<&str>, json: bool) -> ExitCode {
+    let mut findings = Vec::new();
+    diagnose(port, &mut findings);
+    let failed = findings
+        .iter()
+        .any(|finding| finding.status == CheckStatus::Fail);
+    let exit = if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    };
+
+    if json {
+        if let Err(e) = print_json(&Diagnosis {
+            ready: !failed,
+            findings: &findings,
+        }) {
+            eprintln!("nsrt: {e}");
+        }
+        return exit;
+    }
+
+    for finding in &findings {
+        let status = match finding.status {
+            CheckStatus::Pass => "ok",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        };
+        println!("{status:<4}  {:<11}  {}", finding.step, finding.detail);
+        if let Some(hint) = &finding.hint {
+            println!("{:<17}-> {hint}", "");
+        }
+    }
+
+    let warned = findings
+        .iter()
+        .any(|finding| finding.status == CheckStatus::Warn);
+    if failed {
+        println!(
+            "\nThe meter is not ready; fix the first failure above and run `nsrt doctor` again"
+        );
+    } else if warned {
+        println!("\nThe meter works, but see the warnings above");
+    } else {
+        println!("\nThe meter is ready");
+    }
+    exit
+}
+
+fn diagnose(port: Option<&str>, findings: &mut Vec<Finding>) {
+    if port.is_none() {
+        #[cfg(target_os = "linux")]
+        if let Some(finding) = usb() {
+            let failed = finding.status == CheckStatus::Fail;
+            findings.push(finding);
+            if failed {
+                return;
+            }
+        }
+
+        let finding = ports();
+        let failed = finding.status == CheckStatus::Fail;
+        findings.push(finding);
+        if failed {
+            return;
+        }
+    }
+
+    let path = match port {
+        Some(port) => port.to_string(),
+        None => match NSRT::ports() {
+            Ok(ports) if !ports.is_empty() => ports[0].clone(),
+            _ => return,
+        },
+    };
+
+    #[cfg(unix)]
+    if std::path::Path::new(&path).exists() {
+        let finding = permissions(&path);
+        let failed = finding.status == CheckStatus::Fail;
+        findings.push(finding);
+        if failed {
+            return;
+        }
+    }
+
+    let mut nsrt = match open(port.or(Some(&path))) {
+        Ok(nsrt) => {
+            findings.push(Finding::pass("open", format!("{path} answers")));
+            nsrt
+        }
+        Err(e) => {
+            findings.push(Finding::fail("open", format!("{path}: {e}"), open_hint(&e)));
+            return;
+        }
+    };
+
+    let report = nsrt.self_test();
+    let problems: Vec<&str> = report
+        .checks
+        .iter()
+        .filter(|check| check.status != CheckStatus::Pass)
+        .map(|check| check.name)
+        .collect();
+    findings.push(Finding {
+        step: "self-test",
+        status: report.status(),
+        detail: if problems.is_empty() {
+            format!("{} checks passed", report.checks.len())
+        } else {
+            format!("problems with {}", problems.join(", "))
+        },
+        hint: (!problems.is_empty()).then(|| {
+            "Run `nsrt self-test` for the values read and what is wrong with them".to_string()
+        }),
+    });
+}
+
+/// Whether the meter is on the USB bus at all, whatever the driver does
+#[cfg(target_os = "linux")]
+fn usb() -> Option<Finding> {
+    let devices = std::fs::read_dir("/sys/bus/usb/devices").ok()?;
+    let id = |device: &std::path::Path, file: &str| {
+        std::fs::read_to_string(device.join(file))
+            .ok()
+            .and_then(|id| u16::from_str_radix(id.trim(), 16).ok())
+    };
+    let attached = devices
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let device = entry.path();
+            id(&device, "idVendor") == Some(nsrt::USB_VID)
+                && id(&device, "idProduct") == Some(nsrt::USB_PID)
+        })
+        .count();
+
+    Some(if attached == 0 {
+        Finding::fail(
+            "usb",
+            "no NSRT_mk4 on the USB bus",
+            "Plug the meter in directly rather than through a hub, and try another cable; charge-only cables don't carry data",
+        )
+    } else {
+        Finding::pass("usb", format!("{attached} NSRT_mk4 attached"))
+    })
+}
+
+/// Whether a driver turned the meter into a serial port
+fn ports() -> Finding {
+    let ports = match NSRT::ports() {
+        Ok(ports) => ports,
+        Err(e) => {
+            return Finding::fail(
+                "driver",
+                format!("can't list serial ports: {e}"),
+                "Check that the system's serial port enumeration works, e.g. that udev is running",
+            );
+        }
+    };
+    if !ports.is_empty() {
+        return Finding::pass("driver", format!("serial ports {}", ports.join(", ")));
+    }
+
+    let others: Vec<String> = serialport::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|port| port.port_name)
+        .collect();
+    let hint = if cfg!(target_os = "linux") {
+        "Load the USB modem driver with `sudo modprobe cdc_acm`; if it is missing, install your distribution's extra kernel modules"
+    } else if cfg!(windows) {
+        "Windows 10 and later bind the built-in usbser driver; on older versions install the driver from Convergence Instruments"
+    } else {
+        "Unplug and replug the meter, then check that it appears as a usbmodem device"
+    };
+    let detail = if others.is_empty() {
+        "no serial port for the meter".to_string()
+    } else {
+        format!(
+            "no serial port for the meter, only {}; list ports of adapters with other USB IDs in NSRT_PORTS",
+            others.join(", ")
+        )
+    };
+    Finding::fail("driver", detail, hint)
+}
+
+/// Whether this user may read and write the port
+#[cfg(unix)]
+fn permissions(path: &str) -> Finding {
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Ok(_) => Finding::pass("permissions", format!("{path} is readable and writable")),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Finding::fail(
+            "permissions",
+            format!("{path} can't be opened by this user"),
+            if cfg!(target_os = "linux") {
+                "Add yourself to the group owning the port with `sudo usermod -aG dialout $USER` (`uucp` on Arch) and log in again, or allow everyone with a udev rule: SUBSYSTEM==\"tty\", ATTRS{idVendor}==\"0a59\", ATTRS{idProduct}==\"0143\", MODE=\"0666\""
+            } else {
+                "Give this user read and write access to the port"
+            },
+        ),
+        // Anything else shows up when the port is opened
+        Err(_) => Finding::pass("permissions", format!("{path} is accessible")),
+    }
+}
+
+fn open_hint(e: &NsrtError) -> &'static str {
+    // Windows denies access to a port another program has open
+    if let NsrtError::SerialError(e) = e
+        && matches!(
+            e.kind,
+            serialport::ErrorKind::Io(
+                io::ErrorKind::ResourceBusy | io::ErrorKind::PermissionDenied
+            )
+        )
+    {
+        return "Another program has the port open, e.g. `nsrt log`, `nsrtd`, ModemManager or a serial terminal; stop it and try again";
+    }
+    match e.kind() {
+        ErrorKind::Timeout | ErrorKind::Desync => {
+            "The port opened but the meter doesn't answer; unplug and replug it, and check it isn't another kind of device"
+        }
+        ErrorKind::Busy => "The meter refused commands; wait a moment and try again",
+        ErrorKind::Disconnected => "The meter went away; check the cable and plug it in again",
+        ErrorKind::InvalidParameter => {
+            "Check the `--port` given, or the alias and the registry entry it names"
+        }
+        _ => "Unplug and replug the meter and run `nsrt doctor` again",
+    }
+}
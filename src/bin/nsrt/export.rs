@@ -0,0 +1,175 @@
+use crate::{
+    daemon::Aggregate,
+    extension, is_csv, is_sqlite,
+    monitor::{Format, Printer},
+    print_json, read_log,
+};
+use clap::Args;
+use nsrt::{
+    Measurement, NsrtError, Pace, Result, Sink, binlog::BinaryLogWriter, csv::CsvWriter,
+    parquet::ParquetWriter, replay, sqlite::SqliteLog,
+};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Log to read: CSV, SQLite (`.db`), Parquet or binary
+    #[arg(required_unless_present = "from", conflicts_with = "from")]
+    input: Option<PathBuf>,
+    /// File to write, in the format given by its extension: `.csv`, `.db`,
+    /// `.parquet`, `.json` for a JSON array, `.ndjson` or `.jsonl` for one
+    /// object per line, `.h5` if built with HDF5, and a binary log otherwise
+    #[arg(required_unless_present = "to", conflicts_with = "to")]
+    output: Option<PathBuf>,
+    /// Log to read, instead of the first argument
+    #[arg(long)]
+    from: Option<PathBuf>,
+    /// File to write, instead of the second argument
+    #[arg(long)]
+    to: Option<PathBuf>,
+    /// Leave out measurements before this time, e.g. `2024-05-01T06:00:00Z`
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    since: Option<SystemTime>,
+    /// Leave out measurements from this time on
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    until: Option<SystemTime>,
+    /// Combine the measurements of each period, e.g. `1min` or `1h`, into
+    /// one with the maximum level and the energy average LEQ
+    #[arg(long, value_parser = humantime::parse_duration)]
+    aggregate: Option<Duration>,
+}
+
+/// Result of `nsrt export --json`
+#[derive(Serialize)]
+struct Exported<'a> {
+    input: &'a Path,
+    output: &'a Path,
+    /// Measurements read in the time range, before any aggregation
+    measurements: u64,
+}
+
+/// Convert a log to another format, optionally cut to a time range and
+/// aggregated
+pub fn export(args: &ExportArgs, json: bool) -> Result<()> {
+    let (Some(input), Some(output)) = (
+        args.from.as_ref().or(args.input.as_ref()),
+        args.to.as_ref().or(args.output.as_ref()),
+    ) else {
+        unreachable!("clap requires an input and an output");
+    };
+    if args.aggregate.is_some_and(|period| period.is_zero()) {
+        return Err(NsrtError::InvalidParameter(
+            "aggregation period must not be zero".to_string(),
+        ));
+    }
+    let measurements = read_log(input)?.filter(|measurement| {
+        measurement.as_ref().map_or(true, |m| {
+            args.since.is_none_or(|since| m.timestamp >= since)
+                && args.until.is_none_or(|until| m.timestamp < until)
+        })
+    });
+
+    let count = match extension(output) {
+        Some("parquet") => {
+            let mut sink = ParquetWriter::create(output)?;
+            let count = convert(measurements, &mut sink, args.aggregate)?;
+            sink.finish()?;
+            count
+        }
+        Some(extension @ ("json" | "ndjson" | "jsonl")) => {
+            let format = if extension == "json" {
+                Format::Json
+            } else {
+                Format::Ndjson
+            };
+            let mut sink = Printer::new(format, BufWriter::new(File::create(output)?))?;
+            let count = convert(measurements, &mut sink, args.aggregate)?;
+            sink.finish()?;
+            count
+        }
+        #[cfg(feature = "hdf5")]
+        Some("h5" | "hdf5") => {
+            let mut collected = Collected(Vec::new());
+            let count = convert(measurements, &mut collected, args.aggregate)?;
+            let measurements = collected.0;
+            let session = nsrt::Session {
+                started: measurements
+                    .first()
+                    .map_or_else(SystemTime::now, |m| m.timestamp),
+                stopped: measurements.last().map(|m| m.timestamp),
+                metadata: nsrt::Metadata::default(),
+                measurements,
+            };
+            nsrt::hdf5::export_session(&session, output)?;
+            count
+        }
+        _ => {
+            let mut sink = create(output)?;
+            convert(measurements, &mut sink, args.aggregate)?
+        }
+    };
+    if json {
+        return print_json(&Exported {
+            input,
+            output,
+            measurements: count,
+        });
+    }
+    eprintln!("Exported {count} measurements");
+    Ok(())
+}
+
+/// Sink for the CSV, SQLite or binary log at `path`, replacing any
+/// existing file
+fn create(path: &Path) -> Result<Box<dyn Sink>> {
+    Ok(if is_csv(path) {
+        Box::new(CsvWriter::create(path)?)
+    } else if is_sqlite(path) {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Box::new(SqliteLog::open(path)?)
+    } else {
+        Box::new(BinaryLogWriter::create(path)?)
+    })
+}
+
+/// Write `measurements` to `sink`, combined into periods of `aggregate` if
+/// given, and flush it, returning the number of measurements read
+fn convert(
+    measurements: impl Iterator<Item = Result<Measurement>>,
+    sink: &mut dyn Sink,
+    aggregate: Option<Duration>,
+) -> Result<u64> {
+    match aggregate {
+        Some(period) => {
+            let mut aggregated = Aggregate::new(sink, period);
+            let count = replay(measurements, &mut aggregated, Pace::AsFastAsPossible)?;
+            aggregated.flush()?;
+            Ok(count)
+        }
+        None => {
+            let count = replay(measurements, sink, Pace::AsFastAsPossible)?;
+            sink.flush()?;
+            Ok(count)
+        }
+    }
+}
+
+/// Sink keeping measurements in memory, for formats written in one go
+#[cfg(feature = "hdf5")]
+struct Collected(Vec<Measurement>);
+
+#[cfg(feature = "hdf5")]
+impl Sink for Collected {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.0.push(*measurement);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{open, print_json};
+use clap::Args;
+use nsrt::{DeviceInfo, NSRT, NsrtError, Result};
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Show every attached device instead of one
+    #[arg(short, long, conflicts_with = "port")]
+    all: bool,
+    /// Calibration interval used to work out when calibration is due
+    #[arg(long, default_value = "1year", value_parser = humantime::parse_duration)]
+    calibration_interval: Duration,
+}
+
+/// Identity of one device, or why it couldn't be read
+#[derive(Serialize)]
+struct Entry {
+    port: String,
+    #[serde(flatten)]
+    status: Status,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Status {
+    Ok {
+        #[serde(flatten)]
+        info: DeviceInfo,
+        #[serde(with = "humantime_serde")]
+        calibrated: SystemTime,
+        #[serde(with = "humantime_serde")]
+        manufactured: SystemTime,
+        #[serde(with = "humantime_serde")]
+        calibration_due: SystemTime,
+        calibration_overdue: bool,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Print the identity of the selected device, or of all attached devices
+///
+/// With `--all`, devices that can't be read are reported alongside the
+/// others and the first error is returned at the end.
+pub fn info(port: Option<&str>, args: &InfoArgs, json: bool) -> Result<()> {
+    let ports = match port {
+        Some(port) => vec![port.to_string()],
+        None => {
+            let mut ports = NSRT::ports()?;
+            if !args.all {
+                ports.truncate(1);
+            }
+            ports
+        }
+    };
+    if ports.is_empty() && !args.all {
+        return Err(NsrtError::NoDevice);
+    }
+
+    let mut first_error = None;
+    let entries: Vec<Entry> = ports
+        .into_iter()
+        .map(|port| {
+            let status = match read(&port, args.calibration_interval) {
+                Ok(status) => status,
+                Err(e) => {
+                    let error = e.to_string();
+                    first_error.get_or_insert(e);
+                    Status::Failed { error }
+                }
+            };
+            Entry { port, status }
+        })
+        .collect();
+
+    if json {
+        if args.all {
+            print_json(&entries)?;
+        } else {
+            print_json(&entries[0])?;
+        }
+    } else {
+        for (i, entry) in entries.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_entry(entry);
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+fn read(port: &str, calibration_interval: Duration) -> Result<Status> {
+    let info = open(Some(port))?.read_info()?;
+    let calibration_due = info.calibration_due(calibration_interval);
+
+    Ok(Status::Ok {
+        calibrated: info.calibration_time(),
+        manufactured: info.birth_time(),
+        calibration_due,
+        calibration_overdue: calibration_due <= SystemTime::now(),
+        info,
+    })
+}
+
+fn print_entry(entry: &Entry) {
+    println!("Port:             {}", entry.port);
+    match &entry.status {
+        Status::Ok {
+            info,
+            calibrated,
+            manufactured,
+            calibration_due,
+            calibration_overdue,
+        } => {
+            println!("Model:            {}", info.model);
+            println!("Serial number:    {}", info.serial_number);
+            println!("Firmware:         {}", info.firmware_revision);
+            println!("User ID:          {}", info.user_id);
+            println!(
+                "Calibrated:       {}",
+                humantime::format_rfc3339_seconds(*calibrated)
+            );
+            println!(
+                "Calibration due:  {}{}",
+                humantime::format_rfc3339_seconds(*calibration_due),
+                if *calibration_overdue {
+                    " (overdue)"
+                } else {
+                    ""
+                }
+            );
+            println!(
+                "Manufactured:     {}",
+                humantime::format_rfc3339_seconds(*manufactured)
+            );
+        }
+        Status::Failed { error } => println!("Error:            {error}"),
+    }
+}
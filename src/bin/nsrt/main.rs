@@ -0,0 +1,946 @@
+use calibrate::CalibrateArgs;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use export::ExportArgs;
+use info::InfoArgs;
+use monitor::MonitorArgs;
+use nsrt::{
+    CheckStatus, Comparison, Divergence, NSRT, NsrtError, PairComparison, Recording, Result,
+    Sampler, Session, Sink,
+    binlog::{BinaryLogReader, BinaryLogWriter},
+    csv::{CsvReader, CsvWriter},
+    encryption::{self, EncryptedLogReader, LogKey},
+    geojson::SiteMap,
+    http::Auth,
+    mdns::{Api, Service},
+    parquet::ParquetReader,
+    registry::Registry,
+    report::{Analysis, SummaryOptions},
+    signing::{self, VerifyingKey},
+    sqlite::SqliteLog,
+    tls::{TlsIdentity, TlsListener},
+};
+use read::ReadArgs;
+use serde::Serialize;
+use settings::{GetArgs, SetArgs};
+use std::{
+    fs,
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{net::TcpListener, runtime::Runtime, signal};
+
+mod calibrate;
+mod daemon;
+mod doctor;
+mod export;
+mod info;
+mod monitor;
+mod read;
+#[cfg(all(windows, feature = "service"))]
+mod service;
+mod settings;
+#[cfg(feature = "tui")]
+mod tui;
+
+/// Command-line interface to NSRT_mk4 sound level meters
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Serial port of the device, or its alias in the device registry,
+    /// instead of the first one found
+    #[arg(short, long, global = true)]
+    port: Option<String>,
+    /// Print results as JSON, with the same fields from release to release,
+    /// for scripts and configuration management
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the serial ports of attached devices
+    List,
+    /// Show device identity and calibration dates
+    Info(InfoArgs),
+    /// Show device settings
+    Get(GetArgs),
+    /// Print one reading, for scripts and Nagios/Icinga checks
+    ///
+    /// Exit status: 0 ok, 1 above `--warn`, 2 above `--crit`, 3 other error,
+    /// 4 no device, 5 timeout. Nagios and Icinga treat statuses above 3 as
+    /// UNKNOWN.
+    Read(ReadArgs),
+    /// Change a device setting
+    Set(SetArgs),
+    /// Exercise every read command and check the answers, before deploying
+    /// a meter
+    ///
+    /// Exit status: 0 when no check failed, 1 otherwise.
+    SelfTest,
+    /// Check everything from USB enumeration and port permissions to the
+    /// meter's answers, with hints for fixing what fails
+    ///
+    /// Exit status: 0 when nothing failed, 1 otherwise.
+    Doctor,
+    /// Measure an acoustic calibrator's tone and store the offset that
+    /// corrects the meter in the device registry
+    Calibrate(CalibrateArgs),
+    /// Print measurements as a table, CSV or JSON
+    Monitor(MonitorArgs),
+    /// Log measurements to a file, or to configured sinks, until interrupted
+    Log(LogArgs),
+    /// Convert a log to another format, optionally cut to a time range and
+    /// aggregated
+    Export(ExportArgs),
+    /// Recompute LEQ, exceedance levels and events from a log
+    Analyze(AnalyzeArgs),
+    /// Replace aged records of an SQLite or binary log with hourly or daily
+    /// aggregates
+    Compact(CompactArgs),
+    /// Check a log against the Ed25519 seals it was signed with
+    ///
+    /// Exit status: 0 when every seal checks out and covers the whole log, 1
+    /// otherwise.
+    Verify(VerifyArgs),
+    /// Summarize the logs of several devices as GeoJSON for mapping
+    Map(MapArgs),
+    /// Compare the levels of several meters sampled side by side, or of
+    /// logs, and report where they diverge
+    Compare(CompareArgs),
+    /// Serve the HTTP API until interrupted
+    Serve(ServeArgs),
+    /// Show a live dashboard in the terminal
+    #[cfg(feature = "tui")]
+    Tui(tui::TuiArgs),
+    /// Install or remove the logging daemon as a Windows service
+    #[cfg(all(windows, feature = "service"))]
+    Service(service::ServiceArgs),
+    /// Print a shell completion script, e.g. `nsrt completions bash >
+    /// /etc/bash_completion.d/nsrt`
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args)]
+struct CompletionsArgs {
+    /// Shell to complete in
+    shell: Shell,
+}
+
+#[derive(Args)]
+struct SamplingArgs {
+    /// Time between measurements, e.g. `1s` or `250ms`
+    #[arg(short, long, default_value = "1s", value_parser = humantime::parse_duration)]
+    interval: Duration,
+}
+
+#[derive(Args)]
+struct LogArgs {
+    /// Log file; `.csv` files are written as CSV, anything else as a binary log
+    #[arg(required_unless_present = "config", conflicts_with = "config")]
+    path: Option<PathBuf>,
+    /// Run unattended as configured in a TOML file (see `docs/nsrt-log.toml`)
+    #[arg(short, long, conflicts_with_all = ["port", "interval"])]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    sampling: SamplingArgs,
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    /// Log to read, CSV, SQLite (`.db`) or binary
+    input: PathBuf,
+    /// Ignore measurements before this time, e.g. `2024-05-01T06:00:00Z`
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    from: Option<SystemTime>,
+    /// Ignore measurements from this time on
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    to: Option<SystemTime>,
+    /// Length of the windows to split the range into, e.g. `15m`, instead of
+    /// one window
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    window: Option<Duration>,
+    /// Exceedance levels to compute, as percentages of the time
+    #[arg(long, value_delimiter = ',', default_value = "10,50,90")]
+    ln: Vec<f32>,
+    /// Running level above which an event is counted, in dB
+    #[arg(long)]
+    threshold: Option<f32>,
+}
+
+#[derive(Args)]
+struct CompactArgs {
+    /// Log to compact in place, SQLite (`.db`) or binary
+    path: PathBuf,
+    /// Age of the records to compact, e.g. `30days`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    older_than: Duration,
+    /// Length of the aggregates, e.g. `1h` or `1day`
+    #[arg(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    period: Duration,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Log the seals cover, binary or encrypted
+    log: PathBuf,
+    /// Seal file written alongside the log
+    seals: PathBuf,
+    /// Public key of the signer, 32 raw bytes
+    key: PathBuf,
+    /// Succeed even if no seal was written yet or records follow the last
+    /// one, as in a log still being written
+    #[arg(long)]
+    allow_unsealed: bool,
+}
+
+#[derive(Args)]
+struct MapArgs {
+    /// Logs as `DEVICE=PATH`, the device by serial number or registry alias;
+    /// the registry entry places and names the site
+    #[arg(required = true, value_parser = parse_site_log)]
+    logs: Vec<(String, PathBuf)>,
+    /// File to write the GeoJSON to, instead of standard output
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Ignore measurements before this time, e.g. `2024-05-01T06:00:00Z`
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    from: Option<SystemTime>,
+    /// Ignore measurements from this time on
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    to: Option<SystemTime>,
+    /// Exceedance levels to include, as percentages of the time
+    #[arg(long, value_delimiter = ',', default_value = "10,50,90")]
+    ln: Vec<f32>,
+    /// Running level above which an event is counted, in dB
+    #[arg(long)]
+    threshold: Option<f32>,
+}
+
+fn parse_site_log(value: &str) -> std::result::Result<(String, PathBuf), String> {
+    let (device, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected DEVICE=PATH, got {value}"))?;
+    Ok((device.to_string(), PathBuf::from(path)))
+}
+
+#[derive(Args)]
+struct CompareArgs {
+    /// Serial ports or registry aliases of the meters to sample, or paths of
+    /// logs to compare, the reference first
+    #[arg(required = true, num_args = 2..)]
+    sources: Vec<String>,
+    /// How long to sample meters, e.g. `10m`, or until interrupted
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+    /// Furthest apart two measurements may be to be compared, half the
+    /// interval by default
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    tolerance: Option<Duration>,
+    /// Difference in dB beyond which a stretch of time is reported as
+    /// diverging
+    #[arg(long, default_value_t = 3.0)]
+    threshold: f32,
+    #[command(flatten)]
+    sampling: SamplingArgs,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+    /// Also serve a live dashboard page at `/`
+    #[arg(long)]
+    ui: bool,
+    /// Also serve the driver metrics at `/metrics` for Prometheus
+    #[arg(long)]
+    metrics: bool,
+    /// Don't advertise the API over mDNS, as `_nsrt._tcp`, which is done
+    /// unless listening on a loopback address
+    #[arg(long)]
+    no_mdns: bool,
+    /// PEM certificate chain to serve HTTPS with
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// TOML file of the tokens and users allowed to read or configure the
+    /// device, see `nsrt::http::Auth`; without one the API is open
+    #[arg(long)]
+    auth: Option<PathBuf>,
+    #[command(flatten)]
+    sampling: SamplingArgs,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let (port, json) = (cli.port.as_deref(), cli.json);
+    match &cli.command {
+        Command::Read(args) => return read::read(port, args, json),
+        Command::SelfTest => return self_test(port, json),
+        Command::Doctor => return doctor::doctor(port, json),
+        _ => {}
+    }
+
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            print_error(&e, json);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    let (port, json) = (cli.port.as_deref(), cli.json);
+
+    match &cli.command {
+        Command::List => list(json),
+        Command::Info(args) => info::info(port, args, json),
+        Command::Get(args) => settings::get(open(port)?, args, json),
+        Command::Read(_) | Command::SelfTest | Command::Doctor => {
+            unreachable!("handled for its exit status in main")
+        }
+        Command::Set(args) => settings::set(port, args, json),
+        Command::Calibrate(args) => calibrate::calibrate(port, args, json),
+        Command::Monitor(args) => monitor::monitor(open(port)?, args, json),
+        Command::Log(args) => {
+            let started = SystemTime::now();
+            match (&args.config, &args.path) {
+                (Some(config), _) => daemon::run(config, shutdown())?,
+                (None, Some(path)) => log(open(port)?, path, &args.sampling)?,
+                (None, None) => unreachable!("clap requires a path or config"),
+            }
+            if json {
+                print_json(&Logged {
+                    output: args.path.as_deref().or(args.config.as_deref()),
+                    started,
+                    stopped: SystemTime::now(),
+                })?;
+            }
+            Ok(())
+        }
+        Command::Export(args) => export::export(args, json),
+        Command::Analyze(args) => analyze(args, json),
+        Command::Compact(args) => compact(args, json),
+        Command::Verify(args) => verify(args, json),
+        // GeoJSON either way
+        Command::Map(args) => map(args),
+        Command::Compare(args) => compare(args, json),
+        Command::Serve(args) => serve(open(port)?, args, json),
+        #[cfg(feature = "tui")]
+        Command::Tui(args) => {
+            if json {
+                return Err(NsrtError::InvalidParameter(
+                    "the terminal dashboard has no JSON output; use `nsrt monitor --json`"
+                        .to_string(),
+                ));
+            }
+            tui::tui(open(port)?, args)
+        }
+        #[cfg(all(windows, feature = "service"))]
+        Command::Service(args) => service::service(args, json),
+        Command::Completions(args) => {
+            if json {
+                return Err(NsrtError::InvalidParameter(
+                    "completion scripts have no JSON output".to_string(),
+                ));
+            }
+            clap_complete::generate(args.shell, &mut Cli::command(), "nsrt", &mut io::stdout());
+            Ok(())
+        }
+    }
+}
+
+/// Summary printed by `nsrt log --json` once logging stops
+#[derive(Serialize)]
+struct Logged<'a> {
+    /// Log file, or configuration file of the daemon
+    output: Option<&'a Path>,
+    #[serde(with = "humantime_serde")]
+    started: SystemTime,
+    #[serde(with = "humantime_serde")]
+    stopped: SystemTime,
+}
+
+/// Error as printed with `--json`
+#[derive(Serialize)]
+struct ErrorReport {
+    error: ErrorDetail,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail {
+    message: String,
+    /// Stable numeric code, see `NsrtError::code`
+    code: u32,
+    /// Lowercase `ErrorKind`, e.g. `timeout`
+    kind: String,
+}
+
+/// Print `value` as pretty JSON on standard output
+fn print_json(value: &impl Serialize) -> Result<()> {
+    serde_json::to_writer_pretty(io::stdout().lock(), value).map_err(io::Error::from)?;
+    println!();
+    Ok(())
+}
+
+/// Report `error` on standard error, or as JSON on standard output
+fn print_error(error: &NsrtError, json: bool) {
+    if json {
+        let report = ErrorReport {
+            error: ErrorDetail {
+                message: error.to_string(),
+                code: error.code(),
+                kind: format!("{:?}", error.kind()).to_lowercase(),
+            },
+        };
+        if print_json(&report).is_ok() {
+            return;
+        }
+    }
+    eprintln!("nsrt: {error}");
+}
+
+/// Open the device at `port`, or registered as `port` in the registry, or
+/// the first one found
+fn open(port: Option<&str>) -> Result<NSRT> {
+    let Some(port) = port else {
+        return NSRT::open();
+    };
+    let registry = Registry::load_default()?;
+    if registry.serial_of(port).is_some() {
+        registry.open(port)
+    } else {
+        NSRT::open_port(port)
+    }
+}
+
+fn self_test(port: Option<&str>, json: bool) -> ExitCode {
+    let report = match open(port) {
+        Ok(mut nsrt) => nsrt.self_test(),
+        Err(e) => {
+            print_error(&e, json);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if json {
+        if let Err(e) = print_json(&report) {
+            eprintln!("nsrt: {e}");
+        }
+    } else {
+        for check in &report.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "ok",
+                CheckStatus::Warn => "WARN",
+                CheckStatus::Fail => "FAIL",
+            };
+            println!(
+                "{:<18} {status:<4} {:>6.1} ms  {}{}",
+                check.name,
+                check.latency.as_secs_f64() * 1000.0,
+                check.value.as_deref().unwrap_or("-"),
+                check
+                    .message
+                    .as_deref()
+                    .map(|message| format!(" ({message})"))
+                    .unwrap_or_default(),
+            );
+        }
+        println!(
+            "latency: mean {:.1} ms, max {:.1} ms",
+            report.mean_latency().as_secs_f64() * 1000.0,
+            report.max_latency().as_secs_f64() * 1000.0
+        );
+    }
+
+    if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn list(json: bool) -> Result<()> {
+    /// Port as listed with `--json`
+    #[derive(Serialize)]
+    struct Listed {
+        port: String,
+    }
+
+    let ports = NSRT::ports()?;
+    if json {
+        return print_json(
+            &ports
+                .into_iter()
+                .map(|port| Listed { port })
+                .collect::<Vec<_>>(),
+        );
+    }
+    for port in ports {
+        println!("{port}");
+    }
+    Ok(())
+}
+
+fn log(nsrt: NSRT, path: &Path, sampling: &SamplingArgs) -> Result<()> {
+    let sink: Box<dyn Sink + Send> = if is_csv(path) {
+        Box::new(CsvWriter::append(path)?)
+    } else {
+        Box::new(BinaryLogWriter::append(path)?)
+    };
+
+    let sampler = Sampler::start(nsrt, sampling.interval);
+    let writer = sampler.attach(sink);
+    runtime()?.block_on(shutdown())?;
+
+    let stopped = sampler.stop();
+    writer.join().expect("log writer panicked")?;
+    stopped
+}
+
+/// Read the CSV, SQLite, Parquet or binary log at `path`
+fn read_log(path: &Path) -> Result<Box<dyn Iterator<Item = Result<nsrt::Measurement>>>> {
+    Ok(if is_csv(path) {
+        Box::new(CsvReader::open(path)?)
+    } else if is_sqlite(path) {
+        Box::new(SqliteLog::open(path)?.measurements(..)?.into_iter().map(Ok))
+    } else if extension(path) == Some("parquet") {
+        Box::new(ParquetReader::open(path)?)
+    } else if encryption::is_encrypted(path)? {
+        Box::new(EncryptedLogReader::open(path, &LogKey::from_env()?)?)
+    } else {
+        Box::new(BinaryLogReader::open(path)?)
+    })
+}
+
+fn analyze(args: &AnalyzeArgs, json: bool) -> Result<()> {
+    let mut analysis = Analysis::new()
+        .exceedance(&args.ln)
+        .options(SummaryOptions {
+            event_threshold: args.threshold,
+            ..SummaryOptions::default()
+        });
+    if let Some(from) = args.from {
+        analysis = analysis.start(from);
+    }
+    if let Some(to) = args.to {
+        analysis = analysis.end(to);
+    }
+    if let Some(window) = args.window {
+        analysis = analysis.window(window);
+    }
+    let windows = analysis.run(read_log(&args.input)?)?;
+
+    if json {
+        return print_json(&windows);
+    }
+
+    let show = |value: Option<f32>| value.map_or_else(|| "-".to_string(), |v| format!("{v:.1}"));
+    print!(
+        "{:<20} {:>8} {:>6} {:>6}",
+        "start", "samples", "leq", "lmax"
+    );
+    for n in &args.ln {
+        print!(" {:>6}", format!("l{n}"));
+    }
+    println!(" {:>6}", "events");
+    for window in &windows {
+        print!(
+            "{:<20} {:>8} {:>6} {:>6}",
+            humantime::format_rfc3339_seconds(window.start),
+            window.measurements,
+            show(window.leq),
+            show(window.lmax),
+        );
+        for &n in &args.ln {
+            print!(" {:>6}", show(window.ln(n)));
+        }
+        println!(" {:>6}", window.events.len());
+    }
+    Ok(())
+}
+
+fn map(args: &MapArgs) -> Result<()> {
+    let registry = Registry::load_default()?;
+    let mut analysis = Analysis::new()
+        .exceedance(&args.ln)
+        .options(SummaryOptions {
+            event_threshold: args.threshold,
+            ..SummaryOptions::default()
+        });
+    if let Some(from) = args.from {
+        analysis = analysis.start(from);
+    }
+    if let Some(to) = args.to {
+        analysis = analysis.end(to);
+    }
+
+    let mut map = SiteMap::new();
+    for (device, path) in &args.logs {
+        let serial = registry.serial_of(device).unwrap_or(device);
+        for statistics in analysis.run(read_log(path)?)? {
+            map.add_registered(&registry, serial, &statistics);
+        }
+    }
+
+    match &args.output {
+        Some(path) => {
+            let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+            map.write(&mut file)?;
+            file.flush()?;
+        }
+        None => {
+            map.write(io::stdout().lock())?;
+            println!();
+        }
+    }
+    Ok(())
+}
+
+fn compact(args: &CompactArgs, json: bool) -> Result<()> {
+    if args.period.is_zero() {
+        return Err(NsrtError::InvalidParameter(
+            "period must not be zero".to_string(),
+        ));
+    }
+    let before = SystemTime::now()
+        .checked_sub(args.older_than)
+        .unwrap_or(UNIX_EPOCH);
+    let compacted = if is_sqlite(&args.path) {
+        SqliteLog::open(&args.path)?.compact(before, args.period)?
+    } else {
+        nsrt::binlog::compact(&args.path, before, args.period)?
+    };
+    if json {
+        /// Result of `nsrt compact --json`
+        #[derive(Serialize)]
+        struct Compacted<'a> {
+            path: &'a Path,
+            compacted: u64,
+        }
+        return print_json(&Compacted {
+            path: &args.path,
+            compacted,
+        });
+    }
+    eprintln!("Compacted {compacted} records");
+    Ok(())
+}
+
+fn verify(args: &VerifyArgs, json: bool) -> Result<()> {
+    let key: [u8; 32] = fs::read(&args.key)?.try_into().map_err(|_| {
+        NsrtError::InvalidParameter(format!(
+            "{} must hold a 32-byte public key",
+            args.key.display()
+        ))
+    })?;
+    let key = VerifyingKey::from_bytes(&key)
+        .map_err(|e| NsrtError::InvalidParameter(format!("Invalid public key: {e}")))?;
+    let seals = signing::read_seals(fs::File::open(&args.seals)?)?;
+    let verified = signing::verify(read_log(&args.log)?, &seals, &key)?;
+    if !args.allow_unsealed {
+        if verified.sealed == 0 {
+            return Err(NsrtError::VerificationFailed(
+                "No records are sealed".to_string(),
+            ));
+        }
+        if verified.unsealed > 0 {
+            return Err(NsrtError::VerificationFailed(format!(
+                "{} trailing records are not sealed",
+                verified.unsealed
+            )));
+        }
+    }
+    if json {
+        /// Result of `nsrt verify --json`
+        #[derive(Serialize)]
+        struct Verified<'a> {
+            log: &'a Path,
+            sealed: u64,
+            unsealed: u64,
+        }
+        return print_json(&Verified {
+            log: &args.log,
+            sealed: verified.sealed,
+            unsealed: verified.unsealed,
+        });
+    }
+    println!("OK: {} records sealed", verified.sealed);
+    if verified.unsealed > 0 {
+        eprintln!(
+            "Warning: {} trailing records are not sealed",
+            verified.unsealed
+        );
+    }
+    Ok(())
+}
+
+fn compare(args: &CompareArgs, json: bool) -> Result<()> {
+    let logs = args
+        .sources
+        .iter()
+        .filter(|source| Path::new(source).is_file());
+    let sessions = match logs.count() {
+        0 => sample_meters(args)?,
+        count if count == args.sources.len() => args
+            .sources
+            .iter()
+            .map(|path| {
+                let measurements = read_log(Path::new(path))?.collect::<Result<Vec<_>>>()?;
+                Ok(Session {
+                    started: measurements.first().map_or(UNIX_EPOCH, |m| m.timestamp),
+                    stopped: measurements.last().map(|m| m.timestamp),
+                    metadata: nsrt::Metadata::default(),
+                    measurements,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => {
+            return Err(NsrtError::InvalidParameter(
+                "compare either meters or logs, not both".to_string(),
+            ));
+        }
+    };
+
+    let tolerance = args.tolerance.unwrap_or(args.sampling.interval / 2);
+    let comparison = Comparison::compute(&sessions, tolerance);
+    let divergences: Vec<Vec<Divergence>> = comparison
+        .pairs
+        .iter()
+        .map(|pair| {
+            Comparison::divergences(
+                &sessions[pair.reference],
+                &sessions[pair.other],
+                tolerance,
+                args.threshold,
+            )
+        })
+        .collect();
+    if json {
+        return print_json(&Compared {
+            sources: &args.sources,
+            pairs: comparison
+                .pairs
+                .iter()
+                .zip(&divergences)
+                .map(|(pair, divergences)| ComparedPair {
+                    reference_source: &args.sources[pair.reference],
+                    other_source: &args.sources[pair.other],
+                    pair,
+                    divergences,
+                })
+                .collect(),
+        });
+    }
+
+    let show = |value: Option<f32>, precision: usize| {
+        value.map_or_else(|| "-".to_string(), |v| format!("{v:.precision$}"))
+    };
+    let signed = |value: Option<f32>| value.map_or_else(|| "-".to_string(), |v| format!("{v:+.2}"));
+    println!(
+        "{:<16} {:<16} {:>7} {:>7} {:>6} {:>7} {:>6} {:>8}",
+        "reference", "other", "samples", "mean", "std", "max", "r", "drift/h"
+    );
+    for pair in &comparison.pairs {
+        println!(
+            "{:<16} {:<16} {:>7} {:>7} {:>6} {:>7} {:>6} {:>8}",
+            args.sources[pair.reference],
+            args.sources[pair.other],
+            pair.samples,
+            signed(pair.mean_difference),
+            show(pair.std_deviation, 2),
+            signed(pair.max_difference),
+            show(pair.correlation, 3),
+            signed(pair.drift),
+        );
+    }
+
+    for (pair, divergences) in comparison.pairs.iter().zip(divergences) {
+        if divergences.is_empty() {
+            continue;
+        }
+        println!(
+            "\n{} diverges from {} by more than {} dB:",
+            args.sources[pair.other], args.sources[pair.reference], args.threshold
+        );
+        for divergence in divergences {
+            println!(
+                "  {} to {} {:>7} samples, max {:+.2}",
+                humantime::format_rfc3339_seconds(divergence.start),
+                humantime::format_rfc3339_seconds(divergence.end),
+                divergence.samples,
+                divergence.max_difference,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Result of `nsrt compare --json`
+#[derive(Serialize)]
+struct Compared<'a> {
+    sources: &'a [String],
+    pairs: Vec<ComparedPair<'a>>,
+}
+
+#[derive(Serialize)]
+struct ComparedPair<'a> {
+    /// Meter or log compared against
+    reference_source: &'a str,
+    other_source: &'a str,
+    #[serde(flatten)]
+    pair: &'a PairComparison,
+    divergences: &'a [Divergence],
+}
+
+/// Sample the meters of `args` side by side for the requested time
+fn sample_meters(args: &CompareArgs) -> Result<Vec<Session>> {
+    let samplers = args
+        .sources
+        .iter()
+        .map(|port| Ok(Sampler::start(open(Some(port))?, args.sampling.interval)))
+        .collect::<Result<Vec<_>>>()?;
+    let recordings: Vec<_> = samplers.iter().map(Session::record).collect();
+
+    eprintln!("Comparing {} meters; press Ctrl-C to stop", samplers.len());
+    runtime()?.block_on(async {
+        match args.duration {
+            Some(duration) => tokio::select! {
+                () = tokio::time::sleep(duration) => Ok(()),
+                result = shutdown() => result,
+            },
+            None => shutdown().await,
+        }
+    })?;
+
+    let sessions: Vec<Session> = recordings.into_iter().map(Recording::stop).collect();
+    for sampler in samplers {
+        sampler.stop()?;
+    }
+    Ok(sessions)
+}
+
+fn serve(mut nsrt: NSRT, args: &ServeArgs, json: bool) -> Result<()> {
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(TlsIdentity::from_pem_files(cert, key)?),
+        _ => None,
+    };
+    let serial = nsrt.read_info()?.serial_number;
+    let sampler = Arc::new(Sampler::start(nsrt, args.sampling.interval));
+    let mut router = nsrt::http::router(sampler);
+    if args.ui {
+        router = router.merge(nsrt::http::dashboard());
+    }
+    if args.metrics {
+        router = router.merge(nsrt::http::metrics(nsrt::metrics::install_prometheus()?));
+    }
+    if let Some(path) = &args.auth {
+        router = load_auth(path)?.protect(router);
+    }
+
+    runtime()?.block_on(async {
+        let listener = TcpListener::bind(args.listen).await?;
+        let addr = listener.local_addr()?;
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        if json {
+            /// Announcement of `nsrt serve --json` once listening
+            #[derive(Serialize)]
+            struct Listening {
+                url: String,
+            }
+            print_json(&Listening {
+                url: format!("{scheme}://{addr}/"),
+            })?;
+        } else if args.ui {
+            eprintln!("Serving the dashboard on {scheme}://{addr}/");
+        } else {
+            eprintln!("Serving the API on {scheme}://{addr}");
+        }
+        let _advertisement = if args.no_mdns || addr.ip().is_loopback() {
+            None
+        } else {
+            let mut service = Service::new(&serial, Api::Http, addr.port()).address(addr.ip());
+            if let Some(alias) = Registry::load_default()?
+                .get(&serial)
+                .and_then(|entry| entry.alias.clone())
+            {
+                service = service.instance(&alias).property("device", &alias);
+            }
+            if tls.is_some() {
+                service = service.property("tls", "1");
+            }
+            Some(service.advertise()?)
+        };
+        let serve = async {
+            match &tls {
+                Some(identity) => {
+                    let listener = TlsListener::new(listener, identity.server_config()?)?;
+                    Ok(axum::serve(listener, router).await?)
+                }
+                None => Ok(axum::serve(listener, router).await?),
+            }
+        };
+        tokio::select! {
+            result = serve => result,
+            result = shutdown() => result,
+        }
+    })
+}
+
+fn load_auth(path: &Path) -> Result<Auth> {
+    let invalid =
+        |message: &str| NsrtError::InvalidParameter(format!("{}: {message}", path.display()));
+    let auth: Auth =
+        toml::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.message()))?;
+    if auth.is_empty() {
+        return Err(invalid("no tokens or users configured"));
+    }
+    Ok(auth)
+}
+
+fn runtime() -> Result<Runtime> {
+    Ok(Runtime::new()?)
+}
+
+/// Wait for Ctrl-C or, on Unix, SIGTERM
+async fn shutdown() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|extension| extension.to_str())
+}
+
+fn is_csv(path: &Path) -> bool {
+    extension(path).is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+fn is_sqlite(path: &Path) -> bool {
+    extension(path).is_some_and(|extension| {
+        ["db", "sqlite", "sqlite3"]
+            .iter()
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    })
+}
@@ -0,0 +1,179 @@
+use crate::{SamplingArgs, runtime, shutdown};
+use clap::{Args, ValueEnum};
+use nsrt::{Measurement, NSRT, NsrtError, Result, Sampler, Sink, csv::CsvWriter};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+
+/// Measurements buffered for a slow output before the oldest are skipped
+const BUFFER: usize = 64;
+
+#[derive(Args)]
+pub struct MonitorArgs {
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    #[command(flatten)]
+    sampling: SamplingArgs,
+    /// Stop after this long, e.g. `10m` or `1h 30m`
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+    /// Stop after this many measurements
+    #[arg(short = 'n', long)]
+    count: Option<u64>,
+    /// File to write to instead of standard output
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for reading in a terminal
+    Table,
+    /// CSV with a header row, as written by `nsrt log`
+    Csv,
+    /// A single JSON array, closed when monitoring stops
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// Print measurements until interrupted or a limit is reached
+///
+/// With `--json`, the table and CSV formats become NDJSON.
+pub fn monitor(nsrt: NSRT, args: &MonitorArgs, json: bool) -> Result<()> {
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    let format = match args.format {
+        Format::Table | Format::Csv if json => Format::Ndjson,
+        format => format,
+    };
+    let mut printer = Printer::new(format, out)?;
+
+    let sampler = Sampler::start(nsrt, args.sampling.interval);
+    let mut rx = sampler.broadcast(BUFFER).subscribe();
+    let deadline = args.duration.map(|duration| Instant::now() + duration);
+
+    let printed = runtime()?.block_on(async {
+        let mut count = 0;
+        while args.count.is_none_or(|limit| count < limit) {
+            let measurement = tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(measurement) => measurement,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                () = sleep_until(deadline) => break,
+                result = shutdown() => {
+                    result?;
+                    break;
+                }
+            };
+            printer.print(&measurement)?;
+            count += 1;
+        }
+        Ok::<_, NsrtError>(())
+    });
+
+    let stopped = sampler.stop();
+    printer.finish()?;
+    printed.and(stopped)
+}
+
+/// Sleep until `deadline`, or forever without one
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Writes measurements in one of the output formats
+pub enum Printer<W: Write> {
+    Table(W),
+    Csv(CsvWriter<W>),
+    Json { out: W, empty: bool },
+    Ndjson(W),
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(format: Format, mut out: W) -> Result<Self> {
+        Ok(match format {
+            Format::Table => {
+                writeln!(
+                    out,
+                    "Time                 | Level (dB) | LEQ (dB) | Temp (°C)"
+                )?;
+                writeln!(
+                    out,
+                    "---------------------+------------+----------+----------"
+                )?;
+                Self::Table(out)
+            }
+            Format::Csv => Self::Csv(CsvWriter::new(out)?),
+            Format::Json => {
+                write!(out, "[")?;
+                Self::Json { out, empty: true }
+            }
+            Format::Ndjson => Self::Ndjson(out),
+        })
+    }
+
+    /// Write one measurement and flush it, so output can be followed live
+    fn print(&mut self, measurement: &Measurement) -> Result<()> {
+        match self {
+            Self::Table(out) => {
+                writeln!(
+                    out,
+                    "{} | {:10.1} | {:8.1} | {:8.1}",
+                    humantime::format_rfc3339_seconds(measurement.timestamp),
+                    measurement.level,
+                    measurement.leq,
+                    measurement.temperature.as_celsius()
+                )?;
+                out.flush()?;
+            }
+            Self::Csv(csv) => {
+                csv.write(measurement)?;
+                csv.flush()?;
+            }
+            Self::Json { out, empty } => {
+                write!(out, "{}\n  ", if *empty { "" } else { "," })?;
+                serde_json::to_writer(&mut *out, measurement).map_err(io::Error::from)?;
+                out.flush()?;
+                *empty = false;
+            }
+            Self::Ndjson(out) => {
+                serde_json::to_writer(&mut *out, measurement).map_err(io::Error::from)?;
+                writeln!(out)?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Terminate the output after the last measurement
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Self::Json { mut out, empty } => {
+                writeln!(out, "{}]", if empty { "" } else { "\n" })?;
+                out.flush()?;
+            }
+            Self::Table(mut out) | Self::Ndjson(mut out) => out.flush()?,
+            Self::Csv(mut csv) => csv.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for Printer<W> {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.print(measurement)
+    }
+}
@@ -0,0 +1,171 @@
+//! Single readings for scripts and monitoring checks
+
+use crate::{open, print_json};
+use clap::{Args, ValueEnum};
+use nsrt::{NsrtError, Result, Temperature};
+use serde::Serialize;
+use std::{io, process::ExitCode, sync::mpsc, thread, time::Duration};
+
+/// Value below any threshold
+const OK: u8 = 0;
+/// Value above `--warn`
+const WARNING: u8 = 1;
+/// Value above `--crit`
+const CRITICAL: u8 = 2;
+/// Any other failure
+const UNKNOWN: u8 = 3;
+/// No device attached, or the given port doesn't exist
+const NO_DEVICE: u8 = 4;
+/// The device didn't answer in time
+const TIMEOUT: u8 = 5;
+
+#[derive(Args)]
+pub struct ReadArgs {
+    /// Value to read
+    quantity: Quantity,
+    /// Exit with status 1 when the value is above this
+    #[arg(short, long)]
+    warn: Option<f32>,
+    /// Exit with status 2 when the value is above this
+    #[arg(short, long)]
+    crit: Option<f32>,
+    /// Give up after this long, e.g. `5s`
+    #[arg(short, long, default_value = "10s", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Quantity {
+    /// Running level in dB
+    Level,
+    /// LEQ in dB since the previous LEQ read
+    Leq,
+    /// Temperature in °C
+    #[value(alias = "temperature")]
+    Temp,
+}
+
+impl Quantity {
+    fn unit(self) -> &'static str {
+        match self {
+            Self::Level | Self::Leq => "dB",
+            Self::Temp => "°C",
+        }
+    }
+}
+
+/// Reading as printed with `--json`
+///
+/// `status` names the exit status, `ok`, `warning`, `critical`, `unknown`,
+/// `no_device` or `timeout`; `value` and `unit` are left out and `error`
+/// given when nothing was read.
+#[derive(Serialize)]
+struct Reading {
+    quantity: Quantity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'static str>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Print one value and map the outcome to a monitoring plugin exit status
+pub fn read(port: Option<&str>, args: &ReadArgs, json: bool) -> ExitCode {
+    let failed = |error: String, status: u8| {
+        if json {
+            let _ = print_json(&Reading {
+                quantity: args.quantity,
+                value: None,
+                unit: None,
+                status: name(status),
+                error: Some(error),
+            });
+        } else {
+            eprintln!("nsrt: {error}");
+        }
+        ExitCode::from(status)
+    };
+
+    let port = port.map(str::to_string);
+    let quantity = args.quantity;
+    let (tx, rx) = mpsc::channel();
+    // The thread is abandoned on timeout; exiting the process ends it
+    thread::spawn(move || {
+        let _ = tx.send(read_value(port.as_deref(), quantity));
+    });
+
+    let value = match rx.recv_timeout(args.timeout) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => return failed(e.to_string(), status(&e)),
+        Err(_) => {
+            return failed(
+                format!(
+                    "no reading within {}",
+                    humantime::format_duration(args.timeout)
+                ),
+                TIMEOUT,
+            );
+        }
+    };
+
+    let above = |limit: Option<f32>| limit.is_some_and(|limit| value > limit);
+    let status = if above(args.crit) {
+        CRITICAL
+    } else if above(args.warn) {
+        WARNING
+    } else {
+        OK
+    };
+    if json {
+        let _ = print_json(&Reading {
+            quantity: args.quantity,
+            // Rounded like the plain output
+            value: Some((value * 10.0).round() / 10.0),
+            unit: Some(args.quantity.unit()),
+            status: name(status),
+            error: None,
+        });
+    } else {
+        println!("{value:.1}");
+    }
+    ExitCode::from(status)
+}
+
+fn read_value(port: Option<&str>, quantity: Quantity) -> Result<f32> {
+    let mut nsrt = open(port)?;
+    match quantity {
+        Quantity::Level => nsrt.read_level(),
+        Quantity::Leq => nsrt.read_leq(),
+        Quantity::Temp => nsrt.read_temperature().map(Temperature::as_celsius),
+    }
+}
+
+/// Name of exit `status` in JSON output
+fn name(status: u8) -> &'static str {
+    match status {
+        OK => "ok",
+        WARNING => "warning",
+        CRITICAL => "critical",
+        NO_DEVICE => "no_device",
+        TIMEOUT => "timeout",
+        _ => "unknown",
+    }
+}
+
+fn status(error: &NsrtError) -> u8 {
+    match error {
+        NsrtError::NoDevice => NO_DEVICE,
+        NsrtError::SerialError(e) => match e.kind() {
+            serialport::ErrorKind::NoDevice => NO_DEVICE,
+            serialport::ErrorKind::Io(io::ErrorKind::NotFound) => NO_DEVICE,
+            serialport::ErrorKind::Io(io::ErrorKind::TimedOut) => TIMEOUT,
+            _ => UNKNOWN,
+        },
+        NsrtError::IoError(e) if e.kind() == io::ErrorKind::NotFound => NO_DEVICE,
+        NsrtError::IoError(e) if e.kind() == io::ErrorKind::TimedOut => TIMEOUT,
+        _ => UNKNOWN,
+    }
+}
@@ -0,0 +1,202 @@
+//! Windows service running the logging daemon
+
+use crate::{daemon, print_json};
+use clap::{Args, Subcommand};
+use nsrt::{NsrtError, Result};
+use serde::Serialize;
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::sync::Notify;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+/// Name the service is registered under
+const SERVICE_NAME: &str = "nsrt";
+
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// Configuration of the running service, set before starting the dispatcher
+static CONFIG: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Args)]
+pub struct ServiceArgs {
+    #[command(subcommand)]
+    command: ServiceCommand,
+}
+
+#[derive(Subcommand)]
+enum ServiceCommand {
+    /// Register `nsrt log --config` as a service starting with Windows
+    Install {
+        /// Daemon configuration; see `docs/nsrt-log.toml`
+        #[arg(short, long)]
+        config: PathBuf,
+    },
+    /// Stop and remove the service
+    Uninstall,
+    /// Run as the service; started by the service control manager
+    #[command(hide = true)]
+    Run {
+        #[arg(short, long)]
+        config: PathBuf,
+    },
+}
+
+/// Result of `nsrt service install --json` or `uninstall --json`
+#[derive(Serialize)]
+struct Registration {
+    service: &'static str,
+    installed: bool,
+}
+
+pub fn service(args: &ServiceArgs, json: bool) -> Result<()> {
+    let report = |installed| {
+        if json {
+            print_json(&Registration {
+                service: SERVICE_NAME,
+                installed,
+            })
+        } else {
+            Ok(())
+        }
+    };
+    match &args.command {
+        ServiceCommand::Install { config } => {
+            install(config)?;
+            report(true)
+        }
+        ServiceCommand::Uninstall => {
+            uninstall()?;
+            report(false)
+        }
+        ServiceCommand::Run { config } => {
+            CONFIG.get_or_init(|| config.clone());
+            service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(service_error)
+        }
+    }
+}
+
+fn install(config: &Path) -> Result<()> {
+    // The service runs with the system directory as working directory
+    let config = config.canonicalize()?;
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(service_error)?;
+
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: "NSRT sound level logger".into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![
+            "service".into(),
+            "run".into(),
+            "--config".into(),
+            config.into_os_string(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)
+        .map_err(service_error)?;
+    service
+        .set_description("Logs NSRT_mk4 sound level meter measurements")
+        .map_err(service_error)?;
+    service.start::<&str>(&[]).map_err(service_error)?;
+
+    eprintln!("Installed and started the {SERVICE_NAME} service");
+    Ok(())
+}
+
+fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(service_error)?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .map_err(service_error)?;
+
+    // Deletion completes once the service has stopped
+    service.delete().map_err(service_error)?;
+    if service.query_status().map_err(service_error)?.current_state != ServiceState::Stopped {
+        service.stop().map_err(service_error)?;
+    }
+
+    eprintln!("Removed the {SERVICE_NAME} service");
+    Ok(())
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    // There is nobody to report a failure to once registration fails
+    let _ = run_service();
+}
+
+fn run_service() -> windows_service::Result<()> {
+    let stop = Arc::new(Notify::new());
+
+    let handler_stop = Arc::clone(&stop);
+    let status = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            handler_stop.notify_one();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+
+    let set_state = |current_state, exit_code| {
+        status.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state,
+            controls_accepted: if current_state == ServiceState::Running {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            } else {
+                ServiceControlAccept::empty()
+            },
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+    set_state(ServiceState::Running, ServiceExitCode::Win32(0))?;
+
+    let config = CONFIG.get().expect("config is set before dispatching");
+    let logged = daemon::run(config, async move {
+        stop.notified().await;
+        Ok(())
+    });
+
+    let exit_code = match logged {
+        Ok(()) => ServiceExitCode::Win32(0),
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    set_state(ServiceState::Stopped, exit_code)
+}
+
+fn service_error(error: windows_service::Error) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
@@ -0,0 +1,241 @@
+use crate::{open, print_json};
+use clap::{Args, ValueEnum};
+use nsrt::{NSRT, NsrtError, Result, SamplingFrequency, Weighting};
+use serde::Serialize;
+use serde_json::{Map, Value as Json};
+
+#[derive(Args)]
+pub struct GetArgs {
+    /// Setting to show; all settings if omitted
+    #[arg(value_enum)]
+    setting: Option<Setting>,
+}
+
+#[derive(Args)]
+pub struct SetArgs {
+    /// Setting to change
+    #[arg(value_enum)]
+    setting: Setting,
+    /// New value: `A`, `C` or `Z` for the weighting, seconds for the time
+    /// constant, 32000 or 48000 Hz for the sampling frequency
+    #[arg(allow_hyphen_values = true)]
+    value: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Setting {
+    /// Weighting curve
+    Weighting,
+    /// Time constant in seconds
+    Tau,
+    /// Sampling frequency in Hz
+    Fs,
+    /// User-defined identifier, at most 31 bytes
+    UserId,
+}
+
+const ALL: [Setting; 4] = [
+    Setting::Weighting,
+    Setting::Tau,
+    Setting::Fs,
+    Setting::UserId,
+];
+
+/// A parsed setting value
+#[derive(Debug, PartialEq)]
+enum Value {
+    Weighting(Weighting),
+    Tau(f32),
+    Fs(SamplingFrequency),
+    UserId(String),
+}
+
+impl Setting {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Weighting => "weighting",
+            Self::Tau => "tau",
+            Self::Fs => "fs",
+            Self::UserId => "user-id",
+        }
+    }
+
+    /// Key of the setting in JSON output
+    fn key(self) -> &'static str {
+        match self {
+            Self::UserId => "user_id",
+            _ => self.name(),
+        }
+    }
+
+    fn parse(self, value: &str) -> Result<Value> {
+        let invalid = |reason: &str| {
+            NsrtError::InvalidParameter(format!("{} `{value}`: {reason}", self.name()))
+        };
+
+        Ok(match self {
+            Self::Weighting => Value::Weighting(match value.to_ascii_uppercase().as_str() {
+                "A" => Weighting::A,
+                "C" => Weighting::C,
+                "Z" => Weighting::Z,
+                _ => return Err(invalid("expected A, C or Z")),
+            }),
+            Self::Tau => {
+                let tau: f32 = value.parse().map_err(|_| invalid("not a number"))?;
+                if !tau.is_finite() || tau <= 0.0 {
+                    return Err(invalid("must be a positive number of seconds"));
+                }
+                Value::Tau(tau)
+            }
+            Self::Fs => {
+                let hz: u32 = value.parse().map_err(|_| invalid("not a number"))?;
+                Value::Fs(
+                    SamplingFrequency::try_from(hz)
+                        .map_err(|_| invalid("expected 32000 or 48000"))?,
+                )
+            }
+            Self::UserId => {
+                if value.len() > 31 {
+                    return Err(invalid("longer than 31 bytes"));
+                }
+                if value.contains('\0') {
+                    return Err(invalid("contains a NUL character"));
+                }
+                Value::UserId(value.to_string())
+            }
+        })
+    }
+
+    fn read(self, nsrt: &mut NSRT) -> Result<Value> {
+        Ok(match self {
+            Self::Weighting => Value::Weighting(nsrt.read_weighting()?),
+            Self::Tau => Value::Tau(nsrt.read_time_constant()?),
+            Self::Fs => Value::Fs(nsrt.read_sampling_frequency()?),
+            Self::UserId => Value::UserId(nsrt.read_user_id()?),
+        })
+    }
+}
+
+impl Value {
+    /// Write the value, waiting for the device to stabilize where needed
+    fn write(&self, nsrt: &mut NSRT) -> Result<()> {
+        match self {
+            Self::Weighting(weighting) => nsrt.set_weighting(*weighting),
+            Self::Tau(tau) => nsrt.set_time_constant(*tau),
+            Self::Fs(fs) => nsrt.set_sampling_frequency(*fs),
+            Self::UserId(user_id) => nsrt.set_user_id(user_id),
+        }
+    }
+}
+
+impl Value {
+    /// The value in JSON output: a string for the weighting and user ID, a
+    /// number otherwise
+    fn to_json(&self) -> Json {
+        match self {
+            Self::Weighting(weighting) => Json::from(format!("{weighting:?}")),
+            Self::Tau(tau) => Json::from(*tau),
+            Self::Fs(fs) => Json::from(u32::from(*fs)),
+            Self::UserId(user_id) => Json::from(user_id.as_str()),
+        }
+    }
+}
+
+/// Result of `nsrt set --json`
+#[derive(Serialize)]
+struct Changed {
+    setting: &'static str,
+    previous: Json,
+    value: Json,
+    /// False if the setting already had the value and wasn't written
+    changed: bool,
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Weighting(weighting) => write!(f, "{weighting:?}"),
+            Self::Tau(tau) => write!(f, "{tau}"),
+            Self::Fs(fs) => write!(f, "{}", u32::from(*fs)),
+            Self::UserId(user_id) => f.write_str(user_id),
+        }
+    }
+}
+
+/// Print one setting's bare value, or all settings as `name: value` lines
+///
+/// As JSON, settings are printed as one object keyed by setting, holding
+/// only the requested one if given.
+pub fn get(mut nsrt: NSRT, args: &GetArgs, json: bool) -> Result<()> {
+    if json {
+        let settings = match args.setting {
+            Some(setting) => vec![setting],
+            None => ALL.to_vec(),
+        };
+        let mut values = Map::new();
+        for setting in settings {
+            values.insert(
+                setting.key().to_string(),
+                setting.read(&mut nsrt)?.to_json(),
+            );
+        }
+        return print_json(&values);
+    }
+
+    match args.setting {
+        Some(setting) => println!("{}", setting.read(&mut nsrt)?),
+        None => {
+            for setting in ALL {
+                println!("{}: {}", setting.name(), setting.read(&mut nsrt)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Change a setting and confirm it by reading it back
+///
+/// The value is validated before the device is opened.
+pub fn set(port: Option<&str>, args: &SetArgs, json: bool) -> Result<()> {
+    let value = args.setting.parse(&args.value)?;
+    let mut nsrt = open(port)?;
+
+    let current = args.setting.read(&mut nsrt)?;
+    if current == value {
+        // Settings live in flash with limited write cycles
+        if json {
+            return print_json(&Changed {
+                setting: args.setting.key(),
+                previous: current.to_json(),
+                value: value.to_json(),
+                changed: false,
+            });
+        }
+        eprintln!("{} is already {value}", args.setting.name());
+        return Ok(());
+    }
+
+    value.write(&mut nsrt)?;
+
+    let confirmed = args.setting.read(&mut nsrt)?;
+    if confirmed != value {
+        eprintln!(
+            "{} reads back as {confirmed} after setting it to {value}",
+            args.setting.name()
+        );
+        return Err(NsrtError::InvalidResponse);
+    }
+    if json {
+        return print_json(&Changed {
+            setting: args.setting.key(),
+            previous: current.to_json(),
+            value: confirmed.to_json(),
+            changed: true,
+        });
+    }
+    eprintln!(
+        "{} changed from {current} to {confirmed}",
+        args.setting.name()
+    );
+    Ok(())
+}
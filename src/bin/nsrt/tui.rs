@@ -0,0 +1,380 @@
+//! Live terminal dashboard
+
+use crate::SamplingArgs;
+use clap::Args;
+use nsrt::{
+    DeviceConfig, DeviceInfo, Measurement, NSRT, Result, Sampler, Threshold, ThresholdMonitor,
+};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, Gauge, Paragraph, Row, Sparkline, Table},
+};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::TryRecvError,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Range of the level gauge and history in dB
+const SCALE: (f32, f32) = (30.0, 130.0);
+
+/// Levels kept for the history sparkline
+const HISTORY: usize = 1024;
+
+/// Width of the level statistics bins in dB
+const BIN_WIDTH: f32 = 0.1;
+
+/// Time between checks for keyboard input and new measurements
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Args)]
+pub struct TuiArgs {
+    #[command(flatten)]
+    sampling: SamplingArgs,
+    /// Show an alarm banner while the level is above this many dB
+    #[arg(short, long)]
+    alarm: Option<f32>,
+    /// Drop in dB below the alarm level required to clear the alarm
+    #[arg(long, default_value_t = 3.0, requires = "alarm")]
+    hysteresis: f32,
+}
+
+/// Show the dashboard until the user quits
+pub fn tui(mut nsrt: NSRT, args: &TuiArgs) -> Result<()> {
+    let info = nsrt.read_info()?;
+    let config = nsrt.read_config()?;
+    let sampler = Sampler::start(nsrt, args.sampling.interval);
+    let alarm = args
+        .alarm
+        .map(|level| ThresholdMonitor::new(Threshold::new(level).hysteresis(args.hysteresis)));
+
+    let mut dashboard = Dashboard::new(info, config, alarm);
+    let shown = ratatui::run(|terminal| dashboard.run(terminal, &sampler));
+
+    let stopped = sampler.stop();
+    shown.and(stopped)
+}
+
+struct Dashboard {
+    info: DeviceInfo,
+    config: DeviceConfig,
+    alarm: Option<ThresholdMonitor>,
+    alarm_since: Option<SystemTime>,
+    latest: Option<Measurement>,
+    history: VecDeque<f32>,
+    stats: Statistics,
+}
+
+impl Dashboard {
+    fn new(info: DeviceInfo, config: DeviceConfig, alarm: Option<ThresholdMonitor>) -> Self {
+        Self {
+            info,
+            config,
+            alarm,
+            alarm_since: None,
+            latest: None,
+            history: VecDeque::with_capacity(HISTORY),
+            stats: Statistics::default(),
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal, sampler: &Sampler) -> Result<()> {
+        let measurements = sampler.subscribe();
+
+        loop {
+            loop {
+                match measurements.try_recv() {
+                    Ok(measurement) => self.update(&measurement),
+                    Err(TryRecvError::Empty) => break,
+                    // The sampler failed; its error is returned when stopping it
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(TICK)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(());
+                    }
+                    KeyCode::Char('r') => self.stats = Statistics::default(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, measurement: &Measurement) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(measurement.level);
+        self.stats.add(measurement);
+        self.latest = Some(*measurement);
+
+        if let Some(alarm) = &mut self.alarm {
+            match alarm.update(measurement) {
+                Some(nsrt::ThresholdEvent::Exceeded(m)) => self.alarm_since = Some(m.timestamp),
+                Some(nsrt::ThresholdEvent::Cleared(_)) => self.alarm_since = None,
+                None => {}
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let alarm_height = if self.alarm_since.is_some() { 3 } else { 0 };
+        let [header, alarm, gauge, history, stats, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(alarm_height),
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(7),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_header(frame, header);
+        if let Some(since) = self.alarm_since {
+            self.draw_alarm(frame, alarm, since);
+        }
+        self.draw_gauge(frame, gauge);
+        self.draw_history(frame, history);
+        self.draw_stats(frame, stats);
+        frame.render_widget(
+            Line::from(" q quit   r reset statistics").dark_gray(),
+            footer,
+        );
+    }
+
+    fn draw_header(&self, frame: &mut Frame, area: Rect) {
+        let info = &self.info;
+        let text = format!(
+            "{} · S/N {} · firmware {} · {} · τ {} s · {} Hz · calibrated {}",
+            info.model,
+            info.serial_number,
+            info.firmware_revision,
+            self.unit(),
+            self.config.time_constant,
+            u32::from(self.config.sampling_frequency),
+            &humantime::format_rfc3339_seconds(info.calibration_time()).to_string()[..10],
+        );
+        let title = if info.user_id.is_empty() {
+            " NSRT ".to_string()
+        } else {
+            format!(" {} ", info.user_id)
+        };
+        frame.render_widget(
+            Paragraph::new(text).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    fn draw_alarm(&self, frame: &mut Frame, area: Rect, since: SystemTime) {
+        let level = self.alarm.as_ref().map_or(0.0, |a| a.threshold().level);
+        let text = format!(
+            "ALARM: level above {level:.1} {} since {}",
+            self.unit(),
+            humantime::format_rfc3339_seconds(since)
+        );
+        frame.render_widget(
+            Paragraph::new(text)
+                .centered()
+                .style(Style::new().fg(Color::White).bg(Color::Red))
+                .add_modifier(Modifier::BOLD)
+                .block(Block::bordered().red()),
+            area,
+        );
+    }
+
+    fn draw_gauge(&self, frame: &mut Frame, area: Rect) {
+        let level = self.latest.map_or(f32::NAN, |m| m.level);
+        let ratio = if level.is_finite() {
+            f64::from((level - SCALE.0) / (SCALE.1 - SCALE.0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let color = if self.alarm_since.is_some() {
+            Color::Red
+        } else {
+            Color::Green
+        };
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title(" Level "))
+                .gauge_style(color)
+                .ratio(ratio)
+                .label(format!("{} {}", format_level(level), self.unit())),
+            area,
+        );
+    }
+
+    fn draw_history(&self, frame: &mut Frame, area: Rect) {
+        // One bar per column, newest on the right
+        let width = usize::from(area.width.saturating_sub(2));
+        let skip = self.history.len().saturating_sub(width);
+        let bars: Vec<u64> = self
+            .history
+            .iter()
+            .skip(skip)
+            .map(|level| {
+                let tenths = ((level - SCALE.0) * 10.0).clamp(0.0, (SCALE.1 - SCALE.0) * 10.0);
+                tenths as u64
+            })
+            .collect();
+
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title(format!(
+                    " History ({:.0}–{:.0} {}) ",
+                    SCALE.0,
+                    SCALE.1,
+                    self.unit()
+                )))
+                .max(((SCALE.1 - SCALE.0) * 10.0) as u64)
+                .data(bars)
+                .cyan(),
+            area,
+        );
+    }
+
+    fn draw_stats(&self, frame: &mut Frame, area: Rect) {
+        let stats = &self.stats;
+        let unit = self.unit();
+        let level =
+            |value: Option<f32>| format!("{} {unit}", format_level(value.unwrap_or(f32::NAN)));
+
+        let rows = [
+            [
+                "LEQ",
+                &level(stats.leq()),
+                "L10",
+                &level(stats.exceedance(10.0)),
+            ],
+            [
+                "Lmax",
+                &level(stats.max),
+                "L50",
+                &level(stats.exceedance(50.0)),
+            ],
+            [
+                "Lmin",
+                &level(stats.min),
+                "L90",
+                &level(stats.exceedance(90.0)),
+            ],
+            [
+                "Temperature",
+                &self
+                    .latest
+                    .map_or("–".to_string(), |m| format!("{:.1}", m.temperature)),
+                "Samples",
+                &stats.count.to_string(),
+            ],
+            [
+                "Duration",
+                &humantime::format_duration(Duration::from_secs(stats.started.elapsed().as_secs()))
+                    .to_string(),
+                "",
+                "",
+            ],
+        ]
+        .map(|row| Row::new(row.map(str::to_string)));
+
+        frame.render_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(12),
+                    Constraint::Length(14),
+                    Constraint::Length(8),
+                    Constraint::Length(14),
+                ],
+            )
+            .block(Block::bordered().title(" Statistics since start or reset ")),
+            area,
+        );
+    }
+
+    fn unit(&self) -> String {
+        format!("dB({:?})", self.config.weighting)
+    }
+}
+
+fn format_level(level: f32) -> String {
+    if level.is_finite() {
+        format!("{level:.1}")
+    } else {
+        "–".to_string()
+    }
+}
+
+/// Running level statistics with bounded memory
+///
+/// Levels are counted in [`BIN_WIDTH`] bins for the exceedance levels, so
+/// those are accurate to one bin.
+struct Statistics {
+    started: Instant,
+    count: u64,
+    energy: f64,
+    max: Option<f32>,
+    min: Option<f32>,
+    bins: Vec<u64>,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            count: 0,
+            energy: 0.0,
+            max: None,
+            min: None,
+            bins: vec![0; (200.0 / BIN_WIDTH) as usize],
+        }
+    }
+}
+
+impl Statistics {
+    fn add(&mut self, measurement: &Measurement) {
+        let level = measurement.level;
+        if !level.is_finite() || !measurement.leq.is_finite() {
+            return;
+        }
+
+        self.count += 1;
+        self.energy += 10f64.powf(f64::from(measurement.leq) / 10.0);
+        self.max = Some(self.max.map_or(level, |max| max.max(level)));
+        self.min = Some(self.min.map_or(level, |min| min.min(level)));
+
+        let bin = ((level / BIN_WIDTH) as usize).min(self.bins.len() - 1);
+        self.bins[bin] += 1;
+    }
+
+    /// Energy average of the LEQs
+    fn leq(&self) -> Option<f32> {
+        (self.count > 0).then(|| (10.0 * (self.energy / self.count as f64).log10()) as f32)
+    }
+
+    /// Level exceeded `n` percent of the time, by nearest rank
+    fn exceedance(&self, n: f32) -> Option<f32> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (((100.0 - n) / 100.0) * self.count as f32).ceil().max(1.0) as u64;
+
+        let mut seen = 0;
+        self.bins.iter().enumerate().find_map(|(bin, &count)| {
+            seen += count;
+            (seen >= rank).then_some(bin as f32 * BIN_WIDTH)
+        })
+    }
+}
@@ -0,0 +1,96 @@
+//! Broker owning an NSRT_mk4 so several processes can use it at once
+//!
+//! Usage: `nsrtd [--port <serial port>] [--socket <path>] [--listen <address>] [--no-mdns]`
+//!
+//! With the `mdns` feature, a broker listening on TCP is advertised over mDNS
+//! unless `--no-mdns` is given or it listens on a loopback address.
+
+use nsrt::{DeviceHandle, NSRT, NsrtError, Result, broker};
+use std::{path::PathBuf, process::ExitCode};
+use tokio::runtime::Runtime;
+
+const USAGE: &str =
+    "Usage: nsrtd [--port <serial port>] [--socket <path>] [--listen <address>] [--no-mdns]";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("nsrtd: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let mut port = None;
+    let mut socket = broker::default_path();
+    let mut listen = None;
+    let mut mdns = true;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| NsrtError::InvalidParameter(format!("{arg} needs a value")))
+        };
+        match arg.as_str() {
+            "-p" | "--port" => port = Some(value()?),
+            "-s" | "--socket" => socket = PathBuf::from(value()?),
+            "-l" | "--listen" => listen = Some(value()?),
+            "--no-mdns" => mdns = false,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => return Err(NsrtError::InvalidParameter(format!("{arg}\n{USAGE}"))),
+        }
+    }
+
+    let nsrt = match &port {
+        Some(port) => NSRT::open_port(port)?,
+        None => NSRT::open()?,
+    };
+    let device = DeviceHandle::spawn(nsrt);
+    #[cfg(feature = "mdns")]
+    let _advertisement = match &listen {
+        Some(listen) if mdns => advertise(&device, listen)?,
+        _ => None,
+    };
+    #[cfg(not(feature = "mdns"))]
+    let _ = mdns;
+
+    eprintln!("Serving the device on {}", socket.display());
+    Runtime::new()?.block_on(async {
+        match listen {
+            Some(addr) => {
+                eprintln!("Serving the device over TCP on {addr}");
+                tokio::try_join!(
+                    broker::serve(&socket, device.clone()),
+                    broker::serve_tcp(addr, device)
+                )
+                .map(|_| ())
+            }
+            None => broker::serve(&socket, device).await,
+        }
+    })?;
+    Ok(())
+}
+
+/// Advertise the broker listening at `listen` over mDNS, unless it is only
+/// reachable from this host
+#[cfg(feature = "mdns")]
+fn advertise(device: &DeviceHandle, listen: &str) -> Result<Option<nsrt::mdns::Advertisement>> {
+    use nsrt::mdns::{Api, Service};
+    use std::net::ToSocketAddrs;
+
+    let Some(addr) = listen.to_socket_addrs()?.next() else {
+        return Ok(None);
+    };
+    if addr.ip().is_loopback() {
+        return Ok(None);
+    }
+    let serial = device.call(NSRT::read_info)?.serial_number;
+    let service = Service::new(&serial, Api::Broker, addr.port()).address(addr.ip());
+    Ok(Some(service.advertise()?))
+}
@@ -0,0 +1,349 @@
+//! Compact binary log format
+//!
+//! A log file starts with the 8-byte magic `NSRTLOG2`, whose last character
+//! is the schema version, followed by any number of concatenated zstd frames.
+//! Decompressed, the frames form a sequence of fixed-size 29-byte records:
+//!
+//! | Offset | Size | Field                                        |
+//! | ------ | ---- | -------------------------------------------- |
+//! | 0      | 8    | timestamp seconds since the Unix epoch, `u64`|
+//! | 8      | 4    | timestamp nanoseconds, `u32`                 |
+//! | 12     | 4    | level in dB, `f32`                           |
+//! | 16     | 4    | LEQ in dB, `f32`                             |
+//! | 20     | 4    | temperature in °C, `f32`                     |
+//! | 24     | 1    | quality flags, [`Quality::bits`]             |
+//! | 25     | 4    | CRC-32 (IEEE) of bytes 0–24, `u32`           |
+//!
+//! All integers and floats are little-endian. The writer compresses records
+//! in blocks, each an independent zstd frame, so a crash loses at most the
+//! block being filled, and logs can be appended to after a restart.
+//!
+//! Records don't hold a measurement's clock status or compensated values,
+//! but an unsynchronized clock is kept as the `unsynchronized` quality flag.
+//! [`compact`] shrinks a log by replacing aged records with one per hour or
+//! day.
+//!
+//! | Version | Changes                                              |
+//! | ------- | ---------------------------------------------------- |
+//! | 1       | 28-byte records without quality flags                |
+//! | 2       | quality flags                                        |
+//!
+//! Readers read logs of every version up to [`SCHEMA_VERSION`], those of
+//! version 1 as good measurements, and refuse later ones with
+//! [`NsrtError::UnsupportedSchema`] rather than misread them.
+
+use crate::{Measurement, NsrtError, Quality, Result, Sink, Temperature};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Magic bytes at the start of every log file
+pub const MAGIC: &[u8; 8] = b"NSRTLOG2";
+
+/// Schema version of the logs written, the last character of [`MAGIC`]
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Size of a decompressed record
+pub const RECORD_LEN: usize = Measurement::STORED_LEN + 4;
+
+/// Records compressed together in one zstd frame
+pub(crate) const BLOCK_RECORDS: usize = 64;
+
+/// zstd compression level
+pub(crate) const COMPRESSION_LEVEL: i32 = 3;
+
+/// Sink writing measurements to a binary log
+///
+/// Buffered records are compressed and written when a block fills up, on
+/// [`Sink::flush`], and when the writer is dropped.
+pub struct BinaryLogWriter<W: Write> {
+    inner: W,
+    version: u32,
+    block: Vec<u8>,
+}
+
+impl BinaryLogWriter<File> {
+    /// Create a new log file at `path`, replacing any existing file
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Append to the log file at `path`, creating it if it doesn't exist
+    ///
+    /// Records are written in the schema version of the log, so those
+    /// appended to a version 1 log lose their quality flags.
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Self::new(file);
+        }
+        let version = read_magic(&mut file, MAGIC, "Binary log")?;
+        Ok(Self::resume(file, version))
+    }
+}
+
+impl<W: Write> BinaryLogWriter<W> {
+    /// Start a new log on `inner`, writing the file header
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(MAGIC)?;
+        Ok(Self::resume(inner, SCHEMA_VERSION))
+    }
+
+    fn resume(inner: W, version: u32) -> Self {
+        Self {
+            inner,
+            version,
+            block: Vec::with_capacity(BLOCK_RECORDS * RECORD_LEN),
+        }
+    }
+
+    /// The underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    fn write_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let compressed = zstd::bulk::compress(&self.block, COMPRESSION_LEVEL)?;
+        self.inner.write_all(&compressed)?;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for BinaryLogWriter<W> {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        encode_record(measurement, self.version, &mut self.block);
+        if self.block.len() >= BLOCK_RECORDS * record_len(self.version) {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_block()?;
+        Ok(self.inner.flush()?)
+    }
+}
+
+impl<W: Write> Drop for BinaryLogWriter<W> {
+    fn drop(&mut self) {
+        let _ = Sink::flush(self);
+    }
+}
+
+/// Iterator over the measurements in a binary log
+///
+/// Yields an error for a record whose CRC doesn't match and for a log that
+/// ends partway through a record.
+pub struct BinaryLogReader<R: Read> {
+    decoder: zstd::Decoder<'static, BufReader<R>>,
+    version: u32,
+}
+
+impl BinaryLogReader<File> {
+    /// Open the log file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read> BinaryLogReader<R> {
+    /// Read a log from `inner`, checking the file header
+    pub fn new(mut inner: R) -> Result<Self> {
+        let version = read_magic(&mut inner, MAGIC, "Binary log")?;
+        Ok(Self {
+            decoder: zstd::Decoder::new(inner)?,
+            version,
+        })
+    }
+
+    /// Schema version the log was written with
+    pub fn schema_version(&self) -> u32 {
+        self.version
+    }
+
+    fn read_record(&mut self) -> Result<Option<Measurement>> {
+        let mut record = [0; RECORD_LEN];
+        let record = &mut record[..record_len(self.version)];
+        let mut filled = 0;
+        while filled < record.len() {
+            match self.decoder.read(&mut record[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(invalid_data("Truncated record")),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        decode_record(record).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for BinaryLogReader<R> {
+    type Item = Result<Measurement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Size of a record of schema `version`
+pub(crate) fn record_len(version: u32) -> usize {
+    match version {
+        1 => Measurement::ENCODED_LEN + 4,
+        _ => RECORD_LEN,
+    }
+}
+
+/// Append `measurement` as a record of schema `version` with its CRC
+pub(crate) fn encode_record(measurement: &Measurement, version: u32, out: &mut Vec<u8>) {
+    let stored = measurement.encode_stored();
+    let data = &stored[..record_len(version) - 4];
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+}
+
+/// Decode a record, of the schema version its length gives, checking its CRC
+pub(crate) fn decode_record(record: &[u8]) -> Result<Measurement> {
+    let (data, crc) = record.split_at(record.len() - 4);
+    if crc32fast::hash(data).to_le_bytes() != crc {
+        return Err(invalid_data("Record CRC mismatch"));
+    }
+    match data.try_into() {
+        Ok(stored) => Measurement::decode_stored(stored),
+        Err(_) => Measurement::decode(data.try_into().expect("version 1 record data")),
+    }
+}
+
+/// Replace the records of every `period` that ended by `before` with one
+/// aggregate record each, rewriting the log at `path` in place
+///
+/// Periods are aligned to multiples of their length since the Unix epoch, so
+/// an hour or a day in UTC. An aggregate is stamped with the period start and
+/// holds the highest level, the energy average of the LEQs, the mean
+/// temperature and the quality flags of every record, so compacting again is
+/// harmless. Records have no room for
+/// exceedance levels, which SQLite storage keeps when compacted. The log is
+/// written to a temporary file next to it and renamed over it, and must not
+/// be appended to meanwhile. Returns the number of records replaced.
+///
+/// # Panics
+///
+/// If `period` is zero.
+pub fn compact(path: impl AsRef<Path>, before: SystemTime, period: Duration) -> Result<u64> {
+    assert!(!period.is_zero(), "compaction period must not be zero");
+    let path = path.as_ref();
+    let length = period.as_nanos();
+    let since_epoch = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let cutoff = since_epoch(before).as_nanos() / length;
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".compacting");
+    let temporary = path.with_file_name(name);
+    let mut writer = BinaryLogWriter::create(&temporary)?;
+
+    let mut compacted = 0;
+    let mut current: Option<(u128, Vec<Measurement>)> = None;
+    for measurement in BinaryLogReader::open(path)? {
+        let measurement = measurement?;
+        let index = since_epoch(measurement.timestamp).as_nanos() / length;
+        if current
+            .as_ref()
+            .is_some_and(|(current, _)| *current != index)
+        {
+            let (index, records) = current.take().expect("checked above");
+            writer.write(&aggregate(index, period, &records))?;
+        }
+        if index >= cutoff {
+            writer.write(&measurement)?;
+            continue;
+        }
+        compacted += 1;
+        current
+            .get_or_insert_with(|| (index, Vec::new()))
+            .1
+            .push(measurement);
+    }
+    if let Some((index, records)) = current {
+        writer.write(&aggregate(index, period, &records))?;
+    }
+    Sink::flush(&mut writer)?;
+    drop(writer);
+
+    fs::rename(&temporary, path)?;
+    Ok(compacted)
+}
+
+/// Aggregate record of the period with `index` of length `period`
+fn aggregate(index: u128, period: Duration, records: &[Measurement]) -> Measurement {
+    let count = records.len() as f64;
+    let energy: f64 = records
+        .iter()
+        .map(|m| 10f64.powf(f64::from(m.leq) / 10.0))
+        .sum();
+    let temperature: f64 = records
+        .iter()
+        .map(|m| f64::from(m.temperature.as_celsius()))
+        .sum();
+    let start = index * period.as_nanos();
+    Measurement {
+        timestamp: UNIX_EPOCH
+            + Duration::new(
+                (start / 1_000_000_000) as u64,
+                (start % 1_000_000_000) as u32,
+            ),
+        level: records.iter().map(|m| m.level).fold(f32::MIN, f32::max),
+        leq: (10.0 * (energy / count).log10()) as f32,
+        temperature: Temperature::from_celsius((temperature / count) as f32),
+        quality: records
+            .iter()
+            .fold(Quality::GOOD, |quality, m| quality | m.quality),
+        ..records[0]
+    }
+}
+
+/// Read a magic like `magic` but for its last character, returning the
+/// schema version that character gives
+///
+/// Fails for a version newer than the one in `magic`, which is the latest.
+pub(crate) fn read_magic(
+    inner: &mut impl Read,
+    magic: &[u8; 8],
+    format: &'static str,
+) -> Result<u32> {
+    let mut read = [0; 8];
+    inner.read_exact(&mut read)?;
+    let version = match (read[..7] == magic[..7], read[7]) {
+        (true, digit @ b'1'..=b'9') => u32::from(digit - b'0'),
+        _ => {
+            return Err(invalid_data(&format!(
+                "Not an NSRT {}",
+                format.to_lowercase()
+            )));
+        }
+    };
+    let supported = u32::from(magic[7] - b'0');
+    if version > supported {
+        return Err(NsrtError::UnsupportedSchema {
+            format,
+            version,
+            supported,
+        });
+    }
+    Ok(version)
+}
+
+fn invalid_data(message: &str) -> NsrtError {
+    NsrtError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
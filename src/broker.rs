@@ -0,0 +1,313 @@
+//! Broker sharing one device between processes, locally or over the network
+//!
+//! The serial port can only be opened by one process at a time. [`serve`]
+//! owns the device and answers requests from any number of local clients
+//! over a Unix domain socket, or a named pipe on Windows. [`BrokerClient`]
+//! talks to it and implements [`SoundLevelMeter`], so it can stand in for a
+//! directly attached [`NSRT`](crate::NSRT).
+//!
+//! [`serve_tcp`] offers the same protocol over TCP, and [`RemoteNsrt`] is the
+//! matching client, so analysis code on a workstation can use a meter
+//! attached to an edge gateway. The protocol has no authentication or
+//! encryption: listen on a trusted network only, or tunnel it, e.g. through
+//! SSH or WireGuard.
+//!
+//! The protocol is line-delimited JSON. Each request is answered in order
+//! with either `{"result": ...}` or `{"error": "..."}`:
+//!
+//! | Request                                               | Result           |
+//! | ----------------------------------------------------- | ---------------- |
+//! | `{"method": "read_level"}`                            | level in dB      |
+//! | `{"method": "read_leq"}`                              | LEQ in dB        |
+//! | `{"method": "read_temperature"}`                      | temperature in °C |
+//! | `{"method": "read_measurement"}`                      | [`Measurement`]  |
+//! | `{"method": "read_info"}`                             | [`DeviceInfo`]   |
+//! | `{"method": "read_config"}`                           | [`DeviceConfig`] |
+//! | `{"method": "configure", "params": {DeviceConfig}}`   | `null`           |
+//!
+//! Requests from all clients are run one at a time against the device, so a
+//! `configure`, which waits for the device to stabilize, delays everyone.
+
+use crate::{
+    DeviceConfig, DeviceHandle, DeviceInfo, Measurement, NsrtError, Result, SoundLevelMeter,
+    Temperature,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+
+/// A request to the broker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+enum Request {
+    ReadLevel,
+    ReadLeq,
+    ReadTemperature,
+    ReadMeasurement,
+    ReadInfo,
+    ReadConfig,
+    Configure(DeviceConfig),
+}
+
+/// The broker's answer to a request
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Result(serde_json::Value),
+    Error(String),
+}
+
+/// Default socket path, or pipe name on Windows
+///
+/// `$XDG_RUNTIME_DIR/nsrt.sock`, falling back to the temporary directory.
+pub fn default_path() -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(r"\\.\pipe\nsrt")
+    } else {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map_or_else(std::env::temp_dir, PathBuf::from)
+            .join("nsrt.sock")
+    }
+}
+
+/// Serve `device` to clients connecting at `path` until the task is cancelled
+///
+/// A socket left behind by a broker that is no longer running is replaced.
+/// A failing connection only affects that client.
+#[cfg(unix)]
+pub async fn serve(path: impl AsRef<Path>, device: DeviceHandle) -> io::Result<()> {
+    use tokio::net::UnixListener;
+
+    let path = path.as_ref();
+    let listener = match UnixListener::bind(path) {
+        Err(e)
+            if e.kind() == io::ErrorKind::AddrInUse
+                && std::os::unix::net::UnixStream::connect(path).is_err() =>
+        {
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)?
+        }
+        listener => listener?,
+    };
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_connection(stream, device.clone()));
+    }
+}
+
+/// Serve `device` to clients connecting at `path` until the task is cancelled
+///
+/// Fails if another broker already serves the pipe. A failing connection only
+/// affects that client.
+#[cfg(windows)]
+pub async fn serve(path: impl AsRef<Path>, device: DeviceHandle) -> io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = path.as_ref();
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(path)?;
+
+    loop {
+        server.connect().await?;
+        // Create the next instance before handing this one off, so clients
+        // never find the pipe missing
+        let connected = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+        tokio::spawn(handle_connection(connected, device.clone()));
+    }
+}
+
+/// Serve `device` to clients connecting over TCP at `addr` until the task is
+/// cancelled
+///
+/// Anyone who can reach `addr` can read and reconfigure the device. A failing
+/// connection only affects that client.
+pub async fn serve_tcp(
+    addr: impl tokio::net::ToSocketAddrs,
+    device: DeviceHandle,
+) -> io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        stream.set_nodelay(true)?;
+        tokio::spawn(handle_connection(stream, device.clone()));
+    }
+}
+
+async fn handle_connection<S>(stream: S, device: DeviceHandle) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => match dispatch(&device, request).await {
+                Ok(result) => Response::Result(result),
+                Err(e) => Response::Error(e.to_string()),
+            },
+            Err(e) => Response::Error(format!("Invalid request: {e}")),
+        };
+
+        let mut json = serde_json::to_vec(&response).map_err(io::Error::other)?;
+        json.push(b'\n');
+        writer.write_all(&json).await?;
+    }
+    Ok(())
+}
+
+async fn dispatch(device: &DeviceHandle, request: Request) -> Result<serde_json::Value> {
+    let result = match request {
+        Request::ReadLevel => serde_json::to_value(device.call_async(|n| n.read_level()).await?),
+        Request::ReadLeq => serde_json::to_value(device.call_async(|n| n.read_leq()).await?),
+        Request::ReadTemperature => {
+            serde_json::to_value(device.call_async(|n| n.read_temperature()).await?)
+        }
+        Request::ReadMeasurement => {
+            serde_json::to_value(device.call_async(|n| n.read_measurement()).await?)
+        }
+        Request::ReadInfo => serde_json::to_value(device.call_async(|n| n.read_info()).await?),
+        Request::ReadConfig => serde_json::to_value(device.call_async(|n| n.read_config()).await?),
+        Request::Configure(config) => {
+            serde_json::to_value(device.call_async(move |n| n.configure(&config)).await?)
+        }
+    };
+    result.map_err(|e| NsrtError::IoError(io::Error::other(e)))
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+/// Client of a device shared by a broker
+///
+/// Calls block until the broker has answered. Errors reported by the broker,
+/// including device errors, are returned as [`NsrtError::BrokerError`].
+pub struct BrokerClient {
+    stream: BufReader<Stream>,
+}
+
+impl BrokerClient {
+    /// Connect to the broker serving at `path`, see [`default_path`]
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        #[cfg(unix)]
+        let stream = Stream::connect(path)?;
+        #[cfg(windows)]
+        let stream = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        Ok(Self {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    fn request<T: DeserializeOwned>(&mut self, request: &Request) -> Result<T> {
+        send(&mut self.stream, request)
+    }
+}
+
+/// Client of a device shared by a broker over the network, see
+/// [`serve_tcp`]
+///
+/// Behaves like [`BrokerClient`]. Without a timeout, calls wait for the
+/// broker indefinitely; a call that times out leaves the connection out of
+/// step with the broker, so reconnect after one.
+pub struct RemoteNsrt {
+    stream: BufReader<TcpStream>,
+}
+
+impl RemoteNsrt {
+    /// Connect to the broker listening at `addr`, e.g. `"gateway:7300"`
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    /// Set how long calls wait for the broker to answer, `None` for no limit
+    ///
+    /// Allow for `configure`, which waits for the device to stabilize, and
+    /// for requests queued behind other clients'.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        let stream = self.stream.get_ref();
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn request<T: DeserializeOwned>(&mut self, request: &Request) -> Result<T> {
+        send(&mut self.stream, request)
+    }
+}
+
+/// Send `request` over `stream` and wait for its answer
+fn send<S: Read + Write, T: DeserializeOwned>(
+    stream: &mut BufReader<S>,
+    request: &Request,
+) -> Result<T> {
+    let mut json = serde_json::to_vec(request).map_err(io::Error::other)?;
+    json.push(b'\n');
+    stream.get_mut().write_all(&json)?;
+
+    let mut line = String::new();
+    if stream.read_line(&mut line)? == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    match serde_json::from_str(&line).map_err(|_| NsrtError::InvalidResponse)? {
+        Response::Result(value) => {
+            serde_json::from_value(value).map_err(|_| NsrtError::InvalidResponse)
+        }
+        Response::Error(message) => Err(NsrtError::BrokerError(message)),
+    }
+}
+
+/// Implement [`SoundLevelMeter`] by sending each call as a broker request
+macro_rules! impl_sound_level_meter {
+    ($client:ty) => {
+        impl SoundLevelMeter for $client {
+            fn read_level(&mut self) -> Result<f32> {
+                self.request(&Request::ReadLevel)
+            }
+
+            fn read_leq(&mut self) -> Result<f32> {
+                self.request(&Request::ReadLeq)
+            }
+
+            fn read_temperature(&mut self) -> Result<Temperature> {
+                self.request(&Request::ReadTemperature)
+            }
+
+            fn read_measurement(&mut self) -> Result<Measurement> {
+                self.request(&Request::ReadMeasurement)
+            }
+
+            fn read_info(&mut self) -> Result<DeviceInfo> {
+                self.request(&Request::ReadInfo)
+            }
+
+            fn read_config(&mut self) -> Result<DeviceConfig> {
+                self.request(&Request::ReadConfig)
+            }
+
+            fn configure(&mut self, config: &DeviceConfig) -> Result<()> {
+                self.request(&Request::Configure(*config))
+            }
+        }
+    };
+}
+
+impl_sound_level_meter!(BrokerClient);
+impl_sound_level_meter!(RemoteNsrt);
@@ -0,0 +1,130 @@
+use crate::{NSRT, NsrtError, Result};
+use std::{
+    collections::VecDeque,
+    io,
+    time::{Duration, Instant},
+};
+
+/// How [`NSRT::calibrate`] finds the tone of an acoustic calibrator
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Calibrator {
+    /// Level in dB the calibrator produces, usually 94 or 114
+    pub nominal: f32,
+    /// Farthest in dB a level may be from `nominal` to be taken for the tone
+    pub tolerance: f32,
+    /// Largest difference in dB between the readings of a steady tone
+    pub stability: f32,
+    /// Consecutive readings that must be steady
+    pub readings: usize,
+    /// Time between readings
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub interval: Duration,
+    /// How long to wait for a steady tone
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub timeout: Duration,
+}
+
+impl Default for Calibrator {
+    /// A 94 dB calibrator, steady within 0.2 dB over 20 readings 100 ms
+    /// apart, found within 5 dB of its level and within 30 s
+    fn default() -> Self {
+        Self {
+            nominal: 94.0,
+            tolerance: 5.0,
+            stability: 0.2,
+            readings: 20,
+            interval: Duration::from_millis(100),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Calibrator {
+    /// Default calibrator producing `nominal` dB
+    pub fn new(nominal: f32) -> Self {
+        Self {
+            nominal,
+            ..Self::default()
+        }
+    }
+}
+
+/// Tone measured by [`NSRT::calibrate`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Calibration {
+    /// Level in dB the calibrator produces
+    pub nominal: f32,
+    /// Mean level in dB of the steady readings, without any calibration
+    /// offset
+    pub measured: f32,
+    /// Difference in dB between the highest and lowest steady reading
+    pub spread: f32,
+}
+
+impl Calibration {
+    /// Calibration offset making the meter read the nominal level, see
+    /// [`NSRT::set_calibration_offset`]
+    pub fn offset(&self) -> f32 {
+        self.nominal - self.measured
+    }
+}
+
+impl NSRT {
+    /// Wait for the steady tone of `calibrator` and measure it
+    ///
+    /// Levels are read every `interval` until `readings` in a row are within
+    /// `tolerance` of the nominal level and within `stability` of each
+    /// other, so the tone is only measured once the calibrator sits on the
+    /// microphone and has settled. The calibration offset is left out of the
+    /// measured level. Fails with a timeout if no steady tone is found
+    /// within `timeout`.
+    pub fn calibrate(&mut self, calibrator: &Calibrator) -> Result<Calibration> {
+        if calibrator.readings == 0 {
+            return Err(NsrtError::InvalidParameter(
+                "A calibration needs at least one reading".to_string(),
+            ));
+        }
+
+        let deadline = Instant::now() + calibrator.timeout;
+        let mut steady = VecDeque::with_capacity(calibrator.readings);
+        loop {
+            let level = self.read_level()? - self.calibration_offset;
+            if (level - calibrator.nominal).abs() > calibrator.tolerance {
+                steady.clear();
+            } else {
+                steady.push_back(level);
+                while spread(&steady) > calibrator.stability {
+                    steady.pop_front();
+                }
+            }
+
+            if steady.len() == calibrator.readings {
+                return Ok(Calibration {
+                    nominal: calibrator.nominal,
+                    measured: steady.iter().sum::<f32>() / steady.len() as f32,
+                    spread: spread(&steady),
+                });
+            }
+            if Instant::now() + calibrator.interval > deadline {
+                return Err(NsrtError::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "No steady {} dB calibrator tone within {} s",
+                        calibrator.nominal,
+                        calibrator.timeout.as_secs()
+                    ),
+                )));
+            }
+            std::thread::sleep(calibrator.interval);
+        }
+    }
+}
+
+/// Difference between the highest and lowest of `levels`
+fn spread(levels: &VecDeque<f32>) -> f32 {
+    let max = levels.iter().copied().fold(f32::MIN, f32::max);
+    let min = levels.iter().copied().fold(f32::MAX, f32::min);
+    (max - min).max(0.0)
+}
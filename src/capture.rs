@@ -0,0 +1,138 @@
+use crate::{MockTransport, NsrtError, Result, Transport};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// Transport recording the traffic of another to a capture file
+///
+/// Traffic is passed through unchanged and written to the capture file as it
+/// happens; [`MockTransport::from_capture`] plays a capture back, so a
+/// session with a real meter can be rerun without it. Captures are text, one
+/// chunk per line, with `>` for bytes written to the device, `<` for bytes
+/// read from it and `#` for comments:
+///
+/// ```text
+/// # nsrt wire capture
+/// > 10 00 00 80 00 00 00 00 04 00 00 00
+/// < 66 66 7c 42
+/// ```
+///
+/// On playback, consecutive chunks in one direction are joined, and each
+/// request is answered with the bytes read after it, however the reads were
+/// split. Failed reads and buffer clears are kept as comments only, as
+/// playback times out by itself where no answer was recorded.
+pub struct RecordingTransport<T> {
+    inner: T,
+    capture: BufWriter<File>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Record the traffic of `inner` to a new capture file at `path`
+    pub fn create(inner: T, path: impl AsRef<Path>) -> Result<Self> {
+        let mut capture = BufWriter::new(File::create(path)?);
+        writeln!(capture, "# nsrt wire capture")?;
+        Ok(Self { inner, capture })
+    }
+
+    /// Flush the capture and return the wrapped transport
+    pub fn into_inner(mut self) -> Result<T> {
+        self.capture.flush()?;
+        Ok(self.inner)
+    }
+
+    fn record(&mut self, direction: char, bytes: &[u8]) -> io::Result<()> {
+        write!(self.capture, "{direction}")?;
+        for byte in bytes {
+            write!(self.capture, " {byte:02x}")?;
+        }
+        writeln!(self.capture)?;
+        // Keep the capture complete if the process dies mid-session
+        self.capture.flush()
+    }
+}
+
+impl<T: Transport> Read for RecordingTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(count) => {
+                if count > 0 {
+                    self.record('<', &buf[..count])?;
+                }
+                Ok(count)
+            }
+            Err(e) => {
+                writeln!(self.capture, "# read failed: {e}")?;
+                self.capture.flush()?;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<T: Transport> Write for RecordingTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.record('>', &buf[..count])?;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.capture.flush()
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.clear()?;
+        writeln!(self.capture, "# cleared")?;
+        self.capture.flush()
+    }
+
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        self.inner.bytes_to_read()
+    }
+}
+
+impl MockTransport {
+    /// Transport playing back a capture written by [`RecordingTransport`]
+    pub fn from_capture(path: impl AsRef<Path>) -> Result<Self> {
+        let mock = Self::new();
+        let mut request = Vec::new();
+        let mut response = Vec::new();
+
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let invalid =
+                || NsrtError::InvalidParameter(format!("Invalid capture line {}", number + 1));
+
+            let (direction, bytes) = match line.chars().next() {
+                None | Some('#') => continue,
+                Some(direction @ ('>' | '<')) => (direction, &line[1..]),
+                Some(_) => return Err(invalid()),
+            };
+            let bytes = bytes
+                .split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| invalid()))
+                .collect::<Result<Vec<_>>>()?;
+
+            if direction == '<' {
+                response.extend(bytes);
+                continue;
+            }
+            // A write after answers starts the next exchange
+            if !response.is_empty() {
+                mock.expect(std::mem::take(&mut request), std::mem::take(&mut response));
+            }
+            request.extend(bytes);
+        }
+
+        if !request.is_empty() || !response.is_empty() {
+            mock.expect(request, response);
+        }
+        Ok(mock)
+    }
+}
@@ -0,0 +1,276 @@
+//! Chart rendering for sessions
+//!
+//! Charts are written as SVG or PNG, chosen by the file extension of the
+//! output path. Axis labels are rendered with the system sans-serif font.
+
+use crate::{NsrtError, Result, Session};
+use plotters::{coord::Shift, prelude::*};
+use std::{io, path::Path, time::SystemTime};
+
+const DEFAULT_SIZE: (u32, u32) = (1024, 480);
+
+/// Marker drawn as a vertical line on a [`LevelChart`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    /// Time of the event
+    pub time: SystemTime,
+    /// Label drawn next to the line
+    pub label: String,
+}
+
+/// Running level and LEQ of a session over time
+pub struct LevelChart<'a> {
+    session: &'a Session,
+    markers: Vec<Marker>,
+    title: Option<String>,
+    size: (u32, u32),
+}
+
+impl<'a> LevelChart<'a> {
+    /// Chart the measurements of `session`
+    pub fn new(session: &'a Session) -> Self {
+        Self {
+            session,
+            markers: Vec::new(),
+            title: None,
+            size: DEFAULT_SIZE,
+        }
+    }
+
+    /// Mark an event at `time`
+    #[must_use]
+    pub fn marker(mut self, time: SystemTime, label: impl Into<String>) -> Self {
+        self.markers.push(Marker {
+            time,
+            label: label.into(),
+        });
+        self
+    }
+
+    /// Set the chart title
+    #[must_use]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Set the image size in pixels
+    #[must_use]
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    /// Render to `path`, which must end in `.svg` or `.png`
+    pub fn render(&self, path: impl AsRef<Path>) -> Result<()> {
+        render(self, path.as_ref(), self.size)
+    }
+
+    /// Seconds between the session start and `time`
+    fn offset(&self, time: SystemTime) -> f64 {
+        time.duration_since(self.session.started)
+            .map_or(0.0, |d| d.as_secs_f64())
+    }
+}
+
+impl Draw for LevelChart<'_> {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<DB> {
+        root.fill(&WHITE)?;
+
+        let measurements = &self.session.measurements;
+        let end = measurements
+            .last()
+            .map_or(1.0, |m| self.offset(m.timestamp))
+            .max(1.0);
+        let (min, max) = level_range(measurements.iter().flat_map(|m| [m.level, m.leq]));
+
+        let mut builder = ChartBuilder::on(root);
+        builder
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50);
+        if let Some(title) = &self.title {
+            builder.caption(title, ("sans-serif", 20));
+        }
+        let mut chart = builder.build_cartesian_2d(0.0..end, min..max)?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Time")
+            .y_desc("Level (dB)")
+            .x_label_formatter(&|secs| format_elapsed(*secs))
+            .draw()?;
+
+        chart
+            .draw_series(LineSeries::new(
+                measurements
+                    .iter()
+                    .map(|m| (self.offset(m.timestamp), f64::from(m.level))),
+                &BLUE,
+            ))?
+            .label("Level")
+            .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], BLUE));
+        chart
+            .draw_series(LineSeries::new(
+                measurements
+                    .iter()
+                    .map(|m| (self.offset(m.timestamp), f64::from(m.leq))),
+                &GREEN,
+            ))?
+            .label("LEQ")
+            .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], GREEN));
+
+        for marker in &self.markers {
+            let x = self.offset(marker.time);
+            chart.draw_series([PathElement::new([(x, min), (x, max)], RED)])?;
+            chart.draw_series([Text::new(
+                marker.label.clone(),
+                (x, max),
+                ("sans-serif", 12).into_font().color(&RED),
+            )])?;
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()
+    }
+}
+
+/// Distribution of the running level of a session in 1 dB bins
+pub struct LevelHistogram<'a> {
+    session: &'a Session,
+    title: Option<String>,
+    size: (u32, u32),
+}
+
+impl<'a> LevelHistogram<'a> {
+    /// Chart the level distribution of `session`
+    pub fn new(session: &'a Session) -> Self {
+        Self {
+            session,
+            title: None,
+            size: DEFAULT_SIZE,
+        }
+    }
+
+    /// Set the chart title
+    #[must_use]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Set the image size in pixels
+    #[must_use]
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    /// Render to `path`, which must end in `.svg` or `.png`
+    pub fn render(&self, path: impl AsRef<Path>) -> Result<()> {
+        render(self, path.as_ref(), self.size)
+    }
+}
+
+impl Draw for LevelHistogram<'_> {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<DB> {
+        root.fill(&WHITE)?;
+
+        let bins: Vec<i32> = self
+            .session
+            .measurements
+            .iter()
+            .filter(|m| m.level.is_finite())
+            .map(|m| m.level.floor() as i32)
+            .collect();
+        let low = bins.iter().copied().min().unwrap_or(0);
+        let high = bins.iter().copied().max().unwrap_or(0) + 1;
+        let tallest = (low..high)
+            .map(|bin| bins.iter().filter(|&&b| b == bin).count())
+            .max()
+            .unwrap_or(0);
+
+        let mut builder = ChartBuilder::on(root);
+        builder
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50);
+        if let Some(title) = &self.title {
+            builder.caption(title, ("sans-serif", 20));
+        }
+        let mut chart =
+            builder.build_cartesian_2d((low..high).into_segmented(), 0..tallest.max(1))?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Level (dB)")
+            .y_desc("Measurements")
+            .draw()?;
+        chart.draw_series(
+            Histogram::vertical(&chart)
+                .style(BLUE.filled())
+                .margin(1)
+                .data(bins.iter().map(|&bin| (bin, 1))),
+        )?;
+        root.present()
+    }
+}
+
+type DrawResult<DB> =
+    std::result::Result<(), DrawingAreaErrorKind<<DB as DrawingBackend>::ErrorType>>;
+
+/// Chart that can be drawn onto any backend
+trait Draw {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<DB>;
+}
+
+/// Draw `chart` onto a backend chosen by the extension of `path`
+fn render(chart: &impl Draw, path: &Path, size: (u32, u32)) -> Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") => chart
+            .draw(&SVGBackend::new(path, size).into_drawing_area())
+            .map_err(chart_error),
+        Some("png") => chart
+            .draw(&BitMapBackend::new(path, size).into_drawing_area())
+            .map_err(chart_error),
+        _ => Err(NsrtError::InvalidParameter(format!(
+            "Chart path must end in .svg or .png: {}",
+            path.display()
+        ))),
+    }
+}
+
+/// Axis range covering `levels` with some headroom, in whole 10 dB steps
+fn level_range(levels: impl Iterator<Item = f32>) -> (f64, f64) {
+    let (min, max) = levels
+        .filter(|l| l.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), l| {
+            (min.min(l), max.max(l))
+        });
+    if min > max {
+        return (0.0, 100.0);
+    }
+    (
+        (f64::from(min) / 10.0).floor() * 10.0,
+        (f64::from(max) / 10.0).ceil() * 10.0 + 5.0,
+    )
+}
+
+/// Format seconds since the start as `m:ss` or `h:mm:ss`
+fn format_elapsed(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+fn chart_error(error: impl std::error::Error + Send + Sync + 'static) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
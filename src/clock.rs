@@ -0,0 +1,64 @@
+use std::time::SystemTime;
+
+/// Synchronization state of the host clock at the time of a reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClockStatus {
+    /// Whether the clock was synchronized to a time reference, e.g. by NTP
+    pub synchronized: bool,
+    /// Phase offset still being corrected, in seconds
+    pub offset: f64,
+    /// Upper bound of the clock error, in seconds
+    pub max_error: f64,
+}
+
+/// Source of measurement timestamps
+pub trait TimeSource: Send + Sync {
+    /// The current time, along with the clock status if known
+    fn now(&self) -> (SystemTime, Option<ClockStatus>);
+}
+
+/// Host wall clock, without synchronization status
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> (SystemTime, Option<ClockStatus>) {
+        (SystemTime::now(), None)
+    }
+}
+
+/// Host wall clock with the synchronization status kept by the Linux kernel
+///
+/// NTP daemons such as chrony, ntpd and systemd-timesyncd report their state
+/// to the kernel, so this works regardless of which one disciplines the clock.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelClock;
+
+#[cfg(target_os = "linux")]
+impl TimeSource for KernelClock {
+    fn now(&self) -> (SystemTime, Option<ClockStatus>) {
+        let now = SystemTime::now();
+
+        // SAFETY: `timex` is plain data, and with `modes` zeroed `adjtimex`
+        // only reads the kernel state into it.
+        let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+        let state = unsafe { libc::adjtimex(&mut timex) };
+        if state == -1 {
+            return (now, None);
+        }
+
+        let offset_unit = if timex.status & libc::STA_NANO != 0 {
+            1e-9
+        } else {
+            1e-6
+        };
+        let status = ClockStatus {
+            synchronized: state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0,
+            offset: timex.offset as f64 * offset_unit,
+            max_error: timex.maxerror as f64 * 1e-6,
+        };
+        (now, Some(status))
+    }
+}
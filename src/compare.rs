@@ -0,0 +1,224 @@
+use crate::{Measurement, Session};
+use std::time::{Duration, SystemTime};
+
+/// How the levels of meters recorded side by side compare, pair by pair
+///
+/// Used to check meters against a reference unit before a survey: ideally
+/// the differences are small and steady, the levels correlate closely and
+/// the difference doesn't drift over the session.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comparison {
+    /// One entry for every pair of sessions, in order of their indices
+    pub pairs: Vec<PairComparison>,
+}
+
+/// Running levels of one session compared to those of another
+///
+/// Differences are the other session's level minus the reference's, in dB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PairComparison {
+    /// Index of the reference session
+    pub reference: usize,
+    /// Index of the session compared to it
+    pub other: usize,
+    /// Number of measurement pairs compared
+    pub samples: usize,
+    /// Mean difference
+    pub mean_difference: Option<f32>,
+    /// Standard deviation of the difference
+    pub std_deviation: Option<f32>,
+    /// Difference furthest from zero
+    pub max_difference: Option<f32>,
+    /// Pearson correlation of the levels, if both vary
+    pub correlation: Option<f32>,
+    /// Change of the difference over time in dB per hour, from a
+    /// least-squares fit
+    pub drift: Option<f32>,
+}
+
+impl Comparison {
+    /// Compare every pair of `sessions`
+    ///
+    /// Each measurement of the reference session is paired with the other
+    /// session's measurement closest in time, if it is at most `tolerance`
+    /// away; half the sampling interval suits sessions sampled at the same
+    /// rate. Measurements must be in chronological order, and those without
+    /// a finite level are left out.
+    pub fn compute(sessions: &[Session], tolerance: Duration) -> Self {
+        let mut pairs = Vec::new();
+        for reference in 0..sessions.len() {
+            for other in reference + 1..sessions.len() {
+                let matched = pair_up(
+                    &sessions[reference].measurements,
+                    &sessions[other].measurements,
+                    tolerance,
+                );
+                pairs.push(PairComparison::of(reference, other, &matched));
+            }
+        }
+        Self { pairs }
+    }
+}
+
+/// Stretch of time over which two sessions disagree by more than a
+/// threshold, found by [`Comparison::divergences`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Divergence {
+    /// Time of the first measurement pair beyond the threshold
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub start: SystemTime,
+    /// Time of the last measurement pair beyond the threshold
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub end: SystemTime,
+    /// Number of measurement pairs in the stretch
+    pub samples: usize,
+    /// Difference furthest from zero in the stretch, the other session's
+    /// level minus the reference's
+    pub max_difference: f32,
+}
+
+impl Comparison {
+    /// Stretches over which `other` differs from `reference` by more than
+    /// `threshold` dB
+    ///
+    /// Measurements are paired as by [`Comparison::compute`]. A stretch runs
+    /// from the first pair beyond the threshold up to the last one before a
+    /// pair within it, so a single outlier is a stretch of one sample.
+    pub fn divergences(
+        reference: &Session,
+        other: &Session,
+        tolerance: Duration,
+        threshold: f32,
+    ) -> Vec<Divergence> {
+        let threshold = f64::from(threshold);
+        let mut divergences: Vec<Divergence> = Vec::new();
+        let mut diverging = false;
+        for (time, a, b) in pair_up(&reference.measurements, &other.measurements, tolerance) {
+            let difference = b - a;
+            if difference.abs() <= threshold {
+                diverging = false;
+                continue;
+            }
+            match divergences.last_mut() {
+                Some(divergence) if diverging => {
+                    divergence.end = time;
+                    divergence.samples += 1;
+                    if difference.abs() > f64::from(divergence.max_difference.abs()) {
+                        divergence.max_difference = difference as f32;
+                    }
+                }
+                _ => divergences.push(Divergence {
+                    start: time,
+                    end: time,
+                    samples: 1,
+                    max_difference: difference as f32,
+                }),
+            }
+            diverging = true;
+        }
+        divergences
+    }
+}
+
+impl PairComparison {
+    /// Statistics of `matched` (time, reference level, other level) pairs
+    fn of(reference: usize, other: usize, matched: &[(SystemTime, f64, f64)]) -> Self {
+        let count = matched.len() as f64;
+        let differences: Vec<f64> = matched.iter().map(|(_, a, b)| b - a).collect();
+        let mean = |values: &mut dyn Iterator<Item = f64>| values.sum::<f64>() / count;
+        let mean_difference = mean(&mut differences.iter().copied());
+        let std_deviation = mean(
+            &mut differences
+                .iter()
+                .map(|difference| (difference - mean_difference).powi(2)),
+        )
+        .sqrt();
+
+        let correlation = {
+            let mean_a = mean(&mut matched.iter().map(|(_, a, _)| *a));
+            let mean_b = mean(&mut matched.iter().map(|(_, _, b)| *b));
+            let (covariance, variance_a, variance_b) = matched.iter().fold(
+                (0.0, 0.0, 0.0),
+                |(covariance, variance_a, variance_b), (_, a, b)| {
+                    let (a, b) = (a - mean_a, b - mean_b);
+                    (covariance + a * b, variance_a + a * a, variance_b + b * b)
+                },
+            );
+            let variance = variance_a * variance_b;
+            (variance > 0.0).then(|| covariance / variance.sqrt())
+        };
+
+        let drift = matched.first().and_then(|(start, _, _)| {
+            let hours: Vec<f64> = matched
+                .iter()
+                .map(|(time, _, _)| {
+                    time.duration_since(*start)
+                        .unwrap_or_default()
+                        .as_secs_f64()
+                        / 3600.0
+                })
+                .collect();
+            let mean_hours = mean(&mut hours.iter().copied());
+            let (covariance, variance) = hours.iter().zip(&differences).fold(
+                (0.0, 0.0),
+                |(covariance, variance), (hours, difference)| {
+                    let hours = hours - mean_hours;
+                    (
+                        covariance + hours * (difference - mean_difference),
+                        variance + hours * hours,
+                    )
+                },
+            );
+            (variance > 0.0).then(|| covariance / variance)
+        });
+
+        let some = |value: f64| (!matched.is_empty()).then_some(value as f32);
+        Self {
+            reference,
+            other,
+            samples: matched.len(),
+            mean_difference: some(mean_difference),
+            std_deviation: some(std_deviation),
+            max_difference: differences
+                .iter()
+                .copied()
+                .reduce(|a, b| if b.abs() > a.abs() { b } else { a })
+                .map(|difference| difference as f32),
+            correlation: correlation.map(|correlation| correlation as f32),
+            drift: drift.map(|drift| drift as f32),
+        }
+    }
+}
+
+/// Pair each measurement of `reference` with the closest one of `other`
+/// within `tolerance`, as (time, reference level, other level)
+fn pair_up(
+    reference: &[Measurement],
+    other: &[Measurement],
+    tolerance: Duration,
+) -> Vec<(SystemTime, f64, f64)> {
+    let distance =
+        |a: SystemTime, b: SystemTime| a.duration_since(b).unwrap_or_else(|e| e.duration());
+    let other: Vec<&Measurement> = other.iter().filter(|m| m.level.is_finite()).collect();
+
+    let mut matched = Vec::new();
+    let mut next = 0;
+    for m in reference.iter().filter(|m| m.level.is_finite()) {
+        // Move on while the following measurement is at least as close
+        while next + 1 < other.len()
+            && distance(other[next + 1].timestamp, m.timestamp)
+                <= distance(other[next].timestamp, m.timestamp)
+        {
+            next += 1;
+        }
+        if let Some(closest) = other.get(next)
+            && distance(closest.timestamp, m.timestamp) <= tolerance
+        {
+            matched.push((m.timestamp, f64::from(m.level), f64::from(closest.level)));
+        }
+    }
+    matched
+}
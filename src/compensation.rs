@@ -0,0 +1,93 @@
+use crate::{Measurement, NSRT, Temperature};
+
+/// Correction of levels for the microphone's sensitivity drift with
+/// temperature
+///
+/// The sensitivity changes by `coefficient` dB for every °C away from the
+/// `reference` temperature at which the meter was calibrated, so a level
+/// measured at temperature `t` reads `coefficient * (t - reference)` dB too
+/// high, with temperatures in °C. The coefficient comes from the microphone's
+/// data sheet or a temperature chamber run; the device's own temperature sensor
+/// supplies `t`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemperatureCompensation {
+    /// Sensitivity change in dB per °C
+    pub coefficient: f32,
+    /// Calibration temperature
+    pub reference: Temperature,
+}
+
+impl TemperatureCompensation {
+    /// Compensation of `coefficient` dB per °C around `reference`
+    pub fn new(coefficient: f32, reference: Temperature) -> Self {
+        Self {
+            coefficient,
+            reference,
+        }
+    }
+
+    /// `level` in dB as it would read at the reference temperature
+    pub fn correct(&self, level: f32, temperature: Temperature) -> f32 {
+        level - self.coefficient * (temperature.as_celsius() - self.reference.as_celsius())
+    }
+
+    /// The corrected level and LEQ of `measurement`
+    pub fn compensate(&self, measurement: &Measurement) -> Compensated {
+        Compensated {
+            level: self.correct(measurement.level, measurement.temperature),
+            leq: self.correct(measurement.leq, measurement.temperature),
+        }
+    }
+}
+
+/// Level and LEQ corrected by a [`TemperatureCompensation`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Compensated {
+    /// Running sound level in dB
+    pub level: f32,
+    /// LEQ in dB
+    pub leq: f32,
+}
+
+impl NSRT {
+    /// Fill in the compensated values of `measurement` if compensation is on
+    pub(crate) fn compensate(&self, mut measurement: Measurement) -> Measurement {
+        measurement.compensated = self
+            .compensation
+            .map(|compensation| compensation.compensate(&measurement));
+        measurement
+    }
+
+    /// Correct measurements for the microphone's temperature coefficient
+    ///
+    /// Measurements read from now on carry the corrected values in
+    /// [`Measurement::compensated`], next to the raw ones. `None` turns the
+    /// correction off again.
+    pub fn set_temperature_compensation(&mut self, compensation: Option<TemperatureCompensation>) {
+        self.compensation = compensation;
+    }
+
+    /// Correct measurements for the microphone's temperature coefficient,
+    /// using fluent API
+    #[must_use]
+    pub fn temperature_compensation(mut self, compensation: TemperatureCompensation) -> Self {
+        self.compensation = Some(compensation);
+        self
+    }
+
+    /// Add `offset` dB to every level and LEQ read from now on
+    ///
+    /// For a correction found by checking the meter against a reference
+    /// calibrator. Temperature compensation applies on top of it; the raw
+    /// answers of [`NSRT::read_many`] are left alone.
+    pub fn set_calibration_offset(&mut self, offset: f32) {
+        self.calibration_offset = offset;
+    }
+
+    /// The offset in dB added to measured levels, 0 unless set
+    pub fn calibration_offset(&self) -> f32 {
+        self.calibration_offset
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{NSRT, Result, SamplingFrequency, Weighting};
+
+/// The persistent measurement settings of the device
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceConfig {
+    /// Weighting curve
+    pub weighting: Weighting,
+    /// Time constant in seconds
+    pub time_constant: f32,
+    /// Sampling frequency
+    pub sampling_frequency: SamplingFrequency,
+}
+
+impl NSRT {
+    /// Read the current measurement settings
+    pub fn read_config(&mut self) -> Result<DeviceConfig> {
+        Ok(DeviceConfig {
+            weighting: self.read_weighting()?,
+            time_constant: self.read_time_constant()?,
+            sampling_frequency: self.read_sampling_frequency()?,
+        })
+    }
+
+    /// Apply measurement settings, writing only the ones that differ
+    ///
+    /// The settings live in flash with a limited number of write cycles, so
+    /// unchanged values are not rewritten. If anything changed, this waits
+    /// once for the device to stabilize.
+    pub fn configure(&mut self, config: &DeviceConfig) -> Result<()> {
+        self.config = Some(*config);
+        let current = self.read_config()?;
+        if current == *config {
+            return Ok(());
+        }
+
+        if current.weighting != config.weighting {
+            self.write_weighting(config.weighting, true)?;
+        }
+        if current.sampling_frequency != config.sampling_frequency {
+            self.write_sampling_frequency(config.sampling_frequency, true)?;
+        }
+        if current.time_constant != config.time_constant {
+            self.write_time_constant(config.time_constant, true)?;
+        }
+
+        Self::wait_for_stabilization(config.time_constant);
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+//! CSV measurement logs
+//!
+//! Files have a header row, a comment giving the schema version and one row
+//! per measurement:
+//!
+//! ```text
+//! timestamp,level,leq,temperature,clock_synchronized,clock_offset,clock_max_error,quality
+//! # schema_version: 3
+//! 2024-05-01T12:00:00.250000000Z,48.3,47.9,22.5,true,0.000012,0.0015,
+//! 2024-05-01T12:00:03.250000000Z,48.1,48.0,22.5,true,0.000012,0.0015,missing_samples
+//! ```
+//!
+//! Timestamps are RFC 3339 in UTC with nanosecond precision; levels are in dB
+//! and temperatures in °C. The clock columns hold the [`ClockStatus`] of the
+//! host clock, with offset and error bound in seconds, and are empty when it is unknown. The
+//! quality column holds the [`Quality`](crate::Quality) flags joined by `|`,
+//! and is empty for good measurements. [`Metadata`] is written as comment
+//! lines of the form `# site: Main Street`, which the reader skips.
+//!
+//! Older logs have fewer columns, and no version comment, but their header
+//! row tells their version:
+//!
+//! | Version | Columns added                                          |
+//! | ------- | ------------------------------------------------------ |
+//! | 1       | `timestamp`, `level`, `leq`, `temperature`             |
+//! | 2       | `clock_synchronized`, `clock_offset`, `clock_max_error` |
+//! | 3       | `quality`, and the version comment                     |
+//!
+//! [`CsvReader`] reads every version, leaving what a log lacks unknown or
+//! good, and refuses logs of later versions with
+//! [`NsrtError::UnsupportedSchema`]. [`CsvWriter::append`] goes on writing a
+//! log in its own version, so the file stays consistent.
+
+use crate::{
+    ClockStatus, Measurement, Metadata, NsrtError, RecordSerializer, Result, Sink, Temperature,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Lines, Write},
+    path::Path,
+};
+
+/// Header row of every CSV log
+pub const HEADER: &str =
+    "timestamp,level,leq,temperature,clock_synchronized,clock_offset,clock_max_error,quality";
+
+/// Schema version of the logs written
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Header rows of each schema version, from version 1
+const HEADERS: [&str; SCHEMA_VERSION as usize] = [
+    "timestamp,level,leq,temperature",
+    "timestamp,level,leq,temperature,clock_synchronized,clock_offset,clock_max_error",
+    HEADER,
+];
+
+/// Start of the comment giving the schema version
+const VERSION_COMMENT: &str = "# schema_version: ";
+
+/// Sink writing measurements as CSV rows
+pub struct CsvWriter<W: Write> {
+    inner: W,
+    version: u32,
+}
+
+impl CsvWriter<BufWriter<File>> {
+    /// Create a new CSV file at `path`, replacing any existing file
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Append to the CSV file at `path`, creating it if it doesn't exist
+    ///
+    /// Rows are written in the schema version of the existing file, without
+    /// the columns it lacks.
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Self::new(BufWriter::new(file));
+        }
+        let version = CsvReader::new(BufReader::new(&file))?.schema_version();
+        Ok(Self {
+            inner: BufWriter::new(file),
+            version,
+        })
+    }
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Start a new CSV log on `inner`, writing the header row and version
+    pub fn new(mut inner: W) -> Result<Self> {
+        write_header(&mut inner)?;
+        Ok(Self {
+            inner,
+            version: SCHEMA_VERSION,
+        })
+    }
+
+    /// The underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+}
+
+impl<W: Write> Sink for CsvWriter<W> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        Ok(write_metadata(&mut self.inner, metadata)?)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        Ok(write_row(&mut self.inner, measurement, self.version)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.inner.flush()?)
+    }
+}
+
+/// CSV rows for a [`RecordSink`](crate::RecordSink), in the format of
+/// [`CsvWriter`], with the header row and version as the header and metadata
+/// as comment lines
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CsvSerializer;
+
+impl RecordSerializer for CsvSerializer {
+    fn header(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        Ok(write_header(out)?)
+    }
+
+    fn metadata(&mut self, metadata: &Metadata, out: &mut Vec<u8>) -> Result<()> {
+        Ok(write_metadata(out, metadata)?)
+    }
+
+    fn serialize(&mut self, measurement: &Measurement, out: &mut Vec<u8>) -> Result<()> {
+        Ok(write_row(out, measurement, SCHEMA_VERSION)?)
+    }
+}
+
+fn write_header(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{HEADER}")?;
+    writeln!(out, "{VERSION_COMMENT}{SCHEMA_VERSION}")
+}
+
+fn write_metadata(out: &mut impl Write, metadata: &Metadata) -> io::Result<()> {
+    for (name, value) in metadata.fields() {
+        // Keep multi-line values within their comment
+        writeln!(out, "# {name}: {}", value.replace('\n', " "))?;
+    }
+    Ok(())
+}
+
+/// Write the row of `measurement` with the columns of schema `version`
+fn write_row(out: &mut impl Write, measurement: &Measurement, version: u32) -> io::Result<()> {
+    write!(
+        out,
+        "{},{},{},{}",
+        humantime::format_rfc3339_nanos(measurement.timestamp),
+        measurement.level,
+        measurement.leq,
+        measurement.temperature.as_celsius()
+    )?;
+    if version >= 2 {
+        match measurement.clock {
+            Some(clock) => write!(
+                out,
+                ",{},{},{}",
+                clock.synchronized, clock.offset, clock.max_error
+            )?,
+            None => write!(out, ",,,")?,
+        }
+    }
+    if version >= 3 {
+        write!(out, ",")?;
+        if !measurement.quality.is_good() {
+            write!(out, "{}", measurement.quality)?;
+        }
+    }
+    writeln!(out)
+}
+
+/// Iterator over the measurements in a CSV log
+pub struct CsvReader<R: BufRead> {
+    lines: Lines<R>,
+    line: usize,
+    version: u32,
+    /// Line read after the header that wasn't the version comment
+    pending: Option<String>,
+}
+
+impl CsvReader<BufReader<File>> {
+    /// Open the CSV file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> CsvReader<R> {
+    /// Read a CSV log from `inner`, checking the header row and version
+    pub fn new(inner: R) -> Result<Self> {
+        let mut lines = inner.lines();
+        let header = lines.next().transpose()?;
+        let mut pending = lines.next().transpose()?;
+        let comment = pending
+            .as_deref()
+            .and_then(|line| line.trim_end().strip_prefix(VERSION_COMMENT));
+        let stated = match comment {
+            Some(version) => Some(
+                version
+                    .parse()
+                    .map_err(|_| invalid_data(2, &format!("invalid version {version:?}")))?,
+            ),
+            None => None,
+        };
+        if let Some(version) = stated.filter(|&version| version > SCHEMA_VERSION) {
+            return Err(NsrtError::UnsupportedSchema {
+                format: "CSV log",
+                version,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        if stated.is_some() {
+            pending = None;
+        }
+
+        let Some(position) =
+            header.and_then(|header| HEADERS.iter().position(|known| *known == header.trim_end()))
+        else {
+            return Err(invalid_data(1, "expected header row"));
+        };
+        Ok(Self {
+            lines,
+            line: if stated.is_some() { 2 } else { 1 },
+            version: position as u32 + 1,
+            pending,
+        })
+    }
+
+    /// Schema version the log was written with
+    pub fn schema_version(&self) -> u32 {
+        self.version
+    }
+
+    fn parse(&self, row: &str) -> Result<Measurement> {
+        let mut fields: Vec<&str> = row.split(',').map(str::trim).collect();
+        // Rows of older versions lack the later columns, also in logs that
+        // were appended to by a later version before it kept to theirs
+        let quality = match fields.len() {
+            4 => {
+                fields.extend(["", "", ""]);
+                ""
+            }
+            7 => "",
+            8 => fields.pop().expect("8 fields"),
+            _ => return Err(invalid_data(self.line, "expected 8 fields")),
+        };
+        let &[
+            timestamp,
+            level,
+            leq,
+            temperature,
+            synchronized,
+            offset,
+            max_error,
+        ] = &fields[..]
+        else {
+            unreachable!("7 fields made above");
+        };
+        let number = |field: &str| {
+            field
+                .parse::<f32>()
+                .map_err(|_| invalid_data(self.line, &format!("invalid number {field:?}")))
+        };
+        let seconds = |field: &str| {
+            field
+                .parse::<f64>()
+                .map_err(|_| invalid_data(self.line, &format!("invalid number {field:?}")))
+        };
+        let clock = match synchronized {
+            "" => None,
+            synchronized => Some(ClockStatus {
+                synchronized: synchronized.parse().map_err(|_| {
+                    invalid_data(self.line, &format!("invalid flag {synchronized:?}"))
+                })?,
+                offset: seconds(offset)?,
+                max_error: seconds(max_error)?,
+            }),
+        };
+
+        Ok(Measurement {
+            timestamp: humantime::parse_rfc3339(timestamp)
+                .map_err(|e| invalid_data(self.line, &e.to_string()))?,
+            level: number(level)?,
+            leq: number(leq)?,
+            temperature: Temperature::from_celsius(number(temperature)?),
+            clock,
+            compensated: None,
+            quality: quality
+                .parse()
+                .map_err(|_| invalid_data(self.line, &format!("invalid quality {quality:?}")))?,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<Measurement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let row = match self.pending.take().map(Ok).or_else(|| self.lines.next())? {
+                Ok(row) => row,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line += 1;
+            if !row.trim().is_empty() && !row.starts_with('#') {
+                return Some(self.parse(&row));
+            }
+        }
+    }
+}
+
+fn invalid_data(line: usize, message: &str) -> NsrtError {
+    NsrtError::IoError(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {line}: {message}"),
+    ))
+}
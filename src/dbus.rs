@@ -0,0 +1,275 @@
+//! D-Bus service exposing the device as `org.nsrt.Meter`
+//!
+//! The [`Meter`] object is served at [`OBJECT_PATH`] and, by [`serve`], under
+//! the well-known name [`BUS_NAME`]. Its `org.nsrt.Meter` interface has these
+//! read-only properties:
+//!
+//! | Property            | Signature | Value                                   |
+//! | ------------------- | --------- | --------------------------------------- |
+//! | `Level`             | `d`       | running level in dB                     |
+//! | `Leq`               | `d`       | LEQ in dB since the previous sample     |
+//! | `Temperature`       | `d`       | temperature in °C                       |
+//! | `Timestamp`         | `t`       | measurement time in µs since the epoch  |
+//! | `Weighting`         | `s`       | `"A"`, `"C"` or `"Z"`                   |
+//! | `TimeConstant`      | `d`       | time constant in seconds                |
+//! | `SamplingFrequency` | `u`       | 32000 or 48000 Hz                       |
+//! | `Model`             | `s`       | model name                              |
+//! | `SerialNumber`      | `s`       | serial number                           |
+//! | `FirmwareRevision`  | `s`       | firmware revision                       |
+//! | `UserId`            | `s`       | user-defined identifier                 |
+//! | `Threshold`         | `d`       | alarm threshold in dB, NaN if none      |
+//! | `Alarm`             | `b`       | whether the level exceeds the threshold |
+//!
+//! Measurement properties are NaN, and `Timestamp` 0, until the first
+//! measurement. `PropertiesChanged` is emitted for them on every measurement,
+//! along with these signals:
+//!
+//! - `Measurement(d level, d leq, d temperature, t timestamp)`
+//! - `ThresholdExceeded(d level, d threshold)`
+//! - `ThresholdCleared(d level, d threshold)`
+
+use crate::{
+    DeviceConfig, DeviceHandle, DeviceInfo, Measurement, NsrtError, Result, Sampler, Threshold,
+    ThresholdEvent, ThresholdMonitor,
+};
+use std::{io, time::UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use zbus::{Connection, interface, object_server::SignalEmitter};
+
+/// Well-known bus name requested by [`serve`]
+pub const BUS_NAME: &str = "org.nsrt.Meter";
+
+/// Path of the meter object
+pub const OBJECT_PATH: &str = "/org/nsrt/Meter";
+
+/// Number of measurements buffered while signals are being sent
+const CHANNEL_CAPACITY: usize = 64;
+
+/// The `org.nsrt.Meter` object of one device
+pub struct Meter {
+    device: DeviceHandle,
+    info: DeviceInfo,
+    config: DeviceConfig,
+    alarm: Option<ThresholdMonitor>,
+    latest: Option<Measurement>,
+}
+
+impl Meter {
+    /// Create the object for the device sampled by `sampler`
+    pub async fn new(sampler: &Sampler) -> Result<Self> {
+        let device = sampler.device().clone();
+        let (info, config) = device
+            .call_async(|nsrt| Ok((nsrt.read_info()?, nsrt.read_config()?)))
+            .await?;
+
+        Ok(Self {
+            device,
+            info,
+            config,
+            alarm: None,
+            latest: sampler.latest(),
+        })
+    }
+
+    /// Send threshold signals as the level crosses `threshold`
+    #[must_use]
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.alarm = Some(ThresholdMonitor::new(threshold));
+        self
+    }
+
+    fn measured<T>(&self, f: impl FnOnce(&Measurement) -> T, none: T) -> T {
+        self.latest.as_ref().map_or(none, f)
+    }
+}
+
+#[interface(name = "org.nsrt.Meter")]
+impl Meter {
+    #[zbus(property)]
+    fn level(&self) -> f64 {
+        self.measured(|m| f64::from(m.level), f64::NAN)
+    }
+
+    #[zbus(property)]
+    fn leq(&self) -> f64 {
+        self.measured(|m| f64::from(m.leq), f64::NAN)
+    }
+
+    #[zbus(property)]
+    fn temperature(&self) -> f64 {
+        self.measured(|m| f64::from(m.temperature.as_celsius()), f64::NAN)
+    }
+
+    #[zbus(property)]
+    fn timestamp(&self) -> u64 {
+        self.measured(micros, 0)
+    }
+
+    #[zbus(property)]
+    fn weighting(&self) -> String {
+        format!("{:?}", self.config.weighting)
+    }
+
+    #[zbus(property)]
+    fn time_constant(&self) -> f64 {
+        f64::from(self.config.time_constant)
+    }
+
+    #[zbus(property)]
+    fn sampling_frequency(&self) -> u32 {
+        self.config.sampling_frequency.into()
+    }
+
+    #[zbus(property)]
+    fn model(&self) -> String {
+        self.info.model.clone()
+    }
+
+    #[zbus(property)]
+    fn serial_number(&self) -> String {
+        self.info.serial_number.clone()
+    }
+
+    #[zbus(property)]
+    fn firmware_revision(&self) -> String {
+        self.info.firmware_revision.clone()
+    }
+
+    #[zbus(property)]
+    fn user_id(&self) -> String {
+        self.info.user_id.clone()
+    }
+
+    #[zbus(property, name = "Threshold")]
+    fn alarm_threshold(&self) -> f64 {
+        self.alarm
+            .as_ref()
+            .map_or(f64::NAN, |a| f64::from(a.threshold().level))
+    }
+
+    #[zbus(property)]
+    fn alarm(&self) -> bool {
+        self.alarm
+            .as_ref()
+            .is_some_and(ThresholdMonitor::is_exceeded)
+    }
+
+    /// Re-read the measurement settings, e.g. after another client changed them
+    async fn reload_config(&mut self) -> zbus::fdo::Result<()> {
+        self.config = self
+            .device
+            .call_async(|nsrt| nsrt.read_config())
+            .await
+            .map_err(|e| zbus::fdo::Error::IOError(e.to_string()))?;
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn measurement(
+        emitter: &SignalEmitter<'_>,
+        level: f64,
+        leq: f64,
+        temperature: f64,
+        timestamp: u64,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn threshold_exceeded(
+        emitter: &SignalEmitter<'_>,
+        level: f64,
+        threshold: f64,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn threshold_cleared(
+        emitter: &SignalEmitter<'_>,
+        level: f64,
+        threshold: f64,
+    ) -> zbus::Result<()>;
+}
+
+/// Serve `meter` on `connection` until the sampler stops
+///
+/// Registers the object at [`OBJECT_PATH`], requests [`BUS_NAME`] and sends
+/// signals for every measurement of `sampler`. Use
+/// [`Connection::session`] or [`Connection::system`] to connect.
+pub async fn serve(connection: &Connection, sampler: &Sampler, meter: Meter) -> Result<()> {
+    let mut measurements = sampler.broadcast(CHANNEL_CAPACITY).subscribe();
+
+    let server = connection.object_server();
+    server.at(OBJECT_PATH, meter).await.map_err(dbus_error)?;
+    connection
+        .request_name(BUS_NAME)
+        .await
+        .map_err(dbus_error)?;
+    let meter = server
+        .interface::<_, Meter>(OBJECT_PATH)
+        .await
+        .map_err(dbus_error)?;
+    let emitter = meter.signal_emitter();
+
+    loop {
+        let measurement = match measurements.recv().await {
+            Ok(measurement) => measurement,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        let event = {
+            let mut meter = meter.get_mut().await;
+            meter.latest = Some(measurement);
+            meter.alarm.as_mut().and_then(|alarm| {
+                let threshold = f64::from(alarm.threshold().level);
+                alarm.update(&measurement).map(|event| (event, threshold))
+            })
+        };
+
+        let sent = send_signals(emitter, &*meter.get().await, &measurement, event).await;
+        sent.map_err(dbus_error)?;
+    }
+}
+
+async fn send_signals(
+    emitter: &SignalEmitter<'_>,
+    meter: &Meter,
+    measurement: &Measurement,
+    event: Option<(ThresholdEvent, f64)>,
+) -> zbus::Result<()> {
+    meter.level_changed(emitter).await?;
+    meter.leq_changed(emitter).await?;
+    meter.temperature_changed(emitter).await?;
+    meter.timestamp_changed(emitter).await?;
+
+    let level = f64::from(measurement.level);
+    Meter::measurement(
+        emitter,
+        level,
+        f64::from(measurement.leq),
+        f64::from(measurement.temperature.as_celsius()),
+        micros(measurement),
+    )
+    .await?;
+
+    match event {
+        Some((ThresholdEvent::Exceeded(_), threshold)) => {
+            meter.alarm_changed(emitter).await?;
+            Meter::threshold_exceeded(emitter, level, threshold).await
+        }
+        Some((ThresholdEvent::Cleared(_), threshold)) => {
+            meter.alarm_changed(emitter).await?;
+            Meter::threshold_cleared(emitter, level, threshold).await
+        }
+        None => Ok(()),
+    }
+}
+
+fn micros(measurement: &Measurement) -> u64 {
+    measurement
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64)
+}
+
+fn dbus_error(error: zbus::Error) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
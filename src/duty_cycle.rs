@@ -0,0 +1,139 @@
+use crate::{Compensated, Measurement, NsrtError, Quality, Result};
+use std::time::Duration;
+
+/// Bursts of measurement with the host idle in between, for loggers running
+/// on batteries or solar power
+///
+/// Every `period`, a [`Sampler`](crate::Sampler) started with
+/// [`Sampler::start_duty_cycled`](crate::Sampler::start_duty_cycled) wakes,
+/// restarts the meter's LEQ integration and takes `samples` readings spread
+/// over `burst`, the last one ending it. It then reports one measurement for
+/// the burst: its LEQ is the burst Leq, integrated over the whole burst, its
+/// level the highest running level read, and its timestamp and temperature
+/// those of the last reading. Between bursts the sampling thread sleeps and
+/// the serial port is idle, so each period costs `samples + 1` wakeups and
+/// `3 samples + 1` commands.
+///
+/// The power saved is paid for in accuracy. The burst Leq represents the
+/// period only as far as the bursts represent its noise: longer or more
+/// frequent bursts catch more intermittent noise for more time awake. More
+/// samples catch more of the peaks of the running level for more serial
+/// traffic; a single sample only reads the level at the end of the burst.
+/// Bursts shorter than the time constant read a running level still settling
+/// from the time between bursts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DutyCycle {
+    /// Time measured in each period
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub burst: Duration,
+    /// Time from the start of one burst to the start of the next
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub period: Duration,
+    /// Readings spread over each burst
+    #[cfg_attr(feature = "serde", serde(default = "default_samples"))]
+    pub samples: u32,
+}
+
+#[cfg(feature = "serde")]
+fn default_samples() -> u32 {
+    1
+}
+
+impl DutyCycle {
+    /// Measure for `burst` every `period`, reading the meter once at the end
+    /// of each burst
+    pub fn new(burst: Duration, period: Duration) -> Self {
+        Self {
+            burst,
+            period,
+            samples: 1,
+        }
+    }
+
+    /// Spread `samples` readings over each burst
+    #[must_use]
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Share of the time measured
+    pub fn fraction(&self) -> f64 {
+        self.burst.as_secs_f64() / self.period.as_secs_f64()
+    }
+
+    /// Check that the bursts are not empty and fit the period, and that they
+    /// read the meter at all
+    pub fn validate(&self) -> Result<()> {
+        if self.burst.is_zero() || self.burst > self.period {
+            return Err(NsrtError::InvalidParameter(format!(
+                "Burst of {:?} does not fit a period of {:?}",
+                self.burst, self.period
+            )));
+        }
+        if self.samples == 0 {
+            return Err(NsrtError::InvalidParameter(
+                "A burst needs at least one sample".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Readings of one burst, combined into its measurement
+#[derive(Default)]
+pub(crate) struct Burst {
+    /// Energy of the LEQs weighted by the time they cover, and the time
+    energy: f64,
+    compensated_energy: f64,
+    covered: f64,
+    /// Highest running levels
+    level: f32,
+    compensated_level: f32,
+    quality: Quality,
+    last: Option<Measurement>,
+}
+
+impl Burst {
+    /// Add a reading whose LEQ covers `covered` of the burst
+    pub(crate) fn add(&mut self, measurement: &Measurement, covered: Duration) {
+        let energy = |level: f32| covered.as_secs_f64() * 10f64.powf(f64::from(level) / 10.0);
+        let first = self.last.is_none();
+        self.energy += energy(measurement.leq);
+        self.covered += covered.as_secs_f64();
+        if first || measurement.level > self.level {
+            self.level = measurement.level;
+        }
+        if let Some(compensated) = measurement.compensated {
+            self.compensated_energy += energy(compensated.leq);
+            if first || compensated.level > self.compensated_level {
+                self.compensated_level = compensated.level;
+            }
+        }
+        self.quality |= measurement.quality;
+        self.last = Some(*measurement);
+    }
+
+    /// The measurement of the burst, none before the first reading
+    pub(crate) fn measurement(&self) -> Option<Measurement> {
+        let last = self.last?;
+        let leq = |energy: f64, fallback: f32| {
+            if self.covered > 0.0 {
+                (10.0 * (energy / self.covered).log10()) as f32
+            } else {
+                fallback
+            }
+        };
+        Some(Measurement {
+            level: self.level,
+            leq: leq(self.energy, last.leq),
+            compensated: last.compensated.map(|compensated| Compensated {
+                level: self.compensated_level,
+                leq: leq(self.compensated_energy, compensated.leq),
+            }),
+            quality: self.quality,
+            ..last
+        })
+    }
+}
@@ -0,0 +1,190 @@
+//! Transports over embedded serial traits
+//!
+//! For hosts such as an ESP32 running ESP-IDF, which has `std` but exposes
+//! its UARTs through the `embedded-io` or `embedded-hal-nb` traits, wired to
+//! the meter through a USB-to-UART bridge:
+//!
+//! ```ignore
+//! let uart = UartDriver::new(/* ... */)?;
+//! let mut nsrt = NSRT::with_transport(EmbeddedIo::new(uart));
+//! let level = nsrt.read_level()?;
+//! ```
+//!
+//! Neither trait family has timeouts, so both adapters poll the UART and
+//! report [`io::ErrorKind::TimedOut`] when the meter stays silent, like the
+//! native serial port does.
+
+use crate::{TIMEOUT, Transport};
+use embedded_hal_nb::nb;
+use std::{
+    io::{self, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Poll `ready` until it returns something or `timeout` passes
+fn poll<T>(timeout: Duration, mut ready: impl FnMut() -> io::Result<Option<T>>) -> io::Result<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(value) = ready()? {
+            return Ok(value);
+        }
+        if Instant::now() >= deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        thread::yield_now();
+    }
+}
+
+fn io_error(e: impl embedded_io::Error) -> io::Error {
+    io::Error::new(e.kind().into(), format!("{e:?}"))
+}
+
+/// [`Transport`] over an `embedded-io` UART
+pub struct EmbeddedIo<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T> EmbeddedIo<T> {
+    /// Wrap `inner`, timing out reads after one second
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            timeout: TIMEOUT,
+        }
+    }
+
+    /// Time out reads after `timeout` instead
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Return the wrapped UART
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: embedded_io::Read + embedded_io::ReadReady> Read for EmbeddedIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        poll(self.timeout, || {
+            Ok(inner.read_ready().map_err(io_error)?.then_some(()))
+        })?;
+        self.inner.read(buf).map_err(io_error)
+    }
+}
+
+impl<T: embedded_io::Write> Write for EmbeddedIo<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf).map_err(io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().map_err(io_error)
+    }
+}
+
+impl<T> Transport for EmbeddedIo<T>
+where
+    T: embedded_io::Read + embedded_io::ReadReady + embedded_io::Write + Send,
+{
+    fn clear(&mut self) -> io::Result<()> {
+        let mut buf = [0; 64];
+        while self.inner.read_ready().map_err(io_error)? {
+            if self.inner.read(&mut buf).map_err(io_error)? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn nb_error(e: impl embedded_hal_nb::serial::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e.kind()))
+}
+
+/// [`Transport`] over an `embedded-hal-nb` UART, one byte at a time
+pub struct EmbeddedHalNb<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T> EmbeddedHalNb<T> {
+    /// Wrap `inner`, timing out reads after one second
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            timeout: TIMEOUT,
+        }
+    }
+
+    /// Time out reads after `timeout` instead
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Return the wrapped UART
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: embedded_hal_nb::serial::Read> Read for EmbeddedHalNb<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((first, rest)) = buf.split_first_mut() else {
+            return Ok(0);
+        };
+        let inner = &mut self.inner;
+        *first = poll(self.timeout, || match inner.read() {
+            Ok(byte) => Ok(Some(byte)),
+            Err(nb::Error::WouldBlock) => Ok(None),
+            Err(nb::Error::Other(e)) => Err(nb_error(e)),
+        })?;
+
+        // Take whatever else has already arrived
+        let mut count = 1;
+        for byte in rest {
+            match self.inner.read() {
+                Ok(value) => *byte = value,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(nb_error(e)),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<T: embedded_hal_nb::serial::Write> Write for EmbeddedHalNb<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            nb::block!(self.inner.write(byte)).map_err(nb_error)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        nb::block!(self.inner.flush()).map_err(nb_error)
+    }
+}
+
+impl<T> Transport for EmbeddedHalNb<T>
+where
+    T: embedded_hal_nb::serial::Read + embedded_hal_nb::serial::Write + Send,
+{
+    fn clear(&mut self) -> io::Result<()> {
+        loop {
+            match self.inner.read() {
+                Ok(_) => {}
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(nb_error(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Binary logs encrypted at rest
+//!
+//! [`EncryptedLogWriter`] writes the records of a [binary log](crate::binlog),
+//! compressed in blocks in the same way, but seals every block with
+//! AES-256-GCM so that a stolen SD card or disk reveals nothing and any
+//! change to it is detected. A log starts with the 8-byte magic `NSRTENC2`,
+//! whose last character is the schema version, and a random 16-byte log ID,
+//! followed by one segment per block:
+//!
+//! | Offset | Size | Field                                       |
+//! | ------ | ---- | ------------------------------------------- |
+//! | 0      | 4    | ciphertext length `n`, `u32` little-endian  |
+//! | 4      | 12   | random nonce                                |
+//! | 16     | `n`  | ciphertext of the zstd frame, with its tag  |
+//!
+//! The additional authenticated data of a segment is the log ID followed by
+//! the segment's index from 0 as a little-endian `u64`, so segments can't be
+//! reordered, removed from the middle or moved between logs unnoticed.
+//! Segments cut off the end of the log can't be told from a log that ended
+//! there.
+//!
+//! Keys are 32 bytes, written as 64 hex digits in key files and in the
+//! [`KEY_ENV`] environment variable.
+//!
+//! The schema version follows that of the binary log records: version 1 logs
+//! hold records without quality flags, version 2 logs records with them.
+//! Readers read both, and refuse logs of later versions with
+//! [`NsrtError::UnsupportedSchema`].
+
+use crate::{
+    Measurement, NsrtError, Result, Sink,
+    binlog::{self, BLOCK_RECORDS, COMPRESSION_LEVEL, RECORD_LEN, record_len},
+};
+use aes_gcm::{
+    Aes256Gcm, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, Payload, rand_core::RngCore},
+};
+use std::{
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Magic bytes at the start of every encrypted log file
+pub const MAGIC: &[u8; 8] = b"NSRTENC2";
+
+/// Schema version of the logs written, the last character of [`MAGIC`]
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Environment variable [`LogKey::from_env`] reads the key from
+pub const KEY_ENV: &str = "NSRT_LOG_KEY";
+
+const ID_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Largest ciphertext accepted when reading, well above a full block
+const MAX_SEGMENT_LEN: usize = 1 << 20;
+
+/// AES-256 key of encrypted logs
+#[derive(Clone, PartialEq, Eq)]
+pub struct LogKey([u8; 32]);
+
+impl LogKey {
+    /// A new random key
+    pub fn generate() -> Self {
+        Self(Aes256Gcm::generate_key(OsRng).into())
+    }
+
+    /// Key from 64 hex digits, ignoring surrounding whitespace
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        let invalid = || NsrtError::InvalidParameter("Log key must be 64 hex digits".to_string());
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut key = [0; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(key))
+    }
+
+    /// Key from a file holding 64 hex digits
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_hex(&fs::read_to_string(path)?)
+    }
+
+    /// Key from the [`KEY_ENV`] environment variable
+    pub fn from_env() -> Result<Self> {
+        let hex = env::var(KEY_ENV)
+            .map_err(|_| NsrtError::InvalidParameter(format!("{KEY_ENV} is not set")))?;
+        Self::from_hex(&hex)
+    }
+
+    /// The key as 64 hex digits, for writing a key file
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0))
+    }
+}
+
+impl fmt::Debug for LogKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LogKey(..)")
+    }
+}
+
+/// Sink writing measurements to an encrypted binary log
+///
+/// Like [`BinaryLogWriter`](binlog::BinaryLogWriter), buffered records are
+/// compressed, encrypted and written when a block fills up, on
+/// [`Sink::flush`], and when the writer is dropped.
+pub struct EncryptedLogWriter<W: Write> {
+    inner: W,
+    cipher: Aes256Gcm,
+    version: u32,
+    id: [u8; ID_LEN],
+    segments: u64,
+    block: Vec<u8>,
+}
+
+impl EncryptedLogWriter<File> {
+    /// Create a new log file at `path`, replacing any existing file
+    pub fn create(path: impl AsRef<Path>, key: &LogKey) -> Result<Self> {
+        Self::new(File::create(path)?, key)
+    }
+
+    /// Append to the log file at `path`, creating it if it doesn't exist
+    ///
+    /// A segment cut short by a crash is removed first, so the log stays
+    /// readable. Records are written in the schema version of the log, so
+    /// those appended to a version 1 log lose their quality flags.
+    pub fn append(path: impl AsRef<Path>, key: &LogKey) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Self::new(file, key);
+        }
+
+        let (version, id) = read_header(&mut file)?;
+        let (segments, end) = scan_segments(&mut file)?;
+        file.set_len(end)?;
+        Ok(Self::resume(file, key, version, id, segments))
+    }
+}
+
+impl<W: Write> EncryptedLogWriter<W> {
+    /// Start a new log on `inner`, writing the file header with a random ID
+    pub fn new(mut inner: W, key: &LogKey) -> Result<Self> {
+        let mut id = [0; ID_LEN];
+        OsRng.fill_bytes(&mut id);
+        inner.write_all(MAGIC)?;
+        inner.write_all(&id)?;
+        Ok(Self::resume(inner, key, SCHEMA_VERSION, id, 0))
+    }
+
+    fn resume(inner: W, key: &LogKey, version: u32, id: [u8; ID_LEN], segments: u64) -> Self {
+        Self {
+            inner,
+            cipher: key.cipher(),
+            version,
+            id,
+            segments,
+            block: Vec::with_capacity(BLOCK_RECORDS * RECORD_LEN),
+        }
+    }
+
+    /// The underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    fn write_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let compressed = zstd::bulk::compress(&self.block, COMPRESSION_LEVEL)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = aad(&self.id, self.segments);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &compressed,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| invalid_data("Encryption failed"))?;
+        let len = u32::try_from(ciphertext.len()).map_err(|_| invalid_data("Block too large"))?;
+
+        let segment = [&len.to_le_bytes()[..], &nonce, &ciphertext].concat();
+        self.inner.write_all(&segment)?;
+        self.segments += 1;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for EncryptedLogWriter<W> {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        binlog::encode_record(measurement, self.version, &mut self.block);
+        if self.block.len() >= BLOCK_RECORDS * record_len(self.version) {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_block()?;
+        Ok(self.inner.flush()?)
+    }
+}
+
+impl<W: Write> Drop for EncryptedLogWriter<W> {
+    fn drop(&mut self) {
+        let _ = Sink::flush(self);
+    }
+}
+
+/// Iterator over the measurements in an encrypted binary log
+///
+/// Yields a [`NsrtError::VerificationFailed`] error for a segment that fails
+/// authentication, because of a wrong key or because the log was altered,
+/// and an I/O error for a log that ends partway through a segment.
+pub struct EncryptedLogReader<R: Read> {
+    inner: R,
+    cipher: Aes256Gcm,
+    version: u32,
+    id: [u8; ID_LEN],
+    segments: u64,
+    records: Vec<u8>,
+    position: usize,
+}
+
+impl EncryptedLogReader<BufReader<File>> {
+    /// Open the log file at `path`
+    pub fn open(path: impl AsRef<Path>, key: &LogKey) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?), key)
+    }
+}
+
+impl<R: Read> EncryptedLogReader<R> {
+    /// Read a log from `inner`, checking the file header
+    pub fn new(mut inner: R, key: &LogKey) -> Result<Self> {
+        let (version, id) = read_header(&mut inner)?;
+        Ok(Self {
+            inner,
+            cipher: key.cipher(),
+            version,
+            id,
+            segments: 0,
+            records: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Schema version the log was written with
+    pub fn schema_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Decrypt the next segment into `records`, returning whether there was
+    /// one
+    fn read_segment(&mut self) -> Result<bool> {
+        let mut len = [0; 4];
+        match self.inner.read(&mut len[..1])? {
+            0 => return Ok(false),
+            _ => self.inner.read_exact(&mut len[1..])?,
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_SEGMENT_LEN {
+            return Err(invalid_data("Segment too large"));
+        }
+        let mut nonce = [0; NONCE_LEN];
+        self.inner.read_exact(&mut nonce)?;
+        let mut ciphertext = vec![0; len];
+        self.inner.read_exact(&mut ciphertext)?;
+
+        let aad = aad(&self.id, self.segments);
+        let compressed = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| {
+                NsrtError::VerificationFailed(format!(
+                    "Segment {} failed authentication; wrong key or altered log",
+                    self.segments
+                ))
+            })?;
+        self.records = zstd::decode_all(&compressed[..])?;
+        if !self.records.len().is_multiple_of(record_len(self.version)) {
+            return Err(invalid_data("Truncated record"));
+        }
+        self.position = 0;
+        self.segments += 1;
+        Ok(true)
+    }
+
+    fn read_record(&mut self) -> Result<Option<Measurement>> {
+        while self.position >= self.records.len() {
+            if !self.read_segment()? {
+                return Ok(None);
+            }
+        }
+        let len = record_len(self.version);
+        let record = &self.records[self.position..][..len];
+        self.position += len;
+        binlog::decode_record(record).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for EncryptedLogReader<R> {
+    type Item = Result<Measurement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Whether the file at `path` starts like an encrypted log, of any schema
+/// version
+pub fn is_encrypted(path: impl AsRef<Path>) -> Result<bool> {
+    let mut magic = [0; MAGIC.len()];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic[..7] == MAGIC[..7]),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read the schema version and log ID
+fn read_header(inner: &mut impl Read) -> Result<(u32, [u8; ID_LEN])> {
+    let version = binlog::read_magic(inner, MAGIC, "Encrypted log")?;
+    let mut id = [0; ID_LEN];
+    inner.read_exact(&mut id)?;
+    Ok((version, id))
+}
+
+/// Count the complete segments after the header, returning the count and
+/// the offset just past the last of them
+fn scan_segments(file: &mut File) -> Result<(u64, u64)> {
+    let len = file.metadata()?.len();
+    let mut end = (MAGIC.len() + ID_LEN) as u64;
+    let mut segments = 0;
+    loop {
+        let mut header = [0; 4];
+        file.seek(SeekFrom::Start(end))?;
+        if end + (4 + NONCE_LEN) as u64 > len || file.read_exact(&mut header).is_err() {
+            break;
+        }
+        let next = end + (4 + NONCE_LEN) as u64 + u64::from(u32::from_le_bytes(header));
+        if next > len {
+            break;
+        }
+        end = next;
+        segments += 1;
+    }
+    Ok((segments, end))
+}
+
+fn aad(id: &[u8; ID_LEN], segment: u64) -> Vec<u8> {
+    [&id[..], &segment.to_le_bytes()].concat()
+}
+
+fn invalid_data(message: &str) -> NsrtError {
+    NsrtError::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
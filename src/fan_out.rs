@@ -0,0 +1,388 @@
+use crate::{Measurement, Metadata, NsrtError, Result, Sink};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, JoinHandle},
+};
+
+/// What a [`QueuedSink`] does with a measurement when its queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Backpressure {
+    /// Wait for room, holding up the writer, so that nothing is lost
+    #[default]
+    Block,
+    /// Discard the oldest queued measurement to make room
+    DropOldest,
+    /// Keep one in `n` incoming measurements, in place of the oldest queued
+    /// one, and discard the others, so that a sink that can't keep up gets
+    /// an evenly thinned stream rather than gaps
+    Sample(u32),
+}
+
+/// What the sink thread is asked to do, in order
+enum Item {
+    Measurement(Measurement),
+    Metadata(Metadata),
+    Flush,
+}
+
+struct Queue {
+    items: VecDeque<Item>,
+    /// Measurements among the items
+    measurements: usize,
+    /// Measurements that found the queue full since it last had room
+    skipped: u32,
+    dropped: u64,
+    /// Flushes requested, and flushes done
+    flushes: u64,
+    flushed: u64,
+    /// Error that stopped the sink thread, until reported
+    error: Option<NsrtError>,
+    /// Whether the sink thread stopped, or is to stop once the queue is empty
+    stopped: bool,
+    closing: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signalled when items are queued, and when they are taken or done
+    queued: Condvar,
+    taken: Condvar,
+}
+
+/// Sink writing to another sink on its own thread, through a bounded queue
+///
+/// Writing only queues the measurement, so a slow sink, such as one sending
+/// over the network, holds up neither the sampler nor the other sinks, up to
+/// the capacity of the queue. What happens beyond it is set by the
+/// [`Backpressure`]. Metadata and flushes are queued in order with the
+/// measurements, and never discarded; [`Sink::flush`] waits until everything
+/// queued before it is written and flushed.
+///
+/// An error of the inner sink stops its thread and is returned by the next
+/// call, after which the sink accepts nothing more, see
+/// [`QueuedSink::has_failed`]. Dropping the sink writes
+/// what is queued, flushes the inner sink and waits for its thread.
+pub struct QueuedSink {
+    shared: Arc<Shared>,
+    capacity: usize,
+    backpressure: Backpressure,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl QueuedSink {
+    /// Run `sink` on a thread of its own behind a queue of `capacity`
+    /// measurements
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new<S: Sink + Send + 'static>(
+        sink: S,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> Self {
+        assert!(capacity > 0, "queue capacity must not be zero");
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                items: VecDeque::new(),
+                measurements: 0,
+                skipped: 0,
+                dropped: 0,
+                flushes: 0,
+                flushed: 0,
+                error: None,
+                stopped: false,
+                closing: false,
+            }),
+            queued: Condvar::new(),
+            taken: Condvar::new(),
+        });
+        let thread = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(sink, &shared))
+        };
+        Self {
+            shared,
+            capacity,
+            backpressure,
+            thread: Some(thread),
+        }
+    }
+
+    /// Number of measurements waiting to be written
+    pub fn queued(&self) -> usize {
+        self.lock().measurements
+    }
+
+    /// Number of measurements discarded because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    /// Whether the inner sink failed, and its thread stopped
+    pub fn has_failed(&self) -> bool {
+        let queue = self.lock();
+        queue.stopped && !queue.closing
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        lock(&self.shared.queue)
+    }
+
+    /// Queue `item`, failing with the error that stopped the sink thread
+    fn push(&self, item: Item) -> Result<MutexGuard<'_, Queue>> {
+        let mut queue = self.lock();
+        if let Some(e) = queue.error.take() {
+            return Err(e);
+        }
+        if queue.stopped {
+            return Err(stopped());
+        }
+        queue.items.push_back(item);
+        self.shared.queued.notify_one();
+        Ok(queue)
+    }
+}
+
+impl Sink for QueuedSink {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.push(Item::Metadata(metadata.clone())).map(drop)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let mut queue = self.lock();
+        if queue.measurements >= self.capacity {
+            match self.backpressure {
+                Backpressure::Block => {
+                    queue = self
+                        .shared
+                        .taken
+                        .wait_while(queue, |queue| {
+                            queue.measurements >= self.capacity && !queue.stopped
+                        })
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                Backpressure::DropOldest => queue.drop_oldest(),
+                Backpressure::Sample(n) => {
+                    queue.skipped = queue.skipped.wrapping_add(1);
+                    if queue.skipped.is_multiple_of(n.max(1)) {
+                        queue.drop_oldest();
+                    } else {
+                        queue.dropped += 1;
+                        return Ok(());
+                    }
+                }
+            }
+        } else {
+            queue.skipped = 0;
+        }
+        drop(queue);
+
+        let mut queue = self.push(Item::Measurement(*measurement))?;
+        queue.measurements += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut queue = self.push(Item::Flush)?;
+        queue.flushes += 1;
+        let flush = queue.flushes;
+        let mut queue = self
+            .shared
+            .taken
+            .wait_while(queue, |queue| queue.flushed < flush && !queue.stopped)
+            .unwrap_or_else(PoisonError::into_inner);
+        match queue.error.take() {
+            Some(e) => Err(e),
+            None if queue.flushed < flush => Err(stopped()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for QueuedSink {
+    fn drop(&mut self) {
+        self.lock().closing = true;
+        self.shared.queued.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Queue {
+    /// Discard the oldest queued measurement, keeping metadata and flushes
+    fn drop_oldest(&mut self) {
+        if let Some(index) = self
+            .items
+            .iter()
+            .position(|item| matches!(item, Item::Measurement(_)))
+        {
+            self.items.remove(index);
+            self.measurements -= 1;
+            self.dropped += 1;
+        }
+    }
+}
+
+fn run<S: Sink>(mut sink: S, shared: &Shared) {
+    loop {
+        let item = {
+            let mut queue = shared
+                .queued
+                .wait_while(lock(&shared.queue), |queue| {
+                    queue.items.is_empty() && !queue.closing
+                })
+                .unwrap_or_else(PoisonError::into_inner);
+            let Some(item) = queue.items.pop_front() else {
+                break;
+            };
+            if matches!(item, Item::Measurement(_)) {
+                queue.measurements -= 1;
+            }
+            item
+        };
+
+        let result = match &item {
+            Item::Measurement(measurement) => sink.write(measurement),
+            Item::Metadata(metadata) => sink.set_metadata(metadata),
+            Item::Flush => sink.flush(),
+        };
+        let mut queue = lock(&shared.queue);
+        if matches!(item, Item::Flush) {
+            queue.flushed += 1;
+        }
+        if let Err(e) = result {
+            queue.error = Some(e);
+            queue.stopped = true;
+            queue.items.clear();
+            queue.measurements = 0;
+        }
+        shared.taken.notify_all();
+        if queue.stopped {
+            return;
+        }
+    }
+
+    let result = sink.flush();
+    let mut queue = lock(&shared.queue);
+    queue.stopped = true;
+    if let Err(e) = result {
+        queue.error.get_or_insert(e);
+    }
+    shared.taken.notify_all();
+}
+
+fn stopped() -> NsrtError {
+    NsrtError::IoError(std::io::Error::other("Queued sink stopped after an error"))
+}
+
+/// Sink handing every measurement to several sinks, each on its own thread
+/// behind its own queue
+///
+/// Each sink is wrapped in a [`QueuedSink`] with its own capacity and
+/// [`Backpressure`], so that a CSV log can block to keep everything while a
+/// network sink drops or thins what it can't keep up with, without one
+/// holding up the others.
+///
+/// A failed sink is left out from then on, and its error is kept for
+/// [`FanOut::take_errors`] rather than returned, so that the others go on,
+/// e.g. when the fan-out is [attached](crate::Sampler::attach) to a sampler.
+/// Only once every sink has failed do calls fail, the first with the error
+/// of the last sink.
+#[derive(Default)]
+pub struct FanOut {
+    sinks: Vec<QueuedSink>,
+    /// Whether each sink's error has been taken, leaving it out
+    failed: Vec<bool>,
+    errors: Vec<(usize, NsrtError)>,
+}
+
+impl FanOut {
+    /// Create a fan-out without sinks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `sink` behind a queue of `capacity` measurements
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    #[must_use]
+    pub fn sink<S: Sink + Send + 'static>(
+        mut self,
+        sink: S,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> Self {
+        self.sinks
+            .push(QueuedSink::new(sink, capacity, backpressure));
+        self.failed.push(false);
+        self
+    }
+
+    /// The queued sinks, in the order they were added
+    pub fn sinks(&self) -> &[QueuedSink] {
+        &self.sinks
+    }
+
+    /// Indices of the sinks left out after failing
+    pub fn failed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.failed
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &failed)| failed.then_some(index))
+    }
+
+    /// Errors of the sinks that failed since the last call, each with the
+    /// index of its sink
+    pub fn take_errors(&mut self) -> Vec<(usize, NsrtError)> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Apply `f` to every sink still going, keeping the errors of those that
+    /// fail, and fail once none is left
+    fn each(&mut self, mut f: impl FnMut(&mut QueuedSink) -> Result<()>) -> Result<()> {
+        for (index, sink) in self.sinks.iter_mut().enumerate() {
+            if self.failed[index] {
+                continue;
+            }
+            if let Err(e) = f(sink) {
+                self.failed[index] = true;
+                self.errors.push((index, e));
+            }
+        }
+        if self.sinks.is_empty() || !self.failed.iter().all(|&failed| failed) {
+            return Ok(());
+        }
+        Err(match self.errors.pop() {
+            Some((_, e)) => e,
+            None => NsrtError::IoError(std::io::Error::other("Every sink of the fan-out failed")),
+        })
+    }
+}
+
+impl Sink for FanOut {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.each(|sink| sink.set_metadata(metadata))
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.each(|sink| sink.write(measurement))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.each(Sink::flush)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
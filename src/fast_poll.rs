@@ -0,0 +1,113 @@
+use crate::{
+    DeviceConfig, Measurement, NSRT, NsrtError, Quality, Result, SystemClock, Temperature,
+    TimeSource,
+    protocol::{self, Command, PACKET_LEN},
+};
+use std::{io::Write, time::Instant};
+
+/// The level, LEQ and temperature requests, sent as one write
+pub(crate) const REQUESTS: [Command; 3] = [
+    Command::ReadLevel,
+    Command::ReadLEQ,
+    Command::ReadTemperature,
+];
+
+/// Length of the answers to [`REQUESTS`], four bytes each
+pub(crate) const ANSWER_LEN: usize = 12;
+
+/// Dense polling of level, LEQ and temperature
+///
+/// Created by [`NSRT::fast_poll`], which applies and checks the measurement
+/// settings once up front. Each [`FastPoll::read`] then sends the three read
+/// commands in a single write and takes the three answers in a single read,
+/// like [`NSRT::read_many`] but decoding them without its checks of the
+/// values. Answers are still checked in [strict mode](NSRT::strict_mode).
+/// Compared to three separate reads, this saves two round trips per
+/// measurement on links with high latency.
+///
+/// The running level is smoothed with the time constant, so polling much
+/// faster than `1 / time_constant` adds little; the LEQ covers exactly the
+/// time since the previous read at any rate.
+pub struct FastPoll<'a> {
+    nsrt: &'a mut NSRT,
+    config: DeviceConfig,
+    request: [u8; REQUESTS.len() * PACKET_LEN],
+}
+
+impl NSRT {
+    /// Apply `config` and start polling measurements as fast as possible
+    ///
+    /// The settings are written only where they differ, followed by the
+    /// stabilization wait, then read back so that polling never has to check
+    /// them again.
+    pub fn fast_poll(&mut self, config: &DeviceConfig) -> Result<FastPoll<'_>> {
+        if !config.time_constant.is_finite() || config.time_constant <= 0.0 {
+            return Err(NsrtError::InvalidParameter(format!(
+                "Time constant {} is not a positive number of seconds",
+                config.time_constant
+            )));
+        }
+        self.configure(config)?;
+        if self.read_config()? != *config {
+            return Err(NsrtError::InvalidResponse);
+        }
+
+        let mut request = [0; REQUESTS.len() * PACKET_LEN];
+        protocol::encode_requests(&REQUESTS, &mut request)?;
+        Ok(FastPoll {
+            nsrt: self,
+            config: *config,
+            request,
+        })
+    }
+}
+
+impl FastPoll<'_> {
+    /// The settings in effect while polling
+    pub fn config(&self) -> &DeviceConfig {
+        &self.config
+    }
+
+    /// Read the level, LEQ and temperature as a single timestamped measurement
+    ///
+    /// Like [`NSRT::read_leq`], this restarts integration for the next LEQ.
+    pub fn read(&mut self) -> Result<Measurement> {
+        self.read_with(&SystemClock)
+    }
+
+    /// Read a measurement timestamped by `time_source`
+    pub fn read_with(&mut self, time_source: &dyn TimeSource) -> Result<Measurement> {
+        let (timestamp, clock) = time_source.now();
+        self.nsrt.pace();
+        self.nsrt.port.write_all(&self.request)?;
+        let mut answer = [0; ANSWER_LEN];
+        self.nsrt.read_answer(None, &mut answer)?;
+        self.nsrt.settle();
+
+        let [level, leq, temperature] = answer.as_chunks().0 else {
+            unreachable!("{ANSWER_LEN} bytes are three floats")
+        };
+        let measurement = self.nsrt.compensate(Measurement {
+            timestamp,
+            level: f32::from_le_bytes(*level) + self.nsrt.calibration_offset,
+            leq: f32::from_le_bytes(*leq) + self.nsrt.calibration_offset,
+            temperature: Temperature::from_celsius(f32::from_le_bytes(*temperature)),
+            clock,
+            compensated: None,
+            quality: Quality::of_clock(clock),
+        });
+        Ok(self.nsrt.check_range(measurement))
+    }
+
+    /// Measure the highest rate in Hz at which this host and link sustain
+    /// polling, by timing `polls` reads back to back
+    ///
+    /// The reads restart LEQ integration like any other.
+    pub fn max_rate(&mut self, polls: u32) -> Result<f64> {
+        let start = Instant::now();
+        for _ in 0..polls {
+            self.read()?;
+        }
+        Ok(f64::from(polls) / start.elapsed().as_secs_f64())
+    }
+}
@@ -0,0 +1,459 @@
+//! C API for test benches and other non-Rust hosts
+//!
+//! The `ffi` feature exports these functions from the `nsrt` cdylib and
+//! generates `include/nsrt.h` for them. Devices are opaque [`NsrtDevice`]
+//! handles, safe to share between threads; every call returns an
+//! [`NsrtStatus`], and [`nsrt_last_error`] describes the error of the last
+//! call on the calling thread, with [`nsrt_last_error_code`] giving its
+//! stable code from [`NsrtError::code`]:
+//!
+//! ```c
+//! NsrtDevice *dev;
+//! if (nsrt_open(NULL, &dev) != NSRT_STATUS_OK) {
+//!     fprintf(stderr, "%s\n", nsrt_last_error());
+//!     return 1;
+//! }
+//! float level;
+//! if (nsrt_read_level(dev, &level) == NSRT_STATUS_OK)
+//!     printf("%.1f dB\n", level);
+//! nsrt_close(dev);
+//! ```
+//!
+//! Functions never unwind into the caller; a panic is reported as
+//! `NSRT_STATUS_PANIC`.
+
+use crate::{DeviceConfig, NSRT, NsrtError, Result, SamplingFrequency, Weighting};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{CStr, CString, c_char},
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+/// Version of the C API, raised on incompatible changes
+pub const NSRT_API_VERSION: u32 = 1;
+
+/// C weighting
+pub const NSRT_WEIGHTING_C: i32 = 0;
+/// A weighting
+pub const NSRT_WEIGHTING_A: i32 = 1;
+/// Z weighting
+pub const NSRT_WEIGHTING_Z: i32 = 2;
+
+/// Size of the text fields of [`NsrtInfo`], including the NUL terminator
+pub const NSRT_TEXT_LEN: usize = 32;
+
+/// Outcome of a call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NsrtStatus {
+    /// Success
+    Ok = 0,
+    /// No device attached, or the port doesn't exist
+    NoDevice = 1,
+    /// The device didn't answer in time
+    Timeout = 2,
+    /// The device rejected a setting
+    NoAcknowledge = 3,
+    /// The device answered with something unexpected
+    InvalidResponse = 4,
+    /// An argument was out of range, not valid UTF-8, or a null pointer
+    InvalidArgument = 5,
+    /// Any other serial port or I/O error
+    IoError = 6,
+    /// A bug in the library; the device handle should be closed
+    Panic = 7,
+    /// The device's firmware is too old for the operation
+    Unsupported = 8,
+}
+
+/// Opaque device handle
+pub struct NsrtDevice {
+    nsrt: Mutex<NSRT>,
+}
+
+/// Readings taken at one point in time
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NsrtMeasurement {
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+    /// Running sound level in dB
+    pub level: f32,
+    /// LEQ in dB since the previous LEQ read
+    pub leq: f32,
+    /// Temperature in °C
+    pub temperature: f32,
+}
+
+/// Measurement settings
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NsrtConfig {
+    /// One of the `NSRT_WEIGHTING_*` constants
+    pub weighting: i32,
+    /// Time constant in seconds
+    pub time_constant: f32,
+    /// Sampling frequency in Hz, 32000 or 48000
+    pub sampling_frequency: u32,
+}
+
+/// Identity of a device
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NsrtInfo {
+    /// Model name
+    pub model: [c_char; NSRT_TEXT_LEN],
+    /// Serial number
+    pub serial_number: [c_char; NSRT_TEXT_LEN],
+    /// Firmware revision
+    pub firmware_revision: [c_char; NSRT_TEXT_LEN],
+    /// User-defined identifier
+    pub user_id: [c_char; NSRT_TEXT_LEN],
+    /// Date of last calibration, in seconds since Jan 1 1904 UTC
+    pub calibration_date: u64,
+    /// Date of manufacture, in seconds since Jan 1 1904 UTC
+    pub birth_date: u64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+    static LAST_ERROR_CODE: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Run `f`, recording its error for [`nsrt_last_error`] and
+/// [`nsrt_last_error_code`]
+fn call(f: impl FnOnce() -> Result<()>) -> NsrtStatus {
+    let (status, message, code) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (NsrtStatus::Ok, String::new(), 0),
+        Ok(Err(e)) => (status(&e), e.to_string(), e.code()),
+        Err(_) => (NsrtStatus::Panic, "internal error".to_string(), 0),
+    };
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    });
+    LAST_ERROR_CODE.set(code);
+    status
+}
+
+fn status(error: &NsrtError) -> NsrtStatus {
+    let io_status = |kind: io::ErrorKind| match kind {
+        io::ErrorKind::NotFound => NsrtStatus::NoDevice,
+        io::ErrorKind::TimedOut => NsrtStatus::Timeout,
+        _ => NsrtStatus::IoError,
+    };
+    match error {
+        NsrtError::NoDevice => NsrtStatus::NoDevice,
+        NsrtError::NoAcknowledge => NsrtStatus::NoAcknowledge,
+        NsrtError::InvalidResponse
+        | NsrtError::Malformed(_)
+        | NsrtError::FromBytesUntilNulError(_)
+        | NsrtError::Utf8Error(_)
+        | NsrtError::ProtocolViolation(_) => NsrtStatus::InvalidResponse,
+        NsrtError::InvalidParameter(_) => NsrtStatus::InvalidArgument,
+        NsrtError::Unsupported { .. } => NsrtStatus::Unsupported,
+        NsrtError::SerialError(e) => match e.kind() {
+            serialport::ErrorKind::NoDevice => NsrtStatus::NoDevice,
+            serialport::ErrorKind::Io(kind) => io_status(kind),
+            _ => NsrtStatus::IoError,
+        },
+        NsrtError::IoError(e) => io_status(e.kind()),
+        _ => NsrtStatus::IoError,
+    }
+}
+
+fn null_pointer() -> NsrtError {
+    NsrtError::InvalidParameter("Null pointer".to_string())
+}
+
+fn not_utf8(what: &str) -> NsrtError {
+    NsrtError::InvalidParameter(format!("{what} is not valid UTF-8"))
+}
+
+/// Borrow the device behind `device`
+///
+/// # Safety
+///
+/// `device` must be null or a live handle from [`nsrt_open`].
+unsafe fn device<'a>(device: *const NsrtDevice) -> Result<&'a Mutex<NSRT>> {
+    // SAFETY: guaranteed by the caller
+    unsafe { device.as_ref() }
+        .map(|device| &device.nsrt)
+        .ok_or_else(null_pointer)
+}
+
+/// Run `f` against the device and store its result in `out`
+///
+/// # Safety
+///
+/// `dev` must be null or a live handle, and `out` null or valid for writes.
+unsafe fn read<T>(
+    dev: *const NsrtDevice,
+    out: *mut T,
+    f: impl FnOnce(&mut NSRT) -> Result<T>,
+) -> NsrtStatus {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let device = unsafe { device(dev) }?;
+        if out.is_null() {
+            return Err(null_pointer());
+        }
+        let value = f(&mut device.lock().unwrap_or_else(|e| e.into_inner()))?;
+        // SAFETY: checked for null above, valid for writes per the caller
+        unsafe { out.write(value) };
+        Ok(())
+    })
+}
+
+/// Version of the C API the library implements, `NSRT_API_VERSION`
+#[unsafe(no_mangle)]
+pub extern "C" fn nsrt_api_version() -> u32 {
+    NSRT_API_VERSION
+}
+
+/// Description of the error of the last call on this thread, or an empty
+/// string if it succeeded
+///
+/// The string stays valid until the next call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn nsrt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Stable code of the error of the last call on this thread, see
+/// [`NsrtError::code`], or 0 if it succeeded or panicked
+#[unsafe(no_mangle)]
+pub extern "C" fn nsrt_last_error_code() -> u32 {
+    LAST_ERROR_CODE.get()
+}
+
+/// Open the device on serial port `port`, or the first one found if `port`
+/// is null, and store its handle in `*out`
+///
+/// # Safety
+///
+/// `port` must be null or a NUL-terminated string, and `out` valid for
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_open(port: *const c_char, out: *mut *mut NsrtDevice) -> NsrtStatus {
+    call(|| {
+        if out.is_null() {
+            return Err(null_pointer());
+        }
+        let nsrt = if port.is_null() {
+            NSRT::open()?
+        } else {
+            // SAFETY: NUL-terminated per the caller
+            let port = unsafe { CStr::from_ptr(port) }
+                .to_str()
+                .map_err(|_| not_utf8("Port"))?;
+            NSRT::open_port(port)?
+        };
+        let device = Box::new(NsrtDevice {
+            nsrt: Mutex::new(nsrt),
+        });
+        // SAFETY: checked for null above, valid for writes per the caller
+        unsafe { out.write(Box::into_raw(device)) };
+        Ok(())
+    })
+}
+
+/// Close the device and free its handle; null is ignored
+///
+/// # Safety
+///
+/// `dev` must be null or a handle from [`nsrt_open`] not closed before, and
+/// no other call may be using it.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_close(dev: *mut NsrtDevice) {
+    if !dev.is_null() {
+        // SAFETY: allocated by nsrt_open and not freed before, per the caller
+        drop(unsafe { Box::from_raw(dev) });
+    }
+}
+
+/// Read the running sound level in dB
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_read_level(dev: *const NsrtDevice, out: *mut f32) -> NsrtStatus {
+    // SAFETY: forwarded from the caller
+    unsafe { read(dev, out, NSRT::read_level) }
+}
+
+/// Read the LEQ in dB and restart its integration
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_read_leq(dev: *const NsrtDevice, out: *mut f32) -> NsrtStatus {
+    // SAFETY: forwarded from the caller
+    unsafe { read(dev, out, NSRT::read_leq) }
+}
+
+/// Read the temperature in °C
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_read_temperature(
+    dev: *const NsrtDevice,
+    out: *mut f32,
+) -> NsrtStatus {
+    // SAFETY: forwarded from the caller
+    unsafe { read(dev, out, |nsrt| Ok(nsrt.read_temperature()?.as_celsius())) }
+}
+
+/// Read level, LEQ and temperature with a host timestamp
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_read_measurement(
+    dev: *const NsrtDevice,
+    out: *mut NsrtMeasurement,
+) -> NsrtStatus {
+    // SAFETY: forwarded from the caller
+    unsafe {
+        read(dev, out, |nsrt| {
+            let m = nsrt.read_measurement()?;
+            Ok(NsrtMeasurement {
+                timestamp: m
+                    .timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+                level: m.level,
+                leq: m.leq,
+                temperature: m.temperature.as_celsius(),
+            })
+        })
+    }
+}
+
+/// Read the measurement settings
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_read_config(
+    dev: *const NsrtDevice,
+    out: *mut NsrtConfig,
+) -> NsrtStatus {
+    // SAFETY: forwarded from the caller
+    unsafe {
+        read(dev, out, |nsrt| {
+            let config = nsrt.read_config()?;
+            Ok(NsrtConfig {
+                weighting: u8::from(config.weighting).into(),
+                time_constant: config.time_constant,
+                sampling_frequency: config.sampling_frequency.into(),
+            })
+        })
+    }
+}
+
+/// Apply measurement settings, writing only those that differ, and wait for
+/// the device to stabilize
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `config` valid for reads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_configure(
+    dev: *const NsrtDevice,
+    config: *const NsrtConfig,
+) -> NsrtStatus {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let device = unsafe { device(dev) }?;
+        // SAFETY: valid for reads per the caller
+        let config = unsafe { config.as_ref() }.ok_or_else(null_pointer)?;
+        let weighting = match config.weighting {
+            NSRT_WEIGHTING_C => Weighting::C,
+            NSRT_WEIGHTING_A => Weighting::A,
+            NSRT_WEIGHTING_Z => Weighting::Z,
+            other => {
+                return Err(NsrtError::InvalidParameter(format!(
+                    "Unknown weighting {other}"
+                )));
+            }
+        };
+        let config = DeviceConfig {
+            weighting,
+            time_constant: config.time_constant,
+            sampling_frequency: SamplingFrequency::try_from(config.sampling_frequency)?,
+        };
+        device
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .configure(&config)
+    })
+}
+
+/// Read the identity of the device
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_read_info(dev: *const NsrtDevice, out: *mut NsrtInfo) -> NsrtStatus {
+    // SAFETY: forwarded from the caller
+    unsafe {
+        read(dev, out, |nsrt| {
+            let info = nsrt.read_info()?;
+            Ok(NsrtInfo {
+                model: text(&info.model),
+                serial_number: text(&info.serial_number),
+                firmware_revision: text(&info.firmware_revision),
+                user_id: text(&info.user_id),
+                calibration_date: info.calibration_date,
+                birth_date: info.birth_date,
+            })
+        })
+    }
+}
+
+/// Set the user ID, at most 31 bytes of UTF-8
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `user_id` a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nsrt_set_user_id(
+    dev: *const NsrtDevice,
+    user_id: *const c_char,
+) -> NsrtStatus {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let device = unsafe { device(dev) }?;
+        if user_id.is_null() {
+            return Err(null_pointer());
+        }
+        // SAFETY: NUL-terminated per the caller
+        let user_id = unsafe { CStr::from_ptr(user_id) }
+            .to_str()
+            .map_err(|_| not_utf8("User ID"))?;
+        device
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_user_id(user_id)
+    })
+}
+
+/// NUL-terminated copy of `value`, truncated to fit
+fn text(value: &str) -> [c_char; NSRT_TEXT_LEN] {
+    let mut field = [0; NSRT_TEXT_LEN];
+    for (dst, src) in field.iter_mut().zip(value.bytes().take(NSRT_TEXT_LEN - 1)) {
+        *dst = src as c_char;
+    }
+    field
+}
@@ -0,0 +1,131 @@
+use crate::{
+    NSRT, NsrtError, Result,
+    protocol::{self, Command},
+};
+use std::{fmt, str::FromStr};
+
+/// A firmware revision, ordered so that later releases compare greater
+///
+/// Parsed from revisions like `1.4`, `V1.4` or `1.4.2`; a missing patch
+/// number counts as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FirmwareVersion {
+    /// Major version
+    pub major: u16,
+    /// Minor version
+    pub minor: u16,
+    /// Patch version
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    /// Version `major.minor.patch`
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = NsrtError;
+
+    fn from_str(revision: &str) -> Result<Self> {
+        let invalid =
+            || NsrtError::InvalidParameter(format!("Unrecognized firmware revision {revision:?}"));
+        let revision = revision.trim();
+        let digits = revision
+            .strip_prefix(['V', 'v'])
+            .unwrap_or(revision)
+            .trim_start();
+        let mut parts = digits.split('.').map(|part| part.parse::<u16>());
+        let major = parts
+            .next()
+            .and_then(|part| part.ok())
+            .ok_or_else(invalid)?;
+        let minor = parts
+            .next()
+            .and_then(|part| part.ok())
+            .ok_or_else(invalid)?;
+        let patch = parts.next().transpose().map_err(|_| invalid())?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self::new(major, minor, patch.unwrap_or(0)))
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// First firmware with [`Command::WriteAudioDebug`]
+const AUDIO_DEBUG: FirmwareVersion = FirmwareVersion::new(1, 4, 0);
+
+/// Optional features the connected device's firmware supports
+///
+/// Derived from the firmware revision by [`NSRT::capabilities`]. A revision
+/// that doesn't parse, as reported by development builds and the simulator,
+/// is taken to support everything and leaves the device to refuse what it
+/// can't do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// Parsed firmware version, if the revision is in a known format
+    pub firmware: Option<FirmwareVersion>,
+    /// Audio debug mode, see [`NSRT::set_audio_debug`]
+    pub audio_debug: bool,
+    /// Reads queued in one write answered in order, see [`NSRT::read_many`]
+    pub pipelined_reads: bool,
+}
+
+impl Capabilities {
+    /// Features of firmware `version`, or of unknown firmware for `None`
+    pub fn of(version: Option<FirmwareVersion>) -> Self {
+        let since = |first| version.is_none_or(|version| version >= first);
+        Self {
+            firmware: version,
+            audio_debug: since(AUDIO_DEBUG),
+            // Every release so far buffers commands on the serial link
+            pipelined_reads: true,
+        }
+    }
+}
+
+impl NSRT {
+    /// Optional features of the device's firmware
+    ///
+    /// The firmware revision is read on first use and remembered for the
+    /// life of this driver.
+    pub fn capabilities(&mut self) -> Result<Capabilities> {
+        if let Some(capabilities) = self.capabilities {
+            return Ok(capabilities);
+        }
+        let mut buf = [0; protocol::TEXT_LEN];
+        let revision = self.read_firmware_revision_into(&mut buf)?;
+        let capabilities = Capabilities::of(revision.parse().ok());
+        self.capabilities = Some(capabilities);
+        Ok(capabilities)
+    }
+
+    /// Switch the USB audio output between the microphone and a 94 dB
+    /// 1 kHz test tone
+    ///
+    /// Levels read over this interface keep coming from the microphone.
+    /// Firmware before 1.4 doesn't have the command; on it this fails with
+    /// [`NsrtError::Unsupported`] without sending anything.
+    pub fn set_audio_debug(&mut self, enabled: bool) -> Result<()> {
+        if !self.capabilities()?.audio_debug {
+            return Err(NsrtError::Unsupported {
+                feature: "Audio debug mode",
+                required: AUDIO_DEBUG,
+            });
+        }
+        self.send_command_with_data(Command::WriteAudioDebug, 0, &[u8::from(enabled)])
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{Measurement, Metadata, NsrtError, Quality, Result, Sink};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const QUEUE_FILE: &str = "queue";
+const HEAD_FILE: &str = "head";
+const VERSION_FILE: &str = "version";
+/// Queue file of the current version written while upgrading an older one
+const UPGRADE_FILE: &str = "queue.upgrade";
+
+/// Version of the queue directory layout, in its version file
+///
+/// Version 1 queue files, also those from before the version file, hold the
+/// 24-byte encoding of each measurement, version 2 files the quality flags
+/// after it.
+const QUEUE_VERSION: u32 = 2;
+
+/// Records skipped at the front of the queue file before it is compacted
+const COMPACT_THRESHOLD: u64 = 1024;
+
+/// What to drop when the buffer of a [`StoreAndForward`] sink or a
+/// [`Subscription`](crate::Subscription) is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Discard the oldest buffered measurement to make room
+    #[default]
+    DropOldest,
+    /// Discard the incoming measurement
+    DropNewest,
+}
+
+/// Durable on-disk queue in front of another sink
+///
+/// Measurements are passed straight through while the inner sink accepts
+/// them. Once a write fails, they are appended to a queue in a directory
+/// instead, and every following write first replays the queue in order,
+/// so nothing taken while the uplink is down is lost, even across restarts.
+/// Delivery is at-least-once: a crash right after a replayed write can send
+/// that measurement again. A queue left by an earlier crate version is
+/// upgraded when opened, while one left by a later version, whose layout
+/// this one can't read, is refused rather than misread.
+///
+/// Metadata is passed on as soon as the inner sink accepts it, and is held
+/// in memory until then, ahead of the queued measurements.
+pub struct StoreAndForward<S> {
+    sink: S,
+    queue: File,
+    head_path: PathBuf,
+    queue_path: PathBuf,
+    /// Index of the first record not yet forwarded
+    head: u64,
+    /// Number of records in the queue file
+    len: u64,
+    capacity: Option<u64>,
+    policy: DropPolicy,
+    /// Metadata the inner sink hasn't accepted yet
+    metadata: Option<Metadata>,
+}
+
+impl<S: Sink> StoreAndForward<S> {
+    /// Buffer measurements for `sink` in `dir`, resuming any existing queue
+    pub fn open(dir: impl AsRef<Path>, sink: S) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        upgrade(dir)?;
+
+        let queue_path = dir.join(QUEUE_FILE);
+        let head_path = dir.join(HEAD_FILE);
+        let queue = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&queue_path)?;
+
+        // Drop a record torn by a crash mid-append
+        let len = queue.metadata()?.len() / Measurement::STORED_LEN as u64;
+        queue.set_len(len * Measurement::STORED_LEN as u64)?;
+
+        let head = match fs::read(&head_path) {
+            Ok(bytes) => bytes
+                .try_into()
+                .map(u64::from_le_bytes)
+                .unwrap_or(0)
+                .min(len),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            sink,
+            queue,
+            head_path,
+            queue_path,
+            head,
+            len,
+            capacity: None,
+            policy: DropPolicy::default(),
+            metadata: None,
+        })
+    }
+
+    /// Limit the buffer to `records` measurements
+    #[must_use]
+    pub fn capacity(mut self, records: u64) -> Self {
+        self.capacity = Some(records);
+        self
+    }
+
+    /// Set what to drop once the buffer is full
+    #[must_use]
+    pub fn drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Number of measurements waiting to be forwarded
+    pub fn pending(&self) -> u64 {
+        self.len - self.head
+    }
+
+    /// The wrapped sink
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Forward as much of the queue as the inner sink accepts
+    ///
+    /// Returns whether everything, including any held metadata, has been
+    /// passed on. A record that can't be decoded, e.g. after the file was
+    /// corrupted, is dropped with an error, and the next call goes on with
+    /// the records after it.
+    pub fn forward(&mut self) -> Result<bool> {
+        if let Some(metadata) = &self.metadata {
+            if self.sink.set_metadata(metadata).is_err() {
+                return Ok(false);
+            }
+            self.metadata = None;
+        }
+        if self.pending() == 0 {
+            return Ok(true);
+        }
+
+        self.queue
+            .seek(SeekFrom::Start(self.head * Measurement::STORED_LEN as u64))?;
+        let mut record = [0; Measurement::STORED_LEN];
+        while self.head < self.len {
+            self.queue.read_exact(&mut record)?;
+            let measurement = match Measurement::decode_stored(&record) {
+                Ok(measurement) => measurement,
+                Err(e) => {
+                    // Skip the corrupt record so it doesn't hold up the rest
+                    self.set_head(self.head + 1)?;
+                    return Err(e);
+                }
+            };
+            if self.sink.write(&measurement).is_err() {
+                return Ok(false);
+            }
+            self.set_head(self.head + 1)?;
+        }
+
+        self.queue.set_len(0)?;
+        self.len = 0;
+        self.set_head(0)?;
+        Ok(true)
+    }
+
+    fn enqueue(&mut self, measurement: &Measurement) -> Result<()> {
+        if self
+            .capacity
+            .is_some_and(|capacity| self.pending() >= capacity)
+        {
+            match self.policy {
+                DropPolicy::DropNewest => return Ok(()),
+                DropPolicy::DropOldest if self.pending() > 0 => self.set_head(self.head + 1)?,
+                DropPolicy::DropOldest => return Ok(()),
+            }
+        }
+        if self.head >= COMPACT_THRESHOLD.max(self.pending()) {
+            self.compact()?;
+        }
+
+        self.queue.seek(SeekFrom::End(0))?;
+        self.queue.write_all(&measurement.encode_stored())?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Rewrite the queue file without the forwarded records
+    fn compact(&mut self) -> Result<()> {
+        let mut pending = Vec::new();
+        self.queue
+            .seek(SeekFrom::Start(self.head * Measurement::STORED_LEN as u64))?;
+        self.queue.read_to_end(&mut pending)?;
+
+        let tmp_path = self.queue_path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&pending)?;
+        tmp.sync_data()?;
+        fs::rename(&tmp_path, &self.queue_path)?;
+
+        self.queue = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.queue_path)?;
+        self.len -= self.head;
+        self.set_head(0)
+    }
+
+    /// Report the number of pending measurements to the driver metrics,
+    /// labelled with the queue directory
+    #[cfg(feature = "metrics")]
+    fn report_depth(&self) {
+        let dir = self.queue_path.parent().unwrap_or(&self.queue_path);
+        crate::metrics::queue_depth(dir.display().to_string(), self.pending());
+    }
+
+    fn set_head(&mut self, head: u64) -> Result<()> {
+        fs::write(&self.head_path, head.to_le_bytes())?;
+        self.head = head;
+        Ok(())
+    }
+}
+
+impl<S: Sink> Sink for StoreAndForward<S> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.metadata = Some(metadata.clone());
+        self.forward().map(drop)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let written = if self.forward()? && self.sink.write(measurement).is_ok() {
+            Ok(())
+        } else {
+            self.enqueue(measurement)
+        };
+        #[cfg(feature = "metrics")]
+        self.report_depth();
+        written
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.queue.sync_data()?;
+        let forwarded = self.forward()?;
+        #[cfg(feature = "metrics")]
+        self.report_depth();
+        if forwarded {
+            self.sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Bring the queue directory `dir` to the current layout version, refusing
+/// later ones
+///
+/// An older queue file is rewritten next to the old one, which it replaces
+/// once the new version is recorded, so a crash midway leaves a queue of
+/// either version.
+fn upgrade(dir: &Path) -> Result<()> {
+    let version_path = dir.join(VERSION_FILE);
+    let queue_path = dir.join(QUEUE_FILE);
+    let upgrade_path = dir.join(UPGRADE_FILE);
+    let version = match fs::read_to_string(&version_path) {
+        Ok(version) => version.trim().parse().map_err(|_| {
+            NsrtError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid queue version {version:?}"),
+            ))
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && queue_path.exists() => 1,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    if version > QUEUE_VERSION {
+        return Err(NsrtError::UnsupportedSchema {
+            format: "Store-and-forward queue",
+            version,
+            supported: QUEUE_VERSION,
+        });
+    }
+
+    if version == 1 {
+        let records = fs::read(&queue_path)?;
+        let mut upgraded = Vec::new();
+        for record in records.chunks_exact(Measurement::ENCODED_LEN) {
+            upgraded.extend_from_slice(record);
+            upgraded.push(Quality::GOOD.bits());
+        }
+        let mut file = File::create(&upgrade_path)?;
+        file.write_all(&upgraded)?;
+        file.sync_data()?;
+    }
+    if version < QUEUE_VERSION {
+        let tmp_path = version_path.with_extension("tmp");
+        fs::write(&tmp_path, QUEUE_VERSION.to_string())?;
+        fs::rename(&tmp_path, &version_path)?;
+    }
+    match fs::rename(&upgrade_path, &queue_path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
@@ -0,0 +1,148 @@
+//! GeoJSON export of results by site
+//!
+//! [`SiteMap`] collects one [`Statistics`] per device and writes them as an
+//! RFC 7946 feature collection that QGIS, Leaflet and most other mapping
+//! tools open directly. Each feature is a point at the site's
+//! [`Position`](crate::Position), taken from its [`Metadata`] or from the
+//! device's entry in the [registry](crate::registry), with flat properties:
+//!
+//! ```json
+//! {
+//!   "type": "Feature",
+//!   "geometry": { "type": "Point", "coordinates": [13.405, 52.52] },
+//!   "properties": {
+//!     "serial": "2103A4F1",
+//!     "device": "rooftop-north",
+//!     "site": "Alexanderplatz",
+//!     "start": "2024-05-01T00:00:00Z",
+//!     "end": "2024-05-08T00:00:00Z",
+//!     "measurements": 604800,
+//!     "leq": 61.2,
+//!     "lmax": 94.0,
+//!     "lmin": 38.5,
+//!     "l10": 64.0,
+//!     "l90": 49.1,
+//!     "events": 12,
+//!     "event_seconds": 340.0
+//!   }
+//! }
+//! ```
+//!
+//! Sites without a position get a `null` geometry, which keeps their results
+//! in the attribute table. Missing levels are left out of the properties.
+
+use crate::{Metadata, Result, registry::Registry, report::Statistics};
+use serde_json::{Map, Value, json};
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
+/// Feature collection of per-site results
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SiteMap {
+    features: Vec<Value>,
+}
+
+impl SiteMap {
+    /// An empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the results of the device with serial number `serial`, placed and
+    /// named by `metadata`
+    pub fn add(&mut self, serial: &str, metadata: &Metadata, statistics: &Statistics) {
+        let geometry = metadata.position.map_or(Value::Null, |position| {
+            json!({ "type": "Point", "coordinates": [position.longitude, position.latitude] })
+        });
+
+        let mut properties = Map::new();
+        properties.insert("serial".into(), serial.into());
+        let mut text = |name: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                properties.insert(name.into(), value.as_str().into());
+            }
+        };
+        text("device", &metadata.device);
+        text("site", &metadata.site);
+        text("operator", &metadata.operator);
+        text("notes", &metadata.notes);
+        if let Some(height) = metadata.microphone_height {
+            properties.insert("microphone_height".into(), height.into());
+        }
+
+        properties.insert("start".into(), rfc3339(statistics.start).into());
+        properties.insert("end".into(), rfc3339(statistics.end).into());
+        properties.insert("measurements".into(), statistics.measurements.into());
+        if !statistics.quality.is_good() {
+            properties.insert("quality".into(), statistics.quality.to_string().into());
+        }
+        let levels = [
+            ("leq".to_string(), statistics.leq),
+            ("lmax".to_string(), statistics.lmax),
+            ("lmin".to_string(), statistics.lmin),
+        ];
+        let exceedance = statistics
+            .exceedance
+            .iter()
+            .map(|exceedance| (format!("l{}", exceedance.n), Some(exceedance.level)));
+        for (name, level) in levels.into_iter().chain(exceedance) {
+            if let Some(level) = level {
+                properties.insert(name, level.into());
+            }
+        }
+        properties.insert("events".into(), statistics.events.len().into());
+        properties.insert(
+            "event_seconds".into(),
+            statistics.event_time().as_secs_f64().into(),
+        );
+
+        self.features.push(json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": properties,
+        }));
+    }
+
+    /// Add the results of the device with serial number `serial`, placed and
+    /// named by its registry entry
+    ///
+    /// The entry's alias names the device unless its metadata does. A device
+    /// missing from the registry gets no position.
+    pub fn add_registered(&mut self, registry: &Registry, serial: &str, statistics: &Statistics) {
+        let mut metadata = Metadata::default();
+        if let Some(entry) = registry.get(serial) {
+            metadata = entry.metadata.clone();
+            if metadata.device.is_none() {
+                metadata.device = entry.alias.clone();
+            }
+        }
+        self.add(serial, &metadata, statistics);
+    }
+
+    /// Number of sites added
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    /// Whether no site was added
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// The feature collection as a JSON value
+    pub fn to_json(&self) -> Value {
+        json!({ "type": "FeatureCollection", "features": self.features })
+    }
+
+    /// Write the feature collection as pretty-printed GeoJSON
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(writer, &self.to_json()).map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
@@ -0,0 +1,233 @@
+//! gRPC service exposing devices over the `nsrt.v1.Meter` API
+//!
+//! The service definition lives in `proto/nsrt.proto`; the generated message
+//! types and client are available under [`proto`].
+
+use crate::{
+    DeviceConfig, DeviceHandle, DeviceInfo, Measurement, NSRT, NsrtError, Sampler,
+    SamplingFrequency, Weighting,
+};
+use std::{net::SocketAddr, pin::Pin, time::SystemTime};
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tonic::{Request, Response, Status, transport::Server};
+
+/// Types generated from `proto/nsrt.proto`
+#[allow(clippy::all, clippy::pedantic)]
+pub mod proto {
+    tonic::include_proto!("nsrt.v1");
+}
+
+use proto::meter_server::{Meter, MeterServer};
+
+/// Number of measurements buffered per stream before it starts missing some
+const CHANNEL_CAPACITY: usize = 64;
+
+struct Device {
+    info: DeviceInfo,
+    handle: DeviceHandle,
+    measurements: broadcast::Sender<Measurement>,
+}
+
+/// Implementation of the `Meter` service over one or more sampled devices
+#[derive(Default)]
+pub struct MeterService {
+    devices: Vec<Device>,
+}
+
+impl MeterService {
+    /// Create a service with no devices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the device sampled by `sampler`
+    ///
+    /// Reads the device identity once; clients address the device by its
+    /// serial number.
+    pub fn add_device(&mut self, sampler: &Sampler) -> crate::Result<()> {
+        let handle = sampler.device().clone();
+        let info = handle.call(NSRT::read_info)?;
+
+        self.devices.push(Device {
+            info,
+            handle,
+            measurements: sampler.broadcast(CHANNEL_CAPACITY),
+        });
+
+        Ok(())
+    }
+
+    /// Wrap the service for use with a tonic server
+    pub fn into_server(self) -> MeterServer<Self> {
+        MeterServer::new(self)
+    }
+
+    fn device(&self, serial_number: &str) -> Result<&Device, Status> {
+        let device = if serial_number.is_empty() {
+            self.devices.first()
+        } else {
+            self.devices
+                .iter()
+                .find(|d| d.info.serial_number == serial_number)
+        };
+
+        device.ok_or_else(|| Status::not_found(format!("no device '{serial_number}'")))
+    }
+}
+
+/// Serve `service` on `addr` until the task is cancelled
+pub async fn serve(addr: SocketAddr, service: MeterService) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(service.into_server())
+        .serve(addr)
+        .await
+}
+
+/// Serve `service` over TLS on `addr`, presenting `identity`, until the task
+/// is cancelled
+#[cfg(feature = "tls")]
+pub async fn serve_tls(
+    addr: SocketAddr,
+    identity: &crate::tls::TlsIdentity,
+    service: MeterService,
+) -> Result<(), tonic::transport::Error> {
+    let identity = tonic::transport::Identity::from_pem(identity.cert_pem(), identity.key_pem());
+    Server::builder()
+        .tls_config(tonic::transport::ServerTlsConfig::new().identity(identity))?
+        .add_service(service.into_server())
+        .serve(addr)
+        .await
+}
+
+type MeasurementStream = Pin<Box<dyn Stream<Item = Result<proto::Measurement, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Meter for MeterService {
+    async fn list_devices(
+        &self,
+        _request: Request<proto::ListDevicesRequest>,
+    ) -> Result<Response<proto::ListDevicesResponse>, Status> {
+        let devices = self.devices.iter().map(|d| (&d.info).into()).collect();
+        Ok(Response::new(proto::ListDevicesResponse { devices }))
+    }
+
+    async fn get_info(
+        &self,
+        request: Request<proto::DeviceRequest>,
+    ) -> Result<Response<proto::DeviceInfo>, Status> {
+        let device = self.device(&request.get_ref().serial_number)?;
+        let info = device
+            .handle
+            .call_async(NSRT::read_info)
+            .await
+            .map_err(status)?;
+        Ok(Response::new((&info).into()))
+    }
+
+    async fn configure(
+        &self,
+        request: Request<proto::ConfigureRequest>,
+    ) -> Result<Response<proto::Configuration>, Status> {
+        let request = request.into_inner();
+        let device = self.device(&request.serial_number)?;
+
+        let weighting = match request.weighting() {
+            proto::Weighting::Unspecified => None,
+            proto::Weighting::C => Some(Weighting::C),
+            proto::Weighting::A => Some(Weighting::A),
+            proto::Weighting::Z => Some(Weighting::Z),
+        };
+        let sampling_frequency = request
+            .sampling_frequency
+            .map(SamplingFrequency::try_from)
+            .transpose()
+            .map_err(|e| status(e.into()))?;
+        let time_constant = request.time_constant;
+
+        let config = device
+            .handle
+            .call_async(move |nsrt| {
+                let mut config = nsrt.read_config()?;
+                config.weighting = weighting.unwrap_or(config.weighting);
+                config.time_constant = time_constant.unwrap_or(config.time_constant);
+                config.sampling_frequency = sampling_frequency.unwrap_or(config.sampling_frequency);
+                nsrt.configure(&config)?;
+                nsrt.read_config()
+            })
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(config.into()))
+    }
+
+    type StreamMeasurementsStream = MeasurementStream;
+
+    async fn stream_measurements(
+        &self,
+        request: Request<proto::DeviceRequest>,
+    ) -> Result<Response<Self::StreamMeasurementsStream>, Status> {
+        let device = self.device(&request.get_ref().serial_number)?;
+
+        let stream = BroadcastStream::new(device.measurements.subscribe())
+            // Slow clients skip the measurements they lagged behind on
+            .filter_map(Result::ok)
+            .map(|measurement| Ok(measurement.into()));
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+fn status(error: NsrtError) -> Status {
+    match error {
+        NsrtError::InvalidParameter(_) => Status::invalid_argument(error.to_string()),
+        NsrtError::NoDevice | NsrtError::HandleClosed => Status::unavailable(error.to_string()),
+        NsrtError::Unsupported { .. } => Status::unimplemented(error.to_string()),
+        _ => Status::internal(error.to_string()),
+    }
+}
+
+impl From<&DeviceInfo> for proto::DeviceInfo {
+    fn from(info: &DeviceInfo) -> Self {
+        Self {
+            model: info.model.clone(),
+            serial_number: info.serial_number.clone(),
+            firmware_revision: info.firmware_revision.clone(),
+            user_id: info.user_id.clone(),
+            calibration_date: Some(timestamp(info.calibration_time())),
+            birth_date: Some(timestamp(info.birth_time())),
+        }
+    }
+}
+
+impl From<DeviceConfig> for proto::Configuration {
+    fn from(config: DeviceConfig) -> Self {
+        let weighting = match config.weighting {
+            Weighting::C => proto::Weighting::C,
+            Weighting::A => proto::Weighting::A,
+            Weighting::Z => proto::Weighting::Z,
+            _ => proto::Weighting::Unspecified,
+        };
+
+        Self {
+            weighting: weighting.into(),
+            time_constant: config.time_constant,
+            sampling_frequency: config.sampling_frequency.into(),
+        }
+    }
+}
+
+impl From<Measurement> for proto::Measurement {
+    fn from(measurement: Measurement) -> Self {
+        Self {
+            timestamp: Some(timestamp(measurement.timestamp)),
+            level: measurement.level,
+            leq: measurement.leq,
+            temperature: measurement.temperature.as_celsius(),
+        }
+    }
+}
+
+fn timestamp(time: SystemTime) -> prost_types::Timestamp {
+    time.into()
+}
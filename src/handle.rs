@@ -0,0 +1,195 @@
+use crate::{NSRT, NsrtError, Result};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread,
+};
+
+type Job = Box<dyn FnOnce(&mut NSRT) + Send>;
+
+/// Order in which a [`DeviceHandle`] runs queued operations
+///
+/// Queued operations of a higher priority run before any of a lower one,
+/// and operations of the same priority in the order they were submitted. An
+/// operation already running is never interrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Steady background work such as the [`Sampler`](crate::Sampler)'s
+    /// polls
+    Low,
+    /// Everything else
+    #[default]
+    Normal,
+    /// Work that must not wait behind the polls, such as health checks
+    High,
+}
+
+/// Cloneable handle sharing a device between threads
+///
+/// The device is owned by a dedicated thread that runs the submitted
+/// operations one at a time, so the protocol's strict command/response
+/// ordering is preserved no matter how many handles are in use. Operations
+/// wait in a queue ordered by [`Priority`], so configuration writes and
+/// health checks don't queue up behind the polls of a busy sampler. The
+/// thread exits, closing the port, once the last handle is dropped.
+#[derive(Clone)]
+pub struct DeviceHandle {
+    owner: Arc<Owner>,
+}
+
+/// Closes the queue when the last handle goes
+struct Owner {
+    queue: Arc<Queue>,
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Jobs by priority, lowest first
+    jobs: [VecDeque<Job>; 3],
+    closed: bool,
+}
+
+impl DeviceHandle {
+    /// Move the device onto its own thread and return a handle to it
+    pub fn spawn(mut nsrt: NSRT) -> Self {
+        let queue = Arc::new(Queue::default());
+
+        {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                let _close = Close(&queue);
+                while let Some(job) = queue.next() {
+                    job(&mut nsrt);
+                }
+            });
+        }
+
+        Self {
+            owner: Arc::new(Owner { queue }),
+        }
+    }
+
+    /// Run `f` against the device and wait for its result
+    pub fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut NSRT) -> Result<T> + Send + 'static,
+    {
+        self.call_with_priority(Priority::Normal, f)
+    }
+
+    /// Run `f` against the device at `priority` and wait for its result
+    pub fn call_with_priority<T, F>(&self, priority: Priority, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut NSRT) -> Result<T> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+
+        self.owner.queue.push(
+            priority,
+            Box::new(move |nsrt| {
+                let _ = tx.send(f(nsrt));
+            }),
+        )?;
+
+        rx.recv().map_err(|_| NsrtError::HandleClosed)?
+    }
+
+    /// Run `f` against the device without blocking the async runtime
+    #[cfg(feature = "tokio")]
+    pub async fn call_async<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut NSRT) -> Result<T> + Send + 'static,
+    {
+        self.call_async_with_priority(Priority::Normal, f).await
+    }
+
+    /// Run `f` against the device at `priority` without blocking the async
+    /// runtime
+    #[cfg(feature = "tokio")]
+    pub async fn call_async_with_priority<T, F>(&self, priority: Priority, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut NSRT) -> Result<T> + Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.owner.queue.push(
+            priority,
+            Box::new(move |nsrt| {
+                let _ = tx.send(f(nsrt));
+            }),
+        )?;
+
+        rx.await.map_err(|_| NsrtError::HandleClosed)?
+    }
+}
+
+impl From<NSRT> for DeviceHandle {
+    fn from(nsrt: NSRT) -> Self {
+        Self::spawn(nsrt)
+    }
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        lock(&self.queue.state).closed = true;
+        self.queue.ready.notify_one();
+    }
+}
+
+/// Closes the queue as the device thread exits, even by panicking, failing
+/// the jobs still queued
+struct Close<'a>(&'a Queue);
+
+impl Drop for Close<'_> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.0.state);
+        state.closed = true;
+        state.jobs = Default::default();
+    }
+}
+
+impl Queue {
+    fn push(&self, priority: Priority, job: Job) -> Result<()> {
+        let mut state = lock(&self.state);
+        if state.closed {
+            return Err(NsrtError::HandleClosed);
+        }
+        state.jobs[priority as usize].push_back(job);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// The next job to run, waiting for one, or `None` once the queue is
+    /// closed and drained
+    fn next(&self) -> Option<Job> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(job) = state.jobs.iter_mut().rev().find_map(VecDeque::pop_front) {
+                return Some(job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self
+                .ready
+                .wait(state)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
@@ -0,0 +1,104 @@
+//! HDF5 export of sessions
+//!
+//! [`export_session`] writes a file with one group per session, readable with
+//! `h5py`, MATLAB's `h5read` or any other HDF5 tool:
+//!
+//! | Path                   | Kind      | Type     | Contents                              |
+//! | ---------------------- | --------- | -------- | ------------------------------------- |
+//! | `/@schema_version`     | attribute | `u32`    | [`SCHEMA_VERSION`] of the layout      |
+//! | `/session/time`        | dataset   | `f64[n]` | seconds since the Unix epoch          |
+//! | `/session/level`       | dataset   | `f32[n]` | running level in dB                   |
+//! | `/session/leq`         | dataset   | `f32[n]` | LEQ in dB since the previous sample   |
+//! | `/session/temperature` | dataset   | `f32[n]` | temperature in °C                     |
+//! | `/session@started`     | attribute | `f64`    | session start, seconds since epoch    |
+//! | `/session@stopped`     | attribute | `f64`    | session end, if the session stopped   |
+//! | `/session@<field>`     | attribute | string   | each set [`Metadata`] field           |
+//!
+//! The meter reports broadband levels only, so there are no spectrum or audio
+//! datasets; tools that expect them should treat their absence as empty.
+
+use crate::{Metadata, NsrtError, Result, Session};
+use ::hdf5::{File, Group, types::VarLenUnicode};
+use std::{
+    io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the group holding the session
+pub const SESSION_GROUP: &str = "session";
+
+/// Version of the file layout, incremented when it changes incompatibly
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Write `session` to a new HDF5 file at `path`, replacing any existing file
+pub fn export_session(session: &Session, path: impl AsRef<Path>) -> Result<()> {
+    let file = File::create(path).map_err(hdf5_error)?;
+    file.new_attr::<u32>()
+        .create("schema_version")
+        .and_then(|attr| attr.write_scalar(&SCHEMA_VERSION))
+        .map_err(hdf5_error)?;
+    let group = file.create_group(SESSION_GROUP).map_err(hdf5_error)?;
+    write_session(&group, session).map_err(hdf5_error)?;
+    file.close().map_err(hdf5_error)
+}
+
+fn write_session(group: &Group, session: &Session) -> ::hdf5::Result<()> {
+    let measurements = &session.measurements;
+    let time: Vec<f64> = measurements.iter().map(|m| seconds(m.timestamp)).collect();
+    let level: Vec<f32> = measurements.iter().map(|m| m.level).collect();
+    let leq: Vec<f32> = measurements.iter().map(|m| m.leq).collect();
+    let temperature: Vec<f32> = measurements
+        .iter()
+        .map(|m| m.temperature.as_celsius())
+        .collect();
+
+    group
+        .new_dataset_builder()
+        .with_data(time.as_slice())
+        .create("time")?;
+    group
+        .new_dataset_builder()
+        .with_data(level.as_slice())
+        .create("level")?;
+    group
+        .new_dataset_builder()
+        .with_data(leq.as_slice())
+        .create("leq")?;
+    group
+        .new_dataset_builder()
+        .with_data(temperature.as_slice())
+        .create("temperature")?;
+
+    group
+        .new_attr::<f64>()
+        .create("started")?
+        .write_scalar(&seconds(session.started))?;
+    if let Some(stopped) = session.stopped {
+        group
+            .new_attr::<f64>()
+            .create("stopped")?
+            .write_scalar(&seconds(stopped))?;
+    }
+    write_metadata(group, &session.metadata)
+}
+
+fn write_metadata(group: &Group, metadata: &Metadata) -> ::hdf5::Result<()> {
+    for (name, value) in metadata.fields() {
+        let value = value.parse::<VarLenUnicode>().map_err(|e| e.to_string())?;
+        group
+            .new_attr::<VarLenUnicode>()
+            .create(name)?
+            .write_scalar(&value)?;
+    }
+    Ok(())
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+fn hdf5_error(error: ::hdf5::Error) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
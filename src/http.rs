@@ -0,0 +1,437 @@
+//! HTTP server exposing the device over a small web API
+//!
+//! Routes:
+//! - `GET /info`: device identity ([`DeviceInfo`])
+//! - `GET /measurement`: most recent [`Measurement`]
+//! - `GET /config`: current [`DeviceConfig`]
+//! - `PATCH /config`: change some or all settings, returns the new config
+//! - `GET /session`: the current or last recorded [`Session`]
+//! - `POST /session/start`, `POST /session/stop`: session control
+//! - `GET /events`: Server-Sent Events stream of measurements
+//!
+//! [`dashboard`] adds a web page at `/` showing a live chart of the stream,
+//! and `metrics`, with the `prometheus` feature, the driver metrics at
+//! `/metrics`.
+//!
+//! The API is open to anyone who can reach it. [`Auth::protect`] requires a
+//! bearer token or HTTP Basic credentials instead: `GET` routes, the stream
+//! and the dashboard need [`Permission::Read`], while `PATCH /config` and the
+//! session controls need [`Permission::Configure`]. Credentials travel in the
+//! clear over plain HTTP, so combine it with TLS beyond a trusted network.
+
+use crate::{
+    DeviceConfig, DeviceInfo, Measurement, NSRT, NsrtError, Sampler, SamplingFrequency, Session,
+    Weighting, session::Recording,
+};
+use axum::{
+    Json, Router,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio::{net::TcpListener, sync::broadcast};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+/// Number of measurements buffered per client before it starts missing some
+const CHANNEL_CAPACITY: usize = 64;
+
+/// The dashboard page, with inline styles and scripts
+const DASHBOARD: &str = include_str!("../assets/dashboard.html");
+
+#[derive(Clone)]
+struct AppState {
+    sampler: Arc<Sampler>,
+    measurements: broadcast::Sender<Measurement>,
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+#[derive(Default)]
+struct Sessions {
+    recording: Option<Recording>,
+    last: Option<Session>,
+}
+
+/// Build the router serving the device sampled by `sampler`
+///
+/// Nest or merge it into an existing application, or use [`serve`].
+pub fn router(sampler: Arc<Sampler>) -> Router {
+    let measurements = sampler.broadcast(CHANNEL_CAPACITY);
+
+    Router::new()
+        .route("/info", get(info))
+        .route("/measurement", get(measurement))
+        .route("/config", get(config).patch(configure))
+        .route("/session", get(session))
+        .route("/session/start", post(start_session))
+        .route("/session/stop", post(stop_session))
+        .route("/events", get(events))
+        .with_state(AppState {
+            sampler,
+            measurements,
+            sessions: Arc::default(),
+        })
+}
+
+/// Router serving a live dashboard page at `/`
+///
+/// The page is embedded in the binary and loads its data from the API routes
+/// relative to its own URL, so merge it into the [`router`] at the same level.
+pub fn dashboard() -> Router {
+    Router::new().route("/", get(|| async { Html(DASHBOARD) }))
+}
+
+/// Router serving the driver metrics recorded by `handle` at `/metrics`, in
+/// the Prometheus text format
+///
+/// See [`metrics::install_prometheus`](crate::metrics::install_prometheus).
+#[cfg(feature = "prometheus")]
+pub fn metrics(handle: metrics_exporter_prometheus::PrometheusHandle) -> Router {
+    Router::new().route(
+        "/metrics",
+        get(move || async move {
+            handle.run_upkeep();
+            (
+                [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                handle.render(),
+            )
+        }),
+    )
+}
+
+/// Serve the router on `listener` until the task is cancelled
+pub async fn serve(listener: TcpListener, sampler: Arc<Sampler>) -> std::io::Result<()> {
+    axum::serve(listener, router(sampler)).await
+}
+
+/// Serve the router over TLS on `listener`, presenting `identity`, until the
+/// task is cancelled
+#[cfg(feature = "tls")]
+pub async fn serve_tls(
+    listener: TcpListener,
+    identity: &crate::tls::TlsIdentity,
+    sampler: Arc<Sampler>,
+) -> crate::Result<()> {
+    let listener = crate::tls::TlsListener::new(listener, identity.server_config()?)?;
+    axum::serve(listener, router(sampler)).await?;
+    Ok(())
+}
+
+/// Access granted to an API client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Read device info, measurements, settings and sessions
+    Read,
+    /// Also change settings and start and stop sessions
+    Configure,
+}
+
+/// Credentials accepted by a protected router
+///
+/// Deserializes from e.g. TOML:
+///
+/// ```toml
+/// [[token]]
+/// token = "6f1c0a..."
+/// permission = "configure"
+///
+/// [[user]]
+/// name = "dashboard"
+/// password = "correct horse battery staple"
+/// permission = "read"
+/// ```
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    #[serde(rename = "token")]
+    tokens: Vec<TokenCredential>,
+    #[serde(rename = "user")]
+    users: Vec<UserCredential>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenCredential {
+    token: String,
+    permission: Permission,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserCredential {
+    name: String,
+    password: String,
+    permission: Permission,
+}
+
+impl Auth {
+    /// No credentials; add some with [`token`](Self::token) or
+    /// [`user`](Self::user)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `Authorization: Bearer <token>`, granting `permission`
+    #[must_use]
+    pub fn token(mut self, token: &str, permission: Permission) -> Self {
+        self.tokens.push(TokenCredential {
+            token: token.to_string(),
+            permission,
+        });
+        self
+    }
+
+    /// Accept HTTP Basic credentials of `name` and `password`, granting
+    /// `permission`
+    #[must_use]
+    pub fn user(mut self, name: &str, password: &str, permission: Permission) -> Self {
+        self.users.push(UserCredential {
+            name: name.to_string(),
+            password: password.to_string(),
+            permission,
+        });
+        self
+    }
+
+    /// Whether no credentials were added, so every request would be refused
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty() && self.users.is_empty()
+    }
+
+    /// Require credentials for every route of `router`
+    ///
+    /// Requests without valid credentials are answered with 401, those whose
+    /// credentials lack the permission a route needs with 403.
+    pub fn protect(self, router: Router) -> Router {
+        router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(self),
+            authorize,
+        ))
+    }
+
+    /// Permission granted by the `Authorization` header in `headers`
+    fn permission(&self, headers: &HeaderMap) -> Option<Permission> {
+        let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, credentials) = value.split_once(' ')?;
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            self.tokens
+                .iter()
+                .filter(|t| constant_time_eq(t.token.as_bytes(), credentials.as_bytes()))
+                .map(|t| t.permission)
+                .max()
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = BASE64.decode(credentials).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (name, password) = decoded.split_once(':')?;
+            self.users
+                .iter()
+                .filter(|u| {
+                    // Compare both, so the time taken doesn't reveal names
+                    let name = constant_time_eq(u.name.as_bytes(), name.as_bytes());
+                    let password = constant_time_eq(u.password.as_bytes(), password.as_bytes());
+                    name & password
+                })
+                .map(|u| u.permission)
+                .max()
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Auth")
+            .field("tokens", &self.tokens.len())
+            .field("users", &self.users.len())
+            .finish()
+    }
+}
+
+async fn authorize(State(auth): State<Arc<Auth>>, request: Request, next: Next) -> Response {
+    let required = match *request.method() {
+        Method::GET | Method::HEAD => Permission::Read,
+        _ => Permission::Configure,
+    };
+
+    match auth.permission(request.headers()) {
+        Some(granted) if granted >= required => next.run(request).await,
+        Some(_) => ApiError(
+            StatusCode::FORBIDDEN,
+            "Configure permission required".to_string(),
+        )
+        .into_response(),
+        None => {
+            let mut response = ApiError(
+                StatusCode::UNAUTHORIZED,
+                "Authentication required".to_string(),
+            )
+            .into_response();
+            let challenge = if auth.users.is_empty() {
+                "Bearer"
+            } else {
+                "Basic realm=\"nsrt\", charset=\"UTF-8\""
+            };
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(challenge),
+            );
+            response
+        }
+    }
+}
+
+/// Compare secrets in time depending only on their lengths
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Error returned by API handlers as a JSON body
+struct ApiError(StatusCode, String);
+
+impl From<NsrtError> for ApiError {
+    fn from(error: NsrtError) -> Self {
+        let status = match error {
+            NsrtError::InvalidParameter(_) => StatusCode::BAD_REQUEST,
+            NsrtError::NoDevice | NsrtError::HandleClosed => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.1 }));
+        (self.0, body).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+async fn info(State(state): State<AppState>) -> ApiResult<DeviceInfo> {
+    let info = state.sampler.device().call_async(NSRT::read_info).await?;
+    Ok(Json(info))
+}
+
+async fn measurement(State(state): State<AppState>) -> ApiResult<Measurement> {
+    state.sampler.latest().map(Json).ok_or_else(|| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No measurement taken yet".to_string(),
+        )
+    })
+}
+
+async fn config(State(state): State<AppState>) -> ApiResult<DeviceConfig> {
+    let config = state.sampler.device().call_async(NSRT::read_config).await?;
+    Ok(Json(config))
+}
+
+/// Settings to change; omitted fields keep their current value
+#[derive(Deserialize)]
+struct ConfigUpdate {
+    weighting: Option<Weighting>,
+    time_constant: Option<f32>,
+    sampling_frequency: Option<SamplingFrequency>,
+}
+
+async fn configure(
+    State(state): State<AppState>,
+    Json(update): Json<ConfigUpdate>,
+) -> ApiResult<DeviceConfig> {
+    let config = state
+        .sampler
+        .device()
+        .call_async(move |nsrt| {
+            let mut config = nsrt.read_config()?;
+            config.weighting = update.weighting.unwrap_or(config.weighting);
+            config.time_constant = update.time_constant.unwrap_or(config.time_constant);
+            config.sampling_frequency = update
+                .sampling_frequency
+                .unwrap_or(config.sampling_frequency);
+            nsrt.configure(&config)?;
+            nsrt.read_config()
+        })
+        .await?;
+    Ok(Json(config))
+}
+
+async fn session(State(state): State<AppState>) -> ApiResult<Session> {
+    let mut sessions = lock(&state.sessions);
+    let sessions = &mut *sessions;
+
+    let session = match &mut sessions.recording {
+        Some(recording) => Some(recording.snapshot().clone()),
+        None => sessions.last.clone(),
+    };
+
+    session
+        .map(Json)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "No session recorded".to_string()))
+}
+
+async fn start_session(State(state): State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let mut sessions = lock(&state.sessions);
+    if sessions.recording.is_some() {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            "A session is already being recorded".to_string(),
+        ));
+    }
+
+    let mut recording = Session::record(&state.sampler);
+    let session = recording.snapshot().clone();
+    sessions.recording = Some(recording);
+
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+async fn stop_session(State(state): State<AppState>) -> ApiResult<Session> {
+    let mut sessions = lock(&state.sessions);
+    let recording = sessions.recording.take().ok_or_else(|| {
+        ApiError(
+            StatusCode::CONFLICT,
+            "No session is being recorded".to_string(),
+        )
+    })?;
+
+    let session = recording.stop();
+    sessions.last = Some(session.clone());
+
+    Ok(Json(session))
+}
+
+async fn events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(state.measurements.subscribe())
+        // Slow clients skip the measurements they lagged behind on
+        .filter_map(std::result::Result::ok)
+        .filter_map(|measurement| {
+            Event::default()
+                .event("measurement")
+                .json_data(measurement)
+                .ok()
+        })
+        .map(Ok);
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,82 @@
+use crate::{NSRT, Result};
+use std::time::{Duration, SystemTime};
+
+/// Seconds between the device epoch (Jan 1 1904) and the Unix epoch
+pub(crate) const DEVICE_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+/// Identity and provenance information stored on the device
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    /// Model name
+    pub model: String,
+    /// Serial number
+    pub serial_number: String,
+    /// Firmware revision
+    pub firmware_revision: String,
+    /// User-defined identifier
+    pub user_id: String,
+    /// Date of last calibration, in seconds since Jan 1 1904 UTC
+    pub calibration_date: u64,
+    /// Date of manufacture, in seconds since Jan 1 1904 UTC
+    pub birth_date: u64,
+}
+
+impl DeviceInfo {
+    /// Date of last calibration as a system time
+    pub fn calibration_time(&self) -> SystemTime {
+        device_time(self.calibration_date)
+    }
+
+    /// Date of manufacture as a system time
+    pub fn birth_time(&self) -> SystemTime {
+        device_time(self.birth_date)
+    }
+
+    /// Date the next calibration is due, `interval` after the last one
+    pub fn calibration_due(&self, interval: Duration) -> SystemTime {
+        self.calibration_time() + interval
+    }
+}
+
+/// Convert a device timestamp to a system time
+///
+/// Timestamps before the Unix epoch are clamped to it.
+pub(crate) fn device_time(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds.saturating_sub(DEVICE_EPOCH_OFFSET))
+}
+
+impl NSRT {
+    /// Read all identity fields of the device
+    pub fn read_info(&mut self) -> Result<DeviceInfo> {
+        Ok(DeviceInfo {
+            model: self.read_model()?,
+            serial_number: self.read_serial_number()?,
+            firmware_revision: self.read_firmware_revision()?,
+            user_id: self.read_user_id()?,
+            calibration_date: self.read_calibration_date()?,
+            birth_date: self.read_birth_date()?,
+        })
+    }
+
+    /// All identity fields of the device, read on the first call only
+    ///
+    /// For code that tags every record with e.g. the serial number without a
+    /// string read per sample. [`NSRT::set_user_id`] keeps the cached user ID
+    /// up to date and [`NSRT::reconnect`] drops the cache, in case another
+    /// device now answers on the port. Anything else changing the identity,
+    /// such as a firmware update, calls for [`NSRT::invalidate_info`].
+    pub fn info_cached(&mut self) -> Result<&DeviceInfo> {
+        if self.info.is_none() {
+            self.info = Some(self.read_info()?);
+        }
+        Ok(self.info.as_ref().expect("info was just read"))
+    }
+
+    /// Forget the identity and capabilities cached from the device, so that
+    /// they are read again when next needed
+    pub fn invalidate_info(&mut self) {
+        self.info = None;
+        self.capabilities = None;
+    }
+}
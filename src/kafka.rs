@@ -0,0 +1,136 @@
+//! Kafka producer
+//!
+//! [`KafkaSink`] produces one message per measurement, keyed by the device
+//! serial number so all messages from a device land in the same partition and
+//! stay ordered. Any [`Metadata`] is sent as message headers named after its
+//! fields, and JSON payloads also include it under `metadata`.
+
+use crate::{DeviceInfo, Measurement, Metadata, NsrtError, Result, Sink, metadata::Tagged};
+use rdkafka::{
+    ClientConfig,
+    error::KafkaError,
+    message::{Header, OwnedHeaders},
+    producer::{BaseRecord, DefaultProducerContext, Producer, ThreadedProducer},
+    types::RDKafkaErrorCode,
+};
+use std::{io, thread, time::Duration};
+
+/// How long [`Sink::flush`] waits for outstanding messages to be delivered
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Serialization of message payloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// JSON object with the serde field names
+    #[default]
+    Json,
+    /// `nsrt.v1.Measurement` from `proto/nsrt.proto`
+    #[cfg(feature = "grpc")]
+    Protobuf,
+}
+
+/// Sink producing measurements to a Kafka topic
+pub struct KafkaSink {
+    producer: ThreadedProducer<DefaultProducerContext>,
+    topic: String,
+    key: String,
+    format: Format,
+    metadata: Metadata,
+    headers: OwnedHeaders,
+}
+
+impl KafkaSink {
+    /// Produce to `topic` on the cluster at `brokers`, keyed by the serial
+    /// number in `info`
+    ///
+    /// `brokers` is a comma-separated list of `host:port` bootstrap servers.
+    pub fn new(brokers: &str, topic: impl Into<String>, info: &DeviceInfo) -> Result<Self> {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", brokers);
+        Self::with_config(&config, topic, info)
+    }
+
+    /// Produce with a custom client configuration, e.g. for TLS or SASL
+    pub fn with_config(
+        config: &ClientConfig,
+        topic: impl Into<String>,
+        info: &DeviceInfo,
+    ) -> Result<Self> {
+        Ok(Self {
+            producer: config.create().map_err(kafka_error)?,
+            topic: topic.into(),
+            key: info.serial_number.clone(),
+            format: Format::default(),
+            metadata: Metadata::default(),
+            headers: OwnedHeaders::new(),
+        })
+    }
+
+    /// Set the payload serialization
+    #[must_use]
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn payload(&self, measurement: &Measurement) -> Result<Vec<u8>> {
+        match self.format {
+            Format::Json => {
+                let record = Tagged {
+                    measurement,
+                    metadata: &self.metadata,
+                };
+                Ok(serde_json::to_vec(&record).map_err(io::Error::from)?)
+            }
+            #[cfg(feature = "grpc")]
+            Format::Protobuf => Ok(prost::Message::encode_to_vec(
+                &crate::grpc::proto::Measurement::from(*measurement),
+            )),
+        }
+    }
+}
+
+impl Sink for KafkaSink {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.headers =
+            metadata
+                .fields()
+                .iter()
+                .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                    headers.insert(Header {
+                        key,
+                        value: Some(value),
+                    })
+                });
+        self.metadata = metadata.clone();
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let payload = self.payload(measurement)?;
+        let mut record = BaseRecord::to(&self.topic)
+            .key(&self.key)
+            .payload(&payload)
+            .headers(self.headers.clone());
+
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                // The producer thread drains the queue in the background
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), r)) => {
+                    record = r;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err((e, _)) => return Err(kafka_error(e)),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.producer.flush(FLUSH_TIMEOUT).map_err(kafka_error)
+    }
+}
+
+fn kafka_error(error: KafkaError) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
@@ -1,14 +1,181 @@
-use serialport::SerialPort;
+use protocol::{ACK, Command};
 use std::{
-    ffi::CStr,
-    io::{Read, Write},
+    io::{IoSlice, Write},
     thread,
     time::Duration,
 };
 use thiserror::Error;
 
-const VID: u16 = 2649;
-const PID: u16 = 323;
+mod alarm;
+mod background;
+#[cfg(feature = "binlog")]
+pub mod binlog;
+#[cfg(feature = "broker")]
+pub mod broker;
+mod calibration;
+mod capture;
+#[cfg(feature = "plotters")]
+pub mod chart;
+mod clock;
+mod compare;
+mod compensation;
+mod config;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+mod duty_cycle;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+mod fan_out;
+mod fast_poll;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod firmware;
+mod forward;
+#[cfg(feature = "geojson")]
+pub mod geojson;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod handle;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+#[cfg(feature = "http")]
+pub mod http;
+mod info;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+mod manager;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+mod measurement;
+mod metadata;
+mod meter;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+mod monitor;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "node")]
+pub mod node;
+#[cfg(feature = "opcua")]
+pub mod opcua;
+#[cfg(feature = "osc")]
+pub mod osc;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+mod port;
+#[cfg(feature = "python")]
+pub mod python;
+mod quality;
+mod range;
+#[cfg(feature = "registry")]
+pub mod registry;
+mod replay;
+#[cfg(feature = "report")]
+pub mod report;
+mod resample;
+mod resume;
+mod room_noise;
+#[cfg(feature = "rpi")]
+pub mod rpi;
+mod sampler;
+#[cfg(feature = "schedule")]
+pub mod schedule;
+mod self_test;
+mod serializer;
+mod session;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "sim")]
+pub mod sim;
+mod sink;
+#[cfg(feature = "snmp")]
+pub mod snmp;
+mod sound_power;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(any(feature = "report", feature = "sqlite"))]
+mod stats;
+mod strict;
+mod subscription;
+mod temperature;
+mod threshold;
+#[cfg(feature = "tls")]
+pub mod tls;
+mod transport;
+mod watchdog;
+#[cfg(feature = "web")]
+pub mod web;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+pub use alarm::{Alarm, AlarmLevel, AlarmMonitor, AlarmState, AlarmTransition};
+pub use background::{BackgroundCorrected, BackgroundCorrection};
+pub use calibration::{Calibration, Calibrator};
+pub use capture::RecordingTransport;
+#[cfg(target_os = "linux")]
+pub use clock::KernelClock;
+pub use clock::{ClockStatus, SystemClock, TimeSource};
+pub use compare::{Comparison, Divergence, PairComparison};
+pub use compensation::{Compensated, TemperatureCompensation};
+pub use config::DeviceConfig;
+pub use duty_cycle::DutyCycle;
+pub use fan_out::{Backpressure, FanOut, QueuedSink};
+pub use fast_poll::FastPoll;
+pub use firmware::{Capabilities, FirmwareVersion};
+pub use forward::{DropPolicy, StoreAndForward};
+pub use handle::{DeviceHandle, Priority};
+pub use info::DeviceInfo;
+pub use manager::{DeviceEvent, DeviceManager, ManagedDevice};
+pub use measurement::Measurement;
+pub use metadata::{Metadata, Position};
+pub use meter::SoundLevelMeter;
+/// The wire protocol, from the `no_std` `nsrt-protocol` crate
+pub use nsrt_protocol as protocol;
+pub use nsrt_protocol::{SamplingFrequency, Weighting};
+pub use port::{FlowControl, PortSettings};
+pub use quality::Quality;
+pub use range::MeasurementRange;
+pub use replay::{Pace, replay};
+pub use resample::{Interpolation, Resampler};
+pub use room_noise::{NcRating, OCTAVE_BANDS, OctaveBands, RcQuality, RcRating};
+pub use sampler::{Sampler, SamplerEvent};
+pub use self_test::{Check, CheckStatus, SelfTest};
+#[cfg(feature = "json")]
+pub use serializer::JsonSerializer;
+#[cfg(feature = "grpc")]
+pub use serializer::ProtobufSerializer;
+pub use serializer::{
+    ByteStream, ByteTransport, InfluxLineSerializer, RecordSerializer, RecordSink,
+};
+pub use session::{Recording, Session};
+pub use sink::Sink;
+pub use sound_power::{MeasurementSurface, SoundPower, SoundPowerSurvey};
+pub use strict::StrictMode;
+pub use subscription::Subscription;
+pub use temperature::Temperature;
+pub use threshold::{Threshold, ThresholdEvent, ThresholdMonitor};
+pub use transport::{MockTransport, Transport};
+pub use watchdog::{Fault, Recovery, Watchdog};
+
+/// USB vendor ID of Convergence Instruments
+pub const USB_VID: u16 = 2649;
+/// USB product ID of the `NSRT_mk4`
+pub const USB_PID: u16 = 323;
+
+/// Environment variable listing extra ports to treat as devices
+const PORTS_ENV: &str = "NSRT_PORTS";
+
+/// Prefix of port paths reached over TCP instead of a serial port
+const TCP_PREFIX: &str = "tcp://";
+
+/// Read timeout of a port, as the device answers well within it
+const TIMEOUT: Duration = Duration::from_millis(1000);
 
 /// Error type for the `NSRT_mk4` driver
 #[derive(Error, Debug)]
@@ -28,6 +195,9 @@ pub enum NsrtError {
     #[error("Invalid response from device")]
     InvalidResponse,
 
+    #[error("Malformed response from device: {0}")]
+    Malformed(protocol::Malformed),
+
     #[error("Invalid parameter: {0}")]
     InvalidParameter(String),
 
@@ -36,74 +206,188 @@ pub enum NsrtError {
 
     #[error("Utf8 error: {0}")]
     Utf8Error(#[from] std::str::Utf8Error),
+
+    #[error("Device handle is closed")]
+    HandleClosed,
+
+    #[error("Log verification failed: {0}")]
+    VerificationFailed(String),
+
+    #[error("Broker error: {0}")]
+    BrokerError(String),
+
+    #[error("{feature} requires firmware {required} or later")]
+    Unsupported {
+        feature: &'static str,
+        required: FirmwareVersion,
+    },
+
+    #[error("Protocol violation: {0}")]
+    ProtocolViolation(String),
+
+    #[error(
+        "{format} schema version {version} is not supported; this version reads up to {supported}"
+    )]
+    UnsupportedSchema {
+        format: &'static str,
+        version: u32,
+        supported: u32,
+    },
 }
 
 /// Result type for the `NSRT_mk4` driver
 pub type Result<T> = std::result::Result<T, NsrtError>;
 
-/// Weighting functions supported by the `NSRT_mk4`
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Weighting {
-    /// C-weighting (dB-C)
-    C = 0,
-    /// A-weighting (dB-A)
-    A = 1,
-    /// Z-weighting (dB-Z) - flat frequency response
-    Z = 2,
+/// Broad class of an [`NsrtError`], for deciding whether to try again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The device didn't answer in time
+    Timeout,
+    /// The device refused a valid command, as it does while busy
+    Busy,
+    /// An answer out of step with the request, cured by a resync
+    Desync,
+    /// The device or its handle is gone
+    Disconnected,
+    /// A parameter outside what the device accepts
+    InvalidParameter,
+    /// A feature the firmware lacks
+    Unsupported,
+    /// Anything else
+    Other,
 }
 
-/// Sampling frequencies supported by the `NSRT_mk4`
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SamplingFrequency {
-    /// 32 kHz
-    Freq32kHz = 32000,
-    /// 48 kHz
-    Freq48kHz = 48000,
+impl ErrorKind {
+    /// Whether the same operation may succeed if tried again
+    pub fn is_retriable(self) -> bool {
+        matches!(self, Self::Timeout | Self::Busy | Self::Desync)
+    }
 }
 
-/// Command codes for the `NSRT_mk4` device
-#[derive(Debug, Clone, Copy)]
-#[repr(u32)]
-enum Command {
-    ReadLevel = 0x8000_0010,
-    ReadLEQ = 0x8000_0011,
-    ReadTemperature = 0x8000_0012,
-    ReadWeighting = 0x8000_0020,
-    ReadFS = 0x8000_0021,
-    ReadTau = 0x8000_0022,
-    ReadModel = 0x8000_0031,
-    ReadSN = 0x8000_0032,
-    ReadFWRev = 0x8000_0033,
-    ReadDOC = 0x8000_0034,
-    ReadDOB = 0x8000_0035,
-    ReadUserID = 0x8000_0036,
-    WriteWeighting = 0x0000_0020,
-    WriteFS = 0x0000_0021,
-    WriteTau = 0x0000_0022,
-    WriteUserID = 0x0000_0036,
+impl From<std::io::ErrorKind> for ErrorKind {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        use std::io::ErrorKind as Io;
+        match kind {
+            Io::TimedOut | Io::WouldBlock => Self::Timeout,
+            Io::Interrupted => Self::Busy,
+            Io::NotFound
+            | Io::BrokenPipe
+            | Io::ConnectionReset
+            | Io::ConnectionAborted
+            | Io::NotConnected
+            | Io::UnexpectedEof => Self::Disconnected,
+            Io::InvalidInput => Self::InvalidParameter,
+            _ => Self::Other,
+        }
+    }
 }
 
-/// Command packet structure
-#[derive(Debug)]
-struct CommandPacket {
-    command: u32,
-    address: u32,
-    count: u32,
+impl NsrtError {
+    /// Class of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::SerialError(e) => match e.kind() {
+                serialport::ErrorKind::NoDevice => ErrorKind::Disconnected,
+                serialport::ErrorKind::InvalidInput => ErrorKind::InvalidParameter,
+                serialport::ErrorKind::Io(kind) => kind.into(),
+                serialport::ErrorKind::Unknown => ErrorKind::Other,
+            },
+            Self::IoError(e) => e.kind().into(),
+            Self::NoDevice | Self::HandleClosed => ErrorKind::Disconnected,
+            Self::NoAcknowledge => ErrorKind::Busy,
+            Self::InvalidResponse
+            | Self::Malformed(_)
+            | Self::FromBytesUntilNulError(_)
+            | Self::Utf8Error(_)
+            | Self::ProtocolViolation(_) => ErrorKind::Desync,
+            Self::InvalidParameter(_) => ErrorKind::InvalidParameter,
+            Self::Unsupported { .. } => ErrorKind::Unsupported,
+            Self::VerificationFailed(_) | Self::BrokerError(_) | Self::UnsupportedSchema { .. } => {
+                ErrorKind::Other
+            }
+        }
+    }
+
+    /// Stable numeric code of this error, for bindings and for alerts
+    /// matching on logs
+    ///
+    /// Codes are never changed or reused; new variants get new ones.
+    ///
+    /// | Code | Variant                                                  |
+    /// |------|----------------------------------------------------------|
+    /// | 1    | [`SerialError`](Self::SerialError)                       |
+    /// | 2    | [`IoError`](Self::IoError)                               |
+    /// | 3    | [`NoDevice`](Self::NoDevice)                             |
+    /// | 4    | [`NoAcknowledge`](Self::NoAcknowledge)                   |
+    /// | 5    | [`InvalidResponse`](Self::InvalidResponse)               |
+    /// | 6    | [`Malformed`](Self::Malformed)                           |
+    /// | 7    | [`InvalidParameter`](Self::InvalidParameter)             |
+    /// | 8    | [`FromBytesUntilNulError`](Self::FromBytesUntilNulError) |
+    /// | 9    | [`Utf8Error`](Self::Utf8Error)                           |
+    /// | 10   | [`HandleClosed`](Self::HandleClosed)                     |
+    /// | 11   | [`VerificationFailed`](Self::VerificationFailed)         |
+    /// | 12   | [`BrokerError`](Self::BrokerError)                       |
+    /// | 13   | [`Unsupported`](Self::Unsupported)                       |
+    /// | 14   | [`ProtocolViolation`](Self::ProtocolViolation)           |
+    /// | 15   | [`UnsupportedSchema`](Self::UnsupportedSchema)           |
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::SerialError(_) => 1,
+            Self::IoError(_) => 2,
+            Self::NoDevice => 3,
+            Self::NoAcknowledge => 4,
+            Self::InvalidResponse => 5,
+            Self::Malformed(_) => 6,
+            Self::InvalidParameter(_) => 7,
+            Self::FromBytesUntilNulError(_) => 8,
+            Self::Utf8Error(_) => 9,
+            Self::HandleClosed => 10,
+            Self::VerificationFailed(_) => 11,
+            Self::BrokerError(_) => 12,
+            Self::Unsupported { .. } => 13,
+            Self::ProtocolViolation(_) => 14,
+            Self::UnsupportedSchema { .. } => 15,
+        }
+    }
+
+    /// Whether the failed operation may succeed if tried again, after a
+    /// [`NSRT::resync`] for a desync
+    pub fn is_retriable(&self) -> bool {
+        self.kind().is_retriable()
+    }
 }
 
-impl CommandPacket {
-    fn serialize(&self) -> Vec<u8> {
-        let mut result = Vec::with_capacity(12);
-        result.extend_from_slice(&self.command.to_le_bytes());
-        result.extend_from_slice(&self.address.to_le_bytes());
-        result.extend_from_slice(&self.count.to_le_bytes());
-        result
+impl From<protocol::Error> for NsrtError {
+    fn from(e: protocol::Error) -> Self {
+        match e {
+            protocol::Error::Malformed(malformed) => NsrtError::Malformed(malformed),
+            e => NsrtError::InvalidParameter(e.to_string()),
+        }
     }
 }
 
 /// The main driver for the `NSRT_mk4` device
 pub struct NSRT {
-    port: Box<dyn SerialPort>,
+    port: Box<dyn Transport>,
+    compensation: Option<TemperatureCompensation>,
+    calibration_offset: f32,
+    range: Option<MeasurementRange>,
+    /// Minimum time between the starts of consecutive commands
+    command_gap: Duration,
+    /// When the last command was sent, for pacing the next one
+    last_command: Option<std::time::Instant>,
+    /// Pause after every command
+    settle_delay: Duration,
+    capabilities: Option<Capabilities>,
+    /// Identity cached by [`NSRT::info_cached`]
+    info: Option<DeviceInfo>,
+    /// Port path and settings the device was opened with, for reconnecting
+    origin: Option<(String, PortSettings)>,
+    /// Settings last applied with [`NSRT::configure`]
+    config: Option<DeviceConfig>,
+    /// Strict checks of answers, see [`NSRT::set_strict_mode`]
+    strict: Option<StrictMode>,
 }
 
 impl NSRT {
@@ -123,110 +407,221 @@ impl NSRT {
     /// This method automatically finds and opens the first `NSRT_mk4` device
     /// connected to the system using the Convergence Instruments VID/PID.
     pub fn open() -> Result<Self> {
-        let ports = serialport::available_ports()?;
+        Self::open_with(&PortSettings::default())
+    }
 
-        for port_info in ports {
-            if let serialport::SerialPortType::UsbPort(usb_info) = &port_info.port_type
-                && usb_info.vid == VID
-                && usb_info.pid == PID
-            {
-                let port = serialport::new(&port_info.port_name, 9600)
-                    .timeout(Duration::from_millis(1000))
-                    .open()?;
+    /// Open the `NSRT_mk4` device on the serial port at `path`
+    ///
+    /// A path of the form `tcp://host:port` connects to a serial-over-TCP
+    /// server or a simulator instead.
+    pub fn open_port(path: &str) -> Result<Self> {
+        Self::open_port_with(path, &PortSettings::default())
+    }
 
-                return Ok(Self { port });
-            }
+    /// Talk to the device over `transport` instead of a serial port
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self {
+            port: Box::new(transport),
+            compensation: None,
+            calibration_offset: 0.0,
+            range: Some(MeasurementRange::default()),
+            command_gap: Duration::ZERO,
+            last_command: None,
+            settle_delay: Duration::ZERO,
+            capabilities: None,
+            info: None,
+            origin: None,
+            config: None,
+            strict: None,
         }
+    }
 
-        Err(NsrtError::NoDevice)
+    /// Record all further traffic with the device to a capture file
+    ///
+    /// See [`RecordingTransport`] for the format and
+    /// [`MockTransport::from_capture`] for playing it back.
+    pub fn record(self, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Ok(Self {
+            port: Box::new(RecordingTransport::create(self.port, path)?),
+            ..self
+        })
     }
 
-    /// Send a command to the device
-    fn send_command(&mut self, cmd: Command, address: u32, count: u32) -> Result<()> {
-        let packet = CommandPacket {
-            command: cmd as u32,
-            address,
-            count,
-        };
+    /// Paths of the serial ports with an `NSRT_mk4` device attached
+    ///
+    /// Ports listed in the comma-separated `NSRT_PORTS` environment variable
+    /// come first, so simulators and devices behind adapters with other USB
+    /// IDs are found too.
+    pub fn ports() -> Result<Vec<String>> {
+        let mut extra: Vec<String> = std::env::var(PORTS_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|port| !port.is_empty())
+            .map(str::to_string)
+            .collect();
+        let ports = serialport::available_ports()?;
 
-        let bytes = packet.serialize();
-        self.port.write_all(&bytes)?;
+        extra.extend(
+            ports
+                .into_iter()
+                .filter(|port_info| {
+                    matches!(
+                        &port_info.port_type,
+                        serialport::SerialPortType::UsbPort(usb_info)
+                            if usb_info.vid == USB_VID && usb_info.pid == USB_PID
+                    )
+                })
+                .map(|port_info| port_info.port_name),
+        );
+        Ok(extra)
+    }
 
-        Ok(())
+    /// Drop any partial exchange and check that the device answers
+    ///
+    /// A timed-out read can leave the rest of a response in the receive
+    /// buffer, which would otherwise be taken as the answer to the next
+    /// command.
+    pub fn resync(&mut self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::resync();
+        self.port.clear()?;
+        self.read_level().map(|_| ())
     }
 
     /// Send a command with data to the device
     fn send_command_with_data(&mut self, cmd: Command, address: u32, data: &[u8]) -> Result<()> {
-        self.send_command(
-            cmd,
-            address,
-            u32::try_from(data.len()).map_err(|_| {
-                NsrtError::InvalidParameter("Data too large for command".to_string())
-            })?,
-        )?;
-
-        self.port.write_all(data)?;
-
-        let mut ack = [0u8; 1];
-        self.port.read_exact(&mut ack)?;
-
-        if ack[0] != 0x06 {
+        let count = u32::try_from(data.len())
+            .map_err(|_| NsrtError::InvalidParameter("Data too large for command".to_string()))?;
+        let packet = cmd.packet(address, count);
+        self.pace();
+        let ack = self.exchange(Some(cmd), |nsrt| {
+            // One write for packet and data where the transport supports it
+            transport::write_all_vectored(
+                &mut nsrt.port,
+                &mut [IoSlice::new(&packet), IoSlice::new(data)],
+            )?;
+            let written = std::time::Instant::now();
+            let mut ack = [0u8; 1];
+            nsrt.read_answer(Some(cmd), &mut ack)?;
+            nsrt.check_ack_time(cmd, written.elapsed())?;
+            Ok(ack)
+        })?;
+        self.settle();
+
+        if ack[0] != ACK {
             return Err(NsrtError::NoAcknowledge);
         }
 
         Ok(())
     }
 
-    /// Send a command and read response data
-    fn send_command_and_read(&mut self, cmd: Command, address: u32, count: u32) -> Result<Vec<u8>> {
-        self.send_command(cmd, address, count)?;
-
-        let mut response = vec![0u8; count as usize];
-        self.port.read_exact(&mut response)?;
+    /// Send a command and read its `N`-byte response
+    fn send_command_and_read<const N: usize>(
+        &mut self,
+        cmd: Command,
+        address: u32,
+    ) -> Result<[u8; N]> {
+        debug_assert_eq!(N, cmd.length(), "response length of {cmd:?}");
+        self.pace();
+        let response = self.exchange(Some(cmd), |nsrt| {
+            nsrt.port.write_all(&cmd.packet(address, N as u32))?;
+            let mut response = [0; N];
+            nsrt.read_answer(Some(cmd), &mut response)?;
+            Ok(response)
+        })?;
+        self.settle();
 
         Ok(response)
     }
 
+    /// Run the round trip `f` of `cmd`, or of a batch for `None`, recording
+    /// its duration or failure in the driver metrics
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn exchange<T>(
+        &mut self,
+        cmd: Option<Command>,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = f(self);
+        #[cfg(feature = "metrics")]
+        crate::metrics::command(
+            cmd.map_or_else(|| "batch".to_string(), |cmd| format!("{cmd:?}")),
+            started.elapsed(),
+            result.as_ref().err(),
+        );
+        result
+    }
+
+    /// Read a text setting into `buf`, returning the text borrowed from it
+    fn read_text_into<'a>(
+        &mut self,
+        cmd: Command,
+        buf: &'a mut [u8; protocol::TEXT_LEN],
+    ) -> Result<&'a str> {
+        *buf = self.send_command_and_read(cmd, 0)?;
+        Ok(protocol::decode_text(buf)?)
+    }
+
+    /// Read several values with one write and one read
+    ///
+    /// The packets of the read `commands`, at most as many as there are
+    /// [`Command::ALL`], go out back to back and the device answers them in
+    /// order, so this takes a single round trip however many values are
+    /// read. The answers are read into `buf`, which must hold
+    /// [`protocol::answers_len`] bytes, and split into typed values from it.
+    pub fn read_many<'a, 'c>(
+        &mut self,
+        commands: &'c [Command],
+        buf: &'a mut [u8],
+    ) -> Result<protocol::Values<'a, 'c>> {
+        if commands.len() > Command::ALL.len() {
+            return Err(NsrtError::InvalidParameter(format!(
+                "At most {} commands can be read at once",
+                Command::ALL.len()
+            )));
+        }
+        let mut request = [0; Command::ALL.len() * protocol::PACKET_LEN];
+        let request = protocol::encode_requests(commands, &mut request)?;
+        let answers = buf
+            .get_mut(..protocol::answers_len(commands))
+            .ok_or(protocol::Error::BufferTooSmall)?;
+
+        self.pace();
+        self.exchange(None, |nsrt| {
+            nsrt.port.write_all(request)?;
+            nsrt.read_answer(None, answers)
+        })?;
+        self.settle();
+
+        Ok(protocol::Values::new(commands, answers))
+    }
+
     /// Read the current sound level in dB
     pub fn read_level(&mut self) -> Result<f32> {
-        let data = self.send_command_and_read(Command::ReadLevel, 0, 4)?;
-        if data.len() < 4 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        Ok(f32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+        let data: [u8; 4] = self.send_command_and_read(Command::ReadLevel, 0)?;
+        Ok(protocol::decode_f32(&data)? + self.calibration_offset)
     }
 
     /// Read the current LEQ (Equivalent Continuous Sound Level) in dB
     /// and restart integration for the next LEQ measurement
     pub fn read_leq(&mut self) -> Result<f32> {
-        let data = self.send_command_and_read(Command::ReadLEQ, 0, 4)?;
-        if data.len() < 4 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        Ok(f32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+        let data: [u8; 4] = self.send_command_and_read(Command::ReadLEQ, 0)?;
+        Ok(protocol::decode_f32(&data)? + self.calibration_offset)
     }
 
-    /// Read the current temperature in degrees Celsius
-    pub fn read_temperature(&mut self) -> Result<f32> {
-        let data = self.send_command_and_read(Command::ReadTemperature, 0, 4)?;
-        if data.len() < 4 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        Ok(f32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+    /// Read the current temperature
+    pub fn read_temperature(&mut self) -> Result<Temperature> {
+        let data: [u8; 4] = self.send_command_and_read(Command::ReadTemperature, 0)?;
+        Ok(Temperature::from_celsius(protocol::decode_f32(&data)?))
     }
 
     /// Read the current weighting curve
     pub fn read_weighting(&mut self) -> Result<Weighting> {
-        let data = self.send_command_and_read(Command::ReadWeighting, 0, 1)?;
-        if data.is_empty() {
-            return Err(NsrtError::InvalidResponse);
-        }
-        match data.first() {
-            Some(0) => Ok(Weighting::C),
-            Some(1) => Ok(Weighting::A),
-            Some(2) => Ok(Weighting::Z),
-            _ => Err(NsrtError::InvalidResponse),
-        }
+        let data: [u8; 1] = self.send_command_and_read(Command::ReadWeighting, 0)?;
+        Ok(protocol::decode_weighting(&data)?)
     }
 
     /// Set the weighting curve
@@ -234,7 +629,7 @@ impl NSRT {
     /// After setting the weighting, this automatically waits for the device to stabilize
     /// unless `skip_wait` is set to true (useful when changing multiple parameters).
     fn write_weighting(&mut self, weighting: Weighting, skip_wait: bool) -> Result<()> {
-        let data = [(weighting as u8)];
+        let data = [u8::from(weighting)];
         self.send_command_with_data(Command::WriteWeighting, 0, &data)?;
 
         if !skip_wait {
@@ -254,45 +649,49 @@ impl NSRT {
         Ok(self)
     }
 
+    /// Set the weighting curve and wait for the device to stabilize
+    pub fn set_weighting(&mut self, weighting: Weighting) -> Result<()> {
+        self.write_weighting(weighting, false)
+    }
+
     /// Read the current sampling frequency
     pub fn read_sampling_frequency(&mut self) -> Result<SamplingFrequency> {
-        let data = self.send_command_and_read(Command::ReadFS, 0, 2)?;
-        if data.len() < 2 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        let value = u16::from_le_bytes([data[0], data[1]]);
-        match value {
-            32000 => Ok(SamplingFrequency::Freq32kHz),
-            48000 => Ok(SamplingFrequency::Freq48kHz),
-            _ => Err(NsrtError::InvalidResponse),
-        }
+        let data: [u8; 2] = self.send_command_and_read(Command::ReadFS, 0)?;
+        Ok(protocol::decode_sampling_frequency(&data)?)
     }
 
     /// Set the sampling frequency
     ///
     /// After setting the sampling frequency, this automatically waits for the device to stabilize
     /// unless `skip_wait` is set to true (useful when changing multiple parameters).
-    fn write_sampling_frequency(&mut self, freq: SamplingFrequency) -> Result<()> {
-        let data = (freq as u16).to_le_bytes();
+    fn write_sampling_frequency(&mut self, freq: SamplingFrequency, skip_wait: bool) -> Result<()> {
+        let data = u16::from(freq).to_le_bytes();
         self.send_command_with_data(Command::WriteFS, 0, &data)?;
-        Self::wait_for_stabilization(self.read_time_constant()?);
+
+        if !skip_wait {
+            let tau = self.read_time_constant()?;
+            Self::wait_for_stabilization(tau);
+        }
+
         Ok(())
     }
 
     /// Set the sampling frequency using fluent API
     #[must_use = "This method returns the updated NSRT instance which should be used for further operations"]
     pub fn sampling_frequency(mut self, freq: SamplingFrequency) -> Result<Self> {
-        self.write_sampling_frequency(freq)?;
+        self.write_sampling_frequency(freq, false)?;
         Ok(self)
     }
 
+    /// Set the sampling frequency and wait for the device to stabilize
+    pub fn set_sampling_frequency(&mut self, freq: SamplingFrequency) -> Result<()> {
+        self.write_sampling_frequency(freq, false)
+    }
+
     /// Read the current time constant in seconds
     pub fn read_time_constant(&mut self) -> Result<f32> {
-        let data = self.send_command_and_read(Command::ReadTau, 0, 4)?;
-        if data.len() < 4 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        Ok(f32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+        let data: [u8; 4] = self.send_command_and_read(Command::ReadTau, 0)?;
+        Ok(protocol::decode_f32(&data)?)
     }
 
     /// Set the time constant in seconds
@@ -319,63 +718,88 @@ impl NSRT {
         Ok(self)
     }
 
+    /// Set the time constant in seconds and wait for the device to stabilize
+    pub fn set_time_constant(&mut self, tau: f32) -> Result<()> {
+        self.write_time_constant(tau, false)
+    }
+
     /// Read the model name
     pub fn read_model(&mut self) -> Result<String> {
-        let data = self.send_command_and_read(Command::ReadModel, 0, 32)?;
-        Ok(CStr::from_bytes_until_nul(&data)?.to_str()?.to_string())
+        let mut buf = [0; protocol::TEXT_LEN];
+        Ok(self.read_model_into(&mut buf)?.to_string())
+    }
+
+    /// Read the model name into `buf`, without allocating
+    pub fn read_model_into<'a>(
+        &mut self,
+        buf: &'a mut [u8; protocol::TEXT_LEN],
+    ) -> Result<&'a str> {
+        self.read_text_into(Command::ReadModel, buf)
     }
 
     /// Read the serial number
     pub fn read_serial_number(&mut self) -> Result<String> {
-        let data = self.send_command_and_read(Command::ReadSN, 0, 32)?;
-        Ok(CStr::from_bytes_until_nul(&data)?.to_str()?.to_string())
+        let mut buf = [0; protocol::TEXT_LEN];
+        Ok(self.read_serial_number_into(&mut buf)?.to_string())
+    }
+
+    /// Read the serial number into `buf`, without allocating
+    pub fn read_serial_number_into<'a>(
+        &mut self,
+        buf: &'a mut [u8; protocol::TEXT_LEN],
+    ) -> Result<&'a str> {
+        self.read_text_into(Command::ReadSN, buf)
     }
 
     /// Read the firmware revision
     pub fn read_firmware_revision(&mut self) -> Result<String> {
-        let data = self.send_command_and_read(Command::ReadFWRev, 0, 32)?;
-        Ok(CStr::from_bytes_until_nul(&data)?.to_str()?.to_string())
+        let mut buf = [0; protocol::TEXT_LEN];
+        Ok(self.read_firmware_revision_into(&mut buf)?.to_string())
+    }
+
+    /// Read the firmware revision into `buf`, without allocating
+    pub fn read_firmware_revision_into<'a>(
+        &mut self,
+        buf: &'a mut [u8; protocol::TEXT_LEN],
+    ) -> Result<&'a str> {
+        self.read_text_into(Command::ReadFWRev, buf)
     }
 
     /// Read the date of last calibration
     pub fn read_calibration_date(&mut self) -> Result<u64> {
-        let data = self.send_command_and_read(Command::ReadDOC, 0, 8)?;
-        if data.len() < 8 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        Ok(u64::from_le_bytes([
-            data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-        ]))
+        let data: [u8; 8] = self.send_command_and_read(Command::ReadDOC, 0)?;
+        Ok(protocol::decode_date(&data)?)
     }
 
     /// Read the date of birth (manufacturing date)
     pub fn read_birth_date(&mut self) -> Result<u64> {
-        let data = self.send_command_and_read(Command::ReadDOB, 0, 8)?;
-        if data.len() < 8 {
-            return Err(NsrtError::InvalidResponse);
-        }
-        Ok(u64::from_le_bytes([
-            data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-        ]))
+        let data: [u8; 8] = self.send_command_and_read(Command::ReadDOB, 0)?;
+        Ok(protocol::decode_date(&data)?)
     }
 
     /// Read the user ID
     pub fn read_user_id(&mut self) -> Result<String> {
-        let data = self.send_command_and_read(Command::ReadUserID, 0, 32)?;
-        Ok(CStr::from_bytes_until_nul(&data)?.to_str()?.to_string())
+        let mut buf = [0; protocol::TEXT_LEN];
+        Ok(self.read_user_id_into(&mut buf)?.to_string())
     }
 
-    /// Write the user ID
-    #[allow(dead_code)]
-    fn write_user_id(&mut self, user_id: &str) -> Result<()> {
-        if user_id.len() > 31 {
-            return Err(NsrtError::InvalidParameter("User ID too long".to_string()));
-        }
-
-        let mut data = user_id.as_bytes().to_vec();
-        data.push(0);
+    /// Read the user ID into `buf`, without allocating
+    pub fn read_user_id_into<'a>(
+        &mut self,
+        buf: &'a mut [u8; protocol::TEXT_LEN],
+    ) -> Result<&'a str> {
+        self.read_text_into(Command::ReadUserID, buf)
+    }
 
-        self.send_command_with_data(Command::WriteUserID, 0, &data)
+    /// Set the user ID, at most 31 bytes
+    pub fn set_user_id(&mut self, user_id: &str) -> Result<()> {
+        let mut buf = [0; protocol::TEXT_LEN];
+        let data = protocol::encode_text(user_id, &mut buf)?;
+        self.send_command_with_data(Command::WriteUserID, 0, data)?;
+        if let Some(info) = &mut self.info {
+            info.user_id = user_id.to_string();
+        }
+        Ok(())
     }
 
     /// Helper method to wait for stabilization after changing parameters
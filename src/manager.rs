@@ -0,0 +1,303 @@
+use crate::{DeviceHandle, Measurement, NSRT, NsrtError, Sampler, Subscription};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Time between scans for attached, detached and failed devices
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Change in the set of devices of a [`DeviceManager`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum DeviceEvent {
+    /// A device was found and its sampling started
+    Attached {
+        /// Serial number
+        serial: String,
+        /// Port it was found on
+        port: String,
+    },
+    /// A device went away
+    Detached {
+        /// Serial number
+        serial: String,
+    },
+    /// Sampling a device failed; it is reopened at the next scan
+    Error {
+        /// Serial number
+        serial: String,
+        /// The error that stopped sampling
+        error: Arc<NsrtError>,
+    },
+    /// A device that failed was reopened and samples again
+    Recovered {
+        /// Serial number
+        serial: String,
+        /// Port it was found on
+        port: String,
+    },
+}
+
+/// A device sampled by a [`DeviceManager`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedDevice {
+    /// Serial number
+    pub serial: String,
+    /// Alias from the device registry, if registered with one
+    pub alias: Option<String>,
+    /// Port the device was opened on
+    pub port: String,
+}
+
+/// Samples every attached device and keeps track of them coming and going
+///
+/// A background thread scans the ports listed by [`NSRT::ports`] every two
+/// seconds, opens the devices not seen yet and starts a [`Sampler`] for each.
+/// A device whose sampler stops is dropped: if it disconnected, it is
+/// reported [detached](DeviceEvent::Detached) and attaches again when it
+/// comes back; after any other error it is reopened at the next scan and
+/// reported [recovered](DeviceEvent::Recovered) once it samples again.
+///
+/// Devices are identified by serial number, or by alias with the `registry`
+/// feature, in which case the registry's settings and calibration offset are
+/// applied as each device is opened. Handles returned by
+/// [`DeviceManager::device`] keep the port open, and so keep a failed device
+/// from being reopened, until they are dropped.
+pub struct DeviceManager {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    running: AtomicBool,
+    interval: Duration,
+    #[cfg(feature = "registry")]
+    registry: crate::registry::Registry,
+    devices: Mutex<BTreeMap<String, Managed>>,
+    events: Mutex<Vec<Sender<DeviceEvent>>>,
+}
+
+struct Managed {
+    device: ManagedDevice,
+    sampler: Sampler,
+}
+
+impl DeviceManager {
+    /// Start managing all attached devices, sampling each every `interval`
+    pub fn start(interval: Duration) -> Self {
+        Self::with_shared(Shared {
+            running: AtomicBool::new(true),
+            interval,
+            #[cfg(feature = "registry")]
+            registry: crate::registry::Registry::default(),
+            devices: Mutex::new(BTreeMap::new()),
+            events: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Start managing all attached devices, with aliases, settings and
+    /// calibration offsets from `registry`
+    #[cfg(feature = "registry")]
+    pub fn start_with_registry(interval: Duration, registry: crate::registry::Registry) -> Self {
+        Self::with_shared(Shared {
+            running: AtomicBool::new(true),
+            interval,
+            registry,
+            devices: Mutex::new(BTreeMap::new()),
+            events: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn with_shared(shared: Shared) -> Self {
+        let shared = Arc::new(shared);
+        let thread = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared))
+        };
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Receive the events happening from now on
+    ///
+    /// Devices attached before the call are not reported; see
+    /// [`DeviceManager::devices`]. The receiver disconnects when the manager
+    /// is dropped.
+    pub fn events(&self) -> Receiver<DeviceEvent> {
+        let (tx, rx) = mpsc::channel();
+        lock(&self.shared.events).push(tx);
+        rx
+    }
+
+    /// The devices being sampled, by serial number
+    pub fn devices(&self) -> Vec<ManagedDevice> {
+        lock(&self.shared.devices)
+            .values()
+            .map(|managed| managed.device.clone())
+            .collect()
+    }
+
+    /// Handle to the device with serial number or alias `key`
+    pub fn device(&self, key: &str) -> Option<DeviceHandle> {
+        self.with_sampler(key, |sampler| sampler.device().clone())
+    }
+
+    /// Subscribe to the measurements of the device with serial number or
+    /// alias `key`, see [`Sampler::subscribe`]
+    ///
+    /// The subscription ends when the device is detached or fails.
+    pub fn subscribe(&self, key: &str) -> Option<Subscription> {
+        self.with_sampler(key, Sampler::subscribe)
+    }
+
+    /// The most recent measurement of the device with serial number or alias
+    /// `key`
+    pub fn latest(&self, key: &str) -> Option<Measurement> {
+        self.with_sampler(key, Sampler::latest).flatten()
+    }
+
+    fn with_sampler<T>(&self, key: &str, f: impl FnOnce(&Sampler) -> T) -> Option<T> {
+        let devices = lock(&self.shared.devices);
+        let managed = devices.get(key).or_else(|| {
+            devices
+                .values()
+                .find(|managed| managed.device.alias.as_deref() == Some(key))
+        })?;
+        Some(f(&managed.sampler))
+    }
+}
+
+impl Drop for DeviceManager {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+        lock(&self.shared.devices).clear();
+    }
+}
+
+fn run(shared: &Shared) {
+    // Devices whose sampling failed, to report as recovered when reopened
+    let mut failed = Vec::new();
+
+    while shared.running.load(Ordering::Relaxed) {
+        reap(shared, &mut failed);
+        scan(shared, &mut failed);
+        thread::park_timeout(SCAN_INTERVAL);
+    }
+}
+
+/// Drop the devices whose sampler stopped, reporting why
+fn reap(shared: &Shared, failed: &mut Vec<String>) {
+    let stopped: Vec<Managed> = {
+        let mut devices = lock(&shared.devices);
+        let serials: Vec<String> = devices
+            .iter()
+            .filter(|(_, managed)| !managed.sampler.is_running())
+            .map(|(serial, _)| serial.clone())
+            .collect();
+        serials
+            .iter()
+            .filter_map(|serial| devices.remove(serial))
+            .collect()
+    };
+
+    for managed in stopped {
+        let serial = managed.device.serial;
+        let event = match managed.sampler.stop() {
+            Err(e) if e.kind() != crate::ErrorKind::Disconnected => {
+                failed.push(serial.clone());
+                DeviceEvent::Error {
+                    serial,
+                    error: Arc::new(e),
+                }
+            }
+            _ => DeviceEvent::Detached { serial },
+        };
+        emit(shared, &event);
+    }
+}
+
+/// Open and start sampling the devices on ports not in use yet
+fn scan(shared: &Shared, failed: &mut Vec<String>) {
+    // A failed listing is retried at the next scan
+    let Ok(ports) = NSRT::ports() else {
+        return;
+    };
+    for port in ports {
+        if lock(&shared.devices)
+            .values()
+            .any(|managed| managed.device.port == port)
+        {
+            continue;
+        }
+        // Ports that can't be opened or don't answer may belong to another
+        // program, or hold a device still starting up
+        let Ok(mut nsrt) = NSRT::open_port(&port) else {
+            continue;
+        };
+        let Ok(serial) = nsrt.read_serial_number() else {
+            continue;
+        };
+        if lock(&shared.devices).contains_key(&serial) {
+            continue;
+        }
+
+        #[cfg(feature = "registry")]
+        let entry = shared.registry.get(&serial);
+        #[cfg(feature = "registry")]
+        if let Some(entry) = entry
+            && let Err(e) = entry.apply(&mut nsrt)
+        {
+            // Reported once, and retried at every scan
+            if !failed.contains(&serial) {
+                failed.push(serial.clone());
+                let error = Arc::new(e);
+                emit(shared, &DeviceEvent::Error { serial, error });
+            }
+            continue;
+        }
+        #[cfg(feature = "registry")]
+        let alias = entry.and_then(|entry| entry.alias.clone());
+        #[cfg(not(feature = "registry"))]
+        let alias = None;
+
+        let device = ManagedDevice {
+            serial: serial.clone(),
+            alias,
+            port: port.clone(),
+        };
+        let sampler = Sampler::start(nsrt, shared.interval);
+        lock(&shared.devices).insert(serial.clone(), Managed { device, sampler });
+
+        let event = if let Some(index) = failed.iter().position(|f| *f == serial) {
+            failed.swap_remove(index);
+            DeviceEvent::Recovered { serial, port }
+        } else {
+            DeviceEvent::Attached { serial, port }
+        };
+        emit(shared, &event);
+    }
+}
+
+/// Send `event` to every receiver still listening
+fn emit(shared: &Shared, event: &DeviceEvent) {
+    lock(&shared.events).retain(|tx| tx.send(event.clone()).is_ok());
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
@@ -0,0 +1,221 @@
+//! Zeroconf advertisement of meters served on the network
+//!
+//! [`Service::advertise`] announces an HTTP API or broker over multicast DNS
+//! as an instance of [`SERVICE_TYPE`], so dashboards on the same LAN can find
+//! meters without static configuration; [`discover`] is the other side. The
+//! TXT record of an instance carries:
+//!
+//! | Key       | Value                                      |
+//! | --------- | ------------------------------------------ |
+//! | `txtvers` | `1`                                        |
+//! | `serial`  | serial number of the device                |
+//! | `api`     | `http` for the HTTP API, `broker` for the broker over TCP |
+//!
+//! followed by any properties added with [`Service::property`], such as
+//! `device` for the device's alias and `tls=1` for a service requiring TLS.
+
+use crate::{NsrtError, Result};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::{
+    io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// DNS-SD service type meters are advertised as
+pub const SERVICE_TYPE: &str = "_nsrt._tcp.local.";
+
+/// How a meter is served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Api {
+    /// The HTTP API of `nsrt::http`
+    Http,
+    /// The line-delimited JSON protocol of `nsrt::broker` over TCP
+    Broker,
+}
+
+impl Api {
+    /// Value of the `api` TXT property
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Broker => "broker",
+        }
+    }
+}
+
+/// Service to advertise
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    serial: String,
+    api: Api,
+    port: u16,
+    instance: Option<String>,
+    address: Option<IpAddr>,
+    properties: Vec<(String, String)>,
+}
+
+impl Service {
+    /// Service for the device with serial number `serial` served as `api` on
+    /// `port`
+    pub fn new(serial: &str, api: Api, port: u16) -> Self {
+        Self {
+            serial: serial.to_string(),
+            api,
+            port,
+            instance: None,
+            address: None,
+            properties: Vec::new(),
+        }
+    }
+
+    /// Set the instance name shown to users, `NSRT <serial>` by default
+    #[must_use]
+    pub fn instance(mut self, name: &str) -> Self {
+        self.instance = Some(name.to_string());
+        self
+    }
+
+    /// Advertise only `address`, the one the service listens on, instead of
+    /// the addresses of all interfaces
+    ///
+    /// Unspecified addresses such as `0.0.0.0` keep the default.
+    #[must_use]
+    pub fn address(mut self, address: IpAddr) -> Self {
+        self.address = (!address.is_unspecified()).then_some(address);
+        self
+    }
+
+    /// Add a TXT property
+    #[must_use]
+    pub fn property(mut self, key: &str, value: &str) -> Self {
+        self.properties.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Start answering mDNS queries for the service until the returned
+    /// advertisement is dropped
+    pub fn advertise(&self) -> Result<Advertisement> {
+        let instance = self
+            .instance
+            .clone()
+            .unwrap_or_else(|| format!("NSRT {}", self.serial));
+        let host: String = self
+            .serial
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let host = format!("nsrt-{}.local.", host.to_ascii_lowercase());
+
+        let mut properties = vec![
+            ("txtvers".to_string(), "1".to_string()),
+            ("serial".to_string(), self.serial.clone()),
+            ("api".to_string(), self.api.as_str().to_string()),
+        ];
+        properties.extend(self.properties.iter().cloned());
+
+        let info = match self.address {
+            Some(address) => ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance,
+                &host,
+                address,
+                self.port,
+                &properties[..],
+            ),
+            None => ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance,
+                &host,
+                (),
+                self.port,
+                &properties[..],
+            )
+            .map(ServiceInfo::enable_addr_auto),
+        }
+        .map_err(mdns_error)?;
+
+        let daemon = ServiceDaemon::new().map_err(mdns_error)?;
+        let fullname = info.get_fullname().to_string();
+        daemon.register(info).map_err(mdns_error)?;
+        Ok(Advertisement { daemon, fullname })
+    }
+}
+
+/// Running advertisement of a [`Service`]
+///
+/// Dropping it sends a goodbye so browsers remove the instance at once.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Advertisement {
+    /// Full DNS-SD name of the instance, e.g.
+    /// `NSRT 2103A4F1._nsrt._tcp.local.`
+    pub fn fullname(&self) -> &str {
+        &self.fullname
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_timeout(Duration::from_secs(1));
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Meter found by [`discover`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discovered {
+    /// Full DNS-SD name of the instance
+    pub fullname: String,
+    /// Host name the service runs on
+    pub host: String,
+    /// Addresses of the host
+    pub addresses: Vec<IpAddr>,
+    /// Port the service listens on
+    pub port: u16,
+    /// Serial number of the device, if advertised
+    pub serial: Option<String>,
+    /// How the meter is served, `http` or `broker`, if advertised
+    pub api: Option<String>,
+}
+
+/// Browse for advertised meters for `timeout`
+///
+/// Instances are returned in the order they were resolved.
+pub fn discover(timeout: Duration) -> Result<Vec<Discovered>> {
+    let daemon = ServiceDaemon::new().map_err(mdns_error)?;
+    let events = daemon.browse(SERVICE_TYPE).map_err(mdns_error)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<Discovered> = Vec::new();
+    while let Ok(event) = events.recv_deadline(deadline) {
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+        addresses.sort();
+        let meter = Discovered {
+            fullname: info.get_fullname().to_string(),
+            host: info.get_hostname().to_string(),
+            addresses,
+            port: info.get_port(),
+            serial: info.get_property_val_str("serial").map(str::to_string),
+            api: info.get_property_val_str("api").map(str::to_string),
+        };
+        match found.iter_mut().find(|m| m.fullname == meter.fullname) {
+            Some(known) => *known = meter,
+            None => found.push(meter),
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+fn mdns_error(e: mdns_sd::Error) -> NsrtError {
+    NsrtError::IoError(io::Error::other(e))
+}
@@ -0,0 +1,197 @@
+use crate::{
+    ClockStatus, Compensated, NSRT, NsrtError, Quality, Result, SystemClock, Temperature,
+    TimeSource, fast_poll, protocol::Value,
+};
+use std::{
+    io,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A set of readings taken from the device at a single point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Measurement {
+    /// Time at which the readings were taken
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub timestamp: SystemTime,
+    /// Running sound level in dB
+    pub level: f32,
+    /// LEQ in dB since the previous LEQ read
+    pub leq: f32,
+    /// Temperature of the device
+    pub temperature: Temperature,
+    /// Host clock synchronization at `timestamp`, if the time source knows it
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub clock: Option<ClockStatus>,
+    /// Level and LEQ corrected for the microphone's temperature coefficient,
+    /// if [`NSRT::set_temperature_compensation`] is in use
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub compensated: Option<Compensated>,
+    /// Conditions that make the readings less trustworthy, flagged as they
+    /// are read and by the [`Sampler`](crate::Sampler)
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Quality::is_good")
+    )]
+    pub quality: Quality,
+}
+
+impl Measurement {
+    /// Size of the fixed-width binary encoding
+    pub(crate) const ENCODED_LEN: usize = 24;
+
+    /// Size of the encoding followed by the quality flags
+    pub(crate) const STORED_LEN: usize = Self::ENCODED_LEN + 1;
+
+    /// Whether the timestamp came from a clock known to be unsynchronized
+    pub fn is_unsynchronized(&self) -> bool {
+        self.clock.is_some_and(|clock| !clock.synchronized)
+    }
+
+    /// Encode as little-endian seconds and nanoseconds since the Unix epoch,
+    /// followed by level, LEQ and temperature
+    ///
+    /// The clock status, compensated values and quality are not encoded.
+    pub(crate) fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[0..8].copy_from_slice(&since_epoch.as_secs().to_le_bytes());
+        bytes[8..12].copy_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.level.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.leq.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.temperature.as_celsius().to_le_bytes());
+        bytes
+    }
+
+    /// Decode the encoding produced by [`Measurement::encode`]
+    ///
+    /// Fails on a timestamp beyond what [`SystemTime`] can hold, which no
+    /// encoder writes but a corrupted or crafted record may hold.
+    pub(crate) fn decode(bytes: &[u8; Self::ENCODED_LEN]) -> Result<Self> {
+        let field = |range: std::ops::Range<usize>| -> [u8; 4] {
+            bytes[range].try_into().expect("4-byte field")
+        };
+        let secs = u64::from_le_bytes(bytes[0..8].try_into().expect("8-byte field"));
+        let nanos = u32::from_le_bytes(field(8..12)).min(999_999_999);
+        let timestamp = UNIX_EPOCH
+            .checked_add(Duration::new(secs, nanos))
+            .ok_or_else(|| {
+                NsrtError::IoError(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Timestamp {secs} s after the epoch is out of range"),
+                ))
+            })?;
+
+        Ok(Self {
+            timestamp,
+            level: f32::from_le_bytes(field(12..16)),
+            leq: f32::from_le_bytes(field(16..20)),
+            temperature: Temperature::from_celsius(f32::from_le_bytes(field(20..24))),
+            clock: None,
+            compensated: None,
+            quality: Quality::GOOD,
+        })
+    }
+
+    /// Encode as by [`Measurement::encode`], followed by the quality bits
+    pub(crate) fn encode_stored(&self) -> [u8; Self::STORED_LEN] {
+        let mut bytes = [0; Self::STORED_LEN];
+        bytes[..Self::ENCODED_LEN].copy_from_slice(&self.encode());
+        bytes[Self::ENCODED_LEN] = self.quality.bits();
+        bytes
+    }
+
+    /// Decode the encoding produced by [`Measurement::encode_stored`]
+    pub(crate) fn decode_stored(bytes: &[u8; Self::STORED_LEN]) -> Result<Self> {
+        let (encoded, quality) = bytes.split_at(Self::ENCODED_LEN);
+        Ok(Self {
+            quality: Quality::from_bits(quality[0]),
+            ..Self::decode(encoded.try_into().expect("encoded measurement"))?
+        })
+    }
+}
+
+impl NSRT {
+    /// Read the level, LEQ and temperature as a single timestamped measurement
+    ///
+    /// Like [`NSRT::read_leq`], this restarts integration for the next LEQ.
+    pub fn read_measurement(&mut self) -> Result<Measurement> {
+        self.read_measurement_with(&SystemClock)
+    }
+
+    /// Read a measurement every `interval`, the first right away
+    ///
+    /// For synchronous code that wants a fixed number of readings, e.g.
+    /// `nsrt.iter_measurements(interval).take(60).collect::<Result<Vec<_>>>()`.
+    /// The iterator never ends; a failed read yields its error and the next
+    /// read is still attempted. Reads are scheduled on a fixed cadence, and a
+    /// read running late moves the schedule rather than reading in a burst to
+    /// catch up.
+    pub fn iter_measurements(
+        &mut self,
+        interval: Duration,
+    ) -> impl Iterator<Item = Result<Measurement>> + '_ {
+        let mut next = Instant::now();
+        std::iter::from_fn(move || {
+            let now = Instant::now();
+            if let Some(wait) = next.checked_duration_since(now) {
+                std::thread::sleep(wait);
+            } else {
+                next = now;
+            }
+            next += interval;
+            Some(self.read_measurement())
+        })
+    }
+
+    /// Read a measurement timestamped by `time_source`
+    ///
+    /// The three values are read in one round trip with [`NSRT::read_many`],
+    /// unless the [capabilities](NSRT::capabilities) already read from the
+    /// device say its firmware can't pipeline reads.
+    pub fn read_measurement_with(&mut self, time_source: &dyn TimeSource) -> Result<Measurement> {
+        let (timestamp, clock) = time_source.now();
+        let (level, leq, temperature) = if self.capabilities.is_none_or(|c| c.pipelined_reads) {
+            let mut buf = [0; fast_poll::ANSWER_LEN];
+            let mut values = self.read_many(&fast_poll::REQUESTS, &mut buf)?;
+            let mut next = || match values.next() {
+                Some(Ok(Value::F32(value))) => Ok(value),
+                Some(Err(e)) => Err(NsrtError::from(e)),
+                _ => Err(NsrtError::InvalidResponse),
+            };
+            let (level, leq, temperature) = (next()?, next()?, next()?);
+            (
+                level + self.calibration_offset,
+                leq + self.calibration_offset,
+                Temperature::from_celsius(temperature),
+            )
+        } else {
+            (
+                self.read_level()?,
+                self.read_leq()?,
+                self.read_temperature()?,
+            )
+        };
+
+        let measurement = self.compensate(Measurement {
+            timestamp,
+            level,
+            leq,
+            temperature,
+            clock,
+            compensated: None,
+            quality: Quality::of_clock(clock),
+        });
+        Ok(self.check_range(measurement))
+    }
+}
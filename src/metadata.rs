@@ -0,0 +1,79 @@
+/// Static information about a measurement setup
+///
+/// Set on a [`Sampler`](crate::Sampler) with
+/// [`Sampler::set_metadata`](crate::Sampler::set_metadata), it is carried by
+/// recorded sessions and passed to attached sinks, which include it in the
+/// records they emit where their format allows.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Metadata {
+    /// Name of the device, such as its alias in the device registry
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub device: Option<String>,
+    /// Name of the measurement site
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub site: Option<String>,
+    /// Geographic position of the microphone
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub position: Option<Position>,
+    /// Person responsible for the measurement
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub operator: Option<String>,
+    /// Microphone height above ground in metres
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub microphone_height: Option<f32>,
+    /// Free-form notes
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub notes: Option<String>,
+}
+
+impl Metadata {
+    /// Whether no field is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Set fields as `(name, value)` pairs, in a fixed order
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(device) = &self.device {
+            fields.push(("device", device.clone()));
+        }
+        if let Some(site) = &self.site {
+            fields.push(("site", site.clone()));
+        }
+        if let Some(position) = &self.position {
+            fields.push(("latitude", position.latitude.to_string()));
+            fields.push(("longitude", position.longitude.to_string()));
+        }
+        if let Some(operator) = &self.operator {
+            fields.push(("operator", operator.clone()));
+        }
+        if let Some(height) = self.microphone_height {
+            fields.push(("microphone_height", height.to_string()));
+        }
+        if let Some(notes) = &self.notes {
+            fields.push(("notes", notes.clone()));
+        }
+        fields
+    }
+}
+
+/// WGS 84 coordinates in decimal degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Measurement serialized together with its metadata
+#[cfg(any(feature = "json", feature = "kafka", feature = "nats"))]
+#[derive(serde::Serialize)]
+pub(crate) struct Tagged<'a> {
+    #[serde(flatten)]
+    pub measurement: &'a crate::Measurement,
+    #[serde(skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: &'a Metadata,
+}
@@ -0,0 +1,90 @@
+use crate::{DeviceConfig, DeviceHandle, DeviceInfo, Measurement, NSRT, Result, Temperature};
+
+/// Operations on a sound level meter, wherever it is attached
+///
+/// Implemented by the [`NSRT`] driver itself, by [`DeviceHandle`] for devices
+/// shared between threads, and by clients of remote devices, so code can be
+/// written once against any of them.
+pub trait SoundLevelMeter {
+    /// Read the current sound level in dB
+    fn read_level(&mut self) -> Result<f32>;
+
+    /// Read the LEQ in dB since the previous LEQ read
+    fn read_leq(&mut self) -> Result<f32>;
+
+    /// Read the temperature
+    fn read_temperature(&mut self) -> Result<Temperature>;
+
+    /// Read level, LEQ and temperature as one timestamped measurement
+    fn read_measurement(&mut self) -> Result<Measurement>;
+
+    /// Read all identity fields of the device
+    fn read_info(&mut self) -> Result<DeviceInfo>;
+
+    /// Read the current measurement settings
+    fn read_config(&mut self) -> Result<DeviceConfig>;
+
+    /// Apply measurement settings, writing only the ones that differ
+    fn configure(&mut self, config: &DeviceConfig) -> Result<()>;
+}
+
+impl SoundLevelMeter for NSRT {
+    fn read_level(&mut self) -> Result<f32> {
+        NSRT::read_level(self)
+    }
+
+    fn read_leq(&mut self) -> Result<f32> {
+        NSRT::read_leq(self)
+    }
+
+    fn read_temperature(&mut self) -> Result<Temperature> {
+        NSRT::read_temperature(self)
+    }
+
+    fn read_measurement(&mut self) -> Result<Measurement> {
+        NSRT::read_measurement(self)
+    }
+
+    fn read_info(&mut self) -> Result<DeviceInfo> {
+        NSRT::read_info(self)
+    }
+
+    fn read_config(&mut self) -> Result<DeviceConfig> {
+        NSRT::read_config(self)
+    }
+
+    fn configure(&mut self, config: &DeviceConfig) -> Result<()> {
+        NSRT::configure(self, config)
+    }
+}
+
+impl SoundLevelMeter for DeviceHandle {
+    fn read_level(&mut self) -> Result<f32> {
+        self.call(NSRT::read_level)
+    }
+
+    fn read_leq(&mut self) -> Result<f32> {
+        self.call(NSRT::read_leq)
+    }
+
+    fn read_temperature(&mut self) -> Result<Temperature> {
+        self.call(NSRT::read_temperature)
+    }
+
+    fn read_measurement(&mut self) -> Result<Measurement> {
+        self.call(NSRT::read_measurement)
+    }
+
+    fn read_info(&mut self) -> Result<DeviceInfo> {
+        self.call(NSRT::read_info)
+    }
+
+    fn read_config(&mut self) -> Result<DeviceConfig> {
+        self.call(NSRT::read_config)
+    }
+
+    fn configure(&mut self, config: &DeviceConfig) -> Result<()> {
+        let config = *config;
+        self.call(move |nsrt| nsrt.configure(&config))
+    }
+}
@@ -0,0 +1,111 @@
+//! Driver health metrics
+//!
+//! The driver reports how the link to the device is doing through the
+//! [`metrics`](https://docs.rs/metrics) facade, so a fleet of loggers shows
+//! a failing cable or hub as rising latency, errors and recoveries well
+//! before samples are lost. Nothing is recorded until an application
+//! installs a recorder; with the `prometheus` feature, [`install_prometheus`]
+//! installs one and `http::metrics` serves it for scraping.
+//!
+//! | Metric                          | Type      | Labels            | Meaning |
+//! | ------------------------------- | --------- | ----------------- | ------- |
+//! | `nsrt_command_duration_seconds` | histogram | `command`         | Time from sending a command to the end of its answer, pacing excluded |
+//! | `nsrt_command_errors_total`     | counter   | `command`, `kind` | Commands failed, by lowercase [`ErrorKind`](crate::ErrorKind) |
+//! | `nsrt_retries_total`            | counter   |                   | Reads retried by a [`Sampler`](crate::Sampler) |
+//! | `nsrt_resyncs_total`            | counter   |                   | Calls of [`NSRT::resync`](crate::NSRT::resync) |
+//! | `nsrt_reconnects_total`         | counter   |                   | Calls of [`NSRT::reconnect`](crate::NSRT::reconnect) |
+//! | `nsrt_sink_queue_depth`         | gauge     | `queue`           | Measurements waiting in a [`StoreAndForward`](crate::StoreAndForward) queue, by directory |
+//!
+//! Batched reads with [`NSRT::read_many`](crate::NSRT::read_many) are
+//! labelled with the command `batch`.
+
+use crate::NsrtError;
+use metrics::{
+    Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram,
+};
+use std::time::Duration;
+
+/// Histogram of command round trips in seconds
+pub const COMMAND_DURATION: &str = "nsrt_command_duration_seconds";
+/// Counter of failed commands
+pub const COMMAND_ERRORS: &str = "nsrt_command_errors_total";
+/// Counter of reads retried by a sampler
+pub const RETRIES: &str = "nsrt_retries_total";
+/// Counter of resyncs
+pub const RESYNCS: &str = "nsrt_resyncs_total";
+/// Counter of reconnects
+pub const RECONNECTS: &str = "nsrt_reconnects_total";
+/// Gauge of measurements waiting in a store-and-forward queue
+pub const SINK_QUEUE_DEPTH: &str = "nsrt_sink_queue_depth";
+
+/// Upper bounds of the latency histogram buckets in seconds
+///
+/// The device answers within a few milliseconds over USB, so the buckets
+/// are fine at the low end and reach up to the one second read timeout.
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0,
+];
+
+/// Register units and descriptions of the metrics with the installed
+/// recorder, for exporters showing them as help text
+pub fn describe() {
+    describe_histogram!(
+        COMMAND_DURATION,
+        Unit::Seconds,
+        "Time from sending a command to the end of its answer"
+    );
+    describe_counter!(COMMAND_ERRORS, "Commands failed, by error kind");
+    describe_counter!(RETRIES, "Reads retried by a sampler");
+    describe_counter!(RESYNCS, "Resyncs of the link to the device");
+    describe_counter!(RECONNECTS, "Reconnects of the port");
+    describe_gauge!(
+        SINK_QUEUE_DEPTH,
+        "Measurements waiting in a store-and-forward queue"
+    );
+}
+
+/// Install a Prometheus recorder as the global recorder and
+/// [`describe`] the metrics
+///
+/// Render the returned handle for each scrape, as `http::metrics` does.
+/// Fails if a recorder is already installed.
+#[cfg(feature = "prometheus")]
+pub fn install_prometheus() -> crate::Result<metrics_exporter_prometheus::PrometheusHandle> {
+    use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(COMMAND_DURATION.to_string()),
+            &LATENCY_BUCKETS,
+        )
+        .and_then(PrometheusBuilder::install_recorder)
+        .map_err(|e| NsrtError::IoError(std::io::Error::other(e)))?;
+    describe();
+    Ok(handle)
+}
+
+pub(crate) fn command(command: String, elapsed: Duration, error: Option<&NsrtError>) {
+    match error {
+        None => histogram!(COMMAND_DURATION, "command" => command).record(elapsed),
+        Some(e) => {
+            let kind = format!("{:?}", e.kind()).to_lowercase();
+            counter!(COMMAND_ERRORS, "command" => command, "kind" => kind).increment(1);
+        }
+    }
+}
+
+pub(crate) fn retry() {
+    counter!(RETRIES).increment(1);
+}
+
+pub(crate) fn resync() {
+    counter!(RESYNCS).increment(1);
+}
+
+pub(crate) fn reconnect() {
+    counter!(RECONNECTS).increment(1);
+}
+
+pub(crate) fn queue_depth(queue: String, depth: u64) {
+    gauge!(SINK_QUEUE_DEPTH, "queue" => queue).set(depth as f64);
+}
@@ -0,0 +1,248 @@
+//! Modbus TCP server exposing the device as a register map
+//!
+//! All registers are 16 bits wide. Any unit identifier is accepted.
+//!
+//! Input registers (function 0x04), updated on every sampler measurement:
+//!
+//! | Address | Value               | Type                   | Scaling                           |
+//! | ------- | ------------------- | ---------------------- | --------------------------------- |
+//! | 0       | Level               | `i16`                  | 0.01 dB                           |
+//! | 1       | LEQ                 | `i16`                  | 0.01 dB                           |
+//! | 2       | Temperature         | `i16`                  | 0.01 °C                           |
+//! | 3–4     | Measurement time    | `u32`, high word first | seconds since the Unix epoch      |
+//! | 5       | Measurement counter | `u16`                  | increments per measurement, wraps |
+//!
+//! Holding registers (function 0x03 to read, 0x06 or 0x10 to write):
+//!
+//! | Address | Value              | Type  | Scaling                   |
+//! | ------- | ------------------ | ----- | ------------------------- |
+//! | 0       | Weighting          | `u16` | 0 = C, 1 = A, 2 = Z       |
+//! | 1       | Time constant      | `u16` | 1 ms                      |
+//! | 2       | Sampling frequency | `u16` | 1 Hz (32000 or 48000)     |
+//!
+//! Reading input registers before the first measurement fails with
+//! `ServerDeviceBusy`. Writes to holding registers only return once the device
+//! has stabilized, which takes at least one second; configure client timeouts
+//! accordingly.
+
+use crate::{
+    DeviceConfig, DeviceHandle, Measurement, NSRT, NsrtError, Sampler, SamplingFrequency, Weighting,
+};
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+use tokio::{net::TcpListener, net::TcpStream, sync::broadcast};
+use tokio_modbus::{
+    ExceptionCode, Request, Response,
+    server::{
+        Service,
+        tcp::{Server, accept_tcp_connection},
+    },
+};
+
+/// Number of input registers
+const INPUT_REGISTERS: u16 = 6;
+
+/// Number of holding registers
+const HOLDING_REGISTERS: u16 = 3;
+
+#[derive(Default)]
+struct Inputs {
+    latest: Option<Measurement>,
+    counter: u16,
+}
+
+/// Modbus service answering register reads and writes for one device
+#[derive(Clone)]
+pub struct ModbusService {
+    device: DeviceHandle,
+    inputs: Arc<Mutex<Inputs>>,
+}
+
+impl ModbusService {
+    /// Create a service for the device sampled by `sampler`
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(sampler: &Sampler) -> Self {
+        let inputs = Arc::new(Mutex::new(Inputs::default()));
+
+        let mut rx = sampler.broadcast(1).subscribe();
+        let update = Arc::clone(&inputs);
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(measurement) => {
+                        let mut inputs = update.lock().expect("register state poisoned");
+                        inputs.latest = Some(measurement);
+                        inputs.counter = inputs.counter.wrapping_add(1);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        Self {
+            device: sampler.device().clone(),
+            inputs,
+        }
+    }
+
+    fn read_inputs(&self, address: u16, count: u16) -> Result<Vec<u16>, ExceptionCode> {
+        let range = register_range(address, count, INPUT_REGISTERS)?;
+        let inputs = self.inputs.lock().expect("register state poisoned");
+        let measurement = inputs.latest.ok_or(ExceptionCode::ServerDeviceBusy)?;
+
+        let seconds = measurement
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| u32::try_from(d.as_secs()).unwrap_or(u32::MAX));
+
+        let registers = [
+            centi(measurement.level),
+            centi(measurement.leq),
+            centi(measurement.temperature.as_celsius()),
+            (seconds >> 16) as u16,
+            seconds as u16,
+            inputs.counter,
+        ];
+        Ok(registers[range].to_vec())
+    }
+
+    async fn read_holding(&self, address: u16, count: u16) -> Result<Vec<u16>, ExceptionCode> {
+        let range = register_range(address, count, HOLDING_REGISTERS)?;
+        let config = self
+            .device
+            .call_async(NSRT::read_config)
+            .await
+            .map_err(exception)?;
+        Ok(holding_registers(&config)[range].to_vec())
+    }
+
+    async fn write_holding(&self, address: u16, values: Vec<u16>) -> Result<(), ExceptionCode> {
+        let count = u16::try_from(values.len()).map_err(|_| ExceptionCode::IllegalDataValue)?;
+        let range = register_range(address, count, HOLDING_REGISTERS)?;
+
+        self.device
+            .call_async(move |nsrt| {
+                let mut registers = holding_registers(&nsrt.read_config()?);
+                registers[range].copy_from_slice(&values);
+                let config = config_from_registers(registers)?;
+                nsrt.configure(&config)
+            })
+            .await
+            .map_err(exception)
+    }
+
+    async fn handle(self, request: Request<'static>) -> Result<Response, ExceptionCode> {
+        match request {
+            Request::ReadInputRegisters(address, count) => self
+                .read_inputs(address, count)
+                .map(Response::ReadInputRegisters),
+            Request::ReadHoldingRegisters(address, count) => self
+                .read_holding(address, count)
+                .await
+                .map(Response::ReadHoldingRegisters),
+            Request::WriteSingleRegister(address, value) => {
+                self.write_holding(address, vec![value]).await?;
+                Ok(Response::WriteSingleRegister(address, value))
+            }
+            Request::WriteMultipleRegisters(address, values) => {
+                let count =
+                    u16::try_from(values.len()).map_err(|_| ExceptionCode::IllegalDataValue)?;
+                self.write_holding(address, values.into_owned()).await?;
+                Ok(Response::WriteMultipleRegisters(address, count))
+            }
+            _ => Err(ExceptionCode::IllegalFunction),
+        }
+    }
+}
+
+impl Service for ModbusService {
+    type Request = Request<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, ExceptionCode>> + Send>>;
+
+    fn call(&self, request: Self::Request) -> Self::Future {
+        Box::pin(self.clone().handle(request))
+    }
+}
+
+/// Serve the register map for the device sampled by `sampler` on `listener`
+pub async fn serve(listener: TcpListener, sampler: &Sampler) -> io::Result<()> {
+    let service = ModbusService::new(sampler);
+    let new_service = |_addr: SocketAddr| Ok(Some(service.clone()));
+    let on_connected = |stream: TcpStream, addr: SocketAddr| {
+        let connection = accept_tcp_connection(stream, addr, new_service);
+        async move { connection }
+    };
+
+    // A failing connection only affects that client
+    Server::new(listener)
+        .serve(&on_connected, |_error| {})
+        .await
+}
+
+fn register_range(
+    address: u16,
+    count: u16,
+    size: u16,
+) -> Result<std::ops::Range<usize>, ExceptionCode> {
+    let end = address
+        .checked_add(count)
+        .filter(|&end| count > 0 && end <= size)
+        .ok_or(ExceptionCode::IllegalDataAddress)?;
+    Ok(usize::from(address)..usize::from(end))
+}
+
+/// Encode a value in hundredths as a signed register
+fn centi(value: f32) -> u16 {
+    (value * 100.0).round() as i16 as u16
+}
+
+fn holding_registers(config: &DeviceConfig) -> [u16; HOLDING_REGISTERS as usize] {
+    [
+        u8::from(config.weighting).into(),
+        (config.time_constant * 1000.0).round() as u16,
+        config.sampling_frequency.into(),
+    ]
+}
+
+fn config_from_registers(
+    registers: [u16; HOLDING_REGISTERS as usize],
+) -> crate::Result<DeviceConfig> {
+    let weighting = match registers[0] {
+        0 => Weighting::C,
+        1 => Weighting::A,
+        2 => Weighting::Z,
+        value => {
+            return Err(NsrtError::InvalidParameter(format!(
+                "Invalid weighting: {value}"
+            )));
+        }
+    };
+    if registers[1] == 0 {
+        return Err(NsrtError::InvalidParameter(
+            "Time constant must be positive".to_string(),
+        ));
+    }
+
+    Ok(DeviceConfig {
+        weighting,
+        time_constant: f32::from(registers[1]) / 1000.0,
+        sampling_frequency: SamplingFrequency::try_from(u32::from(registers[2]))?,
+    })
+}
+
+fn exception(error: NsrtError) -> ExceptionCode {
+    match error {
+        NsrtError::InvalidParameter(_) => ExceptionCode::IllegalDataValue,
+        _ => ExceptionCode::ServerDeviceFailure,
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{
+    Measurement, NSRT, Quality, Result,
+    resume::SleepDetector,
+    sampler::{QualityTracker, RETRIES},
+};
+use std::{
+    ops::ControlFlow,
+    thread,
+    time::{Duration, Instant},
+};
+
+impl NSRT {
+    /// Read a measurement every `interval` and hand it to `f` until it
+    /// returns [`ControlFlow::Break`]
+    ///
+    /// The smallest way to embed the meter in an application: the value `f`
+    /// breaks with is returned. Errors are handled like by the
+    /// [`Sampler`](crate::Sampler): a retriable one is retried on the next
+    /// tick after [`NSRT::resume`], up to 3 times in a row, and any other
+    /// error, or the last retried one, is returned. The device is resumed
+    /// when the host wakes from sleep, and measurements carry the same
+    /// [`Quality`] flags. Time spent in `f` counts towards the interval.
+    pub fn monitor<B>(
+        &mut self,
+        interval: Duration,
+        mut f: impl FnMut(Measurement) -> ControlFlow<B>,
+    ) -> Result<B> {
+        let mut next = Instant::now();
+        let mut failures = 0;
+        let mut sleep = SleepDetector::new();
+        let mut quality = QualityTracker::default();
+
+        loop {
+            if sleep.check().is_some() {
+                quality.slept();
+                // A failed resume shows in the next read
+                let _ = self.resume();
+            }
+
+            let polled = Instant::now();
+            match self.read_measurement() {
+                Ok(mut measurement) => {
+                    failures = 0;
+                    quality.assess(&mut measurement, polled, interval);
+                    if let ControlFlow::Break(value) = f(measurement) {
+                        return Ok(value);
+                    }
+                }
+                Err(e) if e.is_retriable() && failures < RETRIES => {
+                    failures += 1;
+                    quality.pending |= Quality::MISSING_SAMPLES | Quality::RECONNECTED;
+                    // A failed resume shows in the next read
+                    let _ = self.resume();
+                }
+                Err(e) => return Err(e),
+            }
+
+            next += interval;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else {
+                next = now;
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! NATS publisher
+//!
+//! [`NatsSink`] publishes each measurement as a JSON message to a subject,
+//! optionally through JetStream so messages are persisted and acknowledged by
+//! the server before the next one is sent. Any [`Metadata`] is included in
+//! every message under `metadata`.
+
+use crate::{Measurement, Metadata, NsrtError, Result, Sink, metadata::Tagged};
+use async_nats::{Client, ToServerAddrs, jetstream};
+use std::io;
+use tokio::runtime::Handle;
+
+/// Sink publishing measurements to a NATS subject
+pub struct NatsSink {
+    client: Client,
+    jetstream: Option<jetstream::Context>,
+    subject: String,
+    metadata: Metadata,
+    runtime: Handle,
+}
+
+impl NatsSink {
+    /// Connect to the NATS server at `addrs` and publish to `subject`
+    ///
+    /// Must be called from within a tokio runtime; the sink publishes on that
+    /// runtime, so it must not be written to from one of its worker threads.
+    /// [`Sampler::attach`](crate::Sampler::attach) satisfies this.
+    pub async fn connect(addrs: impl ToServerAddrs, subject: impl Into<String>) -> Result<Self> {
+        let client = async_nats::connect(addrs).await.map_err(nats_error)?;
+        Ok(Self::new(client, subject))
+    }
+
+    /// Publish to `subject` with an existing client
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(client: Client, subject: impl Into<String>) -> Self {
+        Self {
+            client,
+            jetstream: None,
+            subject: subject.into(),
+            metadata: Metadata::default(),
+            runtime: Handle::current(),
+        }
+    }
+
+    /// Publish through JetStream, persisting messages in `stream`
+    ///
+    /// The stream is created with the sink's subject if it doesn't exist yet.
+    /// Every write then waits for the server to acknowledge the message.
+    pub async fn jetstream(mut self, stream: &str) -> Result<Self> {
+        let context = jetstream::new(self.client.clone());
+        context
+            .get_or_create_stream(jetstream::stream::Config {
+                name: stream.to_string(),
+                subjects: vec![self.subject.clone()],
+                ..Default::default()
+            })
+            .await
+            .map_err(nats_error)?;
+
+        self.jetstream = Some(context);
+        Ok(self)
+    }
+
+    async fn publish(&self, payload: Vec<u8>) -> Result<()> {
+        let subject = self.subject.clone();
+        match &self.jetstream {
+            Some(context) => {
+                context
+                    .publish(subject, payload.into())
+                    .await
+                    .map_err(nats_error)?
+                    .await
+                    .map_err(nats_error)?;
+            }
+            None => self
+                .client
+                .publish(subject, payload.into())
+                .await
+                .map_err(nats_error)?,
+        }
+        Ok(())
+    }
+}
+
+impl Sink for NatsSink {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.metadata = metadata.clone();
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let record = Tagged {
+            measurement,
+            metadata: &self.metadata,
+        };
+        let payload = serde_json::to_vec(&record).map_err(io::Error::from)?;
+        self.runtime.block_on(self.publish(payload))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.runtime
+            .block_on(self.client.flush())
+            .map_err(nats_error)
+    }
+}
+
+fn nats_error(error: impl std::error::Error + Send + Sync + 'static) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
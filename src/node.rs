@@ -0,0 +1,311 @@
+//! Node.js bindings
+//!
+//! The `node` feature builds a Node-API addon with napi-rs, for dashboards
+//! and collection agents written in JavaScript. Every call returns a promise;
+//! the device is driven by its own thread through a [`DeviceHandle`], so the
+//! event loop never blocks on serial I/O. Build it with `npm run build`:
+//!
+//! ```js
+//! const { NSRT } = require("./nsrt.node");
+//!
+//! const meter = await NSRT.open();
+//! await meter.configure({ weighting: "A", timeConstant: 0.125 });
+//! for await (const m of meter.stream(500, 20)) {
+//!   console.log(new Date(m.timestamp), m.level);
+//! }
+//! ```
+//!
+//! Timestamps and dates are milliseconds since the Unix epoch, as taken by
+//! `new Date()`. Errors reject with an `Error` whose `code` is `InvalidArg`
+//! for invalid arguments and `GenericFailure` otherwise.
+
+use crate::{DeviceHandle, NSRT, NsrtError, SamplingFrequency, Weighting};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+fn error(e: NsrtError) -> Error {
+    let status = match e {
+        NsrtError::InvalidParameter(_) => Status::InvalidArg,
+        _ => Status::GenericFailure,
+    };
+    Error::new(status, e.to_string())
+}
+
+fn millis(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        * 1000.0
+}
+
+/// Readings taken at one point in time
+#[napi(object)]
+pub struct Measurement {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: f64,
+    /// Running sound level in dB
+    pub level: f64,
+    /// LEQ in dB since the previous measurement
+    pub leq: f64,
+    /// Temperature in °C
+    pub temperature: f64,
+}
+
+impl From<crate::Measurement> for Measurement {
+    fn from(m: crate::Measurement) -> Self {
+        Self {
+            timestamp: millis(m.timestamp),
+            level: m.level.into(),
+            leq: m.leq.into(),
+            temperature: m.temperature.as_celsius().into(),
+        }
+    }
+}
+
+/// Measurement settings; fields left out of `configure` are kept
+#[napi(object)]
+pub struct Config {
+    /// `"A"`, `"C"` or `"Z"`
+    pub weighting: Option<String>,
+    /// Time constant in seconds
+    pub time_constant: Option<f64>,
+    /// Sampling frequency in Hz, 32000 or 48000
+    pub sampling_frequency: Option<u32>,
+}
+
+/// Identity and provenance of the device
+#[napi(object)]
+pub struct Info {
+    pub model: String,
+    pub serial_number: String,
+    pub firmware_revision: String,
+    pub user_id: String,
+    /// Date of last calibration, in milliseconds since the Unix epoch
+    pub calibration_date: f64,
+    /// Date of manufacture, in milliseconds since the Unix epoch
+    pub birth_date: f64,
+}
+
+fn parse_weighting(weighting: &str) -> Result<Weighting> {
+    match weighting.to_ascii_uppercase().as_str() {
+        "A" => Ok(Weighting::A),
+        "C" => Ok(Weighting::C),
+        "Z" => Ok(Weighting::Z),
+        _ => Err(Error::new(
+            Status::InvalidArg,
+            format!("Unknown weighting {weighting:?}, expected A, C or Z"),
+        )),
+    }
+}
+
+/// An `NSRT_mk4` sound level meter
+#[napi(js_name = "NSRT")]
+pub struct Meter {
+    device: Mutex<Option<DeviceHandle>>,
+}
+
+impl Meter {
+    fn device(&self) -> Result<DeviceHandle> {
+        self.device
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .ok_or_else(|| error(NsrtError::HandleClosed))
+    }
+
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut NSRT) -> crate::Result<T> + Send + 'static,
+    {
+        self.device()?.call_async(f).await.map_err(error)
+    }
+}
+
+#[napi]
+impl Meter {
+    /// Open the meter on `port`, or the first one found
+    ///
+    /// Ports may also be `tcp://host:port`, such as a simulator.
+    #[napi]
+    pub async fn open(port: Option<String>) -> Result<Meter> {
+        let nsrt = tokio::task::spawn_blocking(move || match port {
+            Some(port) => NSRT::open_port(&port),
+            None => NSRT::open(),
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))?
+        .map_err(error)?;
+        Ok(Self {
+            device: Mutex::new(Some(DeviceHandle::spawn(nsrt))),
+        })
+    }
+
+    /// Ports with a meter attached
+    #[napi]
+    pub async fn ports() -> Result<Vec<String>> {
+        tokio::task::spawn_blocking(NSRT::ports)
+            .await
+            .map_err(|e| Error::from_reason(e.to_string()))?
+            .map_err(error)
+    }
+
+    /// Release the meter; the port closes once running streams end
+    #[napi]
+    pub fn close(&self) {
+        self.device.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+
+    /// Running sound level in dB
+    #[napi]
+    pub async fn read_level(&self) -> Result<f64> {
+        self.call(NSRT::read_level).await.map(f64::from)
+    }
+
+    /// LEQ in dB since the previous LEQ read, restarting integration
+    #[napi]
+    pub async fn read_leq(&self) -> Result<f64> {
+        self.call(NSRT::read_leq).await.map(f64::from)
+    }
+
+    /// Temperature in °C
+    #[napi]
+    pub async fn read_temperature(&self) -> Result<f64> {
+        self.call(NSRT::read_temperature)
+            .await
+            .map(|t| t.as_celsius().into())
+    }
+
+    /// Level, LEQ and temperature with a timestamp
+    #[napi]
+    pub async fn read_measurement(&self) -> Result<Measurement> {
+        self.call(NSRT::read_measurement)
+            .await
+            .map(Measurement::from)
+    }
+
+    /// Current measurement settings, with every field set
+    #[napi]
+    pub async fn read_config(&self) -> Result<Config> {
+        let config = self.call(NSRT::read_config).await?;
+        Ok(Config {
+            weighting: Some(format!("{:?}", config.weighting)),
+            time_constant: Some(config.time_constant.into()),
+            sampling_frequency: Some(config.sampling_frequency.into()),
+        })
+    }
+
+    /// Change the given settings, leaving the others as they are
+    ///
+    /// Only settings that differ are written, followed by a single wait for
+    /// the device to stabilize.
+    #[napi]
+    pub async fn configure(&self, config: Config) -> Result<()> {
+        let weighting = config
+            .weighting
+            .as_deref()
+            .map(parse_weighting)
+            .transpose()?;
+        let time_constant = config.time_constant.map(|tau| tau as f32);
+        let sampling_frequency = config
+            .sampling_frequency
+            .map(SamplingFrequency::try_from)
+            .transpose()
+            .map_err(|e| error(e.into()))?;
+        self.call(move |nsrt| {
+            let mut config = nsrt.read_config()?;
+            config.weighting = weighting.unwrap_or(config.weighting);
+            config.time_constant = time_constant.unwrap_or(config.time_constant);
+            config.sampling_frequency = sampling_frequency.unwrap_or(config.sampling_frequency);
+            nsrt.configure(&config)
+        })
+        .await
+    }
+
+    /// Model, serial number, firmware, user ID and dates
+    #[napi]
+    pub async fn read_info(&self) -> Result<Info> {
+        let info = self.call(NSRT::read_info).await?;
+        Ok(Info {
+            calibration_date: millis(info.calibration_time()),
+            birth_date: millis(info.birth_time()),
+            model: info.model,
+            serial_number: info.serial_number,
+            firmware_revision: info.firmware_revision,
+            user_id: info.user_id,
+        })
+    }
+
+    /// Set the user-defined identifier, at most 31 bytes
+    #[napi]
+    pub async fn set_user_id(&self, user_id: String) -> Result<()> {
+        self.call(move |nsrt| nsrt.set_user_id(&user_id)).await
+    }
+
+    /// Async iterator over measurements taken every `intervalMs`
+    ///
+    /// The stream ends after `count` measurements, or runs until the loop is
+    /// left. Measurements are taken as the iterator is advanced, so a slow
+    /// consumer gets fewer of them rather than a backlog.
+    #[napi]
+    pub fn stream(&self, interval_ms: f64, count: Option<u32>) -> Result<MeasurementStream> {
+        let interval = Duration::try_from_secs_f64(interval_ms / 1000.0).map_err(|_| {
+            Error::new(
+                Status::InvalidArg,
+                format!("Invalid interval {interval_ms}"),
+            )
+        })?;
+        Ok(MeasurementStream {
+            device: self.device()?,
+            interval,
+            next: Instant::now(),
+            remaining: count,
+        })
+    }
+}
+
+/// Measurements from [`NSRT.stream`](Meter::stream)
+#[napi(async_iterator)]
+pub struct MeasurementStream {
+    device: DeviceHandle,
+    interval: Duration,
+    next: Instant,
+    remaining: Option<u32>,
+}
+
+impl AsyncGenerator for MeasurementStream {
+    type Yield = Measurement;
+    type Next = ();
+    type Return = ();
+
+    fn next(
+        &mut self,
+        _value: Option<()>,
+    ) -> impl Future<Output = Result<Option<Measurement>>> + Send + 'static {
+        let done = self.remaining == Some(0);
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(1);
+        }
+        let deadline = self.next;
+        // Skip ticks missed by a slow consumer rather than bursting to catch up
+        self.next = deadline.max(Instant::now()) + self.interval;
+        let device = self.device.clone();
+
+        async move {
+            if done {
+                return Ok(None);
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+            device
+                .call_async(NSRT::read_measurement)
+                .await
+                .map(|m| Some(m.into()))
+                .map_err(error)
+        }
+    }
+}
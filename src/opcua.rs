@@ -0,0 +1,295 @@
+//! OPC UA server exposing the device as a node set
+//!
+//! Nodes live in the [`NAMESPACE_URI`] namespace, in an `NSRT` folder under
+//! `Objects`, addressed by string node IDs:
+//!
+//! | Node ID             | Type     | Access     | Value                               |
+//! | ------------------- | -------- | ---------- | ----------------------------------- |
+//! | `Level`             | `Float`  | read       | running level in dB                 |
+//! | `Leq`               | `Float`  | read       | LEQ in dB since the previous sample |
+//! | `Temperature`       | `Float`  | read       | temperature in °C                   |
+//! | `Weighting`         | `String` | read/write | `"A"`, `"C"` or `"Z"`               |
+//! | `TimeConstant`      | `Float`  | read/write | time constant in seconds            |
+//! | `SamplingFrequency` | `UInt32` | read/write | 32000 or 48000 Hz                   |
+//! | `Model`             | `String` | read       | model name                          |
+//! | `SerialNumber`      | `String` | read       | serial number                       |
+//! | `FirmwareRevision`  | `String` | read       | firmware revision                   |
+//! | `UserId`            | `String` | read       | user-defined identifier             |
+//!
+//! Measurement variables are updated on every sampler measurement, with the
+//! measurement time as source timestamp, so clients can subscribe to them.
+//! Writes to configuration variables are validated immediately and applied in
+//! the background; the variables reflect the new settings once the device has
+//! stabilized.
+
+use crate::{DeviceConfig, DeviceHandle, NsrtError, Result, Sampler, SamplingFrequency, Weighting};
+use opcua::{
+    nodes::Variable,
+    server::{
+        ServerBuilder, SubscriptionCache,
+        diagnostics::NamespaceMetadata,
+        node_manager::memory::{SimpleNodeManager, simple_node_manager},
+    },
+    types::{DataValue, DateTime, NodeId, StatusCode, Variant},
+};
+use std::{
+    io,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+
+/// Namespace URI of the device nodes
+pub const NAMESPACE_URI: &str = "urn:nsrt";
+
+/// Seconds between the OPC UA epoch (Jan 1 1601) and the Unix epoch
+const OPCUA_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Serve the device sampled by `sampler` on an anonymous, unsecured endpoint
+///
+/// Use [`serve_with`] to configure endpoints, security policies and
+/// certificates.
+pub async fn serve(sampler: &Sampler, host: &str, port: u16) -> Result<()> {
+    let builder = ServerBuilder::new_anonymous("nsrt").host(host).port(port);
+    serve_with(builder, sampler).await
+}
+
+/// Serve the device sampled by `sampler` with a custom server configuration
+pub async fn serve_with(builder: ServerBuilder, sampler: &Sampler) -> Result<()> {
+    let (server, handle) = builder
+        .with_node_manager(simple_node_manager(
+            NamespaceMetadata {
+                namespace_uri: NAMESPACE_URI.to_owned(),
+                ..Default::default()
+            },
+            "nsrt",
+        ))
+        .build()
+        .map_err(server_error)?;
+
+    let node_manager = handle
+        .node_managers()
+        .get_of_type::<SimpleNodeManager>()
+        .expect("nsrt node manager is registered");
+    let ns = handle
+        .get_namespace_index(NAMESPACE_URI)
+        .expect("nsrt namespace is registered");
+
+    let device = sampler.device().clone();
+    let (info, config) = device
+        .call_async(|nsrt| Ok((nsrt.read_info()?, nsrt.read_config()?)))
+        .await?;
+
+    let nodes = Nodes {
+        ns,
+        node_manager,
+        subscriptions: Arc::clone(handle.subscriptions()),
+    };
+    nodes.add(&info, &config);
+    nodes.add_write_callbacks(&device);
+    nodes.update_measurements(sampler.broadcast(1).subscribe());
+
+    server.run().await.map_err(server_error)
+}
+
+#[derive(Clone)]
+struct Nodes {
+    ns: u16,
+    node_manager: Arc<SimpleNodeManager>,
+    subscriptions: Arc<SubscriptionCache>,
+}
+
+impl Nodes {
+    fn id(&self, name: &str) -> NodeId {
+        NodeId::new(self.ns, name)
+    }
+
+    fn add(&self, info: &crate::DeviceInfo, config: &DeviceConfig) {
+        let folder = self.id("NSRT");
+        let variable =
+            |name: &str, value: Variant| Variable::new(&self.id(name), name, name, value);
+        let writable = |name: &str, value: Variant| {
+            let mut variable = variable(name, value);
+            variable.set_writable(true);
+            variable.set_user_access_level(variable.access_level());
+            variable
+        };
+
+        let mut address_space = self.node_manager.address_space().write();
+        address_space.add_folder(&folder, "NSRT", &info.model, &NodeId::objects_folder_id());
+        address_space.add_variables(
+            vec![
+                variable("Level", f32::NAN.into()),
+                variable("Leq", f32::NAN.into()),
+                variable("Temperature", f32::NAN.into()),
+                writable("Weighting", weighting_name(config.weighting).into()),
+                writable("TimeConstant", config.time_constant.into()),
+                writable(
+                    "SamplingFrequency",
+                    u32::from(config.sampling_frequency).into(),
+                ),
+                variable("Model", info.model.as_str().into()),
+                variable("SerialNumber", info.serial_number.as_str().into()),
+                variable("FirmwareRevision", info.firmware_revision.as_str().into()),
+                variable("UserId", info.user_id.as_str().into()),
+            ],
+            &folder,
+        );
+    }
+
+    fn add_write_callbacks(&self, device: &DeviceHandle) {
+        self.add_write_callback(device, "Weighting", |value, config| {
+            let Variant::String(name) = value else {
+                return Err(StatusCode::BadTypeMismatch);
+            };
+            config.weighting = match name.as_ref() {
+                "A" => Weighting::A,
+                "C" => Weighting::C,
+                "Z" => Weighting::Z,
+                _ => return Err(StatusCode::BadOutOfRange),
+            };
+            Ok(())
+        });
+        self.add_write_callback(device, "TimeConstant", |value, config| {
+            let Variant::Float(tau) = value else {
+                return Err(StatusCode::BadTypeMismatch);
+            };
+            if tau.is_nan() || tau <= 0.0 {
+                return Err(StatusCode::BadOutOfRange);
+            }
+            config.time_constant = tau;
+            Ok(())
+        });
+        self.add_write_callback(device, "SamplingFrequency", |value, config| {
+            let Variant::UInt32(hz) = value else {
+                return Err(StatusCode::BadTypeMismatch);
+            };
+            config.sampling_frequency =
+                SamplingFrequency::try_from(hz).map_err(|_| StatusCode::BadOutOfRange)?;
+            Ok(())
+        });
+    }
+
+    /// Validate writes to `name` with `apply` and change the device settings
+    fn add_write_callback<F>(&self, device: &DeviceHandle, name: &str, apply: F)
+    where
+        F: Fn(Variant, &mut DeviceConfig) -> std::result::Result<(), StatusCode>
+            + Send
+            + Sync
+            + Copy
+            + 'static,
+    {
+        let nodes = self.clone();
+        let device = device.clone();
+
+        self.node_manager
+            .inner()
+            .add_write_callback(self.id(name), move |value, _range| {
+                let Some(value) = value.value else {
+                    return StatusCode::BadNothingToDo;
+                };
+                // Validate against a default config so bad writes fail early
+                let mut probe = DeviceConfig {
+                    weighting: Weighting::A,
+                    time_constant: 1.0,
+                    sampling_frequency: SamplingFrequency::Freq48kHz,
+                };
+                if let Err(status) = apply(value.clone(), &mut probe) {
+                    return status;
+                }
+
+                let nodes = nodes.clone();
+                let device = device.clone();
+                tokio::spawn(async move {
+                    let config = device
+                        .call_async(move |nsrt| {
+                            let mut config = nsrt.read_config()?;
+                            // Validated above
+                            let _ = apply(value, &mut config);
+                            nsrt.configure(&config)?;
+                            nsrt.read_config()
+                        })
+                        .await;
+                    if let Ok(config) = config {
+                        nodes.update_config(&config);
+                    }
+                });
+
+                StatusCode::Good
+            });
+    }
+
+    fn update_config(&self, config: &DeviceConfig) {
+        let values = [
+            (
+                self.id("Weighting"),
+                DataValue::new_now(weighting_name(config.weighting)),
+            ),
+            (
+                self.id("TimeConstant"),
+                DataValue::new_now(config.time_constant),
+            ),
+            (
+                self.id("SamplingFrequency"),
+                DataValue::new_now(u32::from(config.sampling_frequency)),
+            ),
+        ];
+        self.set_values(&values);
+    }
+
+    fn update_measurements(&self, mut rx: broadcast::Receiver<crate::Measurement>) {
+        let nodes = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let measurement = match rx.recv().await {
+                    Ok(measurement) => measurement,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                let time = date_time(measurement.timestamp);
+                let values = [
+                    (
+                        nodes.id("Level"),
+                        DataValue::new_at(measurement.level, time),
+                    ),
+                    (nodes.id("Leq"), DataValue::new_at(measurement.leq, time)),
+                    (
+                        nodes.id("Temperature"),
+                        DataValue::new_at(measurement.temperature.as_celsius(), time),
+                    ),
+                ];
+                nodes.set_values(&values);
+            }
+        });
+    }
+
+    fn set_values(&self, values: &[(NodeId, DataValue)]) {
+        // The nodes are created before any update, so this cannot fail
+        let _ = self.node_manager.set_values(
+            &self.subscriptions,
+            values.iter().map(|(id, value)| (id, None, value.clone())),
+        );
+    }
+}
+
+fn weighting_name(weighting: Weighting) -> &'static str {
+    match weighting {
+        Weighting::A => "A",
+        Weighting::C => "C",
+        Weighting::Z => "Z",
+        _ => "Unknown",
+    }
+}
+
+/// Convert a system time to an OPC UA date time (100 ns ticks since 1601)
+fn date_time(time: SystemTime) -> DateTime {
+    let since_unix = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let ticks = (OPCUA_EPOCH_OFFSET + since_unix.as_secs() as i64) * 10_000_000
+        + i64::from(since_unix.subsec_nanos() / 100);
+    DateTime::from(ticks)
+}
+
+fn server_error(message: String) -> NsrtError {
+    NsrtError::IoError(io::Error::other(message))
+}
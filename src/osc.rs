@@ -0,0 +1,118 @@
+//! Open Sound Control output
+//!
+//! [`OscSink`] sends each measurement as a single OSC message over UDP, with
+//! the running level and LEQ in dB as two `float32` arguments:
+//!
+//! ```text
+//! /nsrt ,ff <level> <leq>
+//! ```
+
+use crate::{Measurement, NsrtError, Result, Sink};
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// Default OSC address pattern
+pub const DEFAULT_ADDRESS: &str = "/nsrt";
+
+/// Sink sending measurements as OSC messages
+#[derive(Debug)]
+pub struct OscSink {
+    socket: UdpSocket,
+    address: String,
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl OscSink {
+    /// Send messages to the OSC server at `target`
+    pub fn connect(target: impl ToSocketAddrs) -> Result<Self> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| NsrtError::InvalidParameter("No OSC target address".to_string()))?;
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+
+        Ok(Self {
+            socket,
+            address: DEFAULT_ADDRESS.to_string(),
+            interval: Duration::ZERO,
+            last_sent: None,
+        })
+    }
+
+    /// Set the OSC address pattern messages are sent to
+    ///
+    /// The address must start with `/`.
+    pub fn address(mut self, address: impl Into<String>) -> Result<Self> {
+        let address = address.into();
+        if !address.starts_with('/') {
+            return Err(NsrtError::InvalidParameter(format!(
+                "OSC address must start with '/': {address}"
+            )));
+        }
+        self.address = address;
+        Ok(self)
+    }
+
+    /// Send at most one message per `interval`, dropping measurements in between
+    #[must_use]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl Sink for OscSink {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let now = Instant::now();
+        if self
+            .last_sent
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return Ok(());
+        }
+
+        let packet = message(&self.address, &[measurement.level, measurement.leq]);
+        match self.socket.send(&packet) {
+            Ok(_) => {}
+            // Nothing is listening yet; OSC receivers come and go
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        self.last_sent = Some(now);
+        Ok(())
+    }
+}
+
+/// Encode an OSC message with `float32` arguments
+fn message(address: &str, args: &[f32]) -> Vec<u8> {
+    let mut packet = Vec::new();
+    write_string(&mut packet, address);
+
+    let tags: String = std::iter::once(',')
+        .chain(args.iter().map(|_| 'f'))
+        .collect();
+    write_string(&mut packet, &tags);
+
+    for arg in args {
+        packet.extend_from_slice(&arg.to_be_bytes());
+    }
+    packet
+}
+
+/// Write a null-terminated string padded to a multiple of four bytes
+fn write_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    packet.extend(std::iter::repeat_n(0, padding));
+}
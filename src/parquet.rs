@@ -0,0 +1,345 @@
+//! Parquet measurement logs
+//!
+//! [`ParquetWriter`] writes one row per measurement, for handing data to
+//! analysts using pandas, Polars, DuckDB, Spark or R's `arrow` package:
+//!
+//! | Column               | Type                         | Contents                               |
+//! | -------------------- | ---------------------------- | -------------------------------------- |
+//! | `timestamp`          | `INT64` `TIMESTAMP(NANOS)`   | time of the readings, UTC              |
+//! | `level`              | `FLOAT`                      | running level in dB                    |
+//! | `leq`                | `FLOAT`                      | LEQ in dB since the previous sample    |
+//! | `temperature`        | `FLOAT`                      | temperature in °C                      |
+//! | `clock_synchronized` | optional `BOOLEAN`           | whether the host clock was synchronized |
+//! | `clock_offset`       | optional `DOUBLE`            | clock offset in seconds                |
+//! | `clock_max_error`    | optional `DOUBLE`            | clock error bound in seconds           |
+//! | `quality`            | `BYTE_ARRAY` `STRING`        | [`Quality`] flags joined by `\|`, empty for good measurements |
+//!
+//! The clock columns are null when the clock status is unknown. Columns are
+//! Snappy compressed. [`Metadata`] is stored as key-value metadata of the
+//! file, under the names of [`Metadata::fields`], and so is the schema
+//! version, under `schema_version`. [`SCHEMA_VERSION`] is the only version so
+//! far; files from before the version was recorded are version 1, and files
+//! of later versions are refused with [`NsrtError::UnsupportedSchema`].
+
+use crate::{ClockStatus, Measurement, Metadata, NsrtError, Quality, Result, Sink, Temperature};
+use ::parquet::{
+    basic::Compression,
+    data_type::{BoolType, ByteArray, ByteArrayType, DataType, DoubleType, FloatType, Int64Type},
+    errors::ParquetError,
+    file::{
+        properties::WriterProperties,
+        reader::{FileReader, SerializedFileReader},
+        writer::{SerializedFileWriter, SerializedRowGroupWriter},
+    },
+    format::KeyValue,
+    record::{Row, RowAccessor, reader::RowIter},
+    schema::{parser::parse_message_type, types::TypePtr},
+};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Schema of every Parquet log
+pub const SCHEMA: &str = "
+message measurement {
+    required int64 timestamp (TIMESTAMP(NANOS, true));
+    required float level;
+    required float leq;
+    required float temperature;
+    optional boolean clock_synchronized;
+    optional double clock_offset;
+    optional double clock_max_error;
+    required binary quality (STRING);
+}
+";
+
+/// Schema version of the files written
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Key-value metadata key of the schema version
+const VERSION_KEY: &str = "schema_version";
+
+/// Measurements buffered before they are written as a row group
+const ROW_GROUP_ROWS: usize = 65_536;
+
+/// Sink writing measurements to a Parquet file
+///
+/// Measurements are buffered and written in row groups of 65536, or
+/// whatever has been buffered when the sink is flushed, so flush rarely. The
+/// file is only readable once it is [finished](ParquetWriter::finish), which
+/// also happens, ignoring errors, when the writer is dropped.
+pub struct ParquetWriter<W: Write + Send> {
+    writer: Option<SerializedFileWriter<W>>,
+    rows: Vec<Measurement>,
+    metadata: Metadata,
+}
+
+impl ParquetWriter<File> {
+    /// Create a new Parquet file at `path`, replacing any existing file
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::create(path)?)
+    }
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    /// Start a new Parquet log on `inner`
+    pub fn new(inner: W) -> Result<Self> {
+        let schema = Arc::new(parse_message_type(SCHEMA).map_err(parquet_error)?);
+        let properties = Arc::new(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+        );
+        Ok(Self {
+            writer: Some(
+                SerializedFileWriter::new(inner, schema, properties).map_err(parquet_error)?,
+            ),
+            rows: Vec::new(),
+            metadata: Metadata::default(),
+        })
+    }
+
+    /// Write the buffered measurements and the file footer, returning the
+    /// underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.close()?
+            .ok_or_else(|| NsrtError::IoError(io::Error::other("Parquet log already finished")))
+    }
+
+    fn close(&mut self) -> Result<Option<W>> {
+        self.write_row_group()?;
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(None);
+        };
+        writer.append_key_value_metadata(KeyValue::new(
+            VERSION_KEY.to_string(),
+            SCHEMA_VERSION.to_string(),
+        ));
+        for (name, value) in self.metadata.fields() {
+            writer.append_key_value_metadata(KeyValue::new(name.to_string(), value));
+        }
+        let mut inner = writer.into_inner().map_err(parquet_error)?;
+        inner.flush()?;
+        Ok(Some(inner))
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let clocks: Vec<ClockStatus> = rows.iter().filter_map(|m| m.clock).collect();
+        let clocked: Vec<i16> = rows.iter().map(|m| i16::from(m.clock.is_some())).collect();
+
+        let mut group = writer.next_row_group().map_err(parquet_error)?;
+        write_column::<Int64Type, W>(
+            &mut group,
+            &rows.iter().map(nanos).collect::<Vec<_>>(),
+            None,
+        )?;
+        write_column::<FloatType, W>(
+            &mut group,
+            &rows.iter().map(|m| m.level).collect::<Vec<_>>(),
+            None,
+        )?;
+        write_column::<FloatType, W>(
+            &mut group,
+            &rows.iter().map(|m| m.leq).collect::<Vec<_>>(),
+            None,
+        )?;
+        write_column::<FloatType, W>(
+            &mut group,
+            &rows
+                .iter()
+                .map(|m| m.temperature.as_celsius())
+                .collect::<Vec<_>>(),
+            None,
+        )?;
+        write_column::<BoolType, W>(
+            &mut group,
+            &clocks.iter().map(|c| c.synchronized).collect::<Vec<_>>(),
+            Some(&clocked),
+        )?;
+        write_column::<DoubleType, W>(
+            &mut group,
+            &clocks.iter().map(|c| c.offset).collect::<Vec<_>>(),
+            Some(&clocked),
+        )?;
+        write_column::<DoubleType, W>(
+            &mut group,
+            &clocks.iter().map(|c| c.max_error).collect::<Vec<_>>(),
+            Some(&clocked),
+        )?;
+        write_column::<ByteArrayType, W>(
+            &mut group,
+            &rows
+                .iter()
+                .map(|m| {
+                    if m.quality.is_good() {
+                        ByteArray::from("")
+                    } else {
+                        ByteArray::from(m.quality.to_string().into_bytes())
+                    }
+                })
+                .collect::<Vec<_>>(),
+            None,
+        )?;
+        group.close().map_err(parquet_error)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> Sink for ParquetWriter<W> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.metadata = metadata.clone();
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.rows.push(*measurement);
+        if self.rows.len() >= ROW_GROUP_ROWS {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_row_group()
+    }
+}
+
+impl<W: Write + Send> Drop for ParquetWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Iterator over the measurements in a Parquet log
+pub struct ParquetReader {
+    rows: RowIter<'static>,
+    version: u32,
+}
+
+impl ParquetReader {
+    /// Open the Parquet file at `path`, checking its schema
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = SerializedFileReader::new(File::open(path)?).map_err(parquet_error)?;
+        let version = match reader
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .into_iter()
+            .flatten()
+            .find(|entry| entry.key == VERSION_KEY)
+            .and_then(|entry| entry.value.as_deref())
+        {
+            Some(value) => value.parse().map_err(|_| {
+                NsrtError::IoError(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid schema version {value:?}"),
+                ))
+            })?,
+            None => 1,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(NsrtError::UnsupportedSchema {
+                format: "Parquet log",
+                version,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        let expected = parse_message_type(SCHEMA).map_err(parquet_error)?;
+        let names = |fields: &[TypePtr]| -> Vec<String> {
+            fields
+                .iter()
+                .map(|field| field.name().to_string())
+                .collect()
+        };
+        if names(reader.metadata().file_metadata().schema().get_fields())
+            != names(expected.get_fields())
+        {
+            return Err(NsrtError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a Parquet measurement log",
+            )));
+        }
+        Ok(Self {
+            rows: reader.into_iter(),
+            version,
+        })
+    }
+
+    /// Schema version the file was written with
+    pub fn schema_version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Iterator for ParquetReader {
+    type Item = Result<Measurement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.next()?;
+        Some(row.map_err(parquet_error).and_then(|row| parse(&row)))
+    }
+}
+
+fn parse(row: &Row) -> Result<Measurement> {
+    let nanos = u64::try_from(row.get_long(0).map_err(parquet_error)?).unwrap_or_default();
+    let clock = match row.get_bool(4) {
+        Ok(synchronized) => Some(ClockStatus {
+            synchronized,
+            offset: row.get_double(5).map_err(parquet_error)?,
+            max_error: row.get_double(6).map_err(parquet_error)?,
+        }),
+        Err(_) => None,
+    };
+    let quality: Quality = row.get_string(7).map_err(parquet_error)?.parse()?;
+    Ok(Measurement {
+        timestamp: UNIX_EPOCH + Duration::from_nanos(nanos),
+        level: row.get_float(1).map_err(parquet_error)?,
+        leq: row.get_float(2).map_err(parquet_error)?,
+        temperature: Temperature::from_celsius(row.get_float(3).map_err(parquet_error)?),
+        clock,
+        compensated: None,
+        quality,
+    })
+}
+
+/// Write `values` to the next column of `group`, with `definition_levels`
+/// for optional columns
+fn write_column<T: DataType, W: Write + Send>(
+    group: &mut SerializedRowGroupWriter<'_, W>,
+    values: &[T::T],
+    definition_levels: Option<&[i16]>,
+) -> Result<()> {
+    let mut column = group
+        .next_column()
+        .map_err(parquet_error)?
+        .ok_or_else(|| parquet_error(ParquetError::General("Missing column".to_string())))?;
+    column
+        .typed::<T>()
+        .write_batch(values, definition_levels, None)
+        .map_err(parquet_error)?;
+    column.close().map_err(parquet_error)
+}
+
+/// Timestamp of `measurement` in nanoseconds since the Unix epoch
+fn nanos(measurement: &Measurement) -> i64 {
+    let since_epoch = measurement
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    i64::try_from(since_epoch.as_nanos()).unwrap_or(i64::MAX)
+}
+
+fn parquet_error(e: ParquetError) -> NsrtError {
+    NsrtError::IoError(io::Error::other(e))
+}
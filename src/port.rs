@@ -0,0 +1,166 @@
+use crate::{NSRT, NsrtError, Result, TCP_PREFIX, TIMEOUT};
+use std::{
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+/// Minimum time between commands on a serial port unless set otherwise
+const DEFAULT_COMMAND_GAP: Duration = Duration::from_millis(1);
+
+/// Flow control on the serial line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum FlowControl {
+    /// None, as the device needs
+    #[default]
+    None,
+    /// XON/XOFF bytes
+    Software,
+    /// RTS/CTS signals
+    Hardware,
+}
+
+impl From<FlowControl> for serialport::FlowControl {
+    fn from(flow_control: FlowControl) -> Self {
+        match flow_control {
+            FlowControl::None => serialport::FlowControl::None,
+            FlowControl::Software => serialport::FlowControl::Software,
+            FlowControl::Hardware => serialport::FlowControl::Hardware,
+        }
+    }
+}
+
+/// Serial line settings for opening the device
+///
+/// The defaults suit the device on a direct USB connection. Some hubs and
+/// adapters only pass data to the device's CDC interface once DTR is
+/// asserted, which [`PortSettings::dtr`] does as the port opens. Apart from
+/// the command gap and settle delay, the settings don't apply to `tcp://`
+/// ports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct PortSettings {
+    /// Level to drive DTR to as the port opens, or the OS default if unset
+    pub dtr: Option<bool>,
+    /// Level to drive RTS to once the port is open, or the OS default if
+    /// unset
+    pub rts: Option<bool>,
+    /// Flow control
+    pub flow_control: FlowControl,
+    /// Minimum time between commands, see [`NSRT::set_command_gap`]
+    ///
+    /// 1 ms on serial ports and none on `tcp://` ports if unset.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")
+    )]
+    pub command_gap: Option<Duration>,
+    /// Pause after every command, see [`NSRT::set_settle_delay`]
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub settle_delay: Duration,
+}
+
+impl NSRT {
+    /// Open the first `NSRT_mk4` device found, like [`NSRT::open`], with
+    /// `settings` for its serial line
+    pub fn open_with(settings: &PortSettings) -> Result<Self> {
+        let port = Self::ports()?
+            .into_iter()
+            .next()
+            .ok_or(NsrtError::NoDevice)?;
+        Self::open_port_with(&port, settings)
+    }
+
+    /// Open the `NSRT_mk4` device at `path`, like [`NSRT::open_port`], with
+    /// `settings` for its serial line
+    pub fn open_port_with(path: &str, settings: &PortSettings) -> Result<Self> {
+        if let Some(addr) = path.strip_prefix(TCP_PREFIX) {
+            let stream = TcpStream::connect(addr)?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            let mut nsrt = Self::with_transport(stream);
+            nsrt.command_gap = settings.command_gap.unwrap_or_default();
+            nsrt.settle_delay = settings.settle_delay;
+            return Ok(nsrt.opened_from(path, settings));
+        }
+
+        let mut builder = serialport::new(path, 9600)
+            .timeout(TIMEOUT)
+            .flow_control(settings.flow_control.into());
+        if let Some(dtr) = settings.dtr {
+            builder = builder.dtr_on_open(dtr);
+        }
+        let mut port = builder.open()?;
+        if let Some(rts) = settings.rts {
+            port.write_request_to_send(rts)?;
+        }
+
+        let mut nsrt = Self::with_transport(port);
+        nsrt.command_gap = settings.command_gap.unwrap_or(DEFAULT_COMMAND_GAP);
+        nsrt.settle_delay = settings.settle_delay;
+        Ok(nsrt.opened_from(path, settings))
+    }
+
+    /// Leave at least `gap` between the starts of consecutive commands
+    ///
+    /// Commands sent faster than the firmware drains its serial buffer can
+    /// lose their acknowledges, so a command sent sooner waits out the rest
+    /// of the gap first. Devices opened on a serial port default to 1 ms,
+    /// which a request and answer over USB usually take anyway; zero turns
+    /// the limit off.
+    pub fn set_command_gap(&mut self, gap: Duration) {
+        self.command_gap = gap;
+    }
+
+    /// Minimum time between the starts of consecutive commands
+    pub fn command_gap(&self) -> Duration {
+        self.command_gap
+    }
+
+    /// Pause for `delay` after every command has been answered
+    ///
+    /// For long cables and RFC 2217 bridges that occasionally interleave
+    /// the answers of back-to-back commands. Unlike the command gap this
+    /// adds to every command however slowly they are sent. None by default.
+    pub fn set_settle_delay(&mut self, delay: Duration) {
+        self.settle_delay = delay;
+    }
+
+    /// Pause after every command
+    pub fn settle_delay(&self) -> Duration {
+        self.settle_delay
+    }
+
+    /// Pause for the settle delay after a command was answered
+    pub(crate) fn settle(&self) {
+        if !self.settle_delay.is_zero() {
+            std::thread::sleep(self.settle_delay);
+        }
+    }
+
+    /// Wait out the command gap, then note the start of a command
+    pub(crate) fn pace(&mut self) {
+        if let Some(last) = self.last_command {
+            let ready = last + self.command_gap;
+            let now = Instant::now();
+            if ready > now {
+                std::thread::sleep(ready - now);
+            }
+        }
+        self.last_command = Some(Instant::now());
+    }
+
+    /// Remember the port for [`NSRT::reconnect`]
+    fn opened_from(mut self, path: &str, settings: &PortSettings) -> Self {
+        self.origin = Some((path.to_string(), *settings));
+        self
+    }
+}
@@ -0,0 +1,274 @@
+//! Python bindings
+//!
+//! The `python` feature builds the `nsrt` extension module, so the meter can
+//! be scripted from Python and Jupyter with this crate doing the I/O. Build
+//! and install it into the current environment with `maturin develop
+//! --release`:
+//!
+//! ```python
+//! import nsrt
+//!
+//! meter = nsrt.NSRT()
+//! meter.configure(weighting="A", time_constant=0.125)
+//! for m in meter.stream(interval=0.5, count=20):
+//!     print(m["timestamp"], m["level"])
+//! ```
+//!
+//! The GIL is released during serial I/O and while a stream waits for its
+//! next reading, so other Python threads keep running. Errors are raised as
+//! `ValueError` for invalid arguments, `TimeoutError` when the device doesn't
+//! answer and `nsrt.NsrtError` otherwise. Each exception carries the error's
+//! stable code from [`NsrtError::code`] in its `code` attribute.
+
+use crate::{
+    DeviceConfig, DeviceInfo, ErrorKind, Measurement, NSRT, NsrtError, SamplingFrequency,
+    Temperature, Weighting,
+};
+use pyo3::{
+    exceptions::{PyOSError, PyTimeoutError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+pyo3::create_exception!(nsrt, Error, PyOSError, "Error talking to the meter");
+
+/// Longest time a stream sleeps before checking for Ctrl-C
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+fn error(e: NsrtError) -> PyErr {
+    let timed_out = e.kind() == ErrorKind::Timeout;
+    let code = e.code();
+    let error = match e {
+        NsrtError::InvalidParameter(message) => PyValueError::new_err(message),
+        e if timed_out => PyTimeoutError::new_err(e.to_string()),
+        e => Error::new_err(e.to_string()),
+    };
+    Python::attach(|py| {
+        // Exceptions have a __dict__, so this can't fail
+        let _ = error.value(py).setattr("code", code);
+    });
+    error
+}
+
+fn parse_weighting(weighting: &str) -> PyResult<Weighting> {
+    match weighting.to_ascii_uppercase().as_str() {
+        "A" => Ok(Weighting::A),
+        "C" => Ok(Weighting::C),
+        "Z" => Ok(Weighting::Z),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown weighting {weighting:?}, expected A, C or Z"
+        ))),
+    }
+}
+
+fn measurement<'py>(py: Python<'py>, m: &Measurement) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let timestamp = m.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    dict.set_item("timestamp", timestamp.as_secs_f64())?;
+    dict.set_item("level", m.level)?;
+    dict.set_item("leq", m.leq)?;
+    dict.set_item("temperature", m.temperature.as_celsius())?;
+    Ok(dict)
+}
+
+fn config<'py>(py: Python<'py>, config: &DeviceConfig) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("weighting", format!("{:?}", config.weighting))?;
+    dict.set_item("time_constant", config.time_constant)?;
+    dict.set_item("sampling_frequency", u32::from(config.sampling_frequency))?;
+    Ok(dict)
+}
+
+fn info<'py>(py: Python<'py>, info: &DeviceInfo) -> PyResult<Bound<'py, PyDict>> {
+    let seconds = |time: std::time::SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+    };
+    let dict = PyDict::new(py);
+    dict.set_item("model", &info.model)?;
+    dict.set_item("serial_number", &info.serial_number)?;
+    dict.set_item("firmware_revision", &info.firmware_revision)?;
+    dict.set_item("user_id", &info.user_id)?;
+    dict.set_item("calibration_date", seconds(info.calibration_time()))?;
+    dict.set_item("birth_date", seconds(info.birth_time()))?;
+    Ok(dict)
+}
+
+/// An `NSRT_mk4` sound level meter
+///
+/// Timestamps and dates are seconds since the Unix epoch, as taken by
+/// `datetime.fromtimestamp` and `pandas.to_datetime(..., unit="s")`.
+#[pyclass(name = "NSRT", module = "nsrt", frozen)]
+pub struct PyNsrt {
+    nsrt: Mutex<NSRT>,
+}
+
+impl PyNsrt {
+    /// Run `f` against the device with the GIL released
+    fn with<T: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&mut NSRT) -> crate::Result<T> + Send,
+    ) -> PyResult<T> {
+        py.detach(|| f(&mut self.nsrt.lock().unwrap_or_else(|e| e.into_inner())))
+            .map_err(error)
+    }
+}
+
+#[pymethods]
+impl PyNsrt {
+    /// Open the meter on `port`, or the first one found
+    ///
+    /// Ports may also be `tcp://host:port`, such as a simulator.
+    #[new]
+    #[pyo3(signature = (port=None))]
+    fn new(py: Python<'_>, port: Option<&str>) -> PyResult<Self> {
+        let nsrt = py
+            .detach(|| match port {
+                Some(port) => NSRT::open_port(port),
+                None => NSRT::open(),
+            })
+            .map_err(error)?;
+        Ok(Self {
+            nsrt: Mutex::new(nsrt),
+        })
+    }
+
+    /// Ports with a meter attached
+    #[staticmethod]
+    fn ports(py: Python<'_>) -> PyResult<Vec<String>> {
+        py.detach(NSRT::ports).map_err(error)
+    }
+
+    /// Running sound level in dB
+    fn read_level(&self, py: Python<'_>) -> PyResult<f32> {
+        self.with(py, NSRT::read_level)
+    }
+
+    /// LEQ in dB since the previous LEQ read, restarting integration
+    fn read_leq(&self, py: Python<'_>) -> PyResult<f32> {
+        self.with(py, NSRT::read_leq)
+    }
+
+    /// Temperature in °C
+    fn read_temperature(&self, py: Python<'_>) -> PyResult<f32> {
+        self.with(py, NSRT::read_temperature)
+            .map(Temperature::as_celsius)
+    }
+
+    /// Level, LEQ and temperature as a dict with a timestamp
+    fn read_measurement<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        measurement(py, &self.with(py, NSRT::read_measurement)?)
+    }
+
+    /// Weighting, time constant and sampling frequency as a dict
+    fn read_config<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        config(py, &self.with(py, NSRT::read_config)?)
+    }
+
+    /// Change the given settings, leaving the others as they are
+    ///
+    /// Only settings that differ are written, followed by a single wait for
+    /// the device to stabilize.
+    #[pyo3(signature = (weighting=None, time_constant=None, sampling_frequency=None))]
+    fn configure(
+        &self,
+        py: Python<'_>,
+        weighting: Option<&str>,
+        time_constant: Option<f32>,
+        sampling_frequency: Option<u32>,
+    ) -> PyResult<()> {
+        let weighting = weighting.map(parse_weighting).transpose()?;
+        let sampling_frequency = sampling_frequency
+            .map(SamplingFrequency::try_from)
+            .transpose()
+            .map_err(|e| error(e.into()))?;
+        self.with(py, |nsrt| {
+            let mut config = nsrt.read_config()?;
+            config.weighting = weighting.unwrap_or(config.weighting);
+            config.time_constant = time_constant.unwrap_or(config.time_constant);
+            config.sampling_frequency = sampling_frequency.unwrap_or(config.sampling_frequency);
+            nsrt.configure(&config)
+        })
+    }
+
+    /// Model, serial number, firmware, user ID and dates as a dict
+    fn read_info<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        info(py, &self.with(py, NSRT::read_info)?)
+    }
+
+    /// Set the user-defined identifier, at most 31 bytes
+    fn set_user_id(&self, py: Python<'_>, user_id: &str) -> PyResult<()> {
+        self.with(py, |nsrt| nsrt.set_user_id(user_id))
+    }
+
+    /// Iterate over measurements taken every `interval` seconds
+    ///
+    /// The stream ends after `count` measurements, or runs until interrupted.
+    #[pyo3(signature = (interval=1.0, count=None))]
+    fn stream(slf: Py<Self>, interval: f64, count: Option<usize>) -> PyResult<Stream> {
+        let interval = Duration::try_from_secs_f64(interval)
+            .map_err(|_| PyValueError::new_err(format!("Invalid interval {interval}")))?;
+        Ok(Stream {
+            nsrt: slf,
+            interval,
+            next: Instant::now(),
+            remaining: count,
+        })
+    }
+}
+
+/// Iterator over periodic measurements, from [`NSRT.stream`](PyNsrt::stream)
+#[pyclass(module = "nsrt")]
+pub struct Stream {
+    nsrt: Py<PyNsrt>,
+    interval: Duration,
+    next: Instant,
+    remaining: Option<usize>,
+}
+
+#[pymethods]
+impl Stream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return Ok(None);
+            }
+            *remaining -= 1;
+        }
+
+        // Sleep in slices, so Ctrl-C in a notebook stops the stream promptly
+        loop {
+            let wait = self.next.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                break;
+            }
+            py.detach(|| std::thread::sleep(wait.min(SIGNAL_CHECK_INTERVAL)));
+            py.check_signals()?;
+        }
+        // Skip ticks missed by a slow consumer rather than bursting to catch up
+        self.next = (self.next + self.interval).max(Instant::now());
+
+        let m = self.nsrt.get().with(py, NSRT::read_measurement)?;
+        measurement(py, &m).map(Some)
+    }
+}
+
+/// The `nsrt` Python module
+#[pymodule(name = "nsrt")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyNsrt>()?;
+    m.add_class::<Stream>()?;
+    m.add("NsrtError", m.py().get_type::<Error>())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use crate::{ClockStatus, NsrtError};
+use std::{fmt, ops, str::FromStr};
+
+/// Data-quality flags of a measurement or of an aggregate over a window
+///
+/// A measurement without flags is good. Flags are combined with `|`, so an
+/// aggregate carries every flag of the measurements it was computed from.
+/// They print and parse as their names joined by `|`, e.g.
+/// `missing_samples|reconnected`, and `good` without flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct Quality(u8);
+
+impl Quality {
+    /// No flags
+    pub const GOOD: Self = Self(0);
+    /// Samples are missing before this one, e.g. after failed reads, or in
+    /// the window
+    pub const MISSING_SAMPLES: Self = Self(1 << 0);
+    /// The link to the device was resynchronized or reopened before this
+    /// sample, or during the window
+    pub const RECONNECTED: Self = Self(1 << 1);
+    /// The host clock stepped since the previous sample, so the time between
+    /// the two timestamps is wrong
+    pub const CLOCK_STEP: Self = Self(1 << 2);
+    /// The level or LEQ is at the top of the meter's range and may be
+    /// clipped, see [`MeasurementRange`](crate::MeasurementRange)
+    pub const OVERLOAD: Self = Self(1 << 3);
+    /// The level or LEQ is at the bottom of the meter's range and may be
+    /// the microphone's self-noise rather than the sound
+    pub const UNDER_RANGE: Self = Self(1 << 4);
+    /// The timestamp came from a host clock known to be unsynchronized, as
+    /// [`Measurement::is_unsynchronized`](crate::Measurement::is_unsynchronized)
+    /// reports, kept even where the clock status itself isn't stored
+    pub const UNSYNCHRONIZED: Self = Self(1 << 5);
+
+    const NAMES: [(Self, &'static str); 6] = [
+        (Self::MISSING_SAMPLES, "missing_samples"),
+        (Self::RECONNECTED, "reconnected"),
+        (Self::CLOCK_STEP, "clock_step"),
+        (Self::OVERLOAD, "overload"),
+        (Self::UNDER_RANGE, "under_range"),
+        (Self::UNSYNCHRONIZED, "unsynchronized"),
+    ];
+
+    /// [`Quality::UNSYNCHRONIZED`] for a timestamp taken with `clock`, if it
+    /// is known to be unsynchronized, otherwise no flags
+    pub(crate) fn of_clock(clock: Option<ClockStatus>) -> Self {
+        match clock {
+            Some(clock) if !clock.synchronized => Self::UNSYNCHRONIZED,
+            _ => Self::GOOD,
+        }
+    }
+
+    /// Whether no flag is set
+    pub fn is_good(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every flag of `other` is set
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set the flags of `other`
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Flags as bits, in the order of the constants starting at bit 0
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Flags from [`Quality::bits`], ignoring unknown bits
+    pub fn from_bits(bits: u8) -> Self {
+        let known = Self::NAMES.iter().fold(0, |all, (flag, _)| all | flag.0);
+        Self(bits & known)
+    }
+}
+
+impl ops::BitOr for Quality {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl ops::BitOrAssign for Quality {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_good() {
+            return f.write_str("good");
+        }
+        let mut names = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| name);
+        if let Some(first) = names.next() {
+            f.write_str(first)?;
+        }
+        for name in names {
+            write!(f, "|{name}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Quality {
+    type Err = NsrtError;
+
+    fn from_str(text: &str) -> Result<Self, NsrtError> {
+        let text = text.trim();
+        if text.is_empty() || text == "good" {
+            return Ok(Self::GOOD);
+        }
+        text.split('|').try_fold(Self::GOOD, |quality, name| {
+            let (flag, _) = Self::NAMES
+                .iter()
+                .find(|(_, known)| *known == name.trim())
+                .ok_or_else(|| {
+                    NsrtError::InvalidParameter(format!("Unknown quality flag {name:?}"))
+                })?;
+            Ok(quality | *flag)
+        })
+    }
+}
+
+impl From<Quality> for String {
+    fn from(quality: Quality) -> Self {
+        quality.to_string()
+    }
+}
+
+impl TryFrom<String> for Quality {
+    type Error = NsrtError;
+
+    fn try_from(text: String) -> Result<Self, NsrtError> {
+        text.parse()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{Measurement, NSRT, Quality};
+
+/// Levels in dB the meter measures reliably, for flagging readings at its
+/// limits
+///
+/// The protocol doesn't report overloads, so readings are judged by level:
+/// a level or LEQ within `margin` dB of `upper` may be clipped and is flagged
+/// [`Quality::OVERLOAD`], one within `margin` dB of `lower` may be the
+/// microphone's self-noise and is flagged [`Quality::UNDER_RANGE`]. Levels
+/// are compared before the calibration offset is added. The default is the
+/// `NSRT_mk4`'s 30 to 130 dB with a margin of 1 dB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeasurementRange {
+    /// Lowest level in dB
+    pub lower: f32,
+    /// Highest level in dB
+    pub upper: f32,
+    /// Distance in dB from a limit at which readings are flagged
+    pub margin: f32,
+}
+
+impl Default for MeasurementRange {
+    fn default() -> Self {
+        Self::new(30.0, 130.0)
+    }
+}
+
+impl MeasurementRange {
+    /// Range from `lower` to `upper` dB with a margin of 1 dB
+    pub fn new(lower: f32, upper: f32) -> Self {
+        Self {
+            lower,
+            upper,
+            margin: 1.0,
+        }
+    }
+
+    /// Flags for a level in dB
+    pub fn assess(&self, level: f32) -> Quality {
+        if level >= self.upper - self.margin {
+            Quality::OVERLOAD
+        } else if level <= self.lower + self.margin {
+            Quality::UNDER_RANGE
+        } else {
+            Quality::GOOD
+        }
+    }
+}
+
+impl NSRT {
+    /// Flag the readings of `measurement` at the limits of the range
+    pub(crate) fn check_range(&self, mut measurement: Measurement) -> Measurement {
+        if let Some(range) = self.range {
+            for level in [measurement.level, measurement.leq] {
+                measurement.quality |= range.assess(level - self.calibration_offset);
+            }
+        }
+        measurement
+    }
+
+    /// Flag measurements at the limits of `range`, or none with `None`
+    ///
+    /// Measurements are checked against [`MeasurementRange::default`] unless
+    /// set otherwise.
+    pub fn set_measurement_range(&mut self, range: Option<MeasurementRange>) {
+        self.range = range;
+    }
+
+    /// Flag measurements at the limits of `range`, using fluent API
+    #[must_use]
+    pub fn measurement_range(mut self, range: MeasurementRange) -> Self {
+        self.range = Some(range);
+        self
+    }
+}
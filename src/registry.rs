@@ -0,0 +1,224 @@
+//! Persistent registry of known devices
+//!
+//! The registry maps device serial numbers to a friendly alias, the settings
+//! to apply when the device is opened and a calibration offset. It is kept as
+//! a TOML file, by default `nsrt/devices.toml` in the user's configuration
+//! directory:
+//!
+//! ```toml
+//! [devices.2103A4F1]
+//! alias = "rooftop-north"
+//! offset = -0.4
+//! calibrated = "2024-05-01T08:30:00Z"
+//! config = { weighting = "A", time_constant = 0.125, sampling_frequency = 48000 }
+//! metadata = { site = "Alexanderplatz", position = { latitude = 52.52, longitude = 13.405 } }
+//! ```
+//!
+//! [`NSRT::open_by_alias`] finds the attached device registered under an
+//! alias and opens it with its entry applied.
+
+use crate::{DeviceConfig, Metadata, NSRT, NsrtError, PortSettings, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Environment variable overriding the registry location
+const REGISTRY_ENV: &str = "NSRT_REGISTRY";
+
+/// What the registry knows about one device
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceEntry {
+    /// Friendly name to open the device by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Settings applied when the device is opened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<DeviceConfig>,
+    /// Calibration offset in dB, see [`NSRT::set_calibration_offset`]
+    pub offset: f32,
+    /// When the offset was last checked against a calibrator, e.g. by
+    /// `nsrt calibrate`
+    ///
+    /// The device's own calibration date, see
+    /// [`NSRT::read_calibration_date`], is set by the manufacturer and can't
+    /// be written over the serial protocol, so field calibrations are
+    /// recorded here.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    pub calibrated: Option<SystemTime>,
+    /// Where and how the device is installed, e.g. for placing its results
+    /// on a [map](crate::geojson)
+    #[serde(skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+}
+
+impl DeviceEntry {
+    /// Apply the entry's settings and calibration offset to `nsrt`
+    pub fn apply(&self, nsrt: &mut NSRT) -> Result<()> {
+        if let Some(config) = &self.config {
+            nsrt.configure(config)?;
+        }
+        nsrt.set_calibration_offset(self.offset);
+        Ok(())
+    }
+}
+
+/// Devices by serial number
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Registry {
+    devices: BTreeMap<String, DeviceEntry>,
+}
+
+impl Registry {
+    /// Default registry location
+    ///
+    /// `$NSRT_REGISTRY` if set, otherwise `nsrt/devices.toml` in
+    /// `%APPDATA%` on Windows and in `$XDG_CONFIG_HOME`, falling back to
+    /// `~/.config`, elsewhere.
+    pub fn default_path() -> PathBuf {
+        if let Some(path) = std::env::var_os(REGISTRY_ENV) {
+            return PathBuf::from(path);
+        }
+        let dir = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        };
+        dir.unwrap_or_default().join("nsrt").join("devices.toml")
+    }
+
+    /// Load the registry at the default location
+    pub fn load_default() -> Result<Self> {
+        Self::load(Self::default_path())
+    }
+
+    /// Load the registry at `path`, which is empty if the file doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => text.parse(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+        .map_err(|e| match e {
+            NsrtError::InvalidParameter(message) => {
+                NsrtError::InvalidParameter(format!("{}: {message}", path.display()))
+            }
+            e => e,
+        })
+    }
+
+    /// Write the registry to `path`, creating its directory if needed
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// The entry of the device with serial number `serial`
+    pub fn get(&self, serial: &str) -> Option<&DeviceEntry> {
+        self.devices.get(serial)
+    }
+
+    /// Register the device with serial number `serial`, returning its
+    /// previous entry
+    ///
+    /// Fails if another device already has the same alias.
+    pub fn insert(&mut self, serial: &str, entry: DeviceEntry) -> Result<Option<DeviceEntry>> {
+        if let Some(alias) = &entry.alias
+            && let Some(other) = self.serial_of(alias)
+            && other != serial
+        {
+            return Err(NsrtError::InvalidParameter(format!(
+                "Alias {alias:?} is already used by device {other}"
+            )));
+        }
+        Ok(self.devices.insert(serial.to_string(), entry))
+    }
+
+    /// Forget the device with serial number `serial`
+    pub fn remove(&mut self, serial: &str) -> Option<DeviceEntry> {
+        self.devices.remove(serial)
+    }
+
+    /// Serial number of the device registered as `alias`
+    pub fn serial_of(&self, alias: &str) -> Option<&str> {
+        self.devices
+            .iter()
+            .find(|(_, entry)| entry.alias.as_deref() == Some(alias))
+            .map(|(serial, _)| serial.as_str())
+    }
+
+    /// Serial numbers and entries of all devices, by serial number
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DeviceEntry)> {
+        self.devices
+            .iter()
+            .map(|(serial, entry)| (serial.as_str(), entry))
+    }
+
+    /// Open the attached device registered as `alias`
+    pub fn open(&self, alias: &str) -> Result<NSRT> {
+        self.open_with(alias, &PortSettings::default())
+    }
+
+    /// Open the attached device registered as `alias`, with `settings` for
+    /// its serial line
+    ///
+    /// Every attached device is opened in turn until one with the right
+    /// serial number answers. Its entry's settings and calibration offset are
+    /// applied before it is returned.
+    pub fn open_with(&self, alias: &str, settings: &PortSettings) -> Result<NSRT> {
+        let serial = self.serial_of(alias).ok_or_else(|| {
+            NsrtError::InvalidParameter(format!("No device registered as {alias:?}"))
+        })?;
+        let entry = &self.devices[serial];
+
+        for port in NSRT::ports()? {
+            // Ports that can't be opened, e.g. because they are in use, are
+            // some other device's
+            let Ok(mut nsrt) = NSRT::open_port_with(&port, settings) else {
+                continue;
+            };
+            if nsrt.read_serial_number().ok().as_deref() != Some(serial) {
+                continue;
+            }
+            entry.apply(&mut nsrt)?;
+            return Ok(nsrt);
+        }
+        Err(NsrtError::NoDevice)
+    }
+}
+
+impl std::str::FromStr for Registry {
+    type Err = NsrtError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| NsrtError::InvalidParameter(e.message().to_string()))
+    }
+}
+
+impl std::fmt::Display for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = toml::to_string(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(&text)
+    }
+}
+
+impl NSRT {
+    /// Open the attached device registered as `alias` in the default
+    /// registry, see [`Registry::open`]
+    pub fn open_by_alias(alias: &str) -> Result<Self> {
+        Registry::load_default()?.open(alias)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{Measurement, Result, Sink};
+use std::{thread, time::Duration};
+
+/// How fast [`replay`] feeds stored measurements to a sink
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// Write measurements back to back
+    AsFastAsPossible,
+    /// Wait between measurements as long as passed between them originally
+    RealTime,
+    /// Like [`Pace::RealTime`], sped up by the given factor
+    Scaled(f64),
+}
+
+/// Write stored measurements to `sink` in order, then flush it
+///
+/// `measurements` is any log reader, such as
+/// [`CsvReader`](crate::csv::CsvReader) or
+/// [`BinaryLogReader`](crate::binlog::BinaryLogReader). Stops at the first
+/// read or write error. Returns the number of measurements written.
+pub fn replay<S: Sink + ?Sized>(
+    measurements: impl IntoIterator<Item = Result<Measurement>>,
+    sink: &mut S,
+    pace: Pace,
+) -> Result<u64> {
+    let speed = match pace {
+        Pace::AsFastAsPossible => None,
+        Pace::RealTime => Some(1.0),
+        Pace::Scaled(factor) => Some(factor),
+    };
+
+    let mut previous: Option<Measurement> = None;
+    let mut count = 0;
+    for measurement in measurements {
+        let measurement = measurement?;
+
+        if let (Some(speed), Some(previous)) = (speed, previous) {
+            let gap = measurement
+                .timestamp
+                .duration_since(previous.timestamp)
+                .unwrap_or_default();
+            if let Ok(wait) = Duration::try_from_secs_f64(gap.as_secs_f64() / speed) {
+                thread::sleep(wait);
+            }
+        }
+
+        sink.write(&measurement)?;
+        previous = Some(measurement);
+        count += 1;
+    }
+
+    sink.flush()?;
+    Ok(count)
+}
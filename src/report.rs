@@ -0,0 +1,891 @@
+//! Daily summary reports
+//!
+//! [`DailySummary::compute`] rolls a day of measurements into hourly LEQs,
+//! Lmax events, exceedance levels, traffic noise indices and noise dose.
+//! [`DailyReports`] is a sink that does this automatically, writing one JSON
+//! file per day:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "start": "2024-05-01T00:00:00Z",
+//!   "measurements": 86400,
+//!   "leq": 52.1,
+//!   "lmax": 81.3,
+//!   "lmin": 38.0,
+//!   "percentiles": { "l10": 55.2, "l50": 49.8, "l90": 42.1 },
+//!   "tni": 64.5,
+//!   "npl": 65.2,
+//!   "lden": { "lday": 54.0, "levening": 51.2, "lnight": 44.3, "lden": 54.9 },
+//!   "dose": 3.2,
+//!   "hourly": [{ "hour": 0, "measurements": 3600, "leq": 44.0, "lmax": 61.2 }, ...],
+//!   "events": [{ "start": "2024-05-01T07:12:03Z", "end": "2024-05-01T07:12:41Z", "lmax": 81.3 }]
+//! }
+//! ```
+//!
+//! LEQ and dose use each measurement's LEQ over the time since the previous
+//! measurement; exceedance levels and Lmax use the running level. Days and
+//! hours carry the [quality flags](Quality) of their measurements.
+//!
+//! Fields added since the first reports are optional, so reports of every
+//! crate version so far are [`SCHEMA_VERSION`] 1, including those from before
+//! `schema_version` was written. [`DailySummary::read_json`] reads them, and
+//! refuses reports of later versions with [`NsrtError::UnsupportedSchema`].
+//!
+//! Days and hours follow the clock of a [`TimeZone`], UTC unless configured
+//! otherwise, so across a daylight saving change a day has 23 or 25 hourly
+//! entries and the day-evening-night periods of [`DayEveningNight`] start at
+//! 07:00, 19:00 and 23:00 local time whatever the offset.
+//!
+//! [`Analysis`] recomputes the same statistics offline from a stored log, over
+//! any time range and in windows of any length, e.g. to regenerate reports
+//! with 15-minute windows after the fact.
+
+use crate::{Measurement, Metadata, NsrtError, Quality, Result, Sink, stats};
+pub use jiff::tz::TimeZone;
+use jiff::{ToSpan, Zoned, tz::Offset};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    mem,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+const HOUR: Duration = Duration::from_secs(3600);
+const DAY: Duration = Duration::from_secs(24 * 3600);
+
+/// Schema version of the reports written
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Criteria for computing noise dose
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DoseCriteria {
+    /// Level allowed for the full criterion duration, in dB
+    pub level: f32,
+    /// Level increase in dB that halves the allowed duration
+    pub exchange_rate: f32,
+    /// Exposure duration allowed at the criterion level
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    /// Level in dB below which exposure doesn't count toward the dose, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f32>,
+}
+
+impl DoseCriteria {
+    /// NIOSH recommended exposure limit: 85 dBA for 8 hours, 3 dB exchange rate
+    pub const NIOSH: Self = Self {
+        level: 85.0,
+        exchange_rate: 3.0,
+        duration: Duration::from_secs(8 * 3600),
+        threshold: None,
+    };
+
+    /// OSHA permissible exposure limit: 90 dBA for 8 hours, 5 dB exchange
+    /// rate, levels below 90 dBA not counted
+    pub const OSHA: Self = Self {
+        level: 90.0,
+        exchange_rate: 5.0,
+        duration: Duration::from_secs(8 * 3600),
+        threshold: Some(90.0),
+    };
+
+    /// OSHA action level for hearing conservation: as [`Self::OSHA`], but
+    /// counting levels from 80 dBA
+    pub const OSHA_ACTION_LEVEL: Self = Self {
+        threshold: Some(80.0),
+        ..Self::OSHA
+    };
+
+    /// Dose in percent of exposure to `level` for `duration`
+    fn dose(&self, level: f32, duration: Duration) -> f64 {
+        if self.threshold.is_some_and(|threshold| level < threshold) {
+            return 0.0;
+        }
+        let allowed = self.duration.as_secs_f64()
+            / 2f64.powf(f64::from(level - self.level) / f64::from(self.exchange_rate));
+        100.0 * duration.as_secs_f64() / allowed
+    }
+}
+
+impl Default for DoseCriteria {
+    fn default() -> Self {
+        Self::NIOSH
+    }
+}
+
+/// Options for computing a [`DailySummary`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SummaryOptions {
+    /// Running level above which an Lmax event is recorded, if any
+    pub event_threshold: Option<f32>,
+    /// Dose criteria
+    pub dose: DoseCriteria,
+    /// Longest gap between measurements still counted as measured time
+    ///
+    /// Longer gaps, e.g. while the device was disconnected, are left out of
+    /// LEQ and dose.
+    pub max_gap: Duration,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            event_threshold: None,
+            dose: DoseCriteria::default(),
+            max_gap: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Summary of one day of measurements
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DailySummary {
+    /// Start of the day
+    #[serde(with = "humantime_serde")]
+    pub start: SystemTime,
+    /// Number of measurements in the day
+    pub measurements: usize,
+    /// Quality flags of all the measurements in the day
+    #[serde(default, skip_serializing_if = "Quality::is_good")]
+    pub quality: Quality,
+    /// Setup the measurements were taken with
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+    /// LEQ over the measured time in dB
+    pub leq: Option<f32>,
+    /// Highest running level in dB
+    pub lmax: Option<f32>,
+    /// Lowest running level in dB
+    pub lmin: Option<f32>,
+    /// Exceedance levels of the running level
+    pub percentiles: Option<Percentiles>,
+    /// Traffic noise index, see [`Percentiles::traffic_noise_index`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tni: Option<f32>,
+    /// Noise pollution level, see [`Percentiles::noise_pollution_level`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub npl: Option<f32>,
+    /// Day-evening-night levels, if all three periods were measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lden: Option<DayEveningNight>,
+    /// Noise dose in percent
+    pub dose: f64,
+    /// One entry per hour of the day
+    pub hourly: Vec<HourlySummary>,
+    /// Periods during which the running level exceeded the event threshold
+    pub events: Vec<LmaxEvent>,
+}
+
+/// Exceedance levels in dB
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Percentiles {
+    /// Level exceeded 10% of the time
+    pub l10: f32,
+    /// Level exceeded 50% of the time
+    pub l50: f32,
+    /// Level exceeded 90% of the time
+    pub l90: f32,
+}
+
+impl Percentiles {
+    /// Traffic noise index in dB, `4 (L10 - L90) + L90 - 30`
+    ///
+    /// Rates road traffic noise by its fluctuation as well as its level.
+    pub fn traffic_noise_index(&self) -> f32 {
+        4.0 * (self.l10 - self.l90) + self.l90 - 30.0
+    }
+
+    /// Noise pollution level in dB from the LEQ `leq` over the same time,
+    /// `Leq + (L10 - L90)`
+    ///
+    /// Adds a penalty for fluctuation to the LEQ. `L10 - L90` stands in for
+    /// the `2.56 σ` of the original definition, as for a normal distribution
+    /// of levels.
+    pub fn noise_pollution_level(&self, leq: f32) -> f32 {
+        leq + self.l10 - self.l90
+    }
+}
+
+/// Day-evening-night levels of the EU Environmental Noise Directive in dB
+///
+/// The day runs from 07:00 to 19:00, the evening to 23:00 and the night to
+/// 07:00 local time. A report's night is the parts of its own day before
+/// 07:00 and after 23:00.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DayEveningNight {
+    /// LEQ over the day period
+    pub lday: f32,
+    /// LEQ over the evening period
+    pub levening: f32,
+    /// LEQ over the night period
+    pub lnight: f32,
+    /// Day-evening-night level
+    pub lden: f32,
+}
+
+impl DayEveningNight {
+    /// Combine the period LEQs into Lden, weighting them by 12, 4 and 8 hours
+    /// with 5 dB and 10 dB penalties for evening and night
+    pub fn new(lday: f32, levening: f32, lnight: f32) -> Self {
+        let energy = |level: f32, hours: f64| hours * 10f64.powf(f64::from(level) / 10.0);
+        let lden = 10.0
+            * ((energy(lday, 12.0) + energy(levening + 5.0, 4.0) + energy(lnight + 10.0, 8.0))
+                / 24.0)
+                .log10();
+        Self {
+            lday,
+            levening,
+            lnight,
+            lden: lden as f32,
+        }
+    }
+}
+
+/// Summary of one hour of measurements
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HourlySummary {
+    /// Local hour of the day, from 0, skipped or repeated when the clocks
+    /// change
+    pub hour: u8,
+    /// Number of measurements in the hour
+    pub measurements: usize,
+    /// Quality flags of all the measurements in the hour
+    #[serde(default, skip_serializing_if = "Quality::is_good")]
+    pub quality: Quality,
+    /// LEQ over the measured time in dB
+    pub leq: Option<f32>,
+    /// Highest running level in dB
+    pub lmax: Option<f32>,
+}
+
+/// Period during which the running level exceeded the event threshold
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LmaxEvent {
+    /// First measurement above the threshold
+    #[serde(with = "humantime_serde")]
+    pub start: SystemTime,
+    /// Last measurement above the threshold
+    #[serde(with = "humantime_serde")]
+    pub end: SystemTime,
+    /// Highest running level during the event in dB
+    pub lmax: f32,
+    /// Quality flags of the measurements during the event, overload meaning
+    /// that `lmax` may be clipped
+    #[serde(default, skip_serializing_if = "Quality::is_good")]
+    pub quality: Quality,
+}
+
+impl DailySummary {
+    /// Summarize the measurements taken in the 24 hours from `start`, taking
+    /// `start` as midnight
+    ///
+    /// Measurements outside the day are ignored; the rest must be in
+    /// chronological order.
+    pub fn compute(
+        start: SystemTime,
+        measurements: &[Measurement],
+        options: &SummaryOptions,
+    ) -> Self {
+        let hours = (0..24u8)
+            .map(|hour| (hour, start + HOUR * u32::from(hour)))
+            .collect();
+        Self::summarize(start, start + DAY, hours, measurements, options)
+    }
+
+    /// Summarize the measurements taken on the day in `time_zone` containing
+    /// `time`
+    ///
+    /// The day runs from local midnight to local midnight, so it is 23 or 25
+    /// hours long when the clocks change. Measurements outside the day are
+    /// ignored; the rest must be in chronological order.
+    pub fn compute_in(
+        time_zone: &TimeZone,
+        time: SystemTime,
+        measurements: &[Measurement],
+        options: &SummaryOptions,
+    ) -> Result<Self> {
+        let (start, end) = local_day(time_zone, time)?;
+        let mut hours = Vec::with_capacity(25);
+        let mut hour = start.clone();
+        while hour < end {
+            hours.push((hour.hour() as u8, SystemTime::from(hour.timestamp())));
+            hour = hour.checked_add(1.hour()).map_err(time_error)?;
+        }
+        Ok(Self::summarize(
+            SystemTime::from(start.timestamp()),
+            SystemTime::from(end.timestamp()),
+            hours,
+            measurements,
+            options,
+        ))
+    }
+
+    /// Summarize the measurements from `start` to `end` into the hours
+    /// starting at the given times, labelled with their local hour
+    fn summarize(
+        start: SystemTime,
+        end: SystemTime,
+        hours: Vec<(u8, SystemTime)>,
+        measurements: &[Measurement],
+        options: &SummaryOptions,
+    ) -> Self {
+        let day: Vec<&Measurement> = measurements
+            .iter()
+            .filter(|m| m.timestamp >= start && m.timestamp < end)
+            .collect();
+
+        let durations = durations(&day, options.max_gap);
+        let weighted = |indices: &mut dyn Iterator<Item = usize>| {
+            stats::energy_average(indices.map(|i| (day[i].leq, durations[i].as_secs_f64())))
+        };
+        let lmax = |indices: &mut dyn Iterator<Item = usize>| {
+            indices
+                .map(|i| day[i].level)
+                .filter(|l| l.is_finite())
+                .reduce(f32::max)
+        };
+        let quality = |indices: &mut dyn Iterator<Item = usize>| {
+            indices.fold(Quality::GOOD, |quality, i| quality | day[i].quality)
+        };
+
+        let bounds: Vec<(u8, SystemTime, SystemTime)> = hours
+            .iter()
+            .enumerate()
+            .map(|(i, &(hour, from))| (hour, from, hours.get(i + 1).map_or(end, |next| next.1)))
+            .collect();
+        let period_leq = |period: fn(u8) -> bool| {
+            weighted(&mut (0..day.len()).filter(|&i| {
+                bounds.iter().any(|&(hour, from, to)| {
+                    period(hour) && day[i].timestamp >= from && day[i].timestamp < to
+                })
+            }))
+        };
+        let lden = period_leq(|hour| (7..19).contains(&hour))
+            .zip(period_leq(|hour| (19..23).contains(&hour)))
+            .zip(period_leq(|hour| !(7..23).contains(&hour)))
+            .map(|((lday, levening), lnight)| DayEveningNight::new(lday, levening, lnight));
+
+        let hourly = bounds
+            .iter()
+            .map(|&(hour, from, to)| {
+                let in_hour = |i: &usize| day[*i].timestamp >= from && day[*i].timestamp < to;
+                HourlySummary {
+                    hour,
+                    measurements: (0..day.len()).filter(in_hour).count(),
+                    quality: quality(&mut (0..day.len()).filter(in_hour)),
+                    leq: weighted(&mut (0..day.len()).filter(in_hour)),
+                    lmax: lmax(&mut (0..day.len()).filter(in_hour)),
+                }
+            })
+            .collect();
+
+        let levels = stats::sorted_levels(day.iter().map(|m| m.level));
+        let percentiles = (!levels.is_empty()).then(|| Percentiles {
+            l10: stats::exceedance_level(&levels, 10.0).expect("levels are not empty"),
+            l50: stats::exceedance_level(&levels, 50.0).expect("levels are not empty"),
+            l90: stats::exceedance_level(&levels, 90.0).expect("levels are not empty"),
+        });
+
+        let dose = day
+            .iter()
+            .zip(&durations)
+            .filter(|(m, _)| m.leq.is_finite())
+            .map(|(m, &duration)| options.dose.dose(m.leq, duration))
+            .sum();
+
+        let leq = weighted(&mut (0..day.len()));
+        Self {
+            start,
+            measurements: day.len(),
+            quality: quality(&mut (0..day.len())),
+            metadata: Metadata::default(),
+            leq,
+            lmax: levels.last().copied(),
+            lmin: levels.first().copied(),
+            percentiles,
+            tni: percentiles.map(|percentiles| percentiles.traffic_noise_index()),
+            npl: percentiles
+                .zip(leq)
+                .map(|(percentiles, leq)| percentiles.noise_pollution_level(leq)),
+            lden,
+            dose,
+            hourly,
+            events: options
+                .event_threshold
+                .map(|threshold| events(&day, threshold))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Write the summary as pretty-printed JSON, with its schema version
+    pub fn write_json(&self, writer: impl Write) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Versioned<'a> {
+            schema_version: u32,
+            #[serde(flatten)]
+            summary: &'a DailySummary,
+        }
+
+        let report = Versioned {
+            schema_version: SCHEMA_VERSION,
+            summary: self,
+        };
+        serde_json::to_writer_pretty(writer, &report).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    /// Read a summary written by [`write_json`](Self::write_json) of this or
+    /// an earlier crate version
+    pub fn read_json(reader: impl Read) -> Result<Self> {
+        let mut report: serde_json::Value =
+            serde_json::from_reader(reader).map_err(io::Error::from)?;
+        let version = match report
+            .as_object_mut()
+            .and_then(|report| report.remove("schema_version"))
+        {
+            Some(version) => serde_json::from_value(version).map_err(io::Error::from)?,
+            None => 1,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(NsrtError::UnsupportedSchema {
+                format: "Report",
+                version,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        Ok(serde_json::from_value(report).map_err(io::Error::from)?)
+    }
+}
+
+/// Duration covered by each measurement's LEQ, zero after gaps longer than
+/// `max_gap`
+fn durations(measurements: &[&Measurement], max_gap: Duration) -> Vec<Duration> {
+    measurements
+        .iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let previous = i
+                .checked_sub(1)
+                .map_or(m.timestamp, |p| measurements[p].timestamp);
+            let gap = m.timestamp.duration_since(previous).unwrap_or_default();
+            if gap <= max_gap { gap } else { Duration::ZERO }
+        })
+        .collect()
+}
+
+fn events(day: &[&Measurement], threshold: f32) -> Vec<LmaxEvent> {
+    let mut events = Vec::new();
+    let mut current: Option<LmaxEvent> = None;
+
+    for m in day {
+        match (&mut current, m.level > threshold) {
+            (Some(event), true) => {
+                event.end = m.timestamp;
+                event.lmax = event.lmax.max(m.level);
+                event.quality |= m.quality;
+            }
+            (None, true) => {
+                current = Some(LmaxEvent {
+                    start: m.timestamp,
+                    end: m.timestamp,
+                    lmax: m.level,
+                    quality: m.quality,
+                });
+            }
+            (Some(_), false) => events.extend(current.take()),
+            (None, false) => {}
+        }
+    }
+    events.extend(current);
+    events
+}
+
+/// Sink writing a [`DailySummary`] to a directory at the end of every day
+///
+/// Files are named after the day, e.g. `2024-05-01.json`. Measurements are
+/// kept in memory until their day is summarized; the current day is also
+/// summarized on [`Sink::flush`], so stopping the sampler writes a partial
+/// report.
+pub struct DailyReports {
+    dir: PathBuf,
+    options: SummaryOptions,
+    time_zone: TimeZone,
+    metadata: Metadata,
+    day: Option<SystemTime>,
+    measurements: Vec<Measurement>,
+}
+
+impl DailyReports {
+    /// Write reports to `dir`, with days starting at midnight UTC
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            options: SummaryOptions::default(),
+            time_zone: TimeZone::UTC,
+            metadata: Metadata::default(),
+            day: None,
+            measurements: Vec::new(),
+        }
+    }
+
+    /// Set the options for computing summaries
+    #[must_use]
+    pub fn options(mut self, options: SummaryOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Start days at local midnight for a fixed time zone `seconds` east of
+    /// UTC
+    ///
+    /// Offsets beyond ±25:59:59 are ignored.
+    #[must_use]
+    pub fn utc_offset(mut self, seconds: i32) -> Self {
+        if let Ok(offset) = Offset::from_seconds(seconds) {
+            self.time_zone = TimeZone::fixed(offset);
+        }
+        self
+    }
+
+    /// Start days and hours by the clock of `time_zone`, following its
+    /// daylight saving changes
+    ///
+    /// Get the zone with e.g. `TimeZone::get("Europe/Berlin")`.
+    #[must_use]
+    pub fn time_zone(mut self, time_zone: TimeZone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Start of the day containing `time`
+    fn day_start(&self, time: SystemTime) -> Result<SystemTime> {
+        let (start, _) = local_day(&self.time_zone, time)?;
+        Ok(SystemTime::from(start.timestamp()))
+    }
+
+    fn write_report(&mut self) -> Result<()> {
+        let Some(day) = self.day else {
+            return Ok(());
+        };
+        let mut summary =
+            DailySummary::compute_in(&self.time_zone, day, &self.measurements, &self.options)?;
+        summary.metadata = self.metadata.clone();
+
+        let (start, _) = local_day(&self.time_zone, day)?;
+        let date = start.date();
+
+        let mut file = BufWriter::new(File::create(self.dir.join(format!("{date}.json")))?);
+        summary.write_json(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Sink for DailyReports {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.metadata = metadata.clone();
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let day = self.day_start(measurement.timestamp)?;
+        if self.day.is_some_and(|current| current != day) {
+            self.write_report()?;
+            self.measurements.clear();
+        }
+        self.day = Some(day);
+        self.measurements.push(*measurement);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_report()
+    }
+}
+
+/// Local midnights starting and ending the day in `time_zone` containing
+/// `time`
+fn local_day(time_zone: &TimeZone, time: SystemTime) -> Result<(Zoned, Zoned)> {
+    let time = jiff::Timestamp::try_from(time).map_err(time_error)?;
+    let start = time
+        .to_zoned(time_zone.clone())
+        .start_of_day()
+        .map_err(time_error)?;
+    let end = start
+        .tomorrow()
+        .map_err(time_error)?
+        .start_of_day()
+        .map_err(time_error)?;
+    Ok((start, end))
+}
+
+fn time_error(e: jiff::Error) -> NsrtError {
+    NsrtError::InvalidParameter(format!("Time out of range: {e}"))
+}
+
+/// Statistics over one window of an [`Analysis`]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Statistics {
+    /// Start of the window
+    #[serde(with = "humantime_serde")]
+    pub start: SystemTime,
+    /// End of the window, exclusive, or the last measurement of a window
+    /// running to the end of the log
+    #[serde(with = "humantime_serde")]
+    pub end: SystemTime,
+    /// Number of measurements in the window
+    pub measurements: usize,
+    /// Quality flags of all the measurements in the window
+    #[serde(default, skip_serializing_if = "Quality::is_good")]
+    pub quality: Quality,
+    /// LEQ over the measured time in dB
+    pub leq: Option<f32>,
+    /// Highest running level in dB
+    pub lmax: Option<f32>,
+    /// Lowest running level in dB
+    pub lmin: Option<f32>,
+    /// Requested exceedance levels of the running level, in the order given
+    /// to [`Analysis::exceedance`]
+    pub exceedance: Vec<ExceedanceLevel>,
+    /// Periods during which the running level exceeded the event threshold
+    pub events: Vec<LmaxEvent>,
+}
+
+impl Statistics {
+    /// Level exceeded `n` percent of the time, if it was requested
+    pub fn ln(&self, n: f32) -> Option<f32> {
+        self.exceedance
+            .iter()
+            .find(|exceedance| exceedance.n == n)
+            .map(|exceedance| exceedance.level)
+    }
+
+    /// Total time the running level exceeded the event threshold, from the
+    /// first to the last measurement of each event
+    pub fn event_time(&self) -> Duration {
+        self.events
+            .iter()
+            .map(|event| event.end.duration_since(event.start).unwrap_or_default())
+            .sum()
+    }
+}
+
+/// Level exceeded `n` percent of the time (Ln)
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ExceedanceLevel {
+    /// Percentage of the time, e.g. 90 for L90
+    pub n: f32,
+    /// Level in dB
+    pub level: f32,
+}
+
+/// Offline statistics over a stored log
+///
+/// Reads measurements from any log reader, such as
+/// [`CsvReader`](crate::csv::CsvReader) or
+/// [`BinaryLogReader`](crate::binlog::BinaryLogReader), keeps those in the
+/// time range and splits them into windows aligned to the start of the
+/// range, or of the log if the range has no start. Only one window is held
+/// in memory at a time.
+///
+/// ```no_run
+/// # fn main() -> nsrt::Result<()> {
+/// use nsrt::{csv::CsvReader, report::Analysis};
+/// use std::time::Duration;
+///
+/// let windows = Analysis::new()
+///     .window(Duration::from_secs(15 * 60))
+///     .exceedance(&[1.0, 10.0, 90.0])
+///     .run(CsvReader::open("survey.csv")?)?;
+/// for window in windows {
+///     println!("{:?} L90 {:?}", window.start, window.ln(90.0));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    start: Option<SystemTime>,
+    end: Option<SystemTime>,
+    window: Option<Duration>,
+    exceedance: Vec<f32>,
+    options: SummaryOptions,
+}
+
+impl Analysis {
+    /// Analyze the whole log as one window, with L10, L50 and L90
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            end: None,
+            window: None,
+            exceedance: vec![10.0, 50.0, 90.0],
+            options: SummaryOptions::default(),
+        }
+    }
+
+    /// Ignore measurements before `start`
+    #[must_use]
+    pub fn start(mut self, start: SystemTime) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Ignore measurements from `end` on
+    #[must_use]
+    pub fn end(mut self, end: SystemTime) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Split the range into consecutive windows of `length`
+    ///
+    /// # Panics
+    ///
+    /// If `length` is zero.
+    #[must_use]
+    pub fn window(mut self, length: Duration) -> Self {
+        assert!(!length.is_zero(), "window length must not be zero");
+        self.window = Some(length);
+        self
+    }
+
+    /// Compute the levels exceeded `n` percent of the time for each `n`
+    #[must_use]
+    pub fn exceedance(mut self, n: &[f32]) -> Self {
+        self.exceedance = n.to_vec();
+        self
+    }
+
+    /// Set the event threshold and the longest gap counted as measured time
+    ///
+    /// The dose criteria are not used.
+    #[must_use]
+    pub fn options(mut self, options: SummaryOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Compute the statistics of every window with measurements, in order
+    ///
+    /// The measurements must be in chronological order. Each LEQ covers the
+    /// time since the previous measurement, and counts toward a window for
+    /// the part of that time inside it. Stops at the first read error.
+    pub fn run(
+        &self,
+        measurements: impl IntoIterator<Item = Result<Measurement>>,
+    ) -> Result<Vec<Statistics>> {
+        let mut windows = Vec::new();
+        let mut origin = self.start;
+        // Window bounds, and the time of the measurement before its first
+        let mut current: Option<(SystemTime, Option<SystemTime>, Option<SystemTime>)> = None;
+        let mut previous = None;
+        let mut buffer = Vec::new();
+
+        for measurement in measurements {
+            let measurement = measurement?;
+            let time = measurement.timestamp;
+            if self.start.is_some_and(|start| time < start)
+                || self.end.is_some_and(|end| time >= end)
+            {
+                previous = Some(time);
+                continue;
+            }
+
+            let origin = *origin.get_or_insert(time);
+            if current.is_none_or(|(_, end, _)| end.is_some_and(|end| time >= end)) {
+                if let Some((start, end, before)) = current {
+                    windows.push(self.statistics(start, end, before, &mem::take(&mut buffer)));
+                }
+                let (start, end) = self.window_containing(origin, time);
+                current = Some((start, end, previous));
+            }
+            buffer.push(measurement);
+            previous = Some(time);
+        }
+        if let Some((start, end, before)) = current {
+            windows.push(self.statistics(start, end, before, &buffer));
+        }
+        Ok(windows)
+    }
+
+    /// Bounds of the window containing `time`, with windows aligned to
+    /// `origin`
+    fn window_containing(
+        &self,
+        origin: SystemTime,
+        time: SystemTime,
+    ) -> (SystemTime, Option<SystemTime>) {
+        let Some(length) = self.window else {
+            return (origin, self.end);
+        };
+        let elapsed = time.duration_since(origin).unwrap_or_default();
+        let index = elapsed.as_nanos() / length.as_nanos();
+        let start = origin + length * u32::try_from(index).unwrap_or(u32::MAX);
+        let end = start + length;
+        (start, Some(self.end.map_or(end, |limit| end.min(limit))))
+    }
+
+    /// Statistics of the measurements of the window from `start`, the first
+    /// taken after one at `previous`
+    fn statistics(
+        &self,
+        start: SystemTime,
+        end: Option<SystemTime>,
+        previous: Option<SystemTime>,
+        window: &[Measurement],
+    ) -> Statistics {
+        let window: Vec<&Measurement> = window.iter().collect();
+        let durations: Vec<Duration> = window
+            .iter()
+            .scan(previous, |previous, m| {
+                let since = |time: SystemTime| m.timestamp.duration_since(time).unwrap_or_default();
+                let duration = match previous.replace(m.timestamp) {
+                    Some(previous) if since(previous) <= self.options.max_gap => {
+                        since(previous.max(start))
+                    }
+                    _ => Duration::ZERO,
+                };
+                Some(duration)
+            })
+            .collect();
+        let levels = stats::sorted_levels(window.iter().map(|m| m.level));
+
+        Statistics {
+            start,
+            end: end.unwrap_or_else(|| window.last().map_or(start, |m| m.timestamp)),
+            measurements: window.len(),
+            quality: window
+                .iter()
+                .fold(Quality::GOOD, |quality, m| quality | m.quality),
+            leq: stats::energy_average(
+                window
+                    .iter()
+                    .zip(&durations)
+                    .map(|(m, duration)| (m.leq, duration.as_secs_f64())),
+            ),
+            lmax: levels.last().copied(),
+            lmin: levels.first().copied(),
+            exceedance: self
+                .exceedance
+                .iter()
+                .filter_map(|&n| {
+                    stats::exceedance_level(&levels, n).map(|level| ExceedanceLevel { n, level })
+                })
+                .collect(),
+            events: self
+                .options
+                .event_threshold
+                .map(|threshold| events(&window, threshold))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{Compensated, Measurement, Metadata, Result, Sink, Temperature};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How a [`Resampler`] derives a grid point from the measurements around it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Interpolation {
+    /// The measurement closest in time, the earlier one on a tie
+    Nearest,
+    /// Linear between the two measurements, in the energy domain for levels
+    #[default]
+    Linear,
+}
+
+/// Sink moving measurements onto a fixed time grid before passing them on
+///
+/// Grid points are the multiples of `step` since the Unix epoch, so the
+/// output of several devices, and of windows computed from it downstream,
+/// line up exactly. Each grid point between two consecutive measurements is
+/// written once both are known, stamped with the grid time. Levels are
+/// interpolated as energies, `10^(L/10)`, and the temperature linearly; the
+/// clock status is taken from the nearer measurement, and the quality flags
+/// from both.
+///
+/// Grid points in gaps longer than [`Resampler::max_gap`], such as a device
+/// outage, are left out rather than made up. A timestamp going backwards
+/// writes nothing until the measurements pass the last grid point written.
+pub struct Resampler<S> {
+    sink: S,
+    step: u128,
+    interpolation: Interpolation,
+    max_gap: Option<Duration>,
+    previous: Option<Measurement>,
+    /// Index of the next grid point to write
+    next: u128,
+}
+
+impl<S: Sink> Resampler<S> {
+    /// Resample onto a grid of `step` before writing to `sink`
+    ///
+    /// # Panics
+    ///
+    /// If `step` is zero.
+    pub fn new(sink: S, step: Duration, interpolation: Interpolation) -> Self {
+        assert!(!step.is_zero(), "resampling step must not be zero");
+        Self {
+            sink,
+            step: step.as_nanos(),
+            interpolation,
+            max_gap: None,
+            previous: None,
+            next: 0,
+        }
+    }
+
+    /// Leave out grid points between measurements more than `max_gap` apart
+    ///
+    /// Unlimited by default.
+    #[must_use]
+    pub fn max_gap(mut self, max_gap: Duration) -> Self {
+        self.max_gap = Some(max_gap);
+        self
+    }
+
+    /// The inner sink
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    /// The measurement at grid time `at` between `before` and `after`
+    fn interpolate(&self, before: &Measurement, after: &Measurement, at: u128) -> Measurement {
+        let start = nanos(before.timestamp);
+        let span = nanos(after.timestamp) - start;
+        let fraction = if span == 0 {
+            0.0
+        } else {
+            (at - start) as f64 / span as f64
+        };
+        let nearest = if fraction <= 0.5 { before } else { after };
+        let timestamp = UNIX_EPOCH + Duration::from_nanos(u64::try_from(at).unwrap_or(u64::MAX));
+
+        match self.interpolation {
+            Interpolation::Nearest => Measurement {
+                timestamp,
+                quality: before.quality | after.quality,
+                ..*nearest
+            },
+            Interpolation::Linear => Measurement {
+                timestamp,
+                level: energy_lerp(before.level, after.level, fraction),
+                leq: energy_lerp(before.leq, after.leq, fraction),
+                temperature: Temperature::from_celsius(lerp(
+                    before.temperature.as_celsius(),
+                    after.temperature.as_celsius(),
+                    fraction,
+                )),
+                clock: nearest.clock,
+                compensated: match (before.compensated, after.compensated) {
+                    (Some(before), Some(after)) => Some(Compensated {
+                        level: energy_lerp(before.level, after.level, fraction),
+                        leq: energy_lerp(before.leq, after.leq, fraction),
+                    }),
+                    _ => nearest.compensated,
+                },
+                quality: before.quality | after.quality,
+            },
+        }
+    }
+}
+
+impl<S: Sink> Sink for Resampler<S> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.sink.set_metadata(metadata)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        let time = nanos(measurement.timestamp);
+        let previous = match self.previous.replace(*measurement) {
+            Some(previous) => previous,
+            None => {
+                self.next = time.div_ceil(self.step);
+                *measurement
+            }
+        };
+        let start = nanos(previous.timestamp);
+        let in_gap = self
+            .max_gap
+            .is_some_and(|max_gap| time.saturating_sub(start) > max_gap.as_nanos());
+
+        while self.next * self.step <= time {
+            let at = self.next * self.step;
+            if at >= start && !in_gap {
+                let resampled = self.interpolate(&previous, measurement, at);
+                self.sink.write(&resampled)?;
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sink.flush()
+    }
+}
+
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+fn lerp(a: f32, b: f32, fraction: f64) -> f32 {
+    (f64::from(a) + (f64::from(b) - f64::from(a)) * fraction) as f32
+}
+
+/// Interpolate between levels `a` and `b` in dB as energies
+fn energy_lerp(a: f32, b: f32, fraction: f64) -> f32 {
+    let energy = |level: f32| 10f64.powf(f64::from(level) / 10.0);
+    let mixed = energy(a) + (energy(b) - energy(a)) * fraction;
+    (10.0 * mixed.log10()) as f32
+}
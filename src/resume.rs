@@ -0,0 +1,79 @@
+use crate::{MockTransport, NSRT, NsrtError, Result};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Shortest gap between the wall and monotonic clocks taken as a sleep
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Notices that the host slept since the previous check
+///
+/// The monotonic clock stops while the host is suspended, on Linux and macOS
+/// at least, but the wall clock doesn't, so a sleep shows as the wall clock
+/// running ahead. A forward step of the wall clock by more than
+/// [`SLEEP_THRESHOLD`] looks the same; taking it for a sleep only costs a
+/// needless [`NSRT::resume`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SleepDetector {
+    instant: Instant,
+    system: SystemTime,
+}
+
+impl SleepDetector {
+    /// Start watching now
+    pub(crate) fn new() -> Self {
+        Self {
+            instant: Instant::now(),
+            system: SystemTime::now(),
+        }
+    }
+
+    /// How long the host slept since the previous check, if it did
+    pub(crate) fn check(&mut self) -> Option<Duration> {
+        let previous = std::mem::replace(self, Self::new());
+        let monotonic = self.instant - previous.instant;
+        let wall = self
+            .system
+            .duration_since(previous.system)
+            .unwrap_or_default();
+        let slept = wall.saturating_sub(monotonic);
+        (slept >= SLEEP_THRESHOLD).then_some(slept)
+    }
+}
+
+impl NSRT {
+    /// Close the port and open it again with the same settings
+    ///
+    /// Only for devices opened from a port path, as by [`NSRT::open`] and
+    /// [`NSRT::open_port`]. The identity cached by [`NSRT::info_cached`] is
+    /// dropped.
+    pub fn reconnect(&mut self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::reconnect();
+        let (path, settings) = self.origin.clone().ok_or_else(|| {
+            NsrtError::InvalidParameter("Device was not opened from a port".to_string())
+        })?;
+        // Release the old port first, as serial ports are opened
+        // exclusively; an unscripted mock stands in meanwhile
+        self.port = Box::new(MockTransport::new());
+        self.port = Self::open_port_with(&path, &settings)?.port;
+        self.invalidate_info();
+        Ok(())
+    }
+
+    /// Restore the link after the host slept
+    ///
+    /// Checks that the device still answers after a [`NSRT::resync`], and if
+    /// not, as when USB autosuspend or a power loss re-enumerated it,
+    /// [reconnects](NSRT::reconnect). The settings last applied with
+    /// [`NSRT::configure`] are then applied again, in case the device was
+    /// reset. The [`Sampler`](crate::Sampler) does this by itself.
+    pub fn resume(&mut self) -> Result<()> {
+        if self.resync().is_err() {
+            self.reconnect()?;
+            self.resync()?;
+        }
+        if let Some(config) = self.config {
+            self.configure(&config)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{NsrtError, Result};
+
+/// Nominal centre frequencies of the octave bands in Hz
+pub const OCTAVE_BANDS: [f32; 10] = [
+    16.0, 31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0,
+];
+
+/// NC curves from NC-15 to NC-70, as levels in dB in the bands from 63 Hz to
+/// 8 kHz
+const NC_CURVES: [(u8, [f32; 8]); 12] = [
+    (15, [47.0, 36.0, 29.0, 22.0, 17.0, 14.0, 12.0, 11.0]),
+    (20, [51.0, 40.0, 33.0, 26.0, 22.0, 19.0, 17.0, 16.0]),
+    (25, [54.0, 44.0, 37.0, 31.0, 27.0, 24.0, 22.0, 21.0]),
+    (30, [57.0, 48.0, 41.0, 35.0, 31.0, 29.0, 28.0, 27.0]),
+    (35, [60.0, 52.0, 45.0, 40.0, 36.0, 34.0, 33.0, 32.0]),
+    (40, [64.0, 56.0, 50.0, 45.0, 41.0, 39.0, 38.0, 37.0]),
+    (45, [67.0, 60.0, 54.0, 49.0, 46.0, 44.0, 43.0, 42.0]),
+    (50, [71.0, 64.0, 58.0, 54.0, 51.0, 49.0, 48.0, 47.0]),
+    (55, [74.0, 67.0, 62.0, 58.0, 56.0, 54.0, 53.0, 52.0]),
+    (60, [77.0, 71.0, 67.0, 63.0, 61.0, 59.0, 58.0, 57.0]),
+    (65, [80.0, 75.0, 71.0, 68.0, 66.0, 64.0, 63.0, 62.0]),
+    (70, [83.0, 79.0, 75.0, 72.0, 71.0, 70.0, 69.0, 68.0]),
+];
+
+/// Index in [`OCTAVE_BANDS`] of the first band of the NC curves, 63 Hz
+const NC_FIRST_BAND: usize = 2;
+
+/// Octave-band levels in dB of a room's background noise
+///
+/// The meter measures broadband levels only, so the bands come from an
+/// octave-band analyzer or filter set. Bands not measured are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OctaveBands {
+    /// Levels in the bands of [`OCTAVE_BANDS`], from 16 Hz to 8 kHz
+    pub levels: [Option<f32>; 10],
+}
+
+/// Noise Criterion rating of a room
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NcRating {
+    /// Lowest NC curve no band exceeds, interpolated between the tabulated
+    /// curves and rounded up
+    pub rating: u8,
+    /// Centre frequency in Hz of the band that sets the rating
+    pub governing_band: f32,
+}
+
+/// Character of a room's noise by the RC Mark II method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum RcQuality {
+    /// Balanced, close to the reference curve
+    Neutral,
+    /// Rumble, excess in the 16 to 63 Hz bands
+    Rumble,
+    /// Rumble with a moderate chance of perceptible vibration in light
+    /// walls and ceilings, from 65 dB in the 16 or 31.5 Hz band
+    RumbleVibrationModerate,
+    /// Rumble with a clear chance of perceptible vibration, from 75 dB in
+    /// the 16 or 31.5 Hz band
+    RumbleVibrationClear,
+    /// Roar, excess in the 125 to 500 Hz bands
+    Roar,
+    /// Hiss, excess in the 1 to 4 kHz bands
+    Hiss,
+}
+
+/// Room Criterion Mark II rating of a room
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RcRating {
+    /// Mean of the 500 Hz, 1 kHz and 2 kHz levels, rounded
+    pub rating: u8,
+    /// Character of the noise
+    pub quality: RcQuality,
+    /// Quality assessment index: the spread in dB of the excesses over the
+    /// reference curve in the low, middle and high regions, neutral up to 5
+    pub qai: f32,
+}
+
+impl OctaveBands {
+    /// Bands with the levels from 16 Hz to 8 kHz
+    pub fn new(levels: [Option<f32>; 10]) -> Self {
+        Self { levels }
+    }
+
+    /// Level in the band centred on `frequency` Hz, if measured
+    pub fn level(&self, frequency: f32) -> Option<f32> {
+        let band = OCTAVE_BANDS.iter().position(|&f| f == frequency)?;
+        self.levels[band]
+    }
+
+    /// NC rating from the bands from 63 Hz to 8 kHz
+    ///
+    /// Each band is rated by interpolating between the curves, and beyond
+    /// them along the nearest two; the rating is that of the worst band.
+    /// Fails if none of the bands is measured.
+    pub fn nc(&self) -> Result<NcRating> {
+        let (rating, band) = self.levels[NC_FIRST_BAND..]
+            .iter()
+            .enumerate()
+            .filter_map(|(band, level)| Some((nc_of_band(band, (*level)?), band)))
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .ok_or_else(|| {
+                NsrtError::InvalidParameter("NC needs a band from 63 Hz to 8 kHz".to_string())
+            })?;
+        Ok(NcRating {
+            // Levels right on a curve rate as that curve despite rounding
+            rating: (rating - 1e-3).max(0.0).ceil() as u8,
+            governing_band: OCTAVE_BANDS[NC_FIRST_BAND + band],
+        })
+    }
+
+    /// RC Mark II rating from the bands from 16 Hz to 4 kHz
+    ///
+    /// The reference curve falls 5 dB per octave through the rating at
+    /// 1 kHz. The excesses over it are energy-averaged in the low (16 to
+    /// 63 Hz), middle (125 to 500 Hz) and high (1 to 4 kHz) regions; with a
+    /// spread of more than 5 dB the noise takes the character of the region
+    /// with the largest excess. Fails unless the 500 Hz, 1 kHz and 2 kHz
+    /// bands are measured.
+    pub fn rc(&self) -> Result<RcRating> {
+        let speech = [500.0, 1000.0, 2000.0]
+            .map(|frequency| self.level(frequency))
+            .into_iter()
+            .collect::<Option<Vec<f32>>>()
+            .ok_or_else(|| {
+                NsrtError::InvalidParameter(
+                    "RC needs the 500 Hz, 1 kHz and 2 kHz bands".to_string(),
+                )
+            })?;
+        let rating = (speech.iter().sum::<f32>() / 3.0).round();
+
+        let excess = |frequencies: &[f32]| {
+            let excesses: Vec<f64> = frequencies
+                .iter()
+                .filter_map(|&frequency| {
+                    let reference = rating - 5.0 * (frequency / 1000.0).log2();
+                    Some(f64::from(self.level(frequency)? - reference))
+                })
+                .collect();
+            (!excesses.is_empty()).then(|| {
+                let energy = excesses.iter().map(|e| 10f64.powf(e / 10.0)).sum::<f64>();
+                (10.0 * (energy / excesses.len() as f64).log10()) as f32
+            })
+        };
+        let regions = [
+            (RcQuality::Rumble, excess(&[16.0, 31.5, 63.0])),
+            (RcQuality::Roar, excess(&[125.0, 250.0, 500.0])),
+            (RcQuality::Hiss, excess(&[1000.0, 2000.0, 4000.0])),
+        ];
+        let measured = regions
+            .iter()
+            .filter_map(|(quality, e)| Some((*quality, (*e)?)));
+        let highest = measured.clone().max_by(|(_, a), (_, b)| a.total_cmp(b));
+        let lowest = measured.map(|(_, e)| e).reduce(f32::min);
+        let qai = highest
+            .zip(lowest)
+            .map_or(0.0, |((_, high), low)| high - low);
+
+        let quality = match highest {
+            Some((RcQuality::Rumble, _)) if qai > 5.0 => {
+                let vibration = [16.0, 31.5]
+                    .iter()
+                    .filter_map(|&frequency| self.level(frequency))
+                    .fold(f32::NEG_INFINITY, f32::max);
+                if vibration >= 75.0 {
+                    RcQuality::RumbleVibrationClear
+                } else if vibration >= 65.0 {
+                    RcQuality::RumbleVibrationModerate
+                } else {
+                    RcQuality::Rumble
+                }
+            }
+            Some((quality, _)) if qai > 5.0 => quality,
+            _ => RcQuality::Neutral,
+        };
+
+        Ok(RcRating {
+            rating: rating.max(0.0) as u8,
+            quality,
+            qai,
+        })
+    }
+}
+
+/// NC of `level` dB in band `band` of the NC curves
+fn nc_of_band(band: usize, level: f32) -> f32 {
+    // The pair of adjacent curves around the level, or the outermost pair
+    let above = NC_CURVES
+        .iter()
+        .position(|(_, curve)| curve[band] >= level)
+        .unwrap_or(NC_CURVES.len() - 1)
+        .max(1);
+    let (low_nc, low) = NC_CURVES[above - 1];
+    let (high_nc, high) = NC_CURVES[above];
+    let fraction = (level - low[band]) / (high[band] - low[band]);
+    f32::from(low_nc) + fraction * f32::from(high_nc - low_nc)
+}
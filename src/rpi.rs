@@ -0,0 +1,110 @@
+//! Raspberry Pi GPIO alarm output
+//!
+//! [`GpioAlarm`] drives a GPIO pin while the level exceeds a [`Threshold`],
+//! so a warning light, buzzer or relay can be wired straight to the Pi. Pins
+//! are numbered by BCM GPIO number, not physical header position.
+
+use crate::{Measurement, NsrtError, Result, Sink, Threshold, ThresholdEvent, ThresholdMonitor};
+use rppal::gpio::{Gpio, OutputPin};
+use std::{
+    io,
+    time::{Duration, SystemTime},
+};
+
+/// Sink driving a GPIO pin while a threshold is exceeded
+///
+/// The pin is switched on when the level rises above the threshold and off
+/// once it has cleared, but never before it has been on for the hold time,
+/// so short peaks still give a visible flash. The pin is switched off when
+/// the sink is dropped.
+pub struct GpioAlarm {
+    pin: OutputPin,
+    monitor: ThresholdMonitor,
+    hold: Duration,
+    active_low: bool,
+    /// Time the pin was last switched on, while it is on
+    on_since: Option<SystemTime>,
+}
+
+impl GpioAlarm {
+    /// Drive BCM GPIO `pin` high while `threshold` is exceeded
+    pub fn new(pin: u8, threshold: Threshold) -> Result<Self> {
+        let pin = Gpio::new()
+            .and_then(|gpio| gpio.get(pin))
+            .map_err(gpio_error)?
+            .into_output_low();
+
+        Ok(Self {
+            pin,
+            monitor: ThresholdMonitor::new(threshold),
+            hold: Duration::ZERO,
+            active_low: false,
+            on_since: None,
+        })
+    }
+
+    /// Keep the pin on for at least `hold` after it is switched on
+    #[must_use]
+    pub fn hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Drive the pin low instead of high when on, as many relay boards expect
+    #[must_use]
+    pub fn active_low(mut self, active_low: bool) -> Self {
+        self.active_low = active_low;
+        self.set(self.on_since.is_some());
+        self
+    }
+
+    /// Whether the pin is currently switched on
+    pub fn is_on(&self) -> bool {
+        self.on_since.is_some()
+    }
+
+    fn set(&mut self, on: bool) {
+        if on != self.active_low {
+            self.pin.set_high();
+        } else {
+            self.pin.set_low();
+        }
+    }
+}
+
+impl Sink for GpioAlarm {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        match self.monitor.update(measurement) {
+            Some(ThresholdEvent::Exceeded(m)) => {
+                if self.on_since.is_none() {
+                    self.set(true);
+                }
+                self.on_since = Some(m.timestamp);
+            }
+            Some(ThresholdEvent::Cleared(_)) | None => {}
+        }
+
+        // Clearing may have happened before the hold time was up
+        if let Some(since) = self.on_since
+            && !self.monitor.is_exceeded()
+            && measurement
+                .timestamp
+                .duration_since(since)
+                .is_ok_and(|on| on >= self.hold)
+        {
+            self.set(false);
+            self.on_since = None;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GpioAlarm {
+    fn drop(&mut self) {
+        self.set(false);
+    }
+}
+
+fn gpio_error(error: rppal::gpio::Error) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
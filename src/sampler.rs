@@ -0,0 +1,471 @@
+use crate::{
+    DeviceHandle, DropPolicy, DutyCycle, Measurement, Metadata, NSRT, Priority, Quality, Result,
+    Sink, Subscription, SystemClock, TimeSource, duty_cycle::Burst, resume::SleepDetector,
+    subscription::Channel,
+};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Measurements buffered for a subscriber by default
+const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// Failed reads in a row retried before sampling stops
+pub(crate) const RETRIES: u32 = 3;
+
+/// Disagreement between the wall and monotonic clocks taken as a clock step
+const CLOCK_STEP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Background sampler polling the device at a fixed interval
+///
+/// The sampler reads a [`Measurement`] on every tick and hands it to all
+/// subscribers without waiting for them, so a slow subscriber loses
+/// measurements rather than delaying acquisition. Other operations can be
+/// interleaved with sampling through [`Sampler::device`]. A read failing
+/// with a retriable error, see
+/// [`NsrtError::is_retriable`](crate::NsrtError::is_retriable), is tried
+/// again on the next tick after [`NSRT::resume`], up to 3 times in a row. The
+/// sampler stops at any other read error, or the last retried one; the error
+/// is returned from [`Sampler::stop`].
+///
+/// When the host wakes from sleep the sampler resumes the device before the
+/// next read and reports it as [`SamplerEvent::Resumed`].
+///
+/// Measurements are flagged with their [`Quality`]: missing samples when more
+/// than one and a half intervals passed since the previous one, reconnected
+/// after a resume, and a clock step when the timestamps moved more than a
+/// second differently from the host's monotonic clock.
+///
+/// A sampler started with [`Sampler::start_duty_cycled`] measures in bursts
+/// instead, see [`DutyCycle`].
+pub struct Sampler {
+    device: DeviceHandle,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+struct Shared {
+    running: AtomicBool,
+    interval: Mutex<Duration>,
+    duty_cycle: Mutex<Option<DutyCycle>>,
+    latest: Mutex<Option<Measurement>>,
+    metadata: Mutex<Metadata>,
+    time_source: Mutex<Arc<dyn TimeSource>>,
+    subscribers: Mutex<Vec<Arc<Channel>>>,
+    events: Mutex<Vec<Sender<SamplerEvent>>>,
+}
+
+/// Something that happened to a [`Sampler`] besides taking measurements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SamplerEvent {
+    /// The host woke from sleep and the device was resumed, see
+    /// [`NSRT::resume`]
+    Resumed {
+        /// Roughly how long the host slept
+        slept: Duration,
+    },
+}
+
+impl Sampler {
+    /// Start sampling the device every `interval`
+    pub fn start(device: impl Into<DeviceHandle>, interval: Duration) -> Self {
+        Self::spawn(device.into(), interval, None)
+    }
+
+    /// Start measuring the device in bursts, reporting one measurement per
+    /// burst
+    ///
+    /// The interval is set to the period, for when the duty cycle is turned
+    /// off with [`Sampler::set_duty_cycle`].
+    pub fn start_duty_cycled(
+        device: impl Into<DeviceHandle>,
+        duty_cycle: DutyCycle,
+    ) -> Result<Self> {
+        duty_cycle.validate()?;
+        Ok(Self::spawn(
+            device.into(),
+            duty_cycle.period,
+            Some(duty_cycle),
+        ))
+    }
+
+    fn spawn(device: DeviceHandle, interval: Duration, duty_cycle: Option<DutyCycle>) -> Self {
+        let shared = Arc::new(Shared {
+            running: AtomicBool::new(true),
+            interval: Mutex::new(interval),
+            duty_cycle: Mutex::new(duty_cycle),
+            latest: Mutex::new(None),
+            metadata: Mutex::new(Metadata::default()),
+            time_source: Mutex::new(Arc::new(SystemClock)),
+            subscribers: Mutex::new(Vec::new()),
+            events: Mutex::new(Vec::new()),
+        });
+
+        let thread = {
+            let device = device.clone();
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&device, &shared))
+        };
+
+        Self {
+            device,
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Handle to the sampled device, for configuration and other reads
+    pub fn device(&self) -> &DeviceHandle {
+        &self.device
+    }
+
+    /// Subscribe to every measurement taken from now on
+    ///
+    /// Up to 1024 measurements are buffered, after which the oldest are
+    /// dropped. The subscription ends when the sampler stops.
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribe_with(SUBSCRIPTION_CAPACITY, DropPolicy::DropOldest)
+    }
+
+    /// Subscribe with a buffer of `capacity` measurements, dropping per
+    /// `policy` once it is full
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn subscribe_with(&self, capacity: usize, policy: DropPolicy) -> Subscription {
+        self.add_subscriber(Channel::bounded(capacity, policy))
+    }
+
+    /// Subscribe without dropping anything, for consumers that keep every
+    /// measurement anyway
+    pub(crate) fn subscribe_unbounded(&self) -> Subscription {
+        self.add_subscriber(Channel::unbounded())
+    }
+
+    fn add_subscriber(&self, channel: Arc<Channel>) -> Subscription {
+        lock(&self.shared.subscribers).push(Arc::clone(&channel));
+        Subscription::new(channel)
+    }
+
+    /// Receive the events happening from now on
+    ///
+    /// Events are rare, so they are buffered without limit. The receiver
+    /// disconnects when the sampler stops.
+    pub fn events(&self) -> Receiver<SamplerEvent> {
+        let (tx, rx) = mpsc::channel();
+        lock(&self.shared.events).push(tx);
+        rx
+    }
+
+    /// Forward every measurement into a tokio broadcast channel
+    ///
+    /// Receivers that fall more than `capacity` measurements behind skip the
+    /// oldest ones.
+    #[cfg(feature = "tokio")]
+    pub fn broadcast(&self, capacity: usize) -> tokio::sync::broadcast::Sender<Measurement> {
+        let (tx, _) = tokio::sync::broadcast::channel(capacity);
+
+        let rx = self.subscribe();
+        let forward = tx.clone();
+        thread::spawn(move || {
+            for measurement in rx {
+                // Sending only fails while there are no receivers
+                let _ = forward.send(measurement);
+            }
+        });
+
+        tx
+    }
+
+    /// Change the time between measurements
+    ///
+    /// Takes effect immediately: the next measurement is due `interval` after
+    /// the previous one, or right away if that time has passed.
+    pub fn set_interval(&self, interval: Duration) {
+        *lock(&self.shared.interval) = interval;
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+
+    /// The time between measurements
+    pub fn interval(&self) -> Duration {
+        *lock(&self.shared.interval)
+    }
+
+    /// Measure in bursts, or every interval again with `None`
+    ///
+    /// Takes effect after the current burst or interval: the next burst
+    /// starts a period after the previous measurement started.
+    pub fn set_duty_cycle(&self, duty_cycle: Option<DutyCycle>) -> Result<()> {
+        if let Some(duty_cycle) = &duty_cycle {
+            duty_cycle.validate()?;
+        }
+        *lock(&self.shared.duty_cycle) = duty_cycle;
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+        Ok(())
+    }
+
+    /// The bursts measured, if duty cycled
+    pub fn duty_cycle(&self) -> Option<DutyCycle> {
+        *lock(&self.shared.duty_cycle)
+    }
+
+    /// Set the source of measurement timestamps, [`SystemClock`] by default
+    ///
+    /// Use [`KernelClock`](crate::KernelClock) to record the NTP
+    /// synchronization status with every measurement.
+    pub fn set_time_source(&self, time_source: impl TimeSource + 'static) {
+        *lock(&self.shared.time_source) = Arc::new(time_source);
+    }
+
+    /// Set the metadata describing the measurement setup
+    ///
+    /// Applies to sessions recorded and sinks attached from now on.
+    pub fn set_metadata(&self, metadata: Metadata) {
+        *lock(&self.shared.metadata) = metadata;
+    }
+
+    /// The metadata describing the measurement setup
+    pub fn metadata(&self) -> Metadata {
+        lock(&self.shared.metadata).clone()
+    }
+
+    /// Write every measurement to `sink` on a background thread
+    ///
+    /// The sink is given the sampler's metadata first. Measurements are
+    /// buffered without limit, so a slow sink falls behind but loses none.
+    /// The thread flushes the sink and exits when the sampler stops, or at
+    /// the first write error, which is returned when joining it.
+    pub fn attach<S: Sink + Send + 'static>(&self, mut sink: S) -> JoinHandle<Result<()>> {
+        let rx = self.subscribe_unbounded();
+        let metadata = self.metadata();
+        thread::spawn(move || {
+            sink.set_metadata(&metadata)?;
+            for measurement in rx {
+                sink.write(&measurement)?;
+            }
+            sink.flush()
+        })
+    }
+
+    /// The most recent measurement, if any has been taken yet
+    pub fn latest(&self) -> Option<Measurement> {
+        *lock(&self.shared.latest)
+    }
+
+    /// Whether the sampling thread is still running
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    /// Stop sampling
+    ///
+    /// Returns the error that stopped the sampler if it failed on its own.
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown().unwrap_or(Ok(()))
+    }
+
+    fn shutdown(&mut self) -> Option<Result<()>> {
+        self.shared.running.store(false, Ordering::Relaxed);
+        let thread = self.thread.take()?;
+        thread.thread().unpark();
+        Some(thread.join().expect("sampler thread panicked"))
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn run(device: &DeviceHandle, shared: &Shared) -> Result<()> {
+    let mut next = Instant::now();
+    let mut failures = 0;
+    let mut sleep = SleepDetector::new();
+    let mut quality = QualityTracker::default();
+
+    while shared.running.load(Ordering::Relaxed) {
+        if let Some(slept) = sleep.check() {
+            // Nothing was sampled while asleep, and the clocks parted ways
+            quality.slept();
+            // A failed resume shows in the next read
+            if device.call(NSRT::resume).is_ok() {
+                emit(shared, SamplerEvent::Resumed { slept });
+            }
+        }
+
+        let time_source = Arc::clone(&lock(&shared.time_source));
+        let polled = Instant::now();
+        let duty_cycle = *lock(&shared.duty_cycle);
+        let measured = match &duty_cycle {
+            Some(duty_cycle) => burst(device, shared, duty_cycle, time_source),
+            None => {
+                let read = move |nsrt: &mut NSRT| nsrt.read_measurement_with(&*time_source);
+                device.call_with_priority(Priority::Low, read).map(Some)
+            }
+        };
+        match measured {
+            // Stopped during a burst
+            Ok(None) => {}
+            Ok(Some(mut measurement)) => {
+                failures = 0;
+                let interval = duty_cycle.map_or_else(|| *lock(&shared.interval), |d| d.period);
+                quality.assess(&mut measurement, polled, interval);
+                *lock(&shared.latest) = Some(measurement);
+                lock(&shared.subscribers).retain(|channel| {
+                    channel.send(measurement);
+                    !channel.is_abandoned()
+                });
+            }
+            Err(e) if e.is_retriable() && failures < RETRIES => {
+                failures += 1;
+                #[cfg(feature = "metrics")]
+                crate::metrics::retry();
+                quality.pending |= Quality::MISSING_SAMPLES | Quality::RECONNECTED;
+                // A failed resume shows in the next read
+                let _ = device.call(NSRT::resume);
+            }
+            Err(e) => {
+                close(shared);
+                return Err(e);
+            }
+        }
+
+        // The interval is re-read after every wakeup so changes apply at once
+        let previous = next;
+        loop {
+            next = previous + period(shared);
+            let now = Instant::now();
+            if now >= next || !shared.running.load(Ordering::Relaxed) {
+                break;
+            }
+            thread::park_timeout(next - now);
+        }
+        next = next.max(Instant::now());
+    }
+
+    close(shared);
+    Ok(())
+}
+
+/// Time from the start of one measurement to the start of the next
+fn period(shared: &Shared) -> Duration {
+    match *lock(&shared.duty_cycle) {
+        Some(duty_cycle) => duty_cycle.period,
+        None => *lock(&shared.interval),
+    }
+}
+
+/// Measure one burst of `duty_cycle`, returning none if the sampler stopped
+/// meanwhile
+fn burst(
+    device: &DeviceHandle,
+    shared: &Shared,
+    duty_cycle: &DutyCycle,
+    time_source: Arc<dyn TimeSource>,
+) -> Result<Option<Measurement>> {
+    // Restart LEQ integration so that the burst Leq covers the burst only
+    device.call_with_priority(Priority::Low, NSRT::read_leq)?;
+    let started = Instant::now();
+    let samples = duty_cycle.samples.max(1);
+    let mut burst = Burst::default();
+    let mut previous = started;
+    for sample in 1..=samples {
+        let due = started + duty_cycle.burst * sample / samples;
+        loop {
+            let now = Instant::now();
+            if !shared.running.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            if now >= due {
+                break;
+            }
+            thread::park_timeout(due - now);
+        }
+
+        let time_source = Arc::clone(&time_source);
+        let read = move |nsrt: &mut NSRT| nsrt.read_measurement_with(&*time_source);
+        let measurement = device.call_with_priority(Priority::Low, read)?;
+        let now = Instant::now();
+        burst.add(&measurement, now - previous);
+        previous = now;
+    }
+    Ok(burst.measurement())
+}
+
+/// Flags measurements with what happened since the previous one
+#[derive(Default)]
+pub(crate) struct QualityTracker {
+    /// Flags for the next measurement
+    pub(crate) pending: Quality,
+    /// Poll time, timestamp and interval of the previous measurement
+    previous: Option<(Instant, SystemTime, Duration)>,
+    /// Whether the host slept since the previous measurement
+    slept: bool,
+}
+
+impl QualityTracker {
+    pub(crate) fn slept(&mut self) {
+        self.pending |= Quality::MISSING_SAMPLES | Quality::RECONNECTED;
+        self.slept = true;
+    }
+
+    /// Flag `measurement`, polled at `polled` while sampling every `interval`
+    pub(crate) fn assess(
+        &mut self,
+        measurement: &mut Measurement,
+        polled: Instant,
+        interval: Duration,
+    ) {
+        let mut quality = std::mem::take(&mut self.pending);
+        if let Some((instant, timestamp, previous_interval)) = self.previous {
+            let monotonic = polled - instant;
+            // An interval shortened since keeps the longer one for this gap
+            if monotonic > interval.max(previous_interval) * 3 / 2 {
+                quality |= Quality::MISSING_SAMPLES;
+            }
+            let wall = match measurement.timestamp.duration_since(timestamp) {
+                Ok(forward) => forward.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            };
+            let step = (wall - monotonic.as_secs_f64()).abs();
+            if !self.slept && step > CLOCK_STEP_THRESHOLD.as_secs_f64() {
+                quality |= Quality::CLOCK_STEP;
+            }
+        }
+        self.previous = Some((polled, measurement.timestamp, interval));
+        self.slept = false;
+        measurement.quality |= quality;
+    }
+}
+
+/// Send `event` to every receiver still listening
+fn emit(shared: &Shared, event: SamplerEvent) {
+    lock(&shared.events).retain(|tx| tx.send(event).is_ok());
+}
+
+/// End every subscription and event receiver
+fn close(shared: &Shared) {
+    for channel in lock(&shared.subscribers).drain(..) {
+        channel.close();
+    }
+    lock(&shared.events).clear();
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
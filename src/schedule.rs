@@ -0,0 +1,519 @@
+//! Alarm levels varying with the time of day and the day of the week
+//!
+//! Noise ordinances rarely have one limit: 55 dB during the day, 45 dB at
+//! night from 22:00 to 07:00, other hours at weekends. A [`Schedule`] holds
+//! the [`Alarm`] levels that apply by default and [`Period`]s with levels of
+//! their own, on the clock of a [`TimeZone`] so that periods follow daylight
+//! saving changes. In TOML, as under `[alarm]` in `nsrt log` configurations:
+//!
+//! ```toml
+//! time_zone = "Europe/London"
+//! warning = { level = 55.0, dwell = "5min" }
+//!
+//! [[period]]
+//! name = "weekend"
+//! days = ["sat", "sun"]
+//! start = "07:00"
+//! end = "22:00"
+//! warning = { level = 50.0, dwell = "5min" }
+//!
+//! [[period]]
+//! name = "night"
+//! start = "22:00"
+//! end = "07:00"
+//! warning = { level = 45.0, dwell = "5min" }
+//! critical = { level = 55.0 }
+//! ```
+//!
+//! The first period containing a time applies, so list exceptions before the
+//! rules they override. A period whose end is not after its start runs past
+//! midnight and belongs to the day it starts on: the night of a period on
+//! `["fri"]` ends on Saturday morning. `days` defaults to every day and
+//! `time_zone` to UTC.
+//!
+//! Pass [`Schedule::alarm_at`] to [`AlarmMonitor::set_alarm`] before each
+//! [`AlarmMonitor::update`] to follow the schedule while keeping the alarm
+//! state and dwell times across period changes.
+//!
+//! A [`SurveyPlan`] says when to record at all: its [`Window`]s are open in
+//! the minutes matching a [`Cron`] expression, each with the device settings
+//! and interval to record with, so that an unattended logger follows a
+//! survey plan such as weekdays from 07:00 to 19:00, measuring faster during
+//! the morning rush hour. In TOML, as under `[plan]` in `nsrt log`
+//! configurations:
+//!
+//! ```toml
+//! time_zone = "Europe/London"
+//!
+//! [[window]]
+//! name = "rush hour"
+//! when = "* 7-8 * * mon-fri"
+//! interval = "125ms"
+//!
+//! [[window]]
+//! name = "working day"
+//! when = "* 9-18 * * mon-fri"
+//! interval = "1s"
+//! device = { weighting = "A", time_constant = 1.0, sampling_frequency = 48000 }
+//! ```
+//!
+//! As with periods, the first open window applies.
+//!
+//! [`AlarmMonitor::set_alarm`]: crate::AlarmMonitor::set_alarm
+//! [`AlarmMonitor::update`]: crate::AlarmMonitor::update
+
+use crate::{Alarm, DeviceConfig, NsrtError, Result};
+use jiff::{Timestamp, Zoned};
+pub use jiff::{
+    civil::{DateTime, Time},
+    tz::TimeZone,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// Day of the week a [`Period`] starts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Day {
+    #[serde(alias = "monday")]
+    Mon,
+    #[serde(alias = "tuesday")]
+    Tue,
+    #[serde(alias = "wednesday")]
+    Wed,
+    #[serde(alias = "thursday")]
+    Thu,
+    #[serde(alias = "friday")]
+    Fri,
+    #[serde(alias = "saturday")]
+    Sat,
+    #[serde(alias = "sunday")]
+    Sun,
+}
+
+impl Day {
+    /// The day before
+    pub fn previous(self) -> Self {
+        match self {
+            Self::Mon => Self::Sun,
+            Self::Tue => Self::Mon,
+            Self::Wed => Self::Tue,
+            Self::Thu => Self::Wed,
+            Self::Fri => Self::Thu,
+            Self::Sat => Self::Fri,
+            Self::Sun => Self::Sat,
+        }
+    }
+}
+
+impl From<jiff::civil::Weekday> for Day {
+    fn from(weekday: jiff::civil::Weekday) -> Self {
+        use jiff::civil::Weekday;
+
+        match weekday {
+            Weekday::Monday => Self::Mon,
+            Weekday::Tuesday => Self::Tue,
+            Weekday::Wednesday => Self::Wed,
+            Weekday::Thursday => Self::Thu,
+            Weekday::Friday => Self::Fri,
+            Weekday::Saturday => Self::Sat,
+            Weekday::Sunday => Self::Sun,
+        }
+    }
+}
+
+/// Time of day with alarm levels of its own
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Period {
+    /// Name for messages and configuration, e.g. `night`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Days the period starts on, every day if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Day>,
+    /// Local time the period starts at, e.g. `22:00`
+    pub start: Time,
+    /// Local time the period ends at, on the next day unless after `start`
+    pub end: Time,
+    /// Levels during the period, replacing the default ones
+    #[serde(flatten)]
+    pub alarm: Alarm,
+}
+
+impl Period {
+    /// Apply `alarm` every day from `start` to `end` local time
+    pub fn new(start: Time, end: Time, alarm: Alarm) -> Self {
+        Self {
+            name: None,
+            days: Vec::new(),
+            start,
+            end,
+            alarm,
+        }
+    }
+
+    /// Set the name
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Only start the period on `days`
+    #[must_use]
+    pub fn days(mut self, days: impl IntoIterator<Item = Day>) -> Self {
+        self.days = days.into_iter().collect();
+        self
+    }
+
+    /// Whether the period contains `time` on `day`, both local
+    pub fn contains(&self, day: Day, time: Time) -> bool {
+        let starts_on = |day: Day| self.days.is_empty() || self.days.contains(&day);
+        if self.start < self.end {
+            starts_on(day) && self.start <= time && time < self.end
+        } else {
+            (starts_on(day) && time >= self.start) || (starts_on(day.previous()) && time < self.end)
+        }
+    }
+}
+
+/// Alarm levels by time of day and day of the week
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Zone whose clock the periods follow
+    #[serde(with = "jiff::fmt::serde::tz::required", default = "utc")]
+    pub time_zone: TimeZone,
+    /// Levels outside every period
+    #[serde(flatten)]
+    pub alarm: Alarm,
+    /// Periods with levels of their own, the first matching one applying
+    #[serde(default, rename = "period", skip_serializing_if = "Vec::is_empty")]
+    pub periods: Vec<Period>,
+}
+
+impl From<Alarm> for Schedule {
+    fn from(alarm: Alarm) -> Self {
+        Self::new(alarm)
+    }
+}
+
+impl Schedule {
+    /// Apply `alarm` at all times, with periods following UTC
+    pub fn new(alarm: Alarm) -> Self {
+        Self {
+            time_zone: TimeZone::UTC,
+            alarm,
+            periods: Vec::new(),
+        }
+    }
+
+    /// Follow the clock of `time_zone`, e.g. `TimeZone::get("Europe/Berlin")`
+    #[must_use]
+    pub fn time_zone(mut self, time_zone: TimeZone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Add a period, applying unless an earlier one contains the time
+    #[must_use]
+    pub fn period(mut self, period: Period) -> Self {
+        self.periods.push(period);
+        self
+    }
+
+    /// The period containing `time`, if any
+    pub fn period_at(&self, time: SystemTime) -> Result<Option<&Period>> {
+        let local = local(&self.time_zone, time)?;
+        let (day, time) = (Day::from(local.weekday()), local.time());
+        Ok(self
+            .periods
+            .iter()
+            .find(|period| period.contains(day, time)))
+    }
+
+    /// The levels applying at `time`
+    pub fn alarm_at(&self, time: SystemTime) -> Result<Alarm> {
+        Ok(self
+            .period_at(time)?
+            .map_or(self.alarm, |period| period.alarm))
+    }
+}
+
+/// Cron-like expression of the minutes a survey [`Window`] is open
+///
+/// Five fields separated by spaces: minute (0–59), hour (0–23), day of the
+/// month (1–31), month (1–12 or `jan`–`dec`) and day of the week (0–7, both
+/// 0 and 7 being Sunday, or `sun`–`sat`). Each field is `*`, a value or a
+/// range `a-b`, optionally with a step `/n`, or a comma-separated list of
+/// these. As in cron, a day matches if either the day of the month or the
+/// day of the week does when both are restricted. `* 7-18 * * mon-fri` is
+/// open on weekdays from 07:00 to 19:00.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    /// Whether the minute containing the local time `time` matches
+    pub fn matches(&self, time: DateTime) -> bool {
+        let has = |set: u64, value: i8| set & (1 << value) != 0;
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().to_sunday_zero_offset());
+        let day = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+        day && has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+    }
+}
+
+impl FromStr for Cron {
+    type Err = NsrtError;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            NsrtError::InvalidParameter(format!("Invalid cron expression `{expression}`: {reason}"))
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(invalid(format!(
+                "expected 5 fields, found {}",
+                fields.len()
+            )));
+        };
+
+        let mut weekday_set = field(weekdays, 0, 7, &WEEKDAYS, 0).map_err(invalid)?;
+        // Sunday is both 0 and 7
+        if weekday_set & (1 << 7) != 0 {
+            weekday_set |= 1;
+        }
+        Ok(Self {
+            expression: fields.join(" "),
+            minutes: field(minutes, 0, 59, &[], 0).map_err(invalid)?,
+            hours: field(hours, 0, 23, &[], 0).map_err(invalid)?,
+            days: field(days, 1, 31, &[], 0).map_err(invalid)?,
+            months: field(months, 1, 12, &MONTHS, 1).map_err(invalid)?,
+            weekdays: weekday_set,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = NsrtError;
+
+    fn try_from(expression: String) -> Result<Self> {
+        expression.parse()
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.expression
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// The values from `min` to `max` one cron field selects, as bits; `names`
+/// name the values from `first_name` on
+fn field(
+    text: &str,
+    min: u8,
+    max: u8,
+    names: &[&str],
+    first_name: u8,
+) -> std::result::Result<u64, String> {
+    let value = |text: &str| -> std::result::Result<u8, String> {
+        let value = match names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(text))
+        {
+            Some(index) => first_name + index as u8,
+            None => text
+                .parse()
+                .map_err(|_| format!("`{text}` is not a value"))?,
+        };
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!("{value} is outside {min}-{max}"))
+        }
+    };
+
+    let mut set = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u8>()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| format!("`{step}` is not a step"))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` steps from 5 to the end
+            None if part.contains('/') => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(format!("`{range}` is an empty range"));
+        }
+        for value in (start..=end).step_by(usize::from(step)) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Time a survey plan records in, with the settings to record with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    /// Name for messages, e.g. `rush hour`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Minutes the window is open
+    pub when: Cron,
+    /// Device settings while open, the logger's own if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceConfig>,
+    /// Time between measurements while open, the logger's own if unset
+    #[serde(
+        with = "humantime_serde",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub interval: Option<Duration>,
+}
+
+impl Window {
+    /// Open in the minutes matching `when`, with the logger's settings
+    pub fn new(when: Cron) -> Self {
+        Self {
+            name: None,
+            when,
+            device: None,
+            interval: None,
+        }
+    }
+
+    /// Set the name
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Apply `device` settings while open
+    #[must_use]
+    pub fn device(mut self, device: DeviceConfig) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Measure every `interval` while open
+    #[must_use]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+}
+
+/// Windows of time to record in, each with its own settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SurveyPlan {
+    /// Zone whose clock the windows follow
+    #[serde(with = "jiff::fmt::serde::tz::required", default = "utc")]
+    pub time_zone: TimeZone,
+    /// Windows, the first open one applying
+    #[serde(default, rename = "window")]
+    pub windows: Vec<Window>,
+}
+
+impl Default for SurveyPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SurveyPlan {
+    /// Create a plan without windows, which never records, following UTC
+    pub fn new() -> Self {
+        Self {
+            time_zone: TimeZone::UTC,
+            windows: Vec::new(),
+        }
+    }
+
+    /// Follow the clock of `time_zone`
+    #[must_use]
+    pub fn time_zone(mut self, time_zone: TimeZone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Add a window, applying unless an earlier one is open
+    #[must_use]
+    pub fn window(mut self, window: Window) -> Self {
+        self.windows.push(window);
+        self
+    }
+
+    /// The window open at `time`, none if the plan doesn't record then
+    pub fn window_at(&self, time: SystemTime) -> Result<Option<&Window>> {
+        let local = local(&self.time_zone, time)?.datetime();
+        Ok(self
+            .windows
+            .iter()
+            .find(|window| window.when.matches(local)))
+    }
+}
+
+/// `time` on the clock of `time_zone`
+fn local(time_zone: &TimeZone, time: SystemTime) -> Result<Zoned> {
+    Ok(Timestamp::try_from(time)
+        .map_err(time_error)?
+        .to_zoned(time_zone.clone()))
+}
+
+fn utc() -> TimeZone {
+    TimeZone::UTC
+}
+
+fn time_error(e: jiff::Error) -> NsrtError {
+    NsrtError::InvalidParameter(format!("Time out of range: {e}"))
+}
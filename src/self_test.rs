@@ -0,0 +1,295 @@
+use crate::{
+    FirmwareVersion, MeasurementRange, NSRT, Result, SamplingFrequency, Weighting,
+    info::{DEVICE_EPOCH_OFFSET, device_time},
+};
+use std::{
+    ops::RangeInclusive,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Temperatures in °C a working meter reports
+const PLAUSIBLE_TEMPERATURE: RangeInclusive<f32> = -20.0..=60.0;
+
+/// Time constants in seconds from Impulse to the longest commonly used
+const USUAL_TIME_CONSTANT: RangeInclusive<f32> = 0.035..=10.0;
+
+/// Distance in dB outside the measurement range beyond which a level is
+/// implausible rather than just out of range
+const LEVEL_TOLERANCE: f32 = 10.0;
+
+/// Round trips slower than this hint at a poor cable, hub or bridge
+const SLOW_ROUND_TRIP: Duration = Duration::from_millis(100);
+
+/// Outcome of a check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CheckStatus {
+    /// The command answered with a plausible value
+    Pass,
+    /// The command answered, but with an unusual value or slowly
+    Warn,
+    /// The command failed or answered with an implausible value
+    Fail,
+}
+
+/// Result of exercising one read command
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Check {
+    /// What was read, e.g. `level` or `serial_number`
+    pub name: &'static str,
+    /// Outcome of the check
+    pub status: CheckStatus,
+    /// Value read, with its unit, if the command answered
+    pub value: Option<String>,
+    /// Why the check warned or failed
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub message: Option<String>,
+    /// Time from sending the command to its answer or failure
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub latency: Duration,
+}
+
+/// Diagnostic report of [`NSRT::self_test`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SelfTest {
+    /// One check per read command, in the order they ran
+    pub checks: Vec<Check>,
+}
+
+impl SelfTest {
+    /// Worst status of any check
+    pub fn status(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass)
+    }
+
+    /// Whether no check failed, so the meter is fit for deployment
+    pub fn passed(&self) -> bool {
+        self.status() != CheckStatus::Fail
+    }
+
+    /// The check named `name`
+    pub fn check(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+
+    /// Slowest round trip of any check
+    pub fn max_latency(&self) -> Duration {
+        self.checks
+            .iter()
+            .map(|check| check.latency)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Mean round trip of the checks
+    pub fn mean_latency(&self) -> Duration {
+        let total: Duration = self.checks.iter().map(|check| check.latency).sum();
+        total / u32::try_from(self.checks.len()).unwrap_or(u32::MAX).max(1)
+    }
+}
+
+/// Verdict on a value read: its display and, unless it passed, the status
+/// and reason
+type Verdict = (String, Option<(CheckStatus, String)>);
+
+impl NSRT {
+    /// Exercise every read command and judge the answers, for checking a
+    /// meter before deploying it
+    ///
+    /// Levels are judged against the measurement range, see
+    /// [`NSRT::set_measurement_range`]: a level outside it warns, one more
+    /// than 10 dB outside it fails. Temperatures outside -20 to 60 °C fail,
+    /// time constants outside 0.035 to 10 s warn, as do a weighting curve or
+    /// sampling frequency added by later firmware, a model name not
+    /// starting with `NSRT`, an unrecognized firmware revision, a calibration
+    /// date in the future or before the date of manufacture, and round trips
+    /// slower than 100 ms. A failed command fails its check and the link is
+    /// resynchronized before the next.
+    ///
+    /// Reading the LEQ restarts its integration, as [`NSRT::read_leq`] does.
+    pub fn self_test(&mut self) -> SelfTest {
+        let range = self.range.unwrap_or_default();
+        let offset = self.calibration_offset;
+        let level = move |level: &f32| judge_level(*level - offset, range, *level);
+
+        let mut checks = vec![
+            self.run_check("level", NSRT::read_level, level),
+            self.run_check("leq", NSRT::read_leq, level),
+            self.run_check("temperature", NSRT::read_temperature, |t| {
+                let celsius = t.as_celsius();
+                let verdict = (!PLAUSIBLE_TEMPERATURE.contains(&celsius)).then(|| {
+                    (
+                        CheckStatus::Fail,
+                        "Implausible temperature, the sensor may be faulty".to_string(),
+                    )
+                });
+                (format!("{celsius:.1} °C"), verdict)
+            }),
+            self.run_check("weighting", NSRT::read_weighting, |w| {
+                let verdict = matches!(w, Weighting::Unknown(_))
+                    .then(|| (CheckStatus::Warn, "Unknown weighting curve".to_string()));
+                (format!("{w:?}"), verdict)
+            }),
+            self.run_check("sampling_frequency", NSRT::read_sampling_frequency, |&fs| {
+                let verdict = matches!(fs, SamplingFrequency::Unknown(_))
+                    .then(|| (CheckStatus::Warn, "Unknown sampling frequency".to_string()));
+                (u32::from(fs).to_string(), verdict)
+            }),
+            self.run_check("time_constant", NSRT::read_time_constant, |&tau| {
+                let verdict = if !tau.is_finite() || tau <= 0.0 {
+                    Some((CheckStatus::Fail, "Invalid time constant".to_string()))
+                } else if !USUAL_TIME_CONSTANT.contains(&tau) {
+                    Some((CheckStatus::Warn, "Unusual time constant".to_string()))
+                } else {
+                    None
+                };
+                (format!("{tau} s"), verdict)
+            }),
+            self.run_check("model", NSRT::read_model, |model| {
+                let verdict = (!model.starts_with("NSRT"))
+                    .then(|| (CheckStatus::Warn, "Not an NSRT model".to_string()));
+                (model.clone(), verdict)
+            }),
+            self.run_check("serial_number", NSRT::read_serial_number, |serial| {
+                let verdict = serial
+                    .trim()
+                    .is_empty()
+                    .then(|| (CheckStatus::Fail, "Empty serial number".to_string()));
+                (serial.clone(), verdict)
+            }),
+            self.run_check(
+                "firmware_revision",
+                NSRT::read_firmware_revision,
+                |revision| {
+                    let verdict = revision.parse::<FirmwareVersion>().err().map(|_| {
+                        (
+                            CheckStatus::Warn,
+                            "Unrecognized firmware revision".to_string(),
+                        )
+                    });
+                    (revision.clone(), verdict)
+                },
+            ),
+        ];
+
+        let mut born = None;
+        checks.push(
+            self.run_check("birth_date", NSRT::read_birth_date, |&date| {
+                born = Some(date);
+                let verdict = (device_time(date) > SystemTime::now())
+                    .then(|| (CheckStatus::Warn, "Date in the future".to_string()));
+                (format_date(date), verdict)
+            }),
+        );
+        checks.push(
+            self.run_check("calibration_date", NSRT::read_calibration_date, |&date| {
+                let verdict = if device_time(date) > SystemTime::now() {
+                    Some((CheckStatus::Warn, "Date in the future".to_string()))
+                } else if born.is_some_and(|born| date < born) {
+                    Some((
+                        CheckStatus::Warn,
+                        "Calibrated before manufacture".to_string(),
+                    ))
+                } else {
+                    None
+                };
+                (format_date(date), verdict)
+            }),
+        );
+        checks.push(self.run_check("user_id", NSRT::read_user_id, |id| (id.clone(), None)));
+
+        SelfTest { checks }
+    }
+
+    /// Time `read`, judge its answer with `judge` and resync after a failure
+    fn run_check<T>(
+        &mut self,
+        name: &'static str,
+        read: impl FnOnce(&mut Self) -> Result<T>,
+        judge: impl FnOnce(&T) -> Verdict,
+    ) -> Check {
+        let started = Instant::now();
+        let read = read(self);
+        let latency = started.elapsed();
+
+        let (value, verdict) = match read {
+            Ok(value) => {
+                let (value, verdict) = judge(&value);
+                let verdict = verdict.or_else(|| {
+                    (latency > SLOW_ROUND_TRIP).then(|| {
+                        (
+                            CheckStatus::Warn,
+                            format!("Slow answer after {} ms", latency.as_millis()),
+                        )
+                    })
+                });
+                (Some(value), verdict)
+            }
+            Err(e) => {
+                // Drop any rest of the answer so it isn't taken for the next
+                let _ = self.port.clear();
+                (None, Some((CheckStatus::Fail, e.to_string())))
+            }
+        };
+        let (status, message) = match verdict {
+            Some((status, message)) => (status, Some(message)),
+            None => (CheckStatus::Pass, None),
+        };
+        Check {
+            name,
+            status,
+            value,
+            message,
+            latency,
+        }
+    }
+}
+
+/// Judge `level`, the reading before the calibration offset, against `range`
+fn judge_level(level: f32, range: MeasurementRange, reading: f32) -> Verdict {
+    let verdict = if !level.is_finite()
+        || level < range.lower - LEVEL_TOLERANCE
+        || level > range.upper + LEVEL_TOLERANCE
+    {
+        Some((CheckStatus::Fail, "Implausible level".to_string()))
+    } else if level < range.lower || level > range.upper {
+        Some((
+            CheckStatus::Warn,
+            "Outside the measurement range".to_string(),
+        ))
+    } else {
+        None
+    };
+    (format!("{reading:.1} dB"), verdict)
+}
+
+/// Device date as `YYYY-MM-DD`
+fn format_date(seconds: u64) -> String {
+    let days = seconds.saturating_sub(DEVICE_EPOCH_OFFSET) / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Gregorian date of `days` since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
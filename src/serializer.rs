@@ -0,0 +1,349 @@
+use crate::{Measurement, Metadata, NsrtError, Result, Sink};
+use std::{
+    io::{self, Write},
+    sync::mpsc,
+    time::UNIX_EPOCH,
+};
+
+/// Encoding of measurements into the bytes of records
+///
+/// A serializer only decides what the bytes are; a [`RecordSink`] pairs it
+/// with a [`ByteTransport`] that decides where they go, so that each format
+/// can be written to each destination without a sink type for every pair.
+/// Serializers append to the buffer they are given and are told of metadata
+/// in order with the measurements.
+pub trait RecordSerializer {
+    /// Append what precedes the first record, such as a header row
+    fn header(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        let _ = out;
+        Ok(())
+    }
+
+    /// Take the metadata for the records that follow, appending a record of
+    /// its own where the format has one
+    ///
+    /// Formats without room for metadata ignore it.
+    fn metadata(&mut self, metadata: &Metadata, out: &mut Vec<u8>) -> Result<()> {
+        let _ = (metadata, out);
+        Ok(())
+    }
+
+    /// Append the record of one measurement
+    fn serialize(&mut self, measurement: &Measurement, out: &mut Vec<u8>) -> Result<()>;
+}
+
+impl<S: RecordSerializer + ?Sized> RecordSerializer for Box<S> {
+    fn header(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        (**self).header(out)
+    }
+
+    fn metadata(&mut self, metadata: &Metadata, out: &mut Vec<u8>) -> Result<()> {
+        (**self).metadata(metadata, out)
+    }
+
+    fn serialize(&mut self, measurement: &Measurement, out: &mut Vec<u8>) -> Result<()> {
+        (**self).serialize(measurement, out)
+    }
+}
+
+/// Destination of serialized records
+///
+/// Stream transports such as files and TCP connections take records as
+/// consecutive bytes through [`ByteStream`]; message transports such as an
+/// MQTT client publish each record as a message of its own.
+pub trait ByteTransport {
+    /// Send the bytes of one record
+    fn send(&mut self, record: &[u8]) -> Result<()>;
+
+    /// Flush any buffered records
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: ByteTransport + ?Sized> ByteTransport for Box<T> {
+    fn send(&mut self, record: &[u8]) -> Result<()> {
+        (**self).send(record)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Hands each record to the receiver as a message, e.g. for a thread or task
+/// publishing them with an MQTT client
+impl ByteTransport for mpsc::Sender<Vec<u8>> {
+    fn send(&mut self, record: &[u8]) -> Result<()> {
+        mpsc::Sender::send(self, record.to_vec()).map_err(|_| {
+            NsrtError::IoError(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Record receiver is gone",
+            ))
+        })
+    }
+}
+
+/// Transport writing records one after the other to a [`Write`], such as a
+/// [`File`](std::fs::File) or [`TcpStream`](std::net::TcpStream)
+///
+/// Writes go straight to the writer; wrap it in a
+/// [`BufWriter`](std::io::BufWriter) to batch them.
+#[derive(Debug)]
+pub struct ByteStream<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ByteStream<W> {
+    /// Write records to `inner`
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// The underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Take back the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> ByteTransport for ByteStream<W> {
+    fn send(&mut self, record: &[u8]) -> Result<()> {
+        Ok(self.inner.write_all(record)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.inner.flush()?)
+    }
+}
+
+/// Sink serializing measurements with a [`RecordSerializer`] and sending the
+/// records over a [`ByteTransport`]
+///
+/// The header of the serializer, if any, is sent when the sink is created,
+/// and the record of new metadata, if any, when it is set.
+pub struct RecordSink<T: ByteTransport, S: RecordSerializer> {
+    transport: T,
+    serializer: S,
+    buffer: Vec<u8>,
+}
+
+impl<T: ByteTransport, S: RecordSerializer> RecordSink<T, S> {
+    /// Send the records of `serializer` over `transport`, starting with its
+    /// header
+    pub fn new(transport: T, serializer: S) -> Result<Self> {
+        let mut sink = Self::without_header(transport, serializer);
+        sink.serializer.header(&mut sink.buffer)?;
+        sink.send()?;
+        Ok(sink)
+    }
+
+    /// Send the records of `serializer` over `transport` without its header,
+    /// e.g. when appending to a file that has one
+    pub fn without_header(transport: T, serializer: S) -> Self {
+        Self {
+            transport,
+            serializer,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// The underlying transport
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    /// Take back the underlying transport
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Send what is in the buffer, if anything, as one record
+    fn send(&mut self) -> Result<()> {
+        if !self.buffer.is_empty() {
+            self.transport.send(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<T: ByteTransport, S: RecordSerializer> Sink for RecordSink<T, S> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.buffer.clear();
+        self.serializer.metadata(metadata, &mut self.buffer)?;
+        self.send()
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.buffer.clear();
+        self.serializer.serialize(measurement, &mut self.buffer)?;
+        self.send()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.transport.flush()
+    }
+}
+
+/// Newline-delimited JSON objects with the serde field names, including any
+/// [`Metadata`] under `metadata`
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Default)]
+pub struct JsonSerializer {
+    metadata: Metadata,
+}
+
+#[cfg(feature = "json")]
+impl JsonSerializer {
+    /// Create a serializer without metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "json")]
+impl RecordSerializer for JsonSerializer {
+    fn metadata(&mut self, metadata: &Metadata, _: &mut Vec<u8>) -> Result<()> {
+        self.metadata = metadata.clone();
+        Ok(())
+    }
+
+    fn serialize(&mut self, measurement: &Measurement, out: &mut Vec<u8>) -> Result<()> {
+        let record = crate::metadata::Tagged {
+            measurement,
+            metadata: &self.metadata,
+        };
+        serde_json::to_writer(&mut *out, &record).map_err(io::Error::from)?;
+        out.push(b'\n');
+        Ok(())
+    }
+}
+
+/// InfluxDB line protocol, one line per measurement
+///
+/// Lines look like
+///
+/// ```text
+/// nsrt,device=front,site=Main\ Street level=48.3,leq=47.9,temperature=22.5,quality=0i 1714564800250000000
+/// ```
+///
+/// with the device, site and operator of the [`Metadata`] as tags, the
+/// levels in dB, the temperature in °C, the [`Quality`](crate::Quality) bits
+/// as an integer and the timestamp in nanoseconds. Compensated levels and the
+/// clock status are added as fields when known.
+#[derive(Debug, Clone)]
+pub struct InfluxLineSerializer {
+    measurement: String,
+    tags: String,
+}
+
+impl InfluxLineSerializer {
+    /// Write lines of the InfluxDB measurement named `measurement`
+    pub fn new(measurement: &str) -> Self {
+        Self {
+            measurement: escape(measurement, &[',', ' ']),
+            tags: String::new(),
+        }
+    }
+}
+
+impl Default for InfluxLineSerializer {
+    /// Write lines of the measurement `nsrt`
+    fn default() -> Self {
+        Self::new("nsrt")
+    }
+}
+
+impl RecordSerializer for InfluxLineSerializer {
+    fn metadata(&mut self, metadata: &Metadata, _: &mut Vec<u8>) -> Result<()> {
+        self.tags = [
+            ("device", &metadata.device),
+            ("site", &metadata.site),
+            ("operator", &metadata.operator),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = value.as_deref().filter(|value| !value.is_empty())?;
+            Some(format!(",{key}={}", escape(value, &[',', '=', ' '])))
+        })
+        .collect();
+        Ok(())
+    }
+
+    fn serialize(&mut self, measurement: &Measurement, out: &mut Vec<u8>) -> Result<()> {
+        let nanos = measurement
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| {
+                NsrtError::InvalidParameter(
+                    "Line protocol timestamps can't precede 1970".to_string(),
+                )
+            })?
+            .as_nanos();
+        write!(
+            out,
+            "{}{} level={},leq={},temperature={}",
+            self.measurement,
+            self.tags,
+            measurement.level,
+            measurement.leq,
+            measurement.temperature.as_celsius()
+        )?;
+        if let Some(compensated) = measurement.compensated {
+            write!(
+                out,
+                ",compensated_level={},compensated_leq={}",
+                compensated.level, compensated.leq
+            )?;
+        }
+        if let Some(clock) = measurement.clock {
+            write!(
+                out,
+                ",clock_synchronized={},clock_offset={},clock_max_error={}",
+                clock.synchronized, clock.offset, clock.max_error
+            )?;
+        }
+        writeln!(out, ",quality={}i {nanos}", measurement.quality.bits())?;
+        Ok(())
+    }
+}
+
+/// Escape `special` characters with a backslash, and newlines, which line
+/// protocol can't escape, as spaces
+fn escape(text: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        let c = if c == '\n' || c == '\r' { ' ' } else { c };
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `nsrt.v1.Measurement` messages from `proto/nsrt.proto`, each preceded by
+/// its length as a varint
+///
+/// The length prefix delimits the messages in a stream; a message transport
+/// gets it too, and can strip it with `prost::decode_length_delimiter`.
+#[cfg(feature = "grpc")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtobufSerializer;
+
+#[cfg(feature = "grpc")]
+impl RecordSerializer for ProtobufSerializer {
+    fn serialize(&mut self, measurement: &Measurement, out: &mut Vec<u8>) -> Result<()> {
+        prost::Message::encode_length_delimited(
+            &crate::grpc::proto::Measurement::from(*measurement),
+            out,
+        )
+        .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{Measurement, Metadata, Sampler, Subscription};
+use std::time::SystemTime;
+
+/// Measurements collected between the start and end of a recording
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Session {
+    /// Time at which recording started
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub started: SystemTime,
+    /// Time at which recording stopped, or `None` while still recording
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub stopped: Option<SystemTime>,
+    /// Setup the session was recorded with
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: Metadata,
+    /// Measurements in the order they were taken
+    pub measurements: Vec<Measurement>,
+}
+
+impl Session {
+    /// Start recording the measurements taken by `sampler`
+    pub fn record(sampler: &Sampler) -> Recording {
+        Recording {
+            session: Session {
+                started: SystemTime::now(),
+                stopped: None,
+                metadata: sampler.metadata(),
+                measurements: Vec::new(),
+            },
+            measurements: sampler.subscribe_unbounded(),
+        }
+    }
+}
+
+/// A session being recorded from a [`Sampler`]
+pub struct Recording {
+    session: Session,
+    measurements: Subscription,
+}
+
+impl Recording {
+    /// The session recorded so far
+    pub fn snapshot(&mut self) -> &Session {
+        self.session
+            .measurements
+            .extend(self.measurements.try_iter());
+        &self.session
+    }
+
+    /// Stop recording and return the complete session
+    pub fn stop(mut self) -> Session {
+        self.snapshot();
+        self.session.stopped = Some(SystemTime::now());
+        self.session
+    }
+}
@@ -0,0 +1,313 @@
+//! Tamper-evident logs through hash chaining and Ed25519 seals
+//!
+//! [`SigningSink`] passes measurements through to any other sink, such as a
+//! [`BinaryLogWriter`](crate::binlog::BinaryLogWriter), while folding each one
+//! into a SHA-256 hash chain:
+//!
+//! ```text
+//! chain[0] = 32 zero bytes
+//! chain[n] = SHA-256(chain[n - 1] || record[n])
+//! ```
+//!
+//! where `record[n]` is the 25-byte binary log record: timestamp, levels,
+//! temperature and quality flags. After every segment of records, it writes a [`Seal`] to a separate seal
+//! file: the number of records so far, the chain hash, and an Ed25519
+//! signature over both. Since the chain covers every earlier record, changing,
+//! inserting or removing any sealed record breaks verification.
+//!
+//! A seal file starts with the 8-byte magic `NSRTSIG2`, whose last character
+//! is the schema version, followed by one fixed-size 104-byte entry per seal:
+//! record count (`u64`, little-endian), chain hash (32 bytes), signature (64
+//! bytes). The signed message is `NSRTSEAL` followed by the record count and
+//! chain hash.
+//!
+//! Seal files of version 1 have the same entries without the magic, and
+//! their chain leaves the quality flags out, hashing only the first 24 bytes
+//! of each record. [`read_seals`] reads both, telling them apart by the magic, which as a
+//! record count would be beyond any log, and refuses files of later versions
+//! with [`NsrtError::UnsupportedSchema`].
+
+use crate::{Measurement, Metadata, NsrtError, Result, Sink};
+use ed25519_dalek::{Signature, Signer};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// Size of an encoded seal
+pub const SEAL_LEN: usize = 8 + 32 + 64;
+
+/// Magic bytes at the start of every seal file
+pub const MAGIC: &[u8; 8] = b"NSRTSIG2";
+
+/// Schema version of the seal files written, the last character of [`MAGIC`]
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Records covered by each seal unless configured otherwise
+const DEFAULT_SEGMENT_LEN: u64 = 60;
+
+/// Domain separation prefix of signed messages
+const SEAL_CONTEXT: &[u8; 8] = b"NSRTSEAL";
+
+/// Signed checkpoint of a hash chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seal {
+    /// Number of records covered
+    pub records: u64,
+    /// Chain hash after the last covered record
+    pub hash: [u8; 32],
+    /// Signature over the record count and hash
+    pub signature: Signature,
+    /// Schema version of the seal file, which decides what the chain hashes
+    pub version: u32,
+}
+
+impl Seal {
+    fn sign(key: &SigningKey, records: u64, hash: [u8; 32]) -> Self {
+        Self {
+            records,
+            hash,
+            signature: key.sign(&Self::message(records, &hash)),
+            version: SCHEMA_VERSION,
+        }
+    }
+
+    fn message(records: u64, hash: &[u8; 32]) -> Vec<u8> {
+        [&SEAL_CONTEXT[..], &records.to_le_bytes(), hash].concat()
+    }
+
+    /// Encode as a 104-byte seal file entry
+    pub fn to_bytes(&self) -> [u8; SEAL_LEN] {
+        let mut bytes = [0; SEAL_LEN];
+        bytes[..8].copy_from_slice(&self.records.to_le_bytes());
+        bytes[8..40].copy_from_slice(&self.hash);
+        bytes[40..].copy_from_slice(&self.signature.to_bytes());
+        bytes
+    }
+
+    /// Decode an entry of a seal file of the current version
+    pub fn from_bytes(bytes: &[u8; SEAL_LEN]) -> Self {
+        Self {
+            records: u64::from_le_bytes(bytes[..8].try_into().expect("8-byte count")),
+            hash: bytes[8..40].try_into().expect("32-byte hash"),
+            signature: Signature::from_bytes(bytes[40..].try_into().expect("64-byte signature")),
+            version: SCHEMA_VERSION,
+        }
+    }
+
+    /// Check the signature against `key`
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        key.verify_strict(&Self::message(self.records, &self.hash), &self.signature)
+            .is_ok()
+    }
+}
+
+/// Read all seals from a seal file of this or an earlier version
+pub fn read_seals(mut reader: impl Read) -> Result<Vec<Seal>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (version, entries) = match bytes.strip_prefix(&MAGIC[..7]) {
+        Some([digit @ b'2'..=b'9', entries @ ..]) => {
+            let version = u32::from(*digit - b'0');
+            if version > SCHEMA_VERSION {
+                return Err(NsrtError::UnsupportedSchema {
+                    format: "Seal file",
+                    version,
+                    supported: SCHEMA_VERSION,
+                });
+            }
+            (version, entries)
+        }
+        Some(_) => return Err(verification_error("Invalid seal file header")),
+        // Version 1, without a magic
+        None => (1, &bytes[..]),
+    };
+    if entries.len() % SEAL_LEN != 0 {
+        return Err(verification_error("Truncated seal file"));
+    }
+    Ok(entries
+        .chunks_exact(SEAL_LEN)
+        .map(|chunk| Seal {
+            version,
+            ..Seal::from_bytes(chunk.try_into().expect("seal-sized chunk"))
+        })
+        .collect())
+}
+
+/// Hash chain over encoded measurements
+#[derive(Debug, Clone)]
+struct Chain {
+    hash: [u8; 32],
+    records: u64,
+    /// Seal file schema version
+    version: u32,
+}
+
+impl Chain {
+    fn new(version: u32) -> Self {
+        Self {
+            hash: [0; 32],
+            records: 0,
+            version,
+        }
+    }
+
+    fn push(&mut self, measurement: &Measurement) {
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash);
+        if self.version == 1 {
+            hasher.update(measurement.encode());
+        } else {
+            hasher.update(measurement.encode_stored());
+        }
+        self.hash = hasher.finalize().into();
+        self.records += 1;
+    }
+}
+
+/// Sink wrapper sealing everything written through it
+///
+/// The seal file's magic is written with the first seal, and a final seal
+/// covering any remaining records on [`Sink::flush`]. Metadata is passed to
+/// the wrapped sink, but not covered by the seals.
+pub struct SigningSink<S, W: Write> {
+    sink: S,
+    seals: W,
+    key: SigningKey,
+    chain: Chain,
+    sealed: u64,
+    segment_len: u64,
+    /// Whether the magic has been written
+    started: bool,
+}
+
+impl<S: Sink, W: Write> SigningSink<S, W> {
+    /// Pass measurements to `sink`, writing seals signed by `key` to `seals`
+    ///
+    /// The chain starts from scratch, so `sink` should start a new log.
+    pub fn new(sink: S, key: SigningKey, seals: W) -> Self {
+        Self {
+            sink,
+            seals,
+            key,
+            chain: Chain::new(SCHEMA_VERSION),
+            sealed: 0,
+            segment_len: DEFAULT_SEGMENT_LEN,
+            started: false,
+        }
+    }
+
+    /// Seal after every `records` records
+    #[must_use]
+    pub fn segment_len(mut self, records: u64) -> Self {
+        self.segment_len = records.max(1);
+        self
+    }
+
+    /// The wrapped sink
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Return the wrapped sink and seal writer, without sealing pending records
+    pub fn into_parts(self) -> (S, W) {
+        (self.sink, self.seals)
+    }
+
+    fn seal(&mut self) -> Result<()> {
+        if self.chain.records == self.sealed {
+            return Ok(());
+        }
+        // Seals only attest to records the inner sink has persisted
+        self.sink.flush()?;
+
+        let seal = Seal::sign(&self.key, self.chain.records, self.chain.hash);
+        if !self.started {
+            self.seals.write_all(MAGIC)?;
+            self.started = true;
+        }
+        self.seals.write_all(&seal.to_bytes())?;
+        self.seals.flush()?;
+        self.sealed = self.chain.records;
+        Ok(())
+    }
+}
+
+impl<S: Sink, W: Write> Sink for SigningSink<S, W> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.sink.set_metadata(metadata)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.sink.write(measurement)?;
+        self.chain.push(measurement);
+
+        if self.chain.records - self.sealed >= self.segment_len {
+            self.seal()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.seal()?;
+        self.sink.flush()
+    }
+}
+
+/// Outcome of a successful [`verify`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verified {
+    /// Records covered by a valid seal
+    pub sealed: u64,
+    /// Records after the last seal, which cannot be vouched for
+    pub unsealed: u64,
+}
+
+/// Check `measurements` against `seals` signed by `key`
+///
+/// Fails if a seal has an invalid signature, if the chain doesn't match a
+/// seal, or if the log has fewer records than the last seal covers.
+pub fn verify(
+    measurements: impl IntoIterator<Item = Result<Measurement>>,
+    seals: &[Seal],
+    key: &VerifyingKey,
+) -> Result<Verified> {
+    if let Some(seal) = seals.iter().find(|seal| !seal.verify(key)) {
+        return Err(verification_error(&format!(
+            "Invalid signature on seal at record {}",
+            seal.records
+        )));
+    }
+
+    let version = seals.first().map_or(SCHEMA_VERSION, |seal| seal.version);
+    let mut chain = Chain::new(version);
+    let mut pending = seals.iter().peekable();
+    for measurement in measurements {
+        chain.push(&measurement?);
+        while let Some(seal) = pending.next_if(|seal| seal.records == chain.records) {
+            if seal.hash != chain.hash {
+                return Err(verification_error(&format!(
+                    "Records up to {} do not match their seal",
+                    seal.records
+                )));
+            }
+        }
+    }
+
+    if let Some(seal) = pending.next() {
+        return Err(verification_error(&format!(
+            "Log ends at record {} but is sealed up to record {}",
+            chain.records, seal.records
+        )));
+    }
+
+    let sealed = seals.last().map_or(0, |seal| seal.records);
+    Ok(Verified {
+        sealed,
+        unsealed: chain.records - sealed,
+    })
+}
+
+fn verification_error(message: &str) -> NsrtError {
+    NsrtError::VerificationFailed(message.to_string())
+}
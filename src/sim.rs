@@ -0,0 +1,430 @@
+//! Simulated `NSRT_mk4` for development and CI without hardware
+//!
+//! [`Simulator`] answers the device protocol from a level [`Profile`]. It is
+//! a [`Transport`], so it can be handed straight to
+//! [`NSRT::with_transport`](crate::NSRT::with_transport), or exposed on a pty
+//! or TCP port with [`Simulator::serve`] as the `nsrt-sim` binary does.
+//!
+//! Profiles are written as `kind:parameters`:
+//!
+//! | Profile | Meaning |
+//! |---|---|
+//! | `constant:60` | 60 dB throughout |
+//! | `sine:45:85:60` | Between 45 and 85 dB, with a period of 60 s |
+//! | `noise:60:5` | Random level within 5 dB of 60 dB, changing every 100 ms |
+//! | `steps:50@10,90@2` | 50 dB for 10 s, then 90 dB for 2 s, repeating |
+
+use crate::{
+    NsrtError, Result, SamplingFrequency, Transport, Weighting,
+    info::DEVICE_EPOCH_OFFSET,
+    protocol::{ACK, Command, PACKET_LEN},
+};
+use std::{
+    collections::VecDeque,
+    f32::consts::TAU,
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Answer to a write command the device rejected
+const NAK: u8 = 0x15;
+
+/// How long a `noise` level is held
+const NOISE_STEP: Duration = Duration::from_millis(100);
+
+/// Most samples of the profile integrated into one LEQ
+const LEQ_SAMPLES: u32 = 10_000;
+
+/// Level over time produced by a [`Simulator`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Profile {
+    /// Fixed level in dB
+    Constant(f32),
+    /// Level swinging between `min` and `max` dB
+    Sine {
+        min: f32,
+        max: f32,
+        period: Duration,
+    },
+    /// Random level within `spread` dB of `mean`
+    Noise { mean: f32, spread: f32 },
+    /// Levels in dB each held for a duration, repeating
+    Steps(Vec<(f32, Duration)>),
+}
+
+impl Profile {
+    /// Level in dB at `elapsed` since the simulator started
+    pub fn level(&self, elapsed: Duration) -> f32 {
+        match self {
+            Profile::Constant(level) => *level,
+            Profile::Sine { min, max, period } => {
+                let phase = if period.is_zero() {
+                    0.0
+                } else {
+                    (elapsed.as_secs_f64() / period.as_secs_f64()).fract() as f32
+                };
+                min + (max - min) * (1.0 - (phase * TAU).cos()) / 2.0
+            }
+            Profile::Noise { mean, spread } => {
+                let step = elapsed.as_millis() / NOISE_STEP.as_millis();
+                mean + spread * (2.0 * unit(step as u64) - 1.0)
+            }
+            Profile::Steps(steps) => {
+                let cycle: Duration = steps.iter().map(|(_, duration)| *duration).sum();
+                if cycle.is_zero() {
+                    return steps.first().map_or(0.0, |(level, _)| *level);
+                }
+                let mut offset =
+                    Duration::from_secs_f64(elapsed.as_secs_f64() % cycle.as_secs_f64());
+                for (level, duration) in steps {
+                    if offset < *duration {
+                        return *level;
+                    }
+                    offset -= *duration;
+                }
+                steps.last().map_or(0.0, |(level, _)| *level)
+            }
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::Noise {
+            mean: 55.0,
+            spread: 3.0,
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = NsrtError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || NsrtError::InvalidParameter(format!("Invalid level profile: {s}"));
+        let number = |value: &str| value.trim().parse::<f32>().map_err(|_| invalid());
+        let seconds =
+            |value: &str| Duration::try_from_secs_f32(number(value)?).map_err(|_| invalid());
+
+        let (kind, params) = s.split_once(':').ok_or_else(invalid)?;
+        let params: Vec<_> = params.split(':').collect();
+        match (kind, params.as_slice()) {
+            ("constant", [level]) => Ok(Profile::Constant(number(level)?)),
+            ("sine", [min, max, period]) => Ok(Profile::Sine {
+                min: number(min)?,
+                max: number(max)?,
+                period: seconds(period)?,
+            }),
+            ("noise", [mean, spread]) => Ok(Profile::Noise {
+                mean: number(mean)?,
+                spread: number(spread)?,
+            }),
+            ("steps", [steps]) => steps
+                .split(',')
+                .map(|step| {
+                    let (level, duration) = step.split_once('@').ok_or_else(invalid)?;
+                    Ok((number(level)?, seconds(duration)?))
+                })
+                .collect::<Result<Vec<_>>>()
+                .and_then(|steps| {
+                    if steps.is_empty() {
+                        Err(invalid())
+                    } else {
+                        Ok(Profile::Steps(steps))
+                    }
+                }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Profile::Constant(level) => write!(f, "constant:{level}"),
+            Profile::Sine { min, max, period } => {
+                write!(f, "sine:{min}:{max}:{}", period.as_secs_f32())
+            }
+            Profile::Noise { mean, spread } => write!(f, "noise:{mean}:{spread}"),
+            Profile::Steps(steps) => {
+                f.write_str("steps:")?;
+                for (i, (level, duration)) in steps.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{level}@{}", duration.as_secs_f32())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Pseudo-random number in `[0, 1)` derived from `seed`
+fn unit(seed: u64) -> f32 {
+    // splitmix64 finalizer
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Simulated `NSRT_mk4` speaking the device protocol
+///
+/// Bytes written to the simulator are parsed as commands, and its answers are
+/// read back. Reading with no answer pending fails with
+/// [`io::ErrorKind::TimedOut`], as a serial port does. Settings written by
+/// the host are kept, so a configured simulator reads back what was written.
+#[derive(Debug)]
+pub struct Simulator {
+    profile: Profile,
+    start: Instant,
+    /// Time since start of the previous LEQ read
+    leq_since: Duration,
+    temperature: f32,
+    weighting: Weighting,
+    sampling_frequency: SamplingFrequency,
+    time_constant: f32,
+    model: String,
+    serial_number: String,
+    firmware_revision: String,
+    user_id: String,
+    calibration_date: u64,
+    birth_date: u64,
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+}
+
+impl Simulator {
+    /// Simulate a device producing levels from `profile`, starting now
+    pub fn new(profile: Profile) -> Self {
+        // Device dates count from 1904
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            + DEVICE_EPOCH_OFFSET;
+
+        Self {
+            profile,
+            start: Instant::now(),
+            leq_since: Duration::ZERO,
+            temperature: 21.5,
+            weighting: Weighting::A,
+            sampling_frequency: SamplingFrequency::Freq48kHz,
+            time_constant: 0.125,
+            model: "NSRT_mk4 (simulated)".to_string(),
+            serial_number: "SIM00001".to_string(),
+            firmware_revision: "sim".to_string(),
+            user_id: String::new(),
+            calibration_date: now,
+            birth_date: now,
+            input: Vec::new(),
+            output: VecDeque::new(),
+        }
+    }
+
+    /// Report `serial_number`, e.g. to tell several simulators apart
+    #[must_use]
+    pub fn serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.serial_number = serial_number.into();
+        self
+    }
+
+    /// Report a constant `temperature` in °C
+    #[must_use]
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Answer the host on `stream` until it disconnects
+    ///
+    /// Settings and the LEQ integration carry over to the next stream, as
+    /// they would on a device that is unplugged from one host and plugged
+    /// into another.
+    pub fn serve(&mut self, mut stream: impl Read + Write) -> io::Result<()> {
+        let mut buf = [0; 256];
+        loop {
+            let count = match stream.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(count) => count,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.input.extend_from_slice(&buf[..count]);
+            self.process();
+
+            let answer: Vec<u8> = self.output.drain(..).collect();
+            stream.write_all(&answer)?;
+            stream.flush()?;
+        }
+    }
+
+    /// Answer every complete command in the input
+    fn process(&mut self) {
+        while self.input.len() >= PACKET_LEN {
+            let word = |i: usize| {
+                u32::from_le_bytes([
+                    self.input[i],
+                    self.input[i + 1],
+                    self.input[i + 2],
+                    self.input[i + 3],
+                ])
+            };
+            let code = word(0);
+            let count = word(8) as usize;
+
+            // Write commands carry their data after the packet
+            let is_write = code & 0x8000_0000 == 0;
+            let len = PACKET_LEN + if is_write { count } else { 0 };
+            if self.input.len() < len {
+                return;
+            }
+
+            let packet: Vec<u8> = self.input.drain(..len).collect();
+            match Command::from_code(code) {
+                Some(command) if command.is_write() => {
+                    let ack =
+                        count <= command.length() && self.write(command, &packet[PACKET_LEN..]);
+                    self.output.push_back(if ack { ACK } else { NAK });
+                }
+                Some(command) => {
+                    let mut answer = self.read(command);
+                    answer.resize(count, 0);
+                    self.output.extend(answer);
+                }
+                None if is_write => self.output.push_back(NAK),
+                // Unknown reads get no answer, and the host times out
+                None => {}
+            }
+        }
+    }
+
+    /// Payload answering read `command`, `command.length()` bytes long
+    fn read(&mut self, command: Command) -> Vec<u8> {
+        let elapsed = self.start.elapsed();
+        match command {
+            Command::ReadLevel => self.profile.level(elapsed).to_le_bytes().to_vec(),
+            Command::ReadLEQ => self.leq(elapsed).to_le_bytes().to_vec(),
+            Command::ReadTemperature => self.temperature.to_le_bytes().to_vec(),
+            Command::ReadWeighting => vec![u8::from(self.weighting)],
+            Command::ReadFS => u16::from(self.sampling_frequency).to_le_bytes().to_vec(),
+            Command::ReadTau => self.time_constant.to_le_bytes().to_vec(),
+            Command::ReadModel => text(&self.model),
+            Command::ReadSN => text(&self.serial_number),
+            Command::ReadFWRev => text(&self.firmware_revision),
+            Command::ReadDOC => self.calibration_date.to_le_bytes().to_vec(),
+            Command::ReadDOB => self.birth_date.to_le_bytes().to_vec(),
+            Command::ReadUserID => text(&self.user_id),
+            Command::WriteWeighting
+            | Command::WriteFS
+            | Command::WriteTau
+            | Command::WriteUserID
+            | Command::WriteAudioDebug => Vec::new(),
+        }
+    }
+
+    /// Apply write `command`, returning whether it is acknowledged
+    fn write(&mut self, command: Command, data: &[u8]) -> bool {
+        match (command, data) {
+            (Command::WriteWeighting, [0]) => self.weighting = Weighting::C,
+            (Command::WriteWeighting, [1]) => self.weighting = Weighting::A,
+            (Command::WriteWeighting, [2]) => self.weighting = Weighting::Z,
+            (Command::WriteFS, &[low, high]) => match u16::from_le_bytes([low, high]) {
+                32000 => self.sampling_frequency = SamplingFrequency::Freq32kHz,
+                48000 => self.sampling_frequency = SamplingFrequency::Freq48kHz,
+                _ => return false,
+            },
+            (Command::WriteTau, &[a, b, c, d]) => {
+                let tau = f32::from_le_bytes([a, b, c, d]);
+                if !(tau.is_finite() && tau > 0.0) {
+                    return false;
+                }
+                self.time_constant = tau;
+            }
+            (Command::WriteUserID, [text @ .., 0]) => match std::str::from_utf8(text) {
+                Ok(user_id) => self.user_id = user_id.to_string(),
+                Err(_) => return false,
+            },
+            // There is no audio output to switch
+            (Command::WriteAudioDebug, [0 | 1]) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Energy average of the profile since the previous LEQ read
+    fn leq(&mut self, now: Duration) -> f32 {
+        let since = std::mem::replace(&mut self.leq_since, now);
+        let span = now.saturating_sub(since);
+        let samples = (span.as_millis() / 10).clamp(1, u128::from(LEQ_SAMPLES)) as u32;
+        let step = span / samples;
+
+        let energy: f64 = (0..samples)
+            .map(|i| 10f64.powf(f64::from(self.profile.level(since + step * i)) / 10.0))
+            .sum();
+        (10.0 * (energy / f64::from(samples)).log10()) as f32
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new(Profile::default())
+    }
+}
+
+impl Read for Simulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.output.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "simulator has no answer pending",
+            ));
+        }
+
+        let count = buf.len().min(self.output.len());
+        for (byte, answer) in buf.iter_mut().zip(self.output.drain(..count)) {
+            *byte = answer;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for Simulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input.extend_from_slice(buf);
+        self.process();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Simulator {
+    fn clear(&mut self) -> io::Result<()> {
+        self.input.clear();
+        self.output.clear();
+        Ok(())
+    }
+
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        Ok(Some(self.output.len()))
+    }
+}
+
+/// NUL-terminated text field
+fn text(value: &str) -> Vec<u8> {
+    let length = Command::ReadModel.length();
+    let mut bytes: Vec<u8> = value.bytes().take(length - 1).collect();
+    bytes.resize(length, 0);
+    bytes
+}
@@ -0,0 +1,51 @@
+use crate::{Measurement, Metadata, Result};
+
+/// Destination that measurements are written to
+///
+/// Sinks are usually driven by a [`Sampler`](crate::Sampler) through
+/// [`Sampler::attach`](crate::Sampler::attach).
+pub trait Sink {
+    /// Set the metadata to include in the records that follow
+    ///
+    /// Sinks whose format has no room for metadata ignore it.
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        let _ = metadata;
+        Ok(())
+    }
+
+    /// Write one measurement
+    fn write(&mut self, measurement: &Measurement) -> Result<()>;
+
+    /// Flush any buffered measurements
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        (**self).set_metadata(metadata)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        (**self).write(measurement)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        (**self).set_metadata(metadata)
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        (**self).write(measurement)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
@@ -0,0 +1,478 @@
+//! Minimal BER codec for SNMPv2c messages
+
+/// Object identifier as a list of arcs
+pub(super) type Oid = Vec<u32>;
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const GAUGE32: u8 = 0x42;
+const TIME_TICKS: u8 = 0x43;
+const NO_SUCH_OBJECT: u8 = 0x80;
+const NO_SUCH_INSTANCE: u8 = 0x81;
+const END_OF_MIB_VIEW: u8 = 0x82;
+
+pub(super) const GET_REQUEST: u8 = 0xa0;
+pub(super) const GET_NEXT_REQUEST: u8 = 0xa1;
+pub(super) const RESPONSE: u8 = 0xa2;
+pub(super) const SET_REQUEST: u8 = 0xa3;
+pub(super) const GET_BULK_REQUEST: u8 = 0xa5;
+pub(super) const SNMPV2_TRAP: u8 = 0xa7;
+
+/// SNMP version field value for SNMPv2c
+pub(super) const VERSION_2C: i64 = 1;
+
+/// Variable binding value
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Value {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Null,
+    Oid(Oid),
+    Gauge32(u32),
+    TimeTicks(u32),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+/// Protocol data unit
+///
+/// For `GetBulkRequest`, `error_status` and `error_index` carry the
+/// non-repeaters and max-repetitions fields.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Pdu {
+    pub tag: u8,
+    pub request_id: i64,
+    pub error_status: i64,
+    pub error_index: i64,
+    pub varbinds: Vec<(Oid, Value)>,
+}
+
+/// Community-based SNMP message
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Message {
+    pub version: i64,
+    pub community: Vec<u8>,
+    pub pdu: Pdu,
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut pdu = Vec::new();
+        write_integer(&mut pdu, self.pdu.request_id);
+        write_integer(&mut pdu, self.pdu.error_status);
+        write_integer(&mut pdu, self.pdu.error_index);
+
+        let mut varbinds = Vec::new();
+        for (oid, value) in &self.pdu.varbinds {
+            let mut varbind = Vec::new();
+            write_tlv(&mut varbind, OBJECT_IDENTIFIER, &encode_oid(oid));
+            write_value(&mut varbind, value);
+            write_tlv(&mut varbinds, SEQUENCE, &varbind);
+        }
+        write_tlv(&mut pdu, SEQUENCE, &varbinds);
+
+        let mut message = Vec::new();
+        write_integer(&mut message, self.version);
+        write_tlv(&mut message, OCTET_STRING, &self.community);
+        write_tlv(&mut message, self.pdu.tag, &pdu);
+
+        let mut out = Vec::new();
+        write_tlv(&mut out, SEQUENCE, &message);
+        out
+    }
+
+    /// Decode a message, returning `None` if it is malformed
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut outer = Reader(bytes);
+        let mut message = Reader(outer.expect(SEQUENCE)?);
+
+        let version = message.integer()?;
+        let community = message.expect(OCTET_STRING)?.to_vec();
+        let (tag, body) = message.tlv()?;
+        let mut body = Reader(body);
+
+        let request_id = body.integer()?;
+        let error_status = body.integer()?;
+        let error_index = body.integer()?;
+
+        let mut list = Reader(body.expect(SEQUENCE)?);
+        let mut varbinds = Vec::new();
+        while !list.0.is_empty() {
+            let mut varbind = Reader(list.expect(SEQUENCE)?);
+            let oid = decode_oid(varbind.expect(OBJECT_IDENTIFIER)?)?;
+            let value = varbind.value()?;
+            varbinds.push((oid, value));
+        }
+
+        Some(Self {
+            version,
+            community,
+            pdu: Pdu {
+                tag,
+                request_id,
+                error_status,
+                error_index,
+                varbinds,
+            },
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn tlv(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (&first, mut rest) = rest.split_first()?;
+
+        let len = if first & 0x80 == 0 {
+            usize::from(first)
+        } else {
+            let count = usize::from(first & 0x7f);
+            if count == 0 || count > std::mem::size_of::<usize>() || rest.len() < count {
+                return None;
+            }
+            let (len_bytes, tail) = rest.split_at(count);
+            rest = tail;
+            len_bytes
+                .iter()
+                .fold(0usize, |len, &b| (len << 8) | usize::from(b))
+        };
+
+        if rest.len() < len {
+            return None;
+        }
+        let (content, tail) = rest.split_at(len);
+        self.0 = tail;
+        Some((tag, content))
+    }
+
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        self.tlv().filter(|(t, _)| *t == tag).map(|(_, c)| c)
+    }
+
+    fn integer(&mut self) -> Option<i64> {
+        decode_integer(self.expect(INTEGER)?)
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        let (tag, content) = self.tlv()?;
+        Some(match tag {
+            INTEGER => Value::Integer(decode_integer(content)?),
+            OCTET_STRING => Value::OctetString(content.to_vec()),
+            NULL => Value::Null,
+            OBJECT_IDENTIFIER => Value::Oid(decode_oid(content)?),
+            GAUGE32 => Value::Gauge32(u32::try_from(decode_integer(content)?).ok()?),
+            TIME_TICKS => Value::TimeTicks(u32::try_from(decode_integer(content)?).ok()?),
+            NO_SUCH_OBJECT => Value::NoSuchObject,
+            NO_SUCH_INSTANCE => Value::NoSuchInstance,
+            END_OF_MIB_VIEW => Value::EndOfMibView,
+            _ => return None,
+        })
+    }
+}
+
+fn write_tlv(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+}
+
+fn write_integer(out: &mut Vec<u8>, value: i64) {
+    write_tlv(out, INTEGER, &encode_integer(value));
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Integer(v) => write_integer(out, *v),
+        Value::OctetString(v) => write_tlv(out, OCTET_STRING, v),
+        Value::Null => write_tlv(out, NULL, &[]),
+        Value::Oid(v) => write_tlv(out, OBJECT_IDENTIFIER, &encode_oid(v)),
+        Value::Gauge32(v) => write_tlv(out, GAUGE32, &encode_integer(i64::from(*v))),
+        Value::TimeTicks(v) => write_tlv(out, TIME_TICKS, &encode_integer(i64::from(*v))),
+        Value::NoSuchObject => write_tlv(out, NO_SUCH_OBJECT, &[]),
+        Value::NoSuchInstance => write_tlv(out, NO_SUCH_INSTANCE, &[]),
+        Value::EndOfMibView => write_tlv(out, END_OF_MIB_VIEW, &[]),
+    }
+}
+
+/// Minimal two's complement encoding
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < bytes.len() - 1 {
+        let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn decode_integer(content: &[u8]) -> Option<i64> {
+    if content.is_empty() || content.len() > 8 {
+        return None;
+    }
+    let sign = if content[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Some(
+        content
+            .iter()
+            .fold(sign, |value, &b| (value << 8) | i64::from(b)),
+    )
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let (first, rest) = match oid {
+        [a, b, rest @ ..] => (a * 40 + b, rest),
+        [a] => (a * 40, &[][..]),
+        [] => (0, &[][..]),
+    };
+
+    for arc in std::iter::once(first).chain(rest.iter().copied()) {
+        let mut chunk = [0u8; 5];
+        let mut i = chunk.len();
+        let mut value = arc;
+        loop {
+            i -= 1;
+            chunk[i] = (value & 0x7f) as u8 | if i == chunk.len() - 1 { 0 } else { 0x80 };
+            value >>= 7;
+            if value == 0 {
+                break;
+            }
+        }
+        out.extend_from_slice(&chunk[i..]);
+    }
+    out
+}
+
+fn decode_oid(content: &[u8]) -> Option<Oid> {
+    let mut arcs = Vec::new();
+    let mut value: u32 = 0;
+    for &b in content {
+        value = value.checked_mul(128)? | u32::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            arcs.push(value);
+            value = 0;
+        }
+    }
+    if content.last().is_some_and(|b| b & 0x80 != 0) {
+        return None;
+    }
+
+    let first = *arcs.first()?;
+    let (a, b) = if first < 80 {
+        (first / 40, first % 40)
+    } else {
+        (2, first - 80)
+    };
+    arcs.splice(0..1, [a, b]);
+    Some(arcs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `GetRequest` for `sysDescr.0` with community `public` and request ID 1
+    const GET_SYS_DESCR: &[u8] = &[
+        0x30, 0x26, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c', 0xa0, 0x19,
+        0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00, 0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08,
+        0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+    ];
+
+    const SYS_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 1, 0];
+
+    #[test]
+    fn integers() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (127, &[0x7f]),
+            (128, &[0x00, 0x80]),
+            (256, &[0x01, 0x00]),
+            (-1, &[0xff]),
+            (-128, &[0x80]),
+            (-129, &[0xff, 0x7f]),
+            (i64::MAX, &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            (i64::MIN, &[0x80, 0, 0, 0, 0, 0, 0, 0]),
+        ] {
+            assert_eq!(encode_integer(value), encoded, "{value}");
+            assert_eq!(decode_integer(encoded), Some(value), "{value}");
+        }
+        assert_eq!(decode_integer(&[]), None);
+        assert_eq!(decode_integer(&[0x01; 9]), None);
+    }
+
+    #[test]
+    fn oids() {
+        for (oid, encoded) in [
+            (
+                SYS_DESCR,
+                &[0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00][..],
+            ),
+            (
+                &[1, 3, 6, 1, 4, 1, 8072, 9999, 9999],
+                &[
+                    0x2b, 0x06, 0x01, 0x04, 0x01, 0xbf, 0x08, 0xce, 0x0f, 0xce, 0x0f,
+                ],
+            ),
+            (&[2, 999, 3], &[0x88, 0x37, 0x03]),
+            (&[1, 3, u32::MAX], &[0x2b, 0x8f, 0xff, 0xff, 0xff, 0x7f]),
+        ] {
+            assert_eq!(encode_oid(oid), encoded, "{oid:?}");
+            assert_eq!(decode_oid(encoded).as_deref(), Some(oid), "{oid:?}");
+        }
+        // Empty, ending mid-arc, or with an arc beyond 32 bits
+        assert_eq!(decode_oid(&[]), None);
+        assert_eq!(decode_oid(&[0x2b, 0x86]), None);
+        assert_eq!(
+            decode_oid(&[0x2b, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+            None
+        );
+    }
+
+    #[test]
+    fn lengths() {
+        for (len, header) in [
+            (0, &[0x04, 0x00][..]),
+            (127, &[0x04, 0x7f]),
+            (128, &[0x04, 0x81, 0x80]),
+            (255, &[0x04, 0x81, 0xff]),
+            (300, &[0x04, 0x82, 0x01, 0x2c]),
+            (70_000, &[0x04, 0x83, 0x01, 0x11, 0x70]),
+        ] {
+            let content = vec![0x5a; len];
+            let mut out = Vec::new();
+            write_tlv(&mut out, OCTET_STRING, &content);
+            assert_eq!(&out[..header.len()], header, "{len}");
+
+            let mut reader = Reader(&out);
+            assert_eq!(reader.tlv(), Some((OCTET_STRING, &content[..])), "{len}");
+            assert!(reader.0.is_empty());
+            // Content cut short of the length
+            assert_eq!(Reader(&out[..out.len() - 1]).tlv(), None, "{len}");
+        }
+
+        // Length bytes missing, none at all, or more than fit a usize
+        assert_eq!(Reader(&[0x04, 0x82, 0x01]).tlv(), None);
+        assert_eq!(Reader(&[0x04, 0x80, 0x00]).tlv(), None);
+        assert_eq!(
+            Reader(&[0x04, 0x89, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0]).tlv(),
+            None
+        );
+        assert_eq!(
+            Reader(&[0x04, 0x88, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]).tlv(),
+            None
+        );
+        assert_eq!(Reader(&[0x04]).tlv(), None);
+    }
+
+    #[test]
+    fn get_request_to_response() {
+        let request = Message::decode(GET_SYS_DESCR).unwrap();
+        assert_eq!(
+            request,
+            Message {
+                version: VERSION_2C,
+                community: b"public".to_vec(),
+                pdu: Pdu {
+                    tag: GET_REQUEST,
+                    request_id: 1,
+                    error_status: 0,
+                    error_index: 0,
+                    varbinds: vec![(SYS_DESCR.to_vec(), Value::Null)],
+                },
+            }
+        );
+        assert_eq!(request.encode(), GET_SYS_DESCR);
+
+        let response = Message {
+            pdu: Pdu {
+                tag: RESPONSE,
+                varbinds: vec![(SYS_DESCR.to_vec(), Value::OctetString(b"nsrt".to_vec()))],
+                ..request.pdu
+            },
+            ..request
+        };
+        let encoded = response.encode();
+        assert_eq!(
+            encoded,
+            [
+                0x30, 0x2a, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c', 0xa2,
+                0x1d, 0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00, 0x30, 0x12, 0x30, 0x10,
+                0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x04, 0x04, b'n', b's',
+                b'r', b't',
+            ]
+        );
+        assert_eq!(Message::decode(&encoded), Some(response));
+    }
+
+    #[test]
+    fn values() {
+        let values = [
+            Value::Integer(-5),
+            Value::OctetString(vec![0; 200]),
+            Value::Null,
+            Value::Oid(SYS_DESCR.to_vec()),
+            Value::Gauge32(u32::MAX),
+            Value::TimeTicks(360_000),
+            Value::NoSuchObject,
+            Value::NoSuchInstance,
+            Value::EndOfMibView,
+        ];
+        let message = Message {
+            version: VERSION_2C,
+            community: b"public".to_vec(),
+            pdu: Pdu {
+                tag: RESPONSE,
+                request_id: i64::from(i32::MAX),
+                error_status: 0,
+                error_index: 0,
+                varbinds: values
+                    .into_iter()
+                    .map(|value| (SYS_DESCR.to_vec(), value))
+                    .collect(),
+            },
+        };
+        assert_eq!(Message::decode(&message.encode()), Some(message));
+    }
+
+    #[test]
+    fn malformed_messages() {
+        // Every truncation of a valid message
+        for len in 0..GET_SYS_DESCR.len() {
+            assert_eq!(Message::decode(&GET_SYS_DESCR[..len]), None, "{len}");
+        }
+
+        // A varbind list claiming more than the PDU holds
+        let mut oversized = GET_SYS_DESCR.to_vec();
+        oversized[25] = 0x7f;
+        assert_eq!(Message::decode(&oversized), None);
+
+        // An unknown value type, and a Gauge32 beyond 32 bits
+        let mut unknown = GET_SYS_DESCR.to_vec();
+        unknown[38] = 0x4f;
+        assert_eq!(Message::decode(&unknown), None);
+        let mut message = Message::decode(GET_SYS_DESCR).unwrap();
+        message.pdu.varbinds[0].1 = Value::Integer(1 << 32);
+        let mut gauge = message.encode();
+        let tag = gauge.len() - 7;
+        assert_eq!(gauge[tag], INTEGER);
+        gauge[tag] = GAUGE32;
+        assert_eq!(Message::decode(&gauge), None);
+    }
+}
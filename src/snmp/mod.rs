@@ -0,0 +1,356 @@
+//! SNMPv2c agent exposing the device as a small MIB
+//!
+//! Objects live under a configurable base OID, by default the Net-SNMP
+//! experimental arc `1.3.6.1.4.1.8072.9999.9999`. The full definitions are in
+//! `docs/NSRT-MIB.txt`.
+//!
+//! | OID          | Name                 | Type          | Value                             |
+//! | ------------ | -------------------- | ------------- | --------------------------------- |
+//! | `base.1.1.0` | `nsrtLevel`          | `Integer32`   | running level in 0.01 dB          |
+//! | `base.1.2.0` | `nsrtLeq`            | `Integer32`   | LEQ in 0.01 dB                    |
+//! | `base.1.3.0` | `nsrtTemperature`    | `Integer32`   | temperature in 0.01 °C            |
+//! | `base.1.4.0` | `nsrtSerialNumber`   | `OCTET STRING`| serial number                     |
+//! | `base.1.5.0` | `nsrtModel`          | `OCTET STRING`| model name                        |
+//! | `base.1.6.0` | `nsrtFirmware`       | `OCTET STRING`| firmware revision                 |
+//! | `base.1.7.0` | `nsrtThreshold`      | `Integer32`   | alarm threshold in 0.01 dB        |
+//! | `base.1.8.0` | `nsrtAlarmState`     | `INTEGER`     | `normal(1)` or `exceeded(2)`      |
+//!
+//! Measurement objects return `noSuchInstance` until the first measurement,
+//! and `nsrtThreshold` only exists when a threshold is configured. All objects
+//! are read-only; `Get`, `GetNext` and `GetBulk` are supported.
+//!
+//! When a [`Threshold`] is configured, `SNMPv2-Trap` notifications are sent to
+//! every trap target as the level crosses it: `nsrtThresholdExceeded`
+//! (`base.0.1`) and `nsrtThresholdCleared` (`base.0.2`), each carrying
+//! `nsrtLevel` and `nsrtThreshold`.
+
+mod ber;
+
+use crate::{
+    DeviceInfo, Measurement, NSRT, Result, Sampler, Threshold, ThresholdEvent, ThresholdMonitor,
+};
+use ber::{Message, Oid, Pdu, Value};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Instant,
+};
+
+/// Default base OID (`netSnmpPlaypen`)
+pub const DEFAULT_BASE_OID: &[u32] = &[1, 3, 6, 1, 4, 1, 8072, 9999, 9999];
+
+/// `sysUpTime.0`
+const SYS_UP_TIME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 3, 0];
+
+/// `snmpTrapOID.0`
+const SNMP_TRAP_OID: &[u32] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+
+/// Upper bound on varbinds in a `GetBulk` response
+const MAX_BULK_VARBINDS: usize = 64;
+
+/// `notWritable` error status
+const NOT_WRITABLE: i64 = 17;
+
+/// Largest datagram the agent accepts
+const MAX_MESSAGE_SIZE: usize = 65_507;
+
+/// SNMP agent for one device
+#[derive(Debug, Clone)]
+pub struct Agent {
+    community: String,
+    base: Oid,
+    trap_targets: Vec<SocketAddr>,
+    threshold: Option<Threshold>,
+}
+
+impl Agent {
+    /// Create an agent answering requests for `community`
+    pub fn new(community: impl Into<String>) -> Self {
+        Self {
+            community: community.into(),
+            base: DEFAULT_BASE_OID.to_vec(),
+            trap_targets: Vec::new(),
+            threshold: None,
+        }
+    }
+
+    /// Set the OID under which the objects are registered
+    #[must_use]
+    pub fn base_oid(mut self, oid: &[u32]) -> Self {
+        self.base = oid.to_vec();
+        self
+    }
+
+    /// Add a manager to send notifications to, usually on port 162
+    #[must_use]
+    pub fn trap_target(mut self, target: SocketAddr) -> Self {
+        self.trap_targets.push(target);
+        self
+    }
+
+    /// Raise the alarm state and send notifications when `threshold` is crossed
+    #[must_use]
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Answer requests on `socket` for the device sampled by `sampler`
+    ///
+    /// Blocks until receiving from the socket fails. Malformed requests and
+    /// requests with the wrong version or community are dropped.
+    pub fn serve(self, socket: UdpSocket, sampler: &Sampler) -> Result<()> {
+        let info = sampler.device().call(NSRT::read_info)?;
+        let agent = Arc::new(Running {
+            started: Instant::now(),
+            info,
+            state: Mutex::new(State {
+                latest: None,
+                monitor: self.threshold.map(ThresholdMonitor::new),
+            }),
+            config: self,
+        });
+
+        let measurements = sampler.subscribe();
+        let traps = socket.try_clone()?;
+        let updater = Arc::clone(&agent);
+        thread::spawn(move || {
+            for measurement in measurements {
+                updater.update(&traps, measurement);
+            }
+        });
+
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf)?;
+            if let Some(response) = agent.respond(&buf[..len]) {
+                // A failed reply only affects that manager
+                let _ = socket.send_to(&response, peer);
+            }
+        }
+    }
+}
+
+struct State {
+    latest: Option<Measurement>,
+    monitor: Option<ThresholdMonitor>,
+}
+
+struct Running {
+    config: Agent,
+    started: Instant,
+    info: DeviceInfo,
+    state: Mutex<State>,
+}
+
+impl Running {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn oid(&self, arcs: &[u32]) -> Oid {
+        let mut oid = self.config.base.clone();
+        oid.extend_from_slice(arcs);
+        oid
+    }
+
+    fn update(&self, socket: &UdpSocket, measurement: Measurement) {
+        let mut state = self.lock();
+        state.latest = Some(measurement);
+
+        let event = state
+            .monitor
+            .as_mut()
+            .and_then(|monitor| monitor.update(&measurement));
+        let Some(event) = event else {
+            return;
+        };
+
+        let notification = match event {
+            ThresholdEvent::Exceeded(_) => 1,
+            ThresholdEvent::Cleared(_) => 2,
+        };
+        let objects = self.objects(&state);
+        drop(state);
+
+        let mut varbinds = vec![
+            (SYS_UP_TIME.to_vec(), Value::TimeTicks(self.uptime())),
+            (
+                SNMP_TRAP_OID.to_vec(),
+                Value::Oid(self.oid(&[0, notification])),
+            ),
+        ];
+        for arcs in [[1, 1, 0], [1, 7, 0]] {
+            let oid = self.oid(&arcs);
+            if let Some((_, Some(value))) = objects.iter().find(|(o, _)| *o == oid) {
+                varbinds.push((oid, value.clone()));
+            }
+        }
+
+        let trap = Message {
+            version: ber::VERSION_2C,
+            community: self.config.community.as_bytes().to_vec(),
+            pdu: Pdu {
+                tag: ber::SNMPV2_TRAP,
+                request_id: i64::from(self.uptime()),
+                error_status: 0,
+                error_index: 0,
+                varbinds,
+            },
+        }
+        .encode();
+        for target in &self.config.trap_targets {
+            let _ = socket.send_to(&trap, target);
+        }
+    }
+
+    /// Hundredths of a second since the agent started
+    fn uptime(&self) -> u32 {
+        (self.started.elapsed().as_millis() / 10) as u32
+    }
+
+    /// Objects in OID order, with `None` for instances that don't exist yet
+    fn objects(&self, state: &State) -> Vec<(Oid, Option<Value>)> {
+        let measurement =
+            |f: fn(&Measurement) -> f32| state.latest.as_ref().map(|m| Value::Integer(centi(f(m))));
+        let text = |s: &str| Some(Value::OctetString(s.as_bytes().to_vec()));
+        let monitor = state.monitor.as_ref();
+
+        vec![
+            (self.oid(&[1, 1, 0]), measurement(|m| m.level)),
+            (self.oid(&[1, 2, 0]), measurement(|m| m.leq)),
+            (
+                self.oid(&[1, 3, 0]),
+                measurement(|m| m.temperature.as_celsius()),
+            ),
+            (self.oid(&[1, 4, 0]), text(&self.info.serial_number)),
+            (self.oid(&[1, 5, 0]), text(&self.info.model)),
+            (self.oid(&[1, 6, 0]), text(&self.info.firmware_revision)),
+            (
+                self.oid(&[1, 7, 0]),
+                monitor.map(|m| Value::Integer(centi(m.threshold().level))),
+            ),
+            (
+                self.oid(&[1, 8, 0]),
+                Some(Value::Integer(
+                    if monitor.is_some_and(|m| m.is_exceeded()) {
+                        2
+                    } else {
+                        1
+                    },
+                )),
+            ),
+        ]
+    }
+
+    fn respond(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let request = Message::decode(request)?;
+        if request.version != ber::VERSION_2C
+            || request.community != self.config.community.as_bytes()
+        {
+            return None;
+        }
+
+        let objects = self.objects(&self.lock());
+        let pdu = request.pdu;
+        let mut response = Pdu {
+            tag: ber::RESPONSE,
+            request_id: pdu.request_id,
+            error_status: 0,
+            error_index: 0,
+            varbinds: Vec::new(),
+        };
+
+        match pdu.tag {
+            ber::GET_REQUEST => {
+                response.varbinds = pdu
+                    .varbinds
+                    .into_iter()
+                    .map(|(oid, _)| {
+                        let value = get(&objects, &oid);
+                        (oid, value)
+                    })
+                    .collect();
+            }
+            ber::GET_NEXT_REQUEST => {
+                response.varbinds = pdu
+                    .varbinds
+                    .into_iter()
+                    .map(|(oid, _)| get_next(&objects, oid))
+                    .collect();
+            }
+            ber::GET_BULK_REQUEST => {
+                let non_repeaters = usize::try_from(pdu.error_status.max(0)).unwrap_or(0);
+                let max_repetitions = usize::try_from(pdu.error_index.max(0)).unwrap_or(0);
+                let (scalars, repeaters) =
+                    pdu.varbinds.split_at(non_repeaters.min(pdu.varbinds.len()));
+
+                for (oid, _) in scalars {
+                    response.varbinds.push(get_next(&objects, oid.clone()));
+                }
+                let mut cursors: Vec<Oid> = repeaters.iter().map(|(oid, _)| oid.clone()).collect();
+                for _ in 0..max_repetitions {
+                    if response.varbinds.len() + cursors.len() > MAX_BULK_VARBINDS {
+                        break;
+                    }
+                    let mut done = true;
+                    for cursor in &mut cursors {
+                        let (oid, value) = get_next(&objects, cursor.clone());
+                        done &= value == Value::EndOfMibView;
+                        cursor.clone_from(&oid);
+                        response.varbinds.push((oid, value));
+                    }
+                    if done {
+                        break;
+                    }
+                }
+            }
+            ber::SET_REQUEST => {
+                response.error_status = NOT_WRITABLE;
+                response.error_index = 1;
+                response.varbinds = pdu.varbinds;
+            }
+            _ => return None,
+        }
+
+        Some(
+            Message {
+                version: request.version,
+                community: request.community,
+                pdu: response,
+            }
+            .encode(),
+        )
+    }
+}
+
+fn get(objects: &[(Oid, Option<Value>)], oid: &[u32]) -> Value {
+    match objects.iter().find(|(o, _)| o == oid) {
+        Some((_, Some(value))) => value.clone(),
+        Some((_, None)) => Value::NoSuchInstance,
+        // The object exists, but not with this instance suffix
+        None if objects
+            .iter()
+            .any(|(o, _)| oid.len() > 1 && o[..o.len() - 1] == oid[..oid.len() - 1]) =>
+        {
+            Value::NoSuchInstance
+        }
+        None => Value::NoSuchObject,
+    }
+}
+
+fn get_next(objects: &[(Oid, Option<Value>)], oid: Oid) -> (Oid, Value) {
+    objects
+        .iter()
+        .find_map(|(o, value)| match value {
+            Some(value) if *o > oid => Some((o.clone(), value.clone())),
+            _ => None,
+        })
+        .unwrap_or((oid, Value::EndOfMibView))
+}
+
+/// Encode a value in hundredths
+fn centi(value: f32) -> i64 {
+    (value * 100.0).round() as i64
+}
@@ -0,0 +1,195 @@
+use crate::{BackgroundCorrected, BackgroundCorrection, NSRT, NsrtError, Result, background};
+use std::{f32::consts::PI, time::Duration};
+
+/// Surface enveloping the source on which the positions lie
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum MeasurementSurface {
+    /// Hemisphere over a reflecting floor of `radius` m
+    Hemisphere {
+        /// Radius in m
+        radius: f32,
+    },
+    /// Box over a reflecting floor at `distance` m from a reference box of
+    /// `length` by `width` by `height` m enclosing the source
+    Parallelepiped {
+        /// Length of the reference box in m
+        length: f32,
+        /// Width of the reference box in m
+        width: f32,
+        /// Height of the reference box in m
+        height: f32,
+        /// Measurement distance in m
+        distance: f32,
+    },
+    /// Any other surface, by its area in m²
+    Area(f32),
+}
+
+impl MeasurementSurface {
+    /// Area in m²
+    pub fn area(&self) -> f32 {
+        match *self {
+            Self::Hemisphere { radius } => 2.0 * PI * radius * radius,
+            Self::Parallelepiped {
+                length,
+                width,
+                height,
+                distance,
+            } => {
+                let a = length / 2.0 + distance;
+                let b = width / 2.0 + distance;
+                let c = height + distance;
+                4.0 * (a * b + b * c + c * a)
+            }
+            Self::Area(area) => area,
+        }
+    }
+}
+
+/// Levels measured for a sound power determination by the ISO 3744 survey
+/// method
+///
+/// The time-averaged level is measured at several positions on a surface
+/// enveloping the source, with the source running, and optionally the
+/// background with it off. The sound power level is the energy mean of the
+/// levels, corrected for background noise (K1) and for the test
+/// environment (K2), plus `10 log10(S / 1 m²)` for the surface area S.
+/// Positions can be measured at once with several meters and added with
+/// [`SoundPowerSurvey::position`], or one after another with a single meter:
+///
+/// ```no_run
+/// # use nsrt::{MeasurementSurface, NSRT, SoundPowerSurvey};
+/// # use std::time::Duration;
+/// # fn main() -> nsrt::Result<()> {
+/// let mut nsrt = NSRT::open()?;
+/// let mut survey = SoundPowerSurvey::new(MeasurementSurface::Hemisphere { radius: 1.0 })
+///     .environmental_correction(0.5);
+/// survey.measure_background(&mut nsrt, Duration::from_secs(30))?;
+/// for _ in 0..10 {
+///     // Move the meter to the next position, then
+///     survey.measure_position(&mut nsrt, Duration::from_secs(30))?;
+/// }
+/// println!("LwA = {:.1} dB", survey.compute()?.sound_power_level);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundPowerSurvey {
+    surface: MeasurementSurface,
+    environmental_correction: f32,
+    levels: Vec<f32>,
+    backgrounds: Vec<f32>,
+}
+
+/// Sound power level determined from a [`SoundPowerSurvey`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SoundPower {
+    /// Sound power level in dB re 1 pW
+    pub sound_power_level: f32,
+    /// Energy mean of the levels at the positions in dB, uncorrected
+    pub mean_level: f32,
+    /// Background correction (K1) of the mean level, if the background was
+    /// measured
+    pub background: Option<BackgroundCorrected>,
+    /// Environmental correction (K2) in dB
+    pub environmental_correction: f32,
+    /// Area of the measurement surface in m²
+    pub surface_area: f32,
+    /// Number of positions
+    pub positions: usize,
+    /// Whether the result is more than an upper bound, i.e. the mean level
+    /// is at least 3 dB above the background
+    pub reliable: bool,
+}
+
+impl SoundPowerSurvey {
+    /// Survey over `surface`, without positions yet
+    pub fn new(surface: MeasurementSurface) -> Self {
+        Self {
+            surface,
+            environmental_correction: 0.0,
+            levels: Vec::new(),
+            backgrounds: Vec::new(),
+        }
+    }
+
+    /// Subtract `k2` dB for reflections in the test room
+    ///
+    /// 0 in a hemi-anechoic room or outdoors over a hard floor, the default.
+    #[must_use]
+    pub fn environmental_correction(mut self, k2: f32) -> Self {
+        self.environmental_correction = k2;
+        self
+    }
+
+    /// Add the level in dB measured at a position with the source running
+    #[must_use]
+    pub fn position(mut self, level: f32) -> Self {
+        self.levels.push(level);
+        self
+    }
+
+    /// Add the background level in dB measured at a position with the source
+    /// off
+    #[must_use]
+    pub fn background(mut self, level: f32) -> Self {
+        self.backgrounds.push(level);
+        self
+    }
+
+    /// Measure the LEQ at the next position over `duration`, with the source
+    /// running, returning it
+    pub fn measure_position(&mut self, nsrt: &mut NSRT, duration: Duration) -> Result<f32> {
+        let level = background::measure_leq(nsrt, duration)?;
+        self.levels.push(level);
+        Ok(level)
+    }
+
+    /// Measure the background LEQ over `duration`, with the source off,
+    /// returning it
+    pub fn measure_background(&mut self, nsrt: &mut NSRT, duration: Duration) -> Result<f32> {
+        let level = background::measure_leq(nsrt, duration)?;
+        self.backgrounds.push(level);
+        Ok(level)
+    }
+
+    /// Compute the sound power level
+    ///
+    /// The background correction applies to the mean level, from the energy
+    /// mean of the background levels, as in the standard. Fails without
+    /// positions.
+    pub fn compute(&self) -> Result<SoundPower> {
+        let mean_level = energy_mean(&self.levels)
+            .ok_or_else(|| NsrtError::InvalidParameter("No positions measured".to_string()))?;
+        let background = energy_mean(&self.backgrounds)
+            .map(|background| BackgroundCorrection::new(background).correct(mean_level));
+        let corrected = background.map_or(mean_level, |background| background.level);
+        let surface_area = self.surface.area();
+
+        Ok(SoundPower {
+            sound_power_level: corrected - self.environmental_correction
+                + 10.0 * surface_area.log10(),
+            mean_level,
+            background,
+            environmental_correction: self.environmental_correction,
+            surface_area,
+            positions: self.levels.len(),
+            reliable: background.is_none_or(|background| background.reliable),
+        })
+    }
+}
+
+/// Energy mean of `levels` in dB
+fn energy_mean(levels: &[f32]) -> Option<f32> {
+    let energy: f64 = levels
+        .iter()
+        .map(|&level| 10f64.powf(f64::from(level) / 10.0))
+        .sum();
+    (!levels.is_empty()).then(|| (10.0 * (energy / levels.len() as f64).log10()) as f32)
+}
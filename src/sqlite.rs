@@ -0,0 +1,594 @@
+//! SQLite measurement storage
+//!
+//! [`SqliteLog`] stores measurements in an SQLite database and answers typed
+//! queries over them, so applications don't need to write SQL. The schema is
+//! created when a database is opened:
+//!
+//! ```sql
+//! CREATE TABLE measurements (
+//!     timestamp   INTEGER NOT NULL, -- nanoseconds since the Unix epoch
+//!     level       REAL NOT NULL,    -- running level in dB
+//!     leq         REAL NOT NULL,    -- LEQ in dB
+//!     temperature REAL NOT NULL,    -- °C
+//!     quality     INTEGER NOT NULL  -- Quality::bits
+//! );
+//! CREATE INDEX measurements_timestamp ON measurements (timestamp);
+//!
+//! CREATE TABLE aggregates (
+//!     start        INTEGER NOT NULL, -- nanoseconds since the Unix epoch
+//!     duration     INTEGER NOT NULL, -- nanoseconds
+//!     measurements INTEGER NOT NULL,
+//!     leq          REAL,             -- energy average of the LEQs in dB
+//!     lmax         REAL,             -- running level extremes and
+//!     lmin         REAL,             -- exceedance levels in dB
+//!     l10          REAL,
+//!     l50          REAL,
+//!     l90          REAL,
+//!     quality      INTEGER NOT NULL  -- Quality::bits
+//! );
+//! CREATE INDEX aggregates_start ON aggregates (start);
+//! ```
+//!
+//! The schema version is kept in `PRAGMA user_version`:
+//!
+//! | Version | Changes                                                   |
+//! | ------- | --------------------------------------------------------- |
+//! | 1       | `measurements`                                            |
+//! | 2       | `aggregates`, for [`SqliteLog::compact`]                  |
+//!
+//! Databases written before the version was recorded have a `user_version`
+//! of 0. Opening a database of an older version migrates it to
+//! [`SCHEMA_VERSION`], so it can be written to and compacted like a new one.
+//!
+//! Like binary logs, rows don't hold a measurement's clock status or
+//! compensated values, only the `unsynchronized` quality flag.
+//! [`SqliteLog::compact`] replaces aged measurements with one `aggregates`
+//! row per hour or day, a few hundred bytes instead of thousands of rows.
+
+use crate::{Measurement, NsrtError, Quality, Result, Sink, Temperature, stats};
+use rusqlite::{CachedStatement, Connection, Row, params};
+use std::{
+    io,
+    ops::{Bound, RangeBounds},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Statements bringing a database from each schema version to the next, the
+/// first creating version 1 in an empty database
+///
+/// Databases from before the version was recorded are at version 0 whatever
+/// tables they have, so every statement tolerates what it creates existing
+/// already.
+const MIGRATIONS: [&str; 2] = [
+    // 1: measurements
+    "
+    CREATE TABLE IF NOT EXISTS measurements (
+        timestamp INTEGER NOT NULL,
+        level REAL NOT NULL,
+        leq REAL NOT NULL,
+        temperature REAL NOT NULL,
+        quality INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS measurements_timestamp ON measurements (timestamp);
+    ",
+    // 2: aggregates of compacted measurements
+    "
+    CREATE TABLE IF NOT EXISTS aggregates (
+        start INTEGER NOT NULL,
+        duration INTEGER NOT NULL,
+        measurements INTEGER NOT NULL,
+        leq REAL,
+        lmax REAL,
+        lmin REAL,
+        l10 REAL,
+        l50 REAL,
+        l90 REAL,
+        quality INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS aggregates_start ON aggregates (start);
+    ",
+];
+
+/// Schema version of the databases written, kept in `PRAGMA user_version`
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Measurements inserted together in one transaction
+const BATCH: usize = 64;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Sink storing measurements in an SQLite database, and queries over them
+///
+/// Measurements are buffered and inserted in one transaction when 64 of them
+/// have accumulated, on [`Sink::flush`], and when the log is dropped, so an
+/// SD card isn't synced for every row. Queries only see inserted
+/// measurements.
+///
+/// ```no_run
+/// # fn main() -> nsrt::Result<()> {
+/// use nsrt::sqlite::SqliteLog;
+/// use std::time::{Duration, SystemTime};
+///
+/// let log = SqliteLog::open("levels.db")?;
+/// let day_ago = SystemTime::now() - Duration::from_secs(24 * 3600);
+/// for hour in log.hourly(day_ago..)? {
+///     println!("{:?} LEQ {:?} dB", hour.start, hour.leq);
+/// }
+/// for event in log.events(day_ago.., 85.0)? {
+///     println!("{:?}: {} dB", event.start, event.lmax);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SqliteLog {
+    connection: Connection,
+    pending: Vec<Measurement>,
+}
+
+impl SqliteLog {
+    /// Open the database at `path`, creating it if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(Connection::open(path).map_err(sqlite_error)?)
+    }
+
+    /// Open a database held in memory, for tests and scratch work
+    pub fn open_in_memory() -> Result<Self> {
+        Self::new(Connection::open_in_memory().map_err(sqlite_error)?)
+    }
+
+    /// Store measurements on `connection`, creating the schema if needed
+    ///
+    /// A database of an older schema version is migrated to the current one
+    /// in a single transaction; one of a newer version is refused.
+    pub fn new(connection: Connection) -> Result<Self> {
+        let version: u32 = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(sqlite_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(NsrtError::UnsupportedSchema {
+                format: "SQLite log",
+                version,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        if version < SCHEMA_VERSION {
+            let migrations = MIGRATIONS[version as usize..].concat();
+            connection
+                .execute_batch(&format!(
+                    "BEGIN; {migrations} PRAGMA user_version = {SCHEMA_VERSION}; COMMIT;"
+                ))
+                .map_err(sqlite_error)?;
+        }
+        Ok(Self {
+            connection,
+            pending: Vec::with_capacity(BATCH),
+        })
+    }
+
+    /// The underlying connection
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Measurements with timestamps in `range`, in chronological order
+    pub fn measurements(&self, range: impl RangeBounds<SystemTime>) -> Result<Vec<Measurement>> {
+        let (from, to) = bounds(&range);
+        let mut statement = self
+            .connection
+            .prepare_cached(
+                "SELECT timestamp, level, leq, temperature, quality FROM measurements
+                 WHERE timestamp >= ?1 AND timestamp < ?2 ORDER BY timestamp",
+            )
+            .map_err(sqlite_error)?;
+        statement
+            .query_map(params![from, to], measurement)
+            .map_err(sqlite_error)?
+            .map(|row| row.map_err(sqlite_error))
+            .collect()
+    }
+
+    /// Number of measurements with timestamps in `range`
+    pub fn count(&self, range: impl RangeBounds<SystemTime>) -> Result<u64> {
+        let (from, to) = bounds(&range);
+        let count: i64 = self
+            .connection
+            .prepare_cached(
+                "SELECT COUNT(*) FROM measurements WHERE timestamp >= ?1 AND timestamp < ?2",
+            )
+            .and_then(|mut statement| statement.query_row(params![from, to], |row| row.get(0)))
+            .map_err(sqlite_error)?;
+        Ok(count.unsigned_abs())
+    }
+
+    /// Timestamps of the first and last stored measurements, if any
+    pub fn span(&self) -> Result<Option<(SystemTime, SystemTime)>> {
+        let (first, last): (Option<i64>, Option<i64>) = self
+            .connection
+            .query_row(
+                "SELECT MIN(timestamp), MAX(timestamp) FROM measurements",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(sqlite_error)?;
+        Ok(first
+            .zip(last)
+            .map(|(first, last)| (time(first), time(last))))
+    }
+
+    /// Aggregates of every UTC hour with measurements in `range`, in order
+    ///
+    /// Hours compacted into hourly aggregates are included; hours compacted
+    /// into longer ones are not.
+    pub fn hourly(&self, range: impl RangeBounds<SystemTime>) -> Result<Vec<Aggregate>> {
+        let mut hours: Vec<Aggregate> = self
+            .aggregates((range.start_bound().cloned(), range.end_bound().cloned()))?
+            .into_iter()
+            .filter(|aggregate| aggregate.duration == HOUR)
+            .collect();
+        periods(
+            self.measurements(range)?.into_iter().map(Ok),
+            HOUR,
+            |start, hour| {
+                hours.push(Aggregate::of(start, HOUR, hour));
+                Ok(())
+            },
+        )?;
+        hours.sort_by_key(|hour| hour.start);
+        Ok(hours)
+    }
+
+    /// Aggregates stored by [`SqliteLog::compact`] that start in `range`, in
+    /// order
+    pub fn aggregates(&self, range: impl RangeBounds<SystemTime>) -> Result<Vec<Aggregate>> {
+        let (from, to) = bounds(&range);
+        let mut statement = self
+            .connection
+            .prepare_cached(
+                "SELECT start, duration, measurements, leq, lmax, lmin, l10, l50, l90, quality
+                 FROM aggregates WHERE start >= ?1 AND start < ?2 ORDER BY start",
+            )
+            .map_err(sqlite_error)?;
+        statement
+            .query_map(params![from, to], aggregate)
+            .map_err(sqlite_error)?
+            .map(|row| row.map_err(sqlite_error))
+            .collect()
+    }
+
+    /// Replace the measurements of every `period` that ended by `before`
+    /// with one aggregate each, returning the number of measurements
+    /// replaced
+    ///
+    /// Periods are aligned to multiples of their length since the Unix
+    /// epoch, so an hour or a day in UTC. Aggregates keep the LEQ, the
+    /// extremes and the L10, L50 and L90 of the running level, and the
+    /// quality flags; individual measurements and events within them are
+    /// lost. Buffered measurements are inserted first.
+    ///
+    /// # Panics
+    ///
+    /// If `period` is zero.
+    pub fn compact(&mut self, before: SystemTime, period: Duration) -> Result<u64> {
+        assert!(!period.is_zero(), "compaction period must not be zero");
+        self.insert_pending()?;
+        let length = i64::try_from(period.as_nanos()).unwrap_or(i64::MAX);
+        let cutoff = nanos(before).div_euclid(length) * length;
+
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        {
+            let mut select = transaction
+                .prepare(
+                    "SELECT timestamp, level, leq, temperature, quality FROM measurements
+                     WHERE timestamp < ?1 ORDER BY timestamp",
+                )
+                .map_err(sqlite_error)?;
+            let mut insert = transaction
+                .prepare_cached(
+                    "INSERT INTO aggregates
+                     (start, duration, measurements, leq, lmax, lmin, l10, l50, l90, quality)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                )
+                .map_err(sqlite_error)?;
+            let rows = select
+                .query_map(params![cutoff], measurement)
+                .map_err(sqlite_error)?
+                .map(|row| row.map_err(sqlite_error));
+            periods(rows, period, |start, measurements| {
+                insert_aggregate(&mut insert, &Aggregate::of(start, period, measurements))
+            })?;
+        }
+        let compacted = transaction
+            .execute(
+                "DELETE FROM measurements WHERE timestamp < ?1",
+                params![cutoff],
+            )
+            .map_err(sqlite_error)?;
+        transaction.commit().map_err(sqlite_error)?;
+        Ok(compacted as u64)
+    }
+
+    /// Periods in `range` during which the running level exceeded
+    /// `threshold`, in order
+    pub fn events(
+        &self,
+        range: impl RangeBounds<SystemTime>,
+        threshold: f32,
+    ) -> Result<Vec<Event>> {
+        let mut events: Vec<Event> = Vec::new();
+        let mut ongoing = false;
+        for m in self.measurements(range)? {
+            if m.level <= threshold {
+                ongoing = false;
+                continue;
+            }
+            match events.last_mut() {
+                Some(event) if ongoing => {
+                    event.end = m.timestamp;
+                    event.lmax = event.lmax.max(m.level);
+                    event.measurements += 1;
+                    event.quality |= m.quality;
+                }
+                _ => events.push(Event {
+                    start: m.timestamp,
+                    end: m.timestamp,
+                    lmax: m.level,
+                    measurements: 1,
+                    quality: m.quality,
+                }),
+            }
+            ongoing = true;
+        }
+        Ok(events)
+    }
+
+    /// Delete the measurements from before `before`, returning how many were
+    ///
+    /// Buffered measurements are inserted first. SQLite reuses the freed
+    /// space for new rows rather than shrinking the file.
+    pub fn prune(&mut self, before: SystemTime) -> Result<u64> {
+        self.insert_pending()?;
+        let deleted = self
+            .connection
+            .execute(
+                "DELETE FROM measurements WHERE timestamp < ?1",
+                params![nanos(before)],
+            )
+            .map_err(sqlite_error)?;
+        Ok(deleted as u64)
+    }
+
+    fn insert_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        {
+            let mut statement = transaction
+                .prepare_cached(
+                    "INSERT INTO measurements (timestamp, level, leq, temperature, quality)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                )
+                .map_err(sqlite_error)?;
+            for m in &self.pending {
+                statement
+                    .execute(params![
+                        nanos(m.timestamp),
+                        m.level,
+                        m.leq,
+                        m.temperature.as_celsius(),
+                        m.quality.bits(),
+                    ])
+                    .map_err(sqlite_error)?;
+            }
+        }
+        transaction.commit().map_err(sqlite_error)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for SqliteLog {
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.pending.push(*measurement);
+        if self.pending.len() >= BATCH {
+            self.insert_pending()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.insert_pending()
+    }
+}
+
+impl Drop for SqliteLog {
+    fn drop(&mut self) {
+        let _ = self.insert_pending();
+    }
+}
+
+/// Aggregate of the measurements in one period, such as a UTC hour
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Aggregate {
+    /// Start of the period
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub start: SystemTime,
+    /// Length of the period
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub duration: Duration,
+    /// Number of measurements in the period
+    pub measurements: u32,
+    /// Energy average of the measurements' LEQs in dB, each weighted equally
+    pub leq: Option<f32>,
+    /// Highest running level in dB
+    pub lmax: Option<f32>,
+    /// Lowest running level in dB
+    pub lmin: Option<f32>,
+    /// Running level exceeded 10% of the time in dB
+    pub l10: Option<f32>,
+    /// Running level exceeded 50% of the time in dB
+    pub l50: Option<f32>,
+    /// Running level exceeded 90% of the time in dB
+    pub l90: Option<f32>,
+    /// Quality flags of all the measurements in the period
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Quality::is_good")
+    )]
+    pub quality: Quality,
+}
+
+impl Aggregate {
+    fn of(start: SystemTime, duration: Duration, measurements: &[Measurement]) -> Self {
+        let levels = stats::sorted_levels(measurements.iter().map(|m| m.level));
+        Self {
+            start,
+            duration,
+            measurements: u32::try_from(measurements.len()).unwrap_or(u32::MAX),
+            leq: stats::energy_average(measurements.iter().map(|m| (m.leq, 1.0))),
+            lmax: levels.last().copied(),
+            lmin: levels.first().copied(),
+            l10: stats::exceedance_level(&levels, 10.0),
+            l50: stats::exceedance_level(&levels, 50.0),
+            l90: stats::exceedance_level(&levels, 90.0),
+            quality: measurements
+                .iter()
+                .fold(Quality::GOOD, |quality, m| quality | m.quality),
+        }
+    }
+}
+
+/// Period during which the running level exceeded a threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Event {
+    /// First measurement above the threshold
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub start: SystemTime,
+    /// Last measurement above the threshold
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub end: SystemTime,
+    /// Highest running level during the event in dB
+    pub lmax: f32,
+    /// Number of measurements above the threshold
+    pub measurements: u32,
+    /// Quality flags of the measurements during the event
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Quality::is_good")
+    )]
+    pub quality: Quality,
+}
+
+fn measurement(row: &Row<'_>) -> rusqlite::Result<Measurement> {
+    Ok(Measurement {
+        timestamp: time(row.get(0)?),
+        level: row.get(1)?,
+        leq: row.get(2)?,
+        temperature: Temperature::from_celsius(row.get(3)?),
+        clock: None,
+        compensated: None,
+        quality: Quality::from_bits(row.get(4)?),
+    })
+}
+
+fn aggregate(row: &Row<'_>) -> rusqlite::Result<Aggregate> {
+    Ok(Aggregate {
+        start: time(row.get(0)?),
+        duration: Duration::from_nanos(row.get::<_, i64>(1)?.unsigned_abs()),
+        measurements: row.get(2)?,
+        leq: row.get(3)?,
+        lmax: row.get(4)?,
+        lmin: row.get(5)?,
+        l10: row.get(6)?,
+        l50: row.get(7)?,
+        l90: row.get(8)?,
+        quality: Quality::from_bits(row.get(9)?),
+    })
+}
+
+fn insert_aggregate(insert: &mut CachedStatement<'_>, aggregate: &Aggregate) -> Result<()> {
+    insert
+        .execute(params![
+            nanos(aggregate.start),
+            i64::try_from(aggregate.duration.as_nanos()).unwrap_or(i64::MAX),
+            aggregate.measurements,
+            aggregate.leq,
+            aggregate.lmax,
+            aggregate.lmin,
+            aggregate.l10,
+            aggregate.l50,
+            aggregate.l90,
+            aggregate.quality.bits(),
+        ])
+        .map_err(sqlite_error)?;
+    Ok(())
+}
+
+/// Call `each` with the start and the measurements of every period of
+/// `length` that has measurements, in order
+///
+/// Periods are aligned to multiples of `length` since the Unix epoch, and the
+/// measurements must be in chronological order.
+fn periods(
+    measurements: impl IntoIterator<Item = Result<Measurement>>,
+    length: Duration,
+    mut each: impl FnMut(SystemTime, &[Measurement]) -> Result<()>,
+) -> Result<()> {
+    let length = i64::try_from(length.as_nanos()).unwrap_or(i64::MAX);
+    let mut current = None;
+    let mut period = Vec::new();
+    for measurement in measurements {
+        let measurement = measurement?;
+        let start = nanos(measurement.timestamp).div_euclid(length) * length;
+        if let Some(current) = current
+            && current != start
+        {
+            each(time(current), &period)?;
+            period.clear();
+        }
+        current = Some(start);
+        period.push(measurement);
+    }
+    if let Some(current) = current {
+        each(time(current), &period)?;
+    }
+    Ok(())
+}
+
+/// Half-open range of nanosecond timestamps covering `range`
+fn bounds(range: &impl RangeBounds<SystemTime>) -> (i64, i64) {
+    let from = match range.start_bound() {
+        Bound::Included(&start) => nanos(start),
+        Bound::Excluded(&start) => nanos(start).saturating_add(1),
+        Bound::Unbounded => i64::MIN,
+    };
+    let to = match range.end_bound() {
+        Bound::Included(&end) => nanos(end).saturating_add(1),
+        Bound::Excluded(&end) => nanos(end),
+        Bound::Unbounded => i64::MAX,
+    };
+    (from, to)
+}
+
+fn nanos(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => i64::try_from(since.as_nanos()).unwrap_or(i64::MAX),
+        Err(before) => i64::try_from(before.duration().as_nanos()).map_or(i64::MIN, |n| -n),
+    }
+}
+
+fn time(nanos: i64) -> SystemTime {
+    let magnitude = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos >= 0 {
+        UNIX_EPOCH + magnitude
+    } else {
+        UNIX_EPOCH - magnitude
+    }
+}
+
+fn sqlite_error(error: rusqlite::Error) -> NsrtError {
+    NsrtError::IoError(io::Error::other(error))
+}
@@ -0,0 +1,33 @@
+//! Acoustic statistics over levels in dB
+
+/// Energy average of `(level, weight)` pairs, e.g. LEQs and their durations
+pub(crate) fn energy_average(levels: impl IntoIterator<Item = (f32, f64)>) -> Option<f32> {
+    let (energy, weight) = levels
+        .into_iter()
+        .filter(|(level, weight)| level.is_finite() && *weight > 0.0)
+        .fold((0.0, 0.0), |(energy, total), (level, weight)| {
+            (
+                energy + weight * 10f64.powf(f64::from(level) / 10.0),
+                total + weight,
+            )
+        });
+
+    (weight > 0.0).then(|| (10.0 * (energy / weight).log10()) as f32)
+}
+
+/// Level exceeded `n` percent of the time (Ln), from levels sorted ascending
+pub(crate) fn exceedance_level(sorted: &[f32], n: f32) -> Option<f32> {
+    if sorted.is_empty() {
+        return None;
+    }
+    // Ln is the (100 - n)th percentile, by nearest rank
+    let rank = ((100.0 - n) / 100.0 * sorted.len() as f32).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Finite levels sorted ascending
+pub(crate) fn sorted_levels(levels: impl IntoIterator<Item = f32>) -> Vec<f32> {
+    let mut levels: Vec<f32> = levels.into_iter().filter(|l| l.is_finite()).collect();
+    levels.sort_by(f32::total_cmp);
+    levels
+}
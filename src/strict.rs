@@ -0,0 +1,112 @@
+use crate::{NSRT, NsrtError, Result, protocol::Command};
+use std::{io::Read, time::Duration};
+
+/// Checks of the device's answers beyond what the driver needs, turning
+/// subtle disagreements between firmware and driver into errors
+///
+/// Off by default, as a device that works in practice may fail them; meant
+/// for development and for qualifying new firmware. When on, every exchange
+/// fails with [`NsrtError::ProtocolViolation`] if:
+///
+/// - an answer is shorter than the command's response length, rather than
+///   just timing out
+/// - bytes are left over after an answer, which are then discarded; this
+///   needs a transport that can tell, see [`Transport::bytes_to_read`]
+/// - the acknowledge of a write takes longer than `ack_timeout`
+///
+/// [`Transport::bytes_to_read`]: crate::Transport::bytes_to_read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StrictMode {
+    /// Longest time from the end of a write to its acknowledge
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub ack_timeout: Duration,
+}
+
+impl Default for StrictMode {
+    /// Acknowledges within 100 ms
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+impl NSRT {
+    /// Check answers strictly with `mode`, or not with `None`
+    pub fn set_strict_mode(&mut self, mode: Option<StrictMode>) {
+        self.strict = mode;
+    }
+
+    /// Check answers strictly with `mode`, using fluent API
+    #[must_use]
+    pub fn strict_mode(mut self, mode: StrictMode) -> Self {
+        self.strict = Some(mode);
+        self
+    }
+
+    /// Read the answer to `cmd`, or to a batch for `None`, into `buf`
+    pub(crate) fn read_answer(&mut self, cmd: Option<Command>, buf: &mut [u8]) -> Result<()> {
+        if self.strict.is_none() {
+            self.port.read_exact(buf)?;
+            return Ok(());
+        }
+
+        let mut read = 0;
+        while read < buf.len() {
+            match self.port.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(count) => read += count,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) if read == 0 => return Err(e.into()),
+                Err(_) => break,
+            }
+        }
+        if read < buf.len() {
+            return Err(violation(format!(
+                "answer to {} is {read} bytes, expected {}",
+                describe(cmd),
+                buf.len()
+            )));
+        }
+        self.check_drained(cmd)
+    }
+
+    /// Fail if the acknowledge of `cmd` took `elapsed`, longer than allowed
+    pub(crate) fn check_ack_time(&self, cmd: Command, elapsed: Duration) -> Result<()> {
+        match self.strict {
+            Some(mode) if elapsed > mode.ack_timeout => Err(violation(format!(
+                "acknowledge of {cmd:?} took {} ms, allowed {} ms",
+                elapsed.as_millis(),
+                mode.ack_timeout.as_millis()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail, discarding them, if bytes are left over after the answer to
+    /// `cmd`
+    pub(crate) fn check_drained(&mut self, cmd: Option<Command>) -> Result<()> {
+        if self.strict.is_none() {
+            return Ok(());
+        }
+        match self.port.bytes_to_read()? {
+            Some(left) if left > 0 => {
+                self.port.clear()?;
+                Err(violation(format!(
+                    "{left} bytes left over after the answer to {}",
+                    describe(cmd)
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn describe(cmd: Option<Command>) -> String {
+    cmd.map_or_else(|| "a batch".to_string(), |cmd| format!("{cmd:?}"))
+}
+
+fn violation(message: String) -> NsrtError {
+    NsrtError::ProtocolViolation(message)
+}
@@ -0,0 +1,313 @@
+use crate::{DropPolicy, Measurement};
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{RecvError, RecvTimeoutError, TryRecvError},
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+/// Buffer of a subscription, bounded unless it keeps everything
+enum Queue {
+    Bounded(ArrayQueue<Measurement>, DropPolicy),
+    Unbounded(SegQueue<Measurement>),
+}
+
+/// The sampler's end of a [`Subscription`]
+///
+/// Sending never blocks: the queues are lock-free, and the receiver's lock is
+/// only taken while it waits for a measurement.
+pub(crate) struct Channel {
+    queue: Queue,
+    dropped: AtomicU64,
+    closed: AtomicBool,
+    /// Set while the receiver is parked waiting
+    waiting: AtomicBool,
+    receiver: Mutex<Option<Thread>>,
+}
+
+impl Channel {
+    pub(crate) fn bounded(capacity: usize, policy: DropPolicy) -> Arc<Self> {
+        Self::new(Queue::Bounded(ArrayQueue::new(capacity), policy))
+    }
+
+    pub(crate) fn unbounded() -> Arc<Self> {
+        Self::new(Queue::Unbounded(SegQueue::new()))
+    }
+
+    fn new(queue: Queue) -> Arc<Self> {
+        Arc::new(Self {
+            queue,
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            waiting: AtomicBool::new(false),
+            receiver: Mutex::new(None),
+        })
+    }
+
+    /// Queue `measurement`, dropping one per the policy if full
+    pub(crate) fn send(&self, measurement: Measurement) {
+        let dropped = match &self.queue {
+            Queue::Bounded(queue, DropPolicy::DropOldest) => {
+                queue.force_push(measurement).is_some()
+            }
+            Queue::Bounded(queue, DropPolicy::DropNewest) => queue.push(measurement).is_err(),
+            Queue::Unbounded(queue) => {
+                queue.push(measurement);
+                false
+            }
+        };
+        if dropped {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        self.wake();
+    }
+
+    /// Tell the receiver no more measurements will come
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.wake();
+    }
+
+    /// Whether the receiver has been dropped
+    pub(crate) fn is_abandoned(self: &Arc<Self>) -> bool {
+        Arc::strong_count(self) == 1
+    }
+
+    fn wake(&self) {
+        if self.waiting.load(Ordering::SeqCst)
+            && let Some(receiver) = &*lock(&self.receiver)
+        {
+            receiver.unpark();
+        }
+    }
+
+    fn pop(&self) -> Option<Measurement> {
+        match &self.queue {
+            Queue::Bounded(queue, _) => queue.pop(),
+            Queue::Unbounded(queue) => queue.pop(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.queue {
+            Queue::Bounded(queue, _) => queue.is_empty(),
+            Queue::Unbounded(queue) => queue.is_empty(),
+        }
+    }
+}
+
+/// Measurements from a [`Sampler`](crate::Sampler), from
+/// [`Sampler::subscribe`](crate::Sampler::subscribe)
+///
+/// The sampler hands measurements over through a lock-free ring buffer and
+/// never waits for the subscriber. Once the buffer is full, the
+/// [`DropPolicy`] decides which measurement is lost, and
+/// [`Subscription::dropped`] counts them. Iterating blocks for the next
+/// measurement and ends when the sampler stops.
+pub struct Subscription {
+    channel: Arc<Channel>,
+}
+
+impl Subscription {
+    pub(crate) fn new(channel: Arc<Channel>) -> Self {
+        Self { channel }
+    }
+
+    /// Wait for the next measurement
+    ///
+    /// Fails once the sampler has stopped and every measurement has been
+    /// received.
+    pub fn recv(&self) -> Result<Measurement, RecvError> {
+        self.wait(None).map_err(|_| RecvError)
+    }
+
+    /// Wait at most `timeout` for the next measurement
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Measurement, RecvTimeoutError> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    /// Take the next measurement if one is waiting
+    pub fn try_recv(&self) -> Result<Measurement, TryRecvError> {
+        if let Some(measurement) = self.channel.pop() {
+            return Ok(measurement);
+        }
+        if self.channel.closed.load(Ordering::SeqCst) {
+            // A last measurement may have been queued before closing
+            return self.channel.pop().ok_or(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    /// Iterate over the measurements waiting, without blocking
+    pub fn try_iter(&self) -> impl Iterator<Item = Measurement> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    /// Number of measurements dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.channel.dropped.load(Ordering::Relaxed)
+    }
+
+    fn wait(&self, deadline: Option<Instant>) -> Result<Measurement, RecvTimeoutError> {
+        loop {
+            match self.try_recv() {
+                Ok(measurement) => return Ok(measurement),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {}
+            }
+
+            *lock(&self.channel.receiver) = Some(thread::current());
+            self.channel.waiting.store(true, Ordering::SeqCst);
+            // Check again, as the sampler may have sent before seeing the flag
+            if self.channel.is_empty() && !self.channel.closed.load(Ordering::SeqCst) {
+                match deadline {
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            self.channel.waiting.store(false, Ordering::SeqCst);
+                            return Err(RecvTimeoutError::Timeout);
+                        }
+                        thread::park_timeout(deadline - now);
+                    }
+                    None => thread::park(),
+                }
+            }
+            self.channel.waiting.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Measurement;
+
+    fn next(&mut self) -> Option<Measurement> {
+        self.recv().ok()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Quality, Temperature};
+    use std::time::UNIX_EPOCH;
+
+    fn at(secs: u64) -> Measurement {
+        Measurement {
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+            level: 50.0,
+            leq: 50.0,
+            temperature: Temperature::from_celsius(20.0),
+            clock: None,
+            compensated: None,
+            quality: Quality::GOOD,
+        }
+    }
+
+    fn received(subscription: &Subscription) -> Vec<u64> {
+        subscription
+            .try_iter()
+            .map(|m| m.timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs())
+            .collect()
+    }
+
+    #[test]
+    fn drop_policies() {
+        for (channel, kept) in [
+            (Channel::bounded(2, DropPolicy::DropOldest), [1, 2, 3, 4]),
+            (Channel::bounded(2, DropPolicy::DropNewest), [0, 1, 3, 4]),
+        ] {
+            let subscription = Subscription::new(Arc::clone(&channel));
+            for secs in 0..3 {
+                channel.send(at(secs));
+            }
+            assert_eq!(subscription.dropped(), 1);
+            assert_eq!(received(&subscription)[..], kept[..2]);
+
+            // Draining makes room again
+            channel.send(at(3));
+            channel.send(at(4));
+            assert_eq!(received(&subscription)[..], kept[2..]);
+            assert_eq!(subscription.dropped(), 1);
+        }
+
+        let channel = Channel::unbounded();
+        let subscription = Subscription::new(Arc::clone(&channel));
+        for secs in 0..5000 {
+            channel.send(at(secs));
+        }
+        assert_eq!(received(&subscription).len(), 5000);
+        assert_eq!(subscription.dropped(), 0);
+    }
+
+    #[test]
+    fn close_delivers_queued_measurements() {
+        let channel = Channel::bounded(4, DropPolicy::DropOldest);
+        let subscription = Subscription::new(Arc::clone(&channel));
+        assert_eq!(subscription.try_recv(), Err(TryRecvError::Empty));
+        channel.send(at(0));
+        channel.close();
+        assert_eq!(subscription.try_recv(), Ok(at(0)));
+        assert_eq!(subscription.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(subscription.recv(), Err(RecvError));
+        assert!(!channel.is_abandoned());
+        drop(subscription);
+        assert!(channel.is_abandoned());
+    }
+
+    #[test]
+    fn recv_timeout() {
+        let channel = Channel::bounded(4, DropPolicy::DropOldest);
+        let subscription = Subscription::new(Arc::clone(&channel));
+        let start = Instant::now();
+        assert_eq!(
+            subscription.recv_timeout(Duration::from_millis(50)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // A send wakes the waiting receiver well before the timeout
+        let sender = {
+            let channel = Arc::clone(&channel);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                channel.send(at(1));
+            })
+        };
+        let start = Instant::now();
+        assert_eq!(
+            subscription.recv_timeout(Duration::from_secs(10)),
+            Ok(at(1))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn close_wakes_receiver() {
+        let channel = Channel::bounded(4, DropPolicy::DropOldest);
+        let subscription = Subscription::new(Arc::clone(&channel));
+        let closer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            channel.close();
+        });
+        let start = Instant::now();
+        assert_eq!(
+            subscription.recv_timeout(Duration::from_secs(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // Blocking iteration ends too
+        assert_eq!(subscription.count(), 0);
+        closer.join().unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// A temperature, as measured by the device's sensor
+///
+/// Stored in degrees Celsius, as the device reports it, and serialized as a
+/// plain number of degrees Celsius.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Temperature(f32);
+
+impl Temperature {
+    /// Temperature of `degrees` °C
+    pub const fn from_celsius(degrees: f32) -> Self {
+        Self(degrees)
+    }
+
+    /// Temperature of `degrees` °F
+    pub fn from_fahrenheit(degrees: f32) -> Self {
+        Self((degrees - 32.0) / 1.8)
+    }
+
+    /// Temperature of `kelvin` K
+    pub fn from_kelvin(kelvin: f32) -> Self {
+        Self(kelvin - 273.15)
+    }
+
+    /// Degrees Celsius
+    pub const fn as_celsius(self) -> f32 {
+        self.0
+    }
+
+    /// Degrees Fahrenheit
+    pub fn as_fahrenheit(self) -> f32 {
+        self.0 * 1.8 + 32.0
+    }
+
+    /// Kelvin
+    pub fn as_kelvin(self) -> f32 {
+        self.0 + 273.15
+    }
+}
+
+/// Formats as degrees Celsius with the unit, honouring the precision, e.g.
+/// `21.5 °C` for `{:.1}`
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)?;
+        f.write_str(" °C")
+    }
+}
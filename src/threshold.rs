@@ -0,0 +1,84 @@
+use crate::Measurement;
+
+/// Level threshold with hysteresis
+///
+/// The threshold is exceeded when the running level rises above `level`, and
+/// only clears once it falls below `level - hysteresis`, so a level hovering
+/// around the threshold doesn't toggle the state on every sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Threshold {
+    /// Level in dB above which the threshold is exceeded
+    pub level: f32,
+    /// Drop in dB below `level` required to clear the threshold
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hysteresis: f32,
+}
+
+impl Threshold {
+    /// Create a threshold at `level` dB without hysteresis
+    pub fn new(level: f32) -> Self {
+        Self {
+            level,
+            hysteresis: 0.0,
+        }
+    }
+
+    /// Set the hysteresis in dB
+    #[must_use]
+    pub fn hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+}
+
+/// Transition of a [`ThresholdMonitor`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdEvent {
+    /// The level rose above the threshold
+    Exceeded(Measurement),
+    /// The level fell back below the threshold minus hysteresis
+    Cleared(Measurement),
+}
+
+/// Tracks whether measurements exceed a [`Threshold`]
+#[derive(Debug, Clone)]
+pub struct ThresholdMonitor {
+    threshold: Threshold,
+    exceeded: bool,
+}
+
+impl ThresholdMonitor {
+    /// Create a monitor in the cleared state
+    pub fn new(threshold: Threshold) -> Self {
+        Self {
+            threshold,
+            exceeded: false,
+        }
+    }
+
+    /// The monitored threshold
+    pub fn threshold(&self) -> Threshold {
+        self.threshold
+    }
+
+    /// Whether the threshold is currently exceeded
+    pub fn is_exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// Feed a measurement, returning the transition it caused, if any
+    pub fn update(&mut self, measurement: &Measurement) -> Option<ThresholdEvent> {
+        let level = measurement.level;
+
+        if !self.exceeded && level > self.threshold.level {
+            self.exceeded = true;
+            Some(ThresholdEvent::Exceeded(*measurement))
+        } else if self.exceeded && level < self.threshold.level - self.threshold.hysteresis {
+            self.exceeded = false;
+            Some(ThresholdEvent::Cleared(*measurement))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! TLS for network endpoints
+//!
+//! [`TlsIdentity`] holds a PEM certificate chain and private key, as issued by
+//! an internal CA or Let's Encrypt. With it, `http::serve_tls` and
+//! `grpc::serve_tls` serve their APIs over TLS 1.2 and 1.3 only, using
+//! rustls with the *ring* provider. [`TlsListener`] terminates TLS in front of
+//! any axum router.
+
+use crate::{NsrtError, Result};
+use rustls::{
+    ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use std::{fs, io, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+
+/// Time a client gets to complete the handshake before it is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshaken connections waiting to be accepted
+const BACKLOG: usize = 16;
+
+/// Certificate chain and private key a server presents
+#[derive(Clone)]
+pub struct TlsIdentity {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl TlsIdentity {
+    /// Identity from a PEM certificate chain, leaf first, and a PEM PKCS#8,
+    /// PKCS#1 or SEC1 private key
+    pub fn from_pem(cert: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Result<Self> {
+        let identity = Self {
+            cert: cert.into(),
+            key: key.into(),
+        };
+        // Fail here rather than when the server starts
+        identity.server_config()?;
+        Ok(identity)
+    }
+
+    /// Identity from the PEM files at `cert` and `key`
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self> {
+        Self::from_pem(fs::read(cert)?, fs::read(key)?)
+    }
+
+    /// The certificate chain as PEM
+    pub fn cert_pem(&self) -> &[u8] {
+        &self.cert
+    }
+
+    /// The private key as PEM
+    pub fn key_pem(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// rustls server configuration presenting this identity
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>> {
+        let certs = CertificateDer::pem_slice_iter(&self.cert)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| invalid(format!("Invalid certificate: {e}")))?;
+        if certs.is_empty() {
+            return Err(invalid("No certificate found".to_string()));
+        }
+        let key = PrivateKeyDer::from_pem_slice(&self.key)
+            .map_err(|e| invalid(format!("Invalid private key: {e}")))?;
+
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(tls_error)?
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .map_err(tls_error)?;
+        Ok(Arc::new(config))
+    }
+}
+
+impl std::fmt::Debug for TlsIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsIdentity").finish_non_exhaustive()
+    }
+}
+
+/// Listener accepting TLS connections, for `axum::serve`
+///
+/// Handshakes run concurrently in the background, so a slow or stalled client
+/// doesn't hold up others; clients failing theirs, or taking longer than ten
+/// seconds, are dropped. Must be created within a Tokio runtime.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    streams: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    accept: JoinHandle<()>,
+}
+
+impl TlsListener {
+    /// Accept connections on `listener` and handshake with `config`
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (sender, streams) = mpsc::channel(BACKLOG);
+
+        let accept = tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    // Out of file descriptors and the like; wait for some to
+                    // be released
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let handshake =
+                        tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+                    if let Ok(Ok(stream)) = handshake.await {
+                        let _ = sender.send((stream, addr)).await;
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            streams,
+            accept,
+        })
+    }
+
+    /// Wait for the next connection that completed its handshake
+    pub async fn accept(&mut self) -> (TlsStream<TcpStream>, SocketAddr) {
+        match self.streams.recv().await {
+            Some(accepted) => accepted,
+            // The accept task only ends when the listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.accept.abort();
+    }
+}
+
+#[cfg(feature = "http")]
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        TlsListener::accept(self).await
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(TlsListener::local_addr(self))
+    }
+}
+
+fn invalid(message: String) -> NsrtError {
+    NsrtError::InvalidParameter(message)
+}
+
+fn tls_error(e: rustls::Error) -> NsrtError {
+    NsrtError::InvalidParameter(format!("TLS configuration: {e}"))
+}
@@ -0,0 +1,259 @@
+use serialport::{ClearBuffer, SerialPort};
+use std::{
+    collections::VecDeque,
+    io::{self, IoSlice, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Byte stream to an `NSRT_mk4`
+///
+/// Implemented for serial ports and TCP streams. Implement it to reach a device over another
+/// link, or use [`MockTransport`] to exercise the driver without hardware.
+pub trait Transport: Read + Write + Send {
+    /// Discard data buffered in either direction
+    fn clear(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Number of received bytes waiting to be read, or `None` if the
+    /// transport can't tell
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        Ok(None)
+    }
+}
+
+impl Transport for Box<dyn Transport> {
+    fn clear(&mut self) -> io::Result<()> {
+        self.as_mut().clear()
+    }
+
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        self.as_mut().bytes_to_read()
+    }
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn clear(&mut self) -> io::Result<()> {
+        SerialPort::clear(self.as_ref(), ClearBuffer::All).map_err(io::Error::from)
+    }
+
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        let count = SerialPort::bytes_to_read(self.as_ref())?;
+        Ok(Some(count as usize))
+    }
+}
+
+impl Transport for TcpStream {
+    fn clear(&mut self) -> io::Result<()> {
+        // Drain whatever has arrived without waiting for more
+        self.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let drained = loop {
+            match self.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.set_nonblocking(false)?;
+        drained
+    }
+
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        self.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let peeked = match self.peek(&mut buf) {
+            Ok(count) => Ok(Some(count)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(Some(0)),
+            Err(e) => Err(e),
+        };
+        self.set_nonblocking(false)?;
+        peeked
+    }
+}
+
+/// Write all of `bufs`, in as few calls as `writer` allows
+///
+/// Stands in for the unstable `Write::write_all_vectored`. Writers without a
+/// native vectored write, such as serial ports, take one call per buffer.
+pub(crate) fn write_all_vectored(
+    writer: &mut impl Write,
+    mut bufs: &mut [IoSlice<'_>],
+) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match writer.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(count) => IoSlice::advance_slices(&mut bufs, count),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// One scripted request and its response
+#[derive(Debug)]
+struct Exchange {
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    exchanges: VecDeque<Exchange>,
+    /// Bytes of the front request written so far
+    written: usize,
+    /// Response bytes not read yet
+    pending: VecDeque<u8>,
+}
+
+impl Script {
+    /// Answer exchanges that don't wait for a request
+    fn advance(&mut self) {
+        while let Some(exchange) = self
+            .exchanges
+            .pop_front_if(|exchange| exchange.request.is_empty())
+        {
+            self.pending.extend(exchange.response);
+        }
+    }
+}
+
+/// Transport replaying a scripted conversation
+///
+/// Every [`MockTransport::expect`] adds an exchange: the bytes the driver must
+/// write, and the bytes the device answers with once they have all been
+/// written. A write that departs from the script fails with
+/// [`io::ErrorKind::InvalidData`]. Reading beyond the answers fails with
+/// [`io::ErrorKind::TimedOut`], as a serial port does when the device stays
+/// silent.
+///
+/// Clones share the script, so a clone kept aside can check what is left
+/// after the transport has been handed to [`NSRT::with_transport`](crate::NSRT::with_transport).
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    script: Arc<Mutex<Script>>,
+}
+
+impl MockTransport {
+    /// Transport with an empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect `request` to be written, then answer with `response`
+    ///
+    /// An empty `request` answers as soon as the earlier exchanges are done,
+    /// like a device sending data unasked.
+    pub fn expect(&self, request: impl Into<Vec<u8>>, response: impl Into<Vec<u8>>) -> &Self {
+        let mut script = self.script();
+        script.exchanges.push_back(Exchange {
+            request: request.into(),
+            response: response.into(),
+        });
+        if script.written == 0 {
+            script.advance();
+        }
+        self
+    }
+
+    /// Whether every exchange has taken place and every answer has been read
+    pub fn is_done(&self) -> bool {
+        let script = self.script();
+        script.exchanges.is_empty() && script.pending.is_empty()
+    }
+
+    /// Panic unless every exchange has taken place and every answer has been
+    /// read
+    pub fn assert_done(&self) {
+        let script = self.script();
+        assert!(
+            script.exchanges.is_empty(),
+            "{} scripted exchanges did not take place, next request: {:02x?}",
+            script.exchanges.len(),
+            script.exchanges.front().map(|e| &e.request)
+        );
+        assert!(
+            script.pending.is_empty(),
+            "answer not read: {:02x?}",
+            script.pending
+        );
+    }
+
+    fn script(&self) -> MutexGuard<'_, Script> {
+        self.script.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut script = self.script();
+        if script.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no scripted answer left",
+            ));
+        }
+
+        let count = buf.len().min(script.pending.len());
+        for (byte, answer) in buf.iter_mut().zip(script.pending.drain(..count)) {
+            *byte = answer;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut script = self.script();
+        for &byte in buf {
+            let written = script.written;
+            let Some(exchange) = script.exchanges.front() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected write of {byte:#04x}, no request left"),
+                ));
+            };
+            if exchange.request.get(written) != Some(&byte) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "unexpected write of {byte:#04x} at byte {written} of request {:02x?}",
+                        exchange.request
+                    ),
+                ));
+            }
+
+            let complete = written + 1 == exchange.request.len();
+            script.written += 1;
+            if complete {
+                let exchange = script.exchanges.pop_front().expect("front exchange exists");
+                script.written = 0;
+                script.pending.extend(exchange.response);
+                script.advance();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn clear(&mut self) -> io::Result<()> {
+        self.script().pending.clear();
+        Ok(())
+    }
+
+    fn bytes_to_read(&mut self) -> io::Result<Option<usize>> {
+        Ok(Some(self.script().pending.len()))
+    }
+}
@@ -0,0 +1,131 @@
+use crate::Measurement;
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Default number of reconnects before a stall is escalated
+const DEFAULT_RECONNECTS: u32 = 3;
+
+/// Recovery step requested by a [`Watchdog`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recovery {
+    /// Discard buffered serial data and check the device answers, see
+    /// [`NSRT::resync`](crate::NSRT::resync)
+    Resync,
+    /// Close the device and open it again
+    Reconnect,
+    /// Recovery failed; report the fault, then keep reconnecting
+    Escalate(Fault),
+}
+
+/// Device stall that automatic recovery could not resolve
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fault {
+    /// Time of the last measurement, if any was taken
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub last_measurement: Option<SystemTime>,
+    /// Time since the last measurement, or since watching started
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub stalled_for: Duration,
+    /// Recovery steps tried, including the resync
+    pub attempts: u32,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no measurement for {} s despite {} recovery attempts",
+            self.stalled_for.as_secs(),
+            self.attempts
+        )
+    }
+}
+
+/// Detects stalled sampling and schedules recovery
+///
+/// Feed it every measurement and call [`Watchdog::check`] periodically. Once
+/// no measurement has arrived for the stall timeout, it asks for a resync,
+/// then for a reconnect whenever another timeout passes without one. When the configured
+/// number of reconnects has not helped either, it escalates with a [`Fault`]
+/// once, and keeps asking for reconnects until measurements resume.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    reconnects: u32,
+    last_fed: Instant,
+    /// Start of the current timeout
+    timer: Instant,
+    last_measurement: Option<SystemTime>,
+    /// Recovery steps requested since the last measurement
+    attempts: u32,
+    escalated: bool,
+}
+
+impl Watchdog {
+    /// Watch for `timeout` without measurements, starting now
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            reconnects: DEFAULT_RECONNECTS,
+            last_fed: Instant::now(),
+            timer: Instant::now(),
+            last_measurement: None,
+            attempts: 0,
+            escalated: false,
+        }
+    }
+
+    /// Escalate after `reconnects` reconnects fail to resume sampling
+    #[must_use]
+    pub fn reconnects(mut self, reconnects: u32) -> Self {
+        self.reconnects = reconnects;
+        self
+    }
+
+    /// Record a measurement, ending any stall
+    ///
+    /// Returns whether sampling recovered from an escalated fault.
+    pub fn feed(&mut self, measurement: &Measurement) -> bool {
+        let recovered = self.escalated;
+        self.last_fed = Instant::now();
+        self.timer = self.last_fed;
+        self.last_measurement = Some(measurement.timestamp);
+        self.attempts = 0;
+        self.escalated = false;
+        recovered
+    }
+
+    /// Restart the timeout, e.g. once the device has been reopened
+    ///
+    /// Recovery attempts made so far still count towards escalation.
+    pub fn restart(&mut self) {
+        self.timer = Instant::now();
+    }
+
+    /// The recovery step due now, if any
+    pub fn check(&mut self) -> Option<Recovery> {
+        // Each step gets a full timeout to show an effect
+        if self.timer.elapsed() < self.timeout {
+            return None;
+        }
+        self.timer = Instant::now();
+        let stalled_for = self.last_fed.elapsed();
+
+        self.attempts += 1;
+        if self.attempts == 1 {
+            return Some(Recovery::Resync);
+        }
+        if self.attempts > self.reconnects + 1 && !self.escalated {
+            self.escalated = true;
+            return Some(Recovery::Escalate(Fault {
+                last_measurement: self.last_measurement,
+                stalled_for,
+                attempts: self.attempts - 1,
+            }));
+        }
+        Some(Recovery::Reconnect)
+    }
+}
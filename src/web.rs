@@ -0,0 +1,329 @@
+//! Browser driver over Web Serial
+//!
+//! The `web` feature exports [`WebMeter`] through wasm-bindgen for builds
+//! targeting `wasm32-unknown-unknown`. Web Serial is asynchronous, so this is
+//! a separate read-only driver rather than a [`Transport`](crate::Transport)
+//! for [`NSRT`](crate::NSRT), but it sends the same [`protocol`] packets and
+//! decodes answers with the same functions. `web/index.html` is a read-out
+//! page built on it:
+//!
+//! ```js
+//! import init, { WebMeter } from "./pkg/nsrt.js";
+//!
+//! await init();
+//! const meter = await WebMeter.request();
+//! const { level, leq } = await meter.readMeasurement();
+//! ```
+//!
+//! Web Serial is available in Chromium-based browsers, on pages served over
+//! HTTPS or from localhost. Requests on one meter must not overlap; a call
+//! made while another is still waiting fails.
+
+use crate::{
+    NsrtError, Result, TIMEOUT, USB_PID, USB_VID,
+    info::DEVICE_EPOCH_OFFSET,
+    protocol::{self, Command},
+};
+use js_sys::{Array, Date, Function, Object, Promise, Reflect, Uint8Array};
+use std::{
+    cell::{Cell, RefCell},
+    io,
+};
+use wasm_bindgen::{JsCast, prelude::*};
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A serial port granted to the page, as returned by
+    /// `navigator.serial.getPorts()`
+    pub type SerialPort;
+
+    #[wasm_bindgen(method)]
+    fn open(this: &SerialPort, options: &Object) -> Promise;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &SerialPort) -> Promise;
+
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &SerialPort) -> ReadableStream;
+
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &SerialPort) -> WritableStream;
+
+    type Serial;
+
+    #[wasm_bindgen(method, js_name = requestPort)]
+    fn request_port(this: &Serial, options: &Object) -> Promise;
+
+    type ReadableStream;
+
+    #[wasm_bindgen(method, js_name = getReader)]
+    fn get_reader(this: &ReadableStream) -> Reader;
+
+    type Reader;
+
+    #[wasm_bindgen(method)]
+    fn read(this: &Reader) -> Promise;
+
+    #[wasm_bindgen(method)]
+    fn cancel(this: &Reader) -> Promise;
+
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &Reader);
+
+    type WritableStream;
+
+    #[wasm_bindgen(method, js_name = getWriter)]
+    fn get_writer(this: &WritableStream) -> Writer;
+
+    type Writer;
+
+    #[wasm_bindgen(method)]
+    fn write(this: &Writer, chunk: &Uint8Array) -> Promise;
+
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &Writer);
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &Function, millis: f64) -> JsValue;
+}
+
+fn js_error(e: JsValue) -> NsrtError {
+    let message = match e.dyn_ref::<js_sys::Error>() {
+        Some(e) => String::from(e.message()),
+        None => format!("{e:?}"),
+    };
+    NsrtError::IoError(io::Error::other(message))
+}
+
+/// Build a plain object from `fields`
+fn object(fields: &[(&str, JsValue)]) -> Result<Object> {
+    let object = Object::new();
+    for (name, value) in fields {
+        Reflect::set(&object, &(*name).into(), value).map_err(js_error)?;
+    }
+    Ok(object)
+}
+
+/// Promise resolving to `undefined` after `millis`
+fn timer(millis: f64) -> Promise {
+    Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, millis);
+    })
+}
+
+/// A date in seconds since the device epoch, as a JS `Date`
+fn device_date(seconds: u64) -> JsValue {
+    let unix = seconds.saturating_sub(DEVICE_EPOCH_OFFSET);
+    Date::new(&JsValue::from_f64(unix as f64 * 1000.0)).into()
+}
+
+/// Marks a meter busy until dropped
+struct Busy<'a>(&'a Cell<bool>);
+
+impl Drop for Busy<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+/// An `NSRT_mk4` opened through Web Serial
+#[wasm_bindgen]
+pub struct WebMeter {
+    port: SerialPort,
+    reader: Reader,
+    writer: Writer,
+    /// Bytes read but not yet consumed
+    buffer: RefCell<Vec<u8>>,
+    /// A read still outstanding after a timeout, whose chunk is consumed first
+    pending: RefCell<Option<Promise>>,
+    busy: Cell<bool>,
+}
+
+#[wasm_bindgen]
+impl WebMeter {
+    /// Ask the user to pick a meter, then open it
+    ///
+    /// Must be called from a user gesture such as a button click.
+    pub async fn request() -> std::result::Result<WebMeter, JsError> {
+        let serial = Reflect::get(&js_sys::global(), &"navigator".into())
+            .and_then(|navigator| Reflect::get(&navigator, &"serial".into()))
+            .ok()
+            .filter(|serial| !serial.is_undefined())
+            .ok_or_else(|| JsError::new("Web Serial is not supported by this browser"))?;
+
+        let filter = object(&[
+            ("usbVendorId", USB_VID.into()),
+            ("usbProductId", USB_PID.into()),
+        ])?;
+        let options = object(&[("filters", Array::of1(&filter).into())])?;
+        let port = JsFuture::from(serial.unchecked_into::<Serial>().request_port(&options))
+            .await
+            .map_err(js_error)?;
+        Self::open(port.unchecked_into()).await
+    }
+
+    /// Open a port granted earlier, without asking the user again
+    pub async fn open(port: SerialPort) -> std::result::Result<WebMeter, JsError> {
+        // The device ignores line settings, but Web Serial requires a rate
+        let options = object(&[("baudRate", 9600.into())])?;
+        JsFuture::from(port.open(&options))
+            .await
+            .map_err(js_error)?;
+
+        Ok(Self {
+            reader: port.readable().get_reader(),
+            writer: port.writable().get_writer(),
+            port,
+            buffer: RefCell::default(),
+            pending: RefCell::default(),
+            busy: Cell::default(),
+        })
+    }
+
+    /// Release the port
+    pub async fn close(&self) -> std::result::Result<(), JsError> {
+        let _busy = self.lock()?;
+        // Cancelling settles any read left outstanding by a timeout
+        JsFuture::from(self.reader.cancel())
+            .await
+            .map_err(js_error)?;
+        self.reader.release_lock();
+        self.writer.release_lock();
+        JsFuture::from(self.port.close()).await.map_err(js_error)?;
+        Ok(())
+    }
+
+    /// Running sound level in dB
+    #[wasm_bindgen(js_name = readLevel)]
+    pub async fn read_level(&self) -> std::result::Result<f32, JsError> {
+        let _busy = self.lock()?;
+        Ok(self.read_f32(Command::ReadLevel).await?)
+    }
+
+    /// LEQ in dB since the previous LEQ read, restarting integration
+    #[wasm_bindgen(js_name = readLeq)]
+    pub async fn read_leq(&self) -> std::result::Result<f32, JsError> {
+        let _busy = self.lock()?;
+        Ok(self.read_f32(Command::ReadLEQ).await?)
+    }
+
+    /// Temperature in °C
+    #[wasm_bindgen(js_name = readTemperature)]
+    pub async fn read_temperature(&self) -> std::result::Result<f32, JsError> {
+        let _busy = self.lock()?;
+        Ok(self.read_f32(Command::ReadTemperature).await?)
+    }
+
+    /// `{ timestamp, level, leq, temperature }`, with `timestamp` a `Date`
+    ///
+    /// Like `readLeq`, this restarts integration for the next LEQ.
+    #[wasm_bindgen(js_name = readMeasurement)]
+    pub async fn read_measurement(&self) -> std::result::Result<JsValue, JsError> {
+        let _busy = self.lock()?;
+        let timestamp = Date::new_0();
+        let level = self.read_f32(Command::ReadLevel).await?;
+        let leq = self.read_f32(Command::ReadLEQ).await?;
+        let temperature = self.read_f32(Command::ReadTemperature).await?;
+        Ok(object(&[
+            ("timestamp", timestamp.into()),
+            ("level", level.into()),
+            ("leq", leq.into()),
+            ("temperature", temperature.into()),
+        ])?
+        .into())
+    }
+
+    /// `{ weighting, timeConstant, samplingFrequency }`, with the weighting
+    /// as `"A"`, `"C"` or `"Z"` and the sampling frequency in Hz
+    #[wasm_bindgen(js_name = readConfig)]
+    pub async fn read_config(&self) -> std::result::Result<JsValue, JsError> {
+        let _busy = self.lock()?;
+        let weighting = protocol::decode_weighting(&self.read(Command::ReadWeighting).await?)?;
+        let time_constant = self.read_f32(Command::ReadTau).await?;
+        let sampling_frequency =
+            protocol::decode_sampling_frequency(&self.read(Command::ReadFS).await?)?;
+        Ok(object(&[
+            ("weighting", format!("{weighting:?}").into()),
+            ("timeConstant", time_constant.into()),
+            ("samplingFrequency", u32::from(sampling_frequency).into()),
+        ])?
+        .into())
+    }
+
+    /// `{ model, serialNumber, firmwareRevision, userId, calibrationDate,
+    /// birthDate }`, with the dates as `Date`s
+    #[wasm_bindgen(js_name = readInfo)]
+    pub async fn read_info(&self) -> std::result::Result<JsValue, JsError> {
+        let _busy = self.lock()?;
+        let mut fields = Vec::new();
+        for (name, command) in [
+            ("model", Command::ReadModel),
+            ("serialNumber", Command::ReadSN),
+            ("firmwareRevision", Command::ReadFWRev),
+            ("userId", Command::ReadUserID),
+        ] {
+            let data = self.read(command).await?;
+            fields.push((name, protocol::decode_text(&data)?.into()));
+        }
+        for (name, command) in [
+            ("calibrationDate", Command::ReadDOC),
+            ("birthDate", Command::ReadDOB),
+        ] {
+            let date = protocol::decode_date(&self.read(command).await?)?;
+            fields.push((name, device_date(date)));
+        }
+        Ok(object(&fields)?.into())
+    }
+}
+
+impl WebMeter {
+    fn lock(&self) -> Result<Busy<'_>> {
+        if self.busy.replace(true) {
+            return Err(NsrtError::InvalidParameter(
+                "Another request to the meter is in progress".to_string(),
+            ));
+        }
+        Ok(Busy(&self.busy))
+    }
+
+    async fn read_f32(&self, command: Command) -> Result<f32> {
+        Ok(protocol::decode_f32(&self.read(command).await?)?)
+    }
+
+    /// Send the packet of read `command` and return its answer
+    async fn read(&self, command: Command) -> Result<Vec<u8>> {
+        // The device only talks when asked, so anything left over belongs to
+        // an earlier request that timed out
+        self.buffer.borrow_mut().clear();
+
+        let count = command.length();
+        let packet = command.packet(0, count as u32);
+        JsFuture::from(self.writer.write(&Uint8Array::from(&packet[..])))
+            .await
+            .map_err(js_error)?;
+
+        let deadline = Date::now() + TIMEOUT.as_millis() as f64;
+        while self.buffer.borrow().len() < count {
+            let read = self.pending.take().unwrap_or_else(|| self.reader.read());
+            let timeout = timer(deadline - Date::now());
+            let chunk = JsFuture::from(Promise::race(&Array::of2(&read, &timeout)))
+                .await
+                .map_err(js_error)?;
+            if chunk.is_undefined() {
+                self.pending.replace(Some(read));
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+            }
+
+            let done = Reflect::get(&chunk, &"done".into()).map_err(js_error)?;
+            if done.is_truthy() {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let value = Reflect::get(&chunk, &"value".into()).map_err(js_error)?;
+            self.buffer
+                .borrow_mut()
+                .extend(Uint8Array::new(&value).to_vec());
+        }
+        Ok(self.buffer.borrow_mut().drain(..count).collect())
+    }
+}
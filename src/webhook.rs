@@ -0,0 +1,629 @@
+//! Alarm notifications to HTTP webhooks
+//!
+//! [`WebhookSink`] watches measurements for [`Alarm`] state changes and POSTs
+//! a JSON payload to every configured [`Webhook`] when the alarm escalates to
+//! a warning or critical level and when it clears again. A webhook may be
+//! limited to critical alarms, so a site manager hears of warnings while
+//! only critical alarms page the on-call engineer. A plain [`Threshold`]
+//! acts as a warning level. [`WebhookSink::fault`] sends the same kind of
+//! notification for device faults. Deliveries happen on a background thread
+//! and are retried with exponential backoff, so a slow or unreachable
+//! endpoint never holds up sampling; deliveries given up on are counted by
+//! [`WebhookSink::failed`] and their errors kept for
+//! [`WebhookSink::take_errors`].
+//!
+//! Without a template, the payload is:
+//!
+//! ```json
+//! {
+//!   "event": "exceeded",
+//!   "state": "critical",
+//!   "message": "Level 87.3 dB exceeds the 85.0 dB critical threshold",
+//!   "level": 87.3,
+//!   "leq": 84.1,
+//!   "temperature": 21.5,
+//!   "threshold": 85.0,
+//!   "timestamp": "2024-05-01T13:45:00.250000000Z",
+//!   "metadata": { "site": "North boundary" }
+//! }
+//! ```
+//!
+//! `event` is `exceeded` when the alarm escalates, `cleared` when it falls
+//! back to a less severe `state`, or `fault`. `threshold` is the level of the
+//! alarm level entered or left, or for faults the lowest level. Measurement
+//! fields hold the triggering measurement, or for faults the last one taken,
+//! and are `null` when there is none, as is `threshold` when no alarm is set.
+//!
+//! A [`Webhook::template`] replaces the payload with any text, e.g. to match
+//! what Slack, PagerDuty or ntfy expect. `{{name}}` placeholders are replaced
+//! with the fields above and the metadata fields (`site`, `operator`, ...).
+//! Text values are JSON-escaped without quotes, so they can be placed inside
+//! JSON strings; missing values become `null`:
+//!
+//! ```text
+//! {"text": "{{site}}: {{message}}"}
+//! ```
+
+use crate::{
+    Alarm, AlarmMonitor, AlarmState, AlarmTransition, Measurement, Metadata, NsrtError, Result,
+    Sink, Threshold,
+};
+use serde_json::{Value, json};
+use std::{
+    fmt::Display,
+    io,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        mpsc::{self, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use ureq::Agent;
+
+/// Default number of retries after a failed delivery
+const DEFAULT_RETRIES: u32 = 5;
+
+/// Wait before the first retry; doubled for every further one
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Time allowed for one delivery attempt
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors kept until taken, the oldest dropped beyond
+const MAX_ERRORS: usize = 64;
+
+/// An endpoint notified of alarms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    url: String,
+    template: Option<String>,
+    min_state: AlarmState,
+}
+
+impl Webhook {
+    /// POST the default JSON payload to `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            template: None,
+            min_state: AlarmState::Warning,
+        }
+    }
+
+    /// Send `template` with its `{{name}}` placeholders filled in instead
+    #[must_use]
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Only notify of alarms reaching `state`, e.g. critical ones; faults are
+    /// always sent
+    #[must_use]
+    pub fn min_state(mut self, state: AlarmState) -> Self {
+        self.min_state = state;
+        self
+    }
+}
+
+struct Delivery {
+    url: String,
+    body: String,
+    retries: u32,
+    backoff: Duration,
+}
+
+/// Deliveries given up on, recorded by the worker
+#[derive(Default)]
+struct Failures {
+    count: u64,
+    errors: Vec<NsrtError>,
+}
+
+/// Sink notifying webhooks of alarms and device faults
+///
+/// Dropping the sink waits for pending deliveries, including their retries.
+pub struct WebhookSink {
+    webhooks: Vec<Webhook>,
+    monitor: Option<AlarmMonitor>,
+    metadata: Metadata,
+    last: Option<Measurement>,
+    retries: u32,
+    backoff: Duration,
+    deliveries: Option<Sender<Delivery>>,
+    worker: Option<JoinHandle<()>>,
+    failures: Arc<Mutex<Failures>>,
+}
+
+impl WebhookSink {
+    /// Notify `webhooks`; only faults are sent until an alarm is set
+    pub fn new(webhooks: impl IntoIterator<Item = Webhook>) -> Self {
+        let (deliveries, rx) = mpsc::channel::<Delivery>();
+        let failures = Arc::new(Mutex::new(Failures::default()));
+        let worker = thread::spawn({
+            let failures = Arc::clone(&failures);
+            move || {
+                let agent: Agent = Agent::config_builder()
+                    .timeout_global(Some(TIMEOUT))
+                    .build()
+                    .into();
+                for delivery in rx {
+                    if let Err(e) = deliver(&agent, &delivery) {
+                        let mut failures = lock(&failures);
+                        failures.count += 1;
+                        if failures.errors.len() == MAX_ERRORS {
+                            failures.errors.remove(0);
+                        }
+                        failures.errors.push(e);
+                    }
+                }
+            }
+        });
+
+        Self {
+            webhooks: webhooks.into_iter().collect(),
+            monitor: None,
+            metadata: Metadata::default(),
+            last: None,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            deliveries: Some(deliveries),
+            worker: Some(worker),
+            failures,
+        }
+    }
+
+    /// Notify when the level crosses `threshold`, as a warning
+    #[must_use]
+    pub fn threshold(self, threshold: Threshold) -> Self {
+        self.alarm(Alarm::new().warning(threshold.into()))
+    }
+
+    /// Notify when the state of `alarm` changes
+    #[must_use]
+    pub fn alarm(mut self, alarm: Alarm) -> Self {
+        self.monitor = Some(AlarmMonitor::new(alarm));
+        self
+    }
+
+    /// Change the levels of the alarm, keeping its state, see
+    /// [`AlarmMonitor::set_alarm`]
+    pub fn set_alarm(&mut self, alarm: Alarm) {
+        match &mut self.monitor {
+            Some(monitor) => monitor.set_alarm(alarm),
+            None => self.monitor = Some(AlarmMonitor::new(alarm)),
+        }
+    }
+
+    /// Retry failed deliveries `retries` times, waiting `backoff` before the
+    /// first retry and twice as long before each further one
+    #[must_use]
+    pub fn retry(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Number of deliveries given up on so far, after their retries or on a
+    /// client error
+    pub fn failed(&self) -> u64 {
+        lock(&self.failures).count
+    }
+
+    /// Errors of the deliveries given up on since the last call, up to the
+    /// last 64
+    pub fn take_errors(&self) -> Vec<NsrtError> {
+        std::mem::take(&mut lock(&self.failures).errors)
+    }
+
+    /// Notify the webhooks of a device fault
+    pub fn fault(&self, error: &dyn Display) {
+        let (state, threshold) = self.monitor.as_ref().map_or((None, None), |monitor| {
+            let alarm = monitor.alarm();
+            let lowest = alarm.warning.or(alarm.critical);
+            (Some(monitor.state()), lowest.map(|level| level.level))
+        });
+        self.notify(
+            "fault",
+            state,
+            threshold,
+            self.last.as_ref(),
+            format!("Device fault: {error}"),
+            None,
+        );
+    }
+
+    fn notify_transition(&self, transition: &AlarmTransition) {
+        let Some(monitor) = &self.monitor else {
+            return;
+        };
+        let level = transition.level(&monitor.alarm()).map(|level| level.level);
+        let severity = transition.from.max(transition.to);
+        let threshold = level.map_or_else(String::new, |level| format!("{level:.1} dB "));
+        let m = &transition.measurement;
+        let (event, message) = if transition.is_escalation() {
+            (
+                "exceeded",
+                format!(
+                    "Level {:.1} dB exceeds the {threshold}{severity} threshold",
+                    m.level
+                ),
+            )
+        } else {
+            (
+                "cleared",
+                format!(
+                    "Level {:.1} dB is back below the {threshold}{severity} threshold",
+                    m.level
+                ),
+            )
+        };
+        self.notify(
+            event,
+            Some(transition.to),
+            level,
+            Some(m),
+            message,
+            Some(severity),
+        );
+    }
+
+    /// Send a notification to the webhooks taking alarms of `severity`, or
+    /// to all of them without one
+    fn notify(
+        &self,
+        event: &str,
+        state: Option<AlarmState>,
+        threshold: Option<f32>,
+        measurement: Option<&Measurement>,
+        message: String,
+        severity: Option<AlarmState>,
+    ) {
+        let fields = [
+            ("event", Value::from(event)),
+            (
+                "state",
+                state.map_or(Value::Null, |s| Value::from(s.as_str())),
+            ),
+            ("message", Value::from(message)),
+            ("level", number(measurement.map(|m| m.level))),
+            ("leq", number(measurement.map(|m| m.leq))),
+            (
+                "temperature",
+                number(measurement.map(|m| m.temperature.as_celsius())),
+            ),
+            ("threshold", number(threshold)),
+            (
+                "timestamp",
+                measurement.map_or(Value::Null, |m| {
+                    humantime::format_rfc3339_nanos(m.timestamp)
+                        .to_string()
+                        .into()
+                }),
+            ),
+        ];
+
+        for webhook in &self.webhooks {
+            if severity.is_some_and(|severity| severity < webhook.min_state) {
+                continue;
+            }
+            let body = match &webhook.template {
+                Some(template) => {
+                    let metadata = self
+                        .metadata
+                        .fields()
+                        .into_iter()
+                        .map(|(name, value)| (name, Value::from(value)));
+                    render(template, fields.iter().cloned().chain(metadata))
+                }
+                None => {
+                    let mut payload: serde_json::Map<_, _> = fields
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect();
+                    payload.insert("metadata".into(), json!(self.metadata));
+                    Value::Object(payload).to_string()
+                }
+            };
+
+            if let Some(deliveries) = &self.deliveries {
+                let _ = deliveries.send(Delivery {
+                    url: webhook.url.clone(),
+                    body,
+                    retries: self.retries,
+                    backoff: self.backoff,
+                });
+            }
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.metadata = metadata.clone();
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> Result<()> {
+        self.last = Some(*measurement);
+        if let Some(transition) = self
+            .monitor
+            .as_mut()
+            .and_then(|monitor| monitor.update(measurement))
+        {
+            self.notify_transition(&transition);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WebhookSink {
+    fn drop(&mut self) {
+        self.deliveries.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// POST `delivery`, retrying with exponential backoff
+fn deliver(agent: &Agent, delivery: &Delivery) -> Result<()> {
+    let mut wait = delivery.backoff;
+    let mut attempt = 0;
+    loop {
+        let sent = agent
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .send(&delivery.body);
+        match sent {
+            Ok(_) => return Ok(()),
+            // Client errors other than rate limiting won't go away by retrying
+            Err(ureq::Error::StatusCode(status))
+                if (400..500).contains(&status) && status != 429 =>
+            {
+                return Err(delivery_error(format!(
+                    "Webhook {} rejected the alarm: HTTP {status}",
+                    delivery.url
+                )));
+            }
+            Err(e) if attempt == delivery.retries => {
+                return Err(delivery_error(format!(
+                    "Cannot deliver alarm to webhook {}: {e}",
+                    delivery.url
+                )));
+            }
+            Err(_) => {
+                thread::sleep(wait);
+                wait = wait.saturating_mul(2);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+fn delivery_error(message: String) -> NsrtError {
+    NsrtError::IoError(io::Error::other(message))
+}
+
+fn number(value: Option<f32>) -> Value {
+    // Round to the device's resolution instead of printing f32 noise
+    value.map_or(Value::Null, |v| {
+        json!((f64::from(v) * 100.0).round() / 100.0)
+    })
+}
+
+/// Replace `{{name}}` placeholders in `template` with `fields`
+fn render<'a>(template: &str, fields: impl Iterator<Item = (&'a str, Value)>) -> String {
+    let fields: Vec<_> = fields.collect();
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let name = after[..end].trim();
+        match fields.iter().find(|(field, _)| *field == name) {
+            Some((_, Value::String(text))) => {
+                // Escape as a JSON string, without the surrounding quotes
+                let quoted = Value::from(text.as_str()).to_string();
+                rendered.push_str(&quoted[1..quoted.len() - 1]);
+            }
+            Some((_, value)) => rendered.push_str(&value.to_string()),
+            None if is_known(name) => rendered.push_str("null"),
+            None => rendered.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn is_known(name: &str) -> bool {
+    matches!(
+        name,
+        "device" | "site" | "latitude" | "longitude" | "operator" | "microphone_height" | "notes"
+    )
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AlarmLevel, Quality, Temperature};
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc::Receiver,
+        time::{Instant, UNIX_EPOCH},
+    };
+
+    fn at(secs: u64, level: f32) -> Measurement {
+        Measurement {
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+            level,
+            leq: level,
+            temperature: Temperature::from_celsius(20.0),
+            clock: None,
+            compensated: None,
+            quality: Quality::GOOD,
+        }
+    }
+
+    /// Local endpoint answering every POST with `status`, passing on the
+    /// bodies
+    fn endpoint(status: u16) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!(
+                    "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                if tx.send(String::from_utf8(body).unwrap()).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    fn alarm() -> Alarm {
+        Alarm::new()
+            .warning(AlarmLevel::new(70.0))
+            .critical(AlarmLevel::new(80.0))
+    }
+
+    #[test]
+    fn render_escapes_text() {
+        let fields = [
+            ("site", Value::from("North \"gate\"\n")),
+            ("level", Value::from(75.5)),
+        ];
+        assert_eq!(
+            render(
+                r#"{"text": "{{site}}: {{ level }} dB"}"#,
+                fields.into_iter()
+            ),
+            r#"{"text": "North \"gate\"\n: 75.5 dB"}"#
+        );
+    }
+
+    #[test]
+    fn render_missing_and_unknown_fields() {
+        let fields = [("level", Value::Null)];
+        assert_eq!(
+            render("{{level}} {{operator}} {{x}}", fields.into_iter()),
+            "null null {{x}}"
+        );
+    }
+
+    #[test]
+    fn render_unterminated_placeholder() {
+        let fields = [("site", Value::from("North"))];
+        assert_eq!(
+            render("{{site}} {{site", fields.into_iter()),
+            "North {{site"
+        );
+        assert_eq!(render("{{", std::iter::empty()), "{{");
+    }
+
+    #[test]
+    fn default_payload() {
+        let (url, bodies) = endpoint(200);
+        let mut sink = WebhookSink::new([Webhook::new(url)]).alarm(alarm());
+        let metadata = Metadata {
+            site: Some("North".into()),
+            ..Metadata::default()
+        };
+        sink.set_metadata(&metadata).unwrap();
+        sink.write(&at(100, 75.0)).unwrap();
+        drop(sink);
+
+        let payload: Value = serde_json::from_str(&bodies.recv().unwrap()).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "event": "exceeded",
+                "state": "warning",
+                "message": "Level 75.0 dB exceeds the 70.0 dB warning threshold",
+                "level": 75.0,
+                "leq": 75.0,
+                "temperature": 20.0,
+                "threshold": 70.0,
+                "timestamp": "1970-01-01T00:01:40.000000000Z",
+                "metadata": { "site": "North" }
+            })
+        );
+    }
+
+    #[test]
+    fn min_state_filters_alarms() {
+        let (all, every) = endpoint(200);
+        let (critical, paged) = endpoint(200);
+        let webhooks = [
+            Webhook::new(all).template("{{state}}"),
+            Webhook::new(critical)
+                .template("{{state}}")
+                .min_state(AlarmState::Critical),
+        ];
+        let mut sink = WebhookSink::new(webhooks).alarm(alarm());
+        for (secs, level) in [(0, 75.0), (1, 85.0), (2, 75.0), (3, 60.0)] {
+            sink.write(&at(secs, level)).unwrap();
+        }
+        sink.fault(&"unplugged");
+        drop(sink);
+
+        let every: Vec<_> = every.try_iter().collect();
+        assert_eq!(
+            every,
+            ["warning", "critical", "warning", "normal", "normal"]
+        );
+        // Leaving the critical level still concerns critical webhooks
+        let paged: Vec<_> = paged.try_iter().collect();
+        assert_eq!(paged, ["critical", "warning", "normal"]);
+    }
+
+    #[test]
+    fn failed_deliveries() {
+        let (url, _bodies) = endpoint(400);
+        let sink = WebhookSink::new([Webhook::new(url)]);
+        sink.fault(&"unplugged");
+
+        let start = Instant::now();
+        while sink.failed() == 0 && start.elapsed() < TIMEOUT {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(sink.failed(), 1);
+        let errors = sink.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("HTTP 400"));
+        assert!(sink.take_errors().is_empty());
+    }
+}
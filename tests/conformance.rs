@@ -0,0 +1,235 @@
+//! Conformance of the driver and simulator to the vendor protocol
+//!
+//! `SPEC` transcribes the command table of the `NSRT_mk4` protocol
+//! description. The driver is checked to send exactly these packets and
+//! accept these payloads, and the simulator to answer them with exactly these
+//! lengths, so a change to either side that departs from the device fails
+//! here rather than in the field.
+
+use nsrt::{
+    MockTransport, NSRT, SamplingFrequency, Weighting,
+    protocol::{ACK, Command, Encoding, PACKET_LEN},
+};
+
+/// One row of the vendor command table
+struct Spec {
+    command: Command,
+    code: u32,
+    write: bool,
+    /// Payload bytes; the maximum for text
+    length: usize,
+    encoding: Encoding,
+}
+
+const fn spec(command: Command, code: u32, length: usize, encoding: Encoding) -> Spec {
+    Spec {
+        command,
+        code,
+        write: code & 0x8000_0000 == 0,
+        length,
+        encoding,
+    }
+}
+
+const SPEC: [Spec; 17] = [
+    spec(Command::ReadLevel, 0x8000_0010, 4, Encoding::F32),
+    spec(Command::ReadLEQ, 0x8000_0011, 4, Encoding::F32),
+    spec(Command::ReadTemperature, 0x8000_0012, 4, Encoding::F32),
+    spec(Command::ReadWeighting, 0x8000_0020, 1, Encoding::Weighting),
+    spec(Command::ReadFS, 0x8000_0021, 2, Encoding::SamplingFrequency),
+    spec(Command::ReadTau, 0x8000_0022, 4, Encoding::F32),
+    spec(Command::ReadModel, 0x8000_0031, 32, Encoding::Text),
+    spec(Command::ReadSN, 0x8000_0032, 32, Encoding::Text),
+    spec(Command::ReadFWRev, 0x8000_0033, 32, Encoding::Text),
+    spec(Command::ReadDOC, 0x8000_0034, 8, Encoding::Date),
+    spec(Command::ReadDOB, 0x8000_0035, 8, Encoding::Date),
+    spec(Command::ReadUserID, 0x8000_0036, 32, Encoding::Text),
+    spec(Command::WriteWeighting, 0x20, 1, Encoding::Weighting),
+    spec(Command::WriteFS, 0x21, 2, Encoding::SamplingFrequency),
+    spec(Command::WriteTau, 0x22, 4, Encoding::F32),
+    spec(Command::WriteUserID, 0x36, 32, Encoding::Text),
+    spec(Command::WriteAudioDebug, 0x37, 1, Encoding::Flag),
+];
+
+/// A valid payload of `spec`, as the driver under test writes or reads it
+fn sample(spec: &Spec) -> Vec<u8> {
+    match spec.encoding {
+        Encoding::F32 => 0.125f32.to_le_bytes().to_vec(),
+        Encoding::Weighting => vec![u8::from(Weighting::Z)],
+        Encoding::SamplingFrequency => 32000u16.to_le_bytes().to_vec(),
+        Encoding::Date => 3_800_000_000u64.to_le_bytes().to_vec(),
+        Encoding::Flag => vec![0],
+        Encoding::Text if spec.write => b"conformance\0".to_vec(),
+        Encoding::Text => {
+            let mut text = b"conformance".to_vec();
+            text.resize(spec.length, 0);
+            text
+        }
+    }
+}
+
+fn packet(spec: &Spec, count: usize) -> Vec<u8> {
+    [spec.code, 0, count as u32]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect()
+}
+
+/// Issue `command` through the driver with the sample payload
+fn call(nsrt: NSRT, command: Command) -> nsrt::Result<NSRT> {
+    let mut nsrt = nsrt;
+    match command {
+        Command::ReadLevel => nsrt.read_level().map(drop),
+        Command::ReadLEQ => nsrt.read_leq().map(drop),
+        Command::ReadTemperature => nsrt.read_temperature().map(drop),
+        Command::ReadWeighting => nsrt.read_weighting().map(drop),
+        Command::ReadFS => nsrt.read_sampling_frequency().map(drop),
+        Command::ReadTau => nsrt.read_time_constant().map(drop),
+        Command::ReadModel => nsrt.read_model().map(drop),
+        Command::ReadSN => nsrt.read_serial_number().map(drop),
+        Command::ReadFWRev => nsrt.read_firmware_revision().map(drop),
+        Command::ReadDOC => nsrt.read_calibration_date().map(drop),
+        Command::ReadDOB => nsrt.read_birth_date().map(drop),
+        Command::ReadUserID => nsrt.read_user_id().map(drop),
+        Command::WriteWeighting => return nsrt.weighting(Weighting::Z),
+        // The only setter without a fluent variant skipping the wait
+        Command::WriteFS => nsrt.set_sampling_frequency(SamplingFrequency::Freq32kHz),
+        Command::WriteTau => return nsrt.time_constant(0.125),
+        Command::WriteUserID => nsrt.set_user_id("conformance"),
+        Command::WriteAudioDebug => nsrt.set_audio_debug(false),
+    }?;
+    Ok(nsrt)
+}
+
+#[test]
+fn command_table_matches_spec() {
+    assert_eq!(Command::ALL.len(), SPEC.len());
+    for spec in &SPEC {
+        let command = spec.command;
+        assert_eq!(command.code(), spec.code, "{command:?} code");
+        assert_eq!(Command::from_code(spec.code), Some(command));
+        assert_eq!(command.is_write(), spec.write, "{command:?} direction");
+        assert_eq!(command.length(), spec.length, "{command:?} length");
+        assert_eq!(command.encoding(), spec.encoding, "{command:?} encoding");
+        assert!(Command::ALL.contains(&command));
+
+        let packet = command.packet(0, spec.length as u32);
+        assert_eq!(packet.len(), PACKET_LEN);
+        assert_eq!(packet.to_vec(), self::packet(spec, spec.length));
+    }
+}
+
+#[test]
+fn driver_sends_spec_packets() {
+    for spec in &SPEC {
+        let mock = MockTransport::new();
+        let payload = sample(spec);
+        if spec.command == Command::WriteAudioDebug {
+            // Preceded by the firmware revision read for the capability check
+            let revision = SPEC
+                .iter()
+                .find(|s| s.command == Command::ReadFWRev)
+                .unwrap();
+            let mut version = b"1.4".to_vec();
+            version.resize(revision.length, 0);
+            mock.expect(packet(revision, revision.length), version);
+        }
+        if spec.write {
+            let mut request = packet(spec, payload.len());
+            request.extend(&payload);
+            mock.expect(request, [ACK]);
+            if spec.command == Command::WriteFS {
+                // Followed by the time constant read for the stabilization wait
+                let tau = SPEC.iter().find(|s| s.command == Command::ReadTau).unwrap();
+                mock.expect(packet(tau, tau.length), sample(tau));
+            }
+        } else {
+            mock.expect(packet(spec, spec.length), payload);
+        }
+
+        let result = call(NSRT::with_transport(mock.clone()), spec.command);
+        assert!(result.is_ok(), "{:?}: {:?}", spec.command, result.err());
+        mock.assert_done();
+    }
+}
+
+#[cfg(feature = "sim")]
+mod simulator {
+    use super::*;
+    use nsrt::sim::{Profile, Simulator};
+    use std::io::{ErrorKind, Read, Write};
+
+    /// Check that `payload` is a valid value of `encoding`
+    fn assert_decodes(command: Command, encoding: Encoding, payload: &[u8]) {
+        let valid = match encoding {
+            Encoding::F32 => f32::from_le_bytes(payload.try_into().unwrap()).is_finite(),
+            Encoding::Weighting => payload[0] <= 2,
+            Encoding::SamplingFrequency => {
+                matches!(u16::from_le_bytes([payload[0], payload[1]]), 32000 | 48000)
+            }
+            Encoding::Date => true,
+            Encoding::Flag => payload[0] <= 1,
+            Encoding::Text => std::ffi::CStr::from_bytes_until_nul(payload)
+                .is_ok_and(|text| text.to_str().is_ok()),
+        };
+        assert!(valid, "{command:?} answered {payload:02x?}");
+    }
+
+    #[test]
+    fn simulator_answers_with_spec_lengths() {
+        let mut sim = Simulator::new(Profile::Constant(60.0));
+        for spec in SPEC.iter().filter(|spec| !spec.write) {
+            sim.write_all(&packet(spec, spec.length)).unwrap();
+            let mut answer = vec![0; spec.length];
+            sim.read_exact(&mut answer).unwrap();
+            assert_decodes(spec.command, spec.encoding, &answer);
+
+            let extra = sim.read(&mut [0]).unwrap_err();
+            assert_eq!(extra.kind(), ErrorKind::TimedOut, "{:?}", spec.command);
+        }
+    }
+
+    #[test]
+    fn simulator_acknowledges_spec_writes() {
+        let mut sim = Simulator::new(Profile::Constant(60.0));
+        for spec in SPEC.iter().filter(|spec| spec.write) {
+            let payload = sample(spec);
+            let mut request = packet(spec, payload.len());
+            request.extend(&payload);
+            sim.write_all(&request).unwrap();
+
+            let mut ack = [0];
+            sim.read_exact(&mut ack).unwrap();
+            assert_eq!(ack, [ACK], "{:?}", spec.command);
+        }
+    }
+
+    #[test]
+    fn simulator_rejects_oversized_writes() {
+        let mut sim = Simulator::new(Profile::Constant(60.0));
+        for spec in SPEC.iter().filter(|spec| spec.write) {
+            let mut request = packet(spec, spec.length + 1);
+            request.extend(vec![0; spec.length + 1]);
+            sim.write_all(&request).unwrap();
+
+            let mut ack = [0];
+            sim.read_exact(&mut ack).unwrap();
+            assert_ne!(ack, [ACK], "{:?}", spec.command);
+        }
+    }
+
+    #[test]
+    fn driver_round_trips_through_simulator() {
+        let mut nsrt = NSRT::with_transport(Simulator::new(Profile::Constant(60.0)));
+        for command in Command::ALL {
+            nsrt = call(nsrt, command).unwrap_or_else(|e| panic!("{command:?}: {e}"));
+        }
+        assert_eq!(nsrt.read_weighting().unwrap(), Weighting::Z);
+        assert_eq!(
+            nsrt.read_sampling_frequency().unwrap(),
+            SamplingFrequency::Freq32kHz
+        );
+        assert_eq!(nsrt.read_time_constant().unwrap(), 0.125);
+        assert_eq!(nsrt.read_user_id().unwrap(), "conformance");
+    }
+}
@@ -0,0 +1,2943 @@
+//! Driver protocol tests against a scripted device
+
+use nsrt::{
+    BackgroundCorrection, Comparison, DeviceConfig, DeviceHandle, Divergence, ErrorKind,
+    FirmwareVersion, Interpolation, Measurement, MeasurementSurface, Metadata, MockTransport, NSRT,
+    NsrtError, OCTAVE_BANDS, OctaveBands, Priority, Quality, RcQuality, RecordingTransport,
+    Resampler, SamplingFrequency, Session, Sink, SoundPowerSurvey, Temperature, Weighting,
+    protocol::Encoding,
+};
+use std::{
+    io,
+    ops::ControlFlow,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+const ACK: u8 = 0x06;
+
+const READ_LEVEL: u32 = 0x8000_0010;
+const READ_LEQ: u32 = 0x8000_0011;
+const READ_TEMPERATURE: u32 = 0x8000_0012;
+const READ_WEIGHTING: u32 = 0x8000_0020;
+const READ_FS: u32 = 0x8000_0021;
+const READ_TAU: u32 = 0x8000_0022;
+const READ_MODEL: u32 = 0x8000_0031;
+const READ_SN: u32 = 0x8000_0032;
+const READ_FW_REV: u32 = 0x8000_0033;
+const READ_DOC: u32 = 0x8000_0034;
+const READ_DOB: u32 = 0x8000_0035;
+const READ_USER_ID: u32 = 0x8000_0036;
+const WRITE_WEIGHTING: u32 = 0x20;
+const WRITE_FS: u32 = 0x21;
+const WRITE_TAU: u32 = 0x22;
+const WRITE_USER_ID: u32 = 0x36;
+const WRITE_AUDIO_DEBUG: u32 = 0x37;
+
+/// Command packet for `command` transferring `count` bytes
+fn packet(command: u32, count: u32) -> Vec<u8> {
+    [command, 0, count]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect()
+}
+
+/// Command packet followed by `data`
+fn write_packet(command: u32, data: &[u8]) -> Vec<u8> {
+    let mut bytes = packet(command, data.len() as u32);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// 32-byte NUL-padded string field
+fn text(value: &str) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.resize(32, 0);
+    bytes
+}
+
+fn device() -> (NSRT, MockTransport) {
+    let mock = MockTransport::new();
+    (NSRT::with_transport(mock.clone()), mock)
+}
+
+fn expect_float(mock: &MockTransport, command: u32, value: f32) {
+    mock.expect(packet(command, 4), value.to_le_bytes());
+}
+
+fn assert_timed_out<T>(result: nsrt::Result<T>) {
+    match result {
+        Err(NsrtError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+        Err(e) => panic!("expected a timeout, got {e}"),
+        Ok(_) => panic!("expected a timeout"),
+    }
+}
+
+#[test]
+fn read_level() {
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEVEL, 63.25);
+    assert_eq!(nsrt.read_level().unwrap(), 63.25);
+    mock.assert_done();
+}
+
+#[test]
+fn read_leq() {
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEQ, 58.5);
+    assert_eq!(nsrt.read_leq().unwrap(), 58.5);
+    mock.assert_done();
+}
+
+#[test]
+fn read_temperature() {
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_TEMPERATURE, 21.75);
+    assert_eq!(nsrt.read_temperature().unwrap().as_celsius(), 21.75);
+    mock.assert_done();
+}
+
+#[test]
+fn read_weighting() {
+    let (mut nsrt, mock) = device();
+    for (byte, weighting) in [(0, Weighting::C), (1, Weighting::A), (2, Weighting::Z)] {
+        mock.expect(packet(READ_WEIGHTING, 1), [byte]);
+        assert_eq!(nsrt.read_weighting().unwrap(), weighting);
+    }
+    mock.assert_done();
+}
+
+#[test]
+fn read_weighting_keeps_unknown_curve() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_WEIGHTING, 1), [3]);
+    assert_eq!(nsrt.read_weighting().unwrap(), Weighting::Unknown(3));
+    mock.assert_done();
+}
+
+#[test]
+fn read_sampling_frequency() {
+    let (mut nsrt, mock) = device();
+    for (hz, freq) in [
+        (32000u16, SamplingFrequency::Freq32kHz),
+        (48000, SamplingFrequency::Freq48kHz),
+    ] {
+        mock.expect(packet(READ_FS, 2), hz.to_le_bytes());
+        assert_eq!(nsrt.read_sampling_frequency().unwrap(), freq);
+    }
+    mock.assert_done();
+}
+
+#[test]
+fn read_sampling_frequency_keeps_unknown_rate() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_FS, 2), 44100u16.to_le_bytes());
+    assert_eq!(
+        nsrt.read_sampling_frequency().unwrap(),
+        SamplingFrequency::Unknown(44100)
+    );
+    assert!(SamplingFrequency::try_from(44100u32).is_err());
+    mock.assert_done();
+}
+
+#[test]
+fn read_time_constant() {
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_TAU, 0.125);
+    assert_eq!(nsrt.read_time_constant().unwrap(), 0.125);
+    mock.assert_done();
+}
+
+#[test]
+fn read_strings() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+    mock.expect(packet(READ_SN, 32), text("A1B2C3"));
+    mock.expect(packet(READ_FW_REV, 32), text("1.4"));
+    mock.expect(packet(READ_USER_ID, 32), text("North boundary"));
+
+    assert_eq!(nsrt.read_model().unwrap(), "NSRT_mk4");
+    assert_eq!(nsrt.read_serial_number().unwrap(), "A1B2C3");
+    assert_eq!(nsrt.read_firmware_revision().unwrap(), "1.4");
+    assert_eq!(nsrt.read_user_id().unwrap(), "North boundary");
+    mock.assert_done();
+}
+
+#[test]
+fn read_string_without_nul() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_MODEL, 32), [b'x'; 32]);
+    match nsrt.read_model() {
+        Err(NsrtError::Malformed(malformed)) => {
+            assert_eq!(malformed.expected(), Encoding::Text);
+            assert_eq!(malformed.bytes(), [b'x'; 32]);
+        }
+        other => panic!("expected a malformed response, got {other:?}"),
+    }
+    mock.assert_done();
+}
+
+#[test]
+fn read_string_with_invalid_utf8() {
+    let (mut nsrt, mock) = device();
+    let mut field = text("");
+    field[..2].copy_from_slice(&[0xc3, 0x28]);
+    mock.expect(packet(READ_SN, 32), field);
+    let error = nsrt.read_serial_number().unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .ends_with(&format!("got 32 bytes [c3 28{}]", " 00".repeat(30))),
+        "{error}"
+    );
+    mock.assert_done();
+}
+
+#[test]
+fn read_dates() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_DOC, 8), 1_700_000_000u64.to_le_bytes());
+    mock.expect(packet(READ_DOB, 8), 1_600_000_000u64.to_le_bytes());
+    assert_eq!(nsrt.read_calibration_date().unwrap(), 1_700_000_000);
+    assert_eq!(nsrt.read_birth_date().unwrap(), 1_600_000_000);
+    mock.assert_done();
+}
+
+#[test]
+fn read_info() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+    mock.expect(packet(READ_SN, 32), text("A1B2C3"));
+    mock.expect(packet(READ_FW_REV, 32), text("1.4"));
+    mock.expect(packet(READ_USER_ID, 32), text("site 7"));
+    mock.expect(packet(READ_DOC, 8), 2u64.to_le_bytes());
+    mock.expect(packet(READ_DOB, 8), 1u64.to_le_bytes());
+
+    let info = nsrt.read_info().unwrap();
+    assert_eq!(info.model, "NSRT_mk4");
+    assert_eq!(info.serial_number, "A1B2C3");
+    assert_eq!(info.firmware_revision, "1.4");
+    assert_eq!(info.user_id, "site 7");
+    assert_eq!(info.calibration_date, 2);
+    assert_eq!(info.birth_date, 1);
+    mock.assert_done();
+}
+
+#[test]
+fn info_is_cached() {
+    let (mut nsrt, mock) = device();
+    let expect_info = |user_id: &str| {
+        mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+        mock.expect(packet(READ_SN, 32), text("A1B2C3"));
+        mock.expect(packet(READ_FW_REV, 32), text("1.4"));
+        mock.expect(packet(READ_USER_ID, 32), text(user_id));
+        mock.expect(packet(READ_DOC, 8), 2u64.to_le_bytes());
+        mock.expect(packet(READ_DOB, 8), 1u64.to_le_bytes());
+    };
+    expect_info("site 7");
+    assert_eq!(nsrt.info_cached().unwrap().serial_number, "A1B2C3");
+    assert_eq!(nsrt.info_cached().unwrap().user_id, "site 7");
+    mock.assert_done();
+
+    mock.expect(write_packet(WRITE_USER_ID, b"site 8\0"), [ACK]);
+    nsrt.set_user_id("site 8").unwrap();
+    assert_eq!(nsrt.info_cached().unwrap().user_id, "site 8");
+    mock.assert_done();
+
+    nsrt.invalidate_info();
+    expect_info("site 9");
+    assert_eq!(nsrt.info_cached().unwrap().user_id, "site 9");
+    mock.assert_done();
+}
+
+#[test]
+fn read_measurement() {
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEVEL, 70.0);
+    expect_float(&mock, READ_LEQ, 65.5);
+    expect_float(&mock, READ_TEMPERATURE, 19.0);
+
+    let measurement = nsrt.read_measurement().unwrap();
+    assert_eq!(measurement.level, 70.0);
+    assert_eq!(measurement.leq, 65.5);
+    assert_eq!(measurement.temperature, Temperature::from_celsius(19.0));
+    mock.assert_done();
+}
+
+#[test]
+fn read_many() {
+    use nsrt::protocol::{self, Command, Value, Values};
+
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEVEL, 63.0);
+    mock.expect(packet(READ_WEIGHTING, 1), [1]);
+    mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+    mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+
+    let commands = [
+        Command::ReadLevel,
+        Command::ReadWeighting,
+        Command::ReadFS,
+        Command::ReadModel,
+    ];
+    let mut buf = [0; 64];
+    let values: Vec<Value> = nsrt
+        .read_many(&commands, &mut buf)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        values,
+        [
+            Value::F32(63.0),
+            Value::Weighting(Weighting::A),
+            Value::SamplingFrequency(SamplingFrequency::Freq48kHz),
+            Value::Text("NSRT_mk4"),
+        ]
+    );
+    mock.assert_done();
+
+    // Refused before anything is sent
+    let mut small = [0; 8];
+    assert!(matches!(
+        nsrt.read_many(&commands, &mut small),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+    assert!(matches!(
+        nsrt.read_many(&[Command::ReadLevel, Command::WriteUserID], &mut buf),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+    assert!(matches!(
+        nsrt.read_many(&[Command::ReadLevel; 32], &mut buf),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+    mock.assert_done();
+
+    // A block cut short ends in an error for the missing value
+    let block = [0, 0, 0x7c, 0x42, 1];
+    let mut values = Values::new(&commands, &block);
+    assert_eq!(values.len(), 4);
+    assert_eq!(values.next(), Some(Ok(Value::F32(63.0))));
+    assert_eq!(values.next(), Some(Ok(Value::Weighting(Weighting::A))));
+    assert!(matches!(
+        values.next(),
+        Some(Err(protocol::Error::Malformed(_)))
+    ));
+    assert!(values.next().is_some_and(|value| value.is_err()));
+    assert_eq!(protocol::answers_len(&commands), 39);
+}
+
+#[test]
+fn read_measurement_with_calibration_offset() {
+    let (mut nsrt, mock) = device();
+    nsrt.set_calibration_offset(-0.5);
+    expect_float(&mock, READ_LEVEL, 70.0);
+    expect_float(&mock, READ_LEQ, 65.5);
+    expect_float(&mock, READ_TEMPERATURE, 19.0);
+
+    let measurement = nsrt.read_measurement().unwrap();
+    assert_eq!(measurement.level, 69.5);
+    assert_eq!(measurement.leq, 65.0);
+    mock.assert_done();
+}
+
+#[test]
+fn read_measurement_at_range_limits() {
+    let (mut nsrt, mock) = device();
+    // Judged before the offset, so 129.5 dB is an overload even though it
+    // reads 127.5 dB
+    nsrt.set_calibration_offset(-2.0);
+    expect_float(&mock, READ_LEVEL, 129.5);
+    expect_float(&mock, READ_LEQ, 90.0);
+    expect_float(&mock, READ_TEMPERATURE, 19.0);
+    assert_eq!(nsrt.read_measurement().unwrap().quality, Quality::OVERLOAD);
+
+    nsrt.set_calibration_offset(0.0);
+    expect_float(&mock, READ_LEVEL, 45.0);
+    expect_float(&mock, READ_LEQ, 30.5);
+    expect_float(&mock, READ_TEMPERATURE, 19.0);
+    assert_eq!(
+        nsrt.read_measurement().unwrap().quality,
+        Quality::UNDER_RANGE
+    );
+
+    nsrt.set_measurement_range(None);
+    expect_float(&mock, READ_LEVEL, 135.0);
+    expect_float(&mock, READ_LEQ, 20.0);
+    expect_float(&mock, READ_TEMPERATURE, 19.0);
+    assert!(nsrt.read_measurement().unwrap().quality.is_good());
+    mock.assert_done();
+}
+
+#[test]
+fn read_measurement_on_unsynchronized_clock() {
+    struct Clock(bool);
+    impl nsrt::TimeSource for Clock {
+        fn now(&self) -> (std::time::SystemTime, Option<nsrt::ClockStatus>) {
+            let status = nsrt::ClockStatus {
+                synchronized: self.0,
+                offset: 0.0,
+                max_error: 16.0,
+            };
+            (UNIX_EPOCH, Some(status))
+        }
+    }
+
+    let (mut nsrt, mock) = device();
+    for synchronized in [false, true] {
+        expect_float(&mock, READ_LEVEL, 70.0);
+        expect_float(&mock, READ_LEQ, 65.5);
+        expect_float(&mock, READ_TEMPERATURE, 19.0);
+        let measurement = nsrt.read_measurement_with(&Clock(synchronized)).unwrap();
+        assert_eq!(measurement.is_unsynchronized(), !synchronized);
+        // Kept as a quality flag, which every log format stores
+        let expected = if synchronized {
+            Quality::GOOD
+        } else {
+            Quality::UNSYNCHRONIZED
+        };
+        assert_eq!(measurement.quality, expected);
+    }
+    assert_eq!(Quality::UNSYNCHRONIZED.to_string(), "unsynchronized");
+    assert_eq!(
+        "unsynchronized".parse::<Quality>().unwrap(),
+        Quality::UNSYNCHRONIZED
+    );
+    mock.assert_done();
+}
+
+#[test]
+fn iterate_measurements() {
+    let (mut nsrt, mock) = device();
+    for level in [60.0, 61.0, 62.0] {
+        expect_float(&mock, READ_LEVEL, level);
+        expect_float(&mock, READ_LEQ, level);
+        expect_float(&mock, READ_TEMPERATURE, 20.0);
+    }
+
+    let interval = Duration::from_millis(20);
+    let start = std::time::Instant::now();
+    let measurements: Vec<Measurement> = nsrt
+        .iter_measurements(interval)
+        .take(3)
+        .collect::<nsrt::Result<_>>()
+        .unwrap();
+    assert!(start.elapsed() >= interval * 2);
+    let levels: Vec<f32> = measurements.iter().map(|m| m.level).collect();
+    assert_eq!(levels, [60.0, 61.0, 62.0]);
+    mock.assert_done();
+}
+
+#[test]
+fn monitor_until_break() {
+    let (mut nsrt, mock) = device();
+    for level in [60.0, 75.0] {
+        expect_float(&mock, READ_LEVEL, level);
+        expect_float(&mock, READ_LEQ, level);
+        expect_float(&mock, READ_TEMPERATURE, 20.0);
+    }
+
+    let mut seen = 0;
+    let loud = nsrt
+        .monitor(Duration::from_millis(1), |measurement| {
+            seen += 1;
+            if measurement.level > 70.0 {
+                ControlFlow::Break(measurement.level)
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+    assert_eq!((seen, loud), (2, 75.0));
+    mock.assert_done();
+}
+
+#[test]
+fn duty_cycled_bursts() {
+    use nsrt::{DutyCycle, Sampler};
+
+    let (nsrt, mock) = device();
+    // Integration restarts at the start of the burst
+    expect_float(&mock, READ_LEQ, 90.0);
+    for (level, temperature) in [(60.0, 20.0), (70.0, 21.0)] {
+        expect_float(&mock, READ_LEVEL, level);
+        expect_float(&mock, READ_LEQ, level);
+        expect_float(&mock, READ_TEMPERATURE, temperature);
+    }
+
+    let burst = Duration::from_millis(100);
+    let invalid = DutyCycle::new(Duration::from_secs(2), Duration::from_secs(1));
+    assert!(invalid.validate().is_err());
+    assert!(invalid.samples(0).validate().is_err());
+    let duty_cycle = DutyCycle::new(burst, Duration::from_secs(60)).samples(2);
+    assert!((duty_cycle.fraction() - 1.0 / 600.0).abs() < 1e-9);
+
+    let start = std::time::Instant::now();
+    let sampler = Sampler::start_duty_cycled(nsrt, duty_cycle).unwrap();
+    let subscription = sampler.subscribe();
+    let measurement = subscription.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(start.elapsed() >= burst);
+    assert_eq!(sampler.duty_cycle(), Some(duty_cycle));
+    sampler.stop().unwrap();
+
+    // Highest level, energy average LEQ and the last temperature
+    assert_eq!(measurement.level, 70.0);
+    assert!(
+        measurement.leq > 63.0 && measurement.leq < 70.0,
+        "{}",
+        measurement.leq
+    );
+    assert_eq!(measurement.temperature.as_celsius(), 21.0);
+    mock.assert_done();
+}
+
+#[test]
+fn commands_keep_their_gap() {
+    let (mut nsrt, mock) = device();
+    assert_eq!(nsrt.command_gap(), Duration::ZERO);
+    let gap = Duration::from_millis(15);
+    nsrt.set_command_gap(gap);
+    for level in [60.0, 61.0, 62.0] {
+        expect_float(&mock, READ_LEVEL, level);
+    }
+
+    let start = std::time::Instant::now();
+    for _ in 0..3 {
+        nsrt.read_level().unwrap();
+    }
+    assert!(start.elapsed() >= gap * 2);
+    mock.assert_done();
+}
+
+#[test]
+fn commands_settle() {
+    let (mut nsrt, mock) = device();
+    assert_eq!(nsrt.settle_delay(), Duration::ZERO);
+    let delay = Duration::from_millis(15);
+    nsrt.set_settle_delay(delay);
+    expect_float(&mock, READ_LEVEL, 60.0);
+    mock.expect(write_packet(WRITE_USER_ID, b"site 7\0"), [ACK]);
+
+    let start = std::time::Instant::now();
+    nsrt.read_level().unwrap();
+    assert!(start.elapsed() >= delay);
+    nsrt.set_user_id("site 7").unwrap();
+    assert!(start.elapsed() >= delay * 2);
+    mock.assert_done();
+}
+
+#[test]
+fn handle_runs_higher_priority_first() {
+    let (nsrt, _mock) = device();
+    let handle = DeviceHandle::spawn(nsrt);
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    // Hold the device until both calls are queued
+    let (release, hold) = mpsc::channel::<()>();
+    let busy = {
+        let handle = handle.clone();
+        thread::spawn(move || handle.call(move |_| Ok(hold.recv().is_ok())))
+    };
+    let queue = |priority: Priority, name: &'static str| {
+        let handle = handle.clone();
+        let order = Arc::clone(&order);
+        let call = thread::spawn(move || {
+            handle.call_with_priority(priority, move |_| {
+                order.lock().unwrap().push(name);
+                Ok(())
+            })
+        });
+        thread::sleep(Duration::from_millis(50));
+        call
+    };
+    thread::sleep(Duration::from_millis(50));
+    let low = queue(Priority::Low, "poll");
+    let high = queue(Priority::High, "health check");
+
+    release.send(()).unwrap();
+    assert!(busy.join().unwrap().unwrap());
+    for call in [low, high] {
+        call.join().unwrap().unwrap();
+    }
+    assert_eq!(*order.lock().unwrap(), ["health check", "poll"]);
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn registry_round_trips() {
+    use nsrt::registry::{DeviceEntry, Registry};
+
+    let registry: Registry = r#"
+        [devices.2103A4F1]
+        alias = "rooftop-north"
+        offset = -0.4
+        calibrated = "2024-05-01T08:30:00Z"
+        config = { weighting = "A", time_constant = 0.125, sampling_frequency = 48000 }
+
+        [devices.2103A4F2]
+    "#
+    .parse()
+    .unwrap();
+    assert_eq!(registry.serial_of("rooftop-north"), Some("2103A4F1"));
+    assert_eq!(registry.serial_of("rooftop-south"), None);
+    assert_eq!(registry.get("2103A4F2"), Some(&DeviceEntry::default()));
+    assert_eq!(
+        registry.get("2103A4F1").unwrap().config.unwrap().weighting,
+        Weighting::A
+    );
+    assert_eq!(
+        registry.get("2103A4F1").unwrap().calibrated,
+        Some(UNIX_EPOCH + Duration::from_secs(1_714_552_200))
+    );
+    assert_eq!(registry.to_string().parse::<Registry>().unwrap(), registry);
+
+    let mut registry = registry;
+    let taken = DeviceEntry {
+        alias: Some("rooftop-north".to_string()),
+        ..DeviceEntry::default()
+    };
+    assert!(registry.insert("2103A4F2", taken).is_err());
+}
+
+#[test]
+fn truncated_response_times_out() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_LEVEL, 4), [0, 0]);
+    assert_timed_out(nsrt.read_level());
+    mock.assert_done();
+}
+
+#[test]
+fn silent_device_times_out() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_TEMPERATURE, 4), []);
+    assert_timed_out(nsrt.read_temperature());
+    mock.assert_done();
+}
+
+#[test]
+fn classify_errors() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_LEVEL, 4), []);
+    let timeout = nsrt.read_level().unwrap_err();
+    assert_eq!(timeout.kind(), ErrorKind::Timeout);
+    assert!(timeout.is_retriable());
+
+    let invalid = nsrt.set_user_id(&"x".repeat(32)).unwrap_err();
+    assert_eq!(invalid.kind(), ErrorKind::InvalidParameter);
+    assert!(!invalid.is_retriable());
+    assert!(!NsrtError::NoDevice.is_retriable());
+    mock.assert_done();
+}
+
+#[test]
+fn error_codes_are_stable() {
+    assert_eq!(NsrtError::NoDevice.code(), 3);
+    assert_eq!(NsrtError::NoAcknowledge.code(), 4);
+    assert_eq!(NsrtError::InvalidParameter(String::new()).code(), 7);
+    assert_eq!(NsrtError::HandleClosed.code(), 10);
+}
+
+#[test]
+fn write_weighting() {
+    let (nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_WEIGHTING, &[1]), [ACK]);
+    nsrt.weighting(Weighting::A).unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn write_weighting_not_acknowledged() {
+    let (nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_WEIGHTING, &[2]), [0x15]);
+    assert!(matches!(
+        nsrt.weighting(Weighting::Z),
+        Err(NsrtError::NoAcknowledge)
+    ));
+    mock.assert_done();
+}
+
+#[test]
+fn write_time_constant() {
+    let (nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_TAU, &0.125f32.to_le_bytes()), [ACK]);
+    nsrt.time_constant(0.125).unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn write_time_constant_without_ack_times_out() {
+    let (nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_TAU, &1.0f32.to_le_bytes()), []);
+    assert_timed_out(nsrt.time_constant(1.0));
+    mock.assert_done();
+}
+
+#[test]
+fn set_weighting_waits_for_stabilization() {
+    let (mut nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_WEIGHTING, &[0]), [ACK]);
+    expect_float(&mock, READ_TAU, 0.035);
+    nsrt.set_weighting(Weighting::C).unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn set_sampling_frequency() {
+    let (mut nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_FS, &48000u16.to_le_bytes()), [ACK]);
+    expect_float(&mock, READ_TAU, 0.035);
+    nsrt.set_sampling_frequency(SamplingFrequency::Freq48kHz)
+        .unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn set_sampling_frequency_not_acknowledged() {
+    let (mut nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_FS, &32000u16.to_le_bytes()), [0]);
+    assert!(matches!(
+        nsrt.set_sampling_frequency(SamplingFrequency::Freq32kHz),
+        Err(NsrtError::NoAcknowledge)
+    ));
+    mock.assert_done();
+}
+
+#[test]
+fn set_user_id() {
+    let (mut nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_USER_ID, b"site 7\0"), [ACK]);
+    nsrt.set_user_id("site 7").unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn set_user_id_not_acknowledged() {
+    let (mut nsrt, mock) = device();
+    mock.expect(write_packet(WRITE_USER_ID, b"\0"), [0x15]);
+    assert!(matches!(
+        nsrt.set_user_id(""),
+        Err(NsrtError::NoAcknowledge)
+    ));
+    mock.assert_done();
+}
+
+#[test]
+fn set_user_id_validates_before_writing() {
+    let (mut nsrt, mock) = device();
+    assert!(matches!(
+        nsrt.set_user_id(&"x".repeat(32)),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+    assert!(matches!(
+        nsrt.set_user_id("a\0b"),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+    mock.assert_done();
+}
+
+#[test]
+fn parse_firmware_version() {
+    let version = |revision: &str| revision.parse::<FirmwareVersion>().ok();
+    assert_eq!(version("1.4"), Some(FirmwareVersion::new(1, 4, 0)));
+    assert_eq!(version("V1.10.2"), Some(FirmwareVersion::new(1, 10, 2)));
+    assert_eq!(version("sim"), None);
+    assert_eq!(version("1"), None);
+    assert!(version("1.10") > version("1.4"));
+}
+
+#[test]
+fn set_audio_debug() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_FW_REV, 32), text("V1.4"));
+    mock.expect(write_packet(WRITE_AUDIO_DEBUG, &[1]), [ACK]);
+    mock.expect(write_packet(WRITE_AUDIO_DEBUG, &[0]), [ACK]);
+    nsrt.set_audio_debug(true).unwrap();
+    // The firmware revision is read only once
+    nsrt.set_audio_debug(false).unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn set_audio_debug_unsupported_by_old_firmware() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_FW_REV, 32), text("1.3"));
+    assert!(!nsrt.capabilities().unwrap().audio_debug);
+    assert!(matches!(
+        nsrt.set_audio_debug(true),
+        Err(NsrtError::Unsupported { .. })
+    ));
+    mock.assert_done();
+}
+
+#[test]
+fn read_config() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_WEIGHTING, 1), [1]);
+    expect_float(&mock, READ_TAU, 0.125);
+    mock.expect(packet(READ_FS, 2), 32000u16.to_le_bytes());
+
+    let config = nsrt.read_config().unwrap();
+    assert_eq!(
+        config,
+        DeviceConfig {
+            weighting: Weighting::A,
+            time_constant: 0.125,
+            sampling_frequency: SamplingFrequency::Freq32kHz,
+        }
+    );
+    mock.assert_done();
+}
+
+#[test]
+fn configure_unchanged_writes_nothing() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_WEIGHTING, 1), [1]);
+    expect_float(&mock, READ_TAU, 0.125);
+    mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+
+    nsrt.configure(&DeviceConfig {
+        weighting: Weighting::A,
+        time_constant: 0.125,
+        sampling_frequency: SamplingFrequency::Freq48kHz,
+    })
+    .unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn configure_writes_changed_settings() {
+    let (mut nsrt, mock) = device();
+    mock.expect(packet(READ_WEIGHTING, 1), [1]);
+    expect_float(&mock, READ_TAU, 0.125);
+    mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+    mock.expect(write_packet(WRITE_WEIGHTING, &[2]), [ACK]);
+    mock.expect(write_packet(WRITE_TAU, &0.035f32.to_le_bytes()), [ACK]);
+
+    nsrt.configure(&DeviceConfig {
+        weighting: Weighting::Z,
+        time_constant: 0.035,
+        sampling_frequency: SamplingFrequency::Freq48kHz,
+    })
+    .unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn resync_discards_stale_bytes() {
+    let (mut nsrt, mock) = device();
+    // The device answers with more than was asked for, leaving bytes behind
+    mock.expect(packet(READ_LEVEL, 4), [0, 0, 0x80, 0x42, 0xff, 0xff]);
+    assert_eq!(nsrt.read_level().unwrap(), 64.0);
+    assert!(!mock.is_done());
+
+    expect_float(&mock, READ_LEVEL, 65.0);
+    nsrt.resync().unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn resume_reapplies_config() {
+    let (mut nsrt, mock) = device();
+    let config = DeviceConfig {
+        weighting: Weighting::A,
+        time_constant: 0.125,
+        sampling_frequency: SamplingFrequency::Freq48kHz,
+    };
+    mock.expect(packet(READ_WEIGHTING, 1), [1]);
+    expect_float(&mock, READ_TAU, 0.125);
+    mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+    nsrt.configure(&config).unwrap();
+    mock.assert_done();
+
+    // The device was reset to Z weighting while the host slept
+    expect_float(&mock, READ_LEVEL, 40.0);
+    mock.expect(packet(READ_WEIGHTING, 1), [2]);
+    expect_float(&mock, READ_TAU, 0.125);
+    mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+    mock.expect(write_packet(WRITE_WEIGHTING, &[1]), [ACK]);
+    nsrt.resume().unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn resume_without_port_cannot_reconnect() {
+    let (mut nsrt, mock) = device();
+    assert!(matches!(nsrt.resume(), Err(NsrtError::InvalidParameter(_))));
+    mock.assert_done();
+}
+
+#[test]
+fn unexpected_command_is_rejected() {
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEQ, 50.0);
+    match nsrt.read_level() {
+        Err(NsrtError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+        other => panic!("expected a rejected write, got {other:?}"),
+    }
+}
+
+#[test]
+fn capture_plays_back() {
+    let path = std::env::temp_dir().join(format!("nsrt-capture-{}.txt", std::process::id()));
+    let mock = MockTransport::new();
+    expect_float(&mock, READ_LEVEL, 61.5);
+    mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+    mock.expect(packet(READ_TEMPERATURE, 4), []);
+    mock.expect(write_packet(WRITE_WEIGHTING, &[2]), [ACK]);
+
+    let mut nsrt = NSRT::with_transport(RecordingTransport::create(mock.clone(), &path).unwrap());
+    assert_eq!(nsrt.read_level().unwrap(), 61.5);
+    assert_eq!(nsrt.read_model().unwrap(), "NSRT_mk4");
+    assert_timed_out(nsrt.read_temperature());
+    nsrt.weighting(Weighting::Z).unwrap();
+    mock.assert_done();
+
+    let playback = MockTransport::from_capture(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut nsrt = NSRT::with_transport(playback.clone());
+    assert_eq!(nsrt.read_level().unwrap(), 61.5);
+    assert_eq!(nsrt.read_model().unwrap(), "NSRT_mk4");
+    assert_timed_out(nsrt.read_temperature());
+    nsrt.weighting(Weighting::Z).unwrap();
+    playback.assert_done();
+}
+
+#[test]
+fn capture_rejects_garbage() {
+    let path = std::env::temp_dir().join(format!("nsrt-garbage-{}.txt", std::process::id()));
+    std::fs::write(&path, "> 10 00 zz\n").unwrap();
+    let result = MockTransport::from_capture(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(NsrtError::InvalidParameter(_))));
+}
+
+/// Sink keeping everything written to it
+#[derive(Default)]
+struct Collect(Vec<Measurement>);
+
+impl Sink for Collect {
+    fn write(&mut self, measurement: &Measurement) -> nsrt::Result<()> {
+        self.0.push(*measurement);
+        Ok(())
+    }
+}
+
+fn at_millis(millis: u64, level: f32) -> Measurement {
+    Measurement {
+        timestamp: UNIX_EPOCH + Duration::from_millis(millis),
+        level,
+        leq: level,
+        temperature: Temperature::from_celsius(20.0),
+        clock: None,
+        compensated: None,
+        quality: Quality::GOOD,
+    }
+}
+
+#[test]
+fn resample_onto_grid() {
+    let step = Duration::from_secs(1);
+    let mut linear = Resampler::new(Collect::default(), step, Interpolation::Linear);
+    let mut nearest = Resampler::new(Collect::default(), step, Interpolation::Nearest);
+    for measurement in [
+        at_millis(900, 60.0),
+        at_millis(1500, 70.0),
+        at_millis(2100, 60.0),
+        at_millis(3000, 50.0),
+    ] {
+        linear.write(&measurement).unwrap();
+        nearest.write(&measurement).unwrap();
+    }
+
+    let linear = linear.into_inner().0;
+    let times: Vec<_> = linear.iter().map(|m| m.timestamp).collect();
+    let grid = [1000, 2000, 3000].map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+    assert_eq!(times, grid);
+    // A sixth of the way from 60 to 70 dB in energy, not in dB
+    let expected = 10.0 * (1e6 + (1e7 - 1e6) / 6.0f64).log10();
+    assert!((f64::from(linear[0].level) - expected).abs() < 1e-4);
+    assert_eq!(linear[2].level, 50.0);
+
+    let levels: Vec<_> = nearest.into_inner().0.iter().map(|m| m.level).collect();
+    assert_eq!(levels, [60.0, 60.0, 50.0]);
+}
+
+#[test]
+fn resample_skips_gaps() {
+    let mut resampler = Resampler::new(
+        Collect::default(),
+        Duration::from_secs(1),
+        Interpolation::Linear,
+    )
+    .max_gap(Duration::from_secs(2));
+    for measurement in [
+        at_millis(500, 60.0),
+        at_millis(1500, 60.0),
+        at_millis(9500, 60.0),
+        at_millis(10_500, 60.0),
+    ] {
+        resampler.write(&measurement).unwrap();
+    }
+
+    let times: Vec<_> = resampler
+        .into_inner()
+        .0
+        .iter()
+        .map(|m| m.timestamp)
+        .collect();
+    let expected = [1000, 10_000].map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+    assert_eq!(times, expected);
+}
+
+#[test]
+fn quality_flags_round_trip() {
+    let quality = Quality::MISSING_SAMPLES | Quality::CLOCK_STEP;
+    assert_eq!(quality.to_string(), "missing_samples|clock_step");
+    assert_eq!(
+        "missing_samples|clock_step".parse::<Quality>().unwrap(),
+        quality
+    );
+    assert_eq!(Quality::GOOD.to_string(), "good");
+    assert!("".parse::<Quality>().unwrap().is_good());
+    assert!(matches!(
+        "missing_samples|late".parse::<Quality>(),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+
+    // Resampled points carry the flags of the measurements on both sides
+    let mut resampler = Resampler::new(
+        Collect::default(),
+        Duration::from_secs(1),
+        Interpolation::Linear,
+    );
+    resampler.write(&at_millis(900, 60.0)).unwrap();
+    let reconnected = Measurement {
+        quality: Quality::RECONNECTED,
+        ..at_millis(1500, 60.0)
+    };
+    resampler.write(&reconnected).unwrap();
+    let resampled = resampler.into_inner().0;
+    assert_eq!(resampled[0].quality, Quality::RECONNECTED);
+}
+
+#[test]
+fn compare_sessions() {
+    let session = |levels: &[(u64, f32)]| Session {
+        started: UNIX_EPOCH,
+        stopped: None,
+        metadata: Metadata::default(),
+        measurements: levels
+            .iter()
+            .map(|&(millis, level)| at_millis(millis, level))
+            .collect(),
+    };
+    let reference = session(&[(0, 60.0), (1000, 70.0), (2000, 65.0), (3000, 62.0)]);
+    // Reads 1 dB high, polled a little later, and misses the last poll
+    let other = session(&[(100, 61.0), (1100, 71.0), (2050, 66.0)]);
+    // Reads 0.5 dB lower every second
+    let drifting = session(&[(0, 60.0), (1000, 69.5), (2000, 64.0), (3000, 60.5)]);
+
+    let tolerance = Duration::from_millis(500);
+    let comparison = Comparison::compute(
+        &[reference.clone(), other.clone(), drifting.clone()],
+        tolerance,
+    );
+    let pairs: Vec<_> = comparison
+        .pairs
+        .iter()
+        .map(|p| (p.reference, p.other))
+        .collect();
+    assert_eq!(pairs, [(0, 1), (0, 2), (1, 2)]);
+
+    let offset = comparison.pairs[0];
+    assert_eq!(offset.samples, 3);
+    assert_eq!(offset.mean_difference, Some(1.0));
+    assert_eq!(offset.std_deviation, Some(0.0));
+    assert!((offset.correlation.unwrap() - 1.0).abs() < 1e-6);
+    assert_eq!(offset.drift, Some(0.0));
+
+    let drift = comparison.pairs[1];
+    assert_eq!(drift.samples, 4);
+    assert_eq!(drift.max_difference, Some(-1.5));
+    assert!((drift.drift.unwrap() + 0.5 * 3600.0).abs() < 1e-2);
+
+    // Within 1 dB but for the last second
+    let divergences = Comparison::divergences(&reference, &drifting, tolerance, 1.0);
+    assert_eq!(
+        divergences,
+        [Divergence {
+            start: UNIX_EPOCH + Duration::from_secs(3),
+            end: UNIX_EPOCH + Duration::from_secs(3),
+            samples: 1,
+            max_difference: -1.5,
+        }]
+    );
+    let divergences = Comparison::divergences(&reference, &drifting, tolerance, 0.25);
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(divergences[0].start, UNIX_EPOCH + Duration::from_secs(1));
+    assert_eq!(divergences[0].samples, 3);
+    assert!(Comparison::divergences(&reference, &other, tolerance, 1.0).is_empty());
+}
+
+#[test]
+fn correct_for_background() {
+    let (mut nsrt, mock) = device();
+    for leq in [80.0, 50.0, 80.0, 60.0] {
+        expect_float(&mock, READ_LEQ, leq);
+    }
+    let background = BackgroundCorrection::measure(&mut nsrt, Duration::ZERO).unwrap();
+    assert_eq!(background.background, 50.0);
+    let source = background
+        .measure_source(&mut nsrt, Duration::ZERO)
+        .unwrap();
+    mock.assert_done();
+
+    // 10 log10(10^6 - 10^5)
+    assert_eq!(source.margin, 10.0);
+    assert!((source.level - 59.542_4).abs() < 1e-3);
+    assert!(source.reliable);
+
+    // The background dominates: only an upper bound
+    let masked = background.correct(52.0);
+    assert!(!masked.reliable);
+    assert_eq!(masked.level, 49.0);
+}
+
+#[test]
+fn sound_power_survey() {
+    let hemisphere = MeasurementSurface::Hemisphere { radius: 1.0 };
+    assert!((hemisphere.area() - 2.0 * std::f32::consts::PI).abs() < 1e-5);
+    let surface = MeasurementSurface::Parallelepiped {
+        length: 2.0,
+        width: 1.0,
+        height: 1.0,
+        distance: 1.0,
+    };
+    // a = 2, b = 1.5, c = 2
+    assert_eq!(surface.area(), 4.0 * (3.0 + 3.0 + 4.0));
+
+    // 10 positions over 10 m², the mean 10 dB above the background
+    let (mut nsrt, mock) = device();
+    for leq in [80.0, 60.0] {
+        expect_float(&mock, READ_LEQ, leq);
+    }
+    let mut survey = SoundPowerSurvey::new(MeasurementSurface::Area(10.0))
+        .environmental_correction(1.0)
+        .background(50.0);
+    for _ in 0..9 {
+        survey = survey.position(60.0);
+    }
+    assert_eq!(
+        survey.measure_position(&mut nsrt, Duration::ZERO).unwrap(),
+        60.0
+    );
+    mock.assert_done();
+
+    let power = survey.compute().unwrap();
+    assert_eq!(power.positions, 10);
+    assert!((power.mean_level - 60.0).abs() < 1e-4);
+    assert!(power.reliable);
+    // 60 - 0.458 (K1) - 1 (K2) + 10
+    assert!((power.sound_power_level - 68.542).abs() < 1e-2);
+
+    assert!(SoundPowerSurvey::new(hemisphere).compute().is_err());
+}
+
+#[test]
+fn room_noise_ratings() {
+    // On the NC-35 curve but for 1 kHz on the NC-40 one
+    let mut levels = [None; 10];
+    for (band, level) in [60.0, 52.0, 45.0, 40.0, 41.0, 34.0, 33.0, 32.0]
+        .into_iter()
+        .enumerate()
+    {
+        levels[band + 2] = Some(level);
+    }
+    let nc = OctaveBands::new(levels).nc().unwrap();
+    assert_eq!((nc.rating, nc.governing_band), (40, 1000.0));
+    assert!(OctaveBands::default().nc().is_err());
+
+    // On the RC-35 reference curve, 5 dB per octave through 35 dB at 1 kHz
+    let reference = OCTAVE_BANDS.map(|f| Some(35.0 - 5.0 * (f / 1000.0).log2()));
+    let rc = OctaveBands::new(reference).rc().unwrap();
+    assert_eq!((rc.rating, rc.quality), (35, RcQuality::Neutral));
+
+    let mut hiss = reference;
+    hiss[8] = hiss[8].map(|level| level + 12.0);
+    let rc = OctaveBands::new(hiss).rc().unwrap();
+    assert_eq!((rc.rating, rc.quality), (35, RcQuality::Hiss));
+    assert!((rc.qai - 7.75).abs() < 0.01);
+
+    let mut rumble = reference;
+    rumble[0] = Some(80.0);
+    let rc = OctaveBands::new(rumble).rc().unwrap();
+    assert_eq!(rc.quality, RcQuality::RumbleVibrationClear);
+}
+
+#[cfg(feature = "report")]
+#[test]
+fn traffic_noise_indices() {
+    use nsrt::report::Percentiles;
+
+    let percentiles = Percentiles {
+        l10: 70.0,
+        l50: 65.0,
+        l90: 60.0,
+    };
+    // 4 (70 - 60) + 60 - 30
+    assert_eq!(percentiles.traffic_noise_index(), 70.0);
+    assert_eq!(percentiles.noise_pollution_level(66.0), 76.0);
+}
+
+#[cfg(feature = "report")]
+#[test]
+fn analyze_stored_log() {
+    use nsrt::report::{Analysis, SummaryOptions};
+
+    // One measurement a second for 30 s, loud from 12 s to 14 s
+    let log: Vec<nsrt::Result<Measurement>> = (0..30)
+        .map(|s| {
+            Ok(at_millis(
+                s * 1000,
+                if (12..15).contains(&s) { 80.0 } else { 50.0 },
+            ))
+        })
+        .collect();
+
+    let windows = Analysis::new()
+        .start(UNIX_EPOCH + Duration::from_secs(5))
+        .end(UNIX_EPOCH + Duration::from_secs(25))
+        .window(Duration::from_secs(10))
+        .exceedance(&[10.0, 90.0])
+        .options(SummaryOptions {
+            event_threshold: Some(70.0),
+            ..SummaryOptions::default()
+        })
+        .run(log)
+        .unwrap();
+
+    assert_eq!(windows.len(), 2);
+    let [first, second] = &windows[..] else {
+        unreachable!()
+    };
+    assert_eq!(first.start, UNIX_EPOCH + Duration::from_secs(5));
+    assert_eq!(first.end, UNIX_EPOCH + Duration::from_secs(15));
+    assert_eq!(first.measurements, 10);
+    assert_eq!((first.lmax, first.lmin), (Some(80.0), Some(50.0)));
+    assert_eq!((first.ln(10.0), first.ln(90.0)), (Some(80.0), Some(50.0)));
+    assert_eq!(first.ln(50.0), None);
+    assert_eq!(first.events.len(), 1);
+    assert_eq!(first.event_time(), Duration::from_secs(2));
+
+    assert_eq!(second.end, UNIX_EPOCH + Duration::from_secs(25));
+    assert_eq!(second.measurements, 10);
+    assert_eq!(second.leq, Some(50.0));
+    assert!(second.events.is_empty());
+}
+
+#[cfg(feature = "report")]
+#[test]
+fn analyze_log_with_gap() {
+    use nsrt::report::Analysis;
+
+    let log = [(0, 50.0), (30, 50.0), (90, 60.0), (130, 70.0), (150, 80.0)]
+        .map(|(secs, level)| Ok(at_millis(secs * 1000, level)));
+    let windows = Analysis::new()
+        .window(Duration::from_secs(60))
+        .run(log)
+        .unwrap();
+
+    // Aligned to the start of the log despite the gap
+    let bounds: Vec<(u64, u64)> = windows
+        .iter()
+        .map(|w| {
+            let secs = |time: std::time::SystemTime| time.duration_since(UNIX_EPOCH).unwrap();
+            (secs(w.start).as_secs(), secs(w.end).as_secs())
+        })
+        .collect();
+    assert_eq!(bounds, [(0, 60), (60, 120), (120, 180)]);
+    assert_eq!(
+        windows.iter().map(|w| w.measurements).collect::<Vec<_>>(),
+        [2, 1, 2]
+    );
+
+    // A window's first LEQ counts for the time since the window started:
+    // 60 dB for 30 s, then 70 dB for 10 s and 80 dB for 20 s
+    assert_eq!(windows[1].leq, Some(60.0));
+    let expected = 10.0 * ((10.0 * 1e7 + 20.0 * 1e8) / 30.0f64).log10();
+    assert!((f64::from(windows[2].leq.unwrap()) - expected).abs() < 0.01);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_queries() {
+    use nsrt::sqlite::SqliteLog;
+
+    const HOUR: u64 = 3_600_000;
+    let mut log = SqliteLog::open_in_memory().unwrap();
+    for (millis, level) in [
+        (0, 50.0),
+        (1000, 80.0),
+        (2000, 82.0),
+        (3000, 50.0),
+        (HOUR, 60.0),
+        (HOUR + 1000, 60.0),
+    ] {
+        log.write(&at_millis(millis, level)).unwrap();
+    }
+    assert_eq!(log.count(..).unwrap(), 0, "buffered until flushed");
+    log.flush().unwrap();
+
+    let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+    assert_eq!(log.count(..).unwrap(), 6);
+    assert_eq!(log.span().unwrap(), Some((at(0), at(HOUR + 1000))));
+    assert_eq!(log.measurements(at(1000)..at(3000)).unwrap().len(), 2);
+    assert_eq!(log.measurements(at(1000)..=at(3000)).unwrap().len(), 3);
+    assert_eq!(
+        log.measurements(at(HOUR)..).unwrap()[0],
+        at_millis(HOUR, 60.0)
+    );
+
+    let hours = log.hourly(..).unwrap();
+    assert_eq!(hours.len(), 2);
+    assert_eq!((hours[0].start, hours[0].measurements), (at(0), 4));
+    assert_eq!((hours[0].lmax, hours[0].lmin), (Some(82.0), Some(50.0)));
+    assert_eq!((hours[1].start, hours[1].leq), (at(HOUR), Some(60.0)));
+
+    let events = log.events(.., 70.0).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].start, events[0].end), (at(1000), at(2000)));
+    assert_eq!((events[0].lmax, events[0].measurements), (82.0, 2));
+
+    log.write(&at_millis(2 * HOUR, 55.0)).unwrap();
+    assert_eq!(log.prune(at(HOUR)).unwrap(), 4);
+    assert_eq!(log.span().unwrap(), Some((at(HOUR), at(2 * HOUR))));
+
+    // Only the hour that ended by the cutoff is compacted
+    let before = log.hourly(..).unwrap();
+    assert_eq!(
+        log.compact(at(2 * HOUR + 500), Duration::from_secs(3600))
+            .unwrap(),
+        2
+    );
+    assert_eq!(log.count(..).unwrap(), 1);
+    let aggregates = log.aggregates(..).unwrap();
+    assert_eq!(aggregates.len(), 1);
+    assert_eq!(
+        (aggregates[0].l10, aggregates[0].l90),
+        (Some(60.0), Some(60.0))
+    );
+    assert_eq!(log.hourly(..).unwrap(), before);
+}
+
+#[cfg(feature = "binlog")]
+#[test]
+fn compact_binary_log() {
+    use nsrt::binlog::{BinaryLogReader, BinaryLogWriter, compact};
+
+    let path = std::env::temp_dir().join(format!("nsrt-compact-{}.nsrtlog", std::process::id()));
+    let mut writer = BinaryLogWriter::create(&path).unwrap();
+    for (millis, level) in [(0, 50.0), (1000, 60.0), (2000, 50.0), (3000, 70.0)] {
+        writer.write(&at_millis(millis, level)).unwrap();
+    }
+    drop(writer);
+
+    // The first two seconds become one record each, the rest is kept
+    let second = Duration::from_secs(1);
+    let half = Duration::from_secs(2);
+    assert_eq!(compact(&path, UNIX_EPOCH + half, half).unwrap(), 2);
+    let records: Vec<Measurement> = BinaryLogReader::open(&path)
+        .unwrap()
+        .collect::<nsrt::Result<_>>()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].timestamp, UNIX_EPOCH);
+    assert_eq!(records[0].level, 60.0);
+    assert!((records[0].leq - 57.4).abs() < 0.01);
+    assert_eq!(records[1], at_millis(2000, 50.0));
+    assert_eq!(records[2].timestamp, UNIX_EPOCH + 3 * second);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_log() {
+    use nsrt::{
+        ClockStatus,
+        parquet::{ParquetReader, ParquetWriter},
+    };
+
+    let path = std::env::temp_dir().join(format!("nsrt-{}.parquet", std::process::id()));
+    let clocked = Measurement {
+        clock: Some(ClockStatus {
+            synchronized: true,
+            offset: 0.000_012,
+            max_error: 0.0015,
+        }),
+        quality: Quality::MISSING_SAMPLES,
+        ..at_millis(1250, 61.5)
+    };
+    let measurements = [at_millis(0, 50.0), clocked, at_millis(2000, 55.0)];
+
+    let mut writer = ParquetWriter::create(&path).unwrap();
+    writer
+        .set_metadata(&Metadata {
+            site: Some("Main Street".to_string()),
+            ..Metadata::default()
+        })
+        .unwrap();
+    for measurement in &measurements {
+        writer.write(measurement).unwrap();
+    }
+    // Row groups may end anywhere
+    writer.flush().unwrap();
+    writer.write(&at_millis(3000, 45.0)).unwrap();
+    writer.finish().unwrap();
+
+    let records: Vec<Measurement> = ParquetReader::open(&path)
+        .unwrap()
+        .collect::<nsrt::Result<_>>()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[..3], measurements);
+    assert_eq!(records[3], at_millis(3000, 45.0));
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_log() {
+    use nsrt::encryption::{EncryptedLogReader, EncryptedLogWriter, LogKey};
+
+    let key = LogKey::generate();
+    assert_eq!(LogKey::from_hex(&key.to_hex()).unwrap(), key);
+    assert!(LogKey::from_hex("00").is_err());
+
+    let path = std::env::temp_dir().join(format!("nsrt-encrypted-{}.nsrtlog", std::process::id()));
+    let mut writer = EncryptedLogWriter::create(&path, &key).unwrap();
+    writer.write(&at_millis(0, 50.0)).unwrap();
+    writer.flush().unwrap();
+    writer.write(&at_millis(1000, 60.0)).unwrap();
+    drop(writer);
+    // Appending continues the segment count of the existing log
+    let mut writer = EncryptedLogWriter::append(&path, &key).unwrap();
+    writer.write(&at_millis(2000, 70.0)).unwrap();
+    drop(writer);
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let read = |bytes: &[u8], key: &LogKey| {
+        EncryptedLogReader::new(bytes, key)
+            .unwrap()
+            .collect::<nsrt::Result<Vec<_>>>()
+    };
+    assert_eq!(
+        read(&bytes, &key).unwrap(),
+        [
+            at_millis(0, 50.0),
+            at_millis(1000, 60.0),
+            at_millis(2000, 70.0)
+        ]
+    );
+
+    assert!(matches!(
+        read(&bytes, &LogKey::generate()),
+        Err(NsrtError::VerificationFailed(_))
+    ));
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    assert!(matches!(
+        read(&bytes, &key),
+        Err(NsrtError::VerificationFailed(_))
+    ));
+}
+
+#[cfg(feature = "report")]
+#[test]
+fn dose_thresholds() {
+    use nsrt::report::{DailySummary, DoseCriteria, SummaryOptions};
+
+    // 8 hours at 85 dB, then 8 hours at 95 dB
+    let measurements: Vec<Measurement> = (1..=16 * 60)
+        .map(|minute| at_millis(minute * 60_000, if minute <= 8 * 60 { 85.0 } else { 95.0 }))
+        .collect();
+    let dose = |dose| {
+        let options = SummaryOptions {
+            dose,
+            ..SummaryOptions::default()
+        };
+        DailySummary::compute(UNIX_EPOCH, &measurements, &options).dose
+    };
+
+    // 85 dB is below the OSHA threshold, and 95 dB is allowed for 4 hours
+    assert!((dose(DoseCriteria::OSHA) - 200.0).abs() < 1.0);
+    // From 80 dB, 85 dB is allowed for 16 hours
+    assert!((dose(DoseCriteria::OSHA_ACTION_LEVEL) - 250.0).abs() < 1.0);
+    let unthresholded = DoseCriteria {
+        threshold: None,
+        ..DoseCriteria::OSHA
+    };
+    assert!((dose(unthresholded) - 250.0).abs() < 1.0);
+    assert!((dose(DoseCriteria::NIOSH) - 1107.9).abs() < 1.0);
+}
+
+#[cfg(feature = "report")]
+#[test]
+fn daylight_saving_day() {
+    use nsrt::report::{DailySummary, DayEveningNight, SummaryOptions, TimeZone};
+
+    // Clocks in Berlin go forward from 02:00 to 03:00 on 2024-03-31, which
+    // runs from 2024-03-30T23:00Z to 2024-03-31T22:00Z
+    let midnight_utc = 1_711_843_200;
+    let measurements: Vec<Measurement> = (-7200..86_400)
+        .step_by(60)
+        .map(|offset: i64| {
+            let utc_hour = offset.div_euclid(3600);
+            let local_hour = (utc_hour + if offset < 3600 { 1 } else { 2 }).rem_euclid(24);
+            let level = match local_hour {
+                7..19 => 60.0,
+                19..23 => 50.0,
+                _ => 40.0,
+            };
+            at_millis((midnight_utc + offset) as u64 * 1000, level)
+        })
+        .collect();
+
+    let berlin = TimeZone::get("Europe/Berlin").unwrap();
+    let time = UNIX_EPOCH + Duration::from_secs(midnight_utc as u64 + 12 * 3600);
+    let summary =
+        DailySummary::compute_in(&berlin, time, &measurements, &SummaryOptions::default()).unwrap();
+    assert_eq!(
+        summary.start,
+        UNIX_EPOCH + Duration::from_secs(midnight_utc as u64 - 3600)
+    );
+    assert_eq!(summary.hourly.len(), 23);
+    assert_eq!(
+        summary
+            .hourly
+            .iter()
+            .map(|h| h.hour)
+            .take(4)
+            .collect::<Vec<_>>(),
+        [0, 1, 3, 4]
+    );
+    assert_eq!(summary.measurements, 23 * 60);
+    let lden = summary.lden.unwrap();
+    assert_eq!((lden.lday, lden.levening, lden.lnight), (60.0, 50.0, 40.0));
+    assert_eq!(lden, DayEveningNight::new(60.0, 50.0, 40.0));
+    assert!((lden.lden - 57.68).abs() < 0.01);
+}
+
+#[cfg(feature = "geojson")]
+#[test]
+fn geojson_sites() {
+    use nsrt::{
+        Position,
+        geojson::SiteMap,
+        registry::{DeviceEntry, Registry},
+        report::Analysis,
+    };
+
+    let mut registry = Registry::default();
+    let entry = DeviceEntry {
+        alias: Some("rooftop-north".to_string()),
+        metadata: Metadata {
+            site: Some("Alexanderplatz".to_string()),
+            position: Some(Position {
+                latitude: 52.52,
+                longitude: 13.405,
+            }),
+            ..Metadata::default()
+        },
+        ..DeviceEntry::default()
+    };
+    registry.insert("2103A4F1", entry).unwrap();
+
+    let windows = Analysis::new()
+        .run([at_millis(0, 50.0), at_millis(1000, 60.0)].map(Ok))
+        .unwrap();
+    let mut map = SiteMap::new();
+    map.add_registered(&registry, "2103A4F1", &windows[0]);
+    map.add_registered(&registry, "2103A4F2", &windows[0]);
+    assert_eq!(map.len(), 2);
+
+    let json = map.to_json();
+    assert_eq!(json["type"], "FeatureCollection");
+    let placed = &json["features"][0];
+    assert_eq!(
+        placed["geometry"]["coordinates"],
+        serde_json::json!([13.405, 52.52])
+    );
+    assert_eq!(placed["properties"]["device"], "rooftop-north");
+    assert_eq!(placed["properties"]["site"], "Alexanderplatz");
+    assert_eq!(placed["properties"]["measurements"], 2);
+    assert_eq!(placed["properties"]["lmax"], 60.0);
+    assert!(placed["properties"]["l90"].is_number());
+    let unplaced = &json["features"][1];
+    assert!(unplaced["geometry"].is_null());
+    assert_eq!(unplaced["properties"]["serial"], "2103A4F2");
+}
+
+#[cfg(feature = "broker")]
+#[test]
+fn remote_broker_client() {
+    use nsrt::{
+        SoundLevelMeter,
+        broker::{RemoteNsrt, serve_tcp},
+    };
+
+    let (nsrt, mock) = device();
+    expect_float(&mock, READ_LEVEL, 61.5);
+    let handle = DeviceHandle::spawn(nsrt);
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    thread::spawn(move || {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(serve_tcp(addr, handle))
+    });
+
+    let mut remote = (0..50)
+        .find_map(|_| {
+            RemoteNsrt::connect(addr).ok().or_else(|| {
+                thread::sleep(Duration::from_millis(20));
+                None
+            })
+        })
+        .expect("broker listening");
+    remote.set_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(remote.read_level().unwrap(), 61.5);
+    // Device errors come back as broker errors, leaving the connection usable
+    assert!(matches!(remote.read_leq(), Err(NsrtError::BrokerError(_))));
+    mock.assert_done();
+}
+
+#[cfg(feature = "mdns")]
+#[test]
+fn mdns_advertisement() {
+    use nsrt::mdns::{Api, Service, discover};
+
+    let advertisement = Service::new("2103A4F1", Api::Broker, 7300)
+        .instance("rooftop-north")
+        .property("device", "rooftop-north")
+        .advertise()
+        .unwrap();
+    assert_eq!(advertisement.fullname(), "rooftop-north._nsrt._tcp.local.");
+
+    let found = discover(Duration::from_secs(3)).unwrap();
+    let meter = found
+        .iter()
+        .find(|meter| meter.fullname == advertisement.fullname())
+        .expect("advertised meter discovered");
+    assert_eq!(meter.port, 7300);
+    assert_eq!(meter.serial.as_deref(), Some("2103A4F1"));
+    assert_eq!(meter.api.as_deref(), Some("broker"));
+}
+
+#[cfg(all(feature = "tls", feature = "http"))]
+#[tokio::test]
+async fn https_dashboard() {
+    use nsrt::tls::{TlsIdentity, TlsListener};
+    use rustls::{
+        ClientConfig, RootCertStore,
+        pki_types::{CertificateDer, ServerName, pem::PemObject},
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    assert!(TlsIdentity::from_pem(&b"not a certificate"[..], &b""[..]).is_err());
+    let identity = TlsIdentity::from_pem(
+        &include_bytes!("tls/cert.pem")[..],
+        &include_bytes!("tls/key.pem")[..],
+    )
+    .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let listener = TlsListener::new(listener, identity.server_config().unwrap()).unwrap();
+    let addr = listener.local_addr();
+    tokio::spawn(async move { axum::serve(listener, nsrt::http::dashboard()).await });
+
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_slice(include_bytes!("tls/ca.pem")).unwrap())
+        .unwrap();
+    let config = ClientConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(config));
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
+
+#[cfg(feature = "http")]
+#[tokio::test]
+async fn http_auth() {
+    use nsrt::http::{Auth, Permission};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let auth: Auth = serde_json::from_str(
+        r#"{
+            "token": [{"token": "s3cret", "permission": "configure"}],
+            "user": [{"name": "viewer", "password": "hunter2", "permission": "read"}]
+        }"#,
+    )
+    .unwrap();
+    assert!(!auth.is_empty());
+    let auth = auth.token("read-only", Permission::Read);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = auth.protect(nsrt::http::dashboard());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let status = |method: &'static str, authorization: Option<&'static str>| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let authorization = authorization
+            .map(|value| format!("Authorization: {value}\r\n"))
+            .unwrap_or_default();
+        let request = format!(
+            "{method} / HTTP/1.1\r\nHost: localhost\r\n{authorization}Content-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response[9..12].parse::<u16>().unwrap()
+    };
+
+    assert_eq!(status("GET", None).await, 401);
+    assert_eq!(status("GET", Some("Bearer wrong")).await, 401);
+    assert_eq!(status("GET", Some("Bearer s3cret")).await, 200);
+    assert_eq!(status("GET", Some("Bearer read-only")).await, 200);
+    // viewer:hunter2
+    assert_eq!(status("GET", Some("Basic dmlld2VyOmh1bnRlcjI=")).await, 200);
+    // viewer:hunter3
+    assert_eq!(status("GET", Some("Basic dmlld2VyOmh1bnRlcjM=")).await, 401);
+    // Writes need the configure permission
+    assert_eq!(status("POST", Some("Bearer read-only")).await, 403);
+    assert_eq!(
+        status("POST", Some("Basic dmlld2VyOmh1bnRlcjI=")).await,
+        403
+    );
+    assert_eq!(status("POST", Some("Bearer s3cret")).await, 405);
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn driver_metrics() {
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    let recorder = PrometheusBuilder::new()
+        .set_buckets(&nsrt::metrics::LATENCY_BUCKETS)
+        .unwrap()
+        .build_recorder();
+    let handle = recorder.handle();
+
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEVEL, 61.5);
+    mock.expect(packet(READ_TEMPERATURE, 4), []);
+    expect_float(&mock, READ_LEVEL, 61.0);
+    metrics::with_local_recorder(&recorder, || {
+        nsrt.read_level().unwrap();
+        assert!(nsrt.read_temperature().is_err());
+        nsrt.resync().unwrap();
+    });
+    mock.assert_done();
+
+    let rendered = handle.render();
+    assert!(
+        rendered.contains("nsrt_command_duration_seconds_count{command=\"ReadLevel\"} 2"),
+        "{rendered}"
+    );
+    assert!(rendered.contains("nsrt_command_errors_total{command=\"ReadTemperature\",kind="));
+    assert!(rendered.contains("nsrt_resyncs_total 1"));
+    assert!(!rendered.contains("nsrt_reconnects_total"));
+}
+
+#[test]
+fn self_test() {
+    use nsrt::CheckStatus;
+
+    let (mut nsrt, mock) = device();
+    expect_float(&mock, READ_LEVEL, 61.5);
+    mock.expect(packet(READ_LEQ, 4), []);
+    expect_float(&mock, READ_TEMPERATURE, 95.0);
+    mock.expect(packet(READ_WEIGHTING, 1), [0]);
+    mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+    expect_float(&mock, READ_TAU, 0.125);
+    mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+    mock.expect(packet(READ_SN, 32), text("2103A4F1"));
+    mock.expect(packet(READ_FW_REV, 32), text("1.4"));
+    // 2018-01-28, then 2014-11-28
+    mock.expect(packet(READ_DOB, 8), 3_600_000_000u64.to_le_bytes());
+    mock.expect(packet(READ_DOC, 8), 3_500_000_000u64.to_le_bytes());
+    mock.expect(packet(READ_USER_ID, 32), text("North boundary"));
+
+    let report = nsrt.self_test();
+    mock.assert_done();
+    assert_eq!(report.checks.len(), 12);
+    assert_eq!(report.status(), CheckStatus::Fail);
+    assert!(!report.passed());
+
+    let level = report.check("level").unwrap();
+    assert_eq!(level.status, CheckStatus::Pass);
+    assert_eq!(level.value.as_deref(), Some("61.5 dB"));
+    let leq = report.check("leq").unwrap();
+    assert_eq!(leq.status, CheckStatus::Fail);
+    assert_eq!(leq.value, None);
+    assert_eq!(
+        report.check("temperature").unwrap().status,
+        CheckStatus::Fail
+    );
+    assert_eq!(
+        report.check("time_constant").unwrap().status,
+        CheckStatus::Pass
+    );
+    assert_eq!(
+        report.check("birth_date").unwrap().value.as_deref(),
+        Some("2018-01-28")
+    );
+    let calibration = report.check("calibration_date").unwrap();
+    assert_eq!(calibration.status, CheckStatus::Warn);
+    assert_eq!(calibration.value.as_deref(), Some("2014-11-28"));
+    assert!(report.max_latency() >= report.mean_latency());
+
+    // Settings read as the CLI prints them, and warn when from later firmware
+    for (weighting, hz, value, status) in [
+        (1, 48000u16, "A", CheckStatus::Pass),
+        (3, 44100, "Unknown(3)", CheckStatus::Warn),
+    ] {
+        expect_float(&mock, READ_LEVEL, 61.5);
+        expect_float(&mock, READ_LEQ, 60.0);
+        expect_float(&mock, READ_TEMPERATURE, 21.0);
+        mock.expect(packet(READ_WEIGHTING, 1), [weighting]);
+        mock.expect(packet(READ_FS, 2), hz.to_le_bytes());
+        expect_float(&mock, READ_TAU, 0.125);
+        mock.expect(packet(READ_MODEL, 32), text("NSRT_mk4"));
+        mock.expect(packet(READ_SN, 32), text("2103A4F1"));
+        mock.expect(packet(READ_FW_REV, 32), text("1.4"));
+        mock.expect(packet(READ_DOB, 8), 3_500_000_000u64.to_le_bytes());
+        mock.expect(packet(READ_DOC, 8), 3_600_000_000u64.to_le_bytes());
+        mock.expect(packet(READ_USER_ID, 32), text("North boundary"));
+
+        let report = nsrt.self_test();
+        mock.assert_done();
+        assert_eq!(report.status(), status);
+        let weighting = report.check("weighting").unwrap();
+        assert_eq!(weighting.value.as_deref(), Some(value));
+        assert_eq!(weighting.status, status);
+        let sampling_frequency = report.check("sampling_frequency").unwrap();
+        assert_eq!(sampling_frequency.value, Some(hz.to_string()));
+        assert_eq!(sampling_frequency.status, status);
+    }
+}
+
+#[test]
+fn calibrate() {
+    use nsrt::Calibrator;
+
+    let (mut nsrt, mock) = device();
+    nsrt.set_calibration_offset(1.0);
+    // Ambient noise, then the calibrator being fitted and settling
+    for level in [45.0, 90.1, 93.6, 93.7, 93.6] {
+        expect_float(&mock, READ_LEVEL, level);
+    }
+    let calibrator = Calibrator {
+        readings: 3,
+        interval: Duration::ZERO,
+        ..Calibrator::default()
+    };
+    let calibration = nsrt.calibrate(&calibrator).unwrap();
+    mock.assert_done();
+    assert_eq!(calibration.nominal, 94.0);
+    assert!((calibration.measured - 93.633).abs() < 0.001);
+    assert!((calibration.spread - 0.1).abs() < 0.001);
+    assert!((calibration.offset() - 0.367).abs() < 0.001);
+
+    // No tone at all
+    expect_float(&mock, READ_LEVEL, 45.0);
+    assert_timed_out(nsrt.calibrate(&Calibrator {
+        timeout: Duration::ZERO,
+        ..calibrator
+    }));
+    mock.assert_done();
+}
+
+#[test]
+fn strict_mode() {
+    use nsrt::StrictMode;
+
+    // Without strict mode, trailing bytes go unnoticed until they desync
+    // the next answer
+    let (mut nsrt, mock) = device();
+    let mut answer = 61.5f32.to_le_bytes().to_vec();
+    answer.extend([0xaa, 0xbb]);
+    mock.expect(packet(READ_LEVEL, 4), answer.clone());
+    assert_eq!(nsrt.read_level().unwrap(), 61.5);
+    assert!(!mock.is_done());
+
+    let (nsrt, mock) = device();
+    let mut nsrt = nsrt.strict_mode(StrictMode::default());
+    mock.expect(packet(READ_LEVEL, 4), answer);
+    let error = nsrt.read_level().unwrap_err();
+    assert!(matches!(error, NsrtError::ProtocolViolation(_)), "{error}");
+    assert_eq!(error.code(), 14);
+    assert_eq!(error.kind(), ErrorKind::Desync);
+    assert_eq!(
+        error.to_string(),
+        "Protocol violation: 2 bytes left over after the answer to ReadLevel"
+    );
+    // The leftover bytes were discarded
+    mock.assert_done();
+
+    mock.expect(packet(READ_LEQ, 4), [0, 0]);
+    let error = nsrt.read_leq().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Protocol violation: answer to ReadLEQ is 2 bytes, expected 4"
+    );
+
+    mock.expect(write_packet(WRITE_WEIGHTING, &[2]), [ACK]);
+    expect_float(&mock, READ_TAU, 0.125);
+    nsrt.set_strict_mode(Some(StrictMode {
+        ack_timeout: Duration::from_secs(1),
+    }));
+    nsrt.set_weighting(Weighting::Z).unwrap();
+    mock.assert_done();
+}
+
+#[test]
+fn strict_fast_poll() {
+    use nsrt::StrictMode;
+
+    let (nsrt, mock) = device();
+    let mut nsrt = nsrt.strict_mode(StrictMode::default());
+    for _ in 0..2 {
+        mock.expect(packet(READ_WEIGHTING, 1), [1]);
+        expect_float(&mock, READ_TAU, 0.125);
+        mock.expect(packet(READ_FS, 2), 48000u16.to_le_bytes());
+    }
+    let mut poll = nsrt
+        .fast_poll(&DeviceConfig {
+            weighting: Weighting::A,
+            time_constant: 0.125,
+            sampling_frequency: SamplingFrequency::Freq48kHz,
+        })
+        .unwrap();
+
+    expect_float(&mock, READ_LEVEL, 61.5);
+    expect_float(&mock, READ_LEQ, 60.0);
+    let mut temperature = 21.0f32.to_le_bytes().to_vec();
+    temperature.push(0xaa);
+    mock.expect(packet(READ_TEMPERATURE, 4), temperature);
+    let error = poll.read().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Protocol violation: 1 bytes left over after the answer to a batch"
+    );
+    mock.assert_done();
+
+    expect_float(&mock, READ_LEVEL, 61.5);
+    expect_float(&mock, READ_LEQ, 60.0);
+    mock.expect(packet(READ_TEMPERATURE, 4), [0, 0]);
+    let error = poll.read().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Protocol violation: answer to a batch is 10 bytes, expected 12"
+    );
+}
+
+#[test]
+fn alarm_escalation() {
+    use nsrt::{Alarm, AlarmLevel, AlarmMonitor, AlarmState};
+
+    let mut monitor = AlarmMonitor::new(
+        Alarm::new()
+            .warning(
+                AlarmLevel::new(70.0)
+                    .hysteresis(2.0)
+                    .dwell(Duration::from_secs(1)),
+            )
+            .critical(AlarmLevel::new(85.0).dwell(Duration::from_millis(500))),
+    );
+    let mut transitions = Vec::new();
+    for (millis, level) in [
+        // A lull restarts the dwell time
+        (0, 75.0),
+        (500, 60.0),
+        (1000, 75.0),
+        (1500, 90.0),
+        (2000, 90.0),
+        (2500, 80.0),
+        (3000, 80.0),
+        // Within the hysteresis
+        (3500, 69.0),
+        (4000, 60.0),
+        (5000, 60.0),
+    ] {
+        if let Some(transition) = monitor.update(&at_millis(millis, level)) {
+            transitions.push((millis, transition.from, transition.to));
+        }
+    }
+    assert_eq!(
+        transitions,
+        [
+            (2000, AlarmState::Normal, AlarmState::Critical),
+            (3000, AlarmState::Critical, AlarmState::Warning),
+            (5000, AlarmState::Warning, AlarmState::Normal),
+        ]
+    );
+    assert_eq!(monitor.state(), AlarmState::Normal);
+}
+
+#[cfg(feature = "schedule")]
+#[test]
+fn alarm_schedule() {
+    use nsrt::{
+        Alarm, AlarmLevel,
+        schedule::{Day, Period, Schedule, Time, TimeZone},
+    };
+
+    let warning = |level| Alarm::new().warning(AlarmLevel::new(level));
+    let time = |time: &str| time.parse::<Time>().unwrap();
+    // Weekends only have a quieter day; nights start on Friday only here,
+    // so Saturday morning still belongs to Friday's night
+    let schedule = Schedule::new(warning(55.0))
+        .time_zone(TimeZone::get("Europe/London").unwrap())
+        .period(
+            Period::new(time("07:00"), time("22:00"), warning(50.0))
+                .name("weekend")
+                .days([Day::Sat, Day::Sun]),
+        )
+        .period(
+            Period::new(time("22:00"), time("07:00"), warning(45.0))
+                .name("night")
+                .days([Day::Fri]),
+        );
+
+    // Friday 2024-05-03, when London is on UTC+1
+    let friday_utc = 1_714_694_400;
+    let at_utc_hour = |hour: u64| UNIX_EPOCH + Duration::from_secs(friday_utc + hour * 3600);
+    let level_at = |hour| {
+        schedule
+            .alarm_at(at_utc_hour(hour))
+            .unwrap()
+            .warning
+            .unwrap()
+            .level
+    };
+    assert_eq!(level_at(11), 55.0);
+    assert_eq!(level_at(20), 55.0);
+    assert_eq!(level_at(21), 45.0);
+    assert_eq!(level_at(24 + 2), 45.0);
+    assert_eq!(level_at(24 + 6), 50.0);
+    assert_eq!(level_at(24 + 20), 50.0);
+    // No night starts on Saturday
+    assert_eq!(level_at(24 + 22), 55.0);
+    assert_eq!(
+        schedule
+            .period_at(at_utc_hour(24 + 2))
+            .unwrap()
+            .and_then(|period| period.name.as_deref()),
+        Some("night")
+    );
+}
+
+#[cfg(feature = "schedule")]
+#[test]
+fn survey_plan() {
+    use nsrt::schedule::{Cron, DateTime, SurveyPlan, TimeZone, Window};
+
+    let cron = |expression: &str| expression.parse::<Cron>().unwrap();
+    let at = |time: &str| time.parse::<DateTime>().unwrap();
+    // 2024-05-03 is a Friday
+    let quarters = cron("*/15 6-8,18 * * *");
+    assert!(quarters.matches(at("2024-05-03T06:45")));
+    assert!(quarters.matches(at("2024-05-03T18:00")));
+    assert!(!quarters.matches(at("2024-05-03T06:50")));
+    assert!(!quarters.matches(at("2024-05-03T09:00")));
+    // Both days restricted: the 1st of the month or any Sunday, in May
+    let days = cron("0 12 1 may sun");
+    assert!(days.matches(at("2024-05-01T12:00")));
+    assert!(days.matches(at("2024-05-05T12:00")));
+    assert!(!days.matches(at("2024-05-03T12:00")));
+    assert!(!days.matches(at("2024-06-02T12:00")));
+    assert!(cron("0 0 * * 7").matches(at("2024-05-05T00:00")));
+    assert_eq!(cron("  0  0 * *  FRI ").to_string(), "0 0 * * FRI");
+    for invalid in [
+        "* * * *",
+        "60 * * * *",
+        "* * 0 * *",
+        "* 5-1 * * *",
+        "*/0 * * * *",
+    ] {
+        assert!(invalid.parse::<Cron>().is_err(), "{invalid}");
+    }
+
+    let plan = SurveyPlan::new()
+        .time_zone(TimeZone::get("Europe/London").unwrap())
+        .window(
+            Window::new(cron("* 7-8 * * mon-fri"))
+                .name("rush hour")
+                .interval(Duration::from_millis(125)),
+        )
+        .window(Window::new(cron("* 7-18 * * mon-fri")).name("working day"));
+    // Friday 2024-05-03, when London is on UTC+1
+    let friday_utc = 1_714_694_400;
+    let window_at = |minutes: u64| {
+        plan.window_at(UNIX_EPOCH + Duration::from_secs(friday_utc + minutes * 60))
+            .unwrap()
+            .and_then(|window| window.name.as_deref())
+    };
+    assert_eq!(window_at(5 * 60 + 59), None);
+    assert_eq!(window_at(6 * 60), Some("rush hour"));
+    assert_eq!(window_at(7 * 60 + 59), Some("rush hour"));
+    assert_eq!(window_at(8 * 60), Some("working day"));
+    assert_eq!(window_at(17 * 60 + 59), Some("working day"));
+    assert_eq!(window_at(18 * 60), None);
+    // Saturday
+    assert_eq!(window_at((24 + 10) * 60), None);
+}
+
+/// Sink recording levels, each write waiting for a token until the sender
+/// is dropped
+struct Gated {
+    levels: Arc<Mutex<Vec<f32>>>,
+    tokens: mpsc::Receiver<()>,
+}
+
+fn gated() -> (Gated, Arc<Mutex<Vec<f32>>>, mpsc::Sender<()>) {
+    let levels = Arc::new(Mutex::new(Vec::new()));
+    let (tx, tokens) = mpsc::channel();
+    let sink = Gated {
+        levels: Arc::clone(&levels),
+        tokens,
+    };
+    (sink, levels, tx)
+}
+
+impl Sink for Gated {
+    fn write(&mut self, measurement: &Measurement) -> nsrt::Result<()> {
+        let _ = self.tokens.recv();
+        self.levels.lock().unwrap().push(measurement.level);
+        Ok(())
+    }
+}
+
+/// Write a measurement at `level` to `sink` and wait until its thread took it
+fn write_taken(sink: &mut nsrt::QueuedSink, level: f32) {
+    sink.write(&at_millis(0, level)).unwrap();
+    while sink.queued() > 0 {
+        thread::yield_now();
+    }
+}
+
+#[test]
+fn queued_sink_backpressure() {
+    use nsrt::{Backpressure, QueuedSink};
+
+    for (backpressure, kept, dropped) in [
+        (Backpressure::DropOldest, vec![0.0, 5.0, 6.0], 4),
+        // One in two measurements finding the queue full is kept
+        (Backpressure::Sample(2), vec![0.0, 4.0, 6.0], 4),
+    ] {
+        let (gated, levels, tx) = gated();
+        let mut sink = QueuedSink::new(gated, 2, backpressure);
+        // Dropped before the sink, releasing its thread if an assertion fails
+        let tx = tx;
+        write_taken(&mut sink, 0.0);
+        for level in 1..=6 {
+            sink.write(&at_millis(0, level as f32)).unwrap();
+        }
+        assert_eq!((sink.queued(), sink.dropped()), (2, dropped));
+        drop(tx);
+        sink.flush().unwrap();
+        assert_eq!(*levels.lock().unwrap(), kept, "{backpressure:?}");
+    }
+
+    // Blocking holds up the writer until there is room
+    let (gated, levels, tx) = gated();
+    let mut sink = QueuedSink::new(gated, 1, Backpressure::Block);
+    write_taken(&mut sink, 0.0);
+    sink.write(&at_millis(0, 1.0)).unwrap();
+    let start = std::time::Instant::now();
+    let release = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        drop(tx);
+    });
+    sink.write(&at_millis(0, 2.0)).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    drop(sink);
+    release.join().unwrap();
+    assert_eq!(*levels.lock().unwrap(), [0.0, 1.0, 2.0]);
+}
+
+#[test]
+fn fan_out_isolates_slow_sinks() {
+    use nsrt::{Backpressure, FanOut};
+
+    struct Failing;
+    impl Sink for Failing {
+        fn write(&mut self, _: &Measurement) -> nsrt::Result<()> {
+            Err(NsrtError::InvalidParameter("full".to_string()))
+        }
+    }
+
+    let (slow, slow_levels, tx) = gated();
+    let (fast, fast_levels, fast_tx) = gated();
+    drop(fast_tx);
+    let mut fan_out =
+        FanOut::new()
+            .sink(slow, 2, Backpressure::DropOldest)
+            .sink(fast, 16, Backpressure::Block);
+    let tx = tx;
+    fan_out.write(&at_millis(0, 0.0)).unwrap();
+    while fan_out.sinks()[0].queued() > 0 {
+        thread::yield_now();
+    }
+    for level in 1..10 {
+        fan_out.write(&at_millis(0, level as f32)).unwrap();
+    }
+    // The fast sink got everything while the slow one was stuck
+    while fan_out.sinks()[1].queued() > 0 {
+        thread::yield_now();
+    }
+    assert_eq!(fast_levels.lock().unwrap().len(), 10);
+    assert_eq!(fan_out.sinks()[0].dropped(), 7);
+    drop(tx);
+    fan_out.flush().unwrap();
+    assert_eq!(*slow_levels.lock().unwrap(), [0.0, 8.0, 9.0]);
+
+    // A failed sink reports its error once and stays out, the others go on
+    let (fast, fast_levels, fast_tx) = gated();
+    drop(fast_tx);
+    let mut fan_out =
+        FanOut::new()
+            .sink(Failing, 4, Backpressure::Block)
+            .sink(fast, 4, Backpressure::Block);
+    fan_out.write(&at_millis(0, 1.0)).unwrap();
+    fan_out.flush().unwrap();
+    let errors = fan_out.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], (0, NsrtError::InvalidParameter(_))));
+    assert!(fan_out.sinks()[0].has_failed());
+    assert_eq!(fan_out.failed().collect::<Vec<_>>(), [0]);
+    fan_out.write(&at_millis(0, 2.0)).unwrap();
+    fan_out.flush().unwrap();
+    assert!(fan_out.take_errors().is_empty());
+    drop(fan_out);
+    assert_eq!(*fast_levels.lock().unwrap(), [1.0, 2.0]);
+
+    // Once every sink has failed, the fan-out fails with the last error
+    let mut fan_out = FanOut::new().sink(Failing, 4, Backpressure::Block);
+    fan_out.write(&at_millis(0, 1.0)).unwrap();
+    assert!(matches!(
+        fan_out.flush(),
+        Err(NsrtError::InvalidParameter(_))
+    ));
+    assert!(fan_out.write(&at_millis(0, 2.0)).is_err());
+    assert!(fan_out.take_errors().is_empty());
+}
+
+#[test]
+fn attached_fan_out_outlives_failed_sinks() {
+    use nsrt::{Backpressure, FanOut, Sampler};
+
+    /// Sink failing after `0` writes
+    struct FailingAfter(usize);
+    impl Sink for FailingAfter {
+        fn write(&mut self, _: &Measurement) -> nsrt::Result<()> {
+            self.0 = self.0.checked_sub(1).ok_or(NsrtError::InvalidResponse)?;
+            Ok(())
+        }
+    }
+
+    let (nsrt, mock) = device();
+    for level in 0..1000 {
+        expect_float(&mock, READ_LEVEL, level as f32);
+        expect_float(&mock, READ_LEQ, 60.0);
+        expect_float(&mock, READ_TEMPERATURE, 20.0);
+    }
+    let (logger, levels, tx) = gated();
+    drop(tx);
+    let fan_out = FanOut::new()
+        .sink(FailingAfter(2), 4, Backpressure::Block)
+        .sink(logger, 64, Backpressure::Block);
+
+    let sampler = Sampler::start(nsrt, Duration::from_millis(1));
+    let attached = sampler.attach(fan_out);
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while levels.lock().unwrap().len() < 20 {
+        assert!(std::time::Instant::now() < deadline, "the logger stalled");
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!attached.is_finished());
+    sampler.stop().unwrap();
+    attached.join().unwrap().unwrap();
+    let levels = levels.lock().unwrap();
+    assert!(levels.len() >= 20);
+    assert!(levels.windows(2).all(|pair| pair[1] == pair[0] + 1.0));
+}
+
+#[test]
+fn record_serializers_over_transports() {
+    use nsrt::{ByteStream, InfluxLineSerializer, RecordSink};
+
+    let metadata = Metadata {
+        device: Some("front".to_string()),
+        site: Some("Main Street".to_string()),
+        ..Metadata::default()
+    };
+    let mut influx =
+        RecordSink::new(ByteStream::new(Vec::new()), InfluxLineSerializer::default()).unwrap();
+    influx.set_metadata(&metadata).unwrap();
+    influx.write(&at_millis(1_000, 48.5)).unwrap();
+    let mut flagged = at_millis(1_250, 50.0);
+    flagged.quality = Quality::OVERLOAD;
+    influx.write(&flagged).unwrap();
+    assert_eq!(
+        String::from_utf8(influx.into_inner().into_inner()).unwrap(),
+        "nsrt,device=front,site=Main\\ Street level=48.5,leq=48.5,temperature=20,quality=0i 1000000000\n\
+         nsrt,device=front,site=Main\\ Street level=50,leq=50,temperature=20,quality=8i 1250000000\n"
+    );
+
+    // A message transport gets the header, the metadata and each row as
+    // messages of their own
+    #[cfg(feature = "csv")]
+    {
+        let (tx, rx) = mpsc::channel();
+        let mut csv = RecordSink::new(tx, nsrt::csv::CsvSerializer).unwrap();
+        csv.set_metadata(&metadata).unwrap();
+        csv.write(&at_millis(1_000, 48.5)).unwrap();
+        drop(csv);
+        let messages: Vec<String> = rx
+            .iter()
+            .map(|message| String::from_utf8(message).unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                format!("{}\n# schema_version: 3\n", nsrt::csv::HEADER),
+                "# device: front\n# site: Main Street\n".to_string(),
+                "1970-01-01T00:00:01.000000000Z,48.5,48.5,20,,,,\n".to_string(),
+            ]
+        );
+    }
+}
+
+/// Path of a log written by an earlier version of the crate
+#[cfg(any(
+    feature = "binlog",
+    feature = "csv",
+    feature = "parquet",
+    feature = "report",
+    feature = "sqlite"
+))]
+fn fixture(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// The measurements in the log fixtures, without clock status or quality
+#[cfg(any(
+    feature = "binlog",
+    feature = "csv",
+    feature = "parquet",
+    feature = "sqlite"
+))]
+fn fixture_measurements() -> Vec<Measurement> {
+    [(48.3, 47.9, 22.5), (50.1, 49.0, 22.5), (61.7, 58.2, 22.6)]
+        .into_iter()
+        .zip(0..)
+        .map(|((level, leq, celsius), second)| Measurement {
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_714_564_800_250 + second * 1_000),
+            level,
+            leq,
+            temperature: Temperature::from_celsius(celsius),
+            ..at_millis(0, level)
+        })
+        .collect()
+}
+
+/// Copy of a fixture in the temporary directory, for logs changed by opening
+/// or appending
+#[cfg(any(feature = "csv", feature = "sqlite"))]
+fn fixture_copy(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("nsrt-{}-{name}", std::process::id()));
+    std::fs::copy(fixture(name), &path).unwrap();
+    path
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn csv_schema_versions() {
+    use nsrt::csv::{CsvReader, CsvWriter, HEADER, SCHEMA_VERSION};
+
+    // Each historical header reads back its own columns
+    for version in 1..=3 {
+        let reader = CsvReader::open(fixture(&format!("csv-v{version}.csv"))).unwrap();
+        assert_eq!(reader.schema_version(), version);
+        let mut expected = fixture_measurements();
+        if version >= 2 {
+            expected[0].clock = Some(nsrt::ClockStatus {
+                synchronized: true,
+                offset: 0.000_012,
+                max_error: 0.0015,
+            });
+        }
+        if version >= 3 {
+            expected[2].quality = Quality::OVERLOAD;
+        }
+        let read: Vec<Measurement> = reader.collect::<nsrt::Result<_>>().unwrap();
+        assert_eq!(read, expected, "version {version}");
+    }
+
+    // Appending to an old log keeps to its columns
+    let path = fixture_copy("csv-v1.csv");
+    let mut writer = CsvWriter::append(&path).unwrap();
+    writer
+        .write(&Measurement {
+            quality: Quality::OVERLOAD,
+            ..at_millis(1_714_564_803_250, 55.0)
+        })
+        .unwrap();
+    drop(writer);
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.ends_with("\n2024-05-01T12:00:03.250000000Z,55,55,20\n"));
+    let reader = CsvReader::open(&path).unwrap();
+    assert_eq!(reader.schema_version(), 1);
+    assert_eq!(reader.count(), 4);
+    std::fs::remove_file(&path).unwrap();
+
+    // New logs state their version, and later versions are refused
+    let mut log = Vec::new();
+    CsvWriter::new(&mut log).unwrap();
+    assert!(
+        String::from_utf8(log)
+            .unwrap()
+            .starts_with(&format!("{HEADER}\n# schema_version: {SCHEMA_VERSION}\n"))
+    );
+    let newer = format!("{HEADER},spectrum\n# schema_version: 4\n");
+    assert!(matches!(
+        CsvReader::new(newer.as_bytes()),
+        Err(NsrtError::UnsupportedSchema { version: 4, .. })
+    ));
+}
+
+/// The measurements in the binary log fixtures of version 2, with quality
+/// flags
+#[cfg(feature = "binlog")]
+fn flagged_fixture_measurements() -> Vec<Measurement> {
+    let mut measurements = fixture_measurements();
+    measurements[1].quality = Quality::RECONNECTED;
+    measurements[2].quality = Quality::OVERLOAD;
+    measurements
+}
+
+#[cfg(feature = "binlog")]
+#[test]
+fn binary_log_schema_versions() {
+    use nsrt::binlog::{BinaryLogReader, BinaryLogWriter, compact};
+
+    // Version 1 records read as good, version 2 records keep their flags
+    for (version, expected) in [
+        (1, fixture_measurements()),
+        (2, flagged_fixture_measurements()),
+    ] {
+        let reader = BinaryLogReader::open(fixture(&format!("binlog-v{version}.nsrtlog"))).unwrap();
+        assert_eq!(reader.schema_version(), version);
+        let read: Vec<Measurement> = reader.collect::<nsrt::Result<_>>().unwrap();
+        assert_eq!(read, expected, "version {version}");
+    }
+
+    // Appending keeps to the version of the log
+    let path = std::env::temp_dir().join(format!("nsrt-schema-{}.nsrtlog", std::process::id()));
+    std::fs::copy(fixture("binlog-v1.nsrtlog"), &path).unwrap();
+    let mut writer = BinaryLogWriter::append(&path).unwrap();
+    writer.write(&flagged_fixture_measurements()[2]).unwrap();
+    drop(writer);
+    let reader = BinaryLogReader::open(&path).unwrap();
+    assert_eq!(reader.schema_version(), 1);
+    let read: Vec<Measurement> = reader.collect::<nsrt::Result<_>>().unwrap();
+    assert_eq!(read[3], fixture_measurements()[2]);
+
+    // Compacting writes the current version, merging the flags
+    std::fs::copy(fixture("binlog-v2.nsrtlog"), &path).unwrap();
+    let hour = Duration::from_secs(3_600);
+    assert_eq!(
+        compact(&path, UNIX_EPOCH + Duration::from_secs(1_714_568_400), hour).unwrap(),
+        3
+    );
+    let read: Vec<Measurement> = BinaryLogReader::open(&path)
+        .unwrap()
+        .collect::<nsrt::Result<_>>()
+        .unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].quality, Quality::RECONNECTED | Quality::OVERLOAD);
+
+    // A log of a later version is neither read nor appended to
+    let mut newer = std::fs::read(fixture("binlog-v2.nsrtlog")).unwrap();
+    newer[7] = b'3';
+    let error = BinaryLogReader::new(&newer[..]).err().unwrap();
+    assert!(matches!(
+        error,
+        NsrtError::UnsupportedSchema {
+            version: 3,
+            supported: 2,
+            ..
+        }
+    ));
+    assert_eq!(error.kind(), ErrorKind::Other);
+    std::fs::write(&path, &newer).unwrap();
+    assert!(matches!(
+        BinaryLogWriter::append(&path),
+        Err(NsrtError::UnsupportedSchema { version: 3, .. })
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_log_schema_versions() {
+    use nsrt::encryption::{EncryptedLogReader, EncryptedLogWriter, LogKey};
+
+    let key = LogKey::from_hex(&"0123456789abcdef".repeat(4)).unwrap();
+    for (version, expected) in [
+        (1, fixture_measurements()),
+        (2, flagged_fixture_measurements()),
+    ] {
+        let reader =
+            EncryptedLogReader::open(fixture(&format!("encrypted-v{version}.nsrtenc")), &key)
+                .unwrap();
+        assert_eq!(reader.schema_version(), version);
+        let read: Vec<Measurement> = reader.collect::<nsrt::Result<_>>().unwrap();
+        assert_eq!(read, expected, "version {version}");
+    }
+
+    let path = std::env::temp_dir().join(format!("nsrt-schema-{}.nsrtenc", std::process::id()));
+    std::fs::copy(fixture("encrypted-v1.nsrtenc"), &path).unwrap();
+    let mut writer = EncryptedLogWriter::append(&path, &key).unwrap();
+    writer.write(&flagged_fixture_measurements()[2]).unwrap();
+    drop(writer);
+    let reader = EncryptedLogReader::open(&path, &key).unwrap();
+    assert_eq!(reader.schema_version(), 1);
+    let read: Vec<Measurement> = reader.collect::<nsrt::Result<_>>().unwrap();
+    assert_eq!(read[3], fixture_measurements()[2]);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut newer = std::fs::read(fixture("encrypted-v2.nsrtenc")).unwrap();
+    newer[7] = b'3';
+    assert!(matches!(
+        EncryptedLogReader::new(&newer[..], &key),
+        Err(NsrtError::UnsupportedSchema { version: 3, .. })
+    ));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_schema_migrations() {
+    use nsrt::sqlite::{SCHEMA_VERSION, SqliteLog};
+
+    let user_version = |log: &SqliteLog| -> u32 {
+        log.connection()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    };
+    let hour = Duration::from_secs(3_600);
+
+    // A version 1 log, without aggregates, gains the table when opened
+    let path = fixture_copy("sqlite-v1.db");
+    let mut log = SqliteLog::open(&path).unwrap();
+    assert_eq!(user_version(&log), SCHEMA_VERSION);
+    assert_eq!(log.measurements(..).unwrap(), fixture_measurements());
+    assert_eq!(
+        log.compact(UNIX_EPOCH + Duration::from_secs(1_714_568_400), hour)
+            .unwrap(),
+        3
+    );
+    assert_eq!(log.aggregates(..).unwrap()[0].measurements, 3);
+
+    // Later versions are refused rather than written to
+    log.connection()
+        .pragma_update(None, "user_version", 99)
+        .unwrap();
+    drop(log);
+    assert!(matches!(
+        SqliteLog::open(&path),
+        Err(NsrtError::UnsupportedSchema { version: 99, .. })
+    ));
+    std::fs::remove_file(&path).unwrap();
+
+    // A version 2 log, whose schema is current but unnumbered, keeps its rows
+    let path = fixture_copy("sqlite-v2.db");
+    let log = SqliteLog::open(&path).unwrap();
+    assert_eq!(user_version(&log), SCHEMA_VERSION);
+    let measurements = log.measurements(..).unwrap();
+    assert_eq!(measurements.len(), 1);
+    assert_eq!(measurements[0].quality, Quality::OVERLOAD);
+    let aggregates = log.aggregates(..).unwrap();
+    assert_eq!(aggregates.len(), 1);
+    assert_eq!(
+        (aggregates[0].start, aggregates[0].duration),
+        (UNIX_EPOCH + Duration::from_secs(1_714_564_800), hour)
+    );
+    assert_eq!(
+        (aggregates[0].lmax, aggregates[0].lmin),
+        (Some(61.7), Some(48.3))
+    );
+    drop(log);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_schema_versions() {
+    use nsrt::parquet::ParquetReader;
+
+    let reader = ParquetReader::open(fixture("parquet-v1.parquet")).unwrap();
+    assert_eq!(reader.schema_version(), 1);
+    let mut expected = fixture_measurements();
+    expected[0].clock = Some(nsrt::ClockStatus {
+        synchronized: true,
+        offset: 0.000_012,
+        max_error: 0.0015,
+    });
+    expected[2].quality = Quality::OVERLOAD;
+    let read: Vec<Measurement> = reader.collect::<nsrt::Result<_>>().unwrap();
+    assert_eq!(read, expected);
+}
+
+#[cfg(feature = "report")]
+#[test]
+fn report_schema_versions() {
+    use nsrt::report::DailySummary;
+
+    // Reports from before the version field read as version 1
+    let file = std::fs::File::open(fixture("report-v1.json")).unwrap();
+    let summary = DailySummary::read_json(file).unwrap();
+    assert_eq!(
+        summary.start,
+        UNIX_EPOCH + Duration::from_secs(1_714_521_600)
+    );
+    assert_eq!(summary.measurements, 3);
+    assert_eq!(summary.metadata.site.as_deref(), Some("Main Street"));
+    assert_eq!(summary.hourly.len(), 1);
+    assert_eq!(summary.events.len(), 1);
+
+    let mut json = Vec::new();
+    summary.write_json(&mut json).unwrap();
+    let text = String::from_utf8(json).unwrap();
+    assert!(text.contains("\"schema_version\": 1,"));
+    assert_eq!(DailySummary::read_json(text.as_bytes()).unwrap(), summary);
+
+    let newer = text.replace("\"schema_version\": 1,", "\"schema_version\": 2,");
+    assert!(matches!(
+        DailySummary::read_json(newer.as_bytes()),
+        Err(NsrtError::UnsupportedSchema { version: 2, .. })
+    ));
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn seal_file_schema_versions() {
+    use nsrt::signing::{MAGIC, SEAL_LEN, read_seals};
+
+    let mut entry = [0; SEAL_LEN];
+    entry[..8].copy_from_slice(&3u64.to_le_bytes());
+    entry[8..40].copy_from_slice(&[1; 32]);
+
+    // Version 1 seal files are the entries alone
+    let seals = read_seals(&entry[..]).unwrap();
+    assert_eq!(
+        (
+            seals.len(),
+            seals[0].records,
+            seals[0].hash,
+            seals[0].version
+        ),
+        (1, 3, [1; 32], 1)
+    );
+    let mut current = MAGIC.to_vec();
+    current.extend_from_slice(&entry);
+    let current_seals = read_seals(&current[..]).unwrap();
+    assert_eq!(
+        (current_seals[0].records, current_seals[0].hash),
+        (3, [1; 32])
+    );
+    assert_eq!(current_seals[0].version, 2);
+
+    current[7] = b'3';
+    assert!(matches!(
+        read_seals(&current[..]),
+        Err(NsrtError::UnsupportedSchema { version: 3, .. })
+    ));
+}
+
+#[test]
+fn store_and_forward_schema_version() {
+    let dir = std::env::temp_dir().join(format!("nsrt-forward-schema-{}", std::process::id()));
+    let version = || std::fs::read_to_string(dir.join("version")).unwrap();
+
+    // A queue from before the version file, of 24-byte records, is upgraded
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("queue"), stored_record(1)).unwrap();
+    let mut queue = nsrt::StoreAndForward::open(&dir, Collect::default()).unwrap();
+    assert_eq!(version().trim(), "2");
+    assert!(queue.forward().unwrap());
+    assert_eq!(queue.get_ref().0.len(), 1);
+    drop(queue);
+
+    // Quality flags survive the queue
+    let (sink, up, _) = uplink();
+    up.store(false, std::sync::atomic::Ordering::SeqCst);
+    let mut queue = nsrt::StoreAndForward::open(&dir, sink).unwrap();
+    let flagged = Measurement {
+        quality: Quality::OVERLOAD | Quality::RECONNECTED,
+        ..at_millis(1_000, 50.0)
+    };
+    queue.write(&flagged).unwrap();
+    drop(queue);
+    let mut queue = nsrt::StoreAndForward::open(&dir, Collect::default()).unwrap();
+    assert!(queue.forward().unwrap());
+    assert_eq!(queue.get_ref().0, [flagged]);
+    drop(queue);
+
+    std::fs::write(dir.join("version"), "3\n").unwrap();
+    assert!(matches!(
+        nsrt::StoreAndForward::open(&dir, Collect::default()),
+        Err(NsrtError::UnsupportedSchema { version: 3, .. })
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Stored record of `secs` after the epoch at 50 dB and 20 °C
+fn stored_record(secs: u64) -> Vec<u8> {
+    let mut record = secs.to_le_bytes().to_vec();
+    record.extend_from_slice(&0u32.to_le_bytes());
+    for value in [50f32, 50.0, 20.0] {
+        record.extend_from_slice(&value.to_le_bytes());
+    }
+    record
+}
+
+#[test]
+fn stored_timestamps_out_of_range() {
+    let invalid_data = |e: &NsrtError| matches!(e, NsrtError::IoError(e) if e.kind() == io::ErrorKind::InvalidData);
+
+    // A queue record too far in the future is dropped with an error
+    let dir = std::env::temp_dir().join(format!("nsrt-forward-range-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("queue"),
+        [stored_record(u64::MAX), stored_record(1)].concat(),
+    )
+    .unwrap();
+    let mut queue = nsrt::StoreAndForward::open(&dir, Collect::default()).unwrap();
+    assert_eq!(queue.pending(), 2);
+    assert!(invalid_data(&queue.forward().unwrap_err()));
+    assert!(queue.forward().unwrap());
+    assert_eq!(
+        queue.get_ref().0,
+        [Measurement {
+            timestamp: UNIX_EPOCH + Duration::from_secs(1),
+            ..at_millis(0, 50.0)
+        }]
+    );
+    drop(queue);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // So is a binary log record with a valid CRC
+    #[cfg(feature = "binlog")]
+    {
+        let mut record = stored_record(u64::MAX);
+        record.extend_from_slice(&crc32fast::hash(&record).to_le_bytes());
+        let mut log = nsrt::binlog::MAGIC.to_vec();
+        log.extend(zstd::encode_all(&record[..], 3).unwrap());
+        let mut reader = nsrt::binlog::BinaryLogReader::new(&log[..]).unwrap();
+        assert!(invalid_data(&reader.next().unwrap().unwrap_err()));
+    }
+}
+
+/// Sink logging the sites of its metadata and the levels of its
+/// measurements, failing while its uplink is down
+struct Uplink {
+    up: Arc<std::sync::atomic::AtomicBool>,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Uplink {
+    fn check(&self) -> nsrt::Result<()> {
+        if self.up.load(std::sync::atomic::Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(NsrtError::IoError(io::Error::other("Uplink down")))
+        }
+    }
+}
+
+impl Sink for Uplink {
+    fn set_metadata(&mut self, metadata: &Metadata) -> nsrt::Result<()> {
+        self.check()?;
+        let site = metadata.site.as_deref().unwrap_or("-");
+        self.log.lock().unwrap().push(format!("site {site}"));
+        Ok(())
+    }
+
+    fn write(&mut self, measurement: &Measurement) -> nsrt::Result<()> {
+        self.check()?;
+        self.log.lock().unwrap().push(measurement.level.to_string());
+        Ok(())
+    }
+}
+
+fn uplink() -> (
+    Uplink,
+    Arc<std::sync::atomic::AtomicBool>,
+    Arc<Mutex<Vec<String>>>,
+) {
+    let up = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let sink = Uplink {
+        up: Arc::clone(&up),
+        log: Arc::clone(&log),
+    };
+    (sink, up, log)
+}
+
+fn site(name: &str) -> Metadata {
+    Metadata {
+        site: Some(name.to_string()),
+        ..Metadata::default()
+    }
+}
+
+#[test]
+fn store_and_forward_passes_metadata_on() {
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("nsrt-forward-metadata-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let (sink, up, log) = uplink();
+    let mut forward = nsrt::StoreAndForward::open(&dir, sink).unwrap();
+    forward.set_metadata(&site("North")).unwrap();
+    forward.write(&at_millis(0, 50.0)).unwrap();
+
+    // Metadata set during an outage is held, and passed on ahead of the queue
+    up.store(false, Ordering::SeqCst);
+    forward.write(&at_millis(1_000, 51.0)).unwrap();
+    forward.set_metadata(&site("South")).unwrap();
+    forward.write(&at_millis(2_000, 52.0)).unwrap();
+    assert_eq!(forward.pending(), 2);
+    up.store(true, Ordering::SeqCst);
+    forward.write(&at_millis(3_000, 53.0)).unwrap();
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["site North", "50", "site South", "51", "52", "53"]
+    );
+    drop(forward);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "signing")]
+fn signed(log: &[Measurement]) -> (Vec<nsrt::signing::Seal>, nsrt::signing::VerifyingKey) {
+    use nsrt::signing::{SigningKey, SigningSink, read_seals};
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let verifying = key.verifying_key();
+    let mut signer = SigningSink::new(Collect::default(), key, Vec::new()).segment_len(2);
+    for measurement in log {
+        signer.write(measurement).unwrap();
+    }
+    signer.flush().unwrap();
+    let (_, seals) = signer.into_parts();
+    (read_seals(&seals[..]).unwrap(), verifying)
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn seals_cover_quality_flags() {
+    let mut log: Vec<_> = (0..3).map(|i| at_millis(i * 1_000, 50.0)).collect();
+    let (seals, key) = signed(&log);
+    let verified = nsrt::signing::verify(log.iter().copied().map(Ok), &seals, &key).unwrap();
+    assert_eq!((verified.sealed, verified.unsealed), (3, 0));
+
+    log[1].quality = Quality::OVERLOAD;
+    assert!(matches!(
+        nsrt::signing::verify(log.into_iter().map(Ok), &seals, &key),
+        Err(NsrtError::VerificationFailed(_))
+    ));
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn verify_detects_tampering() {
+    use nsrt::signing::Seal;
+
+    let log: Vec<_> = (0..5)
+        .map(|i| at_millis(i * 1_000, 50.0 + i as f32))
+        .collect();
+    let (seals, key) = signed(&log);
+    let fails = |log: Vec<Measurement>, seals: &[Seal]| {
+        matches!(
+            nsrt::signing::verify(log.into_iter().map(Ok), seals, &key),
+            Err(NsrtError::VerificationFailed(_))
+        )
+    };
+    assert!(!fails(log.clone(), &seals));
+
+    let mut altered = log.clone();
+    altered[2].level += 0.1;
+    assert!(fails(altered, &seals), "altered record");
+
+    let mut inserted = log.clone();
+    inserted.insert(1, at_millis(500, 50.5));
+    assert!(fails(inserted, &seals), "inserted record");
+
+    let mut removed = log.clone();
+    removed.remove(1);
+    assert!(fails(removed, &seals), "removed record");
+
+    assert!(
+        fails(log[..4].to_vec(), &seals),
+        "log shorter than its seals"
+    );
+
+    let mut forged = seals.clone();
+    let mut bytes = forged[1].to_bytes();
+    bytes[40] ^= 1;
+    forged[1] = Seal::from_bytes(&bytes);
+    assert!(fails(log, &forged), "wrong signature");
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn signing_sink_passes_metadata_on() {
+    use nsrt::signing::{SigningKey, SigningSink};
+
+    let (sink, _, log) = uplink();
+    let mut signer = SigningSink::new(sink, SigningKey::from_bytes(&[7; 32]), Vec::new());
+    signer.set_metadata(&site("North")).unwrap();
+    signer.write(&at_millis(0, 50.0)).unwrap();
+    assert_eq!(*log.lock().unwrap(), ["site North", "50"]);
+}